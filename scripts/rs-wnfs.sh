@@ -75,7 +75,7 @@ help() {
     echo "COMMAND:"
     echo "   * build [--fs|--wasm|--common|--hamt|--accumulator]  - build projects"
     echo "   * test  [--fs|--wasm|--common|--hamt|--accumulator]  - run tests"
-    echo "   * bench                                             - run wnfs benchmarks"
+    echo "   * bench [--save <baseline>|--compare <baseline>]    - run wnfs benchmarks"
     echo "   * setup                                             - install rs-wnfs script"
     echo "   * help                                              - print this help message"
     echo ""
@@ -197,13 +197,44 @@ test_wasm() {
 
 # DESCRIPTION:
 #   Runs benchmarks.
+#   Results can be saved as a named criterion baseline, e.g. on main before
+#   starting a redesign, and later runs can be compared against it. Comparing
+#   warns about any benchmark that got slower by more than the noise threshold
+#   of 10%, without failing, since timings on shared runners are too noisy to
+#   gate on. Benchmarks without a saved baseline are skipped.
+#
+#   Baselines aren't checked in, since criterion timings are only comparable
+#   on the same machine. CI saves one for the base branch of pull requests
+#   and compares against that instead.
 #
 # USAGE:
-#	rs-wnfs bench
+#	rs-wnfs bench [--save <baseline>|--compare <baseline>]
 #
 bench() {
     display_header "📈 | RUNNING WNFS BENCHMARKS | 📈"
-    cargo bench -p wnfs-bench
+
+    get_flag_value --save
+    local save=$ret
+    get_flag_value --compare
+    local compare=$ret
+
+    if [[ ! -z $save ]]; then
+        cargo bench -p wnfs-bench -- --save-baseline $save
+    elif [[ ! -z $compare ]]; then
+        local output=$(mktemp)
+        cargo bench -p wnfs-bench -- --baseline-lenient $compare --noise-threshold 0.1 | tee $output
+        if [[ ${PIPESTATUS[0]} -ne 0 ]]; then
+            exit 1
+        fi
+        if grep -q "Performance has .*regressed" $output; then
+            warnln "Benchmarks regressed against baseline $compare"
+            if [[ ! -z $GITHUB_ACTIONS ]]; then
+                echo "::warning::Benchmarks regressed against baseline $compare"
+            fi
+        fi
+    else
+        cargo bench -p wnfs-bench
+    fi
 }
 
 #------------------------------------------------------------------------------
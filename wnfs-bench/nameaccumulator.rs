@@ -1,6 +1,9 @@
-use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
 use rand::{thread_rng, Rng};
-use wnfs_nameaccumulator::{AccumulatorSetup, BigNumDig, BigNumRug, NameAccumulator, NameSegment};
+use wnfs_common::{decode, encode, libipld::cbor::DagCborCodec};
+use wnfs_nameaccumulator::{
+    AccumulatorSetup, BigNumDig, BigNumRug, Name, NameAccumulator, NameSegment, ProofBatch,
+};
 
 fn name_segment_from_digest(c: &mut Criterion) {
     c.bench_function("NameSegment::<BigNumDig>::new_hashed", |b| {
@@ -76,12 +79,40 @@ fn name_accumulator_serialize(c: &mut Criterion) {
     });
 }
 
+fn proof_batch_encoding(c: &mut Criterion) {
+    let rng = &mut thread_rng();
+    let setup = &AccumulatorSetup::<BigNumRug>::from_rsa_2048(rng);
+    let dir = NameAccumulator::with_segments(&[NameSegment::new(rng)], setup);
+
+    let mut group = c.benchmark_group("ProofBatch::<BigNumRug> encoding");
+    for proofs in [1, 10, 100] {
+        let mut batch = ProofBatch::new();
+        for _ in 0..proofs {
+            let file = Name::new(dir.clone(), [NameSegment::new(rng)]);
+            let (commitment, proof) = file.into_proven_accumulator(setup);
+            batch.add(&commitment, &proof, setup);
+        }
+
+        // The throughput reports the encoded size, which should grow by ~280 bytes per proof
+        let bytes = encode(&batch, DagCborCodec).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("encode", proofs), &batch, |b, batch| {
+            b.iter(|| encode(batch, DagCborCodec).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("decode", proofs), &bytes, |b, bytes| {
+            b.iter(|| decode::<ProofBatch<BigNumRug>, _>(bytes, DagCborCodec).unwrap())
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     name_segment_from_digest,
     name_segment_rng,
     name_accumulator_add,
     name_accumulator_serialize,
+    proof_batch_encoding,
 );
 
 criterion_main!(benches);
@@ -0,0 +1,203 @@
+use async_std::task;
+use chrono::Utc;
+use criterion::{
+    async_executor::AsyncStdExecutor, black_box, criterion_group, criterion_main, BatchSize,
+    Criterion, Throughput,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use wnfs::{
+    common::{utils::Arc, BlockStore, MemoryBlockStore, Storable, CODEC_RAW},
+    nameaccumulator::{Name, NameSegment},
+    private::{
+        forest::{hamt::HamtForest, traits::PrivateForest},
+        PrivateDirectory, PrivateFile, PrivateNode,
+    },
+};
+
+const FILE_SIZE: usize = 1024 * 1024;
+
+fn random_names(forest: &HamtForest, count: usize, rng: &mut StdRng) -> Vec<Name> {
+    let base = forest.empty_name();
+    (0..count)
+        .map(|_| base.with_segments_added([NameSegment::new(rng), NameSegment::new(rng)]))
+        .collect()
+}
+
+async fn forest_with_names(
+    forest: &mut Arc<HamtForest>,
+    names: &[Name],
+    store: &impl BlockStore,
+) -> Arc<HamtForest> {
+    for (i, name) in names.iter().enumerate() {
+        let cid = store.create_cid(&i.to_le_bytes(), CODEC_RAW).unwrap();
+        forest.put_encrypted(name, [cid], store).await.unwrap();
+    }
+
+    forest.store(store).await.unwrap();
+    Arc::clone(forest)
+}
+
+fn private_file_write(c: &mut Criterion) {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let forest = HamtForest::new_rsa_2048_rc(rng);
+    let content = vec![42u8; FILE_SIZE];
+
+    let mut group = c.benchmark_group("private file write");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.bench_function("1 MiB", |b| {
+        b.to_async(AsyncStdExecutor).iter_batched(
+            || {
+                (
+                    Arc::clone(&forest),
+                    MemoryBlockStore::default(),
+                    content.clone(),
+                    StdRng::seed_from_u64(1),
+                )
+            },
+            |(mut forest, store, content, mut rng)| async move {
+                let name = forest.empty_name();
+                black_box(
+                    PrivateFile::with_content(
+                        &name,
+                        Utc::now(),
+                        content,
+                        &mut forest,
+                        &store,
+                        &mut rng,
+                    )
+                    .await
+                    .unwrap(),
+                );
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+fn private_file_read(c: &mut Criterion) {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let store = MemoryBlockStore::default();
+    let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    let file = task::block_on(async {
+        let name = forest.empty_name();
+        PrivateFile::with_content(
+            &name,
+            Utc::now(),
+            vec![42u8; FILE_SIZE],
+            forest,
+            &store,
+            rng,
+        )
+        .await
+        .unwrap()
+    });
+
+    let mut group = c.benchmark_group("private file read");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.bench_function("1 MiB", |b| {
+        b.to_async(AsyncStdExecutor).iter(|| async {
+            black_box(file.get_content(forest, &store).await.unwrap());
+        })
+    });
+    group.finish();
+}
+
+fn forest_put_get(c: &mut Criterion) {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let forest = HamtForest::new_rsa_2048_rc(rng);
+    let names = random_names(&forest, 100, rng);
+
+    c.bench_function("forest put and get 100 accumulated names", |b| {
+        b.to_async(AsyncStdExecutor).iter_batched(
+            || {
+                (
+                    Arc::clone(&forest),
+                    MemoryBlockStore::default(),
+                    names.clone(),
+                )
+            },
+            |(mut forest, store, names)| async move {
+                let forest = forest_with_names(&mut forest, &names, &store).await;
+                for name in names.iter() {
+                    black_box(forest.get_encrypted(name, &store).await.unwrap().unwrap());
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
+fn ratchet_search_latest(c: &mut Criterion) {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let store = MemoryBlockStore::default();
+    let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    let (access_key, forest) = task::block_on(async {
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let access_key = root.as_node().store(forest, &store, rng).await.unwrap();
+        for i in 0..100 {
+            root.write(
+                &[format!("{i}.txt")],
+                true,
+                Utc::now(),
+                vec![],
+                forest,
+                &store,
+                rng,
+            )
+            .await
+            .unwrap();
+            root.as_node().store(forest, &store, rng).await.unwrap();
+        }
+
+        (access_key, forest)
+    });
+
+    c.bench_function("search latest 100 revisions behind", |b| {
+        b.to_async(AsyncStdExecutor).iter(|| async {
+            let node = PrivateNode::load(&access_key, forest, &store, None)
+                .await
+                .unwrap();
+            black_box(node.search_latest(forest, &store).await.unwrap());
+        })
+    });
+}
+
+fn forest_diff_merge(c: &mut Criterion) {
+    let rng = &mut StdRng::seed_from_u64(0);
+    let store = MemoryBlockStore::default();
+    let base = &mut HamtForest::new_rsa_2048_rc(rng);
+    let common = random_names(base, 1000, rng);
+    let ours = random_names(base, 100, rng);
+    let theirs = random_names(base, 100, rng);
+    let (forest1, forest2) = task::block_on(async {
+        let base = forest_with_names(base, &common, &store).await;
+        (
+            forest_with_names(&mut Arc::clone(&base), &ours, &store).await,
+            forest_with_names(&mut Arc::clone(&base), &theirs, &store).await,
+        )
+    });
+
+    c.bench_function("forest diff 1000 common, 100 diverged names", |b| {
+        b.to_async(AsyncStdExecutor).iter(|| async {
+            black_box(forest1.diff(&forest2, &store).await.unwrap());
+        })
+    });
+
+    c.bench_function("forest merge 1000 common, 100 diverged names", |b| {
+        b.to_async(AsyncStdExecutor).iter(|| async {
+            black_box(forest1.merge(&forest2, &store).await.unwrap());
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    private_file_write,
+    private_file_read,
+    forest_put_get,
+    ratchet_search_latest,
+    forest_diff_merge
+);
+
+criterion_main!(benches);
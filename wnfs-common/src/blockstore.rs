@@ -70,6 +70,8 @@ pub trait BlockStore: CondSync {
     /// The hashing function that was chosen will be readable from the `Cid` metadata.
     ///
     /// If you need control over the concrete hashing function that's used, see `put_block_keyed`.
+    ///
+    /// Fails with `MaximumBlockSizeExceeded` for blocks larger than [`max_block_size`](Self::max_block_size).
     fn put_block(
         &self,
         bytes: impl Into<Bytes> + CondSend,
@@ -77,6 +79,7 @@ pub trait BlockStore: CondSync {
     ) -> impl Future<Output = Result<Cid, BlockStoreError>> + CondSend {
         let bytes = bytes.into();
         async move {
+            self.check_block_size(&bytes)?;
             let cid = self.create_cid(&bytes, codec)?;
             self.put_block_keyed(cid, bytes).await?;
             Ok(cid)
@@ -92,6 +95,10 @@ pub trait BlockStore: CondSync {
     ///
     /// This is useful to be able to add blocks that were generated from other
     /// clients with differently configured hashing functions to this blockstore.
+    ///
+    /// Fails with `MaximumBlockSizeExceeded` for blocks larger than [`max_block_size`](Self::max_block_size).
+    /// Implementations that store blocks themselves, rather than passing them on to another
+    /// store, check this with [`check_block_size`](Self::check_block_size).
     fn put_block_keyed(
         &self,
         cid: Cid,
@@ -107,13 +114,28 @@ pub trait BlockStore: CondSync {
         cid: &Cid,
     ) -> impl Future<Output = Result<bool, BlockStoreError>> + CondSend;
 
-    // This should be the same in all implementations of BlockStore
-    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
-        // If there are too many bytes, abandon this task
-        if bytes.len() > MAX_BLOCK_SIZE {
+    /// The maximum size in bytes of blocks this store accepts, e.g. to stay within
+    /// what the network it syncs with transfers.
+    ///
+    /// Structures that grow with their content, like directories with many entries, split
+    /// themselves into several blocks to stay within this size. Defaults to [`MAX_BLOCK_SIZE`],
+    /// see [`SizeLimitedBlockStore`](crate::SizeLimitedBlockStore) for configuring it.
+    fn max_block_size(&self) -> usize {
+        MAX_BLOCK_SIZE
+    }
+
+    /// Fails with `MaximumBlockSizeExceeded` if given block is larger than
+    /// [`max_block_size`](Self::max_block_size).
+    fn check_block_size(&self, bytes: &[u8]) -> Result<(), BlockStoreError> {
+        if bytes.len() > self.max_block_size() {
             return Err(BlockStoreError::MaximumBlockSizeExceeded(bytes.len()));
         }
 
+        Ok(())
+    }
+
+    // This should be the same in all implementations of BlockStore
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
         // Compute the Blake3 hash of the bytes
         let hash = Code::Blake3_256.digest(bytes);
 
@@ -153,6 +175,10 @@ impl<B: BlockStore> BlockStore for &B {
         (**self).has_block(cid).await
     }
 
+    fn max_block_size(&self) -> usize {
+        (**self).max_block_size()
+    }
+
     fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
         (**self).create_cid(bytes, codec)
     }
@@ -183,6 +209,10 @@ impl<B: BlockStore> BlockStore for Box<B> {
         (**self).has_block(cid).await
     }
 
+    fn max_block_size(&self) -> usize {
+        (**self).max_block_size()
+    }
+
     fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
         (**self).create_cid(bytes, codec)
     }
@@ -223,7 +253,9 @@ impl BlockStore for MemoryBlockStore {
         cid: Cid,
         bytes: impl Into<Bytes> + CondSend,
     ) -> Result<(), BlockStoreError> {
-        self.0.lock().insert(cid, bytes.into());
+        let bytes = bytes.into();
+        self.check_block_size(&bytes)?;
+        self.0.lock().insert(cid, bytes);
 
         Ok(())
     }
@@ -233,6 +265,206 @@ impl BlockStore for MemoryBlockStore {
     }
 }
 
+/// A block store that holds on to all blocks put into it until they're [flushed][Self::flush]
+/// into an underlying block store.
+///
+/// This makes it possible to run an operation like storing a private forest, then look at the
+/// [manifest][Self::manifest] of blocks it would add before actually writing them anywhere,
+/// e.g. to estimate upload sizes or show progress while uploading.
+///
+/// Reads fall through to the underlying store for blocks that haven't been staged.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::{BlockStore, MemoryBlockStore, StagingBlockStore, CODEC_RAW};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = MemoryBlockStore::new();
+///     store.put_block(b"existing".to_vec(), CODEC_RAW).await.unwrap();
+///
+///     let staging = StagingBlockStore::new(&store);
+///     staging.put_block(b"existing".to_vec(), CODEC_RAW).await.unwrap();
+///     let cid = staging.put_block(b"new".to_vec(), CODEC_RAW).await.unwrap();
+///
+///     let manifest = staging.manifest().await.unwrap();
+///     assert_eq!(manifest.new_blocks.len(), 1);
+///     assert_eq!(manifest.existing_blocks.len(), 1);
+///     assert_eq!(manifest.new_bytes(), 3);
+///     assert!(!store.has_block(&cid).await.unwrap());
+///
+///     staging.flush().await.unwrap();
+///     assert!(store.has_block(&cid).await.unwrap());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct StagingBlockStore<B: BlockStore> {
+    base: B,
+    staged: Mutex<StagedBlocks>,
+}
+
+#[derive(Debug, Default)]
+struct StagedBlocks {
+    order: Vec<Cid>,
+    blocks: HashMap<Cid, Bytes>,
+}
+
+/// The CID and size of a single block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub cid: Cid,
+    pub size: usize,
+}
+
+/// A report of the blocks staged in a [`StagingBlockStore`], split by whether the underlying
+/// store already has them.
+///
+/// Blocks are listed in the order they were first put.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockManifest {
+    /// Blocks that flushing would add to the underlying store.
+    pub new_blocks: Vec<BlockInfo>,
+    /// Blocks that the underlying store already has.
+    pub existing_blocks: Vec<BlockInfo>,
+}
+
+impl<B: BlockStore> StagingBlockStore<B> {
+    /// Creates a staging block store on top of given block store.
+    pub fn new(base: B) -> Self {
+        Self {
+            base,
+            staged: Mutex::new(StagedBlocks::default()),
+        }
+    }
+
+    /// Returns the underlying block store.
+    pub fn base(&self) -> &B {
+        &self.base
+    }
+
+    /// Reports which of the staged blocks are missing from the underlying store
+    /// and which it already has.
+    pub async fn manifest(&self) -> Result<BlockManifest, BlockStoreError> {
+        let staged = self.staged_blocks();
+
+        let mut manifest = BlockManifest::default();
+        for info in staged.into_iter().map(|(info, _)| info) {
+            if self.base.has_block(&info.cid).await? {
+                manifest.existing_blocks.push(info);
+            } else {
+                manifest.new_blocks.push(info);
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Writes all staged blocks the underlying store doesn't have yet into it
+    /// and returns the manifest of what was written.
+    ///
+    /// Afterwards nothing is staged anymore.
+    pub async fn flush(&self) -> Result<BlockManifest, BlockStoreError> {
+        let staged = self.staged_blocks();
+
+        let mut manifest = BlockManifest::default();
+        for (info, bytes) in staged {
+            if self.base.has_block(&info.cid).await? {
+                manifest.existing_blocks.push(info);
+            } else {
+                self.base.put_block_keyed(info.cid, bytes).await?;
+                manifest.new_blocks.push(info);
+            }
+        }
+
+        // Blocks staged while flushing stay staged
+        let mut staged = self.staged.lock();
+        for info in manifest.new_blocks.iter().chain(&manifest.existing_blocks) {
+            staged.blocks.remove(&info.cid);
+        }
+        let StagedBlocks { order, blocks } = &mut *staged;
+        order.retain(|cid| blocks.contains_key(cid));
+
+        Ok(manifest)
+    }
+
+    /// Drops all staged blocks without writing them.
+    pub fn discard(&self) {
+        *self.staged.lock() = StagedBlocks::default();
+    }
+
+    pub(crate) fn staged_blocks(&self) -> Vec<(BlockInfo, Bytes)> {
+        let staged = self.staged.lock();
+        staged
+            .order
+            .iter()
+            .map(|cid| {
+                let bytes = staged.blocks[cid].clone();
+                let info = BlockInfo {
+                    cid: *cid,
+                    size: bytes.len(),
+                };
+                (info, bytes)
+            })
+            .collect()
+    }
+}
+
+impl<B: BlockStore> BlockStore for StagingBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        let staged = self.staged.lock().blocks.get(cid).cloned();
+        match staged {
+            Some(bytes) => Ok(bytes),
+            None => self.base.get_block(cid).await,
+        }
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        let bytes = bytes.into();
+        self.check_block_size(&bytes)?;
+
+        let mut staged = self.staged.lock();
+        if !staged.blocks.contains_key(&cid) {
+            staged.order.push(cid);
+            staged.blocks.insert(cid, bytes);
+        }
+
+        Ok(())
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        if self.staged.lock().blocks.contains_key(cid) {
+            return Ok(true);
+        }
+
+        self.base.has_block(cid).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.base.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.base.create_cid(bytes, codec)
+    }
+}
+
+impl BlockManifest {
+    /// The combined size of all new blocks in bytes.
+    pub fn new_bytes(&self) -> usize {
+        self.new_blocks.iter().map(|info| info.size).sum()
+    }
+
+    /// The combined size of all blocks the underlying store already has in bytes.
+    pub fn existing_bytes(&self) -> usize {
+        self.existing_blocks.iter().map(|info| info.size).sum()
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -322,4 +554,62 @@ mod tests {
         bs_serialization_test::<MemoryBlockStore>(store).await?;
         Ok(())
     }
+
+    #[async_std::test]
+    async fn staging_blockstore() -> Result<()> {
+        let store = &StagingBlockStore::new(MemoryBlockStore::new());
+        bs_retrieval_test::<StagingBlockStore<MemoryBlockStore>>(store).await?;
+        bs_duplication_test::<StagingBlockStore<MemoryBlockStore>>(store).await?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn staging_blockstore_flushes_only_new_blocks() -> Result<()> {
+        let base = MemoryBlockStore::new();
+        let existing = base.put_block(vec![1, 2, 3], CODEC_RAW).await?;
+
+        let staging = StagingBlockStore::new(&base);
+        let new = staging.put_block(vec![4, 5, 6, 7], CODEC_RAW).await?;
+        staging.put_block(vec![1, 2, 3], CODEC_RAW).await?;
+        staging.put_block(vec![4, 5, 6, 7], CODEC_RAW).await?;
+
+        assert!(!base.has_block(&new).await?);
+        assert!(staging.has_block(&new).await?);
+
+        let manifest = staging.manifest().await?;
+        assert_eq!(manifest.new_blocks, vec![BlockInfo { cid: new, size: 4 }]);
+        assert_eq!(
+            manifest.existing_blocks,
+            vec![BlockInfo {
+                cid: existing,
+                size: 3
+            }]
+        );
+
+        assert_eq!(staging.flush().await?, manifest);
+        assert_eq!(base.get_block(&new).await?, vec![4, 5, 6, 7]);
+        assert_eq!(staging.manifest().await?, BlockManifest::default());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn keyed_puts_reject_blocks_above_the_maximum_size() -> Result<()> {
+        let large = vec![1; MAX_BLOCK_SIZE + 1];
+        let memory = MemoryBlockStore::new();
+        let cid = memory.create_cid(&large, CODEC_RAW)?;
+
+        let result = memory.put_block_keyed(cid, large.clone()).await;
+        assert!(matches!(
+            result,
+            Err(BlockStoreError::MaximumBlockSizeExceeded(_))
+        ));
+        assert!(!memory.has_block(&cid).await?);
+
+        let staging = StagingBlockStore::new(&memory);
+        assert!(staging.put_block_keyed(cid, large).await.is_err());
+        assert_eq!(staging.manifest().await?, BlockManifest::default());
+
+        Ok(())
+    }
 }
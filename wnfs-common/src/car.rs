@@ -0,0 +1,347 @@
+use crate::{encode, utils::decode_links, BlockStore};
+use anyhow::Result;
+use bytes::Bytes;
+use libipld::{cbor::DagCborCodec, ipld, Cid, Ipld};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Seek, SeekFrom, Write},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The bytes every CARv2 file starts with, a CARv1 header announcing version 2.
+pub const CAR_V2_PRAGMA: [u8; 11] = [
+    0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02,
+];
+
+/// The multicodec of the CARv2 index format written by [`write_car_v2`].
+pub const CAR_MULTIHASH_INDEX_SORTED: u64 = 0x0401;
+
+/// The size of the CARv2 header following the pragma.
+const CAR_V2_HEADER_SIZE: u64 = 40;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What a compaction with [`write_car_v2`] or [`copy_reachable_blocks`] rewrote.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// The number of blocks written.
+    pub blocks: usize,
+    /// The total size of the blocks written.
+    pub bytes: usize,
+}
+
+/// The offsets of block sections in a CAR payload, by multihash code and digest width,
+/// each with the digest of the block.
+type CarIndex = BTreeMap<u64, BTreeMap<usize, Vec<(Vec<u8>, u64)>>>;
+
+/// Visits the blocks reachable from a set of roots depth-first, each block before the
+/// blocks it links to, in the order of its links.
+///
+/// That's the order a reader following links from a root needs them in, so laying
+/// out blocks in this order turns reading a whole file or directory into a sequential read.
+struct DepthFirstBlocks<'a, B: BlockStore> {
+    store: &'a B,
+    stack: Vec<Cid>,
+    visited: BTreeSet<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, B: BlockStore> DepthFirstBlocks<'a, B> {
+    fn new(roots: &[Cid], store: &'a B) -> Self {
+        Self {
+            store,
+            stack: roots.iter().rev().copied().collect(),
+            visited: BTreeSet::new(),
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<(Cid, Bytes)>> {
+        while let Some(cid) = self.stack.pop() {
+            if !self.visited.insert(cid) {
+                continue;
+            }
+
+            let bytes = self.store.get_block(&cid).await?;
+            let links = decode_links(&cid, &bytes)?;
+            self.stack.extend(links.into_iter().rev());
+            return Ok(Some((cid, bytes)));
+        }
+
+        Ok(None)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Writes all blocks reachable from `roots` into a fresh CARv2 file with an index, e.g. to
+/// defragment a long-lived store or to archive it.
+///
+/// Blocks are written depth-first, each one right before the blocks it links to, so
+/// traversing the DAG reads the file sequentially. Blocks that aren't reachable from any
+/// of the roots are left behind. The index is a `MultihashIndexSorted` index, mapping the
+/// multihash of every block to its section in the CARv1 payload.
+///
+/// Blocks are streamed into `writer` one at a time, only their CIDs and offsets are kept
+/// until the index is written at the end. The writer needs to be seekable, since the
+/// CARv2 header that's written first is only known at the end.
+///
+/// # Examples
+///
+/// ```
+/// use libipld::{cbor::DagCborCodec, ipld};
+/// use std::io::Cursor;
+/// use wnfs_common::{encode, write_car_v2, BlockStore, MemoryBlockStore, CODEC_DAG_CBOR, CODEC_RAW};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let leaf = store.put_block(b"leaf".to_vec(), CODEC_RAW).await.unwrap();
+///     let bytes = encode(&ipld!([leaf]), DagCborCodec).unwrap();
+///     let root = store.put_block(bytes, CODEC_DAG_CBOR).await.unwrap();
+///     store.put_block(b"garbage".to_vec(), CODEC_RAW).await.unwrap();
+///
+///     let mut car = Cursor::new(Vec::new());
+///     let report = write_car_v2(&[root], store, &mut car).await.unwrap();
+///
+///     assert_eq!(report.blocks, 2);
+///     assert!(car.get_ref().starts_with(&wnfs_common::CAR_V2_PRAGMA));
+/// }
+/// ```
+pub async fn write_car_v2(
+    roots: &[Cid],
+    store: &impl BlockStore,
+    writer: &mut (impl Write + Seek),
+) -> Result<CompactionReport> {
+    let start = writer.stream_position()?;
+    let data_offset = CAR_V2_PRAGMA.len() as u64 + CAR_V2_HEADER_SIZE;
+    writer.write_all(&CAR_V2_PRAGMA)?;
+    writer.write_all(&[0; CAR_V2_HEADER_SIZE as usize])?;
+
+    let roots_ipld = roots.iter().copied().map(Ipld::Link).collect::<Vec<_>>();
+    let header = encode(&ipld!({ "roots": roots_ipld, "version": 1 }), DagCborCodec)?;
+    let mut data_size = write_varint(writer, header.len() as u64)?;
+    writer.write_all(&header)?;
+    data_size += header.len() as u64;
+
+    let mut index = CarIndex::new();
+    let mut report = CompactionReport::default();
+    let mut blocks = DepthFirstBlocks::new(roots, store);
+    while let Some((cid, bytes)) = blocks.next().await? {
+        let digest = cid.hash().digest().to_vec();
+        index
+            .entry(cid.hash().code())
+            .or_default()
+            .entry(digest.len())
+            .or_default()
+            .push((digest, data_size));
+
+        let cid_bytes = cid.to_bytes();
+        data_size += write_varint(writer, (cid_bytes.len() + bytes.len()) as u64)?;
+        writer.write_all(&cid_bytes)?;
+        writer.write_all(&bytes)?;
+        data_size += (cid_bytes.len() + bytes.len()) as u64;
+
+        report.blocks += 1;
+        report.bytes += bytes.len();
+    }
+
+    write_index(writer, index)?;
+    let end = writer.stream_position()?;
+
+    // Characteristics stay zeroed, then come the offsets relative to the pragma
+    writer.seek(SeekFrom::Start(start + CAR_V2_PRAGMA.len() as u64 + 16))?;
+    writer.write_all(&data_offset.to_le_bytes())?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    writer.write_all(&(data_offset + data_size).to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+
+    Ok(report)
+}
+
+/// Copies all blocks reachable from `roots` into another block store, e.g. a fresh one that
+/// replaces a long-lived store with lots of garbage in it.
+///
+/// Blocks are put in the same depth-first order as in [`write_car_v2`], so stores that
+/// lay out blocks in the order they're put end up optimized for sequential reads.
+pub async fn copy_reachable_blocks(
+    roots: &[Cid],
+    from: &impl BlockStore,
+    to: &impl BlockStore,
+) -> Result<CompactionReport> {
+    let mut report = CompactionReport::default();
+    let mut blocks = DepthFirstBlocks::new(roots, from);
+    while let Some((cid, bytes)) = blocks.next().await? {
+        report.blocks += 1;
+        report.bytes += bytes.len();
+        to.put_block_keyed(cid, bytes).await?;
+    }
+
+    Ok(report)
+}
+
+/// Writes a `MultihashIndexSorted` index: the number of multihash codes, then for each code
+/// in ascending order the code and an `IndexSorted` index of the digests using it.
+///
+/// An `IndexSorted` index is the number of buckets, then for each digest width in ascending
+/// order the width of its entries, their total size and the entries sorted by digest.
+/// Each entry is a digest followed by the offset of its block's section in the payload.
+fn write_index(writer: &mut impl Write, index: CarIndex) -> Result<()> {
+    write_varint(writer, CAR_MULTIHASH_INDEX_SORTED)?;
+    writer.write_all(&(index.len() as i32).to_le_bytes())?;
+    for (code, buckets) in index {
+        writer.write_all(&code.to_le_bytes())?;
+        writer.write_all(&(buckets.len() as i32).to_le_bytes())?;
+        for (digest_width, mut entries) in buckets {
+            entries.sort();
+            let width = digest_width + 8;
+            writer.write_all(&(width as u32).to_le_bytes())?;
+            writer.write_all(&((width * entries.len()) as u64).to_le_bytes())?;
+            for (digest, offset) in entries {
+                writer.write_all(&digest)?;
+                writer.write_all(&offset.to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes an unsigned LEB128 varint and returns the number of bytes written.
+fn write_varint(writer: &mut impl Write, mut value: u64) -> Result<u64> {
+    let mut written = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        written += 1;
+        if value == 0 {
+            writer.write_all(&[byte])?;
+            return Ok(written);
+        }
+
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryBlockStore, CODEC_DAG_CBOR, CODEC_RAW};
+    use std::io::Cursor;
+
+    fn read_varint(bytes: &[u8], pos: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = bytes[*pos];
+            *pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    fn read_u64(bytes: &[u8], pos: usize) -> u64 {
+        u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap())
+    }
+
+    async fn put_node(links: Ipld, store: &impl BlockStore) -> Result<Cid> {
+        Ok(store
+            .put_block(encode(&links, DagCborCodec)?, CODEC_DAG_CBOR)
+            .await?)
+    }
+
+    #[async_std::test]
+    async fn live_blocks_are_written_depth_first_and_indexed() -> Result<()> {
+        let store = &MemoryBlockStore::new();
+        let first = store.put_block(b"first".to_vec(), CODEC_RAW).await?;
+        let second = store.put_block(b"second".to_vec(), CODEC_RAW).await?;
+        store.put_block(b"garbage".to_vec(), CODEC_RAW).await?;
+        let left = put_node(ipld!([first]), store).await?;
+        let root = put_node(ipld!([left, second, first]), store).await?;
+
+        let mut car = Cursor::new(Vec::new());
+        let report = write_car_v2(&[root], store, &mut car).await?;
+        let car = car.into_inner();
+        assert_eq!(report.blocks, 4);
+
+        assert_eq!(car[..11], CAR_V2_PRAGMA);
+        let data_offset = read_u64(&car, 27);
+        let data_size = read_u64(&car, 35);
+        let index_offset = read_u64(&car, 43);
+        assert_eq!(data_offset, 51);
+        assert_eq!(index_offset, data_offset + data_size);
+
+        let data = &car[data_offset as usize..index_offset as usize];
+        let pos = &mut 0;
+        let header_size = read_varint(data, pos) as usize;
+        *pos += header_size;
+        let mut sections = Vec::new();
+        while *pos < data.len() {
+            let offset = *pos as u64;
+            let size = read_varint(data, pos) as usize;
+            let section = &data[*pos..*pos + size];
+            let cid = Cid::read_bytes(section)?;
+            let block = &section[cid.to_bytes().len()..];
+            assert_eq!(store.get_block(&cid).await?, block);
+            sections.push((cid, offset));
+            *pos += size;
+        }
+
+        let order = sections.iter().map(|(cid, _)| *cid).collect::<Vec<_>>();
+        assert_eq!(order, vec![root, left, first, second]);
+
+        let index = &car[index_offset as usize..];
+        let pos = &mut 0;
+        assert_eq!(read_varint(index, pos), CAR_MULTIHASH_INDEX_SORTED);
+        assert_eq!(index[*pos..*pos + 4], 1i32.to_le_bytes());
+        assert_eq!(read_u64(index, *pos + 4), root.hash().code());
+        assert_eq!(index[*pos + 12..*pos + 16], 1i32.to_le_bytes());
+        let width = u32::from_le_bytes(index[*pos + 16..*pos + 20].try_into()?) as usize;
+        assert_eq!(read_u64(index, *pos + 20), (width * 4) as u64);
+        let entries = index[*pos + 28..].chunks(width).collect::<Vec<_>>();
+        assert_eq!(entries.len(), 4);
+        for (cid, offset) in sections {
+            let entry = entries
+                .iter()
+                .find(|entry| entry[..width - 8] == *cid.hash().digest())
+                .unwrap();
+            assert_eq!(read_u64(entry, width - 8), offset);
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn only_reachable_blocks_are_copied() -> Result<()> {
+        let store = &MemoryBlockStore::new();
+        let leaf = store.put_block(b"leaf".to_vec(), CODEC_RAW).await?;
+        let garbage = store.put_block(b"garbage".to_vec(), CODEC_RAW).await?;
+        let root = put_node(ipld!([leaf, leaf]), store).await?;
+
+        let fresh = &MemoryBlockStore::new();
+        let report = copy_reachable_blocks(&[root], store, fresh).await?;
+
+        assert_eq!(report.blocks, 2);
+        assert!(fresh.has_block(&root).await?);
+        assert!(fresh.has_block(&leaf).await?);
+        assert!(!fresh.has_block(&garbage).await?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{utils::CondSend, BlockStore, BlockStoreError, StagingBlockStore};
+use bytes::Bytes;
+use libipld::Cid;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A block store that records the blocks an operation would put into an inner block store,
+/// without putting them there.
+///
+/// Recorded blocks can be read back, so any operation can run against this store as if it
+/// was the inner one. Afterwards, [`estimate`](Self::estimate) tells how much would be
+/// written, e.g. to enforce a quota or to ask the user before uploading. The recorded
+/// blocks can then either be written with [`commit`](Self::commit) or dropped with
+/// [`discard`](Self::discard) or together with this store.
+///
+/// This stages blocks in a [`StagingBlockStore`], except for blocks that the inner store
+/// already has, since putting them wouldn't write anything.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::{BlockStore, DryRunBlockStore, MemoryBlockStore, CODEC_RAW};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = MemoryBlockStore::new();
+///     let dry_run = DryRunBlockStore::new(&store);
+///     let cid = dry_run.put_block(b"hello".to_vec(), CODEC_RAW).await.unwrap();
+///
+///     assert_eq!(dry_run.estimate().byte_count, 5);
+///     assert!(!store.has_block(&cid).await.unwrap());
+///
+///     dry_run.commit().await.unwrap();
+///     assert!(store.has_block(&cid).await.unwrap());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DryRunBlockStore<B: BlockStore> {
+    staging: StagingBlockStore<B>,
+}
+
+/// How much a [`DryRunBlockStore`] would write to its inner block store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteEstimate {
+    /// The number of new blocks.
+    pub block_count: usize,
+    /// The total size of all new blocks in bytes.
+    pub byte_count: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<B: BlockStore> DryRunBlockStore<B> {
+    /// Wraps a block store, without recording any blocks yet.
+    pub fn new(inner: B) -> Self {
+        Self {
+            staging: StagingBlockStore::new(inner),
+        }
+    }
+
+    /// Returns the inner block store.
+    pub fn inner(&self) -> &B {
+        self.staging.base()
+    }
+
+    /// Returns the CIDs and sizes of all recorded blocks, in the order they were put.
+    pub fn recorded(&self) -> Vec<(Cid, u64)> {
+        self.staging
+            .staged_blocks()
+            .into_iter()
+            .map(|(info, _)| (info.cid, info.size as u64))
+            .collect()
+    }
+
+    /// Returns how much would be written to the inner block store on [`commit`](Self::commit).
+    pub fn estimate(&self) -> WriteEstimate {
+        let recorded = self.recorded();
+        WriteEstimate {
+            block_count: recorded.len(),
+            byte_count: recorded.iter().map(|(_, size)| size).sum(),
+        }
+    }
+
+    /// Puts all recorded blocks into the inner block store.
+    ///
+    /// Blocks are only forgotten once they're written, so a failed commit can be retried.
+    pub async fn commit(&self) -> Result<(), BlockStoreError> {
+        self.staging.flush().await?;
+        Ok(())
+    }
+
+    /// Forgets all recorded blocks without writing them.
+    pub fn discard(&self) {
+        self.staging.discard();
+    }
+}
+
+impl<B: BlockStore> BlockStore for DryRunBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        self.staging.get_block(cid).await
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        if self.staging.has_block(&cid).await? {
+            return Ok(());
+        }
+
+        self.staging.put_block_keyed(cid, bytes).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        self.staging.has_block(cid).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.staging.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.staging.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryBlockStore, CODEC_RAW};
+    use anyhow::Result;
+
+    #[async_std::test]
+    async fn only_new_blocks_are_estimated_and_committed() -> Result<()> {
+        let store = MemoryBlockStore::new();
+        let existing = store.put_block(b"existing".to_vec(), CODEC_RAW).await?;
+
+        let dry_run = DryRunBlockStore::new(&store);
+        let new = dry_run.put_block(b"new".to_vec(), CODEC_RAW).await?;
+        dry_run.put_block(b"new".to_vec(), CODEC_RAW).await?;
+        dry_run.put_block(b"existing".to_vec(), CODEC_RAW).await?;
+
+        assert_eq!(dry_run.recorded(), vec![(new, 3)]);
+        assert_eq!(
+            dry_run.estimate(),
+            WriteEstimate {
+                block_count: 1,
+                byte_count: 3
+            }
+        );
+        assert_eq!(dry_run.get_block(&new).await?, b"new".to_vec());
+        assert_eq!(dry_run.get_block(&existing).await?, b"existing".to_vec());
+        assert!(!store.has_block(&new).await?);
+
+        dry_run.commit().await?;
+
+        assert_eq!(store.get_block(&new).await?, b"new".to_vec());
+        assert_eq!(dry_run.estimate(), WriteEstimate::default());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn discarded_blocks_are_not_committed() -> Result<()> {
+        let store = MemoryBlockStore::new();
+        let dry_run = DryRunBlockStore::new(&store);
+        let cid = dry_run.put_block(b"new".to_vec(), CODEC_RAW).await?;
+
+        dry_run.discard();
+        dry_run.commit().await?;
+
+        assert!(!dry_run.has_block(&cid).await?);
+        assert!(!store.has_block(&cid).await?);
+
+        Ok(())
+    }
+}
@@ -19,6 +19,43 @@ pub enum BlockStoreError {
     #[error("CID error during blockstore operation: {0}")]
     CIDError(#[from] cid::Error),
 
+    #[error("Block doesn't match its CID {0}")]
+    CidMismatch(Cid),
+
+    #[error("Transient block store failure: {0}")]
+    Transient(anyhow::Error),
+
     #[error(transparent)]
     Custom(#[from] anyhow::Error),
 }
+
+/// Returned by operations that were cancelled through a
+/// [`CancellationToken`](crate::utils::CancellationToken).
+#[derive(Debug, Error)]
+#[error("Operation was cancelled")]
+pub struct Cancelled;
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl BlockStoreError {
+    /// Whether the block store doesn't have the requested block.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::CIDNotFound(_))
+    }
+
+    /// Whether the operation failed for reasons that may go away by themselves,
+    /// like network failures or rate limits, so retrying it may succeed.
+    ///
+    /// Block stores opt into this by returning [`BlockStoreError::Transient`].
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Transient(_))
+    }
+
+    /// Whether the operation was stopped through a
+    /// [`CancellationToken`](crate::utils::CancellationToken).
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Custom(error) if error.is::<Cancelled>())
+    }
+}
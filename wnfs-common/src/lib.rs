@@ -1,19 +1,29 @@
 //! This crate contains the common types and functions used by the WNFS crates.
 pub mod blockstore;
+mod car;
+mod dry_run;
 mod encoding;
 mod error;
 mod link;
 mod metadata;
 mod pathnodes;
+mod replication;
+mod retry;
+mod size_limit;
 mod storable;
 pub mod utils;
 
 pub use blockstore::*;
+pub use car::*;
+pub use dry_run::*;
 pub use encoding::*;
 pub use error::*;
 pub use link::*;
 pub use metadata::*;
 pub use pathnodes::*;
+pub use replication::*;
+pub use retry::*;
+pub use size_limit::*;
 pub use storable::*;
 
 //--------------------------------------------------------------------------------------------------
@@ -15,7 +15,10 @@ use std::{collections::BTreeMap, fmt::Display};
 //--------------------------------------------------------------------------------------------------
 
 /// The type of file system node.
-#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+///
+/// Type strings starting with `wnfs/` are reserved for the node types defined here.
+/// Any other type string is an app-defined node kind and parses as [`NodeType::Custom`].
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NodeType {
     PublicFile,
     PublicDirectory,
@@ -23,6 +26,7 @@ pub enum NodeType {
     PrivateDirectory,
     TemporalSharePointer,
     SnapshotSharePointer,
+    Custom(String),
 }
 
 impl Display for NodeType {
@@ -34,6 +38,7 @@ impl Display for NodeType {
             NodeType::PrivateDirectory => "wnfs/priv/dir",
             NodeType::TemporalSharePointer => "wnfs/share/temporal",
             NodeType::SnapshotSharePointer => "wnfs/share/snapshot",
+            NodeType::Custom(name) => name,
         })
     }
 }
@@ -146,6 +151,37 @@ impl Metadata {
         })
     }
 
+    /// Sets the MIME type of the content this metadata belongs to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs_common::Metadata;
+    /// use chrono::Utc;
+    ///
+    /// let mut metadata = Metadata::new(Utc::now());
+    /// metadata.put_content_type("image/png");
+    ///
+    /// assert_eq!(metadata.get_content_type(), Some("image/png"));
+    /// ```
+    pub fn put_content_type(&mut self, content_type: &str) {
+        self.0
+            .insert("mimeType".into(), Ipld::String(content_type.into()));
+    }
+
+    /// Returns the MIME type of the content, if known.
+    pub fn get_content_type(&self) -> Option<&str> {
+        match self.0.get("mimeType") {
+            Some(Ipld::String(content_type)) => Some(content_type),
+            _ => None,
+        }
+    }
+
+    /// Removes the MIME type of the content, returning the old value.
+    pub fn remove_content_type(&mut self) -> Option<Ipld> {
+        self.0.remove("mimeType")
+    }
+
     /// Inserts a key-value pair into the metadata.
     /// If the key already existed, the value is updated, and the old value is returned.
     ///
@@ -244,6 +280,28 @@ impl Metadata {
     }
 }
 
+impl NodeType {
+    /// Whether given type string falls into the `wnfs/` namespace, which is
+    /// reserved for the node types WNFS itself defines.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs_common::NodeType;
+    ///
+    /// assert!(NodeType::is_reserved("wnfs/pub/dir"));
+    /// assert!(!NodeType::is_reserved("my-app/mount"));
+    /// ```
+    pub fn is_reserved(name: &str) -> bool {
+        name.to_lowercase().starts_with("wnfs/")
+    }
+
+    /// Whether this is an app-defined node type.
+    pub fn is_custom(&self) -> bool {
+        matches!(self, NodeType::Custom(_))
+    }
+}
+
 impl TryFrom<&Ipld> for NodeType {
     type Error = anyhow::Error;
 
@@ -266,6 +324,7 @@ impl TryFrom<&str> for NodeType {
             "wnfs/pub/file" => NodeType::PublicFile,
             "wnfs/share/temporal" => NodeType::TemporalSharePointer,
             "wnfs/share/snapshot" => NodeType::SnapshotSharePointer,
+            _ if !NodeType::is_reserved(name) => NodeType::Custom(name.to_string()),
             _ => bail!("Unknown UnixFsNodeKind: {}", name),
         })
     }
@@ -280,6 +339,7 @@ impl From<&NodeType> for String {
             NodeType::PublicFile => "wnfs/pub/file".into(),
             NodeType::TemporalSharePointer => "wnfs/share/temporal".into(),
             NodeType::SnapshotSharePointer => "wnfs/share/snapshot".into(),
+            NodeType::Custom(name) => name.clone(),
         }
     }
 }
@@ -308,7 +368,7 @@ impl<'de> Deserialize<'de> for NodeType {
 
 #[cfg(test)]
 mod tests {
-    use crate::{decode, encode, Metadata};
+    use crate::{decode, encode, Metadata, NodeType};
     use chrono::Utc;
     use libipld::cbor::DagCborCodec;
 
@@ -321,4 +381,21 @@ mod tests {
 
         assert_eq!(metadata, decoded_metadata);
     }
+
+    #[test]
+    fn unreserved_node_types_parse_as_custom() {
+        assert_eq!(
+            NodeType::try_from("my-app/mount").unwrap(),
+            NodeType::Custom("my-app/mount".into())
+        );
+        assert_eq!(
+            NodeType::try_from("wnfs/pub/dir").unwrap(),
+            NodeType::PublicDirectory
+        );
+        assert!(NodeType::try_from("wnfs/pub/unknown").is_err());
+        assert_eq!(
+            String::from(&NodeType::Custom("my-app/mount".into())),
+            "my-app/mount"
+        );
+    }
 }
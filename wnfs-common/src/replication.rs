@@ -0,0 +1,376 @@
+use crate::{
+    utils::{reachable_blocks, CondSend},
+    BlockStore, BlockStoreError,
+};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use libipld::Cid;
+use parking_lot::Mutex;
+use std::collections::BTreeSet;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How many of its stores a [`ReplicatingBlockStore`] writes each block to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationPolicy {
+    /// How many stores each block should be written to.
+    ///
+    /// Stores are written to in order, so with fewer replicas than stores, the last stores
+    /// are only written to when earlier ones fail.
+    pub replicas: usize,
+    /// How many writes of a block need to succeed for the put to succeed.
+    ///
+    /// Blocks written to at least this many, but fewer than [`replicas`](Self::replicas)
+    /// stores are remembered as under-replicated, so they can be repaired later.
+    pub write_quorum: usize,
+}
+
+/// A block store that writes each block to several underlying stores, like a local disk
+/// and a remote, and reads from whichever has it.
+///
+/// Reads try the stores in order and fail over to the next one when a store doesn't have
+/// the block or fails. Blocks that were only found after failing over are remembered as
+/// under-replicated, just like blocks whose writes didn't reach enough stores.
+/// [`ReplicatingBlockStore::repair`] copies them to the stores that are missing them.
+///
+/// All stores have to be of the same type. Combine different kinds of stores with an enum
+/// that implements [`BlockStore`] by delegating to its variants.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::{
+///     BlockStore, MemoryBlockStore, ReplicatingBlockStore, ReplicationPolicy, CODEC_RAW,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let policy = ReplicationPolicy {
+///         replicas: 2,
+///         write_quorum: 1,
+///     };
+///     let stores = (0..3).map(|_| MemoryBlockStore::new()).collect();
+///     let store = ReplicatingBlockStore::new(stores, policy);
+///
+///     let cid = store.put_block(b"hello".to_vec(), CODEC_RAW).await.unwrap();
+///
+///     assert!(store.stores()[0].has_block(&cid).await.unwrap());
+///     assert!(store.stores()[1].has_block(&cid).await.unwrap());
+///     assert!(!store.stores()[2].has_block(&cid).await.unwrap());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ReplicatingBlockStore<B: BlockStore> {
+    stores: Vec<B>,
+    policy: ReplicationPolicy,
+    under_replicated: Mutex<BTreeSet<Cid>>,
+}
+
+/// The outcome of repairing blocks with [`ReplicatingBlockStore::repair`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// The number of blocks that were checked.
+    pub blocks_checked: usize,
+    /// The number of copies of blocks that were written to stores missing them.
+    pub copies_written: usize,
+    /// The blocks that are still under-replicated, because no store has them or writing
+    /// them failed.
+    pub unrepaired: Vec<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for ReplicationPolicy {
+    fn default() -> Self {
+        Self {
+            replicas: 2,
+            write_quorum: 1,
+        }
+    }
+}
+
+impl<B: BlockStore> ReplicatingBlockStore<B> {
+    /// Replicates blocks across given stores according to given policy.
+    ///
+    /// The stores are written to and read from in the given order, so put the
+    /// cheapest store first.
+    pub fn new(stores: Vec<B>, policy: ReplicationPolicy) -> Self {
+        Self {
+            stores,
+            policy,
+            under_replicated: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Returns the underlying stores.
+    pub fn stores(&self) -> &[B] {
+        &self.stores
+    }
+
+    /// Returns the blocks that were found to be under-replicated so far.
+    pub fn under_replicated(&self) -> Vec<Cid> {
+        self.under_replicated.lock().iter().copied().collect()
+    }
+
+    /// Copies all blocks that were found to be under-replicated so far to the stores
+    /// missing them, until each is in as many stores as the policy asks for.
+    pub async fn repair(&self) -> RepairReport {
+        let cids = self.under_replicated();
+        self.repair_blocks(cids).await
+    }
+
+    /// Checks all blocks reachable from `root` and copies them to the stores missing them,
+    /// until each is in as many stores as the policy asks for.
+    ///
+    /// Blocks are traversed like in [`reachable_blocks`], reading from any store that has
+    /// them. This finds under-replicated blocks that weren't read or written through this
+    /// store, e.g. after a store lost data.
+    pub async fn repair_reachable(&self, root: &Cid) -> Result<RepairReport> {
+        let cids = reachable_blocks(root, self).await?;
+        Ok(self.repair_blocks(cids).await)
+    }
+
+    async fn repair_blocks(&self, cids: impl IntoIterator<Item = Cid>) -> RepairReport {
+        let mut report = RepairReport::default();
+        for cid in cids {
+            report.blocks_checked += 1;
+            match self.repair_block(&cid).await {
+                Ok(copies_written) => {
+                    report.copies_written += copies_written;
+                    self.under_replicated.lock().remove(&cid);
+                }
+                Err(_) => {
+                    report.unrepaired.push(cid);
+                    self.under_replicated.lock().insert(cid);
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Copies a block to the stores missing it until it's in enough stores,
+    /// returning the number of copies written.
+    async fn repair_block(&self, cid: &Cid) -> Result<usize> {
+        let mut missing = Vec::new();
+        let mut replicas = 0;
+        for store in self.stores.iter() {
+            // Stores that fail to answer can't be counted on to have the block
+            if store.has_block(cid).await.unwrap_or(false) {
+                replicas += 1;
+            } else {
+                missing.push(store);
+            }
+        }
+
+        let wanted = self.replicas();
+        if replicas >= wanted {
+            return Ok(0);
+        }
+
+        let bytes = self.get_block(cid).await?;
+        let mut copies_written = 0;
+        for store in missing {
+            if replicas >= wanted {
+                break;
+            }
+
+            if store.put_block_keyed(*cid, bytes.clone()).await.is_ok() {
+                replicas += 1;
+                copies_written += 1;
+            }
+        }
+
+        if replicas < wanted {
+            return Err(anyhow!("Only {replicas} of {wanted} replicas of {cid}"));
+        }
+
+        Ok(copies_written)
+    }
+
+    /// The number of stores each block should be in, at most the number of stores.
+    fn replicas(&self) -> usize {
+        self.policy.replicas.min(self.stores.len())
+    }
+}
+
+impl<B: BlockStore> BlockStore for ReplicatingBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        let mut last_err = BlockStoreError::CIDNotFound(*cid);
+        for (i, store) in self.stores.iter().enumerate() {
+            match store.get_block(cid).await {
+                Ok(bytes) => {
+                    if i > 0 {
+                        self.under_replicated.lock().insert(*cid);
+                    }
+
+                    return Ok(bytes);
+                }
+                // Prefer reporting failures over missing blocks
+                Err(err) if err.is_not_found() => {}
+                Err(err) => last_err = err,
+            }
+        }
+
+        Err(last_err)
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        let bytes = bytes.into();
+        let wanted = self.replicas();
+        let mut replicas = 0;
+        let mut last_err = None;
+        for store in self.stores.iter() {
+            if replicas >= wanted {
+                break;
+            }
+
+            match store.put_block_keyed(cid, bytes.clone()).await {
+                Ok(()) => replicas += 1,
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        if replicas < self.policy.write_quorum.min(wanted).max(1) {
+            return Err(last_err.unwrap_or_else(|| {
+                BlockStoreError::Custom(anyhow!("No stores to replicate {cid} to"))
+            }));
+        }
+
+        if replicas < wanted {
+            self.under_replicated.lock().insert(cid);
+        }
+
+        Ok(())
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        let mut last_err = None;
+        for store in self.stores.iter() {
+            match store.has_block(cid).await {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        match last_err {
+            Some(err) => Err(err),
+            None => Ok(false),
+        }
+    }
+
+    fn max_block_size(&self) -> usize {
+        // Blocks need to fit into every store they might be written to
+        self.stores
+            .iter()
+            .map(|store| store.max_block_size())
+            .min()
+            .unwrap_or(crate::MAX_BLOCK_SIZE)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encode,
+        utils::{Fault, FaultyBlockStore},
+        MemoryBlockStore, CODEC_DAG_CBOR, CODEC_RAW,
+    };
+    use libipld::{cbor::DagCborCodec, ipld};
+
+    fn replicating(
+        count: usize,
+        policy: ReplicationPolicy,
+    ) -> ReplicatingBlockStore<FaultyBlockStore<MemoryBlockStore>> {
+        let stores = (0..count)
+            .map(|_| FaultyBlockStore::new(MemoryBlockStore::new(), ()))
+            .collect();
+        ReplicatingBlockStore::new(stores, policy)
+    }
+
+    #[async_std::test]
+    async fn failed_writes_fall_through_to_later_stores() -> Result<()> {
+        let store = replicating(3, ReplicationPolicy::default());
+        store.stores()[0].inject_at(0, Fault::Fail);
+
+        let cid = store.put_block(b"hello".to_vec(), CODEC_RAW).await?;
+
+        assert!(!store.stores()[0].inner().has_block(&cid).await?);
+        assert!(store.stores()[1].inner().has_block(&cid).await?);
+        assert!(store.stores()[2].inner().has_block(&cid).await?);
+        assert!(store.under_replicated().is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn writes_below_the_quorum_fail() -> Result<()> {
+        let policy = ReplicationPolicy {
+            replicas: 2,
+            write_quorum: 2,
+        };
+        let store = replicating(2, policy);
+        store.stores()[1].inject_at(0, Fault::Fail);
+
+        let result = store.put_block(b"hello".to_vec(), CODEC_RAW).await;
+
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reads_fail_over_and_repairs_restore_replicas() -> Result<()> {
+        let store = replicating(2, ReplicationPolicy::default());
+        store.stores()[0].inject_at(0, Fault::Missing);
+        let cid = store.put_block(b"hello".to_vec(), CODEC_RAW).await?;
+        assert!(store.under_replicated().is_empty());
+
+        assert_eq!(store.get_block(&cid).await?, b"hello".to_vec());
+        assert_eq!(store.under_replicated(), vec![cid]);
+
+        let report = store.repair().await;
+        assert_eq!(report.blocks_checked, 1);
+        assert_eq!(report.copies_written, 1);
+        assert!(report.unrepaired.is_empty());
+        assert!(store.under_replicated().is_empty());
+        assert!(store.stores()[0].inner().has_block(&cid).await?);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn repairs_find_lost_blocks_by_traversal() -> Result<()> {
+        let store = replicating(2, ReplicationPolicy::default());
+        let leaf = b"leaf".to_vec();
+        let leaf_cid = store.create_cid(&leaf, CODEC_RAW)?;
+        store.stores()[1].inject_for(leaf_cid, Fault::Missing);
+        store.put_block_keyed(leaf_cid, leaf.clone()).await?;
+        let root = store
+            .put_block(encode(&ipld!([leaf_cid]), DagCborCodec)?, CODEC_DAG_CBOR)
+            .await?;
+        store.stores()[1].clear_faults();
+
+        let report = store.repair_reachable(&root).await?;
+
+        assert_eq!(report.blocks_checked, 2);
+        assert_eq!(report.copies_written, 1);
+        assert_eq!(store.stores()[1].get_block(&leaf_cid).await?, leaf);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{
+    utils::{CondSend, CondSync},
+    BlockStore, BlockStoreError,
+};
+use bytes::Bytes;
+use futures::Future;
+use libipld::Cid;
+use parking_lot::Mutex;
+use rand_core::CryptoRngCore;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Waits between retries of a [`RetryBlockStore`].
+///
+/// This crate doesn't depend on an async runtime, so waiting is left to the caller.
+/// Any closure returning a future, like `async_std::task::sleep`, can be used.
+/// The unit type `()` doesn't wait at all.
+pub trait Sleep: CondSync {
+    /// Waits for given duration.
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + CondSend;
+}
+
+/// How a [`RetryBlockStore`] retries operations that failed with a
+/// [transient error](BlockStoreError::is_transient).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// How often a single operation is retried at most.
+    pub max_retries: u32,
+    /// How long to wait before the first retry.
+    pub initial_delay: Duration,
+    /// The longest time to wait before any retry.
+    pub max_delay: Duration,
+    /// The factor by which the delay grows with each retry of an operation.
+    pub multiplier: f64,
+    /// The fraction of each delay that's randomized, between `0.0` and `1.0`.
+    ///
+    /// With a jitter of `0.5`, delays are shortened by up to half. This keeps
+    /// clients that failed at the same time from retrying at the same time.
+    pub jitter: f64,
+    /// How many retries the store can spend across all operations.
+    ///
+    /// Each retry spends one unit of the budget and each successful operation earns
+    /// one back, up to this limit. Once the budget is spent, operations fail on their
+    /// first error, so a failing backend doesn't get flooded with retries.
+    pub retry_budget: u32,
+}
+
+/// A block store that retries operations on an inner block store when they fail
+/// with a [transient error](BlockStoreError::is_transient), backing off exponentially.
+///
+/// Errors that aren't transient, like missing blocks, are returned right away.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use wnfs_common::{BlockStore, MemoryBlockStore, RetryBlockStore, RetryPolicy, CODEC_RAW};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let rng = &mut rand::thread_rng();
+///     let policy = RetryPolicy {
+///         max_retries: 5,
+///         ..Default::default()
+///     };
+///     let store = RetryBlockStore::new(MemoryBlockStore::new(), policy, async_std::task::sleep, rng);
+///
+///     let cid = store.put_block(b"hello".to_vec(), CODEC_RAW).await.unwrap();
+///     assert_eq!(store.get_block(&cid).await.unwrap(), b"hello".to_vec());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RetryBlockStore<B: BlockStore, S: Sleep = ()> {
+    inner: B,
+    policy: RetryPolicy,
+    sleep: S,
+    budget: Mutex<u32>,
+    jitter_state: AtomicU64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Sleep for () {
+    async fn sleep(&self, _duration: Duration) {}
+}
+
+impl<F, Fut> Sleep for F
+where
+    F: Fn(Duration) -> Fut + CondSync,
+    Fut: Future<Output = ()> + CondSend,
+{
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + CondSend {
+        self(duration)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.5,
+            retry_budget: 100,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before given retry of an operation, counting from zero,
+    /// before applying any jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let secs = self.initial_delay.as_secs_f64()
+            * self.multiplier.powi(retry.min(i32::MAX as u32) as i32);
+        Duration::try_from_secs_f64(secs)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+impl<B: BlockStore, S: Sleep> RetryBlockStore<B, S> {
+    /// Wraps a block store to retry its operations according to given policy.
+    ///
+    /// The randomness is only used to seed the jitter of delays.
+    pub fn new(inner: B, policy: RetryPolicy, sleep: S, rng: &mut impl CryptoRngCore) -> Self {
+        Self {
+            inner,
+            budget: Mutex::new(policy.retry_budget),
+            policy,
+            sleep,
+            jitter_state: AtomicU64::new(rng.next_u64()),
+        }
+    }
+
+    /// Returns the inner block store.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns how many retries are left in the retry budget.
+    pub fn remaining_budget(&self) -> u32 {
+        *self.budget.lock()
+    }
+
+    async fn retry<T, F, Fut>(&self, mut operation: F) -> Result<T, BlockStoreError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, BlockStoreError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Ok(value) => {
+                    let mut budget = self.budget.lock();
+                    *budget = (*budget + 1).min(self.policy.retry_budget);
+                    return Ok(value);
+                }
+                Err(err) if err.is_transient() && retry < self.policy.max_retries => {
+                    if !self.spend_budget() {
+                        return Err(err);
+                    }
+
+                    self.sleep.sleep(self.delay(retry)).await;
+                    retry += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn spend_budget(&self) -> bool {
+        let mut budget = self.budget.lock();
+        if *budget == 0 {
+            return false;
+        }
+
+        *budget -= 1;
+        true
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let jitter = self.policy.jitter.clamp(0.0, 1.0) * self.next_unit_random();
+        self.policy.backoff(retry).mul_f64(1.0 - jitter)
+    }
+
+    /// Draws a number in `[0, 1)` using splitmix64.
+    fn next_unit_random(&self) -> f64 {
+        let mut z = self
+            .jitter_state
+            .fetch_add(0x9E3779B97F4A7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl<B: BlockStore, S: Sleep> BlockStore for RetryBlockStore<B, S> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        self.retry(|| self.inner.get_block(cid)).await
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        let bytes = bytes.into();
+        self.retry(|| self.inner.put_block_keyed(cid, bytes.clone()))
+            .await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        self.retry(|| self.inner.has_block(cid)).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.inner.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        utils::{Fault, FaultyBlockStore},
+        MemoryBlockStore, CODEC_RAW,
+    };
+    use anyhow::Result;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn failing(failures: u64) -> FaultyBlockStore<MemoryBlockStore> {
+        let store = FaultyBlockStore::new(MemoryBlockStore::new(), ());
+        for operation in 0..failures {
+            store.inject_at(operation, Fault::Transient);
+        }
+
+        store
+    }
+
+    #[async_std::test]
+    async fn transient_errors_are_retried() -> Result<()> {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let store = RetryBlockStore::new(failing(3), Default::default(), (), rng);
+
+        let cid = store.put_block(b"hello".to_vec(), CODEC_RAW).await?;
+
+        assert_eq!(store.inner().operation_count(), 4);
+        assert_eq!(store.get_block(&cid).await?, b"hello".to_vec());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn missing_blocks_are_not_retried() -> Result<()> {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let store = RetryBlockStore::new(failing(0), Default::default(), (), rng);
+        let cid = store.create_cid(b"missing", CODEC_RAW)?;
+
+        let err = store.get_block(&cid).await.unwrap_err();
+
+        assert!(err.is_not_found());
+        assert_eq!(store.inner().operation_count(), 1);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn spent_retry_budgets_fail_fast() -> Result<()> {
+        let rng = &mut StdRng::seed_from_u64(0);
+        let policy = RetryPolicy {
+            max_retries: 10,
+            retry_budget: 2,
+            ..Default::default()
+        };
+        let store = RetryBlockStore::new(failing(5), policy, (), rng);
+
+        let err = store.has_block(&Cid::default()).await.unwrap_err();
+
+        assert!(err.is_transient());
+        assert_eq!(store.inner().operation_count(), 3);
+        assert_eq!(store.remaining_budget(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+}
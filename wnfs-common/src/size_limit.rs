@@ -0,0 +1,113 @@
+use crate::{utils::CondSend, BlockStore, BlockStoreError};
+use bytes::Bytes;
+use libipld::Cid;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A block store that only accepts blocks up to a configured size, for syncing with networks
+/// that don't transfer larger blocks.
+///
+/// Every block put into this store is checked against the limit, including blocks put with
+/// [`put_block_keyed`](BlockStore::put_block_keyed). The inner store still checks blocks
+/// against its own limit, so limits can only be lowered. Directories split their entries into
+/// several blocks to stay within the limit, while HAMT nodes are bounded by their branching
+/// factor anyway.
+///
+/// Private file content is split into blocks of up to [`MAX_BLOCK_SIZE`](crate::MAX_BLOCK_SIZE),
+/// so limits below that only work for files smaller than the limit.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::{BlockStore, MemoryBlockStore, SizeLimitedBlockStore, CODEC_RAW};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = SizeLimitedBlockStore::new(MemoryBlockStore::new(), 16 * 1024);
+///
+///     assert!(store.put_block(vec![0; 16 * 1024], CODEC_RAW).await.is_ok());
+///     assert!(store.put_block(vec![0; 16 * 1024 + 1], CODEC_RAW).await.is_err());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SizeLimitedBlockStore<B: BlockStore> {
+    inner: B,
+    max_block_size: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<B: BlockStore> SizeLimitedBlockStore<B> {
+    /// Wraps a block store, limiting the size of blocks put into it to `max_block_size` bytes,
+    /// or to the limit of the inner store if that's lower.
+    pub fn new(inner: B, max_block_size: usize) -> Self {
+        Self {
+            inner,
+            max_block_size,
+        }
+    }
+
+    /// Returns the inner block store.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+}
+
+impl<B: BlockStore> BlockStore for SizeLimitedBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        self.inner.get_block(cid).await
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        let bytes = bytes.into();
+        self.check_block_size(&bytes)?;
+        self.inner.put_block_keyed(cid, bytes).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        self.inner.has_block(cid).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.max_block_size.min(self.inner.max_block_size())
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryBlockStore, CODEC_RAW, MAX_BLOCK_SIZE};
+
+    #[async_std::test]
+    async fn limits_apply_to_keyed_puts_and_never_exceed_the_inner_limit() {
+        let store = SizeLimitedBlockStore::new(MemoryBlockStore::new(), MAX_BLOCK_SIZE * 4);
+        let large = vec![1; MAX_BLOCK_SIZE * 2];
+        assert_eq!(store.max_block_size(), MAX_BLOCK_SIZE);
+        assert!(store.put_block(large, CODEC_RAW).await.is_err());
+
+        let store = SizeLimitedBlockStore::new(MemoryBlockStore::new(), 4);
+        let cid = store.create_cid(b"hello", CODEC_RAW).unwrap();
+        let result = store.put_block_keyed(cid, b"hello".to_vec()).await;
+        assert!(matches!(
+            result,
+            Err(BlockStoreError::MaximumBlockSizeExceeded(5))
+        ));
+        assert!(!store.inner().has_block(&cid).await.unwrap());
+    }
+}
@@ -0,0 +1,198 @@
+use super::{Arc, CondSend};
+use crate::{BlockStore, BlockStoreError, Cancelled};
+use anyhow::Result;
+use bytes::Bytes;
+use futures::AsyncRead;
+use libipld::Cid;
+use std::{
+    io,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A token for cooperatively cancelling long-running operations.
+///
+/// Clones of a token share their state, so one of them can be handed to an
+/// operation while another one is kept around to cancel it. Operations check
+/// the token between steps and fail with [`Cancelled`] once it's cancelled.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::utils::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+///
+/// assert!(token.check().is_ok());
+/// handle.cancel();
+/// assert!(token.check().is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+/// Wraps an [`AsyncRead`] so reading from it fails once a [`CancellationToken`] is cancelled.
+///
+/// Functions that stream content in read it fully before they modify the
+/// private forest, so passing one of these cancels them without leaving
+/// any changes behind.
+#[derive(Debug)]
+pub struct CancellableReader<R> {
+    inner: R,
+    token: CancellationToken,
+}
+
+/// Wraps a [`BlockStore`] so getting, putting or checking for blocks fails once a
+/// [`CancellationToken`] is cancelled.
+///
+/// Searches for the latest revision of a node, forest diffs and merges, path lookups and
+/// chunked writes fetch or put blocks on every step, so running them on one of these stops
+/// them at their next step. Steps that only touch blocks that were loaded before don't
+/// check the token.
+#[derive(Debug)]
+pub struct CancellableBlockStore<B> {
+    inner: B,
+    token: CancellationToken,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl CancellationToken {
+    /// Creates a new token that isn't cancelled yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations this token (or any of its clones) was passed to.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Whether this token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Fails with [`Cancelled`] if this token was cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+
+        Ok(())
+    }
+
+    /// Wraps `reader`, so reading from it fails once this token is cancelled.
+    pub fn reader<R>(&self, reader: R) -> CancellableReader<R> {
+        CancellableReader {
+            inner: reader,
+            token: self.clone(),
+        }
+    }
+
+    /// Wraps `store`, so accessing blocks in it fails once this token is cancelled.
+    pub fn store<B: BlockStore>(&self, store: B) -> CancellableBlockStore<B> {
+        CancellableBlockStore {
+            inner: store,
+            token: self.clone(),
+        }
+    }
+
+    fn check_store(&self) -> Result<(), BlockStoreError> {
+        if self.is_cancelled() {
+            return Err(BlockStoreError::Custom(Cancelled.into()));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CancellableReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.token.is_cancelled() {
+            return Poll::Ready(Err(io::Error::other(Cancelled)));
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<B: BlockStore> BlockStore for CancellableBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        self.token.check_store()?;
+        self.inner.get_block(cid).await
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        self.token.check_store()?;
+        self.inner.put_block_keyed(cid, bytes).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        self.token.check_store()?;
+        self.inner.has_block(cid).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.inner.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryBlockStore, CODEC_RAW};
+    use futures::AsyncReadExt;
+
+    #[async_std::test]
+    async fn cancellable_reader_fails_after_cancellation() {
+        let token = CancellationToken::new();
+        let content = [1u8; 8];
+        let reader = &mut token.reader(&content[..]);
+        let buffer = &mut [0u8; 4];
+
+        assert_eq!(reader.read(buffer).await.unwrap(), 4);
+        token.cancel();
+
+        let error = reader.read(buffer).await.unwrap_err();
+        assert!(error.into_inner().unwrap().is::<Cancelled>());
+    }
+
+    #[async_std::test]
+    async fn cancellable_store_fails_after_cancellation() {
+        let token = CancellationToken::new();
+        let store = &token.store(MemoryBlockStore::new());
+        let cid = store.put_block(vec![1, 2, 3], CODEC_RAW).await.unwrap();
+        assert!(store.has_block(&cid).await.unwrap());
+
+        token.cancel();
+
+        let error = store.get_block(&cid).await.unwrap_err();
+        assert!(error.is_cancelled());
+        assert!(store.put_block(vec![4], CODEC_RAW).await.is_err());
+    }
+}
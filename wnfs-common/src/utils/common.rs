@@ -1,9 +1,12 @@
 use super::Arc;
-use crate::HashOutput;
-use anyhow::Result;
+use crate::{BlockStoreError, HashOutput};
+use anyhow::{anyhow, Result};
 use bytes::Bytes;
 use futures::{AsyncRead, AsyncReadExt};
-use libipld::{Cid, IpldCodec};
+use libipld::{
+    multihash::{Code, MultihashDigest},
+    Cid, IpldCodec,
+};
 use parking_lot::Mutex;
 use rand_core::CryptoRngCore;
 use serde::{Deserialize, Serialize, Serializer};
@@ -81,6 +84,28 @@ pub fn to_hash_output(bytes: &[u8]) -> HashOutput {
 }
 
 /// Tries to convert a u64 value to IPLD codec.
+/// Checks that given bytes hash to given CID, with whichever hash function the CID uses.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::{utils, BlockStore, MemoryBlockStore, CODEC_RAW};
+///
+/// let cid = MemoryBlockStore::new().create_cid(b"hello", CODEC_RAW).unwrap();
+///
+/// assert!(utils::verify_block(&cid, b"hello").is_ok());
+/// assert!(utils::verify_block(&cid, b"tampered").is_err());
+/// ```
+pub fn verify_block(cid: &Cid, bytes: &[u8]) -> Result<(), BlockStoreError> {
+    let code = Code::try_from(cid.hash().code())
+        .map_err(|e| anyhow!("Cannot verify block {cid} with unsupported hash: {e:?}"))?;
+    if code.digest(bytes) != *cid.hash() {
+        return Err(BlockStoreError::CidMismatch(*cid));
+    }
+
+    Ok(())
+}
+
 pub fn u64_to_ipld(value: u64) -> Result<IpldCodec> {
     Ok(value.try_into()?)
 }
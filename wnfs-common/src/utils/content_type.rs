@@ -0,0 +1,111 @@
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Content types recognized by their leading magic bytes.
+const MAGIC_BYTES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"II*\0", "image/tiff"),
+    (b"MM\0*", "image/tiff"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (b"\0asm", "application/wasm"),
+    (b"ID3", "audio/mpeg"),
+    (b"fLaC", "audio/flac"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+];
+
+/// Content types of RIFF containers, by the form type at offset 8.
+const RIFF_FORMS: &[(&[u8], &str)] = &[
+    (b"WEBP", "image/webp"),
+    (b"WAVE", "audio/wav"),
+    (b"AVI ", "video/x-msvideo"),
+];
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Detects the MIME type of some content from its leading magic bytes.
+///
+/// Only looks at the first few bytes, so a prefix of the content is enough.
+/// Returns `None` for content without a recognized signature, including plain text.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::utils::sniff_content_type;
+///
+/// assert_eq!(sniff_content_type(b"%PDF-1.7\n..."), Some("application/pdf"));
+/// assert_eq!(sniff_content_type(b"Hello, world!"), None);
+/// ```
+pub fn sniff_content_type(content: &[u8]) -> Option<&'static str> {
+    if let Some((_, content_type)) = MAGIC_BYTES
+        .iter()
+        .find(|(magic, _)| content.starts_with(magic))
+    {
+        return Some(content_type);
+    }
+
+    if content.starts_with(b"RIFF") && content.len() >= 12 {
+        let form = &content[8..12];
+        return RIFF_FORMS
+            .iter()
+            .find(|(magic, _)| *magic == form)
+            .map(|(_, content_type)| *content_type);
+    }
+
+    // ISO base media files start with the size of an `ftyp` box
+    if content.len() >= 12 && &content[4..8] == b"ftyp" {
+        return Some(match &content[8..12] {
+            b"avif" => "image/avif",
+            b"heic" | b"heix" => "image/heic",
+            b"qt  " => "video/quicktime",
+            b"M4A " => "audio/mp4",
+            _ => "video/mp4",
+        });
+    }
+
+    None
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_formats_are_told_apart() {
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WAVEfmt "),
+            Some("audio/wav")
+        );
+        assert_eq!(sniff_content_type(b"RIFF\0\0\0\0????"), None);
+        assert_eq!(
+            sniff_content_type(b"\0\0\0\x20ftypisom\0\0\x02\0"),
+            Some("video/mp4")
+        );
+        assert_eq!(
+            sniff_content_type(b"\0\0\0\x1cftypavif"),
+            Some("image/avif")
+        );
+        assert_eq!(sniff_content_type(b"RIFF"), None);
+        assert_eq!(sniff_content_type(b""), None);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::BlockStore;
+use anyhow::Result;
+use libipld::{prelude::References, Cid, Ipld, IpldCodec};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::Cursor,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The blocks reachable from any of a set of named roots, e.g. the root trees of several
+/// users and the forests of their snapshots, which all have to be kept by garbage collection.
+///
+/// Besides the unified set of reachable blocks, this keeps track of which blocks are only
+/// reachable from a single root, to report how much each root keeps alive on its own, i.e.
+/// how much could be collected by dropping it.
+///
+/// Blocks are only kept as long as needed for finding their links, so memory stays
+/// proportional to the number of reachable blocks, no matter how many roots share them.
+///
+/// # Examples
+///
+/// ```
+/// use libipld::{cbor::DagCborCodec, ipld};
+/// use wnfs_common::{
+///     encode, utils::ReachableBlocks, BlockStore, MemoryBlockStore, CODEC_DAG_CBOR, CODEC_RAW,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let shared = store.put_block(b"shared".to_vec(), CODEC_RAW).await.unwrap();
+///     let own = store.put_block(b"own".to_vec(), CODEC_RAW).await.unwrap();
+///     let put_node = |links| async move {
+///         let bytes = encode(&links, DagCborCodec).unwrap();
+///         store.put_block(bytes, CODEC_DAG_CBOR).await.unwrap()
+///     };
+///     let latest = put_node(ipld!([shared, own])).await;
+///     let snapshot = put_node(ipld!([shared])).await;
+///
+///     let roots = [("latest".into(), latest), ("snapshot".into(), snapshot)];
+///     let reachable = ReachableBlocks::scan(roots, store).await.unwrap();
+///
+///     assert_eq!(reachable.len(), 4);
+///     assert!(reachable.contains(&shared));
+///     assert_eq!(reachable.shared_bytes(), b"shared".len());
+///     assert_eq!(reachable.root_usage()[0].exclusive_blocks, 2);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReachableBlocks {
+    roots: Vec<(String, Cid)>,
+    blocks: BTreeMap<Cid, ReachableBlock>,
+}
+
+/// How much of the blocks in a [`ReachableBlocks`] set is only reachable from one of its roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootUsage {
+    /// The name the root was given.
+    pub name: String,
+    /// The CID of the root.
+    pub root: Cid,
+    /// The number of blocks that are reachable from this root, but no other.
+    pub exclusive_blocks: usize,
+    /// The total size of the blocks that are reachable from this root, but no other.
+    pub exclusive_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReachableBlock {
+    /// The index of the only root the block is reachable from, or `None` if it's shared.
+    owner: Option<usize>,
+    size: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ReachableBlocks {
+    /// Finds all blocks reachable from given roots, following links like [`reachable_blocks`].
+    ///
+    /// Each block is fetched once. Blocks that turn out to be reachable from another root as
+    /// well only have their links fetched again, once, to mark everything below them as shared.
+    pub async fn scan(
+        roots: impl IntoIterator<Item = (String, Cid)>,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let roots: Vec<_> = roots.into_iter().collect();
+        let mut blocks = BTreeMap::new();
+        for (index, (_, root)) in roots.iter().enumerate() {
+            let mut frontier = VecDeque::from([*root]);
+            while let Some(cid) = frontier.pop_front() {
+                match blocks.get_mut(&cid) {
+                    None => {
+                        let bytes = store.get_block(&cid).await?;
+                        frontier.extend(decode_links(&cid, &bytes)?);
+                        let block = ReachableBlock {
+                            owner: Some(index),
+                            size: bytes.len(),
+                        };
+                        blocks.insert(cid, block);
+                    }
+                    // Earlier roots were scanned completely, so everything below a block
+                    // of theirs was found already and only needs to be marked as shared
+                    Some(ReachableBlock {
+                        owner: owner @ Some(_),
+                        ..
+                    }) if *owner != Some(index) => {
+                        *owner = None;
+                        frontier.extend(block_links(&cid, store).await?);
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        Ok(Self { roots, blocks })
+    }
+
+    /// Whether the block with given CID is reachable from any of the roots, i.e. whether
+    /// it needs to be kept.
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.blocks.contains_key(cid)
+    }
+
+    /// Returns the CIDs of all reachable blocks, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Cid> {
+        self.blocks.keys()
+    }
+
+    /// Returns the number of reachable blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Whether no blocks are reachable, i.e. there are no roots.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns the total size of all reachable blocks.
+    pub fn total_bytes(&self) -> usize {
+        self.blocks.values().map(|block| block.size).sum()
+    }
+
+    /// Returns the total size of the blocks that are reachable from more than one root.
+    pub fn shared_bytes(&self) -> usize {
+        self.blocks
+            .values()
+            .filter(|block| block.owner.is_none())
+            .map(|block| block.size)
+            .sum()
+    }
+
+    /// Reports how much each root keeps alive on its own, in the order the roots were given.
+    pub fn root_usage(&self) -> Vec<RootUsage> {
+        let mut usage: Vec<_> = self
+            .roots
+            .iter()
+            .map(|(name, root)| RootUsage {
+                name: name.clone(),
+                root: *root,
+                exclusive_blocks: 0,
+                exclusive_bytes: 0,
+            })
+            .collect();
+
+        for block in self.blocks.values() {
+            if let Some(owner) = block.owner {
+                usage[owner].exclusive_blocks += 1;
+                usage[owner].exclusive_bytes += block.size;
+            }
+        }
+
+        usage
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Collects the CIDs of all blocks reachable from `root`, including `root` itself.
+///
+/// Links are followed in any block with a codec that libipld knows how to decode,
+/// e.g. dag-cbor. Raw blocks, like the ciphertexts in a private forest, are leaves.
+///
+/// # Examples
+///
+/// ```
+/// use libipld::{cbor::DagCborCodec, ipld, Ipld};
+/// use wnfs_common::{encode, utils::reachable_blocks, BlockStore, MemoryBlockStore, CODEC_DAG_CBOR, CODEC_RAW};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let leaf = store.put_block(b"leaf".to_vec(), CODEC_RAW).await.unwrap();
+///     let root = store
+///         .put_block(encode(&ipld!([leaf]), DagCborCodec).unwrap(), CODEC_DAG_CBOR)
+///         .await
+///         .unwrap();
+///
+///     let reachable = reachable_blocks(&root, store).await.unwrap();
+///
+///     assert_eq!(reachable.len(), 2);
+///     assert!(reachable.contains(&leaf));
+/// }
+/// ```
+pub async fn reachable_blocks(root: &Cid, store: &impl BlockStore) -> Result<BTreeSet<Cid>> {
+    let mut visited = BTreeSet::new();
+    walk_blocks(root, &BTreeSet::new(), &mut visited, store).await?;
+    Ok(visited)
+}
+
+/// Collects the CIDs of all blocks reachable from `root` that aren't reachable
+/// from any of the blocks in `known`, in the order they're first visited.
+///
+/// `known` should be closed under reachability, e.g. the result of
+/// [`reachable_blocks`]. Since blocks are content-addressed, everything
+/// below a known block is known as well, so those parts of the DAG are
+/// never fetched.
+pub async fn reachable_blocks_except(
+    root: &Cid,
+    known: &BTreeSet<Cid>,
+    store: &impl BlockStore,
+) -> Result<Vec<Cid>> {
+    let mut visited = BTreeSet::new();
+    walk_blocks(root, known, &mut visited, store).await
+}
+
+async fn walk_blocks(
+    root: &Cid,
+    known: &BTreeSet<Cid>,
+    visited: &mut BTreeSet<Cid>,
+    store: &impl BlockStore,
+) -> Result<Vec<Cid>> {
+    let mut frontier = VecDeque::from([*root]);
+    let mut order = Vec::new();
+
+    while let Some(cid) = frontier.pop_front() {
+        if known.contains(&cid) || !visited.insert(cid) {
+            continue;
+        }
+
+        order.push(cid);
+        frontier.extend(block_links(&cid, store).await?);
+    }
+
+    Ok(order)
+}
+
+/// Collects the CIDs that the block with given CID links to, in the order they appear.
+///
+/// Blocks with unknown codecs and raw blocks don't link anywhere, so they aren't fetched.
+pub async fn block_links(cid: &Cid, store: &impl BlockStore) -> Result<Vec<Cid>> {
+    let Ok(codec) = IpldCodec::try_from(cid.codec()) else {
+        return Ok(Vec::new()); // Unknown codecs can't link to anything we could follow
+    };
+
+    if codec == IpldCodec::Raw {
+        return Ok(Vec::new());
+    }
+
+    let bytes = store.get_block(cid).await?;
+    decode_links(cid, &bytes)
+}
+
+/// Decodes the CIDs that given block links to, in the order they appear.
+pub(crate) fn decode_links(cid: &Cid, bytes: &[u8]) -> Result<Vec<Cid>> {
+    let codec = match IpldCodec::try_from(cid.codec()) {
+        Ok(IpldCodec::Raw) | Err(_) => return Ok(Vec::new()),
+        Ok(codec) => codec,
+    };
+
+    let mut links = Vec::new();
+    <Ipld as References<IpldCodec>>::references(codec, &mut Cursor::new(bytes), &mut links)?;
+    Ok(links)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode, MemoryBlockStore, CODEC_DAG_CBOR, CODEC_RAW};
+    use libipld::{cbor::DagCborCodec, ipld};
+
+    #[async_std::test]
+    async fn known_subgraphs_are_skipped() -> Result<()> {
+        let store = &MemoryBlockStore::new();
+        let shared = store.put_block(b"shared".to_vec(), CODEC_RAW).await?;
+        let new = store.put_block(b"new".to_vec(), CODEC_RAW).await?;
+        let put_node = |links: Ipld| async move {
+            store
+                .put_block(encode(&links, DagCborCodec)?, CODEC_DAG_CBOR)
+                .await
+        };
+
+        let old_root = put_node(ipld!([shared])).await?;
+        let new_root = put_node(ipld!([old_root, new, shared])).await?;
+
+        let known = reachable_blocks(&old_root, store).await?;
+        let added = reachable_blocks_except(&new_root, &known, store).await?;
+
+        assert_eq!(known, BTreeSet::from([old_root, shared]));
+        assert_eq!(added, vec![new_root, new]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn blocks_reachable_from_several_roots_are_shared() -> Result<()> {
+        let store = &MemoryBlockStore::new();
+        let leaf = store.put_block(b"leaf".to_vec(), CODEC_RAW).await?;
+        let put_node = |links: Ipld| async move {
+            store
+                .put_block(encode(&links, DagCborCodec)?, CODEC_DAG_CBOR)
+                .await
+        };
+
+        let shared = put_node(ipld!([leaf])).await?;
+        let first = put_node(ipld!([shared])).await?;
+        // Only reaches `leaf` after `shared` was attributed to the first root
+        let second = put_node(ipld!(["second", leaf])).await?;
+        let third = put_node(ipld!(["third", shared])).await?;
+        let roots = [("first", first), ("second", second), ("third", third)];
+
+        let reachable =
+            ReachableBlocks::scan(roots.map(|(name, cid)| (name.into(), cid)), store).await?;
+        let usage = reachable.root_usage();
+
+        assert_eq!(reachable.len(), 5);
+        assert_eq!(
+            reachable.iter().copied().collect::<BTreeSet<_>>(),
+            BTreeSet::from([leaf, shared, first, second, third])
+        );
+        let size = |cid| async move { Ok::<_, anyhow::Error>(store.get_block(&cid).await?.len()) };
+        assert_eq!(
+            reachable.shared_bytes(),
+            size(leaf).await? + size(shared).await?
+        );
+        assert_eq!(
+            usage.iter().map(|u| u.exclusive_blocks).collect::<Vec<_>>(),
+            vec![1, 1, 1]
+        );
+        assert_eq!(usage[1].exclusive_bytes, size(second).await?);
+        assert_eq!(
+            reachable.total_bytes(),
+            reachable.shared_bytes() + usage.iter().map(|u| u.exclusive_bytes).sum::<usize>()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{utils::CondSend, BlockStore, BlockStoreError, Sleep};
+use anyhow::anyhow;
+use bytes::Bytes;
+use libipld::Cid;
+use parking_lot::Mutex;
+use std::{collections::HashMap, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A fault that a [`FaultyBlockStore`] injects into an operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Fails the operation with an error that isn't transient.
+    Fail,
+    /// Fails the operation with a [transient error](BlockStoreError::is_transient).
+    Transient,
+    /// Behaves as if the block was lost: `get_block` fails with
+    /// [`BlockStoreError::CIDNotFound`], `has_block` returns `false`
+    /// and `put_block` succeeds without storing anything.
+    Missing,
+    /// Flips the bits of the first byte of a block: `get_block` returns corrupted
+    /// bytes and `put_block` stores them. `has_block` isn't affected.
+    Corrupt,
+    /// Waits for given duration, then runs the operation normally.
+    Delay(Duration),
+}
+
+/// A block store for tests that injects scripted faults into the operations
+/// on an inner block store.
+///
+/// Faults can be injected into the n-th operation on the store, counting `get_block`,
+/// `put_block` and `has_block` calls from zero, or into all operations on a CID.
+/// Given the same sequence of operations, the same faults are injected every time.
+///
+/// Delays are waited for with the given [`Sleep`], so the unit type `()` skips them.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::{
+///     utils::{Fault, FaultyBlockStore},
+///     BlockStore, MemoryBlockStore, CODEC_RAW,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = FaultyBlockStore::new(MemoryBlockStore::new(), ());
+///     let cid = store.put_block(b"hello".to_vec(), CODEC_RAW).await.unwrap();
+///
+///     store.inject_at(1, Fault::Transient);
+///     store.inject_for(cid, Fault::Corrupt);
+///
+///     assert!(store.get_block(&cid).await.unwrap_err().is_transient());
+///     assert_ne!(store.get_block(&cid).await.unwrap(), b"hello".to_vec());
+///     assert_eq!(store.operation_count(), 3);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FaultyBlockStore<B: BlockStore, S: Sleep = ()> {
+    inner: B,
+    sleep: S,
+    script: Mutex<FaultScript>,
+}
+
+#[derive(Debug, Default)]
+struct FaultScript {
+    operation_count: u64,
+    at_operation: HashMap<u64, Fault>,
+    for_cid: HashMap<Cid, Fault>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<B: BlockStore, S: Sleep> FaultyBlockStore<B, S> {
+    /// Wraps a block store, without injecting any faults yet.
+    pub fn new(inner: B, sleep: S) -> Self {
+        Self {
+            inner,
+            sleep,
+            script: Mutex::new(FaultScript::default()),
+        }
+    }
+
+    /// Returns the inner block store.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Injects a fault into the n-th operation on this store, counting from zero.
+    ///
+    /// This takes precedence over faults injected for CIDs.
+    pub fn inject_at(&self, operation: u64, fault: Fault) {
+        self.script.lock().at_operation.insert(operation, fault);
+    }
+
+    /// Injects a fault into all following operations on given CID.
+    pub fn inject_for(&self, cid: Cid, fault: Fault) {
+        self.script.lock().for_cid.insert(cid, fault);
+    }
+
+    /// Removes all faults that haven't been injected yet.
+    pub fn clear_faults(&self) {
+        let mut script = self.script.lock();
+        script.at_operation.clear();
+        script.for_cid.clear();
+    }
+
+    /// Returns the number of operations run on this store so far.
+    pub fn operation_count(&self) -> u64 {
+        self.script.lock().operation_count
+    }
+
+    /// Counts an operation and looks up the fault to inject into it.
+    /// Delays are waited for right away, since they don't change the operation.
+    async fn next_fault(&self, cid: &Cid) -> Option<Fault> {
+        let fault = {
+            let mut script = self.script.lock();
+            let operation = script.operation_count;
+            script.operation_count += 1;
+            script
+                .at_operation
+                .remove(&operation)
+                .or_else(|| script.for_cid.get(cid).cloned())
+        };
+
+        match fault {
+            Some(Fault::Delay(duration)) => {
+                self.sleep.sleep(duration).await;
+                None
+            }
+            fault => fault,
+        }
+    }
+}
+
+impl<B: BlockStore, S: Sleep> BlockStore for FaultyBlockStore<B, S> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        match self.next_fault(cid).await {
+            Some(Fault::Fail) => Err(injected_failure()),
+            Some(Fault::Transient) => Err(injected_transient_failure()),
+            Some(Fault::Missing) => Err(BlockStoreError::CIDNotFound(*cid)),
+            Some(Fault::Corrupt) => Ok(corrupt(self.inner.get_block(cid).await?)),
+            Some(Fault::Delay(_)) | None => self.inner.get_block(cid).await,
+        }
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        match self.next_fault(&cid).await {
+            Some(Fault::Fail) => Err(injected_failure()),
+            Some(Fault::Transient) => Err(injected_transient_failure()),
+            Some(Fault::Missing) => Ok(()),
+            Some(Fault::Corrupt) => self.inner.put_block_keyed(cid, corrupt(bytes.into())).await,
+            Some(Fault::Delay(_)) | None => self.inner.put_block_keyed(cid, bytes).await,
+        }
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        match self.next_fault(cid).await {
+            Some(Fault::Fail) => Err(injected_failure()),
+            Some(Fault::Transient) => Err(injected_transient_failure()),
+            Some(Fault::Missing) => Ok(false),
+            Some(Fault::Corrupt | Fault::Delay(_)) | None => self.inner.has_block(cid).await,
+        }
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.inner.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn injected_failure() -> BlockStoreError {
+    BlockStoreError::Custom(anyhow!("Injected block store failure"))
+}
+
+fn injected_transient_failure() -> BlockStoreError {
+    BlockStoreError::Transient(anyhow!("Injected transient block store failure"))
+}
+
+fn corrupt(bytes: Bytes) -> Bytes {
+    let mut bytes = bytes.to_vec();
+    match bytes.first_mut() {
+        Some(byte) => *byte ^= 0xFF,
+        None => bytes.push(0xFF),
+    }
+
+    bytes.into()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryBlockStore, CODEC_RAW};
+    use anyhow::Result;
+
+    #[async_std::test]
+    async fn operation_faults_are_injected_once() -> Result<()> {
+        let store = FaultyBlockStore::new(MemoryBlockStore::new(), ());
+        store.inject_at(0, Fault::Missing);
+        store.inject_at(2, Fault::Fail);
+
+        let cid = store.put_block(b"lost".to_vec(), CODEC_RAW).await?;
+        assert!(!store.has_block(&cid).await?);
+        assert!(store.put_block(b"lost".to_vec(), CODEC_RAW).await.is_err());
+
+        store.put_block(b"lost".to_vec(), CODEC_RAW).await?;
+        assert_eq!(store.get_block(&cid).await?, b"lost".to_vec());
+        assert_eq!(store.operation_count(), 5);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn cid_faults_apply_until_cleared() -> Result<()> {
+        let store = FaultyBlockStore::new(MemoryBlockStore::new(), ());
+        let cid = store.put_block(b"flaky".to_vec(), CODEC_RAW).await?;
+        store.inject_for(cid, Fault::Missing);
+
+        for _ in 0..3 {
+            assert!(store.get_block(&cid).await.unwrap_err().is_not_found());
+        }
+
+        store.clear_faults();
+        assert_eq!(store.get_block(&cid).await?, b"flaky".to_vec());
+
+        Ok(())
+    }
+}
@@ -1,9 +1,21 @@
+mod cancel;
 mod common;
+mod content_type;
+mod dag;
+#[cfg(any(test, feature = "test_utils"))]
+mod fault;
+mod progress;
 mod send_sync_poly;
 #[cfg(any(test, feature = "test_utils"))]
 mod test;
 
+pub use cancel::*;
 pub use common::*;
+pub use content_type::*;
+pub use dag::*;
+#[cfg(any(test, feature = "test_utils"))]
+pub use fault::*;
+pub use progress::*;
 pub use send_sync_poly::*;
 #[cfg(any(test, feature = "test_utils"))]
 pub use test::*;
@@ -0,0 +1,157 @@
+use super::CondSync;
+use futures::AsyncRead;
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Receives progress updates from long-running operations.
+///
+/// What's being counted depends on the operation: streaming content in reports bytes,
+/// while operations on file trees report the number of nodes processed.
+/// `total` is `None` if it isn't known upfront.
+///
+/// The unit type `()` can be passed to ignore progress, and any closure of type
+/// `Fn(u64, Option<u64>)` can be used as a reporter.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use wnfs_common::utils::ProgressReporter;
+///
+/// let latest = AtomicU64::new(0);
+/// let reporter = |done, _total| latest.store(done, Ordering::Relaxed);
+///
+/// reporter.on_progress(42, Some(100));
+///
+/// assert_eq!(latest.load(Ordering::Relaxed), 42);
+/// ```
+pub trait ProgressReporter: CondSync {
+    /// Called with the amount of work done so far and the total amount of work, if known.
+    fn on_progress(&self, done: u64, total: Option<u64>);
+}
+
+/// Wraps an [`AsyncRead`] and reports the number of bytes read from it so far.
+///
+/// Any function that streams in content can report progress by being passed one of these.
+///
+/// # Examples
+///
+/// ```
+/// use futures::AsyncReadExt;
+/// use std::sync::Mutex;
+/// use wnfs_common::utils::ProgressReader;
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let content = vec![0u8; 1000];
+///     let updates = Mutex::new(Vec::new());
+///     let reporter = |done, total| updates.lock().unwrap().push((done, total));
+///
+///     let mut reader = ProgressReader::new(&content[..], Some(1000), &reporter);
+///     let mut buffer = Vec::new();
+///     reader.read_to_end(&mut buffer).await.unwrap();
+///
+///     assert_eq!(updates.lock().unwrap().last(), Some(&(1000, Some(1000))));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ProgressReader<'a, R, P: ?Sized> {
+    inner: R,
+    done: u64,
+    total: Option<u64>,
+    reporter: &'a P,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ProgressReporter for () {
+    fn on_progress(&self, _done: u64, _total: Option<u64>) {}
+}
+
+impl<F: Fn(u64, Option<u64>) + CondSync> ProgressReporter for F {
+    fn on_progress(&self, done: u64, total: Option<u64>) {
+        self(done, total)
+    }
+}
+
+impl<'a, R, P: ProgressReporter + ?Sized> ProgressReader<'a, R, P> {
+    /// Wraps `inner`, reporting progress relative to `total` bytes to `reporter`.
+    pub fn new(inner: R, total: Option<u64>, reporter: &'a P) -> Self {
+        Self {
+            inner,
+            done: 0,
+            total,
+            reporter,
+        }
+    }
+
+    /// The number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.done
+    }
+
+    /// Unwraps the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, P> AsyncRead for ProgressReader<'_, R, P>
+where
+    R: AsyncRead + Unpin,
+    P: ProgressReporter + ?Sized,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(bytes_read)) = poll {
+            if bytes_read > 0 {
+                this.done += bytes_read as u64;
+                this.reporter.on_progress(this.done, this.total);
+            }
+        }
+
+        poll
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::AsyncReadExt;
+    use std::sync::Mutex;
+
+    #[async_std::test]
+    async fn progress_reader_reports_every_read() {
+        let content = [7u8; 10];
+        let updates = Mutex::new(Vec::new());
+        let reporter = |done, total| updates.lock().unwrap().push((done, total));
+        let reader = &mut ProgressReader::new(&content[..], None, &reporter);
+
+        let buffer = &mut [0u8; 4];
+        while reader.read(buffer).await.unwrap() > 0 {}
+
+        assert_eq!(reader.bytes_read(), 10);
+        assert_eq!(
+            *updates.lock().unwrap(),
+            vec![(4, None), (8, None), (10, None)]
+        );
+    }
+}
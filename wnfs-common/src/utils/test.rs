@@ -35,6 +35,7 @@ type BlockHandler = Arc<dyn BytesToIpld>;
 pub struct SnapshotBlockStore {
     inner: MemoryBlockStore,
     block_handlers: Arc<Mutex<HashMap<Cid, BlockHandler>>>,
+    decryptors: Arc<Mutex<Vec<BlockHandler>>>,
 }
 
 base64_serde_type!(Base64Standard, base64::engine::general_purpose::STANDARD);
@@ -67,7 +68,12 @@ impl SnapshotBlockStore {
             CODEC_DAG_CBOR => Ipld::decode(DagCborCodec, &mut Cursor::new(bytes))?,
             CODEC_RAW => match self.block_handlers.lock().get(cid) {
                 Some(func) => func.convert(bytes)?,
-                None => Ipld::Bytes(bytes.to_vec()),
+                None => self
+                    .decryptors
+                    .lock()
+                    .iter()
+                    .find_map(|decryptor| decryptor.convert(bytes).ok())
+                    .unwrap_or_else(|| Ipld::Bytes(bytes.to_vec())),
             },
             _ => unimplemented!(),
         };
@@ -109,6 +115,16 @@ impl SnapshotBlockStore {
     pub fn add_block_handler(&mut self, cid: Cid, f: BlockHandler) {
         self.block_handlers.lock().insert(cid, f);
     }
+
+    /// Adds a handler that's tried on all raw blocks without a handler of their own,
+    /// e.g. one that decrypts blocks with a known key.
+    ///
+    /// Handlers fail on blocks they can't decrypt. The first one that succeeds provides
+    /// the block's snapshot value, so with the right keys, snapshots show decrypted
+    /// blocks instead of ciphertexts, even of blocks whose CIDs aren't known upfront.
+    pub fn add_decryptor(&mut self, f: BlockHandler) {
+        self.decryptors.lock().push(f);
+    }
 }
 
 impl BlockStore for SnapshotBlockStore {
@@ -1,11 +1,11 @@
 use super::HashNibbles;
 use crate::{Hasher, Node, Pair, Pointer, HAMT_BITMASK_BIT_SIZE};
 use anyhow::{Ok, Result};
-use async_recursion::async_recursion;
+use futures::{stream, TryStreamExt};
 use serde::{de::DeserializeOwned, Serialize};
 use std::{collections::HashMap, hash::Hash, mem};
 use wnfs_common::{
-    utils::{Arc, CondSync},
+    utils::{boxed_stream, Arc, BoxStream, CondSync},
     BlockStore, Link, Storable,
 };
 
@@ -30,6 +30,52 @@ pub struct KeyValueChange<K, V> {
     pub value2: Option<V>,
 }
 
+/// A stream of changes, each with the hash nibbles of the bucket it belongs to.
+pub(crate) type BucketChangeStream<'a, K, V> =
+    BoxStream<'a, Result<(Vec<u8>, KeyValueChange<K, V>)>>;
+
+/// A step of a [`diff_stream`] that hasn't been taken yet.
+///
+/// Steps are taken depth-first, so only the siblings along the path to the current node
+/// are pending at any time.
+///
+/// Each step knows the hash nibbles leading to it from the diffed nodes, so a diff can be
+/// resumed after the last bucket it yielded changes for.
+enum DiffStep<K, V, H>
+where
+    K: CondSync,
+    V: CondSync,
+    H: Hasher + CondSync,
+{
+    /// Compare two nodes at a given depth, skipping all buckets up to and including `after`.
+    Nodes {
+        main_link: Link<Arc<Node<K, V, H>>>,
+        other_link: Link<Arc<Node<K, V, H>>>,
+        depth: usize,
+        nibbles: Vec<u8>,
+        after: Option<Vec<u8>>,
+    },
+    /// Generate a change for every pair under a pointer that only one side has.
+    Subtree {
+        pointer: Pointer<K, V, H>,
+        r#type: ChangeType,
+        nibbles: Vec<u8>,
+        after: Option<Vec<u8>>,
+    },
+    /// Yield a change of the bucket at given nibbles.
+    Change(Vec<u8>, KeyValueChange<K, V>),
+}
+
+/// Where a pointer is relative to the bucket a diff resumes after.
+enum Position {
+    /// All changes under the pointer were already yielded.
+    Done,
+    /// The diff resumes somewhere under the pointer.
+    Partial,
+    /// None of the changes under the pointer were yielded yet.
+    Pending,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Implementations
 //--------------------------------------------------------------------------------------------------
@@ -51,6 +97,18 @@ impl<K, V> KeyValueChange<K, V> {
     }
 }
 
+impl Position {
+    fn of(nibbles: &[u8], after: Option<&Vec<u8>>) -> Self {
+        match after {
+            Some(after) if after.len() > nibbles.len() && after.starts_with(nibbles) => {
+                Self::Partial
+            }
+            Some(after) if nibbles <= after.as_slice() => Self::Done,
+            _ => Self::Pending,
+        }
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -62,6 +120,8 @@ impl<K, V> KeyValueChange<K, V> {
 /// This is a more expensive operation because it gathers the key value pairs under a node has
 /// been added or removed even though we can simply return a reference to the node itself.
 ///
+/// All changes are collected into memory. Use [`diff_stream`] for large HAMTs.
+///
 /// # Examples
 ///
 /// ```
@@ -113,14 +173,165 @@ where
     diff_helper(main_link, other_link, 1, store).await
 }
 
-#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
-#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+/// Compare two nodes and stream the key-value changes made to the main node.
+///
+/// Yields the same changes in the same order as [`diff`], but nodes are only loaded once
+/// the changes before them have been consumed. Memory use is bounded by the depth of the
+/// HAMTs, not by the number of changes, so this can be used on forests of any size.
+///
+/// # Examples
+///
+/// ```
+/// use futures::TryStreamExt;
+/// use std::sync::Arc;
+/// use wnfs_hamt::{diff_stream, ChangeType, Node};
+/// use wnfs_common::{Link, MemoryBlockStore};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let main_node = &mut Arc::new(Node::<[u8; 4], String>::default());
+///     for i in 0u32..1000 {
+///         main_node
+///             .set(i.to_le_bytes(), i.to_string(), store)
+///             .await
+///             .unwrap();
+///     }
+///
+///     let other_node = Arc::new(Node::<[u8; 4], String>::default());
+///     let mut changes = diff_stream(
+///         Link::from(Arc::clone(main_node)),
+///         Link::from(other_node),
+///         store,
+///     );
+///
+///     let mut count = 0;
+///     while let Some(change) = changes.try_next().await.unwrap() {
+///         assert_eq!(change.r#type, ChangeType::Add);
+///         count += 1;
+///     }
+///
+///     assert_eq!(count, 1000);
+/// }
+/// ```
+pub fn diff_stream<'a, K, V, H>(
+    main_link: Link<Arc<Node<K, V, H>>>,
+    other_link: Link<Arc<Node<K, V, H>>>,
+    store: &'a impl BlockStore,
+) -> BoxStream<'a, Result<KeyValueChange<K, V>>>
+where
+    K: Storable + Clone + Eq + Hash + AsRef<[u8]> + CondSync + 'a,
+    V: Storable + Clone + Eq + CondSync + 'a,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync + 'a,
+{
+    boxed_stream(diff_stream_after(main_link, other_link, None, store).map_ok(|(_, change)| change))
+}
+
+/// Compare two nodes at a given depth and collect the key-value changes made to the main node.
 pub async fn diff_helper<K, V, H>(
     main_link: Link<Arc<Node<K, V, H>>>,
     other_link: Link<Arc<Node<K, V, H>>>,
     depth: usize,
     store: &impl BlockStore,
 ) -> Result<Vec<KeyValueChange<K, V>>>
+where
+    K: Storable + Clone + Eq + Hash + AsRef<[u8]> + CondSync,
+    V: Storable + Clone + Eq + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    let step = DiffStep::Nodes {
+        main_link,
+        other_link,
+        depth,
+        nibbles: vec![],
+        after: None,
+    };
+
+    stream_steps(vec![step], store)
+        .map_ok(|(_, change)| change)
+        .try_collect()
+        .await
+}
+
+/// Streams the changes like [`diff_stream`], each with the hash nibbles of the bucket it
+/// belongs to, and skips all buckets up to and including the one at `after`.
+///
+/// Buckets are yielded in ascending order of their nibbles, so a diff can be resumed later
+/// from the nibbles of the last bucket whose changes were all consumed.
+pub(crate) fn diff_stream_after<'a, K, V, H>(
+    main_link: Link<Arc<Node<K, V, H>>>,
+    other_link: Link<Arc<Node<K, V, H>>>,
+    after: Option<Vec<u8>>,
+    store: &'a impl BlockStore,
+) -> BucketChangeStream<'a, K, V>
+where
+    K: Storable + Clone + Eq + Hash + AsRef<[u8]> + CondSync + 'a,
+    V: Storable + Clone + Eq + CondSync + 'a,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync + 'a,
+{
+    let step = DiffStep::Nodes {
+        main_link,
+        other_link,
+        depth: 1,
+        nibbles: vec![],
+        after,
+    };
+
+    stream_steps(vec![step], store)
+}
+
+fn stream_steps<'a, K, V, H>(
+    steps: Vec<DiffStep<K, V, H>>,
+    store: &'a impl BlockStore,
+) -> BucketChangeStream<'a, K, V>
+where
+    K: Storable + Clone + Eq + Hash + AsRef<[u8]> + CondSync + 'a,
+    V: Storable + Clone + Eq + CondSync + 'a,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync + 'a,
+{
+    boxed_stream(stream::try_unfold(steps, move |mut steps| async move {
+        while let Some(step) = steps.pop() {
+            let next = match step {
+                DiffStep::Change(nibbles, change) => return Ok(Some(((nibbles, change), steps))),
+                DiffStep::Nodes {
+                    main_link,
+                    other_link,
+                    depth,
+                    nibbles,
+                    after,
+                } => nodes_diff(main_link, other_link, depth, nibbles, after, store).await?,
+                DiffStep::Subtree {
+                    pointer,
+                    r#type,
+                    nibbles,
+                    after,
+                } => generate_add_or_remove_changes(pointer, r#type, nibbles, after, store).await?,
+            };
+
+            // Pushed in reverse, so the first of them is taken next.
+            steps.extend(next.into_iter().rev());
+        }
+
+        Ok(None)
+    }))
+}
+
+async fn nodes_diff<K, V, H>(
+    main_link: Link<Arc<Node<K, V, H>>>,
+    other_link: Link<Arc<Node<K, V, H>>>,
+    depth: usize,
+    nibbles: Vec<u8>,
+    after: Option<Vec<u8>>,
+    store: &impl BlockStore,
+) -> Result<Vec<DiffStep<K, V, H>>>
 where
     K: Storable + Clone + Eq + Hash + AsRef<[u8]> + CondSync,
     V: Storable + Clone + Eq + CondSync,
@@ -140,63 +351,77 @@ where
 
     let mut other_node = other_link.resolve_owned_value(store).await?;
 
-    let mut changes = vec![];
+    let mut steps = vec![];
     for index in 0..HAMT_BITMASK_BIT_SIZE {
+        let child_nibbles = [nibbles.as_slice(), &[index as u8]].concat();
+        let after = match Position::of(&child_nibbles, after.as_ref()) {
+            Position::Done => continue,
+            Position::Partial => after.clone(),
+            Position::Pending => None,
+        };
+
         match (main_node.bitmask[index], other_node.bitmask[index]) {
             (true, false) => {
                 // Main has a value, other doesn't.
-                changes.extend(
-                    generate_add_or_remove_changes(
-                        &main_node.pointers[main_node.get_value_index(index)],
-                        ChangeType::Add,
-                        store,
-                    )
-                    .await?,
-                );
+                steps.push(DiffStep::Subtree {
+                    pointer: take_pointer(&mut main_node, index),
+                    r#type: ChangeType::Add,
+                    nibbles: child_nibbles,
+                    after,
+                });
             }
             (false, true) => {
                 // Main doesn't have a value, other does.
-                changes.extend(
-                    generate_add_or_remove_changes(
-                        &other_node.pointers[other_node.get_value_index(index)],
-                        ChangeType::Remove,
-                        store,
-                    )
-                    .await?,
-                );
+                steps.push(DiffStep::Subtree {
+                    pointer: take_pointer(&mut other_node, index),
+                    r#type: ChangeType::Remove,
+                    nibbles: child_nibbles,
+                    after,
+                });
             }
             (true, true) => {
                 // Main and other have a value. They may be the same or different so we check.
-                let main_index = main_node.get_value_index(index);
-                let main_pointer = mem::take(
-                    Arc::make_mut(&mut main_node)
-                        .pointers
-                        .get_mut(main_index)
-                        .unwrap(),
-                );
-
-                let other_index = other_node.get_value_index(index);
-                let other_pointer = mem::take(
-                    Arc::make_mut(&mut other_node)
-                        .pointers
-                        .get_mut(other_index)
-                        .unwrap(),
+                let main_pointer = take_pointer(&mut main_node, index);
+                let other_pointer = take_pointer(&mut other_node, index);
+
+                steps.extend(
+                    pointers_diff(
+                        main_pointer,
+                        other_pointer,
+                        depth,
+                        child_nibbles,
+                        after,
+                        store,
+                    )
+                    .await?,
                 );
-
-                changes.extend(pointers_diff(main_pointer, other_pointer, depth, store).await?);
             }
             (false, false) => { /* No change */ }
         }
     }
 
-    Ok(changes)
+    Ok(steps)
+}
+
+fn take_pointer<K, V, H>(node: &mut Arc<Node<K, V, H>>, index: usize) -> Pointer<K, V, H>
+where
+    K: Storable + Clone + CondSync,
+    V: Storable + Clone + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    let value_index = node.get_value_index(index);
+    mem::take(Arc::make_mut(node).pointers.get_mut(value_index).unwrap())
 }
 
 async fn generate_add_or_remove_changes<K, V, H>(
-    node_pointer: &Pointer<K, V, H>,
+    node_pointer: Pointer<K, V, H>,
     r#type: ChangeType,
+    nibbles: Vec<u8>,
+    after: Option<Vec<u8>>,
     store: &impl BlockStore,
-) -> Result<Vec<KeyValueChange<K, V>>>
+) -> Result<Vec<DiffStep<K, V, H>>>
 where
     K: Storable + Clone + Eq + Hash + AsRef<[u8]> + CondSync,
     V: Storable + Clone + Eq + CondSync,
@@ -206,29 +431,43 @@ where
 {
     match node_pointer {
         Pointer::Values(values) => Ok(values
-            .iter()
-            .map(|Pair { key, value }| KeyValueChange {
-                r#type,
-                key: key.clone(),
-                value1: Some(value.clone()),
-                value2: None,
+            .into_iter()
+            .map(|Pair { key, value }| {
+                DiffStep::Change(
+                    nibbles.clone(),
+                    KeyValueChange {
+                        r#type,
+                        key,
+                        value1: Some(value),
+                        value2: None,
+                    },
+                )
             })
             .collect()),
         Pointer::Link(link) => {
-            let node = link.resolve_value(store).await?;
-            node.as_ref()
-                .flat_map(
-                    &|Pair { key, value }| {
-                        Ok(KeyValueChange {
-                            r#type,
-                            key: key.clone(),
-                            value1: Some(value.clone()),
-                            value2: None,
-                        })
-                    },
-                    store,
-                )
-                .await
+            let mut node = link.resolve_owned_value(store).await?;
+            let mut steps = vec![];
+            for index in 0..HAMT_BITMASK_BIT_SIZE {
+                if !node.bitmask[index] {
+                    continue;
+                }
+
+                let child_nibbles = [nibbles.as_slice(), &[index as u8]].concat();
+                let after = match Position::of(&child_nibbles, after.as_ref()) {
+                    Position::Done => continue,
+                    Position::Partial => after.clone(),
+                    Position::Pending => None,
+                };
+
+                steps.push(DiffStep::Subtree {
+                    pointer: take_pointer(&mut node, index),
+                    r#type,
+                    nibbles: child_nibbles,
+                    after,
+                });
+            }
+
+            Ok(steps)
         }
     }
 }
@@ -237,8 +476,10 @@ async fn pointers_diff<K, V, H>(
     main_pointer: Pointer<K, V, H>,
     other_pointer: Pointer<K, V, H>,
     depth: usize,
+    nibbles: Vec<u8>,
+    after: Option<Vec<u8>>,
     store: &impl BlockStore,
-) -> Result<Vec<KeyValueChange<K, V>>>
+) -> Result<Vec<DiffStep<K, V, H>>>
 where
     K: Storable + Clone + Eq + Hash + AsRef<[u8]> + CondSync,
     V: Storable + Clone + Eq + CondSync,
@@ -246,10 +487,8 @@ where
     V::Serializable: Serialize + DeserializeOwned,
     H: Hasher + CondSync,
 {
-    match (main_pointer, other_pointer) {
-        (Pointer::Link(main_link), Pointer::Link(other_link)) => {
-            diff_helper(main_link, other_link, depth + 1, store).await
-        }
+    let (main_link, other_link) = match (main_pointer, other_pointer) {
+        (Pointer::Link(main_link), Pointer::Link(other_link)) => (main_link, other_link),
         (Pointer::Values(main_values), Pointer::Values(other_values)) => {
             let mut changes = vec![];
             let mut main_map = HashMap::<&K, &V>::default();
@@ -293,17 +532,28 @@ where
                 }
             }
 
-            Ok(changes)
+            return Ok(changes
+                .into_iter()
+                .map(|change| DiffStep::Change(nibbles.clone(), change))
+                .collect());
         }
         (Pointer::Values(main_values), Pointer::Link(other_link)) => {
             let main_link = Link::from(create_node_from_pairs(main_values, depth, store).await?);
-            diff_helper(main_link, other_link, depth + 1, store).await
+            (main_link, other_link)
         }
         (Pointer::Link(main_link), Pointer::Values(other_values)) => {
             let other_link = Link::from(create_node_from_pairs(other_values, depth, store).await?);
-            diff_helper(main_link, other_link, depth + 1, store).await
+            (main_link, other_link)
         }
-    }
+    };
+
+    Ok(vec![DiffStep::Nodes {
+        main_link,
+        other_link,
+        depth: depth + 1,
+        nibbles,
+        after,
+    }])
 }
 
 async fn create_node_from_pairs<K, V, H>(
@@ -605,6 +855,7 @@ mod proptests {
         ChangeType,
     };
     use async_std::task;
+    use futures::TryStreamExt;
     use proptest::{prop_assert, prop_assert_eq};
     use std::collections::HashSet;
     use test_strategy::proptest;
@@ -677,6 +928,36 @@ mod proptests {
         })?;
     }
 
+    #[proptest(cases = 100)]
+    fn diff_stream_yields_diff_in_order(
+        #[strategy(generate_kvs("[a-z0-9]{1,3}", 0u64..1000, 0..100))] kvs1: Vec<(String, u64)>,
+        #[strategy(generate_kvs("[a-z0-9]{1,3}", 0u64..1000, 0..100))] kvs2: Vec<(String, u64)>,
+    ) {
+        task::block_on(async {
+            let store = &MemoryBlockStore::default();
+
+            let node1 = strategies::node_from_kvs(kvs1, store).await.unwrap();
+            let node2 = strategies::node_from_kvs(kvs2, store).await.unwrap();
+
+            let changes = super::diff(
+                Link::from(Arc::clone(&node1)),
+                Link::from(Arc::clone(&node2)),
+                store,
+            )
+            .await
+            .unwrap();
+
+            let streamed_changes: Vec<_> =
+                super::diff_stream(Link::from(node1), Link::from(node2), store)
+                    .try_collect()
+                    .await
+                    .unwrap();
+
+            prop_assert_eq!(changes, streamed_changes);
+            Ok(())
+        })?;
+    }
+
     #[proptest(cases = 100)]
     fn add_remove_flip(
         #[strategy(generate_kvs("[a-z0-9]{1,3}", 0u64..1000, 0..100))] kvs1: Vec<(String, u64)>,
@@ -6,7 +6,7 @@ use semver::Version;
 use serde::{de::DeserializeOwned, Serialize};
 use std::hash::Hash;
 use wnfs_common::{
-    utils::{Arc, CondSync},
+    utils::{Arc, BoxStream, CondSync},
     BlockStore, Link, Storable,
 };
 
@@ -126,6 +126,28 @@ impl<K: CondSync, V: CondSync, H: Hasher + CondSync> Hamt<K, V, H> {
         )
         .await
     }
+
+    /// Streams the difference between two HAMTs at the key-value level.
+    ///
+    /// See [`diff_stream`](super::diff_stream) for how this differs from [`diff`](Self::diff).
+    pub fn diff_stream<'a>(
+        &self,
+        other: &Self,
+        store: &'a impl BlockStore,
+    ) -> BoxStream<'a, Result<KeyValueChange<K, V>>>
+    where
+        K: Storable + Clone + Eq + Hash + AsRef<[u8]> + 'a,
+        V: Storable + Clone + Eq + 'a,
+        K::Serializable: Serialize + DeserializeOwned,
+        V::Serializable: Serialize + DeserializeOwned,
+        H: 'a,
+    {
+        super::diff_stream(
+            Link::from(Arc::clone(&self.root)),
+            Link::from(Arc::clone(&other.root)),
+            store,
+        )
+    }
 }
 
 impl<K, V, H> Storable for Hamt<K, V, H>
@@ -17,17 +17,21 @@ mod error;
 mod hamt;
 mod hash;
 mod merge;
+mod multivalue;
 mod node;
 mod pointer;
 pub mod serializable;
+mod stats;
 
 pub(crate) use constants::*;
 pub use diff::*;
 pub use hamt::*;
 pub use hash::*;
 pub use merge::*;
+pub use multivalue::*;
 pub use node::*;
 pub use pointer::*;
+pub use stats::*;
 
 #[cfg(any(test, feature = "test_utils"))]
 pub mod strategies;
@@ -1,18 +1,63 @@
-use super::{ChangeType, Node};
+use super::{diff_stream_after, ChangeType, KeyValueChange, Node};
 use crate::{error::HamtError, Hasher};
 use anyhow::Result;
-use serde::{de::DeserializeOwned, Serialize};
+use futures::TryStreamExt;
+use libipld::Cid;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::hash::Hash;
 use wnfs_common::{
     utils::{Arc, CondSync},
     BlockStore, Link, Storable,
 };
 
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Where a [`merge_resumable`] paused, so it can be resumed from there later.
+///
+/// A checkpoint can be serialized and kept across app restarts, as long as the partially
+/// merged node it refers to stays in the block store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeCheckpoint {
+    /// The CID of the partially merged node.
+    pub merged: Cid,
+    /// The hash nibbles of the last HAMT bucket whose changes were all merged.
+    #[serde(with = "serde_bytes")]
+    pub after: Vec<u8>,
+}
+
+/// The outcome of a [`merge_resumable`].
+#[derive(Debug, Clone)]
+pub enum MergeProgress<T> {
+    /// The merge is done, with this result.
+    Done(T),
+    /// The merge paused, and can be resumed from this checkpoint.
+    Paused(MergeCheckpoint),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<T> MergeProgress<T> {
+    /// Maps the result of a merge that is done.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> MergeProgress<U> {
+        match self {
+            Self::Done(result) => MergeProgress::Done(f(result)),
+            Self::Paused(checkpoint) => MergeProgress::Paused(checkpoint),
+        }
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
 /// Merges a node with another with the help of a resolver function.
+///
+/// Changes are consumed from a [`diff_stream`](super::diff_stream), so merging doesn't
+/// need to hold all differences between both nodes in memory at once.
 pub async fn merge<K, V, H>(
     main_link: Link<Arc<Node<K, V, H>>>,
     other_link: Link<Arc<Node<K, V, H>>>,
@@ -26,53 +71,208 @@ where
     V::Serializable: Serialize + DeserializeOwned,
     H: Hasher + CondSync,
 {
-    let kv_changes = super::diff(main_link.clone(), other_link.clone(), store).await?;
-
-    let main_node = main_link.resolve_owned_value(store).await?;
-    let other_node = other_link.resolve_owned_value(store).await?;
-
-    let mut merge_node = Arc::clone(&main_node);
-    for change in kv_changes {
-        match change.r#type {
-            ChangeType::Remove => {
-                merge_node
-                    .set(change.key, change.value1.unwrap(), store)
-                    .await?;
-            }
-            ChangeType::Modify => {
-                let main_value = main_node
-                    .get(&change.key, store)
-                    .await?
-                    .ok_or(HamtError::KeyNotFound)?;
-
-                let other_value = other_node
-                    .get(&change.key, store)
-                    .await?
-                    .ok_or(HamtError::KeyNotFound)?;
-
-                merge_node
-                    .set(change.key, f(main_value, other_value)?, store)
-                    .await?;
-            }
-            _ => (),
+    merge_observed(main_link, other_link, f, |_, _| (), store).await
+}
+
+/// Merges a node with another like [`merge`], calling `observe` for every change that
+/// is merged into the main node, together with the value the key ends up with.
+///
+/// These are the keys only the other node has, and the keys both nodes have with
+/// different values. Keys only the main node has are left as they are.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use wnfs_hamt::{merge_observed, ChangeType, Node};
+/// use wnfs_common::{Link, MemoryBlockStore};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let main_node = &mut Arc::new(Node::<[u8; 4], u32>::default());
+///     main_node.set([0; 4], 1, store).await.unwrap();
+///     let other_node = &mut Arc::new(Node::<[u8; 4], u32>::default());
+///     other_node.set([0; 4], 2, store).await.unwrap();
+///     other_node.set([1; 4], 3, store).await.unwrap();
+///
+///     let mut observed = Vec::new();
+///     merge_observed(
+///         Link::from(Arc::clone(main_node)),
+///         Link::from(Arc::clone(other_node)),
+///         |a, b| Ok(*a.max(b)),
+///         |change, merged| observed.push((change.r#type, *merged)),
+///         store,
+///     )
+///     .await
+///     .unwrap();
+///
+///     observed.sort();
+///     assert_eq!(observed, [(ChangeType::Remove, 3), (ChangeType::Modify, 2)]);
+/// }
+/// ```
+pub async fn merge_observed<K, V, H>(
+    main_link: Link<Arc<Node<K, V, H>>>,
+    other_link: Link<Arc<Node<K, V, H>>>,
+    f: impl Fn(&V, &V) -> Result<V>,
+    mut observe: impl FnMut(&KeyValueChange<K, V>, &V),
+    store: &impl BlockStore,
+) -> Result<Arc<Node<K, V, H>>>
+where
+    K: Storable + Eq + Clone + CondSync + Hash + AsRef<[u8]>,
+    V: Storable + Eq + Clone + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    let mut changes = super::diff_stream(main_link.clone(), other_link, store);
+    let mut merge_node = main_link.resolve_owned_value(store).await?;
+    while let Some(change) = changes.try_next().await? {
+        if let Some(merged) = apply_change(&mut merge_node, &change, &f, store).await? {
+            observe(&change, &merged);
         }
     }
 
     Ok(merge_node)
 }
 
+/// Merges a node with another like [`merge`], but pauses once at least `max_changes`
+/// changes were merged, so long merges can be spread over several calls.
+///
+/// When paused, the partially merged node is stored and a [`MergeCheckpoint`] is returned.
+/// Passing it back in, together with the same two nodes and resolver function, resumes the
+/// merge where it paused. The merge only pauses between HAMT buckets, so a call may merge
+/// a few more changes than `max_changes`.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use wnfs_hamt::{merge_resumable, MergeProgress, Node};
+/// use wnfs_common::{Link, MemoryBlockStore};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let main_node = Arc::new(Node::<[u8; 4], u32>::default());
+///     let other_node = &mut Arc::new(Node::<[u8; 4], u32>::default());
+///     for i in 0u32..100 {
+///         other_node.set(i.to_le_bytes(), i, store).await.unwrap();
+///     }
+///
+///     let mut checkpoint = None;
+///     let merged = loop {
+///         let progress = merge_resumable(
+///             Link::from(Arc::clone(&main_node)),
+///             Link::from(Arc::clone(other_node)),
+///             |a, b| Ok(*a.max(b)),
+///             checkpoint.take(),
+///             10,
+///             store,
+///         )
+///         .await
+///         .unwrap();
+///
+///         match progress {
+///             MergeProgress::Done(merged) => break merged,
+///             MergeProgress::Paused(paused) => checkpoint = Some(paused),
+///         }
+///     };
+///
+///     for i in 0u32..100 {
+///         assert_eq!(merged.get(&i.to_le_bytes(), store).await.unwrap(), Some(&i));
+///     }
+/// }
+/// ```
+pub async fn merge_resumable<K, V, H>(
+    main_link: Link<Arc<Node<K, V, H>>>,
+    other_link: Link<Arc<Node<K, V, H>>>,
+    f: impl Fn(&V, &V) -> Result<V>,
+    checkpoint: Option<MergeCheckpoint>,
+    max_changes: usize,
+    store: &impl BlockStore,
+) -> Result<MergeProgress<Arc<Node<K, V, H>>>>
+where
+    K: Storable + Eq + Clone + CondSync + Hash + AsRef<[u8]>,
+    V: Storable + Eq + Clone + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    let (mut merge_node, after) = match checkpoint {
+        Some(MergeCheckpoint { merged, after }) => (
+            Arc::<Node<K, V, H>>::load(&merged, store).await?,
+            Some(after),
+        ),
+        None => (main_link.clone().resolve_owned_value(store).await?, None),
+    };
+
+    let mut changes = diff_stream_after(main_link, other_link, after, store);
+    let mut merged_count = 0;
+    let mut last_nibbles: Option<Vec<u8>> = None;
+    while let Some((nibbles, change)) = changes.try_next().await? {
+        // Only pause between buckets, so that resuming after the last bucket skips nothing.
+        if let Some(after) =
+            last_nibbles.filter(|last| merged_count >= max_changes && last != &nibbles)
+        {
+            let merged = merge_node.store(store).await?;
+            return Ok(MergeProgress::Paused(MergeCheckpoint { merged, after }));
+        }
+
+        apply_change(&mut merge_node, &change, &f, store).await?;
+        merged_count += 1;
+        last_nibbles = Some(nibbles);
+    }
+
+    Ok(MergeProgress::Done(merge_node))
+}
+
+/// Applies a change to the merge node, returning the value it set, if any.
+async fn apply_change<K, V, H>(
+    merge_node: &mut Arc<Node<K, V, H>>,
+    change: &KeyValueChange<K, V>,
+    f: &impl Fn(&V, &V) -> Result<V>,
+    store: &impl BlockStore,
+) -> Result<Option<V>>
+where
+    K: Storable + Eq + Clone + CondSync + Hash + AsRef<[u8]>,
+    V: Storable + Eq + Clone + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    let merged = match change.r#type {
+        ChangeType::Remove => change.value1.clone().ok_or(HamtError::KeyNotFound)?,
+        ChangeType::Modify => {
+            let main_value = change.value1.as_ref().ok_or(HamtError::KeyNotFound)?;
+            let other_value = change.value2.as_ref().ok_or(HamtError::KeyNotFound)?;
+            f(main_value, other_value)?
+        }
+        _ => return Ok(None),
+    };
+
+    merge_node
+        .set(change.key.clone(), merged.clone(), store)
+        .await?;
+
+    Ok(Some(merged))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
 
 #[cfg(test)]
 mod proptests {
-    use crate::strategies::{self, generate_kvs};
+    use crate::{
+        strategies::{self, generate_kvs},
+        MergeProgress,
+    };
     use async_std::task;
     use proptest::prop_assert_eq;
     use std::cmp;
     use test_strategy::proptest;
-    use wnfs_common::{utils::Arc, Link, MemoryBlockStore};
+    use wnfs_common::{utils::Arc, Link, MemoryBlockStore, Storable};
 
     #[proptest(cases = 100)]
     fn merge_associativity(
@@ -199,4 +399,59 @@ mod proptests {
             Ok(())
         })?;
     }
+
+    #[proptest(cases = 100)]
+    fn resumed_merge_equals_merge(
+        #[strategy(generate_kvs("[a-z0-9]{1,3}", 0u64..1000, 0..100))] kvs1: Vec<(String, u64)>,
+        #[strategy(generate_kvs("[a-z0-9]{1,3}", 0u64..1000, 0..100))] kvs2: Vec<(String, u64)>,
+        #[strategy(1usize..10)] max_changes: usize,
+    ) {
+        task::block_on(async {
+            let store = &MemoryBlockStore::default();
+
+            let node1 = strategies::node_from_kvs(kvs1, store).await.unwrap();
+            let node2 = strategies::node_from_kvs(kvs2, store).await.unwrap();
+
+            // Summing isn't idempotent, so merging a change twice would show up.
+            let merge_node = super::merge(
+                Link::from(Arc::clone(&node1)),
+                Link::from(Arc::clone(&node2)),
+                |a, b| Ok(a + b),
+                store,
+            )
+            .await
+            .unwrap();
+
+            let mut checkpoint_bytes = None;
+            let resumed_node = loop {
+                let checkpoint = checkpoint_bytes
+                    .take()
+                    .map(|bytes: Vec<u8>| serde_ipld_dagcbor::from_slice(&bytes).unwrap());
+
+                let progress = super::merge_resumable(
+                    Link::from(Arc::clone(&node1)),
+                    Link::from(Arc::clone(&node2)),
+                    |a, b| Ok(a + b),
+                    checkpoint,
+                    max_changes,
+                    store,
+                )
+                .await
+                .unwrap();
+
+                match progress {
+                    MergeProgress::Done(node) => break node,
+                    MergeProgress::Paused(checkpoint) => {
+                        checkpoint_bytes = Some(serde_ipld_dagcbor::to_vec(&checkpoint).unwrap());
+                    }
+                }
+            };
+
+            prop_assert_eq!(
+                merge_node.store(store).await.unwrap(),
+                resumed_node.store(store).await.unwrap()
+            );
+            Ok(())
+        })?;
+    }
 }
@@ -0,0 +1,463 @@
+use super::{KeyValueChange, Node, Pair, HAMT_VERSION};
+use crate::{
+    merge_observed, merge_resumable, serializable::HamtSerializable, Hasher, MergeCheckpoint,
+    MergeProgress,
+};
+use anyhow::Result;
+use libipld::Cid;
+use semver::Version;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::BTreeSet, hash::Hash};
+use wnfs_common::{
+    impl_storable_from_serde,
+    utils::{Arc, BoxStream, CondSync},
+    BlockStore, HashOutput, Link, Storable,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A set of CIDs stored as a single value in a [`MultiValueHamt`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct CidSet(pub BTreeSet<Cid>);
+
+/// A HAMT that maps each key to a set of CIDs.
+///
+/// Unlike a plain [`Hamt`](crate::Hamt), adding values extends the set stored at a key
+/// instead of replacing it, and merging two of these takes the union of the sets stored
+/// under the same key.
+///
+/// # Examples
+///
+/// ```
+/// use libipld::Cid;
+/// use wnfs_hamt::MultiValueHamt;
+/// use wnfs_common::MemoryBlockStore;
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let mut hamt = MultiValueHamt::<String>::new();
+///
+///     hamt.add_value("key".into(), Cid::default(), store).await.unwrap();
+///
+///     let values = hamt.get(&"key".into(), store).await.unwrap().unwrap();
+///     assert!(values.contains(&Cid::default()));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultiValueHamt<K: CondSync, H = blake3::Hasher>
+where
+    H: Hasher + CondSync,
+{
+    pub root: Arc<Node<K, CidSet, H>>,
+    pub version: Version,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<K: CondSync, H: Hasher + CondSync> MultiValueHamt<K, H> {
+    /// Creates a new empty multi-value HAMT.
+    pub fn new() -> Self {
+        Self::with_root(Arc::new(Node::default()))
+    }
+
+    /// Creates a new multi-value HAMT with the given root node.
+    pub fn with_root(root: Arc<Node<K, CidSet, H>>) -> Self {
+        Self {
+            root,
+            version: HAMT_VERSION,
+        }
+    }
+}
+
+impl<K, H> MultiValueHamt<K, H>
+where
+    K: Storable + AsRef<[u8]> + Clone + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    /// Adds all `values` to the set stored at `key`.
+    ///
+    /// If there's nothing stored at `key` yet, the key gets inserted, even if `values`
+    /// is empty.
+    pub async fn add_values(
+        &mut self,
+        key: K,
+        values: impl IntoIterator<Item = Cid>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let values = values.into_iter();
+        match self.root.get_mut(&key, store).await? {
+            Some(set) => set.0.extend(values),
+            None => self.root.set(key, CidSet(values.collect()), store).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Adds a single value to the set stored at `key`.
+    pub async fn add_value(&mut self, key: K, value: Cid, store: &impl BlockStore) -> Result<()> {
+        self.add_values(key, Some(value), store).await
+    }
+
+    /// Removes a single value from the set stored at `key`.
+    ///
+    /// Returns whether the value was present. The key is removed once its set is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libipld::Cid;
+    /// use wnfs_hamt::MultiValueHamt;
+    /// use wnfs_common::MemoryBlockStore;
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let mut hamt = MultiValueHamt::<String>::new();
+    ///     hamt.add_value("key".into(), Cid::default(), store).await.unwrap();
+    ///
+    ///     let removed = hamt.remove_value(&"key".into(), &Cid::default(), store).await.unwrap();
+    ///
+    ///     assert!(removed);
+    ///     assert!(hamt.get(&"key".into(), store).await.unwrap().is_none());
+    /// }
+    /// ```
+    pub async fn remove_value(
+        &mut self,
+        key: &K,
+        value: &Cid,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        let (removed, now_empty) = match self.root.get_mut(key, store).await? {
+            Some(set) => (set.0.remove(value), set.0.is_empty()),
+            None => return Ok(false),
+        };
+
+        if now_empty {
+            self.root.remove(key, store).await?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Gets the set of values stored at `key`.
+    pub async fn get<'a>(
+        &'a self,
+        key: &K,
+        store: &impl BlockStore,
+    ) -> Result<Option<&'a BTreeSet<Cid>>> {
+        Ok(self.root.get(key, store).await?.map(|set| &set.0))
+    }
+
+    /// Gets the set of values stored at the key matching the provided hash.
+    pub async fn get_by_hash<'a>(
+        &'a self,
+        hash: &HashOutput,
+        store: &impl BlockStore,
+    ) -> Result<Option<&'a BTreeSet<Cid>>> {
+        Ok(self.root.get_by_hash(hash, store).await?.map(|set| &set.0))
+    }
+
+    /// Removes `key` together with all of its values.
+    pub async fn remove(
+        &mut self,
+        key: &K,
+        store: &impl BlockStore,
+    ) -> Result<Option<Pair<K, BTreeSet<Cid>>>> {
+        Ok(self.root.remove(key, store).await?.map(Self::unwrap_pair))
+    }
+
+    /// Removes the key matching the provided hash together with all of its values.
+    pub async fn remove_by_hash(
+        &mut self,
+        hash: &HashOutput,
+        store: &impl BlockStore,
+    ) -> Result<Option<Pair<K, BTreeSet<Cid>>>> {
+        Ok(self
+            .root
+            .remove_by_hash(hash, store)
+            .await?
+            .map(Self::unwrap_pair))
+    }
+
+    /// Gets the difference between two multi-value HAMTs at the key-value level.
+    pub async fn diff(
+        &self,
+        other: &Self,
+        store: &impl BlockStore,
+    ) -> Result<Vec<KeyValueChange<K, CidSet>>>
+    where
+        K: Eq + Hash,
+    {
+        super::diff(
+            Link::from(Arc::clone(&self.root)),
+            Link::from(Arc::clone(&other.root)),
+            store,
+        )
+        .await
+    }
+
+    /// Streams the difference between two multi-value HAMTs at the key-value level.
+    pub fn diff_stream<'a>(
+        &self,
+        other: &Self,
+        store: &'a impl BlockStore,
+    ) -> BoxStream<'a, Result<KeyValueChange<K, CidSet>>>
+    where
+        K: Eq + Hash + 'a,
+        H: 'a,
+    {
+        super::diff_stream(
+            Link::from(Arc::clone(&self.root)),
+            Link::from(Arc::clone(&other.root)),
+            store,
+        )
+    }
+
+    /// Merges two multi-value HAMTs.
+    ///
+    /// Keys that only exist in one of them are kept as-is. Keys that exist in
+    /// both of them end up with the union of both sets of values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libipld::Cid;
+    /// use wnfs_hamt::MultiValueHamt;
+    /// use wnfs_common::{MemoryBlockStore, BlockStore, CODEC_RAW};
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let one = store.put_block(b"one".to_vec(), CODEC_RAW).await.unwrap();
+    ///     let two = store.put_block(b"two".to_vec(), CODEC_RAW).await.unwrap();
+    ///
+    ///     let mut main = MultiValueHamt::<String>::new();
+    ///     main.add_value("key".into(), one, store).await.unwrap();
+    ///     let mut other = MultiValueHamt::<String>::new();
+    ///     other.add_value("key".into(), two, store).await.unwrap();
+    ///
+    ///     let merged = main.merge(&other, store).await.unwrap();
+    ///
+    ///     let values = merged.get(&"key".into(), store).await.unwrap().unwrap();
+    ///     assert_eq!(values.len(), 2);
+    /// }
+    /// ```
+    pub async fn merge(&self, other: &Self, store: &impl BlockStore) -> Result<Self>
+    where
+        K: Eq + Hash,
+    {
+        self.merge_observed(other, |_, _| (), store).await
+    }
+
+    /// Merges two multi-value HAMTs like [`merge`](Self::merge), calling `observe` for
+    /// every key whose values change, with the values it ends up with.
+    ///
+    /// See [`merge_observed`] for which changes are observed.
+    pub async fn merge_observed(
+        &self,
+        other: &Self,
+        observe: impl FnMut(&KeyValueChange<K, CidSet>, &CidSet),
+        store: &impl BlockStore,
+    ) -> Result<Self>
+    where
+        K: Eq + Hash,
+    {
+        let root = merge_observed(
+            Link::from(Arc::clone(&self.root)),
+            Link::from(Arc::clone(&other.root)),
+            |a, b| Ok(CidSet(a.0.union(&b.0).cloned().collect())),
+            observe,
+            store,
+        )
+        .await?;
+
+        Ok(Self {
+            root,
+            version: self.version.clone(),
+        })
+    }
+
+    /// Merges two multi-value HAMTs like [`merge`](Self::merge), but pauses once at least
+    /// `max_changes` keys were merged.
+    ///
+    /// See [`merge_resumable`] for how to resume a paused merge.
+    pub async fn merge_resumable(
+        &self,
+        other: &Self,
+        checkpoint: Option<MergeCheckpoint>,
+        max_changes: usize,
+        store: &impl BlockStore,
+    ) -> Result<MergeProgress<Self>>
+    where
+        K: Eq + Hash,
+    {
+        let progress = merge_resumable(
+            Link::from(Arc::clone(&self.root)),
+            Link::from(Arc::clone(&other.root)),
+            |a, b| Ok(CidSet(a.0.union(&b.0).cloned().collect())),
+            checkpoint,
+            max_changes,
+            store,
+        )
+        .await?;
+
+        Ok(progress.map(|root| Self {
+            root,
+            version: self.version.clone(),
+        }))
+    }
+
+    fn unwrap_pair(Pair { key, value }: Pair<K, CidSet>) -> Pair<K, BTreeSet<Cid>> {
+        Pair {
+            key,
+            value: value.0,
+        }
+    }
+}
+
+impl_storable_from_serde! { CidSet }
+
+impl<K, H> Storable for MultiValueHamt<K, H>
+where
+    K: Storable + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    type Serializable = HamtSerializable<K::Serializable, CidSet>;
+
+    async fn to_serializable(&self, store: &impl BlockStore) -> Result<Self::Serializable> {
+        Ok(HamtSerializable {
+            root: self.root.to_serializable(store).await?,
+            version: self.version.clone(),
+            structure: "hamt".to_string(),
+        })
+    }
+
+    async fn from_serializable(
+        _cid: Option<&Cid>,
+        serializable: Self::Serializable,
+    ) -> Result<Self> {
+        Ok(Self {
+            root: Arc::new(Node::from_serializable(None, serializable.root).await?),
+            version: serializable.version,
+        })
+    }
+}
+
+impl<K: CondSync, H: Hasher + CondSync> Default for MultiValueHamt<K, H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, H> PartialEq for MultiValueHamt<K, H>
+where
+    K: Storable + PartialEq + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root && self.version == other.version
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, CODEC_RAW};
+
+    async fn cids(store: &impl BlockStore, count: u8) -> Result<Vec<Cid>> {
+        let mut cids = Vec::new();
+        for i in 0..count {
+            cids.push(store.put_block(vec![i], CODEC_RAW).await?);
+        }
+
+        Ok(cids)
+    }
+
+    #[async_std::test]
+    async fn adding_values_extends_the_set() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let cids = cids(store, 3).await?;
+        let hamt = &mut MultiValueHamt::<String>::new();
+
+        hamt.add_value("key".into(), cids[0], store).await?;
+        hamt.add_values("key".into(), cids[1..].to_vec(), store)
+            .await?;
+        hamt.add_value("key".into(), cids[0], store).await?;
+
+        let values = hamt.get(&"key".into(), store).await?.unwrap();
+        assert_eq!(values, &cids.iter().cloned().collect::<BTreeSet<_>>());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn removing_the_last_value_removes_the_key() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let cids = cids(store, 2).await?;
+        let hamt = &mut MultiValueHamt::<String>::new();
+        hamt.add_values("key".into(), cids.clone(), store).await?;
+
+        assert!(hamt.remove_value(&"key".into(), &cids[0], store).await?);
+        assert!(!hamt.remove_value(&"key".into(), &cids[0], store).await?);
+        assert_eq!(hamt.get(&"key".into(), store).await?.unwrap().len(), 1);
+
+        assert!(hamt.remove_value(&"key".into(), &cids[1], store).await?);
+        assert!(hamt.get(&"key".into(), store).await?.is_none());
+        assert!(hamt.root.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn merge_takes_the_union_of_values() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let cids = cids(store, 4).await?;
+
+        let main = &mut MultiValueHamt::<String>::new();
+        main.add_values("shared".into(), cids[0..2].to_vec(), store)
+            .await?;
+        main.add_value("main".into(), cids[2], store).await?;
+
+        let other = &mut MultiValueHamt::<String>::new();
+        other
+            .add_values("shared".into(), cids[1..3].to_vec(), store)
+            .await?;
+        other.add_value("other".into(), cids[3], store).await?;
+
+        let merged = main.merge(other, store).await?;
+
+        assert_eq!(
+            merged.get(&"shared".into(), store).await?.unwrap(),
+            &cids[0..3].iter().cloned().collect::<BTreeSet<_>>()
+        );
+        assert_eq!(
+            merged.get(&"main".into(), store).await?.unwrap(),
+            &BTreeSet::from([cids[2]])
+        );
+        assert_eq!(
+            merged.get(&"other".into(), store).await?.unwrap(),
+            &BTreeSet::from([cids[3]])
+        );
+
+        let reverse = other.merge(main, store).await?;
+        assert_eq!(
+            merged.root.store(store).await?,
+            reverse.root.store(store).await?
+        );
+
+        Ok(())
+    }
+}
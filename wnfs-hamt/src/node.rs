@@ -398,11 +398,11 @@ where
                             // If values has reached threshold, we need to create a node link that splits it.
                             let mut sub_node = Arc::new(Node::<K, V, H>::default());
                             let cursor = hashnibbles.get_cursor();
-                            // We can take because
-                            // Pointer::Values() gets replaced with Pointer::Link at the end
-                            let values = std::mem::take(values);
+                            // The values are cloned instead of taken, so the bucket stays intact
+                            // until it's replaced with the link at the end, even if this future
+                            // is dropped midway.
                             for Pair { key, value } in
-                                values.into_iter().chain(Some(Pair::new(key, value)))
+                                values.iter().cloned().chain(Some(Pair::new(key, value)))
                             {
                                 let hash = &H::hash(&key);
                                 let hashnibbles = &mut HashNibbles::with_cursor(hash, cursor);
@@ -630,6 +630,108 @@ where
         Ok(items)
     }
 
+    /// Returns up to `limit` key-value pairs whose keys hash to more than given hash,
+    /// in order of their hashes.
+    ///
+    /// Passing the hash of the last key of one call to the next one lists all pairs page by
+    /// page, loading only the nodes on the way. Pages stay consistent across changes to the
+    /// trie, since the order only depends on the keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use wnfs_hamt::{Node, Hasher};
+    /// use wnfs_common::MemoryBlockStore;
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let mut node = Arc::new(Node::<[u8; 4], String>::default());
+    ///     for i in 0..99_u32 {
+    ///         node
+    ///             .set(i.to_le_bytes(), i.to_string(), store)
+    ///             .await
+    ///             .unwrap();
+    ///     }
+    ///
+    ///     let first = node.pairs_after(None, 50, store).await.unwrap();
+    ///     let last_hash = blake3::Hasher::hash(&first[49].key);
+    ///     let second = node.pairs_after(Some(&last_hash), 50, store).await.unwrap();
+    ///
+    ///     assert_eq!(first.len() + second.len(), 99);
+    /// }
+    /// ```
+    pub async fn pairs_after(
+        &self,
+        hash: Option<&HashOutput>,
+        limit: usize,
+        store: &impl BlockStore,
+    ) -> Result<Vec<Pair<K, V>>>
+    where
+        K: Storable + AsRef<[u8]> + Clone,
+        V: Storable + Clone,
+        K::Serializable: Serialize + DeserializeOwned,
+        V::Serializable: Serialize + DeserializeOwned,
+    {
+        let mut pairs = Vec::new();
+        self.pairs_after_helper(hash, 0, limit, &mut pairs, store)
+            .await?;
+        Ok(pairs)
+    }
+
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    async fn pairs_after_helper(
+        &self,
+        hash: Option<&'async_recursion HashOutput>,
+        cursor: usize,
+        limit: usize,
+        pairs: &mut Vec<Pair<K, V>>,
+        store: &impl BlockStore,
+    ) -> Result<()>
+    where
+        K: Storable + AsRef<[u8]> + Clone,
+        V: Storable + Clone,
+        K::Serializable: Serialize + DeserializeOwned,
+        V::Serializable: Serialize + DeserializeOwned,
+    {
+        let start = match hash {
+            Some(hash) => HashNibbles::with_cursor(hash, cursor).try_next()?,
+            None => 0,
+        };
+
+        for bit_index in start..HAMT_BITMASK_BIT_SIZE {
+            if pairs.len() >= limit {
+                break;
+            }
+
+            if !self.bitmask[bit_index] {
+                continue;
+            }
+
+            // Only the branch with the given hash in it has pairs that hash to less
+            let hash = hash.filter(|_| bit_index == start);
+            match &self.pointers[self.get_value_index(bit_index)] {
+                Pointer::Values(values) => {
+                    let after = values
+                        .iter()
+                        .filter(|pair| hash.map_or(true, |hash| &H::hash(&pair.key) > hash));
+                    let remaining = limit - pairs.len();
+                    pairs.extend(after.take(remaining).cloned());
+                }
+                Pointer::Link(link) => {
+                    let child = link.resolve_value(store).await?;
+                    child
+                        .pairs_after_helper(hash, cursor + 1, limit, pairs, store)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Given a hashprefix representing the path to a node in the trie. This function will
     /// return the key-value pair or the intermediate node that the hashprefix points to.
     ///
@@ -776,6 +878,49 @@ where
 
         Ok(len)
     }
+
+    /// Clones this node, copying all of its descendants that weren't stored yet and
+    /// mapping the values in them with given function.
+    ///
+    /// Storing a node remembers its CID in the node, and clones share their children,
+    /// so storing the clone returned by this doesn't affect the children of this node.
+    pub fn detached(&self, f: &impl Fn(&V) -> V) -> Self
+    where
+        K: Storable + Clone,
+        V: Storable + Clone,
+        K::Serializable: Serialize + DeserializeOwned,
+        V::Serializable: Serialize + DeserializeOwned,
+    {
+        if self.persisted_as.get().is_some() {
+            return self.clone();
+        }
+
+        let pointers = self
+            .pointers
+            .iter()
+            .map(|pointer| match pointer {
+                Pointer::Values(pairs) => Pointer::Values(
+                    pairs
+                        .iter()
+                        .map(|Pair { key, value }| Pair::new(key.clone(), f(value)))
+                        .collect(),
+                ),
+                Pointer::Link(link) => match link.get_value() {
+                    Some(node) if !link.has_cid() => {
+                        Pointer::Link(Link::from(Arc::new(node.detached(f))))
+                    }
+                    _ => pointer.clone(),
+                },
+            })
+            .collect();
+
+        Self {
+            persisted_as: OnceCell::new(),
+            bitmask: self.bitmask,
+            pointers,
+            hasher: PhantomData,
+        }
+    }
 }
 
 impl<K: Clone + CondSync, V: CondSync + Clone, H: Hasher + CondSync> Clone for Node<K, V, H> {
@@ -1183,6 +1328,59 @@ mod tests {
         assert!(matches!(result, Some(Either::Right(_))));
     }
 
+    #[async_std::test]
+    async fn can_list_pairs_page_by_page() {
+        let store = &MemoryBlockStore::default();
+
+        let node = &mut Arc::new(Node::<[u8; 4], String>::default());
+        for i in 0..500_u32 {
+            node.set(i.to_le_bytes(), i.to_string(), store)
+                .await
+                .unwrap();
+        }
+
+        let mut hashes = Vec::new();
+        let mut last_hash = None;
+        loop {
+            let page = node
+                .pairs_after(last_hash.as_ref(), 64, store)
+                .await
+                .unwrap();
+            if page.is_empty() {
+                break;
+            }
+
+            assert!(page.len() <= 64);
+            hashes.extend(page.iter().map(|pair| blake3::Hasher::hash(&pair.key)));
+            last_hash = hashes.last().copied();
+        }
+
+        let mut expected = (0..500_u32)
+            .map(|i| blake3::Hasher::hash(&i.to_le_bytes()))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(hashes, expected);
+    }
+
+    #[async_std::test]
+    async fn storing_detached_node_leaves_original_unstored() {
+        let dry_run = &MemoryBlockStore::default();
+        let store = &MemoryBlockStore::default();
+        let node = &mut Arc::new(Node::<String, usize>::default());
+        for i in 0..100 {
+            node.set(i.to_string(), i, store).await.unwrap();
+        }
+
+        let detached = node.detached(&|value| value + 1);
+        detached.store(dry_run).await.unwrap();
+        assert_eq!(detached.get(&"42".into(), store).await.unwrap(), Some(&43));
+
+        // All blocks of the original still end up in the store it's stored in
+        let cid = node.store(store).await.unwrap();
+        let loaded = Node::<String, usize>::load(&cid, store).await.unwrap();
+        assert_eq!(loaded.to_hashmap(store).await.unwrap().len(), 100);
+    }
+
     #[async_std::test]
     async fn can_generate_hashmap_from_node() {
         let store = &MemoryBlockStore::default();
@@ -0,0 +1,245 @@
+use crate::{
+    serializable::{NodeSerializable, PointerSerializable},
+    Hasher, Node, Pointer, HAMT_VALUES_BUCKET_SIZE,
+};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use libipld::Cid;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_byte_array::ByteArray;
+use std::fmt::{self, Display, Formatter};
+use wnfs_common::{
+    utils::{Arc, CondSync},
+    BlockStore, Storable, StoreIpld,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Statistics about the shape and size of a HAMT.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use wnfs_hamt::Node;
+/// use wnfs_common::MemoryBlockStore;
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let node = &mut Arc::new(Node::<[u8; 4], String>::default());
+///     for i in 0..100_u32 {
+///         node.set(i.to_le_bytes(), i.to_string(), store).await.unwrap();
+///     }
+///
+///     let stats = node.stats(store).await.unwrap();
+///
+///     assert_eq!(stats.key_count, 100);
+///     println!("{stats}");
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HamtStats {
+    /// The number of nodes, including the root node.
+    pub node_count: usize,
+    /// The number of nodes at each depth, starting with the root at depth 0.
+    pub depth_histogram: Vec<usize>,
+    /// The number of key-value pairs.
+    pub key_count: usize,
+    /// The number of value buckets, i.e. pointers that hold key-value pairs directly.
+    pub bucket_count: usize,
+    /// The combined size of all nodes in bytes, when encoded as dag-cbor.
+    pub serialized_size: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl HamtStats {
+    /// The depth of the deepest node, where the root node is at depth 0.
+    pub fn max_depth(&self) -> usize {
+        self.depth_histogram.len().saturating_sub(1)
+    }
+
+    /// The average number of key-value pairs per bucket.
+    ///
+    /// Buckets can hold at most [`HAMT_VALUES_BUCKET_SIZE`] pairs.
+    pub fn average_bucket_occupancy(&self) -> f64 {
+        if self.bucket_count == 0 {
+            return 0.0;
+        }
+
+        self.key_count as f64 / self.bucket_count as f64
+    }
+
+    fn record_node(&mut self, depth: usize, serialized_size: usize) {
+        if self.depth_histogram.len() <= depth {
+            self.depth_histogram.resize(depth + 1, 0);
+        }
+
+        self.depth_histogram[depth] += 1;
+        self.node_count += 1;
+        self.serialized_size += serialized_size;
+    }
+}
+
+impl Display for HamtStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "nodes:            {}", self.node_count)?;
+        writeln!(f, "keys:             {}", self.key_count)?;
+        writeln!(
+            f,
+            "buckets:          {} (average occupancy {:.2} of {HAMT_VALUES_BUCKET_SIZE})",
+            self.bucket_count,
+            self.average_bucket_occupancy()
+        )?;
+        writeln!(f, "serialized size:  {} bytes", self.serialized_size)?;
+        writeln!(f, "max depth:        {}", self.max_depth())?;
+        write!(f, "nodes per depth:")?;
+        for (depth, count) in self.depth_histogram.iter().enumerate() {
+            write!(f, "\n  {depth}: {count}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, V, H> Node<K, V, H>
+where
+    K: Storable + CondSync,
+    V: Storable + CondSync,
+    K::Serializable: Serialize + DeserializeOwned,
+    V::Serializable: Serialize + DeserializeOwned,
+    H: Hasher + CondSync,
+{
+    /// Walks the whole HAMT and collects statistics about its shape and size.
+    ///
+    /// Serialized sizes are computed by encoding each node in memory, so
+    /// child nodes that haven't been stored yet are not written to the store.
+    pub async fn stats(self: &Arc<Self>, store: &impl BlockStore) -> Result<HamtStats> {
+        let mut stats = HamtStats::default();
+        self.collect_stats(0, &mut stats, store).await?;
+        Ok(stats)
+    }
+
+    /// Records this node and its descendants in `stats` and returns the CID
+    /// this node would be stored under, without storing it.
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    async fn collect_stats(
+        &self,
+        depth: usize,
+        stats: &mut HamtStats,
+        store: &impl BlockStore,
+    ) -> Result<Cid> {
+        let mut pointers = Vec::with_capacity(self.pointers.len());
+        for pointer in self.pointers.iter() {
+            pointers.push(match pointer {
+                Pointer::Values(values) => {
+                    stats.bucket_count += 1;
+                    stats.key_count += values.len();
+                    pointer.to_serializable(store).await?
+                }
+                Pointer::Link(link) => {
+                    let child = link.resolve_value(store).await?;
+                    let cid = child.collect_stats(depth + 1, stats, store).await?;
+                    PointerSerializable::Link(cid)
+                }
+            });
+        }
+
+        let bitmask = ByteArray::from(self.bitmask.into_inner());
+        let (bytes, codec) = NodeSerializable(bitmask, pointers).encode_ipld()?;
+        stats.record_node(depth, bytes.len());
+
+        Ok(store.create_cid(&bytes, codec)?)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn empty_node_has_a_single_node_and_no_keys() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let node = Arc::new(Node::<[u8; 4], String>::default());
+
+        let stats = node.stats(store).await?;
+
+        assert_eq!(stats.node_count, 1);
+        assert_eq!(stats.depth_histogram, vec![1]);
+        assert_eq!(stats.key_count, 0);
+        assert_eq!(stats.average_bucket_occupancy(), 0.0);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn stats_account_for_all_nodes_and_keys() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let node = &mut Arc::new(Node::<[u8; 4], String>::default());
+        for i in 0..100_u32 {
+            node.set(i.to_le_bytes(), i.to_string(), store).await?;
+        }
+
+        let stats = node.stats(store).await?;
+
+        assert_eq!(stats.key_count, 100);
+        assert_eq!(stats.depth_histogram[0], 1);
+        assert_eq!(
+            stats.depth_histogram.iter().sum::<usize>(),
+            stats.node_count
+        );
+        assert!(stats.max_depth() > 0);
+        assert!(stats.average_bucket_occupancy() <= HAMT_VALUES_BUCKET_SIZE as f64);
+
+        let root_cid = node.store(store).await?;
+        let root_size = store.get_block(&root_cid).await?.len();
+        assert!(stats.serialized_size > root_size);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn stats_dont_store_unstored_nodes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let node = &mut Arc::new(Node::<[u8; 4], String>::default());
+        for i in 0..100_u32 {
+            node.set(i.to_le_bytes(), i.to_string(), store).await?;
+        }
+
+        let stats = node.stats(store).await?;
+
+        assert!(stats.node_count > 1);
+
+        // Storing the HAMT elsewhere reveals the CIDs of all its nodes
+        let other_store = &MemoryBlockStore::new();
+        let mut cids = vec![node.store(other_store).await?];
+        let mut nodes = vec![node.as_ref()];
+        while let Some(node) = nodes.pop() {
+            for pointer in node.pointers.iter() {
+                if let Pointer::Link(link) = pointer {
+                    cids.push(link.resolve_cid(other_store).await?);
+                    nodes.push(link.resolve_value(other_store).await?);
+                }
+            }
+        }
+
+        assert_eq!(cids.len(), stats.node_count);
+        for cid in cids {
+            assert!(!store.has_block(&cid).await?);
+        }
+
+        Ok(())
+    }
+}
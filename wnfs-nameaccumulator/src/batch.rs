@@ -0,0 +1,295 @@
+use crate::{
+    AccumulatorSetup, BatchedProofPart, BatchedProofVerification, Big, DefaultBig, ElementsProof,
+    NameAccumulator, UnbatchableProofPart,
+};
+use alloc::{collections::BTreeMap, format, vec::Vec};
+use anyhow::Result;
+#[cfg(feature = "std")]
+use libipld::Cid;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "std")]
+use wnfs_common::{BlockStore, Storable};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The version of the [`ProofBatch`] encoding.
+///
+/// Decoding a batch with any other version fails.
+pub const PROOF_BATCH_VERSION: u64 = 1;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A batch of PoKE* proofs that name accumulators were derived from their bases, with all
+/// batchable proof parts combined into a single PoKCR proof.
+///
+/// This is meant for sending proofs over the wire, e.g. for verifying writes to a private
+/// forest without read access. It's encoded as a tuple of
+/// - the [encoding version](PROOF_BATCH_VERSION),
+/// - a list of the distinct bases as 256-byte accumulators,
+/// - a list of `(base index, commitment, unbatchable proof part)` triples and
+/// - the [`BatchedProofPart`],
+///
+/// so bases shared by many proofs, like the name of a directory that many files get written
+/// to, are only encoded once. Each proof adds about 280 bytes on top of its base.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs_common::{decode, encode, libipld::cbor::DagCborCodec};
+/// use wnfs_nameaccumulator::{AccumulatorSetup, Name, NameAccumulator, NameSegment, ProofBatch};
+///
+/// let rng = &mut rand::thread_rng();
+/// let setup = &AccumulatorSetup::from_rsa_2048(rng);
+/// let dir = NameAccumulator::with_segments(&[NameSegment::new(rng)], setup);
+///
+/// let mut batch = ProofBatch::new();
+/// for _ in 0..3 {
+///     let file = Name::new(dir.clone(), [NameSegment::new(rng)]);
+///     let (commitment, proof) = file.into_proven_accumulator(setup);
+///     batch.add(&commitment, &proof, setup);
+/// }
+///
+/// let bytes = encode(&batch, DagCborCodec).unwrap();
+/// let received: ProofBatch = decode(&bytes, DagCborCodec).unwrap();
+/// assert_eq!(received.len(), 3);
+/// assert!(received.verify(setup).is_ok());
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProofBatch<B: Big = DefaultBig> {
+    bases: Vec<NameAccumulator<B>>,
+    /// Indices into `bases` by the bytes of the base
+    base_indices: BTreeMap<[u8; 256], u32>,
+    relations: Vec<(u32, NameAccumulator<B>, UnbatchableProofPart<B>)>,
+    batched: BatchedProofPart<B>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<B: Big> ProofBatch<B> {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self {
+            bases: Vec::new(),
+            base_indices: BTreeMap::new(),
+            relations: Vec::new(),
+            batched: BatchedProofPart::new(),
+        }
+    }
+
+    /// Adds the proof that `commitment` was derived from the base of the proof.
+    pub fn add(
+        &mut self,
+        commitment: &NameAccumulator<B>,
+        proof: &ElementsProof<B>,
+        setup: &AccumulatorSetup<B>,
+    ) {
+        let base = NameAccumulator::from_state(proof.base.clone());
+        let index = *self
+            .base_indices
+            .entry(*base.as_bytes())
+            .or_insert_with(|| {
+                self.bases.push(base);
+                (self.bases.len() - 1) as u32
+            });
+
+        self.relations
+            .push((index, commitment.clone(), proof.part.clone()));
+        self.batched.add(proof, setup);
+    }
+
+    /// Returns the number of proofs in this batch.
+    pub fn len(&self) -> usize {
+        self.relations.len()
+    }
+
+    /// Returns whether this batch contains no proofs.
+    pub fn is_empty(&self) -> bool {
+        self.relations.is_empty()
+    }
+
+    /// Iterates over the proven relations as `(base, commitment, unbatchable proof part)`.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            &NameAccumulator<B>,
+            &NameAccumulator<B>,
+            &UnbatchableProofPart<B>,
+        ),
+    > {
+        self.relations
+            .iter()
+            .map(|(index, commitment, part)| (&self.bases[*index as usize], commitment, part))
+    }
+
+    /// Returns the part of the proofs that was batched together.
+    pub fn batched_part(&self) -> &BatchedProofPart<B> {
+        &self.batched
+    }
+
+    /// Verifies all proofs in this batch against given setup.
+    pub fn verify(&self, setup: &AccumulatorSetup<B>) -> Result<()> {
+        let mut verification = BatchedProofVerification::new(setup);
+        for (base, commitment, part) in self.iter() {
+            verification.add(base, commitment, part)?;
+        }
+
+        verification.verify(&self.batched)
+    }
+}
+
+impl<B: Big> Default for ProofBatch<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Big> Serialize for ProofBatch<B> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        (
+            PROOF_BATCH_VERSION,
+            &self.bases,
+            &self.relations,
+            &self.batched,
+        )
+            .serialize(serializer)
+    }
+}
+
+impl<'de, B: Big> Deserialize<'de> for ProofBatch<B> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        type Relations<B> = Vec<(u32, NameAccumulator<B>, UnbatchableProofPart<B>)>;
+        let (version, bases, relations, batched): (
+            u64,
+            Vec<NameAccumulator<B>>,
+            Relations<B>,
+            BatchedProofPart<B>,
+        ) = Deserialize::deserialize(deserializer)?;
+
+        if version != PROOF_BATCH_VERSION {
+            return Err(D::Error::custom(format!(
+                "Unsupported proof batch version {version}, expected {PROOF_BATCH_VERSION}"
+            )));
+        }
+
+        if let Some((index, _, _)) = relations
+            .iter()
+            .find(|(i, _, _)| *i as usize >= bases.len())
+        {
+            return Err(D::Error::custom(format!(
+                "Proof batch refers to base {index}, but only has {} bases",
+                bases.len()
+            )));
+        }
+
+        let base_indices = bases
+            .iter()
+            .enumerate()
+            .map(|(index, base)| (*base.as_bytes(), index as u32))
+            .collect();
+
+        Ok(Self {
+            bases,
+            base_indices,
+            relations,
+            batched,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<B: Big> Storable for ProofBatch<B> {
+    type Serializable = ProofBatch<B>;
+
+    async fn to_serializable(&self, _store: &impl BlockStore) -> Result<Self::Serializable> {
+        Ok(self.clone())
+    }
+
+    async fn from_serializable(
+        _cid: Option<&Cid>,
+        serializable: Self::Serializable,
+    ) -> Result<Self> {
+        Ok(serializable)
+    }
+}
+
+impl<B: Big> core::fmt::Debug for ProofBatch<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ProofBatch")
+            .field("bases", &self.bases)
+            .field("relations", &self.relations)
+            .field("batched", &self.batched)
+            .finish()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BigNumDig, Name, NameSegment};
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::{decode, encode, libipld::cbor::DagCborCodec};
+
+    #[test]
+    fn batches_roundtrip_and_encode_shared_bases_once() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let setup = &AccumulatorSetup::<BigNumDig>::from_rsa_2048(rng);
+        let dir = NameAccumulator::with_segments(&[NameSegment::new(rng)], setup);
+
+        let mut batch = ProofBatch::new();
+        for _ in 0..10 {
+            let file = Name::new(dir.clone(), [NameSegment::new(rng)]);
+            let (commitment, proof) = file.into_proven_accumulator(setup);
+            batch.add(&commitment, &proof, setup);
+        }
+
+        let bytes = encode(&batch, DagCborCodec)?;
+        assert!(bytes.len() < 256 + 10 * 300 + 300);
+
+        let decoded: ProofBatch<BigNumDig> = decode(&bytes, DagCborCodec)?;
+        assert_eq!(decoded, batch);
+        decoded.verify(setup)?;
+
+        let (commitment, proof) =
+            Name::new(dir, [NameSegment::new(rng)]).into_proven_accumulator(setup);
+        let mut tampered = decoded.clone();
+        tampered.relations[0].1 = commitment;
+        assert!(tampered.verify(setup).is_err());
+
+        let proof_bytes = encode(&proof, DagCborCodec)?;
+        let decoded_proof: ElementsProof<BigNumDig> = decode(&proof_bytes, DagCborCodec)?;
+        assert_eq!(decoded_proof, proof);
+
+        Ok(())
+    }
+
+    #[test]
+    fn batches_with_unknown_versions_are_rejected() -> Result<()> {
+        let batch = ProofBatch::<BigNumDig>::new();
+        let (_, bases, relations, batched): (u64, Vec<()>, Vec<()>, BatchedProofPart<BigNumDig>) =
+            decode(&encode(&batch, DagCborCodec)?, DagCborCodec)?;
+        let bytes = encode(&(2u64, bases, relations, batched), DagCborCodec)?;
+
+        let result = decode::<ProofBatch<BigNumDig>, _>(&bytes, DagCborCodec);
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
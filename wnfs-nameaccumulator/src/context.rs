@@ -0,0 +1,102 @@
+//! Values derived from accumulator setup moduli, shared across setups.
+
+use crate::{error::VerificationError, lazy::OnceCell, traits::Big};
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "std")]
+use std::{collections::HashMap, sync::Mutex};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of moduli to keep contexts around for.
+///
+/// Processes usually only work with a handful of setups, so once this is exceeded,
+/// the cache is simply cleared.
+#[cfg(feature = "std")]
+const MAX_CACHED_CONTEXTS: usize = 64;
+
+/// Contexts of all moduli seen in this process, keyed by the hash of the modulus.
+#[cfg(feature = "std")]
+static CONTEXTS: Lazy<Mutex<HashMap<blake3::Hash, Arc<ModulusContext>>>> =
+    Lazy::new(Default::default);
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Values derived from an RSA modulus, computed once per process and modulus.
+#[derive(Debug)]
+pub(crate) struct ModulusContext {
+    /// The modulus in the big-endian encoding that's hashed into proofs.
+    pub(crate) modulus_bytes: [u8; 256],
+    /// The result of verifying the modulus, computed on first use.
+    verification: OnceCell<Result<(), VerificationError>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ModulusContext {
+    /// Returns the context for given modulus, creating it if this process hasn't seen
+    /// the modulus yet.
+    ///
+    /// Without `std`, there's no process-wide cache, so each call creates a new context.
+    ///
+    /// The modulus must fit into 2048 bits.
+    pub(crate) fn get<B: Big>(modulus: &B::Num) -> Arc<Self> {
+        let modulus_bytes = B::to_bytes_be::<256>(modulus);
+
+        #[cfg(feature = "std")]
+        {
+            let key = blake3::hash(&modulus_bytes);
+            // The cache is never left half-updated, so it's still usable after a panic
+            let mut contexts = CONTEXTS
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(context) = contexts.get(&key) {
+                return Arc::clone(context);
+            }
+
+            if contexts.len() >= MAX_CACHED_CONTEXTS {
+                contexts.clear();
+            }
+
+            let context = Arc::new(Self::new(modulus_bytes));
+            contexts.insert(key, Arc::clone(&context));
+            context
+        }
+
+        // Without std, there are no other threads to share the context with
+        #[cfg(not(feature = "std"))]
+        #[allow(clippy::arc_with_non_send_sync)]
+        Arc::new(Self::new(modulus_bytes))
+    }
+
+    fn new(modulus_bytes: [u8; 256]) -> Self {
+        Self {
+            modulus_bytes,
+            verification: OnceCell::new(),
+        }
+    }
+
+    /// Checks that the modulus is odd and not prime.
+    ///
+    /// The primality test is the expensive part of verifying a setup, so its result is
+    /// remembered for all setups with this modulus.
+    pub(crate) fn verify_modulus<B: Big>(&self, modulus: &B::Num) -> Result<(), VerificationError> {
+        self.verification
+            .get_or_init(|| {
+                let is_odd = self.modulus_bytes[255] & 1 == 1;
+                if !is_odd || B::is_probably_prime(modulus) {
+                    return Err(VerificationError::InvalidModulus);
+                }
+
+                Ok(())
+            })
+            .clone()
+    }
+}
@@ -1,22 +1,47 @@
 //! Errors
 
-use thiserror::Error;
+use core::fmt;
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
 
-#[derive(Debug, PartialEq, Eq, Error)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VerificationError {
-    #[error("Hash-to-prime didn't end up prime")]
     LHashNonPrime,
-
-    #[error("Residue outside range")]
     ResidueOutsideRange,
-
-    #[error("NameAccumulator batched proof validation failed")]
     ValidationFailed,
-
-    #[error("Couldn't invert base accumulator state")]
     NoInverse,
+    InvalidModulus,
+    InvalidGenerator,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LHashNonPrime => "Hash-to-prime didn't end up prime",
+            Self::ResidueOutsideRange => "Residue outside range",
+            Self::ValidationFailed => "NameAccumulator batched proof validation failed",
+            Self::NoInverse => "Couldn't invert base accumulator state",
+            Self::InvalidModulus => {
+                "Accumulator setup modulus is even, prime or larger than 2048 bits"
+            }
+            Self::InvalidGenerator => "Accumulator setup generator isn't a unit modulo the modulus",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerificationError {}
+
+// Without std, anyhow only converts from errors it knows, so wrap them as messages
+#[cfg(not(feature = "std"))]
+impl From<VerificationError> for anyhow::Error {
+    fn from(error: VerificationError) -> Self {
+        anyhow::Error::msg(error)
+    }
 }
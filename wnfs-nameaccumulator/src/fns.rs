@@ -1,4 +1,5 @@
 use crate::Big;
+use alloc::vec;
 use blake3::traits::digest::{ExtendableOutput, ExtendableOutputReset};
 use num_traits::One;
 
@@ -0,0 +1,9 @@
+//! Lazily initialized values.
+//!
+//! With `std`, these can be shared across threads. Without it, there are no threads to
+//! share them with, so the single-threaded versions are used instead.
+
+#[cfg(feature = "std")]
+pub(crate) use once_cell::sync::OnceCell;
+#[cfg(not(feature = "std"))]
+pub(crate) use once_cell::unsync::OnceCell;
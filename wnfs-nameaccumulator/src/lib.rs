@@ -1,9 +1,19 @@
 //! This library implements the cryptographic primitives necessary for WNFS to prove that its writes were valid in a way that's verifyable by third parties without read access.
 //!
 //! Specifically, it implements 2048-bit RSA accumulators and the PoKE* and PoKCR algorithms from the paper ["Batching Techniques for Accumulators with Applications to IOPs and Stateless Blockchains"](https://eprint.iacr.org/2018/1188.pdf), as well as some WNFS-specific interfaces and serialized representations for them.
+//!
+//! Without the default `std` feature, this crate is `no_std` and only depends on `alloc`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
+#[cfg(any(feature = "rug", feature = "num-bigint-dig"))]
+mod batch;
+mod context;
 mod error;
 mod fns;
+mod lazy;
 #[cfg(any(feature = "rug", feature = "num-bigint-dig"))]
 mod name;
 mod traits;
@@ -13,6 +23,8 @@ mod uint256_serde_be;
 #[cfg(not(feature = "num-bigint-dig"))]
 compile_error!("no backend for big numbers, enable either the 'rug' or 'num-bigint-dig' feature.");
 
+#[cfg(any(feature = "rug", feature = "num-bigint-dig"))]
+pub use batch::*;
 #[cfg(any(feature = "rug", feature = "num-bigint-dig"))]
 pub use name::*;
 pub use traits::*;
@@ -4,17 +4,21 @@ use crate::BigNumDig;
 #[cfg(feature = "rug")]
 use crate::BigNumRug;
 use crate::{
+    context::ModulusContext,
     error::VerificationError,
     fns::{blake3_prime_digest, blake3_prime_digest_fast, multi_exp},
+    lazy::OnceCell,
     traits::Big,
 };
+use alloc::{string::ToString, sync::Arc, vec::Vec};
 use anyhow::Result;
+use core::{hash::Hash, str::FromStr};
+#[cfg(feature = "std")]
 use libipld::Cid;
 use num_traits::{One, Zero};
-use once_cell::sync::OnceCell;
 use rand_core::CryptoRngCore;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::{hash::Hash, str::FromStr};
+#[cfg(feature = "std")]
 use wnfs_common::{BlockStore, Storable};
 
 /// The domain separation string for deriving the l hash in the PoKE* protocol.
@@ -44,7 +48,7 @@ pub struct Name<B: Big = DefaultBig> {
 }
 
 /// Represents a setup needed for RSA accumulator operation.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct AccumulatorSetup<B: Big = DefaultBig> {
     #[serde(bound = "B: Big")]
     #[serde(deserialize_with = "crate::uint256_serde_be::deserialize::<B, _>")]
@@ -54,6 +58,12 @@ pub struct AccumulatorSetup<B: Big = DefaultBig> {
     #[serde(deserialize_with = "crate::uint256_serde_be::deserialize::<B, _>")]
     #[serde(serialize_with = "crate::uint256_serde_be::serialize::<B, _>")]
     pub generator: B::Num,
+    /// Values derived from the modulus, shared with all setups using the same modulus
+    #[serde(skip)]
+    context: OnceCell<Arc<ModulusContext>>,
+    /// The generator this setup was first verified with and the result of verifying it
+    #[serde(skip)]
+    verification: OnceCell<(B::Num, Result<(), VerificationError>)>,
 }
 
 /// A WNFS name represented as the RSA accumulator of all of its name segments.
@@ -246,7 +256,7 @@ impl<B: Big> NameAccumulator<B> {
 
         self.state = B::modpow_product(&self.state, segments.iter(), &setup.modulus);
 
-        let data = poke_fiat_shamir_l_hash_data::<B>(&setup.modulus, &witness, &self.state);
+        let data = poke_fiat_shamir_l_hash_data::<B>(setup.modulus_bytes(), &witness, &self.state);
         let (l, l_hash_inc) = blake3_prime_digest::<B>(L_HASH_DSI, data, 16);
 
         let (q, r) = B::quotrem_product(segments.iter(), &l);
@@ -299,12 +309,12 @@ impl<B: Big> NameAccumulator<B> {
 }
 
 fn poke_fiat_shamir_l_hash_data<B: Big>(
-    modulus: &B::Num,
+    modulus_bytes: &[u8; 256],
     base: &B::Num,
     commitment: &B::Num,
 ) -> impl AsRef<[u8]> {
     [
-        B::to_bytes_be::<256>(modulus),
+        *modulus_bytes,
         B::to_bytes_be::<256>(base),
         B::to_bytes_be::<256>(commitment),
     ]
@@ -319,7 +329,7 @@ impl<B: Big> AccumulatorSetup<B> {
         let modulus = B::from_bytes_be(modulus_big_endian);
         // The generator is just some random quadratic residue.
         let generator = B::squaremod(&B::rand_below(&modulus, rng), &modulus);
-        Self { modulus, generator }
+        Self::from_parts(modulus, generator)
     }
 
     /// Does a trusted setup in-memory and throws away the prime factors.
@@ -333,7 +343,7 @@ impl<B: Big> AccumulatorSetup<B> {
         let modulus = B::rand_rsa_modulus(rng);
         // The generator is just some random quadratic residue.
         let generator = B::squaremod(&B::rand_below(&modulus, rng), &modulus);
-        Self { modulus, generator }
+        Self::from_parts(modulus, generator)
     }
 
     /// Faster than `trusted`, but depends on the 2048-bit [rsa factoring challenge]
@@ -347,7 +357,64 @@ impl<B: Big> AccumulatorSetup<B> {
             "25195908475657893494027183240048398571429282126204032027777137836043662020707595556264018525880784406918290641249515082189298559149176184502808489120072844992687392807287776735971418347270261896375014971824691165077613379859095700097330459748808428401797429100642458691817195118746121515172654632282216869987549182422433637259085141865462043576798423387184774447920739934236584823824281198163815010674810451660377306056201619676256133844143603833904414952634432190114657544454178424020924616515723350778707749817125772467962926386356373289912154831438167899885040445364023527381951378636564391212010397122822120720357",
         ).ok().unwrap();
         let generator = B::squaremod(&B::rand_below(&modulus, rng), &modulus);
-        Self { modulus, generator }
+        Self::from_parts(modulus, generator)
+    }
+
+    /// Creates a setup from a modulus and generator, without checking them.
+    fn from_parts(modulus: B::Num, generator: B::Num) -> Self {
+        Self {
+            modulus,
+            generator,
+            context: OnceCell::new(),
+            verification: OnceCell::new(),
+        }
+    }
+
+    /// Checks that this setup can be used for accumulators: the modulus must be an odd,
+    /// composite number of at most 2048 bits and the generator must be invertible
+    /// modulo the modulus.
+    ///
+    /// Verifying the modulus is expensive, so its result is cached for the whole process
+    /// by the hash of the modulus. Verifying another setup with the same modulus only
+    /// checks the generator. The result is also remembered in this setup, so verifying it
+    /// again, e.g. for every batched proof, is free unless the generator was changed.
+    pub fn verify(&self) -> Result<(), VerificationError> {
+        let (generator, result) = self
+            .verification
+            .get_or_init(|| (self.generator.clone(), self.verify_uncached()));
+        if *generator == self.generator {
+            return result.clone();
+        }
+
+        self.verify_uncached()
+    }
+
+    fn verify_uncached(&self) -> Result<(), VerificationError> {
+        if self.modulus > B::from_bytes_be(&[0xFF; 256]) {
+            return Err(VerificationError::InvalidModulus);
+        }
+
+        self.context().verify_modulus::<B>(&self.modulus)?;
+
+        if self.generator <= B::Num::one()
+            || self.generator >= self.modulus
+            || B::mod_inv(&self.generator, &self.modulus).is_none()
+        {
+            return Err(VerificationError::InvalidGenerator);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the modulus in the big-endian encoding that's hashed into proofs.
+    fn modulus_bytes(&self) -> &[u8; 256] {
+        &self.context().modulus_bytes
+    }
+
+    /// Returns the process-wide context of this setup's modulus, looking it up on first use.
+    fn context(&self) -> &ModulusContext {
+        self.context
+            .get_or_init(|| ModulusContext::get::<B>(&self.modulus))
     }
 }
 
@@ -361,6 +428,11 @@ impl<B: Big> NameSegment<B> {
     pub fn new_hashed(domain_separation_info: &str, data: impl AsRef<[u8]>) -> Self {
         Self(blake3_prime_digest::<B>(domain_separation_info, data, 32).0)
     }
+
+    /// Serializes this segment as its 32 big-endian bytes.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        B::to_bytes_be::<32>(&self.0)
+    }
 }
 
 impl<B: Big> BatchedProofPart<B> {
@@ -402,8 +474,11 @@ impl<'a, B: Big> BatchedProofVerification<'a, B> {
         commitment: &NameAccumulator<B>,
         proof_part: &UnbatchableProofPart<B>,
     ) -> Result<()> {
-        let hasher =
-            poke_fiat_shamir_l_hash_data::<B>(&self.setup.modulus, &base.state, &commitment.state);
+        let hasher = poke_fiat_shamir_l_hash_data::<B>(
+            self.setup.modulus_bytes(),
+            &base.state,
+            &commitment.state,
+        );
         let l = blake3_prime_digest_fast::<B>(L_HASH_DSI, hasher, 16, proof_part.l_hash_inc)
             .ok_or(VerificationError::LHashNonPrime)?;
 
@@ -430,6 +505,8 @@ impl<'a, B: Big> BatchedProofVerification<'a, B> {
     ///
     /// Will return an error if verification fails.
     pub fn verify(&self, batched_proof: &BatchedProofPart<B>) -> Result<()> {
+        self.setup.verify()?;
+
         let exponents = self.bases_and_exponents.iter().map(|(_, l)| l);
         let tmp = B::modpow_product(&batched_proof.big_q_product, exponents, &self.setup.modulus);
 
@@ -443,6 +520,7 @@ impl<'a, B: Big> BatchedProofVerification<'a, B> {
 
 macro_rules! impl_storable {
     ( $ty:ty ) => {
+        #[cfg(feature = "std")]
         impl<B: Big> Storable for $ty {
             type Serializable = $ty;
 
@@ -466,6 +544,7 @@ macro_rules! impl_storable {
 impl_storable!(AccumulatorSetup<B>);
 impl_storable!(NameSegment<B>);
 impl_storable!(NameAccumulator<B>);
+impl_storable!(ElementsProof<B>);
 impl_storable!(UnbatchableProofPart<B>);
 impl_storable!(BatchedProofPart<B>);
 
@@ -474,7 +553,7 @@ impl<B: Big> Serialize for NameSegment<B> {
     where
         S: serde::Serializer,
     {
-        serde_bytes::serialize(B::to_bytes_be::<32>(&self.0).as_ref(), serializer)
+        serde_bytes::serialize(self.to_bytes().as_ref(), serializer)
     }
 }
 
@@ -512,8 +591,8 @@ impl<'de, B: Big> Deserialize<'de> for UnbatchableProofPart<B> {
     where
         D: Deserializer<'de>,
     {
-        let (l_hash_inc, r): (u32, &serde_bytes::Bytes) = Deserialize::deserialize(deserializer)?;
-        let r = B::from_bytes_be(r);
+        let (l_hash_inc, r): (u32, serde_bytes::ByteBuf) = Deserialize::deserialize(deserializer)?;
+        let r = B::from_bytes_be(&r);
         Ok(Self { l_hash_inc, r })
     }
 }
@@ -529,6 +608,37 @@ impl<B: Big> Serialize for UnbatchableProofPart<B> {
     }
 }
 
+impl<'de, B: Big> Deserialize<'de> for ElementsProof<B> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (base, big_q, part): (serde_bytes::ByteBuf, serde_bytes::ByteBuf, _) =
+            Deserialize::deserialize(deserializer)?;
+        Ok(Self {
+            base: B::from_bytes_be(&base),
+            big_q: B::from_bytes_be(&big_q),
+            part,
+        })
+    }
+}
+
+impl<B: Big> Serialize for ElementsProof<B> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let base = B::to_bytes_be::<256>(&self.base);
+        let big_q = B::to_bytes_be::<256>(&self.big_q);
+        (
+            serde_bytes::Bytes::new(&base),
+            serde_bytes::Bytes::new(&big_q),
+            &self.part,
+        )
+            .serialize(serializer)
+    }
+}
+
 impl<B: Big> PartialEq for NameAccumulator<B> {
     fn eq(&self, other: &Self) -> bool {
         self.state == other.state
@@ -536,19 +646,19 @@ impl<B: Big> PartialEq for NameAccumulator<B> {
 }
 
 impl<B: Big> Ord for NameAccumulator<B> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         self.state.cmp(&other.state)
     }
 }
 
 impl<B: Big> PartialOrd for NameAccumulator<B> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<B: Big> std::fmt::Debug for NameAccumulator<B> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<B: Big> core::fmt::Debug for NameAccumulator<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("NameAccumulator")
             .field("state", &self.state.to_string())
             .finish()
@@ -556,11 +666,31 @@ impl<B: Big> std::fmt::Debug for NameAccumulator<B> {
 }
 
 impl<B: Big> Hash for NameAccumulator<B> {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         self.state.hash(state);
     }
 }
 
+impl<B: Big> PartialEq for AccumulatorSetup<B> {
+    fn eq(&self, other: &Self) -> bool {
+        self.modulus == other.modulus && self.generator == other.generator
+    }
+}
+
+impl<B: Big> Eq for AccumulatorSetup<B> {}
+
+impl<B: Big> Ord for AccumulatorSetup<B> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (&self.modulus, &self.generator).cmp(&(&other.modulus, &other.generator))
+    }
+}
+
+impl<B: Big> PartialOrd for AccumulatorSetup<B> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl<B: Big> AsRef<[u8]> for NameAccumulator<B> {
     fn as_ref(&self) -> &[u8] {
         self.as_bytes()
@@ -573,16 +703,16 @@ impl<B: Big> Default for BatchedProofPart<B> {
     }
 }
 
-impl<B: Big> std::fmt::Debug for NameSegment<B> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<B: Big> core::fmt::Debug for NameSegment<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_tuple("NameSegment")
             .field(&self.0.to_string())
             .finish()
     }
 }
 
-impl<B: Big> std::fmt::Debug for AccumulatorSetup<B> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<B: Big> core::fmt::Debug for AccumulatorSetup<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("AccumulatorSetup")
             .field("modulus", &self.modulus.to_string())
             .field("generator", &self.generator.to_string())
@@ -590,8 +720,8 @@ impl<B: Big> std::fmt::Debug for AccumulatorSetup<B> {
     }
 }
 
-impl<B: Big> std::fmt::Debug for UnbatchableProofPart<B> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<B: Big> core::fmt::Debug for UnbatchableProofPart<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("UnbatchableProofPart")
             .field("l_hash_inc", &self.l_hash_inc)
             .field("r", &self.r.to_string())
@@ -599,8 +729,8 @@ impl<B: Big> std::fmt::Debug for UnbatchableProofPart<B> {
     }
 }
 
-impl<B: Big> std::fmt::Debug for ElementsProof<B> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<B: Big> core::fmt::Debug for ElementsProof<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("ElementsProof")
             .field("base", &self.base.to_string())
             .field("big_q", &self.big_q.to_string())
@@ -609,8 +739,8 @@ impl<B: Big> std::fmt::Debug for ElementsProof<B> {
     }
 }
 
-impl<B: Big> std::fmt::Debug for BatchedProofPart<B> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<B: Big> core::fmt::Debug for BatchedProofPart<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("BatchedProofPart")
             .field("big_q_product", &self.big_q_product.to_string())
             .finish()
@@ -625,8 +755,8 @@ impl<B: Big> std::fmt::Debug for BatchedProofPart<B> {
 mod tests {
     use super::DefaultBig;
     use crate::{
-        AccumulatorSetup, BatchedProofPart, BatchedProofVerification, Big, BigNumDig, Name,
-        NameAccumulator, NameSegment,
+        error::VerificationError, AccumulatorSetup, BatchedProofPart, BatchedProofVerification,
+        Big, BigNumDig, Name, NameAccumulator, NameSegment,
     };
     use anyhow::Result;
     use libipld::{
@@ -813,6 +943,90 @@ mod tests {
         let parsed = BigUint::from_bytes_be(bytes.as_ref());
         prop_assert_eq!(parsed, num);
     }
+
+    /// Encodes a setup, segment, accumulator and proof that only depend on their inputs.
+    fn deterministic_encodings<B: Big>() -> Result<Vec<u8>> {
+        let mut setup = AccumulatorSetup::<B>::from_rsa_2048(&mut ChaCha12Rng::seed_from_u64(0));
+        setup.generator = B::squaremod(&B::from_bytes_be(&[2]), &setup.modulus);
+        let segment = NameSegment::<B>::new_hashed("rs-wnfs tests", b"hello");
+        let mut acc = NameAccumulator::empty(&setup);
+        let proof = acc.add(Some(&segment), &setup);
+        let mut batched_proof = BatchedProofPart::<B>::new();
+        batched_proof.add(&proof, &setup);
+
+        Ok([
+            encode(&setup, DagCborCodec)?,
+            encode(&segment, DagCborCodec)?,
+            encode(&acc, DagCborCodec)?,
+            encode(&proof.part, DagCborCodec)?,
+            encode(&batched_proof, DagCborCodec)?,
+        ]
+        .concat())
+    }
+
+    #[test]
+    fn encodings_are_the_same_across_backends() -> Result<()> {
+        let expected = "f3a05ffd1b641af2e6a3d4350f47d0532298449f1f70289492a6c12e3d2c3565";
+        let dig = deterministic_encodings::<BigNumDig>()?;
+        assert_eq!(blake3::hash(&dig).to_hex().as_str(), expected);
+
+        #[cfg(feature = "rug")]
+        {
+            use crate::BigNumRug;
+
+            let rug = deterministic_encodings::<BigNumRug>()?;
+            assert_eq!(rug, dig);
+
+            let setup = AccumulatorSetup::<BigNumDig>::from_rsa_2048(&mut thread_rng());
+            let bytes = encode(&setup, DagCborCodec)?;
+            let rug_setup: AccumulatorSetup<BigNumRug> = decode(&bytes, DagCborCodec)?;
+            assert_eq!(encode(&rug_setup, DagCborCodec)?, bytes);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn setup_verification_rejects_invalid_setups() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let setup = AccumulatorSetup::<BigNumDig>::from_rsa_2048(rng);
+        assert_eq!(setup.verify(), Ok(()));
+
+        let num = |n: u32| BigUint::from(n);
+        let prime = BigNumDig::rand_prime_256bit(rng);
+        let invalid = [
+            (num(4096), num(9), VerificationError::InvalidModulus),
+            (prime, num(9), VerificationError::InvalidModulus),
+            (num(15), num(1), VerificationError::InvalidGenerator),
+            (num(15), num(5), VerificationError::InvalidGenerator),
+            (num(15), num(19), VerificationError::InvalidGenerator),
+        ];
+        for (modulus, generator, error) in invalid {
+            let setup = AccumulatorSetup::<BigNumDig>::from_parts(modulus, generator);
+            assert_eq!(setup.verify(), Err(error));
+        }
+    }
+
+    #[test]
+    fn setups_with_the_same_modulus_share_their_context() {
+        let setup = AccumulatorSetup::<BigNumDig>::from_rsa_2048(&mut thread_rng());
+        let other = AccumulatorSetup::<BigNumDig>::from_rsa_2048(&mut thread_rng());
+        assert_ne!(setup, other);
+        assert!(std::ptr::eq(setup.context(), other.context()));
+
+        let trusted = AccumulatorSetup::<BigNumDig>::trusted(&mut ChaCha12Rng::seed_from_u64(0));
+        assert!(!std::ptr::eq(setup.context(), trusted.context()));
+    }
+
+    #[test]
+    fn setup_verification_is_redone_when_the_generator_changes() {
+        let mut setup = AccumulatorSetup::<BigNumDig>::from_rsa_2048(&mut thread_rng());
+        assert_eq!(setup.verify(), Ok(()));
+        assert_eq!(setup.verify(), Ok(()));
+
+        setup.generator = BigUint::from(1u32);
+        assert_eq!(setup.verify(), Err(VerificationError::InvalidGenerator));
+    }
 }
 
 #[cfg(test)]
@@ -1,3 +1,9 @@
+use core::{
+    fmt::{Debug, Display},
+    hash::Hash,
+    ops::{BitOrAssign, MulAssign, Rem, RemAssign},
+    str::FromStr,
+};
 #[cfg(feature = "num-bigint-dig")]
 use num_bigint_dig::{prime::probably_prime, BigUint, ModInverse, RandBigInt, RandPrime};
 use num_traits::{One, Zero};
@@ -8,16 +14,18 @@ use rug::{
     rand::RandState,
     Integer,
 };
-use std::{
-    fmt::{Debug, Display},
-    hash::Hash,
-    ops::{BitOrAssign, MulAssign, Rem, RemAssign},
-    str::FromStr,
-};
+#[cfg(feature = "std")]
 use wnfs_common::utils::CondSync;
 #[cfg(feature = "num-bigint-dig")]
 use zeroize::Zeroize;
 
+/// Without `std`, there are no threads to share big numbers with.
+#[cfg(not(feature = "std"))]
+pub trait CondSync {}
+
+#[cfg(not(feature = "std"))]
+impl<T> CondSync for T {}
+
 /// Big integer math functions required to implement name accumulators.
 ///
 /// This allows abstracting the big number library backend.
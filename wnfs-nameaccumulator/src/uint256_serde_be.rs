@@ -1,4 +1,5 @@
 use crate::Big;
+use alloc::vec::Vec;
 use serde::{Deserializer, Serializer};
 
 pub(crate) fn deserialize<'de, B: Big, D>(deserializer: D) -> Result<B::Num, D::Error>
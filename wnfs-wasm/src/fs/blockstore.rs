@@ -60,6 +60,7 @@ impl WnfsBlockStore for ForeignBlockStore {
         bytes: impl Into<Bytes>,
     ) -> Result<(), BlockStoreError> {
         let bytes: Bytes = bytes.into();
+        self.check_block_size(&bytes)?;
 
         JsFuture::from(self.0.put_block_keyed(cid.to_bytes(), bytes.into()))
             .await
@@ -92,6 +93,7 @@ impl WnfsBlockStore for ForeignBlockStore {
 
     async fn put_block(&self, bytes: impl Into<Bytes>, codec: u64) -> Result<Cid, BlockStoreError> {
         let bytes: Bytes = bytes.into();
+        self.check_block_size(&bytes)?;
 
         if Reflect::has(&self.0, &"putBlock".into()).map_err(reflection_err)? {
             let codec = codec.try_into().map_err(|e| {
@@ -0,0 +1,447 @@
+//! The bindgen API for the IndexedDB-backed block store.
+
+use crate::{
+    fs::{utils::error, JsResult},
+    value,
+};
+use anyhow::anyhow;
+use bytes::Bytes;
+use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+use libipld_core::cid::Cid;
+use wasm_bindgen::{closure::Closure, prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+use wnfs::common::{utils::verify_block, BlockStore as WnfsBlockStore, BlockStoreError};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+const DATABASE_VERSION: u32 = 1;
+
+/// Maps CID strings to block bytes.
+const BLOCKS_STORE: &str = "blocks";
+
+/// Holds the running totals under [`SIZE_KEY`] and [`COUNT_KEY`].
+const META_STORE: &str = "meta";
+
+const SIZE_KEY: &str = "size";
+
+const COUNT_KEY: &str = "count";
+
+//--------------------------------------------------------------------------------------------------
+// Externs
+//--------------------------------------------------------------------------------------------------
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = "IDBFactory")]
+    type IdbFactory;
+
+    #[wasm_bindgen(method, catch)]
+    fn open(this: &IdbFactory, name: &str, version: u32) -> Result<IdbOpenDbRequest, JsValue>;
+
+    #[wasm_bindgen(js_name = "IDBRequest")]
+    #[derive(Clone)]
+    type IdbRequest;
+
+    #[wasm_bindgen(method, getter, catch)]
+    fn result(this: &IdbRequest) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, getter)]
+    fn error(this: &IdbRequest) -> JsValue;
+
+    #[wasm_bindgen(method, setter)]
+    fn set_onsuccess(this: &IdbRequest, handler: &JsValue);
+
+    #[wasm_bindgen(method, setter)]
+    fn set_onerror(this: &IdbRequest, handler: &JsValue);
+
+    #[wasm_bindgen(extends = IdbRequest, js_name = "IDBOpenDBRequest")]
+    type IdbOpenDbRequest;
+
+    #[wasm_bindgen(method, setter)]
+    fn set_onupgradeneeded(this: &IdbOpenDbRequest, handler: &JsValue);
+
+    #[wasm_bindgen(js_name = "IDBDatabase")]
+    #[derive(Clone)]
+    type IdbDatabase;
+
+    #[wasm_bindgen(method, catch)]
+    fn transaction(
+        this: &IdbDatabase,
+        store_names: &Array,
+        mode: &str,
+    ) -> Result<IdbTransaction, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = "createObjectStore")]
+    fn create_object_store(this: &IdbDatabase, name: &str) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method)]
+    fn close(this: &IdbDatabase);
+
+    #[wasm_bindgen(js_name = "IDBTransaction")]
+    #[derive(Clone)]
+    type IdbTransaction;
+
+    #[wasm_bindgen(method, catch, js_name = "objectStore")]
+    fn object_store(this: &IdbTransaction, name: &str) -> Result<IdbObjectStore, JsValue>;
+
+    #[wasm_bindgen(method, getter)]
+    fn error(this: &IdbTransaction) -> JsValue;
+
+    #[wasm_bindgen(method, setter)]
+    fn set_oncomplete(this: &IdbTransaction, handler: &JsValue);
+
+    #[wasm_bindgen(method, setter)]
+    fn set_onerror(this: &IdbTransaction, handler: &JsValue);
+
+    #[wasm_bindgen(method, setter)]
+    fn set_onabort(this: &IdbTransaction, handler: &JsValue);
+
+    #[wasm_bindgen(js_name = "IDBObjectStore")]
+    type IdbObjectStore;
+
+    #[wasm_bindgen(method, catch)]
+    fn get(this: &IdbObjectStore, key: &JsValue) -> Result<IdbRequest, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn put(this: &IdbObjectStore, value: &JsValue, key: &JsValue) -> Result<IdbRequest, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn count(this: &IdbObjectStore, key: &JsValue) -> Result<IdbRequest, JsValue>;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A block store that persists blocks in the browser's IndexedDB.
+///
+/// It implements the `BlockStore` interface, so it can be passed anywhere a block store
+/// is expected. Blocks are checked against their CIDs when they're put and when they're
+/// read back, and the store keeps track of the number and total size of its blocks.
+///
+/// IndexedDB is available in windows and Web Workers, so a store can be opened by name
+/// in each of them to share the same blocks.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct IndexedDbBlockStore {
+    db: IdbDatabase,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[wasm_bindgen]
+impl IndexedDbBlockStore {
+    /// Opens the IndexedDB database with given name as a block store, creating it if needed.
+    pub fn open(name: String) -> JsResult<Promise> {
+        let factory = Reflect::get(&js_sys::global(), &value!("indexedDB"))
+            .map_err(error("Cannot access IndexedDB"))?;
+        if factory.is_undefined() {
+            return Err(js_sys::Error::new("IndexedDB is not available"));
+        }
+
+        let request = factory
+            .unchecked_into::<IdbFactory>()
+            .open(&name, DATABASE_VERSION)
+            .map_err(error("Cannot open database"))?;
+
+        let on_upgrade_needed = {
+            let request = request.clone();
+            Closure::once_into_js(move || {
+                if let Ok(db) = request.result() {
+                    let db = db.unchecked_into::<IdbDatabase>();
+                    let _ = db.create_object_store(BLOCKS_STORE);
+                    let _ = db.create_object_store(META_STORE);
+                }
+            })
+        };
+        request.set_onupgradeneeded(&on_upgrade_needed);
+
+        let opened = request_future(&request);
+        Ok(future_to_promise(async move {
+            let db = opened.await.map_err(error("Cannot open database"))?;
+            Ok(value!(IndexedDbBlockStore {
+                db: db.unchecked_into()
+            }))
+        }))
+    }
+
+    /// Closes the connection to the database.
+    pub fn close(&self) {
+        self.db.close();
+    }
+
+    /// Stores a block under given CID, after checking that the CID matches the bytes.
+    #[wasm_bindgen(js_name = "putBlockKeyed")]
+    pub fn put_block_keyed_js(&self, cid: Vec<u8>, bytes: Vec<u8>) -> JsResult<Promise> {
+        let cid = Cid::try_from(&cid[..]).map_err(error("Invalid CID"))?;
+        self.put_blocks_promise(vec![(cid, bytes.into())])
+    }
+
+    /// Stores a block and returns its CID.
+    #[wasm_bindgen(js_name = "putBlock")]
+    pub fn put_block_js(&self, bytes: Vec<u8>, codec: u32) -> JsResult<Promise> {
+        let cid = self
+            .create_cid(&bytes, codec.into())
+            .map_err(error("Cannot create CID"))?;
+        let store = self.clone();
+
+        Ok(future_to_promise(async move {
+            store
+                .put_blocks(vec![(cid, bytes.into())])
+                .await
+                .map_err(error("Cannot put block"))?;
+
+            Ok(value!(Uint8Array::from(&cid.to_bytes()[..])))
+        }))
+    }
+
+    /// Stores many blocks at once, given as an array of `[cid, bytes]` pairs.
+    ///
+    /// All blocks are checked against their CIDs first, and are then stored in a
+    /// single transaction, so either all of them are stored or none are.
+    #[wasm_bindgen(js_name = "putBlocks")]
+    pub fn put_blocks_js(&self, blocks: Array) -> JsResult<Promise> {
+        let blocks = blocks
+            .iter()
+            .map(|pair| {
+                let cid = Reflect::get(&pair, &value!(0)).map_err(error("Invalid block"))?;
+                let bytes = Reflect::get(&pair, &value!(1)).map_err(error("Invalid block"))?;
+                let cid = Uint8Array::new(&cid).to_vec();
+                let cid = Cid::try_from(&cid[..]).map_err(error("Invalid CID"))?;
+                Ok((cid, Bytes::from(Uint8Array::new(&bytes).to_vec())))
+            })
+            .collect::<JsResult<Vec<_>>>()?;
+
+        self.put_blocks_promise(blocks)
+    }
+
+    /// Gets the block with given CID, or `undefined` if it isn't stored.
+    #[wasm_bindgen(js_name = "getBlock")]
+    pub fn get_block_js(&self, cid: Vec<u8>) -> JsResult<Promise> {
+        let cid = Cid::try_from(&cid[..]).map_err(error("Invalid CID"))?;
+        let store = self.clone();
+
+        Ok(future_to_promise(async move {
+            match store.get_block(&cid).await {
+                Ok(bytes) => Ok(value!(Uint8Array::from(&bytes[..]))),
+                Err(BlockStoreError::CIDNotFound(_)) => Ok(JsValue::UNDEFINED),
+                Err(e) => Err(error("Cannot get block")(e).into()),
+            }
+        }))
+    }
+
+    /// Finds out whether a block with given CID is stored.
+    #[wasm_bindgen(js_name = "hasBlock")]
+    pub fn has_block_js(&self, cid: Vec<u8>) -> JsResult<Promise> {
+        let cid = Cid::try_from(&cid[..]).map_err(error("Invalid CID"))?;
+        let store = self.clone();
+
+        Ok(future_to_promise(async move {
+            let has_block = store
+                .has_block(&cid)
+                .await
+                .map_err(error("Cannot look up block"))?;
+
+            Ok(value!(has_block))
+        }))
+    }
+
+    /// Gets the total size of all stored blocks in bytes.
+    #[wasm_bindgen(js_name = "getSize")]
+    pub fn get_size(&self) -> JsResult<Promise> {
+        self.get_meta_promise(SIZE_KEY)
+    }
+
+    /// Gets the number of stored blocks.
+    #[wasm_bindgen(js_name = "getBlockCount")]
+    pub fn get_block_count(&self) -> JsResult<Promise> {
+        self.get_meta_promise(COUNT_KEY)
+    }
+}
+
+impl IndexedDbBlockStore {
+    /// Checks blocks against their CIDs and stores the ones that aren't stored yet,
+    /// updating the running totals in the same transaction.
+    async fn put_blocks(&self, blocks: Vec<(Cid, Bytes)>) -> Result<(), BlockStoreError> {
+        for (cid, bytes) in blocks.iter() {
+            self.check_block_size(bytes)?;
+            verify_block(cid, bytes)?;
+        }
+
+        let transaction = self.transaction("readwrite")?;
+        let done = transaction_future(&transaction);
+        let block_store = transaction.object_store(BLOCKS_STORE).map_err(idb_err)?;
+        let meta_store = transaction.object_store(META_STORE).map_err(idb_err)?;
+
+        let mut size = get_number(&meta_store, SIZE_KEY).await?;
+        let mut count = get_number(&meta_store, COUNT_KEY).await?;
+        for (cid, bytes) in blocks {
+            let key = value!(cid.to_string());
+            let existing = request_future(&block_store.count(&key).map_err(idb_err)?)
+                .await
+                .map_err(idb_err)?;
+
+            // Blocks are content-addressed, so there's nothing to overwrite
+            if existing.as_f64().unwrap_or_default() > 0.0 {
+                continue;
+            }
+
+            let value = value!(Uint8Array::from(&bytes[..]));
+            block_store.put(&value, &key).map_err(idb_err)?;
+            size += bytes.len() as f64;
+            count += 1.0;
+        }
+
+        meta_store
+            .put(&value!(size), &value!(SIZE_KEY))
+            .map_err(idb_err)?;
+        meta_store
+            .put(&value!(count), &value!(COUNT_KEY))
+            .map_err(idb_err)?;
+
+        done.await.map_err(idb_err)?;
+        Ok(())
+    }
+
+    fn put_blocks_promise(&self, blocks: Vec<(Cid, Bytes)>) -> JsResult<Promise> {
+        let store = self.clone();
+
+        Ok(future_to_promise(async move {
+            store
+                .put_blocks(blocks)
+                .await
+                .map_err(error("Cannot put blocks"))?;
+
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
+    fn get_meta_promise(&self, key: &'static str) -> JsResult<Promise> {
+        let store = self.clone();
+
+        Ok(future_to_promise(async move {
+            let transaction = store
+                .transaction("readonly")
+                .map_err(error("Cannot read"))?;
+            let meta_store = transaction
+                .object_store(META_STORE)
+                .map_err(error("Cannot read"))?;
+            let number = get_number(&meta_store, key)
+                .await
+                .map_err(error("Cannot read"))?;
+
+            Ok(value!(number))
+        }))
+    }
+
+    fn transaction(&self, mode: &str) -> Result<IdbTransaction, BlockStoreError> {
+        let store_names = Array::of2(&value!(BLOCKS_STORE), &value!(META_STORE));
+        self.db.transaction(&store_names, mode).map_err(idb_err)
+    }
+}
+
+impl WnfsBlockStore for IndexedDbBlockStore {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        let transaction = self.transaction("readonly")?;
+        let block_store = transaction.object_store(BLOCKS_STORE).map_err(idb_err)?;
+        let request = block_store.get(&value!(cid.to_string())).map_err(idb_err)?;
+        let value = request_future(&request).await.map_err(idb_err)?;
+
+        if value.is_undefined() {
+            return Err(BlockStoreError::CIDNotFound(*cid));
+        }
+
+        let bytes = Bytes::from(Uint8Array::new(&value).to_vec());
+        verify_block(cid, &bytes)?;
+        Ok(bytes)
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes>,
+    ) -> Result<(), BlockStoreError> {
+        self.put_blocks(vec![(cid, bytes.into())]).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        let transaction = self.transaction("readonly")?;
+        let block_store = transaction.object_store(BLOCKS_STORE).map_err(idb_err)?;
+        let request = block_store
+            .count(&value!(cid.to_string()))
+            .map_err(idb_err)?;
+        let count = request_future(&request).await.map_err(idb_err)?;
+
+        Ok(count.as_f64().unwrap_or_default() > 0.0)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Resolves with the result of a request, or rejects with its error.
+fn request_future(request: &IdbRequest) -> JsFuture {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_success = {
+            let request = request.clone();
+            Closure::once_into_js(move || {
+                let _ = resolve.call1(&JsValue::UNDEFINED, &request.result().unwrap_or_default());
+            })
+        };
+        let on_error = {
+            let request = request.clone();
+            Closure::once_into_js(move || {
+                let _ = reject.call1(&JsValue::UNDEFINED, &request.error());
+            })
+        };
+
+        request.set_onsuccess(&on_success);
+        request.set_onerror(&on_error);
+    });
+
+    JsFuture::from(promise)
+}
+
+/// Resolves once a transaction is committed, or rejects if it fails or is aborted.
+fn transaction_future(transaction: &IdbTransaction) -> JsFuture {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_complete = Closure::once_into_js(move || {
+            let _ = resolve.call0(&JsValue::UNDEFINED);
+        });
+        let on_error = {
+            let transaction = transaction.clone();
+            let reject = reject.clone();
+            Closure::once_into_js(move || {
+                let _ = reject.call1(&JsValue::UNDEFINED, &transaction.error());
+            })
+        };
+        let on_abort = {
+            let transaction = transaction.clone();
+            Closure::once_into_js(move || {
+                let _ = reject.call1(&JsValue::UNDEFINED, &transaction.error());
+            })
+        };
+
+        transaction.set_oncomplete(&on_complete);
+        transaction.set_onerror(&on_error);
+        transaction.set_onabort(&on_abort);
+    });
+
+    JsFuture::from(promise)
+}
+
+/// Reads a number from the meta store, which is zero if it isn't set yet.
+async fn get_number(meta_store: &IdbObjectStore, key: &str) -> Result<f64, BlockStoreError> {
+    let request = meta_store.get(&value!(key)).map_err(idb_err)?;
+    let value = request_future(&request).await.map_err(idb_err)?;
+    Ok(value.as_f64().unwrap_or_default())
+}
+
+fn idb_err(err: impl core::fmt::Debug) -> BlockStoreError {
+    BlockStoreError::Custom(anyhow!("IndexedDB operation failed: {err:?}"))
+}
@@ -1,11 +1,15 @@
 mod blockstore;
+mod indexed_db;
 mod metadata;
 mod private;
+mod progress;
 mod public;
 mod utils;
 
 pub use blockstore::*;
+pub use indexed_db::*;
 pub use private::*;
+pub use progress::*;
 pub use public::*;
 
 pub type JsResult<T> = Result<T, js_sys::Error>;
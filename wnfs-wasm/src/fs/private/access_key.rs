@@ -28,9 +28,8 @@ impl AccessKey {
     #[wasm_bindgen(js_name = "getTemporalKey")]
     pub fn get_temporal_key(&self) -> Option<Vec<u8>> {
         self.0
-            .get_temporal_key()
-            .ok()
-            .map(|k| k.as_bytes().to_vec())
+            .as_revision()
+            .map(|key| key.get_temporal_key().as_bytes().to_vec())
     }
 
     /// Returns the snapshot key.
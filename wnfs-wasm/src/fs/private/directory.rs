@@ -3,14 +3,16 @@
 use super::Name;
 use crate::{
     fs::{
+        cancellation_token,
         metadata::JsMetadata,
         utils::{self, error},
-        BlockStore, ForeignBlockStore, JsResult, PrivateForest, PrivateNode, Rng,
+        AbortSignal, BlockStore, CancellableBlockStore, ForeignBlockStore, JsProgress, JsResult,
+        PrivateForest, PrivateNode, Rng,
     },
     value,
 };
 use chrono::{DateTime, Utc};
-use js_sys::{Array, Date, Promise, Uint8Array};
+use js_sys::{Array, Date, Function, Promise, Uint8Array};
 use std::rc::Rc;
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 use wasm_bindgen_futures::future_to_promise;
@@ -254,6 +256,67 @@ impl PrivateDirectory {
         }))
     }
 
+    /// Writes a file to the directory like `write`, reporting the number of bytes written
+    /// so far to `onProgress`.
+    ///
+    /// Aborting `signal` cancels the write. Content is written in full before the directory
+    /// is changed, so a cancelled write leaves no changes behind.
+    #[wasm_bindgen(js_name = "writeWithProgress")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_with_progress(
+        &self,
+        path_segments: &Array,
+        search_latest: bool,
+        content: Vec<u8>,
+        time: &Date,
+        forest: &PrivateForest,
+        store: BlockStore,
+        mut rng: Rng,
+        on_progress: Option<Function>,
+        signal: Option<AbortSignal>,
+    ) -> JsResult<Promise> {
+        let mut directory = Rc::clone(&self.0);
+        let mut store = ForeignBlockStore(store);
+        let time = DateTime::<Utc>::from(time);
+        let path_segments = utils::convert_path_segments(path_segments)?;
+        let mut forest = Rc::clone(&forest.0);
+        let progress = JsProgress::new(on_progress);
+        let token = cancellation_token(signal);
+
+        Ok(future_to_promise(async move {
+            token.check().map_err(error("Cannot write to directory"))?;
+            let total = content.len() as u64;
+            directory
+                .open_file_mut(
+                    &path_segments,
+                    search_latest,
+                    time,
+                    &mut forest,
+                    &mut store,
+                    &mut rng,
+                )
+                .await
+                .map_err(error("Cannot write to directory"))?
+                .set_content_with_progress(
+                    token.reader(&content[..]),
+                    Some(total),
+                    &progress,
+                    time,
+                    &mut forest,
+                    &mut store,
+                    &mut rng,
+                )
+                .await
+                .map_err(error("Cannot write to directory"))?;
+
+            Ok(utils::create_private_op_result(
+                directory,
+                forest,
+                JsValue::NULL,
+            )?)
+        }))
+    }
+
     /// Moves a specified path to a new location.
     #[wasm_bindgen(js_name = "basicMv")]
     #[allow(clippy::too_many_arguments)]
@@ -337,6 +400,57 @@ impl PrivateDirectory {
         }))
     }
 
+    /// Copies a specified path to a new location like `cp`, reporting the number of nodes
+    /// copied so far and the total number of nodes to copy to `onProgress`.
+    ///
+    /// Aborting `signal` cancels the copy at the next block it reads or writes. This
+    /// directory is left unchanged then, and blocks written so far stay in the store.
+    #[wasm_bindgen(js_name = "cpWithProgress")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn cp_with_progress(
+        &self,
+        path_segments_from: &Array,
+        path_segments_to: &Array,
+        search_latest: bool,
+        time: &Date,
+        forest: &PrivateForest,
+        store: BlockStore,
+        mut rng: Rng,
+        on_progress: Option<Function>,
+        signal: Option<AbortSignal>,
+    ) -> JsResult<Promise> {
+        let mut directory = Rc::clone(&self.0);
+        let token = cancellation_token(signal);
+        let mut store = CancellableBlockStore::new(ForeignBlockStore(store), token);
+        let time = DateTime::<Utc>::from(time);
+        let path_segments_from = utils::convert_path_segments(path_segments_from)?;
+        let path_segments_to = utils::convert_path_segments(path_segments_to)?;
+        let mut forest = Rc::clone(&forest.0);
+        let progress = JsProgress::new(on_progress);
+
+        Ok(future_to_promise(async move {
+            directory
+                .cp_with_progress(
+                    &path_segments_from,
+                    &path_segments_to,
+                    search_latest,
+                    time,
+                    &progress,
+                    &mut forest,
+                    &mut store,
+                    &mut rng,
+                )
+                .await
+                .map_err(error("Cannot copy content between directories"))?;
+
+            Ok(utils::create_private_op_result(
+                directory,
+                forest,
+                JsValue::NULL,
+            )?)
+        }))
+    }
+
     /// Creates a new directory at the specified path.
     ///
     /// This method acts like `mkdir -p` in Unix because it creates intermediate directories if they do not exist.
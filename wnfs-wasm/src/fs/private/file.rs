@@ -4,7 +4,7 @@ use crate::{
     fs::{
         metadata::JsMetadata,
         utils::{self, error},
-        BlockStore, ForeignBlockStore, JsResult, PrivateForest, PrivateNode, Rng,
+        BlockStore, FileViewToken, ForeignBlockStore, JsResult, PrivateForest, PrivateNode, Rng,
     },
     value,
 };
@@ -106,6 +106,24 @@ impl PrivateFile {
         }))
     }
 
+    /// Creates a view token, which gives read access to this revision's content
+    /// without the private forest.
+    #[wasm_bindgen(js_name = "viewToken")]
+    pub fn view_token(&self, forest: &PrivateForest, store: BlockStore) -> JsResult<Promise> {
+        let file = Rc::clone(&self.0);
+        let store = ForeignBlockStore(store);
+        let forest = Rc::clone(&forest.0);
+
+        Ok(future_to_promise(async move {
+            let token = file
+                .view_token(&forest, &store)
+                .await
+                .map_err(error("Cannot create view token"))?;
+
+            Ok(value!(FileViewToken(Rc::new(token))))
+        }))
+    }
+
     /// Gets the metadata of this file.
     pub fn metadata(&self) -> JsResult<JsValue> {
         JsMetadata(self.0.get_metadata()).try_into()
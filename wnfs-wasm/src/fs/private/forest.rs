@@ -111,6 +111,42 @@ impl PrivateForest {
         }))
     }
 
+    /// Whether nodes and file content may be read from ciphertexts that aren't bound
+    /// to the label they're stored under, because they were written before WNFS bound
+    /// ciphertexts to their labels.
+    #[wasm_bindgen(js_name = "acceptsUnboundCiphertexts")]
+    pub fn accepts_unbound_ciphertexts(&self) -> bool {
+        self.0.accepts_unbound_ciphertexts()
+    }
+
+    /// Whether this forest may still hold ciphertexts that aren't bound to their labels,
+    /// e.g. because it was stored before WNFS bound ciphertexts to their labels.
+    ///
+    /// This is only a hint read from the forest, and never turns on accepting unbound
+    /// ciphertexts by itself.
+    #[wasm_bindgen(js_name = "mayHoldUnboundCiphertexts")]
+    pub fn may_hold_unbound_ciphertexts(&self) -> bool {
+        self.0.may_hold_unbound_ciphertexts()
+    }
+
+    /// Returns a copy of this forest that does or doesn't accept unbound ciphertexts.
+    /// Loaded forests never accept them until this is called.
+    #[wasm_bindgen(js_name = "withAcceptUnboundCiphertexts")]
+    pub fn with_accept_unbound_ciphertexts(&self, accept: bool) -> PrivateForest {
+        let mut forest = Rc::clone(&self.0);
+        Rc::make_mut(&mut forest).set_accept_unbound_ciphertexts(accept);
+        PrivateForest(forest)
+    }
+
+    /// Returns a copy of this forest that does or doesn't encrypt everything written to it
+    /// with deterministic nonces, so the same writes result in the same forest.
+    #[wasm_bindgen(js_name = "withDeterministicNonces")]
+    pub fn with_deterministic_nonces(&self, deterministic: bool) -> PrivateForest {
+        let mut forest = Rc::clone(&self.0);
+        Rc::make_mut(&mut forest).set_deterministic_nonces(deterministic);
+        PrivateForest(forest)
+    }
+
     #[wasm_bindgen(js_name = "emptyName")]
     pub fn empty_name(&self) -> Name {
         Name(self.0.empty_name())
@@ -7,6 +7,8 @@ mod name;
 mod node;
 mod rng;
 mod share;
+mod transfer;
+mod view_token;
 
 pub use access_key::*;
 pub use directory::*;
@@ -17,3 +19,5 @@ pub use name::*;
 pub use node::*;
 pub use rng::*;
 pub use share::*;
+pub use transfer::*;
+pub use view_token::*;
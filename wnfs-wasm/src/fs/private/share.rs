@@ -102,10 +102,10 @@ pub fn receive_share(
     let forest = Rc::clone(&forest.0);
 
     Ok(future_to_promise(async move {
-        let node = recipient::receive_share(&share_name.0, &recipient_key, &forest, &store)
+        let payload = recipient::receive_share(&share_name.0, &recipient_key, &forest, &store)
             .await
             .map_err(error("Cannot receive share"))?;
 
-        Ok(value!(PrivateNode(node)))
+        Ok(value!(PrivateNode(payload.node)))
     }))
 }
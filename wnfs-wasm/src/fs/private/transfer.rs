@@ -0,0 +1,129 @@
+//! The bindgen API for moving private file system state between Web Workers.
+//!
+//! Objects like `PrivateDirectory` or `PrivateForest` point into the memory of the wasm
+//! instance that created them, so they can't be posted to a Web Worker. Instead, the state
+//! is turned into a plain object of byte arrays, which is structured-cloneable, and loaded
+//! again on the other side. Only the roots are loaded eagerly, everything else is loaded
+//! lazily from the block store as usual, so both sides need access to the same blocks.
+
+use super::{AccessKey, PrivateDirectory, PrivateForest};
+use crate::{
+    fs::{utils::error, BlockStore, ForeignBlockStore, JsResult, Rng},
+    value,
+};
+use js_sys::{Array, Error, Object, Promise, Reflect, Uint8Array};
+use libipld_core::cid::Cid;
+use std::rc::Rc;
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::future_to_promise;
+use wnfs::{
+    common::Storable,
+    private::{
+        forest::hamt::HamtForest as WnfsHamtForest, AccessKey as WnfsAccessKey,
+        PrivateNode as WnfsPrivateNode,
+    },
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Stores the root directory and forest, and returns a structured-cloneable object
+/// that can be posted to a Web Worker and passed to `fromTransferable` there.
+///
+/// The object has the forest CID (`forestCid`) and the access key to the root
+/// directory (`accessKey`) as bytes. The access key is secret key material!
+/// Operations that haven't been applied yet can be passed along as `pendingOps`,
+/// which need to be structured-cloneable themselves.
+#[wasm_bindgen(js_name = "toTransferable")]
+pub fn to_transferable(
+    root_dir: &PrivateDirectory,
+    forest: &PrivateForest,
+    pending_ops: Option<Array>,
+    store: BlockStore,
+    mut rng: Rng,
+) -> JsResult<Promise> {
+    let node = WnfsPrivateNode::Dir(Rc::clone(&root_dir.0));
+    let mut forest = Rc::clone(&forest.0);
+    let store = ForeignBlockStore(store);
+
+    Ok(future_to_promise(async move {
+        let access_key = node
+            .store(&mut forest, &store, &mut rng)
+            .await
+            .map_err(error("Cannot store root directory"))?;
+        let forest_cid = forest
+            .store(&store)
+            .await
+            .map_err(error("Cannot store forest"))?;
+        let access_key_bytes = access_key
+            .to_bytes()
+            .map_err(error("Cannot serialize access key"))?;
+
+        let state = Object::new();
+        set(&state, "forestCid", &bytes_value(&forest_cid.to_bytes()))?;
+        set(&state, "accessKey", &bytes_value(&access_key_bytes))?;
+        set(&state, "pendingOps", &pending_ops.unwrap_or_default())?;
+
+        Ok(value!(state))
+    }))
+}
+
+/// Loads the root directory and forest from an object created by `toTransferable`.
+///
+/// Resolves to an object with the `rootDir`, the `forest` and the `pendingOps`.
+#[wasm_bindgen(js_name = "fromTransferable")]
+pub fn from_transferable(state: JsValue, store: BlockStore) -> JsResult<Promise> {
+    let forest_cid = get_bytes(&state, "forestCid")?;
+    let forest_cid = Cid::try_from(&forest_cid[..]).map_err(error("Invalid forest CID"))?;
+    let access_key = WnfsAccessKey::parse(get_bytes(&state, "accessKey")?)
+        .map_err(error("Couldn't parse access key"))?;
+    let pending_ops = match get(&state, "pendingOps")? {
+        ops if ops.is_undefined() => Array::new(),
+        ops => ops
+            .dyn_into::<Array>()
+            .map_err(|_| Error::new("Invalid pendingOps: Expected an array"))?,
+    };
+    let store = ForeignBlockStore(store);
+
+    Ok(future_to_promise(async move {
+        let forest = Rc::new(
+            WnfsHamtForest::load(&forest_cid, &store)
+                .await
+                .map_err(error("Couldn't deserialize forest"))?,
+        );
+        let root_dir = WnfsPrivateNode::load(&access_key, &forest, &store, None)
+            .await
+            .map_err(error("Cannot load root directory"))?
+            .as_dir()
+            .map_err(error("Cannot load root directory"))?;
+
+        let loaded = Object::new();
+        set(&loaded, "rootDir", &PrivateDirectory(root_dir).into())?;
+        set(&loaded, "forest", &PrivateForest(forest).into())?;
+        set(&loaded, "accessKey", &AccessKey(access_key).into())?;
+        set(&loaded, "pendingOps", &pending_ops)?;
+
+        Ok(value!(loaded))
+    }))
+}
+
+fn bytes_value(bytes: &[u8]) -> JsValue {
+    value!(Uint8Array::from(bytes))
+}
+
+fn set(object: &Object, key: &str, value: &JsValue) -> JsResult<()> {
+    Reflect::set(object, &value!(key), value).map_err(error(&format!("Failed to set {key}")))?;
+    Ok(())
+}
+
+fn get(object: &JsValue, key: &str) -> JsResult<JsValue> {
+    Reflect::get(object, &value!(key)).map_err(error(&format!("Failed to get {key}")))
+}
+
+fn get_bytes(object: &JsValue, key: &str) -> JsResult<Vec<u8>> {
+    get(object, key)?
+        .dyn_into::<Uint8Array>()
+        .map(|bytes| bytes.to_vec())
+        .map_err(|_| Error::new(&format!("Invalid {key}: Expected a Uint8Array")))
+}
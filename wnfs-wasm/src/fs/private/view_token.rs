@@ -0,0 +1,80 @@
+//! The bindgen API for FileViewToken.
+use crate::{
+    fs::{utils::error, BlockStore, ForeignBlockStore, JsResult},
+    value,
+};
+use js_sys::{Array, Promise, Uint8Array};
+use std::rc::Rc;
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen_futures::future_to_promise;
+use wnfs::private::FileViewToken as WnfsFileViewToken;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Read access to one revision of a private file's content, without a private forest.
+#[wasm_bindgen]
+pub struct FileViewToken(pub(crate) Rc<WnfsFileViewToken>);
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[wasm_bindgen]
+impl FileViewToken {
+    /// Serialize this view token into bytes.
+    /// This will contain secret key material!
+    #[wasm_bindgen(js_name = "toBytes")]
+    pub fn into_bytes(&self) -> JsResult<Vec<u8>> {
+        let bytes = self
+            .0
+            .to_bytes()
+            .map_err(error("Couldn't serialize view token"))?;
+        Ok(bytes)
+    }
+
+    /// Deserialize a view token previously generated from `toBytes`.
+    #[wasm_bindgen(js_name = "fromBytes")]
+    pub fn from_bytes(bytes: &[u8]) -> JsResult<FileViewToken> {
+        let token = WnfsFileViewToken::parse(bytes).map_err(error("Couldn't parse view token"))?;
+        Ok(Self(Rc::new(token)))
+    }
+
+    /// Gets the exact size of the file content.
+    #[wasm_bindgen(js_name = "getSize")]
+    pub fn get_size(&self) -> usize {
+        self.0.get_size() as usize
+    }
+
+    /// Gets the MIME type of the file content, if it was known.
+    #[wasm_bindgen(js_name = "getContentType")]
+    pub fn get_content_type(&self) -> Option<String> {
+        self.0.get_content_type().map(String::from)
+    }
+
+    /// Gets the CIDs of the encrypted content blocks, in order.
+    #[wasm_bindgen(js_name = "getBlockCids")]
+    pub fn get_block_cids(&self) -> Array {
+        self.0
+            .get_block_cids()
+            .map(|cid| Uint8Array::from(&cid.to_bytes()[..]))
+            .collect()
+    }
+
+    /// Fetches & decrypts the entire content of the file from given block store.
+    #[wasm_bindgen(js_name = "getContent")]
+    pub fn get_content(&self, store: BlockStore) -> JsResult<Promise> {
+        let token = Rc::clone(&self.0);
+        let store = ForeignBlockStore(store);
+
+        Ok(future_to_promise(async move {
+            let content = token
+                .get_content(&store)
+                .await
+                .map_err(error("Cannot read file"))?;
+
+            Ok(value!(Uint8Array::from(content.as_ref())))
+        }))
+    }
+}
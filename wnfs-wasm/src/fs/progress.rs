@@ -0,0 +1,130 @@
+//! Progress callbacks and cancellation for long-running operations.
+
+use bytes::Bytes;
+use js_sys::Function;
+use libipld_core::cid::Cid;
+use wasm_bindgen::{closure::Closure, prelude::wasm_bindgen, JsValue};
+use wnfs::common::{
+    utils::{CancellationToken, ProgressReporter},
+    BlockStore as WnfsBlockStore, BlockStoreError,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Externs
+//--------------------------------------------------------------------------------------------------
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_PROGRESS: &'static str = r#"
+export type ProgressCallback = (done: number, total?: number) => void;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "AbortSignal")]
+    pub type AbortSignal;
+
+    #[wasm_bindgen(method, getter)]
+    fn aborted(this: &AbortSignal) -> bool;
+
+    #[wasm_bindgen(method, js_name = "addEventListener")]
+    fn add_event_listener(this: &AbortSignal, event: &str, listener: &JsValue);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Reports progress to an optional JavaScript `ProgressCallback`.
+pub(crate) struct JsProgress(Option<Function>);
+
+/// A block store that fails every request once its token is cancelled, so operations
+/// that don't check for cancellation themselves stop at their next block.
+pub(crate) struct CancellableBlockStore<B> {
+    inner: B,
+    token: CancellationToken,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl JsProgress {
+    pub(crate) fn new(callback: Option<Function>) -> Self {
+        Self(callback)
+    }
+}
+
+impl ProgressReporter for JsProgress {
+    fn on_progress(&self, done: u64, total: Option<u64>) {
+        let Some(callback) = &self.0 else {
+            return;
+        };
+
+        let total = total.map_or(JsValue::UNDEFINED, |total| JsValue::from(total as f64));
+        // Progress is informational, so errors thrown by the callback are ignored.
+        let _ = callback.call2(&JsValue::NULL, &JsValue::from(done as f64), &total);
+    }
+}
+
+impl<B> CancellableBlockStore<B> {
+    pub(crate) fn new(inner: B, token: CancellationToken) -> Self {
+        Self { inner, token }
+    }
+
+    fn check(&self) -> Result<(), BlockStoreError> {
+        Ok(self.token.check()?)
+    }
+}
+
+impl<B: WnfsBlockStore> WnfsBlockStore for CancellableBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        self.check()?;
+        self.inner.get_block(cid).await
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes>,
+    ) -> Result<(), BlockStoreError> {
+        self.check()?;
+        self.inner.put_block_keyed(cid, bytes).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        self.check()?;
+        self.inner.has_block(cid).await
+    }
+
+    async fn put_block(&self, bytes: impl Into<Bytes>, codec: u64) -> Result<Cid, BlockStoreError> {
+        self.check()?;
+        self.inner.put_block(bytes, codec).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.inner.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Creates a cancellation token that's cancelled once given signal aborts.
+pub(crate) fn cancellation_token(signal: Option<AbortSignal>) -> CancellationToken {
+    let token = CancellationToken::new();
+    if let Some(signal) = signal {
+        if signal.aborted() {
+            token.cancel();
+        } else {
+            let handle = token.clone();
+            signal.add_event_listener("abort", &Closure::once_into_js(move || handle.cancel()));
+        }
+    }
+
+    token
+}
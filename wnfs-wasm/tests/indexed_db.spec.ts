@@ -0,0 +1,100 @@
+///<reference path="server/index.d.ts"/>
+
+import { expect, test } from "@playwright/test";
+
+const url = "http://localhost:8085";
+
+test.beforeEach(async ({ page }) => {
+  await page.goto(url);
+  await page.waitForFunction(() => window.setup != null);
+});
+
+test.describe("IndexedDbBlockStore", () => {
+  test("can be used as block store and keeps blocks across connections", async ({ page }) => {
+    const [content, count, size] = await page.evaluate(async () => {
+      const {
+        wnfs: { IndexedDbBlockStore, PrivateDirectory, PrivateForest, PrivateNode },
+        mock: { Rng },
+      } = await window.setup();
+
+      const rng = new Rng();
+      const store = await IndexedDbBlockStore.open("wnfs-test-reopen");
+      const initialForest = new PrivateForest(rng);
+      const root = new PrivateDirectory(initialForest.emptyName(), new Date(), rng);
+
+      var { rootDir, forest } = await root.write(
+        ["text.txt"],
+        true,
+        new Uint8Array([1, 2, 3, 4, 5]),
+        new Date(),
+        initialForest,
+        store,
+        rng,
+      );
+      var [accessKey, forest] = await rootDir.store(forest, store, rng);
+      const forestCid = await forest.store(store);
+      store.close();
+
+      const reopened = await IndexedDbBlockStore.open("wnfs-test-reopen");
+      const loadedForest = await PrivateForest.load(forestCid, reopened);
+      const node = await PrivateNode.load(accessKey, loadedForest, reopened);
+      var { result } = await node.asDir().read(["text.txt"], true, loadedForest, reopened);
+
+      return [result, await reopened.getBlockCount(), await reopened.getSize()];
+    });
+
+    expect(new Uint8Array(Object.values(content))).toEqual(new Uint8Array([1, 2, 3, 4, 5]));
+    expect(count).toBeGreaterThan(0);
+    expect(size).toBeGreaterThan(0);
+  });
+
+  test("putBlocks stores all blocks at once and counts duplicates once", async ({ page }) => {
+    const [count, size, hasBlock] = await page.evaluate(async () => {
+      const {
+        wnfs: { IndexedDbBlockStore },
+      } = await window.setup();
+
+      const store = await IndexedDbBlockStore.open("wnfs-test-batch");
+      const hello = new TextEncoder().encode("hello");
+      const world = new TextEncoder().encode("world!");
+      const helloCid = await store.putBlock(hello, 0x55);
+      const worldCid = await store.putBlock(world, 0x55);
+      await store.putBlocks([
+        [helloCid, hello],
+        [worldCid, world],
+      ]);
+
+      return [await store.getBlockCount(), await store.getSize(), await store.hasBlock(worldCid)];
+    });
+
+    expect(count).toEqual(2);
+    expect(size).toEqual(11);
+    expect(hasBlock).toBe(true);
+  });
+
+  test("rejects blocks that don't match their CID", async ({ page }) => {
+    const [error, count] = await page.evaluate(async () => {
+      const {
+        wnfs: { IndexedDbBlockStore },
+      } = await window.setup();
+
+      const store = await IndexedDbBlockStore.open("wnfs-test-verify");
+      const cid = await store.putBlock(new TextEncoder().encode("hello"), 0x55);
+
+      let error;
+      try {
+        await store.putBlocks([
+          [cid, new TextEncoder().encode("tampered")],
+          [cid, new TextEncoder().encode("hello")],
+        ]);
+      } catch (e) {
+        error = String(e);
+      }
+
+      return [error, await store.getBlockCount()];
+    });
+
+    expect(error).toContain("doesn't match its CID");
+    expect(count).toEqual(1);
+  });
+});
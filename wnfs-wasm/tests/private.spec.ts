@@ -351,6 +351,59 @@ test.describe("PrivateDirectory", () => {
     expect(imagesContent[0].name).toEqual("cats");
     expect(picturesContent[0].name).toEqual("cats");
   });
+
+  test("writeWithProgress reports progress and can be aborted", async ({ page }) => {
+    const [progress, content, abortError] = await page.evaluate(async () => {
+      const {
+        wnfs: { PrivateDirectory, PrivateForest },
+        mock: { MemoryBlockStore, Rng },
+      } = await window.setup();
+
+      const rng = new Rng();
+      const store = new MemoryBlockStore();
+      const initialForest = new PrivateForest(rng);
+      const root = new PrivateDirectory(initialForest.emptyName(), new Date(), rng);
+      const updates: [number, number | undefined][] = [];
+
+      var { rootDir, forest } = await root.writeWithProgress(
+        ["videos", "talk.mp4"],
+        true,
+        new Uint8Array(600_000),
+        new Date(),
+        initialForest,
+        store,
+        rng,
+        (done: number, total?: number) => updates.push([done, total]),
+      );
+
+      var { result: content } = await rootDir.read(["videos", "talk.mp4"], true, forest, store);
+
+      const controller = new AbortController();
+      controller.abort();
+      let abortError;
+      try {
+        await rootDir.writeWithProgress(
+          ["videos", "other.mp4"],
+          true,
+          new Uint8Array(600_000),
+          new Date(),
+          forest,
+          store,
+          rng,
+          undefined,
+          controller.signal,
+        );
+      } catch (error) {
+        abortError = `${error}`;
+      }
+
+      return [updates[updates.length - 1], content.length, abortError];
+    });
+
+    expect(progress).toEqual([600_000, 600_000]);
+    expect(content).toEqual(600_000);
+    expect(abortError).toContain("cancelled");
+  });
 });
 
 test.describe("PrivateFile", () => {
@@ -478,6 +531,69 @@ test.describe("PrivateFile", () => {
     expect(size).toEqual(2 * 1024 * 1024);
   });
 
+  test("viewToken can be decoded and read without a forest", async ({ page }) => {
+    const [size, content] = await page.evaluate(async () => {
+      const {
+        wnfs: { FileViewToken, PrivateFile, PrivateForest },
+        mock: { MemoryBlockStore, Rng },
+      } = await window.setup();
+
+      const rng = new Rng();
+      const initialForest = new PrivateForest(rng);
+      const store = new MemoryBlockStore();
+      var [file, forest] = await PrivateFile.withContent(
+        initialForest.emptyName(),
+        new Date(),
+        new Uint8Array([1, 2, 3, 4, 5]),
+        initialForest,
+        store,
+        rng,
+      );
+
+      const bytes = (await file.viewToken(forest, store)).toBytes();
+      const token = FileViewToken.fromBytes(bytes);
+
+      return [token.getSize(), await token.getContent(store)];
+    });
+
+    expect(size).toEqual(5);
+    expect(new Uint8Array(Object.values(content))).toEqual(new Uint8Array([1, 2, 3, 4, 5]));
+  });
+
+  test("toTransferable state can be structured-cloned and loaded again", async ({ page }) => {
+    const [content, pendingOps] = await page.evaluate(async () => {
+      const {
+        wnfs: { PrivateDirectory, PrivateForest, toTransferable, fromTransferable },
+        mock: { MemoryBlockStore, Rng },
+      } = await window.setup();
+
+      const rng = new Rng();
+      const initialForest = new PrivateForest(rng);
+      const store = new MemoryBlockStore();
+      const root = new PrivateDirectory(initialForest.emptyName(), new Date(), rng);
+
+      var { rootDir, forest } = await root.write(
+        ["text.txt"],
+        true,
+        new Uint8Array([1, 2, 3, 4, 5]),
+        new Date(),
+        initialForest,
+        store,
+        rng,
+      );
+
+      const pending = [{ op: "rm", path: ["text.txt"] }];
+      const state = structuredClone(await toTransferable(rootDir, forest, pending, store, rng));
+      var { rootDir, forest, pendingOps } = await fromTransferable(state, store);
+
+      var { result } = await rootDir.read(["text.txt"], true, forest, store);
+      return [result, pendingOps];
+    });
+
+    expect(new Uint8Array(Object.values(content))).toEqual(new Uint8Array([1, 2, 3, 4, 5]));
+    expect(pendingOps).toEqual([{ op: "rm", path: ["text.txt"] }]);
+  });
+
   test("A PrivateDirectory has the correct metadata", async ({ page }) => {
     const result = await page.evaluate(async () => {
       const {
@@ -670,6 +786,29 @@ test.describe("PrivateForest", () => {
 
     expect(result).toBeDefined();
   });
+
+  test("accepting unbound ciphertexts is never loaded from the forest", async ({ page }) => {
+    const result = await page.evaluate(async () => {
+      const {
+        wnfs: { PrivateForest },
+        mock: { MemoryBlockStore, Rng },
+      } = await window.setup();
+
+      const rng = new Rng();
+      const store = new MemoryBlockStore();
+      const forest = new PrivateForest(rng);
+      const legacy = forest.withAcceptUnboundCiphertexts(true);
+      const loaded = await PrivateForest.load(await legacy.store(store), store);
+
+      return [
+        forest.acceptsUnboundCiphertexts(),
+        loaded.acceptsUnboundCiphertexts(),
+        loaded.mayHoldUnboundCiphertexts(),
+      ];
+    });
+
+    expect(result).toEqual([false, false, true]);
+  });
 });
 
 test.describe("AccessKey", () => {
@@ -25,10 +25,14 @@ declare global {
         Name: typeof import("../../dist/bundler/wnfs_wasm").Name;
         NameAccumulator: typeof import("../../dist/bundler/wnfs_wasm").NameAccumulator;
         AccessKey: typeof import("../../dist/bundler/wnfs_wasm").AccessKey;
+        FileViewToken: typeof import("../../dist/bundler/wnfs_wasm").FileViewToken;
+        IndexedDbBlockStore: typeof import("../../dist/bundler/wnfs_wasm").IndexedDbBlockStore;
         share: typeof import("../../dist/bundler/wnfs_wasm").share;
         findLatestShareCounter: typeof import("../../dist/bundler/wnfs_wasm").findLatestShareCounter;
         receiveShare: typeof import("../../dist/bundler/wnfs_wasm").receiveShare;
         createShareName: typeof import("../../dist/bundler/wnfs_wasm").createShareName;
+        toTransferable: typeof import("../../dist/bundler/wnfs_wasm").toTransferable;
+        fromTransferable: typeof import("../../dist/bundler/wnfs_wasm").fromTransferable;
       };
       setPanicHook: typeof import("../../dist/bundler/wnfs_wasm").setPanicHook;
     }>;
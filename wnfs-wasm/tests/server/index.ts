@@ -24,11 +24,15 @@ const setup = async () => {
     Name,
     NameAccumulator,
     AccessKey,
+    FileViewToken,
+    IndexedDbBlockStore,
     setPanicHook,
     share,
     createShareName,
     receiveShare,
     findLatestShareCounter,
+    toTransferable,
+    fromTransferable,
   } = await import("../../dist/bundler/wnfs_wasm");
 
   const mock = {
@@ -54,10 +58,14 @@ const setup = async () => {
     Name,
     NameAccumulator,
     AccessKey,
+    FileViewToken,
+    IndexedDbBlockStore,
     share,
     createShareName,
     receiveShare,
     findLatestShareCounter,
+    toTransferable,
+    fromTransferable,
   };
 
   return { mock, wnfs, setPanicHook };
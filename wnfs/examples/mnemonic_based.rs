@@ -160,8 +160,8 @@ async fn regain_access_from_mnemonic(
         forest,
     );
 
-    let node = recipient::receive_share(&name, &exchange_keypair, forest, store).await?;
-    let latest_node = node.search_latest(forest, store).await?;
+    let payload = recipient::receive_share(&name, &exchange_keypair, forest, store).await?;
+    let latest_node = payload.node.search_latest(forest, store).await?;
     Ok(latest_node)
 }
 
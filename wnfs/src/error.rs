@@ -1,6 +1,7 @@
 //! WNFS Errors
 
 use semver::Version;
+#[cfg(feature = "private")]
 use skip_ratchet::PreviousErr;
 use thiserror::Error;
 use wnfs_common::NodeType;
@@ -39,12 +40,19 @@ pub enum FsError {
     #[error("Found unexpected node type, expected {0:?}")]
     UnexpectedNodeType(NodeType),
 
+    #[error("Node type {0:?} is reserved for WNFS")]
+    ReservedNodeType(String),
+
     #[error("Found unexpected version: {0:?}")]
     UnexpectedVersion(Version),
 
+    #[cfg(feature = "private")]
     #[error("Cannot compute in-between ratchet {0}")]
     NoIntermediateRatchet(PreviousErr),
 
+    #[error("Cannot compare revisions of unrelated nodes, or revisions too far apart")]
+    UnrelatedRevisions,
+
     #[error("Cannot find shard for file content")]
     FileShardNotFound,
 
@@ -56,6 +64,58 @@ pub enum FsError {
 
     #[error("Cannot find the partition with this name")]
     PartitionNotFound,
+
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("Entry {0:?} appears more than once in the order")]
+    DuplicateOrderEntry(String),
+
+    #[error("File content is stored inline instead of in blocks")]
+    InlineFileContent,
+
+    #[error("Root is not signed by an authorized device: {0:?}")]
+    UnauthorizedRoot(String),
+
+    #[error("Checksum manifest is not signed by an authorized device: {0:?}")]
+    UnauthorizedManifest(String),
+
+    #[error("Invalid compressed file content: {0}")]
+    InvalidCompressedContent(String),
+
+    #[error("File content is an append-only log")]
+    LogFileContent,
+
+    #[error("File content isn't an append-only log")]
+    NotALog,
+
+    #[error("Log record of {0} bytes doesn't fit into a content block")]
+    LogRecordTooLarge(usize),
+
+    #[error("Invalid wnfs:// URI: {0}")]
+    InvalidUri(String),
+
+    #[error("Tenant {0:?} isn't allowed to access this label")]
+    TenantAccessDenied(String),
+}
+
+/// Errors from validating loaded nodes in strict mode.
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("Invalid previous links: {0}")]
+    InvalidPrevious(String),
+
+    #[error("Metadata field {0:?} is not well-typed, expected {1}")]
+    InvalidMetadata(String, &'static str),
+
+    #[error("Invalid entry name {0:?}")]
+    InvalidEntryName(String),
+
+    #[error("Invalid entry order: {0}")]
+    InvalidOrder(String),
+
+    #[error("Mismatch between PrivateNode name {1} and its mountpoint {0}")]
+    NameMismatch(String, String),
 }
 
 /// Data sharing related errors
@@ -66,6 +126,18 @@ pub enum ShareError {
 
     #[error("Access key not found")]
     AccessKeyNotFound,
+
+    #[error("Share signature not found")]
+    SignatureNotFound,
+
+    #[error("Invalid share signature")]
+    InvalidSignature,
+
+    #[error("Expected a share of {expected}, but got a share of {actual}")]
+    UnexpectedShareKind {
+        expected: &'static str,
+        actual: &'static str,
+    },
 }
 
 /// Symmetric encryption errors.
@@ -78,6 +150,16 @@ pub enum CryptError {
     UnableToDecrypt(anyhow::Error),
 }
 
+/// Why a value in a multivalue couldn't be loaded as a private node.
+#[derive(Debug, Error)]
+pub enum CandidateError {
+    #[error("Unable to decrypt candidate: {0}")]
+    Undecryptable(anyhow::Error),
+
+    #[error("Unable to load candidate: {0}")]
+    Unloadable(anyhow::Error),
+}
+
 /// RSA related errors
 #[cfg(test)]
 #[derive(Debug, Error)]
@@ -106,4 +188,13 @@ pub enum VerificationError {
 pub enum AccessKeyError {
     #[error("Snapshot access keys cannot be used to derive private refs")]
     UnsupportedSnapshotPrivateRefDerive,
+
+    #[error("Expected revision access, but got snapshot access")]
+    ExpectedRevisionAccess,
+
+    #[error("Expected snapshot access, but got revision access")]
+    ExpectedSnapshotAccess,
+
+    #[error("Revision {0} isn't part of the snapshot key bundle")]
+    RevisionNotInBundle(u64),
 }
@@ -0,0 +1,549 @@
+//! Scanning private file systems for corruption, and repairing what can be repaired.
+//!
+//! [`check`] walks a private directory tree and collects every problem it finds into an
+//! [`FsckReport`]. Problems that make an entry unusable can be fixed with [`repair`],
+//! which moves the affected entries into a `lost+found` directory at the root, so the
+//! rest of the tree can be used and stored again.
+
+use crate::{
+    error::FsError,
+    private::{
+        forest::traits::PrivateForest, AccessKey, FileContent, PrivateDirectory,
+        PrivateForestContent, PrivateNode,
+    },
+    utils, SearchResult,
+};
+use anyhow::{bail, Result};
+use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use serde::{Deserialize, Serialize};
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore, BlockStoreError,
+};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The name of the directory [`repair`] moves broken entries into.
+pub const LOST_AND_FOUND: &str = "lost+found";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The result of scanning a private file system with [`check`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FsckReport {
+    /// How many nodes could be loaded and were checked.
+    pub checked_nodes: usize,
+    /// All problems found, in the order they were found.
+    pub issues: Vec<FsckIssue>,
+}
+
+/// A problem found by [`check`], together with the path of the affected entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FsckIssue {
+    /// The entry's revision isn't in the private forest.
+    MissingForestEntry { path: Vec<String> },
+    /// The block with the entry's encrypted content is missing from the block store.
+    MissingContentBlock { path: Vec<String>, cid: Cid },
+    /// The block with the entry's encrypted header is missing from the block store.
+    MissingHeaderBlock { path: Vec<String>, cid: Cid },
+    /// The entry's blocks exist, but can't be decrypted or deserialized.
+    CorruptNode { path: Vec<String>, reason: String },
+    /// A block of the file's content is missing from the forest or the block store.
+    MissingFileContent { path: Vec<String>, block_index: u64 },
+    /// The forest has revisions of the entry that the tree doesn't link to, i.e. newer
+    /// revisions or concurrent writes at the linked revision that were never merged.
+    UnreachableRevision { path: Vec<String> },
+    /// The entry's previous links contradict its revision history.
+    InconsistentPrevious { path: Vec<String>, reason: String },
+}
+
+/// A change [`repair`] can make to fix an issue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RepairAction {
+    /// Moves the entry at given path into the [`LOST_AND_FOUND`] directory.
+    MoveToLostAndFound { path: Vec<String> },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FsckReport {
+    /// Whether no issues were found.
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// The actions that [`repair`] can take to fix this report's issues.
+    ///
+    /// Entries that can't be loaded or whose file content is incomplete are moved
+    /// to [`LOST_AND_FOUND`]. Unreachable revisions and inconsistent previous links
+    /// don't keep the tree from being used, so they don't get repair actions.
+    pub fn repair_actions(&self) -> Vec<RepairAction> {
+        self.issues
+            .iter()
+            .filter_map(|issue| match issue {
+                FsckIssue::MissingForestEntry { path }
+                | FsckIssue::MissingContentBlock { path, .. }
+                | FsckIssue::MissingHeaderBlock { path, .. }
+                | FsckIssue::CorruptNode { path, .. }
+                | FsckIssue::MissingFileContent { path, .. }
+                    if !path.is_empty() =>
+                {
+                    Some(RepairAction::MoveToLostAndFound { path: path.clone() })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl FsckIssue {
+    fn from_load_error(path: Vec<String>, content_cid: &Cid, error: anyhow::Error) -> Self {
+        if let Some(BlockStoreError::CIDNotFound(cid)) = error.downcast_ref() {
+            return if cid == content_cid {
+                Self::MissingContentBlock { path, cid: *cid }
+            } else {
+                Self::MissingHeaderBlock { path, cid: *cid }
+            };
+        }
+
+        if let Some(FsError::NotFound) = error.downcast_ref() {
+            return Self::MissingForestEntry { path };
+        }
+
+        Self::CorruptNode {
+            path,
+            reason: error.to_string(),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Loads the private directory behind given access key and checks it, like [`check`].
+///
+/// If the root directory itself can't be loaded, that is reported as an issue at the empty path.
+pub async fn check_access_key(
+    access_key: &AccessKey,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<FsckReport> {
+    let private_ref = access_key.derive_private_ref()?;
+    match PrivateNode::load(access_key, forest, store, None).await {
+        Ok(PrivateNode::Dir(root)) => check(&root, forest, store).await,
+        Ok(PrivateNode::File(_)) => bail!(FsError::NotADirectory),
+        Err(error) => Ok(FsckReport {
+            checked_nodes: 0,
+            issues: vec![FsckIssue::from_load_error(
+                vec![],
+                &private_ref.content_cid,
+                error,
+            )],
+        }),
+    }
+}
+
+/// Checks every node in given private directory tree for broken links, missing
+/// file content, unreachable revisions and inconsistent previous links.
+///
+/// Only fails on errors from the block store other than missing blocks. Everything
+/// else is reported in the returned [`FsckReport`].
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     fsck,
+///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///
+///     root.write(&["notes.md".into()], true, Utc::now(), b"hi".to_vec(), forest, store, rng).await?;
+///     let access_key = root.as_node().store(forest, store, rng).await?;
+///
+///     let report = fsck::check_access_key(&access_key, forest, store).await?;
+///
+///     assert!(report.is_clean());
+///     assert_eq!(report.checked_nodes, 2);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn check(
+    root: &Arc<PrivateDirectory>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<FsckReport> {
+    let mut report = FsckReport::default();
+    check_node(&root.as_node(), &mut vec![], &mut report, forest, store).await?;
+    Ok(report)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn check_node(
+    node: &PrivateNode,
+    path: &mut Vec<String>,
+    report: &mut FsckReport,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<()> {
+    report.checked_nodes += 1;
+
+    if node.get_persisted_as().get().is_some() && !node.is_known_latest(forest, store).await? {
+        report
+            .issues
+            .push(FsckIssue::UnreachableRevision { path: path.clone() });
+    }
+
+    if let Some(reason) = check_previous(node) {
+        report.issues.push(FsckIssue::InconsistentPrevious {
+            path: path.clone(),
+            reason,
+        });
+    }
+
+    match node {
+        PrivateNode::File(file) => {
+            let missing_block = match &file.content.content {
+                FileContent::External(content) => {
+                    let base_name = &Name::new(content.base_name.clone(), []);
+                    let names = PrivateForestContent::generate_shard_labels(
+                        &content.key,
+                        0,
+                        content.block_count,
+                        base_name,
+                    );
+                    find_missing_block(names, forest, store).await?
+                }
+                FileContent::Log(log) => {
+                    find_missing_block(log.block_names(), forest, store).await?
+                }
+                FileContent::Inline { .. } => None,
+            };
+
+            if let Some(block_index) = missing_block {
+                report.issues.push(FsckIssue::MissingFileContent {
+                    path: path.clone(),
+                    block_index,
+                });
+            }
+        }
+        PrivateNode::Dir(dir) => {
+            for (name, link) in dir.content.entries.iter() {
+                path.push(name.clone());
+                match link
+                    .resolve_node(forest, store, Some(dir.child_parent_name(name)))
+                    .await
+                {
+                    Ok(child) => check_node(child, path, report, forest, store).await?,
+                    Err(error) => {
+                        let Some(content_cid) = link.get_content_cid() else {
+                            return Err(error); // Unstored nodes are in memory already
+                        };
+
+                        if let Some(BlockStoreError::Custom(_)) = error.downcast_ref() {
+                            return Err(error);
+                        }
+
+                        report.issues.push(FsckIssue::from_load_error(
+                            path.clone(),
+                            content_cid,
+                            error,
+                        ));
+                    }
+                }
+                path.pop();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_previous(node: &PrivateNode) -> Option<String> {
+    let previous = node.get_previous();
+    if previous
+        .iter()
+        .any(|(revisions_back, _)| *revisions_back == 0)
+    {
+        return Some("previous link doesn't point to an earlier revision".into());
+    }
+
+    // Nodes stored before revisions were counted are all at revision zero
+    let revision = node.revision();
+    if revision == 0 {
+        return None;
+    }
+
+    if previous.is_empty() {
+        return Some(format!("revision {revision} has no previous links"));
+    }
+
+    if previous
+        .iter()
+        .any(|(revisions_back, _)| *revisions_back as u64 > revision)
+    {
+        return Some(format!(
+            "previous link points further back than revision {revision} allows"
+        ));
+    }
+
+    None
+}
+
+async fn find_missing_block(
+    names: impl Iterator<Item = Name>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Option<u64>> {
+    for (block_index, name) in (0..).zip(names) {
+        let Some(cids) = forest.get_encrypted(&name, store).await? else {
+            return Ok(Some(block_index));
+        };
+
+        for cid in cids.iter() {
+            if !store.has_block(cid).await? {
+                return Ok(Some(block_index));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Applies repair actions, e.g. from [`FsckReport::repair_actions`], to given root directory.
+///
+/// Broken entries are moved into the [`LOST_AND_FOUND`] directory, named after the path they
+/// were found at with its segments joined by `:`. The root directory needs to be stored again
+/// afterwards for the repair to take effect.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     fsck,
+///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     root.as_node().store(forest, store, rng).await?;
+///
+///     let report = fsck::check(root, forest, store).await?;
+///     fsck::repair(root, &report.repair_actions(), Utc::now(), forest, store, rng).await?;
+///
+///     assert!(report.is_clean());
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn repair(
+    root: &mut Arc<PrivateDirectory>,
+    actions: &[RepairAction],
+    time: DateTime<Utc>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut (impl CryptoRngCore + CondSend),
+) -> Result<()> {
+    for action in actions {
+        let RepairAction::MoveToLostAndFound { path } = action;
+        let (parent_path, name) = utils::split_last(path)?;
+
+        let parent = match root
+            .get_leaf_dir_mut(parent_path, false, forest, store)
+            .await?
+        {
+            SearchResult::Found(parent) => parent,
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            _ => bail!(FsError::NotFound),
+        };
+
+        let Some(link) = parent.content.entries.remove(name) else {
+            bail!(FsError::NotFound);
+        };
+        parent.content.pending_ancestry.remove(name);
+
+        let lost_and_found = root
+            .get_or_create_leaf_dir_mut(&[LOST_AND_FOUND.into()], time, false, forest, store, rng)
+            .await?;
+
+        let base_name = path.join(":");
+        let mut found_name = base_name.clone();
+        let mut suffix = 1;
+        while lost_and_found.content.entries.contains_key(&found_name) {
+            found_name = format!("{base_name}.{suffix}");
+            suffix += 1;
+        }
+
+        lost_and_found.content.entries.insert(found_name, link);
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::{utils::reachable_blocks, MemoryBlockStore, Storable};
+
+    #[async_std::test]
+    async fn broken_entries_are_reported_and_moved_to_lost_and_found() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root.write(
+            &["docs".into(), "ok.txt".into()],
+            true,
+            Utc::now(),
+            b"fine".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        root.write(
+            &["docs".into(), "broken.txt".into()],
+            true,
+            Utc::now(),
+            b"soon gone".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let access_key = root.as_node().store(forest, store, rng).await?;
+
+        // Lose the content block of `broken.txt`, by copying over everything else
+        let broken_cid = *root
+            .get_node(&["docs".into(), "broken.txt".into()], false, forest, store)
+            .await?
+            .unwrap()
+            .get_persisted_as()
+            .get()
+            .unwrap();
+        let damaged_store = &MemoryBlockStore::new();
+        let forest_cid = forest.store(store).await?;
+        for cid in reachable_blocks(&forest_cid, store).await? {
+            if cid != broken_cid {
+                let bytes = store.get_block(&cid).await?;
+                damaged_store.put_block_keyed(cid, bytes).await?;
+            }
+        }
+        let forest = &Arc::new(HamtForest::load(&forest_cid, damaged_store).await?);
+
+        let report = check_access_key(&access_key, forest, damaged_store).await?;
+        assert_eq!(
+            report.issues,
+            vec![FsckIssue::MissingContentBlock {
+                path: vec!["docs".into(), "broken.txt".into()],
+                cid: broken_cid,
+            }]
+        );
+
+        let root = &mut PrivateNode::load(&access_key, forest, damaged_store, None)
+            .await?
+            .as_dir()?;
+        let actions = report.repair_actions();
+        repair(root, &actions, Utc::now(), forest, damaged_store, rng).await?;
+
+        let after = check(root, forest, damaged_store).await?;
+        assert!(matches!(
+            &after.issues[..],
+            [FsckIssue::MissingContentBlock { path, .. }]
+                if path == &[LOST_AND_FOUND.to_string(), "docs:broken.txt".into()]
+        ));
+        assert_eq!(
+            root.ls(&["docs".into()], false, forest, damaged_store)
+                .await?
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn unmerged_revisions_are_reported() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root.mkdir(&["a".into()], true, Utc::now(), forest, store, rng)
+            .await?;
+        root.as_node().store(forest, store, rng).await?;
+        let stale = Arc::clone(root);
+
+        root.mkdir(
+            &["a".into(), "b".into()],
+            true,
+            Utc::now(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        root.as_node().store(forest, store, rng).await?;
+
+        let report = check(&stale, forest, store).await?;
+
+        assert_eq!(
+            report.issues,
+            vec![
+                FsckIssue::UnreachableRevision { path: vec![] },
+                FsckIssue::UnreachableRevision {
+                    path: vec!["a".into()]
+                },
+            ]
+        );
+        assert!(report.repair_actions().is_empty());
+        assert!(check(root, forest, store).await?.is_clean());
+
+        Ok(())
+    }
+}
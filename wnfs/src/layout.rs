@@ -0,0 +1,340 @@
+//! Declaring the directory layout an application expects, and creating whatever is missing.
+//!
+//! A [`Layout`] lists paths that must exist, whether they're files or directories, and the
+//! content files are initialized with. [`ensure_private_layout`] and [`ensure_public_layout`]
+//! check a root directory against it and create all missing entries at once.
+
+use crate::error::FsError;
+#[cfg(feature = "private")]
+use crate::private::{forest::traits::PrivateForest, PrivateDirectory, PrivateNode};
+#[cfg(feature = "public")]
+use crate::public::{PublicDirectory, PublicNode};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+#[cfg(feature = "private")]
+use rand_core::CryptoRngCore;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "private")]
+use wnfs_common::utils::CondSend;
+use wnfs_common::{utils::Arc, BlockStore};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A list of entries that must exist in a root directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+    pub entries: Vec<LayoutEntry>,
+}
+
+/// An entry of a [`Layout`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutEntry {
+    pub path: Vec<String>,
+    #[serde(flatten)]
+    pub kind: LayoutKind,
+}
+
+/// What kind of node a [`LayoutEntry`] must be.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LayoutKind {
+    Dir,
+    /// A file, which is created with `init` as content if it's missing.
+    File {
+        init: Vec<u8>,
+    },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Layout {
+    /// Creates an empty layout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a directory that must exist at given path.
+    pub fn dir(mut self, path_segments: &[String]) -> Self {
+        self.entries.push(LayoutEntry {
+            path: path_segments.to_vec(),
+            kind: LayoutKind::Dir,
+        });
+        self
+    }
+
+    /// Adds a file that must exist at given path, and that is created with `init` as
+    /// content if it's missing.
+    pub fn file(mut self, path_segments: &[String], init: impl Into<Vec<u8>>) -> Self {
+        self.entries.push(LayoutEntry {
+            path: path_segments.to_vec(),
+            kind: LayoutKind::File { init: init.into() },
+        });
+        self
+    }
+}
+
+impl LayoutEntry {
+    fn check_kind(&self, is_dir: bool) -> Result<()> {
+        match (&self.kind, is_dir) {
+            (LayoutKind::Dir, false) => bail!(FsError::NotADirectory),
+            (LayoutKind::File { .. }, true) => bail!(FsError::NotAFile),
+            _ => Ok(()),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Makes sure all entries of `layout` exist in the private directory `root`.
+///
+/// Missing entries are created with `time`, files with their initial content. Existing
+/// entries are left untouched. If an existing entry is of the wrong kind, this fails and
+/// `root` stays as it was, so either the whole layout is applied or nothing is.
+///
+/// Returns the paths of the created entries, so `root` only needs to be stored if that
+/// isn't empty.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     layout::{ensure_private_layout, Layout},
+///     private::{forest::{hamt::HamtForest, traits::PrivateForest}, PrivateDirectory},
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///
+///     let layout = Layout::new()
+///         .dir(&["Apps".into(), "notes".into()])
+///         .file(&["Apps".into(), "notes".into(), "settings.json".into()], "{}");
+///
+///     let created =
+///         ensure_private_layout(root, &layout, true, Utc::now(), forest, store, rng).await?;
+///     assert_eq!(created.len(), 2);
+///
+///     // Running it again on the same root has nothing left to create
+///     let created =
+///         ensure_private_layout(root, &layout, true, Utc::now(), forest, store, rng).await?;
+///     assert!(created.is_empty());
+///
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "private")]
+pub async fn ensure_private_layout(
+    root: &mut Arc<PrivateDirectory>,
+    layout: &Layout,
+    search_latest: bool,
+    time: DateTime<Utc>,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut (impl CryptoRngCore + CondSend),
+) -> Result<Vec<Vec<String>>> {
+    let mut missing = Vec::new();
+    for entry in layout.entries.iter() {
+        if entry.path.is_empty() {
+            bail!(FsError::InvalidPath);
+        }
+
+        match root
+            .get_node(&entry.path, search_latest, forest, store)
+            .await?
+        {
+            Some(node) => entry.check_kind(matches!(node, PrivateNode::Dir(_)))?,
+            None => missing.push(entry),
+        }
+    }
+
+    let mut working_root = Arc::clone(root);
+    for entry in missing.iter() {
+        match &entry.kind {
+            LayoutKind::Dir => {
+                working_root
+                    .mkdir(&entry.path, search_latest, time, forest, store, rng)
+                    .await?
+            }
+            LayoutKind::File { init } => {
+                working_root
+                    .write(
+                        &entry.path,
+                        search_latest,
+                        time,
+                        init.clone(),
+                        forest,
+                        store,
+                        rng,
+                    )
+                    .await?
+            }
+        }
+    }
+
+    *root = working_root;
+    Ok(missing
+        .into_iter()
+        .map(|entry| entry.path.clone())
+        .collect())
+}
+
+/// Makes sure all entries of `layout` exist in the public directory `root`.
+///
+/// Works like [`ensure_private_layout`]: either the whole layout is applied or nothing is,
+/// and the paths of the created entries are returned.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     layout::{ensure_public_layout, Layout},
+///     public::PublicDirectory,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let root = &mut PublicDirectory::new_rc(Utc::now());
+///     let layout = Layout::new().file(&["index.html".into()], "<h1>Hello</h1>");
+///
+///     ensure_public_layout(root, &layout, Utc::now(), store).await?;
+///
+///     assert_eq!(root.read(&["index.html".into()], store).await?, b"<h1>Hello</h1>");
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "public")]
+pub async fn ensure_public_layout(
+    root: &mut Arc<PublicDirectory>,
+    layout: &Layout,
+    time: DateTime<Utc>,
+    store: &impl BlockStore,
+) -> Result<Vec<Vec<String>>> {
+    let mut missing = Vec::new();
+    for entry in layout.entries.iter() {
+        if entry.path.is_empty() {
+            bail!(FsError::InvalidPath);
+        }
+
+        match root.get_node(&entry.path, store).await? {
+            Some(node) => entry.check_kind(matches!(node, PublicNode::Dir(_)))?,
+            None => missing.push(entry),
+        }
+    }
+
+    let mut working_root = Arc::clone(root);
+    for entry in missing.iter() {
+        match &entry.kind {
+            LayoutKind::Dir => working_root.mkdir(&entry.path, time, store).await?,
+            LayoutKind::File { init } => {
+                working_root
+                    .write(&entry.path, init.clone(), time, store)
+                    .await?
+            }
+        }
+    }
+
+    *root = working_root;
+    Ok(missing
+        .into_iter()
+        .map(|entry| entry.path.clone())
+        .collect())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+
+    #[cfg(feature = "private")]
+    #[async_std::test]
+    async fn existing_entries_are_kept_and_conflicts_change_nothing() -> TestResult {
+        use crate::private::forest::hamt::HamtForest;
+        use rand_chacha::ChaCha12Rng;
+        use rand_core::SeedableRng;
+        use wnfs_common::MemoryBlockStore;
+
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let time = Utc::now();
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+
+        let settings = &["Apps".into(), "settings.json".into()];
+        root.write(
+            settings,
+            true,
+            time,
+            b"{\"theme\":\"dark\"}".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        let layout = Layout::new()
+            .file(settings, "{}")
+            .dir(&["Apps".into(), "cache".into()])
+            .file(&["Apps".into(), "cache".into(), "index".into()], vec![]);
+        let created = ensure_private_layout(root, &layout, true, time, forest, store, rng).await?;
+
+        assert_eq!(
+            created,
+            vec![
+                vec!["Apps".to_string(), "cache".to_string()],
+                vec!["Apps".to_string(), "cache".to_string(), "index".to_string()],
+            ]
+        );
+        assert_eq!(
+            root.read(settings, true, forest, store).await?,
+            b"{\"theme\":\"dark\"}"
+        );
+
+        let conflicting = Layout::new().dir(&["Documents".into()]).dir(settings);
+        let before = Arc::clone(root);
+        let result =
+            ensure_private_layout(root, &conflicting, true, time, forest, store, rng).await;
+
+        assert!(result.is_err());
+        assert!(Arc::ptr_eq(root, &before));
+
+        Ok(())
+    }
+
+    #[test]
+    fn layouts_can_be_declared_as_json() -> TestResult {
+        let layout: Layout = serde_json::from_str(
+            r#"{"entries":[{"path":["Apps"],"kind":"dir"},{"path":["Apps","a.txt"],"kind":"file","init":[104,105]}]}"#,
+        )?;
+
+        assert_eq!(
+            layout,
+            Layout::new()
+                .dir(&["Apps".into()])
+                .file(&["Apps".into(), "a.txt".into()], "hi")
+        );
+
+        Ok(())
+    }
+}
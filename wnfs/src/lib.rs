@@ -121,14 +121,53 @@
 //! [wnfs-graph-demo]: https://calm-thin-barista.fission.app
 //! [wnfs-spec]: https://github.com/wnfs-wg/spec
 
+//! # Features
+//!
+//! The file system is split into features, so users of only one partition don't compile
+//! the dependencies of the other. All of them are enabled by default.
+//!
+//! - `public`: the public file system in [`public`].
+//! - `private`: the private file system in [`private`], with all of its cryptography, like
+//!   AES key wrapping, XChaCha20-Poly1305 encryption and name accumulators.
+//! - `sharing`: sharing private nodes via exchange keys in the public partition, in
+//!   `private::share` and `private::devices`.
+//! - `proofs`: proving and verifying writes to private forests, in `private::forest::proofs`.
+//!
+//! Modules that work with both partitions, like [`root_tree`] or [`transfer`], need both
+//! `public` and `private`.
+
 #![deny(unsafe_code)]
 
 pub mod error;
+#[cfg(feature = "private")]
+pub mod fsck;
+#[cfg(any(feature = "public", feature = "private"))]
+pub mod layout;
+#[cfg(feature = "private")]
+pub mod maintenance;
+pub mod options;
+#[cfg(all(feature = "public", feature = "private"))]
+#[doc(hidden)] // Builds on the "prerelease" root tree API
+pub mod overlay;
+#[cfg(feature = "private")]
 pub mod private;
+#[cfg(feature = "public")]
 pub mod public;
+#[cfg(all(feature = "public", feature = "private"))]
 #[doc(hidden)] // The API is in "prerelease" for now
 pub mod root_tree;
+pub mod signed_root;
+#[cfg(all(any(test, feature = "test_utils"), feature = "private"))]
+pub mod test_utils;
 pub mod traits;
+#[cfg(all(feature = "public", feature = "private"))]
+pub mod transfer;
+#[cfg(all(feature = "public", feature = "private"))]
+#[doc(hidden)] // Builds on the "prerelease" root tree API
+pub mod trash;
+#[cfg(all(feature = "public", feature = "private"))]
+pub mod uri;
+#[cfg(any(feature = "public", feature = "private"))]
 mod utils;
 
 pub mod rand_core {
@@ -139,10 +178,12 @@ pub mod common {
     //! Re-exports of wnfs-common lib.
     pub use wnfs_common::*;
 }
+#[cfg(any(feature = "public", feature = "private"))]
 pub mod hamt {
     //! Re-exports of wnfs-hamt lib.
     pub use wnfs_hamt::*;
 }
+#[cfg(feature = "private")]
 pub mod nameaccumulator {
     //! Re-exports of wnfs-nameaccumulator lib.
     pub use wnfs_nameaccumulator::*;
@@ -153,13 +194,44 @@ pub mod nameaccumulator {
 //--------------------------------------------------------------------------------------------------
 
 /// The version of the WNFS data format that this library outputs
-pub const WNFS_VERSION: semver::Version = semver::Version::new(1, 0, 0);
+///
+/// Version 1.1 changed the format in these ways:
+/// - Private ciphertexts are bound to the labels they're stored under.
+/// - Public metadata values larger than [`METADATA_OVERFLOW_THRESHOLD`] are stored in
+///   blocks of their own, under a node's `metadataOverflow` field.
+/// - Public directories with more than [`DIRECTORY_SHARD_THRESHOLD`] entries store them
+///   in a HAMT, under the directory's `userlandShards` field, and their number under its
+///   `userlandCount` field.
+///
+/// Public nodes from version 1.0 are still read as-is, since they don't use any of these
+/// fields. Private nodes from version 1.0 are only read from forests that accept unbound
+/// ciphertexts, see `PrivateForest::accepts_unbound_ciphertexts`. To migrate them, turn
+/// that on with `HamtForest::set_accept_unbound_ciphertexts`, re-encrypt their history
+/// with `PrivateNode::reencrypt_history` and turn it off again.
+pub const WNFS_VERSION: semver::Version = semver::Version::new(1, 1, 0);
+
+/// Metadata values whose dag-cbor encoding is larger than this many bytes are stored in
+/// blocks of their own, so they don't bloat the content block of the node they belong to.
+///
+/// They're loaded back together with the node, so this is invisible when reading metadata.
+/// Public and private files and directories use the same threshold, so moving nodes between
+/// the public and the private partition keeps their metadata as it is.
+pub const METADATA_OVERFLOW_THRESHOLD: usize = 1024;
+
+/// Public directories with more entries than this store them in a HAMT of their own, so
+/// looking up an entry or listing a page of entries only loads the blocks on the way.
+///
+/// The blocks of the HAMT are only loaded as far as entries are accessed, and storing a
+/// directory only stores the parts of the HAMT that changed. Apart from that, this is
+/// invisible when working with directories as usual.
+pub const DIRECTORY_SHARD_THRESHOLD: usize = 1024;
 
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
 
 /// The result of an basic get operation.
+#[cfg(any(feature = "public", feature = "private"))]
 pub(crate) enum SearchResult<T> {
     Missing(T, usize),
     NotADir(T, #[allow(unused)] usize),
@@ -175,6 +247,12 @@ pub fn is_readable_wnfs_version(version: &semver::Version) -> bool {
     get_wnfs_version_req().matches(version)
 }
 
+/// Whether given WNFS data format version is from before version 1.1, and can only be
+/// read in the ways described in [`WNFS_VERSION`].
+pub fn is_legacy_wnfs_version(version: &semver::Version) -> bool {
+    version.major == WNFS_VERSION.major && version.minor == 0
+}
+
 /// The WNFS data format version requirement for this version of the library
 pub fn get_wnfs_version_req() -> semver::VersionReq {
     use semver::*;
@@ -0,0 +1,437 @@
+//! Incremental background maintenance, for apps that only get to run in short bursts.
+//!
+//! A [`Maintenance`] runner works through a queue of [`MaintenanceTask`]s one small step
+//! at a time. It can be paused after any step, serialized, e.g. before a mobile app gets
+//! suspended, and resumed later without losing the work done so far.
+
+use crate::private::{forest::hamt::HamtForest, AccessKey, NodeCache, PrivateNode};
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use libipld_core::cid::Cid;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use wnfs_common::{impl_storable_from_serde, utils, BlockStore, Storable};
+use wnfs_hamt::{MergeCheckpoint, MergeProgress};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many forest labels are merged in one step of [`MaintenanceTask::CompactForests`].
+pub const COMPACTION_STEP_CHANGES: usize = 64;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A queue of maintenance tasks that is worked through step by step, see the
+/// [module documentation](self).
+///
+/// Tasks run in the order they were queued. Their results are kept in the runner until
+/// they're taken out with [`take_reachable_blocks`](Self::take_reachable_blocks) or
+/// [`take_compacted_forest`](Self::take_compacted_forest).
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::{Duration, Utc};
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::{MemoryBlockStore, Storable},
+///     maintenance::Maintenance,
+///     private::{
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///         NodeCache, PrivateDirectory,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     root_dir.write(&["notes.txt".into()], true, Utc::now(), vec![1], forest, store, rng).await?;
+///     let access_key = root_dir.as_node().store(forest, store, rng).await?;
+///     let forest_cid = forest.store(store).await?;
+///
+///     let mut maintenance = Maintenance::new();
+///     maintenance.warm_cache(forest_cid, access_key);
+///     maintenance.scan_blocks([forest_cid]);
+///
+///     // Each time the app gets some background time, it does a bit more work
+///     let cache = &NodeCache::new(100);
+///     while !maintenance.is_done() {
+///         maintenance.run_for(Duration::milliseconds(50), cache, store).await?;
+///         let saved = maintenance.store(store).await?;
+///         maintenance = Maintenance::load(&saved, store).await?;
+///     }
+///
+///     assert!(maintenance.take_reachable_blocks().contains(&forest_cid));
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Maintenance {
+    tasks: VecDeque<MaintenanceTask>,
+    reachable: BTreeSet<Cid>,
+    compacted: Option<Cid>,
+    steps: u64,
+}
+
+/// A task of a [`Maintenance`] runner, together with its progress.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MaintenanceTask {
+    /// Loads the private directory tree behind `access_key` into a [`NodeCache`],
+    /// one directory per step.
+    WarmCache {
+        forest: Cid,
+        access_key: AccessKey,
+        /// Paths of the directories that are still to be loaded.
+        pending: Vec<Vec<String>>,
+    },
+    /// Marks all blocks reachable from a set of roots, one block per step, e.g. to find
+    /// out which blocks a garbage collector may remove.
+    ScanBlocks {
+        /// Blocks that were found but not visited yet.
+        frontier: VecDeque<Cid>,
+    },
+    /// Merges a number of private forests, e.g. replicas from different devices, into one,
+    /// [`COMPACTION_STEP_CHANGES`] labels per step.
+    CompactForests {
+        /// The forests that are still to be merged.
+        forests: VecDeque<Cid>,
+        /// The forest merged so far.
+        merged: Option<Cid>,
+        /// Where the merge of `merged` with the next forest paused.
+        checkpoint: Option<MergeCheckpoint>,
+    },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Maintenance {
+    /// Creates a runner without any tasks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues loading the private directory tree behind `access_key`, stored in the forest
+    /// with CID `forest`, into the cache passed to the runner.
+    pub fn warm_cache(&mut self, forest: Cid, access_key: AccessKey) {
+        self.tasks.push_back(MaintenanceTask::WarmCache {
+            forest,
+            access_key,
+            pending: vec![Vec::new()],
+        });
+    }
+
+    /// Queues marking all blocks reachable from `roots`. The result is added to
+    /// [`take_reachable_blocks`](Self::take_reachable_blocks).
+    ///
+    /// Private forests link to the header and content blocks of all private nodes in
+    /// them, so scanning from the CID of a forest finds all of their blocks, even though
+    /// they're encrypted.
+    pub fn scan_blocks(&mut self, roots: impl IntoIterator<Item = Cid>) {
+        self.tasks.push_back(MaintenanceTask::ScanBlocks {
+            frontier: roots.into_iter().collect(),
+        });
+    }
+
+    /// Queues merging the forests with given CIDs into one. The CID of the merged forest
+    /// is available from [`take_compacted_forest`](Self::take_compacted_forest).
+    pub fn compact_forests(&mut self, forests: impl IntoIterator<Item = Cid>) {
+        self.tasks.push_back(MaintenanceTask::CompactForests {
+            forests: forests.into_iter().collect(),
+            merged: None,
+            checkpoint: None,
+        });
+    }
+
+    /// Returns the queued tasks, starting with the one in progress.
+    pub fn tasks(&self) -> impl Iterator<Item = &MaintenanceTask> {
+        self.tasks.iter()
+    }
+
+    /// Whether all queued tasks are done.
+    pub fn is_done(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Returns the number of steps done so far, across all tasks.
+    pub fn steps_done(&self) -> u64 {
+        self.steps
+    }
+
+    /// Takes out the blocks marked by block scans so far.
+    pub fn take_reachable_blocks(&mut self) -> BTreeSet<Cid> {
+        std::mem::take(&mut self.reachable)
+    }
+
+    /// Takes out the CID of the forest the latest finished compaction merged into.
+    pub fn take_compacted_forest(&mut self) -> Option<Cid> {
+        self.compacted.take()
+    }
+
+    /// Runs steps until `duration` passed or all tasks are done, and returns the number
+    /// of steps done.
+    ///
+    /// Steps aren't interrupted, so this can take a bit longer than `duration`.
+    pub async fn run_for(
+        &mut self,
+        duration: Duration,
+        cache: &NodeCache,
+        store: &impl BlockStore,
+    ) -> Result<usize> {
+        let deadline = Utc::now() + duration;
+        let mut steps = 0;
+        while Utc::now() < deadline && self.step(cache, store).await? {
+            steps += 1;
+        }
+
+        Ok(steps)
+    }
+
+    /// Runs up to `n` steps, and returns the number of steps done, which is less than `n`
+    /// only if all tasks are done.
+    pub async fn run_n_steps(
+        &mut self,
+        n: usize,
+        cache: &NodeCache,
+        store: &impl BlockStore,
+    ) -> Result<usize> {
+        let mut steps = 0;
+        while steps < n && self.step(cache, store).await? {
+            steps += 1;
+        }
+
+        Ok(steps)
+    }
+
+    /// Does the next step of the first queued task, and returns whether there was any
+    /// work left to do.
+    ///
+    /// If a step fails, the runner stays at the state before that step, so it can be
+    /// retried.
+    pub async fn step(&mut self, cache: &NodeCache, store: &impl BlockStore) -> Result<bool> {
+        let Some(task) = self.tasks.front_mut() else {
+            return Ok(false);
+        };
+
+        let task_done = match task {
+            MaintenanceTask::WarmCache {
+                forest,
+                access_key,
+                pending,
+            } => Self::warm_cache_step(forest, access_key, pending, cache, store).await?,
+            MaintenanceTask::ScanBlocks { frontier } => {
+                Self::scan_blocks_step(frontier, &mut self.reachable, store).await?
+            }
+            MaintenanceTask::CompactForests {
+                forests,
+                merged,
+                checkpoint,
+            } => {
+                let done = Self::compaction_step(forests, merged, checkpoint, store).await?;
+                if done {
+                    self.compacted = *merged;
+                }
+                done
+            }
+        };
+
+        if task_done {
+            self.tasks.pop_front();
+        }
+
+        self.steps += 1;
+        Ok(true)
+    }
+
+    /// Loads the children of the next pending directory into the cache.
+    async fn warm_cache_step(
+        forest: &Cid,
+        access_key: &AccessKey,
+        pending: &mut Vec<Vec<String>>,
+        cache: &NodeCache,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        let Some(path) = pending.last() else {
+            return Ok(true);
+        };
+
+        let forest = &HamtForest::load(forest, store).await?;
+        let root_dir = PrivateNode::load(access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let dir = match root_dir
+            .get_node_bounded(path, false, cache, forest, store)
+            .await?
+        {
+            _ if path.is_empty() => Some(root_dir),
+            Some(PrivateNode::Dir(dir)) => Some(dir),
+            // Directories may be gone since they were queued
+            _ => None,
+        };
+
+        let mut subdirs = Vec::new();
+        if let Some(dir) = dir {
+            for name in dir.get_entries() {
+                let child = dir
+                    .lookup_node_bounded(name, false, cache, forest, store)
+                    .await?;
+                if let Some(PrivateNode::Dir(_)) = child {
+                    let mut subdir = path.clone();
+                    subdir.push(name.clone());
+                    subdirs.push(subdir);
+                }
+            }
+        }
+
+        pending.pop();
+        pending.extend(subdirs);
+        Ok(pending.is_empty())
+    }
+
+    /// Visits the next block of the frontier that wasn't visited yet.
+    async fn scan_blocks_step(
+        frontier: &mut VecDeque<Cid>,
+        reachable: &mut BTreeSet<Cid>,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        while let Some(cid) = frontier.front().copied() {
+            if reachable.contains(&cid) {
+                frontier.pop_front();
+                continue;
+            }
+
+            let links = utils::block_links(&cid, store).await?;
+            frontier.pop_front();
+            frontier.extend(links);
+            reachable.insert(cid);
+            break;
+        }
+
+        Ok(frontier.is_empty())
+    }
+
+    /// Merges the next batch of labels of the next forest into the merged forest.
+    async fn compaction_step(
+        forests: &mut VecDeque<Cid>,
+        merged: &mut Option<Cid>,
+        checkpoint: &mut Option<MergeCheckpoint>,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        let Some(next) = forests.front() else {
+            return Ok(true);
+        };
+
+        let Some(merged_cid) = merged else {
+            *merged = forests.pop_front();
+            return Ok(forests.is_empty());
+        };
+
+        let merged_forest = HamtForest::load(merged_cid, store).await?;
+        let next_forest = HamtForest::load(next, store).await?;
+        match merged_forest
+            .merge_resumable(
+                &next_forest,
+                checkpoint.clone(),
+                COMPACTION_STEP_CHANGES,
+                store,
+            )
+            .await?
+        {
+            MergeProgress::Done(forest) => {
+                *merged = Some(forest.store(store).await?);
+                *checkpoint = None;
+                forests.pop_front();
+            }
+            MergeProgress::Paused(paused) => *checkpoint = Some(paused),
+        }
+
+        Ok(forests.is_empty())
+    }
+}
+
+impl_storable_from_serde! { Maintenance }
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::traits::PrivateForest, PrivateDirectory};
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::{
+        utils::{reachable_blocks, Arc},
+        MemoryBlockStore,
+    };
+
+    #[async_std::test]
+    async fn tasks_resume_after_being_persisted() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let base = HamtForest::new_rsa_2048(rng);
+
+        let forest_one = &mut Arc::new(base.clone());
+        let root_dir = &mut PrivateDirectory::new_rc(&forest_one.empty_name(), Utc::now(), rng);
+        for i in 0..5 {
+            let path = &["docs".into(), format!("{i}"), "file.txt".into()];
+            root_dir
+                .write(path, true, Utc::now(), vec![], forest_one, store, rng)
+                .await?;
+        }
+        let access_key = root_dir.as_node().store(forest_one, store, rng).await?;
+        let forest_one_cid = forest_one.store(store).await?;
+
+        let forest_two = &mut Arc::new(base);
+        let other_dir = &mut PrivateDirectory::new_rc(&forest_two.empty_name(), Utc::now(), rng);
+        other_dir.as_node().store(forest_two, store, rng).await?;
+        let forest_two_cid = forest_two.store(store).await?;
+
+        let mut maintenance = Maintenance::new();
+        maintenance.warm_cache(forest_one_cid, access_key);
+        maintenance.scan_blocks([forest_one_cid]);
+        maintenance.compact_forests([forest_one_cid, forest_two_cid]);
+
+        // One step per directory: the root, "docs" and its 5 subdirectories
+        let cache = &NodeCache::new(100);
+        assert_eq!(maintenance.run_n_steps(7, cache, store).await?, 7);
+        assert!(matches!(
+            maintenance.tasks().next(),
+            Some(MaintenanceTask::ScanBlocks { .. })
+        ));
+
+        while !maintenance.is_done() {
+            let steps = maintenance.run_n_steps(3, cache, store).await?;
+            assert!(steps == 3 || maintenance.is_done());
+            let cid = maintenance.store(store).await?;
+            maintenance = Maintenance::load(&cid, store).await?;
+        }
+
+        assert_eq!(
+            maintenance.take_reachable_blocks(),
+            reachable_blocks(&forest_one_cid, store).await?
+        );
+
+        let compacted = maintenance.take_compacted_forest().unwrap();
+        let compacted = HamtForest::load(&compacted, store).await?;
+        let merged = forest_one.merge(forest_two, store).await?;
+        assert_eq!(compacted.diff(&merged, store).await?, vec![]);
+
+        assert_eq!(maintenance.run_n_steps(3, cache, store).await?, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+//! Options for file system operations.
+
+#[cfg(feature = "private")]
+use crate::private::{Compression, ConvergenceSecret, Padding};
+#[cfg(any(feature = "public", feature = "private"))]
+use wnfs_common::{utils::sniff_content_type, Metadata};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Options for writing file content, e.g. with `PublicDirectory::write_with_options`
+/// or `PrivateDirectory::write_with_options`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Whether to detect the content's MIME type from its magic bytes and store it in
+    /// the file's metadata, where it can be read with [`Metadata::get_content_type`].
+    ///
+    /// If no type is detected, any previously stored type is removed, since it
+    /// described the file's old content.
+    pub sniff_content_type: bool,
+
+    /// The algorithm to compress private file content with before it's encrypted.
+    ///
+    /// Public file content is stored as-is.
+    #[cfg(feature = "private")]
+    pub compression: Option<Compression>,
+
+    /// The scheme to pad private file content with before it's encrypted, so the sizes
+    /// of content blocks don't reveal the exact size of the file.
+    ///
+    /// Padding is applied after compression, to every compressed block by itself.
+    /// Uncompressed content is padded as a whole. Deployments that need to hide file
+    /// sizes should set this for every write.
+    #[cfg(feature = "private")]
+    pub padding: Option<Padding>,
+
+    /// Whether to derive the nonces private file content is encrypted with from its
+    /// key, its label and the content itself, instead of drawing them randomly.
+    ///
+    /// Content keys are still random, so writes of the same content stay unlinkable.
+    /// But encrypting content with the same keys, e.g. drawn from a seeded random number
+    /// generator, results in the same ciphertexts, which makes forests reproducible for
+    /// tests or verifiable migrations. See
+    /// [`SnapshotKey::encrypt_with_aad_deterministic`][crate::private::SnapshotKey::encrypt_with_aad_deterministic]
+    /// for the construction.
+    ///
+    /// This only applies to the content written with these options. To encrypt nodes and
+    /// everything else deterministically as well, turn it on for the whole forest, see
+    /// [`PrivateForest::uses_deterministic_nonces`][crate::private::forest::traits::PrivateForest::uses_deterministic_nonces].
+    #[cfg(feature = "private")]
+    pub deterministic_nonces: bool,
+
+    /// The secret to derive private file content keys from, for convergent encryption.
+    ///
+    /// By default, every write encrypts content with a fresh random key. With a secret
+    /// set, the key is derived from the content instead, and its blocks are stored under
+    /// labels scoped to the forest rather than to the file, with deterministic nonces.
+    /// Writing the same content with the same secret, to any file of the same forest,
+    /// then results in the same ciphertexts, which deduplicate in the block store.
+    ///
+    /// This reveals which files share content to anyone who can read them, and lets anyone
+    /// holding the secret confirm guesses of file content. Content blocks also no longer
+    /// inherit the write access of the file's name, but that of the forest's root. Use it
+    /// for bulky data like media libraries, not for privacy-sensitive files.
+    #[cfg(feature = "private")]
+    pub convergence: Option<ConvergenceSecret>,
+}
+
+/// How a write changes the content type stored in a file's metadata.
+#[cfg(any(feature = "public", feature = "private"))]
+pub(crate) struct ContentTypeUpdate(Option<Option<&'static str>>);
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl WriteOptions {
+    /// Options that detect the content type of written files.
+    #[cfg_attr(not(feature = "private"), allow(clippy::needless_update))]
+    pub fn sniffing() -> Self {
+        Self {
+            sniff_content_type: true,
+            ..Default::default()
+        }
+    }
+
+    /// Works out how writing given content changes the file's metadata,
+    /// before the content is handed off.
+    #[cfg(any(feature = "public", feature = "private"))]
+    pub(crate) fn content_type_update(&self, content: &[u8]) -> ContentTypeUpdate {
+        ContentTypeUpdate(self.sniff_content_type.then(|| sniff_content_type(content)))
+    }
+}
+
+#[cfg(any(feature = "public", feature = "private"))]
+impl ContentTypeUpdate {
+    pub(crate) fn apply(self, metadata: &mut Metadata) {
+        match self.0 {
+            None => {}
+            Some(Some(content_type)) => metadata.put_content_type(content_type),
+            Some(None) => {
+                metadata.remove_content_type();
+            }
+        }
+    }
+}
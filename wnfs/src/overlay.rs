@@ -0,0 +1,594 @@
+//! Staging edits to a committed root tree in memory.
+
+use crate::{
+    private::{forest::hamt::HamtForest, AccessKey, PrivateDirectory, PrivateNode},
+    public::{PublicDirectory, PublicNode},
+    root_tree::RootTree,
+};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use std::{collections::BTreeMap, mem};
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockManifest, BlockStore, StagingBlockStore,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An edit session on top of a committed [`RootTree`], e.g. for UIs that let users cancel edits.
+///
+/// All changes are made to a working tree, whose blocks are staged in memory instead of
+/// being put into the block store. Reads through the working tree see the staged changes.
+/// Changes are then either [discarded](Self::discard), which goes back to the committed
+/// root, or [materialized](Self::materialize), which stores the working tree and writes
+/// its new blocks into the block store, committing a new root.
+///
+/// Every commit is remembered, so commits can be [undone](Self::undo) and
+/// [redone](Self::redo) like edits in a document editor. This only moves the committed
+/// root back and forth in memory, no blocks are deleted from the block store.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use wnfs::{common::MemoryBlockStore, overlay::Overlay, root_tree::RootTree};
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let root_cid = RootTree::empty(store).store().await?;
+///     let path = &["public".into(), "draft.md".into()];
+///
+///     let mut overlay = Overlay::load(&root_cid, store).await?;
+///     overlay.tree_mut().write(path, b"Hello".to_vec()).await?;
+///     assert_eq!(overlay.tree().read(path).await?, b"Hello");
+///
+///     overlay.discard();
+///     assert!(overlay.tree().read(path).await.is_err());
+///
+///     overlay.tree_mut().write(path, b"Hello, World!".to_vec()).await?;
+///     let root_cid = overlay.materialize().await?;
+///
+///     let root = RootTree::load(&root_cid, store).await?;
+///     assert_eq!(root.read(path).await?, b"Hello, World!");
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Overlay<B: BlockStore> {
+    base_cid: Cid,
+    base: RootSnapshot,
+    tree: RootTree<StagingBlockStore<B>>,
+    undo: Vec<(Cid, RootSnapshot)>,
+    redo: Vec<(Cid, RootSnapshot)>,
+}
+
+/// The paths that differ between the committed root and the working tree of an [`Overlay`].
+///
+/// Paths start with the partition they're in, like `"public"` or the path of a private root.
+/// Added and removed directories are listed by their own path only, not their contents.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverlayStatus {
+    /// Paths of nodes that are only in the working tree.
+    pub added: Vec<Vec<String>>,
+    /// Paths of files that were written to or changed metadata, and of nodes that
+    /// were replaced by a node of another type.
+    pub modified: Vec<Vec<String>>,
+    /// Paths of nodes that are only in the committed root.
+    pub removed: Vec<Vec<String>>,
+}
+
+/// The in-memory state of a root tree, without its block store.
+#[derive(Debug, Clone)]
+struct RootSnapshot {
+    forest: Arc<HamtForest>,
+    public_root: Arc<PublicDirectory>,
+    exchange_root: Arc<PublicDirectory>,
+    private_map: BTreeMap<Vec<String>, Arc<PrivateDirectory>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<B: BlockStore> Overlay<B> {
+    /// Starts an edit session on the root tree committed at given CID.
+    pub async fn load(base_cid: &Cid, store: B) -> Result<Self> {
+        let tree = RootTree::load(base_cid, StagingBlockStore::new(store)).await?;
+        Ok(Self {
+            base_cid: *base_cid,
+            base: RootSnapshot::of(&tree),
+            tree,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        })
+    }
+
+    /// Loads a private root into both the committed root and the working tree.
+    ///
+    /// Loading a private root isn't an edit, so discarding doesn't unload it. Undoing and
+    /// redoing only unloads it if it was created or loaded after the commit gone back to.
+    pub async fn load_private_root(
+        &mut self,
+        path: &[String],
+        access_key: &AccessKey,
+    ) -> Result<()> {
+        self.tree.load_private_root(path, access_key).await?;
+        let root = Arc::clone(&self.tree.private_map[path]);
+        self.base.private_map.insert(path.to_vec(), root);
+        Ok(())
+    }
+
+    /// Whether there's a commit that can be undone.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Whether there's an undone commit that can be redone.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Goes back to the committed root before the last commit, discarding staged changes.
+    ///
+    /// Returns the CID of the committed root gone back to, or `None` if there's nothing to
+    /// undo. The undone commit can be [redone](Self::redo) until the next commit.
+    ///
+    /// Private revisions committed after an undo reuse the revision names of the undone
+    /// ones, so the undone roots shouldn't be merged with later roots.
+    pub fn undo(&mut self) -> Option<Cid> {
+        let (cid, base) = self.undo.pop()?;
+        let current = self.checkout(cid, base);
+        self.redo.push(current);
+        Some(cid)
+    }
+
+    /// Goes forward to the committed root of the last undone commit, discarding staged changes.
+    ///
+    /// Returns the CID of the committed root gone forward to, or `None` if there's nothing
+    /// to redo.
+    pub fn redo(&mut self) -> Option<Cid> {
+        let (cid, base) = self.redo.pop()?;
+        let current = self.checkout(cid, base);
+        self.undo.push(current);
+        Some(cid)
+    }
+
+    /// Forgets all commits that could be undone or redone.
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Returns the CID of the committed root the working tree is based on.
+    pub fn base_cid(&self) -> &Cid {
+        &self.base_cid
+    }
+
+    /// Returns the working tree, including staged changes.
+    pub fn tree(&self) -> &RootTree<StagingBlockStore<B>> {
+        &self.tree
+    }
+
+    /// Returns the working tree for making changes to it.
+    pub fn tree_mut(&mut self) -> &mut RootTree<StagingBlockStore<B>> {
+        &mut self.tree
+    }
+
+    /// Reports the blocks staged so far, and which of them are new to the block store.
+    ///
+    /// Most blocks are only created when materializing, so this mostly covers file content.
+    pub async fn staged_manifest(&self) -> Result<BlockManifest> {
+        Ok(self.tree.store.manifest().await?)
+    }
+
+    /// Lists the paths that were added, modified or removed in the working tree since the
+    /// last commit, e.g. for showing a list of unsaved changes.
+    ///
+    /// Subtrees that weren't touched in the working tree are skipped without loading them,
+    /// so this only reads the directories along the paths that changed.
+    pub async fn status(&self) -> Result<OverlayStatus> {
+        let store = &self.tree.store;
+        let mut status = OverlayStatus::default();
+        let public_roots = [
+            ("public", &self.base.public_root, &self.tree.public_root),
+            (
+                "exchange",
+                &self.base.exchange_root,
+                &self.tree.exchange_root,
+            ),
+        ];
+        for (name, old, new) in public_roots {
+            if !Arc::ptr_eq(old, new) {
+                diff_public(&[name.into()], old, new, store, &mut status).await?;
+            }
+        }
+
+        for (path, old) in self.base.private_map.iter() {
+            match self.tree.private_map.get(path) {
+                Some(new) if Arc::ptr_eq(old, new) => {}
+                Some(new) => {
+                    let forests = (self.base.forest.as_ref(), self.tree.forest.as_ref());
+                    diff_private(path, old, new, forests, store, &mut status).await?;
+                }
+                None => status.removed.push(path.clone()),
+            }
+        }
+
+        for path in self.tree.private_map.keys() {
+            if !self.base.private_map.contains_key(path) {
+                status.added.push(path.clone());
+            }
+        }
+
+        status.added.sort();
+        status.modified.sort();
+        status.removed.sort();
+        Ok(status)
+    }
+
+    /// Drops all staged changes, going back to the committed root.
+    pub fn discard(&mut self) {
+        self.base.clone().restore(&mut self.tree);
+        self.tree.store.discard();
+    }
+
+    /// Stores the working tree and writes all staged blocks into the block store.
+    ///
+    /// The stored working tree becomes the new committed root, whose CID is returned.
+    pub async fn materialize(&mut self) -> Result<Cid> {
+        let cid = self.tree.store().await?;
+        self.commit(cid).await
+    }
+
+    /// Like [`Overlay::materialize`], but with given randomness for storing private roots.
+    pub async fn materialize_with(
+        &mut self,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Cid> {
+        let cid = self.tree.store_with(rng).await?;
+        self.commit(cid).await
+    }
+
+    async fn commit(&mut self, cid: Cid) -> Result<Cid> {
+        self.tree.store.flush().await?;
+        if cid == self.base_cid {
+            return Ok(cid);
+        }
+
+        let previous = (
+            mem::replace(&mut self.base_cid, cid),
+            mem::replace(&mut self.base, RootSnapshot::of(&self.tree)),
+        );
+        self.undo.push(previous);
+        self.redo.clear();
+        Ok(cid)
+    }
+
+    /// Makes given committed root the current one, returning the one it replaced.
+    fn checkout(&mut self, cid: Cid, base: RootSnapshot) -> (Cid, RootSnapshot) {
+        let previous = (
+            mem::replace(&mut self.base_cid, cid),
+            mem::replace(&mut self.base, base),
+        );
+        self.discard();
+        previous
+    }
+}
+
+impl OverlayStatus {
+    /// Whether there are no unsaved changes.
+    pub fn is_clean(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl RootSnapshot {
+    fn of<B: BlockStore>(tree: &RootTree<B>) -> Self {
+        Self {
+            forest: Arc::clone(&tree.forest),
+            public_root: Arc::clone(&tree.public_root),
+            exchange_root: Arc::clone(&tree.exchange_root),
+            private_map: tree.private_map.clone(),
+        }
+    }
+
+    fn restore<B: BlockStore>(self, tree: &mut RootTree<B>) {
+        tree.forest = self.forest;
+        tree.public_root = self.public_root;
+        tree.exchange_root = self.exchange_root;
+        tree.private_map = self.private_map;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Adds the differences between two revisions of a public directory at given path to the status.
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn diff_public(
+    path: &[String],
+    old: &PublicDirectory,
+    new: &PublicDirectory,
+    store: &impl BlockStore,
+    status: &mut OverlayStatus,
+) -> Result<()> {
+    let old_entries = old.entries(store).await?;
+    let new_entries = new.entries(store).await?;
+    for (name, old_link) in old_entries.iter() {
+        let child_path = [path, &[name.clone()]].concat();
+        let Some(new_link) = new_entries.get(name) else {
+            status.removed.push(child_path);
+            continue;
+        };
+
+        if let (Some(old_cid), Some(new_cid)) = (old_link.get_cid(), new_link.get_cid()) {
+            if old_cid == new_cid {
+                continue;
+            }
+        }
+
+        let old_node = old_link.resolve_value(store).await?;
+        let new_node = new_link.resolve_value(store).await?;
+        match (old_node, new_node) {
+            (PublicNode::Dir(old_dir), PublicNode::Dir(new_dir)) => {
+                if !Arc::ptr_eq(old_dir, new_dir) {
+                    diff_public(&child_path, old_dir, new_dir, store, status).await?;
+                }
+            }
+            _ if old_node == new_node => {}
+            _ => status.modified.push(child_path),
+        }
+    }
+
+    for name in new_entries.keys() {
+        if !old_entries.contains_key(name) {
+            status.added.push([path, &[name.clone()]].concat());
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds the differences between two revisions of a private directory at given path to the status.
+///
+/// The old revision is loaded from the first forest, the new one from the second.
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn diff_private(
+    path: &[String],
+    old: &PrivateDirectory,
+    new: &PrivateDirectory,
+    forests: (&HamtForest, &HamtForest),
+    store: &impl BlockStore,
+    status: &mut OverlayStatus,
+) -> Result<()> {
+    let (old_forest, new_forest) = forests;
+    for (name, old_link) in old.content.entries.iter() {
+        let child_path = [path, &[name.clone()]].concat();
+        let Some(new_link) = new.content.entries.get(name) else {
+            status.removed.push(child_path);
+            continue;
+        };
+
+        if let (Some(old_cid), Some(new_cid)) =
+            (old_link.get_content_cid(), new_link.get_content_cid())
+        {
+            if old_cid == new_cid {
+                continue;
+            }
+        }
+
+        let old_node = old.lookup_node(name, false, old_forest, store).await?;
+        let new_node = new.lookup_node(name, false, new_forest, store).await?;
+        match (old_node, new_node) {
+            (Some(PrivateNode::Dir(old_dir)), Some(PrivateNode::Dir(new_dir))) => {
+                if !Arc::ptr_eq(&old_dir, &new_dir) {
+                    diff_private(&child_path, &old_dir, &new_dir, forests, store, status).await?;
+                }
+            }
+            (Some(PrivateNode::File(old_file)), Some(PrivateNode::File(new_file))) => {
+                let unchanged = Arc::ptr_eq(&old_file, &new_file)
+                    || (old_file.content.metadata == new_file.content.metadata
+                        && old_file.content.content == new_file.content.content);
+                if !unchanged {
+                    status.modified.push(child_path);
+                }
+            }
+            _ => status.modified.push(child_path),
+        }
+    }
+
+    for name in new.content.entries.keys() {
+        if !old.content.entries.contains_key(name) {
+            status.added.push([path, &[name.clone()]].concat());
+        }
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn staged_changes_only_reach_the_store_when_materialized() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let mut root = RootTree::empty_with(store, rng, Utc::now());
+        let access_key = root
+            .create_private_root_with(&["private".into()], Utc::now(), rng)
+            .await?;
+        let root_cid = root.store_with(rng).await?;
+
+        let mut overlay = Overlay::load(&root_cid, store).await?;
+        overlay
+            .load_private_root(&["private".into()], &access_key)
+            .await?;
+
+        let path = &["private".into(), "notes.txt".into()];
+        let content = vec![1; 1000];
+        overlay
+            .tree_mut()
+            .write_with(path, content.clone(), Utc::now(), rng)
+            .await?;
+        assert_eq!(overlay.tree().read(path).await?, content);
+
+        let manifest = overlay.staged_manifest().await?;
+        assert!(!manifest.new_blocks.is_empty());
+        for info in manifest.new_blocks.iter() {
+            assert!(!store.has_block(&info.cid).await?);
+        }
+
+        // Discarding keeps the private root loaded
+        overlay.discard();
+        assert!(overlay.tree().read(path).await.is_err());
+        assert!(overlay.tree().ls(&["private".into()]).await?.is_empty());
+        assert!(overlay.staged_manifest().await?.new_blocks.is_empty());
+
+        overlay
+            .tree_mut()
+            .write_with(path, content.clone(), Utc::now(), rng)
+            .await?;
+        let new_root_cid = overlay.materialize_with(rng).await?;
+        assert_eq!(overlay.base_cid(), &new_root_cid);
+        assert!(overlay.staged_manifest().await?.new_blocks.is_empty());
+
+        let mut root = RootTree::load(&new_root_cid, store).await?;
+        root.load_private_root(&["private".into()], &access_key)
+            .await?;
+        assert_eq!(root.read(path).await?, content);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn commits_can_be_undone_and_redone() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let root_cid = RootTree::empty(store).store().await?;
+        let path = &["public".into(), "draft.md".into()];
+
+        let mut overlay = Overlay::load(&root_cid, store).await?;
+        assert!(!overlay.can_undo());
+        overlay.tree_mut().write(path, b"one".to_vec()).await?;
+        let one_cid = overlay.materialize().await?;
+        overlay.tree_mut().write(path, b"two".to_vec()).await?;
+        let two_cid = overlay.materialize().await?;
+
+        // Staged changes are dropped when undoing
+        overlay.tree_mut().write(path, b"three".to_vec()).await?;
+        assert_eq!(overlay.undo(), Some(one_cid));
+        assert_eq!(overlay.base_cid(), &one_cid);
+        assert_eq!(overlay.tree().read(path).await?, b"one");
+
+        assert_eq!(overlay.undo(), Some(root_cid));
+        assert!(overlay.tree().read(path).await.is_err());
+        assert_eq!(overlay.undo(), None);
+
+        assert_eq!(overlay.redo(), Some(one_cid));
+        assert_eq!(overlay.redo(), Some(two_cid));
+        assert_eq!(overlay.tree().read(path).await?, b"two");
+        assert!(!overlay.can_redo());
+
+        // Committing after undoing drops the undone commits
+        overlay.undo();
+        overlay.tree_mut().write(path, b"four".to_vec()).await?;
+        overlay.materialize().await?;
+        assert!(!overlay.can_redo());
+        assert_eq!(overlay.undo(), Some(one_cid));
+
+        // Nothing was deleted from the store
+        let root = RootTree::load(&two_cid, store).await?;
+        assert_eq!(root.read(path).await?, b"two");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn status_lists_unsaved_changes() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let mut root = RootTree::empty_with(store, rng, Utc::now());
+        let access_key = root
+            .create_private_root_with(&["private".into()], Utc::now(), rng)
+            .await?;
+        let root_cid = root.store_with(rng).await?;
+
+        let mut overlay = Overlay::load(&root_cid, store).await?;
+        overlay
+            .load_private_root(&["private".into()], &access_key)
+            .await?;
+        for path in [
+            ["public", "docs", "a.md"],
+            ["public", "docs", "b.md"],
+            ["private", "notes", "todo.txt"],
+        ] {
+            let path = path.map(String::from);
+            overlay
+                .tree_mut()
+                .write_with(&path, b"draft".to_vec(), Utc::now(), rng)
+                .await?;
+        }
+        assert!(!overlay.status().await?.is_clean());
+        overlay.materialize_with(rng).await?;
+        assert!(overlay.status().await?.is_clean());
+
+        let tree = overlay.tree_mut();
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        tree.write(&path(&["public", "docs", "a.md"]), b"final".to_vec())
+            .await?;
+        tree.rm(&path(&["public", "docs", "b.md"])).await?;
+        tree.write(&path(&["public", "new", "c.md"]), b"new".to_vec())
+            .await?;
+        tree.write_with(
+            &path(&["private", "notes", "todo.txt"]),
+            b"done".to_vec(),
+            Utc::now(),
+            rng,
+        )
+        .await?;
+        tree.write_with(
+            &path(&["private", "ideas.txt"]),
+            b"idea".to_vec(),
+            Utc::now(),
+            rng,
+        )
+        .await?;
+
+        let status = overlay.status().await?;
+        assert_eq!(
+            status.added,
+            vec![path(&["private", "ideas.txt"]), path(&["public", "new"])]
+        );
+        assert_eq!(
+            status.modified,
+            vec![
+                path(&["private", "notes", "todo.txt"]),
+                path(&["public", "docs", "a.md"])
+            ]
+        );
+        assert_eq!(status.removed, vec![path(&["public", "docs", "b.md"])]);
+
+        overlay.materialize_with(rng).await?;
+        assert!(overlay.status().await?.is_clean());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! Lazy ancestry updates for moved private nodes.
+//!
+//! A private node's name is derived from its parent's name, so moving a directory
+//! changes the names of everything below it. Rather than re-keying a whole subtree
+//! at once, a moved directory only re-keys itself and marks its children as pending.
+//! Each pending child is re-keyed once it gets modified, passing the mark on to its
+//! own children. Until then, pending children are read under their old names,
+//! which are checked against the name their parent had before it was moved.
+//!
+//! Keys of pending children don't change until they're modified, so unmodified
+//! descendants of a moved directory stay readable to anyone with access to the
+//! directory's old location. Copying re-keys a whole subtree at once instead, for
+//! when that's not acceptable.
+
+use rand_chacha::ChaCha12Rng;
+use rand_core::{CryptoRngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Randomness for re-keying a directory entry whose ancestry update is pending.
+///
+/// Entries get re-keyed whenever they're modified, which is often where no
+/// randomness source is available, so it's drawn when the entry is moved instead.
+/// It's stored in the encrypted directory content, so only readers of the new
+/// location learn the keys derived from it.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AncestrySeed(#[serde(with = "serde_byte_array")] [u8; 32]);
+
+/// A directory entry whose ancestry update is pending.
+#[derive(Clone, Debug)]
+pub(crate) struct PendingAncestry {
+    /// Randomness to re-key the entry with once it's modified.
+    pub(crate) seed: AncestrySeed,
+    /// The name the directory had when the entry was marked, which the entry
+    /// is still named after.
+    pub(crate) parent_name: Name,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl AncestrySeed {
+    /// Draws a fresh seed.
+    pub(crate) fn new(rng: &mut impl CryptoRngCore) -> Self {
+        let mut seed = [0; 32];
+        rng.fill_bytes(&mut seed);
+        Self(seed)
+    }
+
+    /// Returns the randomness source to re-key the entry with.
+    pub(crate) fn rng(&self) -> ChaCha12Rng {
+        ChaCha12Rng::from_seed(self.0)
+    }
+}
+
+impl PendingAncestry {
+    /// Marks an entry of a directory that's about to be moved away from `parent_name`.
+    pub(crate) fn new(parent_name: &Name, rng: &mut impl CryptoRngCore) -> Self {
+        Self {
+            seed: AncestrySeed::new(rng),
+            parent_name: parent_name.clone(),
+        }
+    }
+}
+
+impl PartialEq for PendingAncestry {
+    fn eq(&self, other: &Self) -> bool {
+        // We skip equality-checking the parent name, since loaded names only keep their accumulator.
+        self.seed == other.seed
+    }
+}
+
+impl std::fmt::Debug for AncestrySeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AncestrySeed(..)")
+    }
+}
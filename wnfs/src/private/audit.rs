@@ -0,0 +1,302 @@
+//! Auditing stored private data for weak spots in its encryption.
+//!
+//! [`check_forest`] inspects the blocks a private forest links to without any keys, and
+//! collects everything that looks off into an [`AuditReport`]: blocks that aren't
+//! ciphertexts, ciphertexts sharing a nonce, and forests written in older formats.
+//! With the `sharing` feature, [`check_exchange_keys`] also flags exchange keys that are
+//! weaker than the ones this library generates.
+//!
+//! Checks that need keys, like finding node ciphertexts that aren't bound to their
+//! labels yet, aren't covered. Those are found when decrypting, e.g. with [`fsck`](crate::fsck).
+
+use super::{
+    forest::hamt::{HamtForest, HamtForestSerializable},
+    AUTHENTICATION_TAG_SIZE, CIPHERTEXT_VERSION_SIZE, NONCE_SIZE,
+};
+use anyhow::Result;
+use libipld_core::{cid::Cid, ipld::Ipld};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wnfs_common::{BlockStore, BlockStoreError, Storable, CODEC_RAW};
+use wnfs_hamt::constants::HAMT_VERSION;
+#[cfg(feature = "sharing")]
+use {
+    super::{devices, RSA_KEY_SIZE},
+    crate::public::PublicDirectory,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The size of the smallest ciphertext, an empty plaintext encrypted with a snapshot key.
+const MIN_CIPHERTEXT_SIZE: usize = NONCE_SIZE + AUTHENTICATION_TAG_SIZE;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The result of auditing stored private data with [`check_forest`] or [`check_exchange_keys`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditReport {
+    /// How many blocks were inspected.
+    pub checked_blocks: usize,
+    /// All issues found, in the order they were found.
+    pub issues: Vec<AuditIssue>,
+}
+
+/// A weak spot found by an audit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum AuditIssue {
+    /// The forest was written in an older format version than this library writes.
+    StaleFormatVersion {
+        cid: Cid,
+        version: Version,
+        current: Version,
+    },
+    /// A block the forest links to is missing from the block store.
+    MissingBlock { cid: Cid },
+    /// A block the forest links to isn't stored as a raw block, so it isn't a ciphertext.
+    NotRawCiphertext { cid: Cid },
+    /// A block the forest links to decodes as DAG-CBOR, so it's most likely stored unencrypted.
+    PlaintextBlock { cid: Cid },
+    /// A block the forest links to is too short to be a ciphertext.
+    TruncatedCiphertext { cid: Cid, size: usize },
+    /// Distinct ciphertexts that start with the same nonce, which breaks their confidentiality
+    /// if they were encrypted with the same key.
+    ReusedNonce { cids: Vec<Cid> },
+    /// An exchange key that's shorter than the ones this library generates, so access keys
+    /// shared with the device are wrapped with a weaker key.
+    WeakExchangeKey { path: Vec<String>, bits: usize },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl AuditReport {
+    /// Whether no issues were found.
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Inspects the private forest stored at given CID and all ciphertext blocks it links to.
+///
+/// This doesn't need any keys, so it can be run by a host on the data it stores.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::{MemoryBlockStore, Storable},
+///     private::{
+///         audit,
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///         PrivateDirectory,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     dir.write(&["notes.md".into()], true, Utc::now(), b"secret".to_vec(), forest, store, rng)
+///         .await?;
+///     dir.as_node().store(forest, store, rng).await?;
+///     let forest_cid = forest.store(store).await?;
+///
+///     let report = audit::check_forest(&forest_cid, store).await?;
+///
+///     assert!(report.is_clean());
+///     assert!(report.checked_blocks > 0);
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn check_forest(forest_cid: &Cid, store: &impl BlockStore) -> Result<AuditReport> {
+    let mut report = AuditReport::default();
+
+    let bytes = store.get_block(forest_cid).await?;
+    let serializable: HamtForestSerializable = serde_ipld_dagcbor::from_slice(&bytes)?;
+    report.checked_blocks += 1;
+    if serializable.version < HAMT_VERSION {
+        report.issues.push(AuditIssue::StaleFormatVersion {
+            cid: *forest_cid,
+            version: serializable.version.clone(),
+            current: HAMT_VERSION,
+        });
+    }
+
+    let forest = HamtForest::from_serializable(Some(forest_cid), serializable).await?;
+    let mut nonces = BTreeMap::<Vec<u8>, Vec<Cid>>::new();
+    for cid in forest.ciphertext_cids(store).await? {
+        if cid.codec() != CODEC_RAW {
+            report.issues.push(AuditIssue::NotRawCiphertext { cid });
+        }
+
+        let bytes = match store.get_block(&cid).await {
+            Ok(bytes) => bytes,
+            Err(BlockStoreError::CIDNotFound(_)) => {
+                report.issues.push(AuditIssue::MissingBlock { cid });
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        report.checked_blocks += 1;
+
+        if is_dag_cbor_node(&bytes) {
+            report.issues.push(AuditIssue::PlaintextBlock { cid });
+        } else if bytes.len() < MIN_CIPHERTEXT_SIZE {
+            report.issues.push(AuditIssue::TruncatedCiphertext {
+                cid,
+                size: bytes.len(),
+            });
+        } else {
+            // Covers the nonce both with and without the version flag in front
+            let nonce = bytes[..CIPHERTEXT_VERSION_SIZE + NONCE_SIZE].to_vec();
+            nonces.entry(nonce).or_default().push(cid);
+        }
+    }
+
+    report.issues.extend(
+        nonces
+            .into_values()
+            .filter(|cids| cids.len() > 1)
+            .map(|cids| AuditIssue::ReusedNonce { cids }),
+    );
+
+    Ok(report)
+}
+
+/// Inspects the exchange keys of all devices registered in given exchange partition,
+/// see [`devices::list_devices`].
+#[cfg(feature = "sharing")]
+pub async fn check_exchange_keys(
+    exchange_root: &PublicDirectory,
+    store: &impl BlockStore,
+) -> Result<AuditReport> {
+    let mut report = AuditReport::default();
+    for device in devices::list_devices(exchange_root, store).await? {
+        report.checked_blocks += 1;
+        let bits = modulus_bits(&device.exchange_key);
+        if bits < RSA_KEY_SIZE {
+            report.issues.push(AuditIssue::WeakExchangeKey {
+                path: vec![device.name, super::share::EXCHANGE_KEY_NAME.into()],
+                bits,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Whether given bytes are a whole DAG-CBOR map or list, like an unencrypted node.
+///
+/// Random bytes rarely decode as a single DAG-CBOR value without any bytes left over.
+fn is_dag_cbor_node(bytes: &[u8]) -> bool {
+    matches!(
+        serde_ipld_dagcbor::from_slice(bytes),
+        Ok(Ipld::Map(_) | Ipld::List(_))
+    )
+}
+
+/// The bit length of a big-endian RSA modulus.
+#[cfg(feature = "sharing")]
+fn modulus_bits(modulus: &[u8]) -> usize {
+    match modulus.iter().position(|byte| *byte != 0) {
+        Some(i) => (modulus.len() - i) * 8 - modulus[i].leading_zeros() as usize,
+        None => 0,
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::traits::PrivateForest, PrivateDirectory};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use std::collections::BTreeMap;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, CODEC_DAG_CBOR};
+    use wnfs_nameaccumulator::NameSegment;
+
+    #[async_std::test]
+    async fn plaintext_and_reused_nonces_are_flagged() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        dir.write(
+            &["a.txt".into()],
+            true,
+            Utc::now(),
+            b"a".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        dir.as_node().store(forest, store, rng).await?;
+
+        let report = check_forest(&forest.store(store).await?, store).await?;
+        assert!(report.is_clean(), "{report:?}");
+
+        let secret = BTreeMap::from([("secret".to_string(), "value".to_string())]);
+        let plaintext = serde_ipld_dagcbor::to_vec(&secret)?;
+        let plaintext_cid = store.put_block(plaintext, CODEC_DAG_CBOR).await?;
+        let first_cid = store.put_block([7; 64].to_vec(), CODEC_RAW).await?;
+        let second_cid = store
+            .put_block([[7; 32], [8; 32]].concat(), CODEC_RAW)
+            .await?;
+        let short_cid = store.put_block([9; 8].to_vec(), CODEC_RAW).await?;
+        let name = forest
+            .empty_name()
+            .with_segments_added(Some(NameSegment::new(rng)));
+        forest
+            .put_encrypted(
+                &name,
+                [plaintext_cid, first_cid, second_cid, short_cid],
+                store,
+            )
+            .await?;
+
+        let report = check_forest(&forest.store(store).await?, store).await?;
+        assert!(report
+            .issues
+            .contains(&AuditIssue::NotRawCiphertext { cid: plaintext_cid }));
+        assert!(report
+            .issues
+            .contains(&AuditIssue::PlaintextBlock { cid: plaintext_cid }));
+        assert!(report.issues.contains(&AuditIssue::TruncatedCiphertext {
+            cid: short_cid,
+            size: 8
+        }));
+        let mut reused = vec![first_cid, second_cid];
+        reused.sort();
+        assert!(report
+            .issues
+            .contains(&AuditIssue::ReusedNonce { cids: reused }));
+        assert_eq!(report.issues.len(), 4);
+
+        Ok(())
+    }
+}
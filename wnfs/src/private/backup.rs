@@ -0,0 +1,277 @@
+//! Incremental backups between two snapshots of a private file system.
+
+use super::{AccessKey, SnapshotHandle};
+use crate::error::FsError;
+use anyhow::{bail, ensure, Result};
+use bytes::Bytes;
+use libipld_core::cid::Cid;
+use serde::{Deserialize, Serialize};
+use wnfs_common::{
+    utils::{reachable_blocks, reachable_blocks_except, verify_block},
+    BlockStore,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The blocks needed to get from one snapshot of a private forest to a later one.
+///
+/// Contains only the blocks reachable from the newer forest that aren't reachable from
+/// the older one, plus a [`BackupManifest`] describing them. Private file system blocks
+/// are encrypted and backups don't contain any keys, so they can be stored anywhere.
+///
+/// Backups serialize with serde, e.g. to DAG-CBOR via [`wnfs_common::encode`].
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     private::{
+///         IncrementalBackup, PrivateDirectory, SnapshotHandle,
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///     },
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let old = SnapshotHandle::take(dir, forest, store, rng).await?;
+///
+///     dir.write(&["notes.md".into()], true, Utc::now(), b"hi".to_vec(), forest, store, rng).await?;
+///     let new = SnapshotHandle::take(dir, forest, store, rng).await?;
+///
+///     let backup = IncrementalBackup::between(&old, &new, store).await?;
+///
+///     // Somewhere that only has the old snapshot's blocks:
+///     let backup_store = &MemoryBlockStore::new();
+///     IncrementalBackup::between_roots(None, &old.forest, store).await?
+///         .apply(backup_store)
+///         .await?;
+///     let restored = backup.apply(backup_store).await?;
+///
+///     let snapshot = SnapshotHandle { forest: restored, access_key: new.access_key }
+///         .open(backup_store)
+///         .await?;
+///     assert_eq!(snapshot.read(&["notes.md".into()], backup_store).await?, b"hi");
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncrementalBackup {
+    pub manifest: BackupManifest,
+    /// The block contents, in the same order as [`BackupManifest::blocks`].
+    pub blocks: Vec<Bytes>,
+}
+
+/// Describes what an [`IncrementalBackup`] contains.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// The forest the backup builds on, or `None` if it's a full backup.
+    pub base: Option<Cid>,
+    /// The forest the backup reconstructs.
+    pub target: Cid,
+    /// The CIDs of all blocks in the backup.
+    pub blocks: Vec<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl IncrementalBackup {
+    /// Creates a backup of all blocks in the `new` snapshot's forest that aren't in `old`'s.
+    pub async fn between(
+        old: &SnapshotHandle,
+        new: &SnapshotHandle,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        Self::between_roots(Some(&old.forest), &new.forest, store).await
+    }
+
+    /// Creates a backup of all blocks reachable from `target` that aren't reachable
+    /// from `base`. Without a `base`, all blocks reachable from `target` are included.
+    pub async fn between_roots(
+        base: Option<&Cid>,
+        target: &Cid,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let known = match base {
+            Some(base) => reachable_blocks(base, store).await?,
+            None => Default::default(),
+        };
+
+        let cids = reachable_blocks_except(target, &known, store).await?;
+        let mut blocks = Vec::with_capacity(cids.len());
+        for cid in cids.iter() {
+            blocks.push(store.get_block(cid).await?);
+        }
+
+        Ok(Self {
+            manifest: BackupManifest {
+                base: base.cloned(),
+                target: *target,
+                blocks: cids,
+            },
+            blocks,
+        })
+    }
+
+    /// The total size of all blocks in the backup in bytes.
+    pub fn size(&self) -> usize {
+        self.blocks.iter().map(Bytes::len).sum()
+    }
+
+    /// Puts the blocks of this backup into `store`, which needs to have all blocks of the
+    /// base forest already, and returns the CID of the reconstructed forest.
+    ///
+    /// Fails without writing anything if the base forest is missing or a block doesn't
+    /// match its CID.
+    pub async fn apply(&self, store: &impl BlockStore) -> Result<Cid> {
+        let manifest = &self.manifest;
+        ensure!(
+            manifest.blocks.len() == self.blocks.len(),
+            FsError::InvalidBackup("Manifest and blocks don't line up".into())
+        );
+
+        if let Some(base) = &manifest.base {
+            if !store.has_block(base).await? {
+                bail!(FsError::InvalidBackup(format!(
+                    "Missing base forest {base}"
+                )));
+            }
+        }
+
+        for (cid, bytes) in manifest.blocks.iter().zip(self.blocks.iter()) {
+            verify_block(cid, bytes).map_err(|e| FsError::InvalidBackup(e.to_string()))?;
+        }
+
+        for (cid, bytes) in manifest.blocks.iter().zip(self.blocks.iter()) {
+            store.put_block_keyed(*cid, bytes.clone()).await?;
+        }
+
+        Ok(manifest.target)
+    }
+
+    /// Applies this backup like [`IncrementalBackup::apply`] and returns a handle to the
+    /// reconstructed snapshot, which can be opened with given access key.
+    pub async fn apply_snapshot(
+        &self,
+        access_key: AccessKey,
+        store: &impl BlockStore,
+    ) -> Result<SnapshotHandle> {
+        let forest = self.apply(store).await?;
+        Ok(SnapshotHandle { forest, access_key })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{
+        forest::{hamt::HamtForest, traits::PrivateForest},
+        PrivateDirectory,
+    };
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::{decode, encode, libipld::cbor::DagCborCodec, MemoryBlockStore};
+
+    #[async_std::test]
+    async fn backups_reconstruct_the_new_snapshot() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let content = vec![42u8; 100_000];
+
+        dir.write(
+            &["big.bin".into()],
+            true,
+            Utc::now(),
+            content.clone(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let old = SnapshotHandle::take(dir, forest, store, rng).await?;
+
+        dir.write(
+            &["small.txt".into()],
+            true,
+            Utc::now(),
+            b"small".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let new = SnapshotHandle::take(dir, forest, store, rng).await?;
+
+        let full = IncrementalBackup::between_roots(None, &old.forest, store).await?;
+        let incremental = IncrementalBackup::between(&old, &new, store).await?;
+        assert!(incremental.size() < full.size());
+
+        let incremental: IncrementalBackup =
+            decode(&encode(&incremental, DagCborCodec)?, DagCborCodec)?;
+
+        let backup_store = &MemoryBlockStore::new();
+        assert!(incremental.apply(backup_store).await.is_err());
+
+        full.apply(backup_store).await?;
+        let restored = incremental
+            .apply_snapshot(new.access_key.clone(), backup_store)
+            .await?;
+        assert_eq!(restored, new);
+
+        let snapshot = restored.open(backup_store).await?;
+        assert_eq!(
+            snapshot.read(&["big.bin".into()], backup_store).await?,
+            content
+        );
+        assert_eq!(
+            snapshot.read(&["small.txt".into()], backup_store).await?,
+            b"small"
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn tampered_backups_are_rejected() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let snapshot = SnapshotHandle::take(&dir, forest, store, rng).await?;
+
+        let mut backup = IncrementalBackup::between_roots(None, &snapshot.forest, store).await?;
+        backup.blocks[0] = Bytes::from_static(b"tampered");
+
+        let backup_store = &MemoryBlockStore::new();
+        let result = backup.apply(backup_store).await;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::InvalidBackup(_))
+        ));
+        assert!(!backup_store.has_block(&snapshot.forest).await?);
+
+        Ok(())
+    }
+}
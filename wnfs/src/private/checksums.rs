@@ -0,0 +1,475 @@
+//! Signed manifests of file checksums, for verifying archives and restores of snapshots.
+
+use super::{
+    forest::traits::PrivateForest, PrivateDirectory, PrivateFile, PrivateNode, SnapshotHandle,
+};
+use crate::{
+    error::FsError,
+    is_readable_wnfs_version,
+    signed_root::{RootSigner, RootVerifier},
+    WNFS_VERSION,
+};
+use anyhow::{bail, Result};
+use async_recursion::async_recursion;
+use futures::StreamExt;
+use libipld_core::cid::Cid;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wnfs_common::{
+    decode, encode, libipld::cbor::DagCborCodec, utils::Arc, BlockStore, HashOutput,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The domain separation tag prepended to the manifests in signed messages,
+/// so manifest signatures can't be confused with other signatures by the same key.
+const MANIFEST_SIGNATURE_DSI: &str = "wnfs/manifest/signature";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The plaintext checksums of all files in a snapshot, signed by a device key.
+///
+/// A manifest lets users check an archive exported from the snapshot, or a file system
+/// restored from it, without access to the blocks of the original forest. Files are
+/// compared by the BLAKE3 hash and size of their content. Directories aren't listed, so
+/// empty directories aren't checked.
+///
+/// Plaintext hashes let anyone holding the manifest confirm guesses of file content, so
+/// manifests need to be kept as private as the files themselves.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///         ChecksumManifest, PrivateDirectory, SnapshotHandle,
+///     },
+///     signed_root::{RootSigner, RootVerifier},
+/// };
+///
+/// // Stands in for a real device key pair.
+/// struct Device(&'static str);
+///
+/// impl RootSigner for Device {
+///     fn device(&self) -> &str {
+///         self.0
+///     }
+///
+///     async fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+///         Ok([self.0.as_bytes(), message].concat())
+///     }
+/// }
+///
+/// impl RootVerifier for Device {
+///     async fn verify(&self, device: &str, message: &[u8], signature: &[u8]) -> Result<bool> {
+///         Ok(device == self.0 && signature == [device.as_bytes(), message].concat())
+///     }
+/// }
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let path = vec!["notes.md".to_string()];
+///     dir.write(&path, true, Utc::now(), b"hi".to_vec(), forest, store, rng).await?;
+///     let snapshot = SnapshotHandle::take(dir, forest, store, rng).await?;
+///
+///     let manifest = ChecksumManifest::export(&snapshot, &Device("laptop"), store).await?;
+///
+///     // Later, e.g. after extracting an archive
+///     manifest.verify(&Device("laptop")).await?;
+///     assert!(manifest.check_files([(path.clone(), b"hi")]).is_empty());
+///     assert!(!manifest.check_files([(path, b"ho")]).is_empty());
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChecksumManifest {
+    version: Version,
+    forest: Cid,
+    entries: Vec<ChecksumEntry>,
+    device: String,
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+}
+
+/// The checksum of a file in a [`ChecksumManifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChecksumEntry {
+    /// The path of the file, relative to the snapshot's root directory.
+    pub path: Vec<String>,
+    /// The BLAKE3 hash of the file's content.
+    #[serde(with = "serde_byte_array")]
+    pub hash: HashOutput,
+    /// The size of the file's content in bytes.
+    pub size: u64,
+    /// The revision of the file in the snapshot, see [`PrivateNodeHeader::revision`].
+    ///
+    /// [`PrivateNodeHeader::revision`]: super::PrivateNodeHeader::revision
+    pub revision: u64,
+}
+
+/// A difference between a [`ChecksumManifest`] and the files checked against it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumMismatch {
+    /// The file is in the manifest, but wasn't among the checked files.
+    Missing(Vec<String>),
+    /// The file's content doesn't match its checksum.
+    Changed(Vec<String>),
+    /// The file was among the checked files, but isn't in the manifest.
+    Unexpected(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize)]
+enum ChecksumManifestSerializable {
+    #[serde(rename = "wnfs/manifest/checksums")]
+    Manifest(ChecksumManifest),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ChecksumManifest {
+    /// Computes the checksums of all files in given snapshot and signs them with the
+    /// device key.
+    ///
+    /// All file content is read from `store` to compute the checksums.
+    pub async fn export(
+        snapshot: &SnapshotHandle,
+        signer: &impl RootSigner,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let view = snapshot.open(store).await?;
+        let entries = collect_checksums(view.root(), view.forest(), store).await?;
+        let device = signer.device().to_string();
+        let message = create_signature_message(&snapshot.forest, &entries, &device)?;
+        let signature = signer.sign(&message).await?;
+
+        Ok(Self {
+            version: WNFS_VERSION,
+            forest: snapshot.forest,
+            entries,
+            device,
+            signature,
+        })
+    }
+
+    /// Gets the CID of the forest of the snapshot the manifest was exported from.
+    pub fn get_forest(&self) -> &Cid {
+        &self.forest
+    }
+
+    /// Gets the identifier of the device that signed the manifest.
+    pub fn get_device(&self) -> &str {
+        &self.device
+    }
+
+    /// Gets the checksums of all files, ordered by path.
+    pub fn get_entries(&self) -> &[ChecksumEntry] {
+        &self.entries
+    }
+
+    /// Gets the checksum of the file at given path.
+    pub fn get_entry(&self, path_segments: &[String]) -> Option<&ChecksumEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_slice().cmp(path_segments))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// Checks that the manifest was signed by an authorized device.
+    ///
+    /// Errors with [`FsError::UnauthorizedManifest`] if the verifier rejects the signature.
+    pub async fn verify(&self, verifier: &impl RootVerifier) -> Result<()> {
+        let message = create_signature_message(&self.forest, &self.entries, &self.device)?;
+        if !verifier
+            .verify(&self.device, &message, &self.signature)
+            .await?
+        {
+            bail!(FsError::UnauthorizedManifest(self.device.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Checks files, e.g. from an exported archive, against the manifest.
+    ///
+    /// The files are given by their path relative to the snapshot's root directory and
+    /// their content. Returns all mismatches, ordered by path, so an empty result means
+    /// the files are exactly the ones in the manifest. Doesn't check the signature, see
+    /// [`ChecksumManifest::verify`].
+    pub fn check_files<C: AsRef<[u8]>>(
+        &self,
+        files: impl IntoIterator<Item = (Vec<String>, C)>,
+    ) -> Vec<ChecksumMismatch> {
+        let checked = files
+            .into_iter()
+            .map(|(path, content)| {
+                let content = content.as_ref();
+                let hash = *blake3::hash(content).as_bytes();
+                (path, (hash, content.len() as u64))
+            })
+            .collect();
+
+        self.compare(checked)
+    }
+
+    /// Checks a private directory, e.g. one restored from an archive, against the manifest.
+    ///
+    /// Works like [`ChecksumManifest::check_files`] with all files in the directory tree.
+    /// Only content is compared, so restored files may be at other revisions.
+    pub async fn check_dir(
+        &self,
+        dir: &Arc<PrivateDirectory>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<ChecksumMismatch>> {
+        let checked = collect_checksums(dir, forest, store)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.path, (entry.hash, entry.size)))
+            .collect();
+
+        Ok(self.compare(checked))
+    }
+
+    /// Stores the manifest as a block, returning its CID.
+    pub async fn store(&self, store: &impl BlockStore) -> Result<Cid> {
+        let serializable = ChecksumManifestSerializable::Manifest(self.clone());
+        let bytes = encode(&serializable, DagCborCodec)?;
+        Ok(store.put_block(bytes, DagCborCodec.into()).await?)
+    }
+
+    /// Loads a manifest, without verifying the signature.
+    pub async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let ChecksumManifestSerializable::Manifest(manifest) =
+            decode(&store.get_block(cid).await?, DagCborCodec)?;
+        if !is_readable_wnfs_version(&manifest.version) {
+            bail!(FsError::UnexpectedVersion(manifest.version));
+        }
+
+        Ok(manifest)
+    }
+
+    fn compare(
+        &self,
+        mut checked: BTreeMap<Vec<String>, (HashOutput, u64)>,
+    ) -> Vec<ChecksumMismatch> {
+        let mut mismatches = Vec::new();
+        for entry in self.entries.iter() {
+            match checked.remove(&entry.path) {
+                None => mismatches.push(ChecksumMismatch::Missing(entry.path.clone())),
+                Some(checksum) if checksum != (entry.hash, entry.size) => {
+                    mismatches.push(ChecksumMismatch::Changed(entry.path.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        mismatches.extend(checked.into_keys().map(ChecksumMismatch::Unexpected));
+        mismatches.sort_by(|a, b| a.path().cmp(b.path()));
+        mismatches
+    }
+}
+
+impl ChecksumMismatch {
+    /// Gets the path of the mismatching file.
+    pub fn path(&self) -> &[String] {
+        match self {
+            Self::Missing(path) | Self::Changed(path) | Self::Unexpected(path) => path,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Generates the message that's signed for given manifest contents.
+///
+/// The device is part of the message, so a signature can't be passed off as another device's.
+fn create_signature_message(
+    forest: &Cid,
+    entries: &[ChecksumEntry],
+    device: &str,
+) -> Result<Vec<u8>> {
+    encode(
+        &(MANIFEST_SIGNATURE_DSI, forest, entries, device),
+        DagCborCodec,
+    )
+}
+
+/// Computes the checksums of all files in given directory tree, ordered by path.
+async fn collect_checksums(
+    dir: &Arc<PrivateDirectory>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Vec<ChecksumEntry>> {
+    let mut entries = Vec::new();
+    collect_dir_checksums(&[], dir, forest, store, &mut entries).await?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn collect_dir_checksums(
+    path: &[String],
+    dir: &Arc<PrivateDirectory>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+    entries: &mut Vec<ChecksumEntry>,
+) -> Result<()> {
+    for name in dir.get_entries() {
+        let Some(node) = dir.lookup_node(name, false, forest, store).await? else {
+            continue;
+        };
+
+        let mut child_path = path.to_vec();
+        child_path.push(name.clone());
+        match node {
+            PrivateNode::Dir(child) => {
+                collect_dir_checksums(&child_path, &child, forest, store, entries).await?;
+            }
+            PrivateNode::File(file) => {
+                entries.push(file_checksum(child_path, &file, forest, store).await?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn file_checksum(
+    path: Vec<String>,
+    file: &PrivateFile,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<ChecksumEntry> {
+    let mut hasher = blake3::Hasher::new();
+    let mut size = 0;
+    let mut chunks = file.stream_content(0, forest, store);
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        hasher.update(&chunk);
+    }
+
+    Ok(ChecksumEntry {
+        path,
+        hash: *hasher.finalize().as_bytes(),
+        size,
+        revision: file.header.revision(),
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    /// Stands in for a device key pair by using the device as key for a keyed hash.
+    struct DeviceKey(&'static str);
+
+    impl DeviceKey {
+        fn mac(device: &str, message: &[u8]) -> Vec<u8> {
+            let key = blake3::derive_key("wnfs/test/device key", device.as_bytes());
+            blake3::keyed_hash(&key, message).as_bytes().to_vec()
+        }
+    }
+
+    impl RootSigner for DeviceKey {
+        fn device(&self) -> &str {
+            self.0
+        }
+
+        async fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+            Ok(Self::mac(self.0, message))
+        }
+    }
+
+    impl RootVerifier for DeviceKey {
+        async fn verify(&self, device: &str, message: &[u8], signature: &[u8]) -> Result<bool> {
+            Ok(device == self.0 && Self::mac(device, message) == signature)
+        }
+    }
+
+    #[async_std::test]
+    async fn restored_directories_are_checked_against_the_manifest() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let notes = vec!["docs".to_string(), "notes.md".to_string()];
+        let video = vec!["videos".to_string(), "talk.mp4".to_string()];
+        let video_content = vec![7; 600_000];
+        dir.write(&notes, true, Utc::now(), b"v1".to_vec(), forest, store, rng)
+            .await?;
+        dir.write(&notes, true, Utc::now(), b"v2".to_vec(), forest, store, rng)
+            .await?;
+        dir.write(&video, true, Utc::now(), video_content, forest, store, rng)
+            .await?;
+        let snapshot = SnapshotHandle::take(dir, forest, store, rng).await?;
+
+        let manifest = ChecksumManifest::export(&snapshot, &DeviceKey("laptop"), store).await?;
+        let cid = manifest.store(store).await?;
+        let manifest = ChecksumManifest::load(&cid, store).await?;
+        manifest.verify(&DeviceKey("laptop")).await?;
+        assert!(manifest.verify(&DeviceKey("phone")).await.is_err());
+
+        assert_eq!(manifest.get_entries().len(), 2);
+        assert_eq!(manifest.get_entry(&video).unwrap().size, 600_000);
+
+        // A restore into a fresh forest, where one file got lost and another one changed
+        let restore_forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let restored = &mut PrivateDirectory::new_rc(&restore_forest.empty_name(), Utc::now(), rng);
+        let extra = vec!["extra.txt".to_string()];
+        for (path, content) in [(&notes, b"v2".to_vec()), (&extra, b"?".to_vec())] {
+            restored
+                .write(path, true, Utc::now(), content, restore_forest, store, rng)
+                .await?;
+        }
+
+        let mismatches = manifest.check_dir(restored, restore_forest, store).await?;
+        assert_eq!(
+            mismatches,
+            vec![
+                ChecksumMismatch::Unexpected(extra),
+                ChecksumMismatch::Missing(video),
+            ]
+        );
+
+        // Tampering with the entries breaks the signature
+        let mut tampered = manifest.clone();
+        tampered.entries.pop();
+        assert!(tampered.verify(&DeviceKey("laptop")).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! Compression of private file content.
+
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The deflate compression level, on a scale from 0 (no compression) to 10.
+const DEFLATE_LEVEL: u8 = 6;
+
+/// The size of the header in front of every compressed block, which tells whether the
+/// block's content is compressed or stored as-is.
+pub(crate) const BLOCK_HEADER_SIZE: usize = 1;
+
+/// Header of blocks whose content didn't compress and is stored as-is.
+const STORED_BLOCK: u8 = 0;
+
+/// Header of blocks whose content is compressed.
+const COMPRESSED_BLOCK: u8 = 1;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The algorithm private file content was compressed with before it got encrypted.
+///
+/// Ciphertexts don't compress, so compressing content has to happen before encryption.
+/// The algorithm is recorded next to the keys of the content, so reads decompress it
+/// transparently.
+///
+/// Content is compressed block by block, so reads only need to fetch and decompress
+/// the blocks they cover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Compression {
+    /// Raw DEFLATE, as specified in RFC 1951.
+    Deflate,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Compression {
+    /// Compresses given bytes.
+    pub fn compress(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Deflate => compress_to_vec(bytes, DEFLATE_LEVEL),
+        }
+    }
+
+    /// Decompresses bytes that were compressed with this algorithm into exactly `size`
+    /// bytes, failing on anything that decompresses to more or less than that.
+    pub fn decompress(&self, bytes: &[u8], size: usize) -> Result<Vec<u8>> {
+        let decompressed = match self {
+            Self::Deflate => decompress_to_vec_with_limit(bytes, size)
+                .map_err(|e| FsError::InvalidCompressedContent(e.to_string()))?,
+        };
+
+        if decompressed.len() != size {
+            bail!(FsError::InvalidCompressedContent(format!(
+                "Expected {size} bytes, but got {}",
+                decompressed.len()
+            )));
+        }
+
+        Ok(decompressed)
+    }
+
+    /// Compresses the content of a single block, or keeps it as-is if it doesn't get
+    /// any smaller. The result is prefixed with a header telling which of both it is.
+    pub(crate) fn compress_block(&self, bytes: &[u8]) -> Vec<u8> {
+        let compressed = self.compress(bytes);
+        let (header, data) = if compressed.len() < bytes.len() {
+            (COMPRESSED_BLOCK, &compressed[..])
+        } else {
+            (STORED_BLOCK, bytes)
+        };
+
+        let mut block = Vec::with_capacity(BLOCK_HEADER_SIZE + data.len());
+        block.push(header);
+        block.extend_from_slice(data);
+        block
+    }
+
+    /// Decompresses a block written by [`compress_block`](Self::compress_block), which
+    /// holds `size` bytes of content. Anything after the block's data, like padding,
+    /// is ignored.
+    pub(crate) fn decompress_block(&self, block: &[u8], size: usize) -> Result<Vec<u8>> {
+        match block.split_first() {
+            Some((&STORED_BLOCK, data)) if data.len() >= size => Ok(data[..size].to_vec()),
+            Some((&COMPRESSED_BLOCK, data)) => self.decompress(data, size),
+            _ => bail!(FsError::InvalidCompressedContent(
+                "Invalid compressed block".to_string()
+            )),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deflate_round_trips_and_shrinks_text() {
+        let text = b"Hello, World!\n".repeat(1000);
+        let compressed = Compression::Deflate.compress(&text);
+
+        assert!(compressed.len() < text.len() / 10);
+        assert_eq!(
+            Compression::Deflate
+                .decompress(&compressed, text.len())
+                .unwrap(),
+            text
+        );
+        assert!(Compression::Deflate.decompress(&text, text.len()).is_err());
+    }
+
+    #[test]
+    fn decompression_is_limited_to_the_expected_size() {
+        let bomb = Compression::Deflate.compress(&vec![0; 10_000_000]);
+
+        assert!(Compression::Deflate.decompress(&bomb, 1000).is_err());
+        assert!(Compression::Deflate.decompress(&bomb, 20_000_000).is_err());
+    }
+
+    #[test]
+    fn blocks_round_trip_with_padding() {
+        let text = b"Hello, World!\n".repeat(1000);
+        let random = (0..1000)
+            .map(|i| (i * 7919 % 251) as u8)
+            .collect::<Vec<_>>();
+
+        for content in [text, random] {
+            let mut block = Compression::Deflate.compress_block(&content);
+            assert!(block.len() <= content.len() + BLOCK_HEADER_SIZE);
+
+            block.resize(block.len() + 100, 0);
+            let decompressed = Compression::Deflate
+                .decompress_block(&block, content.len())
+                .unwrap();
+            assert_eq!(decompressed, content);
+        }
+    }
+}
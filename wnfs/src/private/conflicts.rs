@@ -0,0 +1,200 @@
+//! Inspecting and resolving the conflicting heads of concurrently written private nodes.
+
+use super::{forest::traits::PrivateForest, AccessKey, PrivateNode};
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateNode {
+    /// Returns the heads of this node's history, along with the CIDs of their content.
+    ///
+    /// Usually there's just one head, the latest revision. When the node was written
+    /// concurrently from multiple places, there's one head per write that no later
+    /// revision has merged yet. These are the nodes that
+    /// [`PrivateNode::search_latest_reconciled`] merges automatically, so apps can show
+    /// them to the user instead and let them choose with [`PrivateNode::pick_conflict_head`],
+    /// or keep the automatic merge with [`PrivateNode::resolve_conflicts`].
+    ///
+    /// The heads are ordered by their content CIDs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::{utils::Arc, MemoryBlockStore},
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateDirectory,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir =
+    ///         PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng)
+    ///             .await?;
+    ///
+    ///     // Two concurrent writes
+    ///     for name in ["cats", "dogs"] {
+    ///         let mut fork = Arc::clone(&dir);
+    ///         fork.mkdir(&[name.into()], false, Utc::now(), forest, store, rng).await?;
+    ///         fork.as_node().store(forest, store, rng).await?;
+    ///     }
+    ///
+    ///     let mut node = dir.as_node();
+    ///     let heads = node.conflict_heads(forest, store).await?;
+    ///     assert_eq!(heads.len(), 2);
+    ///
+    ///     // Keep the first write only
+    ///     let (cid, head) = &heads[0];
+    ///     node.pick_conflict_head(cid, forest, store, rng).await?;
+    ///     assert_eq!(
+    ///         node.as_dir()?.get_entries().collect::<Vec<_>>(),
+    ///         head.as_dir()?.get_entries().collect::<Vec<_>>(),
+    ///     );
+    ///     assert_eq!(node.conflict_heads(forest, store).await?.len(), 1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn conflict_heads(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<(Cid, PrivateNode)>> {
+        let mut header = self.get_header().clone();
+        let heads = header.seek_unmerged_heads(forest, store).await?;
+        Ok(heads.into_iter().collect())
+    }
+
+    /// Resolves conflicting writes to this node by keeping the content and metadata of the
+    /// head with given content CID, as returned by [`PrivateNode::conflict_heads`], and
+    /// dropping the changes of all other heads.
+    ///
+    /// Like [`PrivateNode::resolve_conflicts`], this stores a revision that links to all
+    /// heads, so the other writes stay in the node's history, and sets this node to it.
+    ///
+    /// Returns an error if the CID doesn't belong to any current head, e.g. because
+    /// another conflicting write happened in the meantime.
+    pub async fn pick_conflict_head(
+        &mut self,
+        head: &Cid,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<AccessKey> {
+        let heads = self.conflict_heads(forest, store).await?;
+        let Some((_, picked)) = heads.into_iter().find(|(cid, _)| cid == head) else {
+            bail!(FsError::NotFound);
+        };
+
+        let mut node = self.search_latest_reconciled(forest, store).await?;
+        match (&mut node, picked) {
+            (PrivateNode::File(file), PrivateNode::File(picked)) => {
+                let file = Arc::make_mut(file);
+                file.content.content.clone_from(&picked.content.content);
+                file.content.metadata.clone_from(&picked.content.metadata);
+            }
+            (PrivateNode::Dir(dir), PrivateNode::Dir(picked)) => {
+                let dir = Arc::make_mut(dir);
+                dir.content.metadata.clone_from(&picked.content.metadata);
+                dir.content.entries.clone_from(&picked.content.entries);
+                dir.content.order.clone_from(&picked.content.order);
+                dir.content
+                    .pending_ancestry
+                    .clone_from(&picked.content.pending_ancestry);
+            }
+            (PrivateNode::File(_), PrivateNode::Dir(_)) => bail!(FsError::NotAFile),
+            (PrivateNode::Dir(_), PrivateNode::File(_)) => bail!(FsError::NotADirectory),
+        }
+
+        let access_key = node.store_collapsed(forest, store, rng).await?;
+        *self = node;
+        Ok(access_key)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, PrivateFile};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn picked_file_heads_win_over_the_automatic_merge() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = PrivateFile::new_rc(&forest.empty_name(), Utc::now(), rng);
+        file.as_node().store(forest, store, rng).await?;
+
+        let node = file.as_node();
+        assert_eq!(node.conflict_heads(forest, store).await?.len(), 1);
+
+        for content in [b"ours".to_vec(), b"theirs".to_vec()] {
+            let mut fork = Arc::clone(&file);
+            fork.prepare_next_revision()?
+                .set_content(&content[..], Utc::now(), forest, store, rng)
+                .await?;
+            fork.as_node().store(forest, store, rng).await?;
+        }
+
+        let heads = node.conflict_heads(forest, store).await?;
+        assert_eq!(heads.len(), 2);
+
+        let merged = node.search_latest_reconciled(forest, store).await?;
+        let merged_content = merged.as_file()?.get_content(forest, store).await?;
+
+        // Pick whichever head the automatic merge didn't
+        let mut picked = None;
+        for (cid, head) in heads {
+            let content = head.as_file()?.get_content(forest, store).await?;
+            if content != merged_content {
+                picked = Some((cid, content));
+            }
+        }
+        let (cid, content) = picked.unwrap();
+
+        let mut resolved = node.clone();
+        resolved
+            .pick_conflict_head(&cid, forest, store, rng)
+            .await?;
+        assert_eq!(resolved.get_previous().len(), 1);
+        assert_eq!(
+            resolved.as_file()?.get_content(forest, store).await?,
+            content
+        );
+
+        let latest = node.search_latest_reconciled(forest, store).await?;
+        assert_eq!(latest.as_file()?.get_content(forest, store).await?, content);
+
+        let result = resolved.pick_conflict_head(&cid, forest, store, rng).await;
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! Diffing the content of private files chunk by chunk.
+
+use super::{forest::traits::PrivateForest, PrivateFile, MAX_BLOCK_CONTENT_SIZE};
+use anyhow::Result;
+use futures::StreamExt;
+use std::ops::Range;
+use wnfs_common::BlockStore;
+use wnfs_hamt::ChangeType;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Content is diffed in chunks that line up with the content blocks of files.
+pub const DIFF_CHUNK_SIZE: u64 = MAX_BLOCK_CONTENT_SIZE as u64;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A change to one chunk of a file's content, see [`PrivateFile::diff_content`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkChange {
+    pub r#type: ChangeType,
+    /// The index of the chunk, counted in [`DIFF_CHUNK_SIZE`] steps.
+    pub index: u64,
+    /// The bytes the chunk covers in the old content, if it exists there.
+    pub old_range: Option<Range<u64>>,
+    /// The bytes the chunk covers in the new content, if it exists there.
+    pub new_range: Option<Range<u64>>,
+}
+
+/// The hash and length of a chunk of plaintext content.
+type ChunkHash = (blake3::Hash, u64);
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateFile {
+    /// Compares the content of this file with the content of `other`, usually a later
+    /// revision of the same file, and returns the chunks that differ.
+    ///
+    /// Since every revision is encrypted with a fresh key, the chunks are compared by the
+    /// hashes of their plaintext, so both contents are read and decrypted in full, unless
+    /// the revisions share the same content. Chunks are [`DIFF_CHUNK_SIZE`] bytes long,
+    /// no matter whether the content is stored inline, in blocks, compressed or as records.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     hamt::ChangeType,
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateFile, DIFF_CHUNK_SIZE,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///
+    ///     let mut content = vec![0; 3 * DIFF_CHUNK_SIZE as usize];
+    ///     let old = PrivateFile::with_content(&forest.empty_name(), Utc::now(), content.clone(), forest, store, rng).await?;
+    ///
+    ///     content[DIFF_CHUNK_SIZE as usize + 10] = 1;
+    ///     let mut new = old.clone();
+    ///     new.set_content(&content[..], Utc::now(), forest, store, rng).await?;
+    ///
+    ///     let changes = old.diff_content(&new, forest, store).await?;
+    ///     assert_eq!(changes.len(), 1);
+    ///     assert_eq!(changes[0].r#type, ChangeType::Modify);
+    ///     assert_eq!(changes[0].index, 1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn diff_content(
+        &self,
+        other: &Self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<ChunkChange>> {
+        if self.content.content == other.content.content {
+            return Ok(Vec::new());
+        }
+
+        let old_hashes = self.chunk_hashes(forest, store).await?;
+        let new_hashes = other.chunk_hashes(forest, store).await?;
+
+        let range = |index: u64, (_, len): &ChunkHash| {
+            let start = index * DIFF_CHUNK_SIZE;
+            start..start + len
+        };
+
+        let mut changes = Vec::new();
+        for i in 0..old_hashes.len().max(new_hashes.len()) {
+            let index = i as u64;
+            let old_range = old_hashes.get(i).map(|chunk| range(index, chunk));
+            let new_range = new_hashes.get(i).map(|chunk| range(index, chunk));
+            let r#type = match (old_hashes.get(i), new_hashes.get(i)) {
+                (Some(old), Some(new)) if old == new => continue,
+                (Some(_), Some(_)) => ChangeType::Modify,
+                (None, Some(_)) => ChangeType::Add,
+                (Some(_), None) => ChangeType::Remove,
+                (None, None) => unreachable!(),
+            };
+
+            changes.push(ChunkChange {
+                r#type,
+                index,
+                old_range,
+                new_range,
+            });
+        }
+
+        Ok(changes)
+    }
+
+    /// Hashes the plaintext content of this file in chunks of [`DIFF_CHUNK_SIZE`].
+    async fn chunk_hashes(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<ChunkHash>> {
+        let chunk_size = DIFF_CHUNK_SIZE as usize;
+        let mut hashes = Vec::new();
+        let mut buffer = Vec::with_capacity(chunk_size);
+        let mut content = self.stream_content(0, forest, store);
+        while let Some(bytes) = content.next().await {
+            let mut bytes = &bytes?[..];
+            while !bytes.is_empty() {
+                let take = bytes.len().min(chunk_size - buffer.len());
+                buffer.extend_from_slice(&bytes[..take]);
+                bytes = &bytes[take..];
+                if buffer.len() == chunk_size {
+                    hashes.push((blake3::hash(&buffer), chunk_size as u64));
+                    buffer.clear();
+                }
+            }
+        }
+
+        if !buffer.is_empty() {
+            hashes.push((blake3::hash(&buffer), buffer.len() as u64));
+        }
+
+        Ok(hashes)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn diffs_report_modified_added_and_removed_chunks() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let chunk = DIFF_CHUNK_SIZE;
+
+        let mut content = vec![7; 2 * chunk as usize + 100];
+        let old = PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            content.clone(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        // Metadata-only revisions share the content
+        let mut tagged = old.clone();
+        tagged.get_metadata_mut().put("tag", "unchanged".into());
+        assert!(old.diff_content(&tagged, forest, store).await?.is_empty());
+
+        // Re-encrypting the same content doesn't show up as a change
+        let mut rewritten = old.clone();
+        rewritten
+            .set_content(&content[..], Utc::now(), forest, store, rng)
+            .await?;
+        assert!(old
+            .diff_content(&rewritten, forest, store)
+            .await?
+            .is_empty());
+
+        content[0] = 0;
+        content.extend(vec![7; chunk as usize]);
+        let mut grown = old.clone();
+        grown
+            .set_content(&content[..], Utc::now(), forest, store, rng)
+            .await?;
+
+        let changes = old.diff_content(&grown, forest, store).await?;
+        assert_eq!(
+            changes,
+            vec![
+                ChunkChange {
+                    r#type: ChangeType::Modify,
+                    index: 0,
+                    old_range: Some(0..chunk),
+                    new_range: Some(0..chunk),
+                },
+                ChunkChange {
+                    r#type: ChangeType::Modify,
+                    index: 2,
+                    old_range: Some(2 * chunk..2 * chunk + 100),
+                    new_range: Some(2 * chunk..3 * chunk),
+                },
+                ChunkChange {
+                    r#type: ChangeType::Add,
+                    index: 3,
+                    old_range: None,
+                    new_range: Some(3 * chunk..3 * chunk + 100),
+                },
+            ]
+        );
+
+        let changes = grown.diff_content(&old, forest, store).await?;
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[2].r#type, ChangeType::Remove);
+        assert_eq!(changes[2].old_range, Some(3 * chunk..3 * chunk + 100));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! Helpers for giving all of a user's devices access to their private root.
+//!
+//! The access key to the private root is the user's root secret. Devices are registered
+//! with their exchange keys in the user's exchange partition, and each device gets the root
+//! secret shared with it, encrypted with its exchange key. The root secret is a temporal
+//! access key, so devices can follow the root to all its later revisions.
+//!
+//! Revoking a device removes it from the exchange partition and re-keys the whole private
+//! root, so the revoked device's root secret doesn't give access to any later revision.
+//! The new root secret is then shared with the remaining devices at a new share counter.
+
+use super::{
+    forest::traits::PrivateForest,
+    share::{recipient, sharer, SharePayload, EXCHANGE_KEY_NAME},
+    AccessKey, ExchangeKey, PrivateDirectory, PrivateKey, PublicKeyModulus,
+};
+use crate::public::PublicDirectory;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rand_core::CryptoRngCore;
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A device registered in a user's exchange partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    /// The name of the device, which is its directory in the exchange partition.
+    pub name: String,
+    /// The public key modulus of the device's exchange key.
+    pub exchange_key: PublicKeyModulus,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Lists the devices registered in the exchange partition, in order of their names.
+///
+/// Directories without an exchange key aren't devices and are skipped.
+pub async fn list_devices(
+    exchange_root: &PublicDirectory,
+    store: &impl BlockStore,
+) -> Result<Vec<Device>> {
+    let mut devices = Vec::new();
+    for (name, _) in exchange_root.ls(&[], store).await? {
+        let path = [name.clone(), EXCHANGE_KEY_NAME.into()];
+        if let Ok(exchange_key) = exchange_root.read(&path, store).await {
+            devices.push(Device { name, exchange_key });
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Registers a device with its exchange key in the exchange partition and shares
+/// the root secret with it at given share counter.
+#[allow(clippy::too_many_arguments)]
+pub async fn add_device<K: ExchangeKey>(
+    device: &Device,
+    root_access_key: &AccessKey,
+    share_count: u64,
+    root_did: &str,
+    exchange_root: &mut Arc<PublicDirectory>,
+    time: DateTime<Utc>,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<()> {
+    exchange_root
+        .write(
+            &[device.name.clone(), EXCHANGE_KEY_NAME.into()],
+            device.exchange_key.clone(),
+            time,
+            store,
+        )
+        .await?;
+
+    sharer::share_with_exchange_key::<K>(
+        root_access_key,
+        share_count,
+        root_did,
+        &device.exchange_key,
+        forest,
+        store,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Removes a device from the exchange partition, re-keys the private root and shares
+/// the new root secret with all remaining devices at given share counter.
+///
+/// Every node in the root is moved to new keys, and the root itself is moved to a new
+/// name under the forest's empty name. The root is stored and updated to the re-keyed
+/// version, and the new root secret is returned.
+///
+/// The share counter needs to be higher than any counter used for the root secret before,
+/// so devices find the new root secret with [`find_device_access`]. The revoked device
+/// keeps access to all revisions it had access to before.
+#[allow(clippy::too_many_arguments)]
+pub async fn revoke_device<K: ExchangeKey>(
+    device_name: &str,
+    root: &mut Arc<PrivateDirectory>,
+    share_count: u64,
+    root_did: &str,
+    exchange_root: &mut Arc<PublicDirectory>,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut (impl CryptoRngCore + CondSend),
+) -> Result<AccessKey> {
+    exchange_root.rm(&[device_name.into()], store).await?;
+
+    let mut node = root.as_node();
+    node.update_ancestry(&forest.empty_name(), &(), &mut 0, 0, forest, store, rng)
+        .await?;
+    let root_access_key = node.store(forest, store, rng).await?;
+    *root = node.as_dir()?;
+
+    for device in list_devices(exchange_root, store).await? {
+        sharer::share_with_exchange_key::<K>(
+            &root_access_key,
+            share_count,
+            root_did,
+            &device.exchange_key,
+            forest,
+            store,
+        )
+        .await?;
+    }
+
+    Ok(root_access_key)
+}
+
+/// Finds the latest root secret shared with a device, looking at share counters
+/// from `share_count_start` up to, but not including, `share_count_start + limit`.
+///
+/// Unlike [`recipient::find_latest_share_counter`], this skips counters that weren't
+/// shared with the device, e.g. because it was added after the root was re-keyed.
+#[allow(clippy::too_many_arguments)]
+pub async fn find_device_access(
+    share_count_start: u64,
+    limit: u64,
+    device_exchange_key: &[u8],
+    device_key: &impl PrivateKey,
+    root_did: &str,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Option<SharePayload>> {
+    let mut latest = None;
+    for share_count in share_count_start..share_count_start + limit {
+        let share_label =
+            sharer::create_share_name(share_count, root_did, device_exchange_key, forest);
+        if forest.has(&share_label, store).await? {
+            latest = Some(share_label);
+        }
+    }
+
+    match latest {
+        Some(share_label) => Ok(Some(
+            recipient::receive_share(&share_label, device_key, forest, store).await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, RsaPrivateKey, RsaPublicKey};
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    const ROOT_DID: &str = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+
+    fn device(name: &str, key: &RsaPrivateKey) -> Result<Device> {
+        Ok(Device {
+            name: name.into(),
+            exchange_key: key.get_public_key().get_public_key_modulus()?,
+        })
+    }
+
+    /// Lists the files in the latest revision of the root the device has access to.
+    async fn visible_files(
+        device: &Device,
+        key: &RsaPrivateKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<String>> {
+        let payload = find_device_access(0, 10, &device.exchange_key, key, ROOT_DID, forest, store)
+            .await?
+            .unwrap();
+        let root = payload.node.search_latest(forest, store).await?.as_dir()?;
+        let files = root.ls(&[], true, forest, store).await?;
+        Ok(files.into_iter().map(|(name, _)| name).collect())
+    }
+
+    #[async_std::test]
+    async fn revoked_devices_lose_access_to_later_revisions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let exchange_root = &mut PublicDirectory::new_rc(Utc::now());
+
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        root.write(
+            &["notes.txt".into()],
+            true,
+            Utc::now(),
+            b"shared".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let root_access_key = root.as_node().store(forest, store, rng).await?;
+
+        let laptop_key = RsaPrivateKey::new()?;
+        let phone_key = RsaPrivateKey::new()?;
+        let laptop = device("laptop", &laptop_key)?;
+        let phone = device("phone", &phone_key)?;
+        for device in [&laptop, &phone] {
+            add_device::<RsaPublicKey>(
+                device,
+                &root_access_key,
+                0,
+                ROOT_DID,
+                exchange_root,
+                Utc::now(),
+                forest,
+                store,
+            )
+            .await?;
+        }
+
+        assert_eq!(
+            list_devices(exchange_root, store).await?,
+            [laptop.clone(), phone.clone()]
+        );
+
+        revoke_device::<RsaPublicKey>(
+            "phone",
+            root,
+            1,
+            ROOT_DID,
+            exchange_root,
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        root.write(
+            &["secret.txt".into()],
+            true,
+            Utc::now(),
+            b"laptop only".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        root.as_node().store(forest, store, rng).await?;
+
+        assert_eq!(list_devices(exchange_root, store).await?, [laptop.clone()]);
+
+        assert_eq!(
+            visible_files(&laptop, &laptop_key, forest, store).await?,
+            ["notes.txt", "secret.txt"]
+        );
+        assert_eq!(
+            visible_files(&phone, &phone_key, forest, store).await?,
+            ["notes.txt"]
+        );
+
+        Ok(())
+    }
+}
@@ -1,9 +1,14 @@
 use super::{
-    encrypted::Encrypted, forest::traits::PrivateForest, link::PrivateLink,
-    PrivateDirectoryContentSerializable, PrivateFile, PrivateNode, PrivateNodeContentSerializable,
-    PrivateNodeHeader, PrivateRef, TemporalKey,
+    ancestry::PendingAncestry, encrypted::Encrypted, forest::traits::PrivateForest,
+    link::PrivateLink, overflow_entries, overflow_metadata, OverflowCache, OverflowWriter,
+    PendingAncestrySerializable, PrivateDirectoryContentSerializable, PrivateFile, PrivateNode,
+    PrivateNodeContentSerializable, PrivateNodeHeader, PrivateRef, TemporalKey,
+    ENCRYPTION_OVERHEAD,
+};
+use crate::{
+    error::FsError, is_legacy_wnfs_version, is_readable_wnfs_version, options::WriteOptions,
+    traits::Id, SearchResult, WNFS_VERSION,
 };
-use crate::{error::FsError, is_readable_wnfs_version, traits::Id, SearchResult, WNFS_VERSION};
 use anyhow::{bail, ensure, Result};
 use async_once_cell::OnceCell;
 use chrono::{DateTime, Utc};
@@ -15,8 +20,8 @@ use std::{
     fmt::Debug,
 };
 use wnfs_common::{
-    utils::{error, Arc, CondSend},
-    BlockStore, Metadata, PathNodes, PathNodesResult, CODEC_RAW,
+    utils::{error, Arc, CondSend, ProgressReporter},
+    BlockStore, HashOutput, Metadata, PathNodes, PathNodesResult, CODEC_RAW,
 };
 use wnfs_nameaccumulator::{Name, NameSegment};
 
@@ -49,11 +54,24 @@ pub struct PrivateDirectory {
     pub(crate) content: PrivateDirectoryContent,
 }
 
+/// The blocks a stored private node's revision consists of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrivateNodeCids {
+    /// The CID of the encrypted node content.
+    pub content: Cid,
+    /// The CID of the encrypted node header.
+    pub header: Cid,
+}
+
 pub(crate) struct PrivateDirectoryContent {
     pub(crate) persisted_as: OnceCell<Cid>,
     pub(crate) previous: BTreeSet<(usize, Encrypted<Cid>)>,
     pub(crate) metadata: Metadata,
     pub(crate) entries: BTreeMap<String, PrivateLink>,
+    pub(crate) order: Vec<String>,
+    pub(crate) order_revision: u64,
+    pub(crate) pending_ancestry: BTreeMap<String, PendingAncestry>,
+    pub(crate) overflow_cache: OverflowCache,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -93,6 +111,10 @@ impl PrivateDirectory {
                 previous: BTreeSet::new(),
                 metadata: Metadata::new(time),
                 entries: BTreeMap::new(),
+                order: Vec::new(),
+                order_revision: 0,
+                pending_ancestry: BTreeMap::new(),
+                overflow_cache: OverflowCache::default(),
             },
         }
     }
@@ -257,7 +279,7 @@ impl PrivateDirectory {
         Ok(match self.content.entries.get(path_segment) {
             Some(private_link) => {
                 let private_node = private_link
-                    .resolve_node(forest, store, Some(self.header.name.clone()))
+                    .resolve_node(forest, store, Some(self.child_parent_name(path_segment)))
                     .await?;
                 if search_latest {
                     Some(private_node.search_latest_reconciled(forest, store).await?)
@@ -269,6 +291,18 @@ impl PrivateDirectory {
         })
     }
 
+    /// Returns the parent name to check the name of given child against when loading it.
+    ///
+    /// Children with a pending ancestry update are still named after where this
+    /// directory was moved from, so they're checked against its name from back then.
+    pub(crate) fn child_parent_name(&self, path_segment: &str) -> Name {
+        self.content
+            .pending_ancestry
+            .get(path_segment)
+            .map_or(&self.header.name, |pending| &pending.parent_name)
+            .clone()
+    }
+
     /// Looks up a node by its path name in the current directory.
     pub(crate) async fn lookup_node_mut<'a>(
         &'a mut self,
@@ -277,15 +311,21 @@ impl PrivateDirectory {
         forest: &impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<Option<&'a mut PrivateNode>> {
+        let parent_name = Some(self.child_parent_name(path_segment));
         Ok(match self.content.entries.get_mut(path_segment) {
             Some(private_link) => {
                 let private_node = private_link
-                    .resolve_node_mut(forest, store, Some(self.header.name.clone()))
+                    .resolve_node_mut(forest, store, parent_name)
                     .await?;
                 if search_latest {
                     *private_node = private_node.search_latest_reconciled(forest, store).await?;
                 }
 
+                // The node is about to be modified, so this is the time to move it under our name.
+                if let Some(pending) = self.content.pending_ancestry.remove(path_segment) {
+                    private_node.update_ancestry_lazily(&self.header.name, &mut pending.seed.rng());
+                }
+
                 Some(private_node)
             }
             None => None,
@@ -422,6 +462,16 @@ impl PrivateDirectory {
         Ok(cloned)
     }
 
+    /// Clones this directory, copying all entries that weren't stored yet,
+    /// see [`PrivateNode::detached`].
+    pub(crate) fn detached(&self) -> Self {
+        let mut cloned = self.clone();
+        for link in cloned.content.entries.values_mut() {
+            *link = link.detached();
+        }
+        cloned
+    }
+
     /// Call this function to prepare this directory for conflict reconciliation merge changes.
     /// Advances this node to the revision given in `target_header`.
     /// Generates another previous link, unless this node is already a merge node, then this
@@ -479,6 +529,15 @@ impl PrivateDirectory {
         rng: &mut (impl CryptoRngCore + CondSend),
     ) {
         self.header.inumber = NameSegment::new(rng);
+        self.prepare_move(parent_name, rng);
+    }
+
+    /// This prepares this directory for moving it to some other place.
+    ///
+    /// Works like [`PrivateDirectory::prepare_key_rotation`], except that it keeps the
+    /// inumber, so the directory keeps its identity and inode number. Write access still
+    /// follows the new parent, since the name is derived from the parent's name.
+    pub(crate) fn prepare_move(&mut self, parent_name: &Name, rng: &mut impl CryptoRngCore) {
         self.header.update_name(parent_name);
         self.header.reset_ratchet(rng);
         self.content.persisted_as = OnceCell::new();
@@ -754,11 +813,70 @@ impl PrivateDirectory {
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.write_with_options(
+            path_segments,
+            search_latest,
+            time,
+            content,
+            &WriteOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+    }
+
+    /// Writes a file to the directory, like [`PrivateDirectory::write`], with given options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     options::WriteOptions,
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let path = &["pixel.png".into()];
+    ///     let png = b"\x89PNG\r\n\x1a\n...".to_vec();
+    ///
+    ///     dir.write_with_options(path, true, Utc::now(), png, &WriteOptions::sniffing(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let file = dir.get_node(path, true, forest, store).await?.unwrap().as_file()?;
+    ///     assert_eq!(file.get_metadata().get_content_type(), Some("image/png"));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn write_with_options(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        time: DateTime<Utc>,
+        content: Vec<u8>,
+        options: &WriteOptions,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
     ) -> Result<()> {
         let (path, filename) = crate::utils::split_last(path_segments)?;
         let dir = self
             .get_or_create_leaf_dir_mut(path, time, search_latest, forest, store, rng)
             .await?;
+        let content_type = options.content_type_update(&content);
 
         match dir
             .lookup_node_mut(filename, search_latest, forest, store)
@@ -766,17 +884,32 @@ impl PrivateDirectory {
         {
             Some(PrivateNode::File(file)) => {
                 let file = file.prepare_next_revision()?;
-                let content =
-                    PrivateFile::prepare_content(&file.header.name, content, forest, store, rng)
-                        .await?;
+                let content = PrivateFile::prepare_content(
+                    &file.header.name,
+                    content,
+                    options,
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
                 file.content.content = content;
                 file.content.metadata.upsert_mtime(time);
+                content_type.apply(&mut file.content.metadata);
             }
             Some(PrivateNode::Dir(_)) => bail!(FsError::DirectoryAlreadyExists),
             None => {
-                let file =
-                    PrivateFile::with_content(&dir.header.name, time, content, forest, store, rng)
-                        .await?;
+                let mut file = PrivateFile::new(&dir.header.name, time, rng);
+                file.content.content = PrivateFile::prepare_content(
+                    &file.header.name,
+                    content,
+                    options,
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+                content_type.apply(&mut file.content.metadata);
                 let link = PrivateLink::with_file(file);
                 dir.content.entries.insert(filename.to_string(), link);
             }
@@ -785,6 +918,105 @@ impl PrivateDirectory {
         Ok(())
     }
 
+    /// Updates the metadata of the node at given path without touching its content.
+    ///
+    /// The node is advanced to its next revision, but only its header and metadata
+    /// change, so file content that is stored in the private forest is reused as-is
+    /// instead of being re-encrypted. An empty path updates this directory's metadata.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use libipld_core::ipld::Ipld;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let path = &["code".into(), "hello.py".into()];
+    ///
+    ///     root_dir
+    ///         .write(path, true, Utc::now(), b"print('hello world')".to_vec(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     root_dir
+    ///         .update_metadata(
+    ///             path,
+    ///             true,
+    ///             |metadata| {
+    ///                 metadata.put("language", Ipld::String("python".into()));
+    ///             },
+    ///             forest,
+    ///             store,
+    ///         )
+    ///         .await?;
+    ///
+    ///     let file = root_dir
+    ///         .get_node(path, true, forest, store)
+    ///         .await?
+    ///         .unwrap()
+    ///         .as_file()?;
+    ///
+    ///     assert_eq!(
+    ///         file.get_metadata().get("language"),
+    ///         Some(&Ipld::String("python".into()))
+    ///     );
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn update_metadata(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        update: impl FnOnce(&mut Metadata),
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let Some((node_name, path)) = path_segments.split_last() else {
+            if search_latest {
+                *self = self.clone().search_latest_reconciled(forest, store).await?;
+            }
+
+            update(&mut self.prepare_next_revision()?.content.metadata);
+            return Ok(());
+        };
+
+        let dir = match self
+            .get_leaf_dir_mut(path, search_latest, forest, store)
+            .await?
+        {
+            SearchResult::Found(dir) => dir,
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            SearchResult::Missing(_, _) => bail!(FsError::NotFound),
+        };
+
+        match dir
+            .lookup_node_mut(node_name, search_latest, forest, store)
+            .await?
+        {
+            Some(PrivateNode::File(file)) => {
+                update(&mut file.prepare_next_revision()?.content.metadata);
+            }
+            Some(PrivateNode::Dir(dir)) => {
+                update(&mut dir.prepare_next_revision()?.content.metadata);
+            }
+            None => bail!(FsError::NotFound),
+        }
+
+        Ok(())
+    }
+
     /// Gets the latest version of the directory using exponential search.
     ///
     /// # Examples
@@ -974,9 +1206,11 @@ impl PrivateDirectory {
         {
             SearchResult::Found(dir) => {
                 let mut result = vec![];
-                for (name, link) in dir.content.entries.iter() {
+                for (name, link) in
+                    crate::utils::iter_in_order(&dir.content.entries, &dir.content.order)
+                {
                     match link
-                        .resolve_node(forest, store, Some(dir.header.name.clone()))
+                        .resolve_node(forest, store, Some(dir.child_parent_name(name)))
                         .await?
                     {
                         PrivateNode::File(file) => {
@@ -994,12 +1228,171 @@ impl PrivateDirectory {
         }
     }
 
+    /// Returns names and metadata of directory's immediate children, like
+    /// [PrivateDirectory::ls], together with the CIDs of the blocks they are stored in.
+    ///
+    /// Children that were changed but haven't been stored since have no CIDs yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::{BlockStore, MemoryBlockStore},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     dir.write(&["notes.md".into()], true, Utc::now(), b"hi".to_vec(), forest, store, rng).await?;
+    ///     dir.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let entries = dir.ls_with_cids(&[], true, forest, store).await?;
+    ///     let (name, _, cids) = &entries[0];
+    ///     let cids = cids.expect("stored entries have CIDs");
+    ///
+    ///     assert_eq!(name, "notes.md");
+    ///     assert!(store.has_block(&cids.content).await?);
+    ///     assert!(store.has_block(&cids.header).await?);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn ls_with_cids(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata, Option<PrivateNodeCids>)>> {
+        match self
+            .get_leaf_dir(path_segments, search_latest, forest, store)
+            .await?
+        {
+            SearchResult::Found(dir) => {
+                let mut result = vec![];
+                for (name, link) in
+                    crate::utils::iter_in_order(&dir.content.entries, &dir.content.order)
+                {
+                    let node = link
+                        .resolve_node(forest, store, Some(dir.child_parent_name(name)))
+                        .await?;
+                    let cids = match node.get_persisted_as().get() {
+                        Some(content) => Some(PrivateNodeCids {
+                            content: *content,
+                            header: node.get_header().compute_cid(store, forest)?,
+                        }),
+                        None => None,
+                    };
+
+                    let metadata = match node {
+                        PrivateNode::File(file) => file.content.metadata.clone(),
+                        PrivateNode::Dir(dir) => dir.content.metadata.clone(),
+                    };
+
+                    result.push((name.clone(), metadata, cids));
+                }
+                Ok(result)
+            }
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            _ => bail!(FsError::NotFound),
+        }
+    }
+
     /// Get the names of directory's immediate children.
     ///
     /// Other than [PrivateDirectory::ls] this returns only the names, without loading the
     /// metadata for each node from the store.
     pub fn get_entries<'a>(self: &'a Arc<Self>) -> impl Iterator<Item = &'a String> {
-        self.content.entries.iter().map(|x| x.0)
+        crate::utils::iter_in_order(&self.content.entries, &self.content.order).map(|x| x.0)
+    }
+
+    /// Returns the order this directory's entries are listed in, as set with
+    /// [PrivateDirectory::reorder].
+    ///
+    /// Empty if the entries are listed in lexicographic order.
+    pub fn get_entry_order(&self) -> &[String] {
+        &self.content.order
+    }
+
+    /// Sets the order in which [PrivateDirectory::ls] lists the entries of the directory at given path.
+    ///
+    /// The order may mention only some of the entries. All others, including any added later,
+    /// are listed after them in lexicographic order. Passing an empty order restores
+    /// lexicographic listing, and removing an entry also removes it from the order.
+    ///
+    /// When concurrent reorders get merged, the latest one wins. Reorders made equally
+    /// often on both sides are tie-broken by the lexicographically greater order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let playlist = &["playlist".into()];
+    ///
+    ///     for song in ["b-side.mp3", "opener.mp3"] {
+    ///         dir.write(&["playlist".into(), song.into()], true, Utc::now(), vec![], forest, store, rng)
+    ///             .await?;
+    ///     }
+    ///
+    ///     dir.reorder(playlist, vec!["opener.mp3".into()], true, Utc::now(), forest, store)
+    ///         .await?;
+    ///
+    ///     let songs = dir.ls(playlist, true, forest, store).await?;
+    ///     assert_eq!(
+    ///         songs.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(),
+    ///         ["opener.mp3", "b-side.mp3"]
+    ///     );
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn reorder(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        order: Vec<String>,
+        search_latest: bool,
+        time: DateTime<Utc>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let dir = match self
+            .get_leaf_dir_mut(path_segments, search_latest, forest, store)
+            .await?
+        {
+            SearchResult::Found(dir) => dir,
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            SearchResult::Missing(_, _) => bail!(FsError::NotFound),
+        };
+
+        crate::utils::check_order(&dir.content.entries, &order)?;
+        dir.content.order = order;
+        dir.content.order_revision += 1;
+        dir.content.metadata.upsert_mtime(time);
+
+        Ok(())
     }
 
     /// Removes a file or directory from the directory.
@@ -1073,32 +1466,126 @@ impl PrivateDirectory {
             bail!(FsError::NotFound)
         };
 
+        let parent_name = Some(dir.child_parent_name(node_name));
         let removed_node = match dir.content.entries.remove(node_name) {
-            Some(link) => {
-                link.resolve_owned_node(forest, store, Some(dir.header.name.clone()))
-                    .await?
-            }
+            Some(link) => link.resolve_owned_node(forest, store, parent_name).await?,
             None => bail!(FsError::NotFound),
         };
 
+        dir.content.order.retain(|name| name != node_name);
+        dir.content.pending_ancestry.remove(node_name);
+
         Ok(removed_node)
     }
 
-    /// Attaches a node to the specified directory.
+    /// Removes all entries of the directory at given path whose names match any of the given
+    /// patterns, returning the removed nodes with their names.
     ///
-    /// Fixes up the subtree bare names to refer to the new parent.
-    #[allow(clippy::too_many_arguments)]
-    async fn attach(
-        self: &mut Arc<Self>,
-        mut node: PrivateNode,
-        path_segments: &[String],
-        search_latest: bool,
-        time: DateTime<Utc>,
-        forest: &mut impl PrivateForest,
-        store: &impl BlockStore,
-        rng: &mut (impl CryptoRngCore + CondSend),
-    ) -> Result<()> {
-        let (path, node_name) = crate::utils::split_last(path_segments)?;
+    /// Patterns are globs matched against whole entry names: `*` matches any sequence of
+    /// characters and `?` any single character, so e.g. `draft-*` removes everything with
+    /// a `draft-` prefix. The directory and its ancestors advance by one revision in total,
+    /// no matter how many entries get removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     for name in ["cat.jpg", "dog.jpg", "notes.md"] {
+    ///         root_dir
+    ///             .write(&["pictures".into(), name.into()], true, Utc::now(), vec![], forest, store, rng)
+    ///             .await?;
+    ///     }
+    ///
+    ///     let removed = root_dir
+    ///         .rm_many(&["pictures".into()], &["*.jpg".into()], true, forest, store)
+    ///         .await?;
+    ///
+    ///     assert_eq!(removed.len(), 2);
+    ///
+    ///     let result = root_dir.ls(&["pictures".into()], true, forest, store).await?;
+    ///
+    ///     assert_eq!(result.len(), 1);
+    ///     assert_eq!(result[0].0, "notes.md");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn rm_many(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        patterns: &[String],
+        search_latest: bool,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, PrivateNode)>> {
+        let SearchResult::Found(dir) = self
+            .get_leaf_dir_mut(path_segments, search_latest, forest, store)
+            .await?
+        else {
+            bail!(FsError::NotFound)
+        };
+
+        let matching = crate::utils::iter_in_order(&dir.content.entries, &dir.content.order)
+            .map(|(name, _)| name)
+            .filter(|name| {
+                patterns
+                    .iter()
+                    .any(|pattern| crate::utils::matches_glob(pattern, name))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut removed = Vec::with_capacity(matching.len());
+        for name in matching {
+            let parent_name = Some(dir.child_parent_name(&name));
+            let Some(link) = dir.content.entries.remove(&name) else {
+                continue;
+            };
+
+            let node = link.resolve_owned_node(forest, store, parent_name).await?;
+            dir.content.pending_ancestry.remove(&name);
+            removed.push((name, node));
+        }
+
+        let entries = &dir.content.entries;
+        dir.content.order.retain(|name| entries.contains_key(name));
+
+        Ok(removed)
+    }
+
+    /// Attaches a node to the specified directory.
+    ///
+    /// Fixes up the subtree bare names to refer to the new parent. If `lazy_ancestry` is set,
+    /// only the node itself is fixed up right away, and its descendants once they're modified.
+    #[allow(clippy::too_many_arguments)]
+    async fn attach(
+        self: &mut Arc<Self>,
+        mut node: PrivateNode,
+        path_segments: &[String],
+        search_latest: bool,
+        time: DateTime<Utc>,
+        lazy_ancestry: bool,
+        progress: &impl ProgressReporter,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let (path, node_name) = crate::utils::split_last(path_segments)?;
         let SearchResult::Found(dir) = self
             .get_leaf_dir_mut(path, search_latest, forest, store)
             .await?
@@ -1112,8 +1599,21 @@ impl PrivateDirectory {
         );
 
         node.upsert_mtime(time);
-        node.update_ancestry(&dir.header.name, forest, store, rng)
+        if lazy_ancestry {
+            node.update_ancestry_lazily(&dir.header.name, rng);
+        } else {
+            let total = node.count_nodes(forest, store).await?;
+            node.update_ancestry(
+                &dir.header.name,
+                progress,
+                &mut 0,
+                total,
+                forest,
+                store,
+                rng,
+            )
             .await?;
+        }
 
         dir.content
             .entries
@@ -1124,6 +1624,11 @@ impl PrivateDirectory {
 
     /// Moves a file or directory from one path to another.
     ///
+    /// The moved node gets new keys and a name under its new parent right away, but its
+    /// descendants only once they're modified, so moving a large directory stays cheap.
+    /// Until then, they can still be read with the keys they had before the move.
+    /// Moved nodes keep their [inode numbers](PrivateNode::inode).
+    ///
     /// # Examples
     ///
     /// ```
@@ -1200,6 +1705,8 @@ impl PrivateDirectory {
             path_segments_to,
             search_latest,
             time,
+            true,
+            &(),
             forest,
             store,
             rng,
@@ -1276,6 +1783,85 @@ impl PrivateDirectory {
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        self.cp_with_progress(
+            path_segments_from,
+            path_segments_to,
+            search_latest,
+            time,
+            &(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+    }
+
+    /// Copies a file or directory from one path to another, like [`cp`][Self::cp],
+    /// reporting the number of nodes in the copied subtree that were processed so far
+    /// to `progress`, along with the total number of nodes in the subtree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     for name in ["one.txt", "two.txt"] {
+    ///         root_dir
+    ///             .write(&["code".into(), name.into()], true, Utc::now(), vec![], forest, store, rng)
+    ///             .await?;
+    ///     }
+    ///
+    ///     let copied_nodes = AtomicU64::new(0);
+    ///     let progress = |done, total| {
+    ///         assert_eq!(total, Some(3));
+    ///         copied_nodes.store(done, Ordering::Relaxed);
+    ///     };
+    ///
+    ///     root_dir
+    ///         .cp_with_progress(
+    ///             &["code".into()],
+    ///             &["backup".into()],
+    ///             true,
+    ///             Utc::now(),
+    ///             &progress,
+    ///             forest,
+    ///             store,
+    ///             rng,
+    ///         )
+    ///         .await?;
+    ///
+    ///     assert_eq!(copied_nodes.load(Ordering::Relaxed), 3);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn cp_with_progress(
+        self: &mut Arc<Self>,
+        path_segments_from: &[String],
+        path_segments_to: &[String],
+        search_latest: bool,
+        time: DateTime<Utc>,
+        progress: &impl ProgressReporter,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
     ) -> Result<()> {
         let result = self
             .get_node(path_segments_from, search_latest, forest, store)
@@ -1286,6 +1872,8 @@ impl PrivateDirectory {
             path_segments_to,
             search_latest,
             time,
+            false,
+            progress,
             forest,
             store,
             rng,
@@ -1293,6 +1881,98 @@ impl PrivateDirectory {
         .await
     }
 
+    /// Instantiates the template subtree at one path as a new subtree at another,
+    /// e.g. for creating a new project from a project template.
+    ///
+    /// Every node of the instance gets fresh keys and inode numbers, starts without
+    /// history and is created at `time`. File content isn't copied: the instance shares
+    /// the encrypted content blocks of the template, so instantiating a template only
+    /// costs as much as its directory structure, regardless of the size of its files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let template = &["templates".into(), "rust".into()];
+    ///     let project = &["projects".into(), "hello".into()];
+    ///
+    ///     root_dir
+    ///         .write(
+    ///             &["templates".into(), "rust".into(), "main.rs".into()],
+    ///             true,
+    ///             Utc::now(),
+    ///             b"fn main() {}".to_vec(),
+    ///             forest,
+    ///             store,
+    ///             rng,
+    ///         )
+    ///         .await?;
+    ///     root_dir.mkdir(&["projects".into()], true, Utc::now(), forest, store, rng).await?;
+    ///
+    ///     root_dir
+    ///         .cp_template(template, project, true, Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let main = root_dir
+    ///         .read(&["projects".into(), "hello".into(), "main.rs".into()], true, forest, store)
+    ///         .await?;
+    ///     assert_eq!(main, b"fn main() {}");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn cp_template(
+        self: &mut Arc<Self>,
+        path_segments_from: &[String],
+        path_segments_to: &[String],
+        search_latest: bool,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let mut node = self
+            .get_node(path_segments_from, search_latest, forest, store)
+            .await?
+            .ok_or(FsError::NotFound)?;
+
+        let (path, node_name) = crate::utils::split_last(path_segments_to)?;
+        let SearchResult::Found(dir) = self
+            .get_leaf_dir_mut(path, search_latest, forest, store)
+            .await?
+        else {
+            bail!(FsError::NotFound);
+        };
+
+        ensure!(
+            !dir.content.entries.contains_key(node_name),
+            FsError::FileAlreadyExists
+        );
+
+        node.instantiate_template(&dir.header.name, time, forest, store, rng)
+            .await?;
+        dir.content
+            .entries
+            .insert(node_name.clone(), PrivateLink::from(node));
+
+        Ok(())
+    }
+
     /// Stores this PrivateDirectory in the PrivateForest.
     pub(crate) async fn store(
         &self,
@@ -1301,12 +1981,20 @@ impl PrivateDirectory {
         rng: &mut (impl CryptoRngCore + CondSend),
     ) -> Result<PrivateRef> {
         let header_cid = self.header.store(store, forest).await?;
-        let temporal_key = self.header.derive_temporal_key();
+        let revision_ref = self.header.derive_revision_ref(forest);
         let name_with_revision = self.header.get_revision_name();
 
         let content_cid = self
             .content
-            .store(header_cid, &temporal_key, forest, store, rng)
+            .store(
+                header_cid,
+                &self.header.name,
+                &revision_ref.temporal_key,
+                &revision_ref.label,
+                forest,
+                store,
+                rng,
+            )
             .await?;
 
         forest
@@ -1317,10 +2005,7 @@ impl PrivateDirectory {
             )
             .await?;
 
-        Ok(self
-            .header
-            .derive_revision_ref(forest)
-            .into_private_ref(content_cid))
+        Ok(revision_ref.into_private_ref(content_cid))
     }
 
     /// Creates a new [`PrivateDirectory`] from a [`PrivateDirectoryContentSerializable`].
@@ -1332,7 +2017,10 @@ impl PrivateDirectory {
         store: &impl BlockStore,
         parent_name: Option<Name>,
     ) -> Result<Self> {
-        if !is_readable_wnfs_version(&serializable.version) {
+        let readable = is_readable_wnfs_version(&serializable.version)
+            || forest.accepts_unbound_ciphertexts()
+                && is_legacy_wnfs_version(&serializable.version);
+        if !readable {
             bail!(FsError::UnexpectedVersion(serializable.version));
         }
 
@@ -1348,6 +2036,20 @@ impl PrivateDirectory {
             metadata: serializable.metadata,
             previous: serializable.previous.into_iter().collect(),
             entries: entries_decrypted,
+            order: serializable.order,
+            order_revision: serializable.order_revision,
+            pending_ancestry: serializable
+                .pending_ancestry
+                .into_iter()
+                .map(|(name, pending)| {
+                    let pending = PendingAncestry {
+                        seed: pending.seed,
+                        parent_name: Name::new(pending.parent_name, []),
+                    };
+                    (name, pending)
+                })
+                .collect(),
+            overflow_cache: serializable.overflow_cache,
         };
 
         let header = PrivateNodeHeader::load(
@@ -1391,12 +2093,10 @@ impl PrivateDirectory {
 
         let other_ratchet_diff = target_header.ratchet_diff_for_merge(&other.header)?;
 
-        let parent_name = Some(self.header.name.clone());
-
         let our = self.prepare_next_merge(our_cid, target_header)?;
 
-        if our.content.previous.len() > 1 {
-            // This is a merge node. We'll just add its previous links.
+        if other.content.previous.len() > 1 {
+            // The other node is a merge node. We'll just add its previous links.
             our.content.previous.extend(
                 other
                     .content
@@ -1417,11 +2117,22 @@ impl PrivateDirectory {
         our.content
             .metadata
             .tie_break_with(&other.content.metadata)?;
+        crate::utils::merge_order(
+            &mut our.content.order,
+            &mut our.content.order_revision,
+            &other.content.order,
+            other.content.order_revision,
+        );
 
+        // Entries where we take the other link, along with whether its ancestry update is pending
+        let mut adopted = Vec::new();
         for (name, other_link) in other.content.entries.iter() {
+            let our_parent_name = Some(our.child_parent_name(name));
+            let other_parent_name = Some(other.child_parent_name(name));
             match our.content.entries.entry(name.clone()) {
                 Entry::Vacant(vacant) => {
                     vacant.insert(other_link.clone());
+                    adopted.push(name);
                 }
                 Entry::Occupied(mut occupied) => {
                     let our_link = occupied.get_mut();
@@ -1440,11 +2151,11 @@ impl PrivateDirectory {
                         // there's nothing for us to do, they're equal
                     } else {
                         let our_node = our_link
-                            .resolve_node_mut(forest, store, parent_name.clone())
+                            .resolve_node_mut(forest, store, our_parent_name)
                             .await?;
 
                         let other_node = other_link
-                            .resolve_node(forest, store, parent_name.clone())
+                            .resolve_node(forest, store, other_parent_name)
                             .await?;
 
                         match (our_node, other_node) {
@@ -1454,12 +2165,14 @@ impl PrivateDirectory {
                             (PrivateNode::File(_), PrivateNode::Dir(_)) => {
                                 // a directory wins over a file
                                 our_link.clone_from(other_link);
+                                adopted.push(name);
                             }
                             // file vs. file and dir vs. dir cases
                             _ => {
                                 // We tie-break as usual
                                 if ord == Ordering::Greater {
                                     our_link.clone_from(other_link);
+                                    adopted.push(name);
                                 }
                             }
                         }
@@ -1468,6 +2181,19 @@ impl PrivateDirectory {
             }
         }
 
+        for name in adopted {
+            match other.content.pending_ancestry.get(name) {
+                Some(pending) => {
+                    our.content
+                        .pending_ancestry
+                        .insert(name.clone(), pending.clone());
+                }
+                None => {
+                    our.content.pending_ancestry.remove(name);
+                }
+            }
+        }
+
         Ok(())
     }
 }
@@ -1485,16 +2211,25 @@ impl Debug for PrivateDirectoryContent {
             .field("previous", &self.previous)
             .field("metadata", &self.metadata)
             .field("entries", &self.entries)
+            .field("order", &self.order)
+            .field("order_revision", &self.order_revision)
+            .field("pending_ancestry", &self.pending_ancestry)
             .finish()
     }
 }
 
 impl PrivateDirectoryContent {
     /// Serializes the directory to dag-cbor.
+    ///
+    /// Large metadata values are stored in blocks of their own, see
+    /// [`METADATA_OVERFLOW_THRESHOLD`](crate::private::METADATA_OVERFLOW_THRESHOLD).
+    /// So are the entries of directories that don't fit into a single block of the store,
+    /// see [`BlockStore::max_block_size`].
     pub(crate) async fn to_dag_cbor(
         &self,
         temporal_key: &TemporalKey,
         header_cid: Cid,
+        name: &Name,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut (impl CryptoRngCore + CondSend),
@@ -1509,14 +2244,48 @@ impl PrivateDirectoryContent {
             entries.insert(name.clone(), private_ref_serializable);
         }
 
+        let snapshot_key = temporal_key.derive_snapshot_key();
+        let mut writer = OverflowWriter::new(name, &snapshot_key, &self.overflow_cache);
+        let (metadata, metadata_overflow) =
+            overflow_metadata(&self.metadata, &mut writer, forest, store, rng).await?;
+
+        let mut serializable = PrivateDirectoryContentSerializable {
+            version: WNFS_VERSION,
+            previous: self.previous.iter().cloned().collect(),
+            header_cid,
+            metadata,
+            metadata_overflow,
+            entries,
+            entries_overflow: Vec::new(),
+            order: self.order.clone(),
+            order_revision: self.order_revision,
+            pending_ancestry: self
+                .pending_ancestry
+                .iter()
+                .map(|(name, pending)| {
+                    let pending = PendingAncestrySerializable {
+                        seed: pending.seed.clone(),
+                        parent_name: forest.get_accumulated_name(&pending.parent_name),
+                    };
+                    (name.clone(), pending)
+                })
+                .collect(),
+            overflow_cache: OverflowCache::default(),
+        };
+
+        let bytes =
+            serde_ipld_dagcbor::to_vec(&PrivateNodeContentSerializable::Dir(serializable.clone()))?;
+        if bytes.len() + ENCRYPTION_OVERHEAD <= store.max_block_size() {
+            writer.finish(&self.overflow_cache);
+            return Ok(bytes);
+        }
+
+        serializable.entries_overflow =
+            overflow_entries(&serializable.entries, &mut writer, forest, store, rng).await?;
+        serializable.entries.clear();
+        writer.finish(&self.overflow_cache);
         Ok(serde_ipld_dagcbor::to_vec(
-            &PrivateNodeContentSerializable::Dir(PrivateDirectoryContentSerializable {
-                version: WNFS_VERSION,
-                previous: self.previous.iter().cloned().collect(),
-                header_cid,
-                metadata: self.metadata.clone(),
-                entries,
-            }),
+            &PrivateNodeContentSerializable::Dir(serializable),
         )?)
     }
 
@@ -1530,11 +2299,13 @@ impl PrivateDirectoryContent {
     ///
     /// The header cid is required as it's not stored in the PrivateDirectoryContent itself, but
     /// stored in the serialized format.
-    #[allow(clippy::suspicious)]
+    #[allow(clippy::suspicious, clippy::too_many_arguments)]
     pub(crate) async fn store(
         &self,
         header_cid: Cid,
+        name: &Name,
         temporal_key: &TemporalKey,
+        label: &HashOutput,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut (impl CryptoRngCore + CondSend),
@@ -1547,11 +2318,17 @@ impl PrivateDirectoryContent {
 
                 // Serialize node to cbor.
                 let bytes = self
-                    .to_dag_cbor(temporal_key, header_cid, forest, store, rng)
+                    .to_dag_cbor(temporal_key, header_cid, name, forest, store, rng)
                     .await?;
 
-                // Encrypt bytes with snapshot key.
-                let block = snapshot_key.encrypt(&bytes, rng)?;
+                // Encrypt bytes with snapshot key, bound to the label it's stored under.
+                let deterministic = forest.uses_deterministic_nonces();
+                let block = snapshot_key.encrypt_with_aad_deterministic_if(
+                    &bytes,
+                    label,
+                    deterministic,
+                    rng,
+                )?;
 
                 // Store content section in blockstore and get Cid.
                 Ok(store.put_block(block, CODEC_RAW).await?)
@@ -1565,6 +2342,9 @@ impl PartialEq for PrivateDirectoryContent {
         self.previous == other.previous
             && self.metadata == other.metadata
             && self.entries == other.entries
+            && self.order == other.order
+            && self.order_revision == other.order_revision
+            && self.pending_ancestry == other.pending_ancestry
     }
 }
 
@@ -1580,6 +2360,10 @@ impl Clone for PrivateDirectoryContent {
             previous: self.previous.clone(),
             metadata: self.metadata.clone(),
             entries: self.entries.clone(),
+            order: self.order.clone(),
+            order_revision: self.order_revision,
+            pending_ancestry: self.pending_ancestry.clone(),
+            overflow_cache: self.overflow_cache.clone(),
         }
     }
 }
@@ -1597,7 +2381,9 @@ impl Id for PrivateDirectory {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::private::forest::hamt::HamtForest;
+    use crate::private::{file::FileContent, forest::hamt::HamtForest, Compression};
+    use chrono::TimeZone;
+    use libipld_core::ipld::Ipld;
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
     use test_log::test;
@@ -1807,6 +2593,40 @@ mod tests {
         assert_eq!(result[1].0, String::from("puppy.jpg"));
     }
 
+    #[test(async_std::test)]
+    async fn ls_with_cids_returns_the_blocks_of_stored_children() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root_dir
+            .mkdir(&["stored".into()], true, Utc::now(), forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+        root_dir
+            .mkdir(&["unstored".into()], true, Utc::now(), forest, store, rng)
+            .await?;
+
+        let result = root_dir.ls_with_cids(&[], true, forest, store).await?;
+
+        assert_eq!(result.len(), 2);
+        assert!(result[1].2.is_none());
+
+        let cids = result[0].2.unwrap();
+        let stored = root_dir
+            .get_node(&["stored".into()], false, forest, store)
+            .await?
+            .unwrap();
+        let revision_cids = forest
+            .get_encrypted(&stored.get_header().get_revision_name(), store)
+            .await?
+            .unwrap();
+        assert_eq!(revision_cids, BTreeSet::from([cids.content, cids.header]));
+
+        Ok(())
+    }
+
     #[test(async_std::test)]
     async fn rm_can_remove_children_from_directory() {
         let rng = &mut ChaCha12Rng::seed_from_u64(0);
@@ -1864,16 +2684,67 @@ mod tests {
     }
 
     #[async_std::test]
-    async fn read_can_fetch_userland_of_file_added_to_directory() {
+    async fn rm_many_removes_matching_entries_in_one_revision() -> TestResult {
         let rng = &mut ChaCha12Rng::seed_from_u64(0);
-        let store = &MemoryBlockStore::default();
+        let store = &MemoryBlockStore::new();
         let forest = &mut HamtForest::new_rsa_2048_rc(rng);
         let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let drafts = &["drafts".into()];
 
-        root_dir
-            .write(
-                &["text.txt".into()],
-                true,
+        for name in ["draft-1.md", "draft-2.md", "final.md", "draft-notes.txt"] {
+            root_dir
+                .write(
+                    &["drafts".into(), name.into()],
+                    true,
+                    Utc::now(),
+                    vec![],
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+        }
+        root_dir.as_node().store(forest, store, rng).await?;
+        let revision = root_dir.header.revision;
+
+        let removed = root_dir
+            .rm_many(
+                drafts,
+                &["draft-*.md".into(), "*.txt".into()],
+                true,
+                forest,
+                store,
+            )
+            .await?;
+
+        let removed_names = removed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            removed_names,
+            ["draft-1.md", "draft-2.md", "draft-notes.txt"]
+        );
+        assert_eq!(root_dir.header.revision, revision + 1);
+
+        let remaining = root_dir.ls(drafts, true, forest, store).await?;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].0, "final.md");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn read_can_fetch_userland_of_file_added_to_directory() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root_dir
+            .write(
+                &["text.txt".into()],
+                true,
                 Utc::now(),
                 b"text".to_vec(),
                 forest,
@@ -2131,6 +3002,345 @@ mod tests {
         assert!(!cats_name_segments.contains(&pictures_dir_inumber));
     }
 
+    #[async_std::test]
+    async fn mv_updates_ancestry_of_descendants_once_they_are_modified() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let old_path = &["pictures".into(), "cats".into(), "tabby.jpg".into()];
+        let new_path = &["images".into(), "cats".into(), "tabby.jpg".into()];
+
+        root_dir
+            .write(
+                old_path,
+                true,
+                Utc::now(),
+                b"tabby".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .mkdir(&["images".into()], true, Utc::now(), forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let inumber_before = root_dir
+            .get_node(old_path, true, forest, store)
+            .await?
+            .unwrap()
+            .get_header()
+            .inumber
+            .clone();
+
+        root_dir
+            .basic_mv(
+                &["pictures".into(), "cats".into()],
+                &["images".into(), "cats".into()],
+                true,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        // The moved file wasn't touched, but is still readable after reloading
+        let root_dir = &mut PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let tabby = root_dir.get_node(new_path, true, forest, store).await?;
+        assert_eq!(tabby.unwrap().get_header().inumber, inumber_before);
+        assert_eq!(
+            root_dir.read(new_path, true, forest, store).await?,
+            b"tabby"
+        );
+
+        // Modifying it moves it under its new parent
+        root_dir
+            .write(
+                new_path,
+                true,
+                Utc::now(),
+                b"luna".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        let root_dir = &mut PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let cats = root_dir
+            .get_node(&["images".into(), "cats".into()], true, forest, store)
+            .await?
+            .unwrap()
+            .as_dir()?;
+        assert!(cats.content.pending_ancestry.is_empty());
+
+        let tabby = cats.lookup_node("tabby.jpg", true, forest, store).await?;
+        let tabby_name_segments = tabby.unwrap().get_header().name.get_segments().clone();
+        assert!(tabby_name_segments.contains(&cats.header.inumber));
+        assert_eq!(root_dir.read(new_path, true, forest, store).await?, b"luna");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn mv_keeps_names_of_descendants_until_they_are_modified() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let old_path = &["pictures".into(), "cats".into(), "tabby.jpg".into()];
+        let new_path = &["images".into(), "cats".into(), "tabby.jpg".into()];
+
+        root_dir
+            .write(old_path, true, Utc::now(), vec![], forest, store, rng)
+            .await?;
+        root_dir
+            .mkdir(&["images".into()], true, Utc::now(), forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let tabby = root_dir.get_node(old_path, true, forest, store).await?;
+        let name_before = forest.get_accumulated_name(&tabby.unwrap().get_header().name);
+
+        root_dir
+            .basic_mv(
+                &["pictures".into(), "cats".into()],
+                &["images".into(), "cats".into()],
+                true,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        let root_dir = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let tabby = root_dir.get_node(new_path, true, forest, store).await?;
+        let tabby_name = forest.get_accumulated_name(&tabby.unwrap().get_header().name);
+        assert_eq!(tabby_name, name_before);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn mv_twice_keeps_descendants_readable_until_they_are_modified() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let old_path = &["pictures".into(), "cats".into(), "tabby.jpg".into()];
+        let new_path = &["photos".into(), "cats".into(), "tabby.jpg".into()];
+
+        root_dir
+            .write(
+                old_path,
+                true,
+                Utc::now(),
+                b"tabby".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        for dir in ["images", "photos"] {
+            root_dir
+                .mkdir(&[dir.into()], true, Utc::now(), forest, store, rng)
+                .await?;
+        }
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let tabby = root_dir.get_node(old_path, true, forest, store).await?;
+        let name_before = forest.get_accumulated_name(&tabby.unwrap().get_header().name);
+
+        for (from, to) in [("pictures", "images"), ("images", "photos")] {
+            root_dir
+                .basic_mv(
+                    &[from.into(), "cats".into()],
+                    &[to.into(), "cats".into()],
+                    true,
+                    Utc::now(),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+            root_dir.as_node().store(forest, store, rng).await?;
+        }
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        let root_dir = &mut PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let tabby = root_dir.get_node(new_path, true, forest, store).await?;
+        let tabby_name = forest.get_accumulated_name(&tabby.unwrap().get_header().name);
+        assert_eq!(tabby_name, name_before);
+        assert_eq!(
+            root_dir.read(new_path, true, forest, store).await?,
+            b"tabby"
+        );
+
+        root_dir
+            .write(
+                new_path,
+                true,
+                Utc::now(),
+                b"luna".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        let root_dir = &mut PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        assert_eq!(root_dir.read(new_path, true, forest, store).await?, b"luna");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn mv_keeps_inode_numbers_but_cp_does_not() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let old_path = &["pictures".into(), "cats".into(), "tabby.jpg".into()];
+        let new_path = &["kittens".into(), "tabby.jpg".into()];
+
+        root_dir
+            .write(old_path, true, Utc::now(), vec![], forest, store, rng)
+            .await?;
+        let cats = root_dir
+            .get_node(&old_path[..2], true, forest, store)
+            .await?;
+        let tabby = root_dir.get_node(old_path, true, forest, store).await?;
+        let (cats_inode, tabby_inode) = (cats.unwrap().inode(), tabby.unwrap().inode());
+
+        root_dir
+            .basic_mv(
+                &old_path[..2],
+                &["kittens".into()],
+                true,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        // Modifying the file updates its ancestry
+        root_dir
+            .write(new_path, true, Utc::now(), vec![], forest, store, rng)
+            .await?;
+
+        let kittens = root_dir
+            .get_node(&new_path[..1], true, forest, store)
+            .await?;
+        let tabby = root_dir.get_node(new_path, true, forest, store).await?;
+        assert_eq!(kittens.unwrap().inode(), cats_inode);
+        assert_eq!(tabby.unwrap().inode(), tabby_inode);
+
+        root_dir
+            .cp(
+                &new_path[..1],
+                &["cats".into()],
+                true,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+
+        let copy = root_dir
+            .get_node(&["cats".into()], true, forest, store)
+            .await?;
+        assert_ne!(copy.unwrap().inode(), cats_inode);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn template_instances_share_content_but_not_keys_or_history() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let template_path = &["templates".into(), "src".into(), "main.rs".into()];
+        let instance_path = &["project".into(), "src".into(), "main.rs".into()];
+        let content = vec![7u8; 100_000];
+
+        root_dir
+            .write(template_path, true, Utc::now(), vec![], forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+        root_dir
+            .write(
+                template_path,
+                true,
+                Utc::now(),
+                content.clone(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+
+        let time = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        root_dir
+            .cp_template(
+                &["templates".into()],
+                &["project".into()],
+                true,
+                time,
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+        let root_dir = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+
+        let template = root_dir
+            .get_node(template_path, true, forest, store)
+            .await?;
+        let instance = root_dir
+            .get_node(instance_path, true, forest, store)
+            .await?;
+        let (template, instance) = (template.unwrap().as_file()?, instance.unwrap().as_file()?);
+        let (FileContent::External(template_content), FileContent::External(instance_content)) =
+            (&template.content.content, &instance.content.content)
+        else {
+            panic!("Expected external content");
+        };
+
+        assert_eq!(instance_content, template_content);
+        assert_eq!(instance.get_content(forest, store).await?, content);
+        assert_ne!(instance.header.inumber, template.header.inumber);
+        assert_ne!(instance.header.get_name(), template.header.get_name());
+        assert!(!template.content.previous.is_empty());
+        assert!(instance.content.previous.is_empty());
+        assert_eq!(instance.get_metadata().get_created(), Some(time));
+        assert_eq!(instance.get_metadata().get_modified(), Some(time));
+
+        Ok(())
+    }
+
     #[async_std::test]
     async fn mv_cannot_move_sub_directory_to_invalid_location() {
         let rng = &mut ChaCha12Rng::from_entropy();
@@ -2480,6 +3690,99 @@ mod tests {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_conflict_reconciliation_of_concurrent_reorders() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut Arc::new(HamtForest::new_rsa_2048(rng));
+        let mut dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        for name in ["a", "b", "c"] {
+            dir.write(&[name.into()], true, Utc::now(), vec![], forest, store, rng)
+                .await?;
+        }
+        dir.store(forest, store, rng).await?;
+
+        let mut fork = Arc::clone(&dir);
+        let forest_fork = &mut Arc::clone(forest);
+
+        dir.reorder(
+            &[],
+            vec!["a".into(), "c".into()],
+            true,
+            Utc::now(),
+            forest,
+            store,
+        )
+        .await?;
+        dir.store(forest, store, rng).await?;
+
+        fork.reorder(
+            &[],
+            vec!["c".into(), "a".into()],
+            true,
+            Utc::now(),
+            forest_fork,
+            store,
+        )
+        .await?;
+        fork.store(forest_fork, store, rng).await?;
+
+        *forest = Arc::new(forest.merge(forest_fork, store).await?);
+        dir = dir.search_latest_reconciled(forest, store).await?;
+
+        let entries = dir.get_entries().cloned().collect::<Vec<_>>();
+        assert_eq!(entries, ["c", "a", "b"]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn merging_three_concurrent_heads_links_all_of_them() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_and_store(
+            &forest.empty_name(),
+            Utc::now(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        let mut heads = BTreeSet::new();
+        for name in ["first.txt", "second.txt", "third.txt"] {
+            let head = &mut Arc::clone(dir);
+            head.write(
+                &[name.into()],
+                false,
+                Utc::now(),
+                vec![],
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+            let access_key = head.as_node().store(forest, store, rng).await?;
+            heads.insert(*access_key.get_content_cid());
+        }
+
+        let merged = dir
+            .as_node()
+            .search_latest_reconciled(forest, store)
+            .await?;
+        let previous = merged
+            .get_previous()
+            .iter()
+            .filter_map(|(_, link)| link.get_value().copied())
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(previous, heads);
+        assert_eq!(merged.as_dir()?.get_entries().count(), 3);
+
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_conflict_reconciliation_concurrently_created_files() -> TestResult {
         let rng = &mut ChaCha12Rng::from_entropy();
@@ -2544,6 +3847,203 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn update_metadata_keeps_file_content() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["docs".into(), "notes.txt".into()];
+
+        root_dir
+            .write(
+                path,
+                true,
+                Utc::now(),
+                b"notes".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.store(forest, store, rng).await?;
+
+        let old_file = root_dir
+            .get_node(path, true, forest, store)
+            .await?
+            .ok_or(FsError::NotFound)?
+            .as_file()?;
+
+        root_dir
+            .update_metadata(
+                path,
+                true,
+                |metadata| {
+                    metadata.put("tag", Ipld::String("work".into()));
+                },
+                forest,
+                store,
+            )
+            .await?;
+        root_dir.store(forest, store, rng).await?;
+
+        let new_file = root_dir
+            .get_node(path, true, forest, store)
+            .await?
+            .ok_or(FsError::NotFound)?
+            .as_file()?;
+
+        assert_eq!(new_file.content.content, old_file.content.content);
+        assert_eq!(
+            new_file.get_metadata().get("tag"),
+            Some(&Ipld::String("work".into()))
+        );
+        assert_ne!(new_file.header.ratchet, old_file.header.ratchet);
+        assert_eq!(new_file.read_at(0, None, forest, store).await?, b"notes");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn update_metadata_fails_for_missing_nodes() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let result = root_dir
+            .update_metadata(&["missing".into()], true, |_| {}, forest, store)
+            .await;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::NotFound)
+        ));
+
+        let path = &["file.txt".into()];
+        root_dir
+            .write(
+                path,
+                true,
+                Utc::now(),
+                b"Hello".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+
+        let result = root_dir
+            .update_metadata(
+                &["file.txt".into(), "child".into()],
+                true,
+                |_| {},
+                forest,
+                store,
+            )
+            .await;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::NotADirectory)
+        ));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn write_with_sniffing_tracks_the_content_type() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["upload".into()];
+        let sniffing = &WriteOptions::sniffing();
+        let png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        root_dir
+            .write(path, true, Utc::now(), png.clone(), forest, store, rng)
+            .await?;
+        let file = root_dir
+            .get_node(path, true, forest, store)
+            .await?
+            .unwrap()
+            .as_file()?;
+        assert_eq!(file.get_metadata().get_content_type(), None);
+
+        root_dir
+            .write_with_options(path, true, Utc::now(), png, sniffing, forest, store, rng)
+            .await?;
+        let file = root_dir
+            .get_node(path, true, forest, store)
+            .await?
+            .unwrap()
+            .as_file()?;
+        assert_eq!(file.get_metadata().get_content_type(), Some("image/png"));
+
+        root_dir
+            .write_with_options(
+                path,
+                true,
+                Utc::now(),
+                b"plain text".to_vec(),
+                sniffing,
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let file = root_dir
+            .get_node(path, true, forest, store)
+            .await?
+            .unwrap()
+            .as_file()?;
+        assert_eq!(file.get_metadata().get_content_type(), None);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn write_with_compression_survives_store_and_load() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["log.txt".into()];
+        let content = b"GET /index.html 200\n".repeat(1000);
+        let options = &WriteOptions {
+            compression: Some(Compression::Deflate),
+            ..Default::default()
+        };
+
+        root_dir
+            .write_with_options(
+                path,
+                true,
+                Utc::now(),
+                content.clone(),
+                options,
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        let loaded = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let file = loaded
+            .get_node(path, true, forest, store)
+            .await?
+            .unwrap()
+            .as_file()?;
+        assert_eq!(file.get_compression(), Some(Compression::Deflate));
+        assert_eq!(loaded.read(path, true, forest, store).await?, content);
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -2594,4 +4094,52 @@ mod snapshot_tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn known_keys_decrypt_all_private_blocks_in_snapshots() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &mut SnapshotBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let time = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+
+        root_dir
+            .write(
+                &["music".into(), "jazz".into()],
+                true,
+                time,
+                b"Hello World".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .open_file_mut(&["events.log".into()], true, time, forest, store, rng)
+            .await?
+            .append_records([b"start".to_vec()], time, forest, store, rng)
+            .await?;
+
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+        let cid = forest.store(store).await?;
+
+        utils::add_known_keys(store, forest, &access_key).await?;
+
+        let mut kinds = Vec::new();
+        for snapshot in store.get_dag_snapshot(cid).await? {
+            if Cid::try_from(snapshot.cid.as_str())?.codec() != CODEC_RAW {
+                continue;
+            }
+
+            let value = snapshot.value.as_object().unwrap();
+            assert!(value.contains_key("label"));
+            kinds.extend(value.keys().filter(|key| *key != "label").cloned());
+        }
+
+        kinds.sort();
+        kinds.dedup();
+        assert_eq!(kinds, ["block", "content", "header"]);
+
+        Ok(())
+    }
 }
@@ -82,6 +82,19 @@ impl<T> Encrypted<T> {
         })
     }
 
+    /// Decrypts and deserializes the value inside the `Encrypted` wrapper using
+    /// given key, without looking at or filling the cache.
+    ///
+    /// Unlike `resolve_value`, this always fails for keys that don't decrypt the
+    /// ciphertext, even if the value was decrypted with the right key before.
+    pub fn decrypt_value(&self, temporal_key: &TemporalKey) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let bytes = temporal_key.key_wrap_decrypt(&self.ciphertext)?;
+        Ok(serde_ipld_dagcbor::from_slice(&bytes)?)
+    }
+
     /// Gets the ciphertext
     pub fn get_ciphertext(&self) -> &Vec<u8> {
         &self.ciphertext
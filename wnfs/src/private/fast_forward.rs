@@ -0,0 +1,269 @@
+//! Fast-forwarding whole subtrees of private nodes to their latest revisions.
+
+use super::{forest::traits::PrivateForest, link::PrivateLink, PrivateNode};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use libipld_core::cid::Cid;
+use std::collections::BTreeMap;
+use wnfs_common::{utils::Arc, BlockStore};
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateNode {
+    /// Resolves the latest revision of this node and of every node below it.
+    ///
+    /// This gives the same result as calling [`PrivateNode::search_latest_reconciled`]
+    /// on every node while walking the tree, but is a lot cheaper: Whether a node is at
+    /// its latest revision already is decided from the forest alone, without fetching and
+    /// decrypting it again, and nodes that are linked more than once are only resolved once.
+    ///
+    /// Directories that end up linking to newer revisions of their children than before
+    /// are advanced to a new, unstored revision, just like writing to them would.
+    /// Directories whose whole subtree was at its latest revision already are returned as-is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let path = &["docs".into(), "notes.md".into()];
+    ///
+    ///     root.write(path, true, Utc::now(), b"old".to_vec(), forest, store, rng).await?;
+    ///     root.as_node().store(forest, store, rng).await?;
+    ///     let stale = root.as_node();
+    ///
+    ///     root.write(path, true, Utc::now(), b"new".to_vec(), forest, store, rng).await?;
+    ///     root.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let latest = stale.search_latest_subtree(forest, store).await?.as_dir()?;
+    ///     assert_eq!(latest.read(path, false, forest, store).await?, b"new");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn search_latest_subtree(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<PrivateNode> {
+        search_latest_subtree(self, &mut BTreeMap::new(), forest, store).await
+    }
+
+    /// Whether this node is stored and neither has concurrent writes at its
+    /// revision nor any newer revisions.
+    ///
+    /// Only looks at the forest, never fetches any ciphertexts: Every stored revision
+    /// consists of a header and a content block, so a revision without concurrent
+    /// writes has exactly two CIDs, one of them being this node's content.
+    pub(crate) async fn is_known_latest(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        let Some(cid) = self.get_persisted_as().get() else {
+            return Ok(false);
+        };
+
+        let header = self.get_header();
+        match forest
+            .get_encrypted(&header.get_revision_name(), store)
+            .await?
+        {
+            Some(cids) if cids.len() == 2 && cids.contains(cid) => {}
+            _ => return Ok(false),
+        }
+
+        let mut next_header = header.clone();
+        next_header.advance_ratchet();
+        Ok(!forest.has(&next_header.get_revision_name(), store).await?)
+    }
+
+    fn is_same_node(&self, other: &PrivateNode) -> bool {
+        match (self, other) {
+            (Self::File(a), Self::File(b)) => Arc::ptr_eq(a, b),
+            (Self::Dir(a), Self::Dir(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn search_latest_subtree(
+    node: &PrivateNode,
+    resolved: &mut BTreeMap<Cid, PrivateNode>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<PrivateNode> {
+    let cid = node.get_persisted_as().get().cloned();
+    if let Some(latest) = cid.as_ref().and_then(|cid| resolved.get(cid)) {
+        return Ok(latest.clone());
+    }
+
+    let latest = if node.is_known_latest(forest, store).await? {
+        node.clone()
+    } else {
+        node.search_latest_reconciled(forest, store).await?
+    };
+
+    let latest = match latest {
+        PrivateNode::Dir(mut dir) => {
+            let mut updated_children = Vec::new();
+            for (name, link) in dir.content.entries.iter() {
+                let child = link
+                    .resolve_node(forest, store, Some(dir.child_parent_name(name)))
+                    .await?;
+                let latest_child = search_latest_subtree(child, resolved, forest, store).await?;
+                if !latest_child.is_same_node(child) {
+                    updated_children.push((name.clone(), latest_child));
+                }
+            }
+
+            if !updated_children.is_empty() {
+                let dir = dir.prepare_next_revision()?;
+                for (name, child) in updated_children {
+                    dir.content.entries.insert(name, PrivateLink::from(child));
+                }
+            }
+
+            PrivateNode::Dir(dir)
+        }
+        file => file,
+    };
+
+    if let Some(cid) = cid {
+        resolved.insert(cid, latest.clone());
+    }
+
+    Ok(latest)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::private::{
+        forest::{hamt::HamtForest, traits::PrivateForest},
+        PrivateDirectory, PrivateNode,
+    };
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn picks_up_writes_that_skipped_the_parent() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let file_path = &["a".into(), "b".into(), "file.txt".into()];
+
+        root.write(
+            file_path,
+            true,
+            Utc::now(),
+            b"v1".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        root.write(
+            &["other.txt".into()],
+            true,
+            Utc::now(),
+            b"other".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let root_access_key = root.as_node().store(forest, store, rng).await?;
+
+        // Someone with access to only `a/b` writes to it, without updating `a` or the root.
+        let mut b = root
+            .get_node(&["a".into(), "b".into()], false, forest, store)
+            .await?
+            .unwrap()
+            .as_dir()?;
+        b.write(
+            &["file.txt".into()],
+            true,
+            Utc::now(),
+            b"v2".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        b.as_node().store(forest, store, rng).await?;
+
+        let stale = PrivateNode::load(&root_access_key, forest, store, None).await?;
+        let stale = stale.as_dir()?;
+        assert_eq!(stale.read(file_path, false, forest, store).await?, b"v1");
+
+        let latest = stale
+            .as_node()
+            .search_latest_subtree(forest, store)
+            .await?
+            .as_dir()?;
+        assert_eq!(latest.read(file_path, false, forest, store).await?, b"v2");
+        assert_eq!(
+            latest
+                .read(&["other.txt".into()], false, forest, store)
+                .await?,
+            b"other"
+        );
+
+        // The root was advanced to link to the newer `a`, so it now needs storing
+        assert!(latest.content.persisted_as.get().is_none());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn leaves_latest_trees_untouched() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root.write(
+            &["a".into(), "file.txt".into()],
+            true,
+            Utc::now(),
+            b"v1".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let access_key = root.as_node().store(forest, store, rng).await?;
+        let loaded = PrivateNode::load(&access_key, forest, store, None).await?;
+
+        let latest = loaded.search_latest_subtree(forest, store).await?;
+
+        assert!(latest.is_same_node(&loaded));
+
+        Ok(())
+    }
+}
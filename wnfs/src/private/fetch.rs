@@ -0,0 +1,270 @@
+//! Fetching only the blocks needed to bring private nodes up to date from a remote store.
+
+use super::{forest::hamt::HamtForest, PrivateNode};
+use bytes::Bytes;
+use libipld_core::cid::Cid;
+use parking_lot::Mutex;
+use wnfs_common::{
+    utils::{verify_block, Arc, CondSend},
+    BlockStore, BlockStoreError, Storable,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A block store that reads from a local block store first, and fetches blocks it
+/// doesn't have from a remote block store.
+///
+/// Fetched blocks are checked against their CID and put into the local store, so
+/// they're only fetched once. New blocks are only put into the local store.
+///
+/// Running an operation against this store instead of syncing the remote store first
+/// fetches exactly the blocks that operation needs. [`PrivateNode::fetch_latest`] uses
+/// this to bring a node up to date with a remote forest.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::{common::{BlockStore, MemoryBlockStore, CODEC_RAW}, private::FetchingBlockStore};
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let (local, remote) = (MemoryBlockStore::new(), MemoryBlockStore::new());
+///     let cid = remote.put_block(b"hello".to_vec(), CODEC_RAW).await.unwrap();
+///
+///     let store = FetchingBlockStore::new(&local, &remote);
+///     assert_eq!(store.get_block(&cid).await.unwrap(), &b"hello"[..]);
+///     assert_eq!(store.fetched(), [cid]);
+///     assert!(local.has_block(&cid).await.unwrap());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FetchingBlockStore<L: BlockStore, R: BlockStore> {
+    local: L,
+    remote: R,
+    fetched: Mutex<Vec<Cid>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<L: BlockStore, R: BlockStore> FetchingBlockStore<L, R> {
+    /// Combines a local and a remote block store, without fetching anything yet.
+    pub fn new(local: L, remote: R) -> Self {
+        Self {
+            local,
+            remote,
+            fetched: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the local block store.
+    pub fn local(&self) -> &L {
+        &self.local
+    }
+
+    /// Returns the remote block store.
+    pub fn remote(&self) -> &R {
+        &self.remote
+    }
+
+    /// Returns the CIDs of all blocks fetched from the remote store so far, in fetch order.
+    pub fn fetched(&self) -> Vec<Cid> {
+        self.fetched.lock().clone()
+    }
+}
+
+impl<L: BlockStore, R: BlockStore> BlockStore for FetchingBlockStore<L, R> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        if self.local.has_block(cid).await? {
+            return self.local.get_block(cid).await;
+        }
+
+        let bytes = self.remote.get_block(cid).await?;
+        verify_block(cid, &bytes)?;
+        self.local.put_block_keyed(*cid, bytes.clone()).await?;
+        self.fetched.lock().push(*cid);
+        Ok(bytes)
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        self.local.put_block_keyed(cid, bytes).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        Ok(self.local.has_block(cid).await? || self.remote.has_block(cid).await?)
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.local.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.local.create_cid(bytes, codec)
+    }
+}
+
+impl PrivateNode {
+    /// Finds the latest revision of this node in a remote forest, fetching only the
+    /// blocks needed for that into the local store.
+    ///
+    /// This loads the forest root at `forest_cid` and searches for later revisions
+    /// like [`PrivateNode::search_latest_reconciled`], through a [`FetchingBlockStore`].
+    /// So instead of syncing the whole forest first, only the forest nodes on the
+    /// paths to this node's labels and the latest revision's blocks are fetched.
+    ///
+    /// Returns the latest revision together with the remote forest. Reading further
+    /// through a `FetchingBlockStore` keeps fetching blocks on demand.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     private::{forest::{hamt::HamtForest, traits::PrivateForest}, PrivateDirectory},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let (local, remote) = (&MemoryBlockStore::new(), &MemoryBlockStore::new());
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     dir.as_node().store(forest, remote, rng).await?;
+    ///     let stale = dir.as_node();
+    ///
+    ///     // Another device writes to the directory
+    ///     dir.write(&["hello.txt".into()], true, Utc::now(), b"Hi".to_vec(), forest, remote, rng).await?;
+    ///     dir.as_node().store(forest, remote, rng).await?;
+    ///     let forest_cid = forest.store(remote).await?;
+    ///
+    ///     let (latest, _) = stale.fetch_latest(&forest_cid, local, remote).await?;
+    ///     let entries = latest.as_dir()?.get_entries().cloned().collect::<Vec<_>>();
+    ///     assert_eq!(entries, ["hello.txt"]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn fetch_latest(
+        &self,
+        forest_cid: &Cid,
+        local: &impl BlockStore,
+        remote: &impl BlockStore,
+    ) -> anyhow::Result<(PrivateNode, Arc<HamtForest>)> {
+        let store = &FetchingBlockStore::new(local, remote);
+        let forest = Arc::new(HamtForest::load(forest_cid, store).await?);
+        let latest = self.search_latest_reconciled(&forest, store).await?;
+        Ok((latest, forest))
+    }
+
+    /// Like [`PrivateNode::fetch_latest`], but also brings all descendants that were
+    /// loaded into this node up to date, like [`PrivateNode::search_latest_subtree`].
+    pub async fn fetch_latest_subtree(
+        &self,
+        forest_cid: &Cid,
+        local: &impl BlockStore,
+        remote: &impl BlockStore,
+    ) -> anyhow::Result<(PrivateNode, Arc<HamtForest>)> {
+        let store = &FetchingBlockStore::new(local, remote);
+        let forest = Arc::new(HamtForest::load(forest_cid, store).await?);
+        let latest = self.search_latest_subtree(&forest, store).await?;
+        Ok((latest, forest))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::traits::PrivateForest, PrivateDirectory};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn fetching_the_latest_revision_skips_unrelated_blocks() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let (local, remote) = (&MemoryBlockStore::new(), &MemoryBlockStore::new());
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let notes_path = &["docs".into(), "notes.txt".into()];
+        let video_path = &["videos".into(), "talk.mp4".into()];
+        root_dir
+            .write(
+                notes_path,
+                true,
+                Utc::now(),
+                b"old".to_vec(),
+                forest,
+                remote,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, remote, rng).await?;
+        let stale_docs = root_dir
+            .get_node(&["docs".into()], true, forest, remote)
+            .await?
+            .unwrap();
+
+        root_dir
+            .write(
+                notes_path,
+                true,
+                Utc::now(),
+                b"new".to_vec(),
+                forest,
+                remote,
+                rng,
+            )
+            .await?;
+        root_dir
+            .write(
+                video_path,
+                true,
+                Utc::now(),
+                vec![7; 600_000],
+                forest,
+                remote,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, remote, rng).await?;
+        let forest_cid = forest.store(remote).await?;
+
+        let (latest_docs, fetched_forest) =
+            stale_docs.fetch_latest(&forest_cid, local, remote).await?;
+        let store = &FetchingBlockStore::new(local, remote);
+        let notes = latest_docs
+            .as_dir()?
+            .read(&["notes.txt".into()], true, &fetched_forest, store)
+            .await?;
+        assert_eq!(notes, b"new");
+
+        let video = root_dir
+            .get_node(video_path, true, forest, remote)
+            .await?
+            .unwrap()
+            .as_file()?;
+        let token = video.view_token(forest, remote).await?;
+        for cid in token.get_block_cids() {
+            assert!(!local.has_block(cid).await?);
+        }
+
+        Ok(())
+    }
+}
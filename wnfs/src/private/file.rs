@@ -1,10 +1,14 @@
 use super::{
-    encrypted::Encrypted, forest::traits::PrivateForest, PrivateFileContentSerializable,
-    PrivateNode, PrivateNodeContentSerializable, PrivateNodeHeader, PrivateRef, SnapshotKey,
-    TemporalKey, AUTHENTICATION_TAG_SIZE, BLOCK_SEGMENT_DSI, HIDING_SEGMENT_DSI, NONCE_SIZE,
+    encrypted::Encrypted, forest::traits::PrivateForest, overflow_metadata, Compression,
+    ConvergenceSecret, FileViewToken, OverflowCache, OverflowWriter, Padding,
+    PrivateFileContentSerializable, PrivateLogContent, PrivateNode, PrivateNodeContentSerializable,
+    PrivateNodeHeader, PrivateRef, SnapshotKey, TemporalKey, AAD_CIPHERTEXT_VERSION,
+    AUTHENTICATION_TAG_SIZE, BLOCK_HEADER_SIZE, BLOCK_SEGMENT_DSI, CIPHERTEXT_VERSION_SIZE,
+    HIDING_SEGMENT_DSI, NONCE_SIZE,
 };
 use crate::{
-    error::FsError, is_readable_wnfs_version, traits::Id, utils::OnceCellDebug, WNFS_VERSION,
+    error::FsError, is_legacy_wnfs_version, is_readable_wnfs_version, options::WriteOptions,
+    traits::Id, utils::OnceCellDebug, WNFS_VERSION,
 };
 use anyhow::{bail, Result};
 use async_once_cell::OnceCell;
@@ -18,25 +22,27 @@ use libipld_core::{
 };
 use rand_core::CryptoRngCore;
 use serde::{Deserialize, Serialize};
-use std::{cmp::Ordering, collections::BTreeSet, iter};
+use std::{cmp::Ordering, collections::BTreeSet, iter, ops::Range};
 use wnfs_common::{
-    utils::{self, Arc, BoxStream},
-    BlockStore, Metadata, CODEC_RAW, MAX_BLOCK_SIZE,
+    utils::{self, Arc, BoxStream, CondSend, ProgressReader, ProgressReporter},
+    BlockStore, HashOutput, Metadata, CODEC_RAW, MAX_BLOCK_SIZE,
 };
+use wnfs_hamt::Hasher;
 use wnfs_nameaccumulator::{Name, NameAccumulator, NameSegment};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
 //--------------------------------------------------------------------------------------------------
 
-/// The maximum block size is 2 ^ 18 but the first 24 bytes are reserved for the cipher text's initialization vector.
-/// The ciphertext then also contains a 16 byte authentication tag.
-/// This leaves a maximum of (2 ^ 18) - 24 - 16 = 262,104 bytes for the actual data.
+/// The maximum block size is 2 ^ 18 but the first 25 bytes are reserved for the cipher text's version flag
+/// and initialization vector. The ciphertext then also contains a 16 byte authentication tag.
+/// This leaves a maximum of (2 ^ 18) - 25 - 16 = 262,103 bytes for the actual data.
 ///
 /// More on that [here][priv-file].
 ///
 /// [priv-file]: https://github.com/wnfs-wg/spec/blob/matheus23/file-sharding/spec/private-wnfs.md#314-private-file
-pub const MAX_BLOCK_CONTENT_SIZE: usize = MAX_BLOCK_SIZE - NONCE_SIZE - AUTHENTICATION_TAG_SIZE;
+pub const MAX_BLOCK_CONTENT_SIZE: usize =
+    MAX_BLOCK_SIZE - CIPHERTEXT_VERSION_SIZE - NONCE_SIZE - AUTHENTICATION_TAG_SIZE;
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -88,15 +94,17 @@ pub(crate) struct PrivateFileContent {
     pub(crate) previous: BTreeSet<(usize, Encrypted<Cid>)>,
     pub(crate) metadata: Metadata,
     pub(crate) content: FileContent,
+    pub(crate) overflow_cache: OverflowCache,
 }
 
 /// The content of a file.
-/// It is stored inline or stored in blocks.
+/// It is stored inline, stored in blocks or stored as an append-only log of records.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) enum FileContent {
     Inline { data: Vec<u8> },
     External(PrivateForestContent),
+    Log(PrivateLogContent),
 }
 
 /// Keys and pointers to encrypted content stored in a `PrivateForest`.
@@ -107,6 +115,16 @@ pub struct PrivateForestContent {
     pub(crate) base_name: NameAccumulator,
     pub(crate) block_count: u64,
     pub(crate) block_content_size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) compression: Option<Compression>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) padding: Option<Padding>,
+    /// The size of the stored content before it was padded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) unpadded_size: Option<u64>,
+    /// The size of compressed content before it was compressed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) uncompressed_size: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -115,6 +133,41 @@ enum MetadataContentCapsule<T> {
     PrivateForestContent(T),
 }
 
+/// Splits content into the plaintexts of its blocks, compressing and padding them as
+/// configured in [`WriteOptions`].
+///
+/// Uncompressed content is split into blocks of the maximum size and padded as a whole.
+/// Compressed content is split into chunks that are compressed into a block each, which
+/// is then padded by itself.
+pub(crate) struct BlockEncoder {
+    compression: Option<Compression>,
+    padding: Option<Padding>,
+    block_content_size: usize,
+    size: u64,
+    pushed: u64,
+    buffer: Vec<u8>,
+}
+
+/// Encrypts content block by block and stores the blocks, so content can be written
+/// without holding all of it in memory.
+///
+/// The blocks are only added to the forest once all content was written, see
+/// [`ContentWriter::finish`].
+pub(crate) struct ContentWriter {
+    encoder: BlockEncoder,
+    key: SnapshotKey,
+    base_name: Name,
+    deterministic_nonces: bool,
+    blocks: Vec<(Name, Cid)>,
+}
+
+/// Derives the key of content written with a convergence secret from the plaintexts
+/// of its blocks, with the content fed to it in parts.
+pub(crate) struct ContentKeyHasher {
+    encoder: BlockEncoder,
+    hasher: blake3::Hasher,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Implementations
 //--------------------------------------------------------------------------------------------------
@@ -146,6 +199,7 @@ impl PrivateFile {
                 metadata: Metadata::new(time),
                 previous: BTreeSet::new(),
                 content: FileContent::Inline { data: vec![] },
+                overflow_cache: OverflowCache::default(),
             },
         }
     }
@@ -218,7 +272,9 @@ impl PrivateFile {
         rng: &mut impl CryptoRngCore,
     ) -> Result<Self> {
         let header = PrivateNodeHeader::new(parent_name, rng);
-        let content = Self::prepare_content(header.get_name(), content, forest, store, rng).await?;
+        let options = &WriteOptions::default();
+        let content =
+            Self::prepare_content(header.get_name(), content, options, forest, store, rng).await?;
 
         Ok(Self {
             header,
@@ -227,6 +283,7 @@ impl PrivateFile {
                 metadata: Metadata::new(time),
                 previous: BTreeSet::new(),
                 content,
+                overflow_cache: OverflowCache::default(),
             },
         })
     }
@@ -337,6 +394,7 @@ impl PrivateFile {
                 metadata: Metadata::new(time),
                 previous: BTreeSet::new(),
                 content,
+                overflow_cache: OverflowCache::default(),
             },
         })
     }
@@ -502,6 +560,7 @@ impl PrivateFile {
                 yield data.clone()
             }),
             FileContent::External(content) => Box::pin(content.stream(block_index, forest, store)),
+            FileContent::Log(log) => Box::pin(log.stream(block_index, forest, store)),
         }
     }
 
@@ -569,6 +628,7 @@ impl PrivateFile {
                     .read_at(byte_offset, len_limit, forest, store)
                     .await
             }
+            FileContent::Log(log) => log.read_at(byte_offset, len_limit, forest, store).await,
         }
     }
 
@@ -629,9 +689,31 @@ impl PrivateFile {
         match &self.content.content {
             FileContent::Inline { data } => Ok(data.len() as u64),
             FileContent::External(forest_content) => forest_content.size(forest, store).await,
+            FileContent::Log(log) => Ok(log.size()),
         }
     }
 
+    /// Creates a [`FileViewToken`] for the current content of this file.
+    ///
+    /// The token gives read access to this revision's content without the private forest,
+    /// e.g. for publishing the file via a link to any host of its content blocks.
+    /// It fails for files whose content is stored inline, which are files that
+    /// never had any content written to them, and for append-only logs.
+    pub async fn view_token(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<FileViewToken> {
+        let forest_content = match &self.content.content {
+            FileContent::External(forest_content) => forest_content,
+            FileContent::Inline { .. } => bail!(FsError::InlineFileContent),
+            FileContent::Log(_) => bail!(FsError::LogFileContent),
+        };
+
+        let content_type = self.content.metadata.get_content_type().map(String::from);
+        forest_content.view_token(content_type, forest, store).await
+    }
+
     /// Gets the entire content of a file.
     ///
     /// # Examples
@@ -687,6 +769,294 @@ impl PrivateFile {
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<()> {
+        self.set_content_with_progress(content, None, &(), time, forest, store, rng)
+            .await
+    }
+
+    /// Sets the content of a file, compressing it before it's encrypted.
+    ///
+    /// Encrypted content doesn't compress, so this is the only point where compression
+    /// can cut down on storage and bandwidth. The algorithm is recorded with the content,
+    /// so reading the file decompresses it transparently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{Compression, PrivateFile, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let file = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     let content = b"Hello, World!\n".repeat(1000);
+    ///     file.set_content_compressed(
+    ///         content.clone(),
+    ///         Compression::Deflate,
+    ///         Utc::now(),
+    ///         forest,
+    ///         store,
+    ///         rng,
+    ///     )
+    ///     .await?;
+    ///
+    ///     assert_eq!(file.get_compression(), Some(Compression::Deflate));
+    ///     assert_eq!(file.get_content(forest, store).await?, content);
+    ///     assert_eq!(file.read_at(14, Some(5), forest, store).await?, b"Hello");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn set_content_compressed(
+        &mut self,
+        content: Vec<u8>,
+        compression: Compression,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let options = &WriteOptions {
+            compression: Some(compression),
+            ..Default::default()
+        };
+
+        self.set_content_with_options(content, options, time, forest, store, rng)
+            .await
+    }
+
+    /// Sets the content of a file, compressing, padding and sniffing its content type
+    /// as configured in given options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     options::WriteOptions,
+    ///     private::{Padding, PrivateFile, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let file = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+    ///     let options = &WriteOptions {
+    ///         padding: Some(Padding::Bucket(4096)),
+    ///         ..Default::default()
+    ///     };
+    ///
+    ///     file.set_content_with_options(b"secret".to_vec(), options, Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     // The content blocks hold 4096 bytes, but reads only return the actual content
+    ///     assert_eq!(file.get_padding(), Some(Padding::Bucket(4096)));
+    ///     assert_eq!(file.size(forest, store).await?, 6);
+    ///     assert_eq!(file.get_content(forest, store).await?, b"secret");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn set_content_with_options(
+        &mut self,
+        content: Vec<u8>,
+        options: &WriteOptions,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let content_type = options.content_type_update(&content);
+        self.content.content =
+            Self::prepare_content(self.header.get_name(), content, options, forest, store, rng)
+                .await?;
+        self.content.metadata.upsert_mtime(time);
+        content_type.apply(&mut self.content.metadata);
+        Ok(())
+    }
+
+    /// Gets the algorithm the current content of this file was compressed with, if any.
+    pub fn get_compression(&self) -> Option<Compression> {
+        match &self.content.content {
+            FileContent::External(content) => content.compression,
+            FileContent::Inline { .. } | FileContent::Log(_) => None,
+        }
+    }
+
+    /// Gets the scheme the current content of this file was padded with, if any.
+    pub fn get_padding(&self) -> Option<Padding> {
+        match &self.content.content {
+            FileContent::External(content) => content.padding,
+            FileContent::Inline { .. } | FileContent::Log(_) => None,
+        }
+    }
+
+    /// Appends records to this file, which has to be an append-only log.
+    ///
+    /// Files that never had any content written to them become logs on their first append.
+    /// Appends only add new content blocks and never rewrite existing ones, so they stay
+    /// cheap no matter how long the log gets. Records are read back by their index with
+    /// [`PrivateFile::read_records`], or as one concatenated stream of bytes with the
+    /// regular read methods.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let path = &["chat.log".into()];
+    ///
+    ///     let log = root_dir
+    ///         .open_file_mut(path, true, Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///     log.append_records([b"hi".to_vec(), b"hey".to_vec()], Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///     log.append_records([b"how are you?".to_vec()], Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     assert_eq!(log.get_record_count(), Some(3));
+    ///     assert_eq!(
+    ///         log.read_records(1..3, forest, store).await?,
+    ///         [b"hey".to_vec(), b"how are you?".to_vec()]
+    ///     );
+    ///     assert_eq!(log.get_content(forest, store).await?, b"hiheyhow are you?");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn append_records(
+        &mut self,
+        records: impl IntoIterator<Item = Vec<u8>>,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let mut log = match &self.content.content {
+            FileContent::Log(log) => log.clone(),
+            FileContent::Inline { data } if data.is_empty() => {
+                PrivateLogContent::new(self.header.get_name(), forest, rng)
+            }
+            _ => bail!(FsError::NotALog),
+        };
+
+        log.append(records, forest, store, rng).await?;
+        self.content.content = FileContent::Log(log);
+        self.content.metadata.upsert_mtime(time);
+        Ok(())
+    }
+
+    /// Reads the records of this append-only log with indices in given range.
+    ///
+    /// The range is clamped to the records in the log, so e.g. `10..u64::MAX` reads
+    /// all records from the 11th onwards.
+    pub async fn read_records(
+        &self,
+        range: Range<u64>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<Vec<u8>>> {
+        let FileContent::Log(log) = &self.content.content else {
+            bail!(FsError::NotALog);
+        };
+
+        log.read_records(range, forest, store).await
+    }
+
+    /// Gets the number of records if this file is an append-only log.
+    pub fn get_record_count(&self) -> Option<u64> {
+        match &self.content.content {
+            FileContent::Log(log) => Some(log.record_count()),
+            _ => None,
+        }
+    }
+
+    /// Sets the content of a file, reporting the number of bytes streamed in
+    /// so far to `progress`.
+    ///
+    /// `total_bytes` is passed on to `progress` as-is, so it can be left out
+    /// if the length of the content isn't known upfront.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use wnfs::{
+    ///     private::{PrivateFile, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let file = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     let content = vec![42u8; 1_000_000];
+    ///     let done_bytes = AtomicU64::new(0);
+    ///     let progress = |done, _total| done_bytes.store(done, Ordering::Relaxed);
+    ///
+    ///     file.set_content_with_progress(
+    ///         &content[..],
+    ///         Some(content.len() as u64),
+    ///         &progress,
+    ///         Utc::now(),
+    ///         forest,
+    ///         store,
+    ///         rng,
+    ///     )
+    ///     .await?;
+    ///
+    ///     assert_eq!(done_bytes.load(Ordering::Relaxed), 1_000_000);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn set_content_with_progress(
+        &mut self,
+        content: impl AsyncRead + Unpin,
+        total_bytes: Option<u64>,
+        progress: &impl ProgressReporter,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let content = ProgressReader::new(content, total_bytes, progress);
         self.content.metadata.upsert_mtime(time);
         // TODO(matheus23): Use heuristic to figure out whether to store data inline
         self.content.content =
@@ -699,13 +1069,15 @@ impl PrivateFile {
     pub(super) async fn prepare_content(
         file_name: &Name,
         content: Vec<u8>,
+        options: &WriteOptions,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<FileContent> {
         // TODO(appcypher): Use a better heuristic to determine when to use external storage.
         Ok(FileContent::External(
-            PrivateForestContent::new(file_name, content, forest, store, rng).await?,
+            PrivateForestContent::new_with_options(file_name, content, options, forest, store, rng)
+                .await?,
         ))
     }
 
@@ -810,11 +1182,20 @@ impl PrivateFile {
         rng: &mut impl CryptoRngCore,
     ) -> Result<()> {
         self.header.inumber = NameSegment::new(rng);
+        self.prepare_move(parent_name, rng);
+
+        Ok(())
+    }
+
+    /// This prepares this file for moving it to some other place.
+    ///
+    /// Works like [`PrivateFile::prepare_key_rotation`], except that it keeps the
+    /// inumber, so the file keeps its identity and inode number. Write access still
+    /// follows the new parent, since the name is derived from the parent's name.
+    pub(crate) fn prepare_move(&mut self, parent_name: &Name, rng: &mut impl CryptoRngCore) {
         self.header.update_name(parent_name);
         self.header.reset_ratchet(rng);
         self.content.persisted_as = OnceCell::new();
-
-        Ok(())
     }
 
     /// Stores this PrivateFile in the PrivateForest.
@@ -822,26 +1203,31 @@ impl PrivateFile {
         &self,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
-        rng: &mut impl CryptoRngCore,
+        rng: &mut (impl CryptoRngCore + CondSend),
     ) -> Result<PrivateRef> {
         let header_cid = self.header.store(store, forest).await?;
-        let temporal_key = self.header.derive_temporal_key();
-        let snapshot_key = temporal_key.derive_snapshot_key();
+        let revision_ref = self.header.derive_revision_ref(forest);
+        let snapshot_key = revision_ref.temporal_key.derive_snapshot_key();
         let name_with_revision = self.header.get_revision_name();
 
         let content_cid = self
             .content
-            .store(header_cid, &snapshot_key, store, rng)
+            .store(
+                header_cid,
+                &self.header.name,
+                &snapshot_key,
+                &revision_ref.label,
+                forest,
+                store,
+                rng,
+            )
             .await?;
 
         forest
             .put_encrypted(&name_with_revision, [header_cid, content_cid], store)
             .await?;
 
-        Ok(self
-            .header
-            .derive_revision_ref(forest)
-            .into_private_ref(content_cid))
+        Ok(revision_ref.into_private_ref(content_cid))
     }
 
     /// Creates a new [`PrivateFile`] from a [`PrivateFileContentSerializable`].
@@ -853,7 +1239,10 @@ impl PrivateFile {
         store: &impl BlockStore,
         parent_name: Option<Name>,
     ) -> Result<Self> {
-        if !is_readable_wnfs_version(&serializable.version) {
+        let readable = is_readable_wnfs_version(&serializable.version)
+            || forest.accepts_unbound_ciphertexts()
+                && is_legacy_wnfs_version(&serializable.version);
+        if !readable {
             bail!(FsError::UnexpectedVersion(serializable.version));
         }
 
@@ -862,6 +1251,7 @@ impl PrivateFile {
             previous: serializable.previous.into_iter().collect(),
             metadata: serializable.metadata,
             content: serializable.content,
+            overflow_cache: serializable.overflow_cache,
         };
 
         let header = PrivateNodeHeader::load(
@@ -899,8 +1289,8 @@ impl PrivateFile {
 
         let our = self.prepare_next_merge(our_cid, target_header)?;
 
-        if our.content.previous.len() > 1 {
-            // This is a merge node. We'll just add its previous links.
+        if other.content.previous.len() > 1 {
+            // The other node is a merge node. We'll just add its previous links.
             our.content.previous.extend(
                 other
                     .content
@@ -942,25 +1332,48 @@ impl PrivateFile {
 
 impl PrivateFileContent {
     /// Serializes the file to a dag-cbor representation.
-    pub(crate) fn to_dag_cbor(&self, header_cid: Cid) -> Result<Vec<u8>> {
-        Ok(serde_ipld_dagcbor::to_vec(
-            &PrivateNodeContentSerializable::File(PrivateFileContentSerializable {
+    ///
+    /// Large metadata values are stored in blocks of their own, see
+    /// [`METADATA_OVERFLOW_THRESHOLD`](crate::private::METADATA_OVERFLOW_THRESHOLD).
+    pub(crate) async fn to_dag_cbor(
+        &self,
+        header_cid: Cid,
+        name: &Name,
+        snapshot_key: &SnapshotKey,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Vec<u8>> {
+        let mut writer = OverflowWriter::new(name, snapshot_key, &self.overflow_cache);
+        let (metadata, metadata_overflow) =
+            overflow_metadata(&self.metadata, &mut writer, forest, store, rng).await?;
+
+        let bytes = serde_ipld_dagcbor::to_vec(&PrivateNodeContentSerializable::File(
+            PrivateFileContentSerializable {
                 version: WNFS_VERSION,
                 previous: self.previous.iter().cloned().collect(),
                 header_cid,
-                metadata: self.metadata.clone(),
+                metadata,
+                metadata_overflow,
                 content: self.content.clone(),
-            }),
-        )?)
+                overflow_cache: OverflowCache::default(),
+            },
+        ))?;
+        writer.finish(&self.overflow_cache);
+
+        Ok(bytes)
     }
 
-    #[allow(clippy::suspicious)]
+    #[allow(clippy::suspicious, clippy::too_many_arguments)]
     pub(crate) async fn store(
         &self,
         header_cid: Cid,
+        name: &Name,
         snapshot_key: &SnapshotKey,
+        label: &HashOutput,
+        forest: &mut impl PrivateForest,
         store: &impl BlockStore,
-        rng: &mut impl CryptoRngCore,
+        rng: &mut (impl CryptoRngCore + CondSend),
     ) -> Result<Cid> {
         Ok(*self
             .persisted_as
@@ -968,10 +1381,18 @@ impl PrivateFileContent {
                 // TODO(matheus23) deduplicate when reworking serialization
 
                 // Serialize node to cbor.
-                let bytes = self.to_dag_cbor(header_cid)?;
+                let bytes = self
+                    .to_dag_cbor(header_cid, name, snapshot_key, forest, store, rng)
+                    .await?;
 
-                // Encrypt bytes with snapshot key.
-                let block = snapshot_key.encrypt(&bytes, rng)?;
+                // Encrypt bytes with snapshot key, bound to the label it's stored under.
+                let deterministic = forest.uses_deterministic_nonces();
+                let block = snapshot_key.encrypt_with_aad_deterministic_if(
+                    &bytes,
+                    label,
+                    deterministic,
+                    rng,
+                )?;
 
                 // Store content section in blockstore and get Cid.
                 Ok(store.put_block(block, CODEC_RAW).await?)
@@ -1005,70 +1426,107 @@ impl PrivateForestContent {
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<Self> {
-        let (key, base_name) = Self::prepare_key_and_base_name(file_name, rng);
-        let block_count = (content.len() as f64 / MAX_BLOCK_CONTENT_SIZE as f64).ceil() as u64;
-
-        for (name, index) in Self::generate_shard_labels(&key, 0, block_count, &base_name).zip(0..)
-        {
-            let start = index * MAX_BLOCK_CONTENT_SIZE;
-            let end = content.len().min((index + 1) * MAX_BLOCK_CONTENT_SIZE);
-            let slice = &content[start..end];
-
-            let enc_bytes = key.encrypt(slice, rng)?;
-            let content_cid = store.put_block(enc_bytes, CODEC_RAW).await?;
-
-            forest
-                .put_encrypted(&name, Some(content_cid), store)
-                .await?;
-        }
-
-        Ok(PrivateForestContent {
-            key,
-            base_name: forest.get_accumulated_name(&base_name),
-            block_count,
-            block_content_size: MAX_BLOCK_CONTENT_SIZE as u64,
-        })
+        Self::new_with_options(
+            file_name,
+            content,
+            &WriteOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .await
     }
 
-    /// Like `new`, but allows streaming in the content.
+    /// Like `new`, but compresses the content before it's split into blocks and encrypted.
     ///
-    /// See `new` for more information.
-    pub async fn new_streaming(
+    /// Reads decompress the content again transparently.
+    pub async fn new_compressed(
         file_name: &Name,
-        mut content: impl AsyncRead + Unpin,
+        content: Vec<u8>,
+        compression: Compression,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Self> {
+        let options = &WriteOptions {
+            compression: Some(compression),
+            ..Default::default()
+        };
+
+        Self::new_with_options(file_name, content, options, forest, store, rng).await
+    }
+
+    /// Like `new`, but compresses and pads the content as configured in given options,
+    /// as it's split into blocks and encrypted, with nonces derived from the content
+    /// if the options ask for deterministic nonces. With a convergence secret, the key
+    /// and base name are derived from the content, see [`WriteOptions::convergence`].
+    ///
+    /// Compression and padding are recorded in the returned struct, so reads undo them
+    /// transparently.
+    pub async fn new_with_options(
+        file_name: &Name,
+        content: Vec<u8>,
+        options: &WriteOptions,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Self> {
+        let size = content.len() as u64;
+        let content_key = match &options.convergence {
+            Some(secret) => {
+                let mut hasher = ContentKeyHasher::new(secret, size, options);
+                hasher.update(&content);
+                Some(hasher.finalize()?)
+            }
+            None => None,
+        };
+
+        let mut writer = ContentWriter::new(file_name, size, options, content_key, forest, rng);
+        writer.write(&content, forest, store, rng).await?;
+        writer.finish(forest, store, rng).await
+    }
+
+    /// Like `new`, but allows streaming in the content.
+    ///
+    /// See `new` for more information.
+    pub async fn new_streaming(
+        file_name: &Name,
+        mut content: impl AsyncRead + Unpin,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<Self> {
         let (key, base_name) = Self::prepare_key_and_base_name(file_name, rng);
 
+        let mut blocks = Vec::new();
         let mut block_index = 0;
 
         loop {
+            let name = Self::create_block_name(&key, block_index, &base_name);
+            let aad = SnapshotKey::versioned_aad(&Self::block_label(&name, forest));
+
             let mut current_block = vec![0u8; MAX_BLOCK_SIZE];
             let nonce = SnapshotKey::generate_nonce(rng);
-            current_block[..NONCE_SIZE].copy_from_slice(nonce.as_ref());
+            let content_start = CIPHERTEXT_VERSION_SIZE + NONCE_SIZE;
+            current_block[0] = AAD_CIPHERTEXT_VERSION;
+            current_block[CIPHERTEXT_VERSION_SIZE..content_start].copy_from_slice(nonce.as_ref());
 
             // read up to MAX_BLOCK_CONTENT_SIZE content
 
-            let content_end = NONCE_SIZE + MAX_BLOCK_CONTENT_SIZE;
+            let content_end = content_start + MAX_BLOCK_CONTENT_SIZE;
             let (bytes_written, done) =
-                utils::read_fully(&mut content, &mut current_block[NONCE_SIZE..content_end])
+                utils::read_fully(&mut content, &mut current_block[content_start..content_end])
                     .await?;
 
             // truncate the vector to its actual length.
-            current_block.truncate(bytes_written + NONCE_SIZE);
+            current_block.truncate(bytes_written + content_start);
 
-            let tag = key.encrypt_in_place(&nonce, &mut current_block[NONCE_SIZE..])?;
+            let tag = key.encrypt_in_place(&nonce, &aad, &mut current_block[content_start..])?;
             current_block.extend_from_slice(tag.as_ref());
 
             let content_cid = store.put_block(current_block, CODEC_RAW).await?;
 
-            let name = Self::create_block_name(&key, block_index, &base_name);
-            forest
-                .put_encrypted(&name, Some(content_cid), store)
-                .await?;
-
+            blocks.push((name, content_cid));
             block_index += 1;
 
             if done {
@@ -1076,14 +1534,38 @@ impl PrivateForestContent {
             }
         }
 
+        Self::put_blocks_in_forest(blocks, forest, store).await?;
+
         Ok(PrivateForestContent {
             key,
             base_name: forest.get_accumulated_name(&base_name),
             block_count: block_index,
             block_content_size: MAX_BLOCK_CONTENT_SIZE as u64,
+            compression: None,
+            padding: None,
+            unpadded_size: None,
+            uncompressed_size: None,
         })
     }
 
+    /// Adds the labels of content blocks to the forest.
+    ///
+    /// This only happens after all content was read and encrypted, so cancelling
+    /// a write before that, e.g. by dropping its future, leaves the forest untouched.
+    async fn put_blocks_in_forest(
+        blocks: Vec<(Name, Cid)>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        for (name, content_cid) in blocks {
+            forest
+                .put_encrypted(&name, Some(content_cid), store)
+                .await?;
+        }
+
+        Ok(())
+    }
+
     /// Load some previously stored keys & pointers to encrypted private forest content
     /// from given metadata key.
     pub fn from_metadata_value(value: &Ipld) -> Result<Self> {
@@ -1102,11 +1584,61 @@ impl PrivateForestContent {
     }
 
     /// Decrypt & stream out the contents that `self` points to in given forest.
+    ///
+    /// Compressed content is decompressed block by block, so only the blocks
+    /// that are streamed out get fetched.
     pub fn stream<'a>(
         &'a self,
         block_index: u64,
         forest: &'a impl PrivateForest,
         store: &'a impl BlockStore,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + 'a {
+        try_stream! {
+            let size = self.known_size()?;
+            let mut offset = block_index * self.block_content_size;
+            let mut blocks = Box::pin(self.stream_blocks(block_index, forest, store));
+            while let Some(block) = blocks.next().await {
+                let mut bytes = block?;
+                if let Some(size) = size {
+                    if offset >= size {
+                        break;
+                    }
+
+                    let len = (size - offset).min(self.block_content_size) as usize;
+                    bytes = match self.compression {
+                        Some(compression) => compression.decompress_block(&bytes, len)?,
+                        None => {
+                            bytes.truncate(len);
+                            bytes
+                        }
+                    };
+                }
+
+                offset += self.block_content_size;
+                yield bytes;
+            }
+        }
+    }
+
+    /// The size of the content, if it's recorded next to the keys. That's the case
+    /// for padded and compressed content, which always has to record it.
+    fn known_size(&self) -> Result<Option<u64>> {
+        match (self.compression, self.uncompressed_size) {
+            (Some(_), None) => bail!(FsError::InvalidCompressedContent(
+                "Missing uncompressed size".to_string()
+            )),
+            (Some(_), size) => Ok(size),
+            (None, _) => Ok(self.unpadded_size),
+        }
+    }
+
+    /// Decrypt & stream out the blocks that `self` points to in given forest,
+    /// without removing padding or decompressing them.
+    fn stream_blocks<'a>(
+        &'a self,
+        block_index: u64,
+        forest: &'a impl PrivateForest,
+        store: &'a impl BlockStore,
     ) -> impl Stream<Item = Result<Vec<u8>>> + 'a {
         try_stream! {
             for name in Self::generate_shard_labels(
@@ -1130,7 +1662,7 @@ impl PrivateForestContent {
         forest: &'a impl PrivateForest,
         store: &'a impl BlockStore,
     ) -> Result<Vec<u8>> {
-        let block_content_size = MAX_BLOCK_CONTENT_SIZE as u64;
+        let block_content_size = self.block_content_size;
         let mut chunk_size_upper_bound = (self.get_size_upper_bound() - byte_offset) as usize;
 
         if let Some(len_limit) = len_limit {
@@ -1195,8 +1727,11 @@ impl PrivateForestContent {
 
     /// Gets the exact size of the content.
     pub async fn size(&self, forest: &impl PrivateForest, store: &impl BlockStore) -> Result<u64> {
-        let size_without_last_block =
-            std::cmp::max(0, self.block_count - 1) * self.block_content_size;
+        if let Some(size) = self.known_size()? {
+            return Ok(size);
+        }
+
+        let size_without_last_block = self.block_count.saturating_sub(1) * self.block_content_size;
 
         let size_last_block = self
             .read_at(size_without_last_block, None, forest, store)
@@ -1206,6 +1741,93 @@ impl PrivateForestContent {
         Ok(size_without_last_block + size_last_block)
     }
 
+    /// Looks up the content blocks and puts them in a [`FileViewToken`].
+    pub(crate) async fn view_token(
+        &self,
+        content_type: Option<String>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<FileViewToken> {
+        let base_name = &Name::new(self.base_name.clone(), []);
+        let mut blocks = Vec::with_capacity(self.block_count as usize);
+        for name in Self::generate_shard_labels(&self.key, 0, self.block_count, base_name) {
+            let cid = forest
+                .get_encrypted(&name, store)
+                .await?
+                .and_then(|cids| cids.first().copied())
+                .ok_or(FsError::FileShardNotFound)?;
+
+            blocks.push((cid, Self::block_label(&name, forest)));
+        }
+
+        let size = self.size(forest, store).await?;
+        Ok(FileViewToken::new(
+            self.key.clone(),
+            blocks,
+            size,
+            content_type,
+            self.compression,
+            self.compression.map(|_| self.block_content_size),
+            self.unpadded_size,
+            forest.accepts_unbound_ciphertexts(),
+        ))
+    }
+
+    /// Re-encrypts the content blocks under a fresh key and base name derived from
+    /// `file_name`. The blocks are decrypted and encrypted as they are, so compression
+    /// and padding stay the same.
+    ///
+    /// The old blocks stay in the forest. Returns the re-encrypted content along with
+    /// the CIDs of the old blocks and the CIDs of the blocks replacing them.
+    pub(crate) async fn reencrypt(
+        &self,
+        file_name: &Name,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<(Self, Vec<(Cid, Cid)>)> {
+        let (key, base_name) = Self::prepare_key_and_base_name(file_name, rng);
+        let old_base_name = &Name::new(self.base_name.clone(), []);
+
+        let mut blocks = Vec::with_capacity(self.block_count as usize);
+        let mut cids = Vec::with_capacity(self.block_count as usize);
+        let old_names = Self::generate_shard_labels(&self.key, 0, self.block_count, old_base_name);
+        let names = Self::generate_shard_labels(&key, 0, self.block_count, &base_name);
+        for (old_name, name) in old_names.zip(names) {
+            let old_cid = forest
+                .get_encrypted(&old_name, store)
+                .await?
+                .and_then(|cids| cids.first().copied())
+                .ok_or(FsError::FileShardNotFound)?;
+
+            let old_label = &Self::block_label(&old_name, forest);
+            let bytes = self.key.decrypt_with_aad_if(
+                &store.get_block(&old_cid).await?,
+                old_label,
+                forest.accepts_unbound_ciphertexts(),
+            )?;
+
+            let label = &Self::block_label(&name, forest);
+            let deterministic = forest.uses_deterministic_nonces();
+            let enc_bytes =
+                key.encrypt_with_aad_deterministic_if(&bytes, label, deterministic, rng)?;
+            let cid = store.put_block(enc_bytes, CODEC_RAW).await?;
+
+            blocks.push((name, cid));
+            cids.push((old_cid, cid));
+        }
+
+        Self::put_blocks_in_forest(blocks, forest, store).await?;
+
+        let content = Self {
+            key,
+            base_name: forest.get_accumulated_name(&base_name),
+            ..self.clone()
+        };
+
+        Ok((content, cids))
+    }
+
     /// Generates the labels for all of the content shard blocks.
     pub(crate) fn generate_shard_labels<'a>(
         key: &'a SnapshotKey,
@@ -1224,7 +1846,19 @@ impl PrivateForestContent {
         })
     }
 
-    async fn decrypt_block(
+    /// Decrypts a single block of content, without removing padding or decompressing it.
+    pub(crate) async fn read_block(
+        &self,
+        block_index: u64,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        let base_name = &Name::new(self.base_name.clone(), []);
+        let name = Self::create_block_name(&self.key, block_index, base_name);
+        Self::decrypt_block(&self.key, &name, forest, store).await
+    }
+
+    pub(crate) async fn decrypt_block(
         key: &SnapshotKey,
         name: &Name,
         forest: &impl PrivateForest,
@@ -1234,17 +1868,24 @@ impl PrivateForestContent {
             .get_encrypted(name, store)
             .await?
             .ok_or(FsError::FileShardNotFound)?
-            .iter()
-            .next()
+            .first()
+            .copied()
             .expect("Expected set with at least a one cid");
 
-        let enc_bytes = store.get_block(cid).await?;
-        let bytes = key.decrypt(&enc_bytes)?;
+        let enc_bytes = store.get_block(&cid).await?;
+        let label = &Self::block_label(name, forest);
+        let bytes =
+            key.decrypt_with_aad_if(&enc_bytes, label, forest.accepts_unbound_ciphertexts())?;
 
         Ok(bytes)
     }
 
-    fn create_block_name(key: &SnapshotKey, index: u64, base_name: &Name) -> Name {
+    /// The label a content block is stored under, which its ciphertext is bound to.
+    pub(crate) fn block_label(name: &Name, forest: &impl PrivateForest) -> HashOutput {
+        blake3::Hasher::hash(&forest.get_accumulated_name(name))
+    }
+
+    pub(crate) fn create_block_name(key: &SnapshotKey, index: u64, base_name: &Name) -> Name {
         let mut vec = Vec::with_capacity(40);
         vec.extend(key.0); // 32 bytes
         vec.extend(index.to_le_bytes()); // 8 bytes
@@ -1253,16 +1894,224 @@ impl PrivateForestContent {
         base_name.with_segments_added(Some(block_segment))
     }
 
-    fn prepare_key_and_base_name(
+    pub(crate) fn prepare_key_and_base_name(
         file_name: &Name,
         rng: &mut impl CryptoRngCore,
     ) -> (SnapshotKey, Name) {
         let key = SnapshotKey::new(rng);
-        let hiding_segment = NameSegment::new_hashed(HIDING_SEGMENT_DSI, key.as_bytes());
-        let base_name = file_name.with_segments_added(Some(hiding_segment));
+        let base_name = Self::hide_base_name(file_name, &key);
 
         (key, base_name)
     }
+
+    /// Appends the hiding segment derived from given content key to given name.
+    pub(crate) fn hide_base_name(name: &Name, key: &SnapshotKey) -> Name {
+        let hiding_segment = NameSegment::new_hashed(HIDING_SEGMENT_DSI, key.as_bytes());
+        name.with_segments_added(Some(hiding_segment))
+    }
+}
+
+impl BlockEncoder {
+    /// Creates an encoder for `size` bytes of content, written with given options.
+    pub(crate) fn new(size: u64, options: &WriteOptions) -> Self {
+        let block_content_size = match options.compression {
+            Some(_) => MAX_BLOCK_CONTENT_SIZE - BLOCK_HEADER_SIZE,
+            None => MAX_BLOCK_CONTENT_SIZE,
+        };
+
+        Self {
+            compression: options.compression,
+            padding: options.padding,
+            block_content_size,
+            size,
+            pushed: 0,
+            buffer: Vec::with_capacity(block_content_size),
+        }
+    }
+
+    /// Adds bytes of content, returning the blocks they completed.
+    pub(crate) fn push(&mut self, mut bytes: &[u8]) -> Vec<Vec<u8>> {
+        self.pushed += bytes.len() as u64;
+
+        let mut blocks = Vec::new();
+        while !bytes.is_empty() {
+            let len = bytes.len().min(self.block_content_size - self.buffer.len());
+            self.buffer.extend_from_slice(&bytes[..len]);
+            bytes = &bytes[len..];
+
+            if self.buffer.len() == self.block_content_size {
+                let chunk = std::mem::take(&mut self.buffer);
+                blocks.push(self.encode(chunk));
+            }
+        }
+
+        blocks
+    }
+
+    /// Returns the remaining blocks, once all content was pushed.
+    pub(crate) fn finish(&mut self) -> Result<Vec<Vec<u8>>> {
+        if self.pushed != self.size {
+            bail!(
+                "Expected {} bytes of content, but got {}",
+                self.size,
+                self.pushed
+            );
+        }
+
+        let mut blocks = Vec::new();
+        if self.compression.is_none() {
+            let padded_size = self.padding.map_or(self.size, |p| p.padded_len(self.size));
+            blocks = self.push(&vec![0; (padded_size - self.size) as usize]);
+        }
+
+        if !self.buffer.is_empty() {
+            let chunk = std::mem::take(&mut self.buffer);
+            blocks.push(self.encode(chunk));
+        }
+
+        Ok(blocks)
+    }
+
+    /// Compresses and pads a chunk of compressed content into a block. Uncompressed
+    /// content is padded as a whole instead, so its chunks are returned as-is.
+    fn encode(&self, chunk: Vec<u8>) -> Vec<u8> {
+        let Some(compression) = self.compression else {
+            return chunk;
+        };
+
+        let mut block = compression.compress_block(&chunk);
+        if let Some(padding) = self.padding {
+            let padded_len = padding.padded_len(block.len() as u64);
+            block.resize((padded_len as usize).min(MAX_BLOCK_CONTENT_SIZE), 0);
+        }
+
+        block
+    }
+}
+
+impl ContentWriter {
+    /// Starts writing `size` bytes of content with given options.
+    ///
+    /// With a convergence secret in the options, `content_key` has to be derived from
+    /// the same content with a [`ContentKeyHasher`]. Otherwise, a fresh key is used.
+    pub(crate) fn new(
+        file_name: &Name,
+        size: u64,
+        options: &WriteOptions,
+        content_key: Option<SnapshotKey>,
+        forest: &impl PrivateForest,
+        rng: &mut impl CryptoRngCore,
+    ) -> Self {
+        let (key, base_name) = match content_key {
+            Some(key) => {
+                let base_name = PrivateForestContent::hide_base_name(&forest.empty_name(), &key);
+                (key, base_name)
+            }
+            None => PrivateForestContent::prepare_key_and_base_name(file_name, rng),
+        };
+
+        Self {
+            encoder: BlockEncoder::new(size, options),
+            key,
+            base_name,
+            deterministic_nonces: options.deterministic_nonces
+                || options.convergence.is_some()
+                || forest.uses_deterministic_nonces(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Encrypts and stores the blocks completed by given bytes of content.
+    pub(crate) async fn write(
+        &mut self,
+        bytes: &[u8],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        for block in self.encoder.push(bytes) {
+            self.write_block(&block, forest, store, rng).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores the remaining blocks and adds all blocks to the forest.
+    pub(crate) async fn finish(
+        mut self,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<PrivateForestContent> {
+        for block in self.encoder.finish()? {
+            self.write_block(&block, forest, store, rng).await?;
+        }
+
+        let block_count = self.blocks.len() as u64;
+        PrivateForestContent::put_blocks_in_forest(self.blocks, forest, store).await?;
+
+        Ok(PrivateForestContent {
+            key: self.key,
+            base_name: forest.get_accumulated_name(&self.base_name),
+            block_count,
+            block_content_size: self.encoder.block_content_size as u64,
+            compression: self.encoder.compression,
+            padding: self.encoder.padding,
+            unpadded_size: match self.encoder.compression {
+                None => self.encoder.padding.map(|_| self.encoder.size),
+                Some(_) => None,
+            },
+            uncompressed_size: self.encoder.compression.map(|_| self.encoder.size),
+        })
+    }
+
+    async fn write_block(
+        &mut self,
+        block: &[u8],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let index = self.blocks.len() as u64;
+        let name = PrivateForestContent::create_block_name(&self.key, index, &self.base_name);
+        let label = PrivateForestContent::block_label(&name, forest);
+        let enc_bytes = self.key.encrypt_with_aad_deterministic_if(
+            block,
+            &label,
+            self.deterministic_nonces,
+            rng,
+        )?;
+
+        let content_cid = store.put_block(enc_bytes, CODEC_RAW).await?;
+        self.blocks.push((name, content_cid));
+        Ok(())
+    }
+}
+
+impl ContentKeyHasher {
+    /// Creates a hasher for `size` bytes of content, written with given options.
+    pub(crate) fn new(secret: &ConvergenceSecret, size: u64, options: &WriteOptions) -> Self {
+        Self {
+            encoder: BlockEncoder::new(size, options),
+            hasher: secret.content_key_hasher(),
+        }
+    }
+
+    /// Adds bytes of content.
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for block in self.encoder.push(bytes) {
+            self.hasher.update(&block);
+        }
+    }
+
+    /// Derives the key, once all content was added.
+    pub(crate) fn finalize(mut self) -> Result<SnapshotKey> {
+        for block in self.encoder.finish()? {
+            self.hasher.update(&block);
+        }
+
+        Ok(SnapshotKey(self.hasher.finalize().into()))
+    }
 }
 
 impl PartialEq for PrivateFileContent {
@@ -1285,6 +2134,7 @@ impl Clone for PrivateFileContent {
             previous: self.previous.clone(),
             metadata: self.metadata.clone(),
             content: self.content.clone(),
+            overflow_cache: self.overflow_cache.clone(),
         }
     }
 }
@@ -1316,12 +2166,17 @@ impl std::fmt::Debug for PrivateFileContent {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::private::forest::hamt::HamtForest;
+    use crate::error::CryptError;
+    use crate::private::{forest::hamt::HamtForest, ConvergenceSecret};
     use async_std::fs::File;
     use rand::Rng;
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
-    use wnfs_common::MemoryBlockStore;
+    use testresult::TestResult;
+    use wnfs_common::{
+        utils::{CancellationToken, Fault, FaultyBlockStore},
+        BlockStoreError, MemoryBlockStore,
+    };
 
     #[async_std::test]
     async fn can_create_empty_file() {
@@ -1335,6 +2190,39 @@ mod tests {
         assert!(file_content.is_empty());
     }
 
+    #[async_std::test]
+    async fn merging_three_concurrent_heads_links_all_of_them() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::new_rc(&forest.empty_name(), Utc::now(), rng);
+        file.as_node().store(forest, store, rng).await?;
+
+        let mut heads = BTreeSet::new();
+        for content in ["first", "second", "third"] {
+            let head = &mut Arc::clone(file);
+            head.prepare_next_revision()?
+                .set_content(content.as_bytes(), Utc::now(), forest, store, rng)
+                .await?;
+            let access_key = head.as_node().store(forest, store, rng).await?;
+            heads.insert(*access_key.get_content_cid());
+        }
+
+        let merged = file
+            .as_node()
+            .search_latest_reconciled(forest, store)
+            .await?;
+        let previous = merged
+            .get_previous()
+            .iter()
+            .filter_map(|(_, link)| link.get_value().copied())
+            .collect::<BTreeSet<_>>();
+
+        assert_eq!(previous, heads);
+
+        Ok(())
+    }
+
     #[async_std::test]
     async fn can_stream_limited_content_from_file() {
         let mut content = vec![0u8; MAX_BLOCK_CONTENT_SIZE * 5];
@@ -1375,6 +2263,372 @@ mod tests {
         );
     }
 
+    #[async_std::test]
+    async fn view_tokens_decrypt_content_without_the_forest() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let content = vec![7u8; MAX_BLOCK_CONTENT_SIZE + 10];
+
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        assert!(file.view_token(forest, store).await.is_err());
+
+        file.set_content(&content[..], Utc::now(), forest, store, rng)
+            .await
+            .unwrap();
+        file.get_metadata_mut().put_content_type("text/plain");
+
+        let bytes = file
+            .view_token(forest, store)
+            .await
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let token = FileViewToken::parse(bytes).unwrap();
+
+        // The token's blocks are all it takes to read the file
+        let host = &MemoryBlockStore::new();
+        for cid in token.get_block_cids() {
+            let block = store.get_block(cid).await.unwrap();
+            host.put_block(block, CODEC_RAW).await.unwrap();
+        }
+
+        assert_eq!(token.get_block_cids().count(), 2);
+        assert_eq!(token.get_size(), content.len() as u64);
+        assert_eq!(token.get_content_type(), Some("text/plain"));
+        assert_eq!(token.get_content(host).await.unwrap(), content);
+    }
+
+    #[async_std::test]
+    async fn compressed_content_is_decompressed_on_read() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let content = b"Hello, World!\n".repeat(50_000);
+
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        file.set_content_compressed(
+            content.clone(),
+            Compression::Deflate,
+            Utc::now(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        // Every block holds a compressed chunk of the content
+        let FileContent::External(forest_content) = &file.content.content else {
+            panic!("Expected external content");
+        };
+        let block_content_size = forest_content.block_content_size as usize;
+        assert_eq!(
+            forest_content.block_count,
+            content.len().div_ceil(block_content_size) as u64
+        );
+        let block = forest_content.read_block(0, forest, store).await.unwrap();
+        assert!(block.len() < block_content_size / 10);
+
+        let streamed: Vec<Vec<u8>> = file
+            .stream_content(1, forest, store)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(streamed.concat(), &content[block_content_size..]);
+        assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+        assert_eq!(
+            file.read_at(699_986, Some(100), forest, store)
+                .await
+                .unwrap(),
+            b"Hello, World!\n"
+        );
+
+        // The size is known without fetching any blocks
+        let empty_store = &MemoryBlockStore::new();
+        assert_eq!(
+            file.size(forest, empty_store).await.unwrap(),
+            content.len() as u64
+        );
+
+        let token = file.view_token(forest, store).await.unwrap();
+        let token = FileViewToken::parse(token.to_bytes().unwrap()).unwrap();
+        assert_eq!(token.get_size(), content.len() as u64);
+        assert_eq!(token.get_content(store).await.unwrap(), content);
+
+        // Writing without compression again stores the content as-is
+        file.set_content(&content[..], Utc::now(), forest, store, rng)
+            .await
+            .unwrap();
+        assert_eq!(file.get_compression(), None);
+        assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+    }
+
+    #[async_std::test]
+    async fn padded_content_hides_its_exact_size() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = &WriteOptions {
+            padding: Some(Padding::Padme),
+            ..Default::default()
+        };
+
+        let mut block_sizes = Vec::new();
+        for len in [MAX_BLOCK_CONTENT_SIZE + 1000, MAX_BLOCK_CONTENT_SIZE + 2000] {
+            let content = vec![7u8; len];
+            let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+            file.set_content_with_options(content.clone(), options, Utc::now(), forest, store, rng)
+                .await
+                .unwrap();
+
+            let token = file.view_token(forest, store).await.unwrap();
+            let mut sizes = Vec::new();
+            for cid in token.get_block_cids() {
+                sizes.push(store.get_block(cid).await.unwrap().len());
+            }
+            block_sizes.push(sizes);
+
+            let streamed: Vec<Vec<u8>> = file
+                .stream_content(1, forest, store)
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(streamed.concat(), &content[MAX_BLOCK_CONTENT_SIZE..]);
+            assert_eq!(
+                file.read_at(len as u64 - 10, Some(100), forest, store)
+                    .await
+                    .unwrap(),
+                &content[len - 10..]
+            );
+            assert_eq!(file.size(forest, store).await.unwrap(), len as u64);
+            assert_eq!(token.get_content(store).await.unwrap(), content);
+        }
+
+        // Both sizes are padded to the same size
+        assert_eq!(block_sizes[0], block_sizes[1]);
+    }
+
+    #[async_std::test]
+    async fn compressed_and_padded_content_is_read_block_by_block() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = &WriteOptions {
+            compression: Some(Compression::Deflate),
+            padding: Some(Padding::Padme),
+            ..Default::default()
+        };
+        let content = (0..3 * MAX_BLOCK_CONTENT_SIZE)
+            .map(|i| (i % 251 + i / 1000 % 7) as u8)
+            .collect::<Vec<_>>();
+
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        file.set_content_with_options(content.clone(), options, Utc::now(), forest, store, rng)
+            .await
+            .unwrap();
+
+        let FileContent::External(forest_content) = &file.content.content else {
+            panic!("Expected external content");
+        };
+        let block_content_size = forest_content.block_content_size as usize;
+        assert_eq!(forest_content.block_count, 4);
+
+        let offset = 2 * block_content_size - 10;
+        assert_eq!(
+            file.read_at(offset as u64, Some(20), forest, store)
+                .await
+                .unwrap(),
+            &content[offset..offset + 20]
+        );
+        assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+
+        let token = file.view_token(forest, store).await.unwrap();
+        let streamed: Vec<Vec<u8>> = token.stream(store).try_collect().await.unwrap();
+        assert_eq!(streamed.len(), 4);
+        assert_eq!(streamed.concat(), content);
+    }
+
+    #[async_std::test]
+    async fn deterministic_nonces_make_ciphertexts_reproducible() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let key = SnapshotKey::new(rng);
+        let content = vec![7u8; MAX_BLOCK_CONTENT_SIZE + 1000];
+
+        let mut block_cids = Vec::new();
+        for deterministic_nonces in [true, true, false] {
+            let forest = &mut Arc::clone(forest);
+            let options = &WriteOptions {
+                deterministic_nonces,
+                ..Default::default()
+            };
+            let size = content.len() as u64;
+            let name = &forest.empty_name();
+            let mut writer =
+                ContentWriter::new(name, size, options, Some(key.clone()), forest, rng);
+            writer.write(&content, forest, store, rng).await.unwrap();
+            let forest_content = writer.finish(forest, store, rng).await.unwrap();
+
+            let base_name = &Name::new(forest_content.base_name.clone(), []);
+            let mut cids = Vec::new();
+            for name in PrivateForestContent::generate_shard_labels(&key, 0, 2, base_name) {
+                cids.push(forest.get_encrypted(&name, store).await.unwrap().unwrap());
+            }
+            block_cids.push(cids);
+
+            assert_eq!(
+                forest_content.get_content(forest, store).await.unwrap(),
+                content
+            );
+        }
+
+        assert_eq!(block_cids[0], block_cids[1]);
+        assert_ne!(block_cids[0], block_cids[2]);
+    }
+
+    #[async_std::test]
+    async fn convergent_content_deduplicates_across_files() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let content = vec![7u8; MAX_BLOCK_CONTENT_SIZE + 1000];
+        let convergent = &WriteOptions {
+            convergence: Some(ConvergenceSecret::new(rng)),
+            ..Default::default()
+        };
+        let other_secret = &WriteOptions {
+            convergence: Some(ConvergenceSecret::new(rng)),
+            ..Default::default()
+        };
+
+        let mut block_cids = Vec::new();
+        for options in [
+            convergent,
+            convergent,
+            other_secret,
+            &WriteOptions::default(),
+        ] {
+            let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+            file.set_content_with_options(content.clone(), options, Utc::now(), forest, store, rng)
+                .await
+                .unwrap();
+            assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+
+            let token = file.view_token(forest, store).await.unwrap();
+            block_cids.push(token.get_block_cids().cloned().collect::<Vec<_>>());
+        }
+
+        assert_eq!(block_cids[0], block_cids[1]);
+        assert_ne!(block_cids[0], block_cids[2]);
+        assert_ne!(block_cids[0], block_cids[3]);
+    }
+
+    #[async_std::test]
+    async fn swapped_content_blocks_fail_to_decrypt() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let content = PrivateForestContent::new_streaming(
+            &forest.empty_name(),
+            &vec![1u8; MAX_BLOCK_CONTENT_SIZE + 10][..],
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let base_name = &Name::new(content.base_name.clone(), []);
+        let names = PrivateForestContent::generate_shard_labels(&content.key, 0, 2, base_name)
+            .collect::<Vec<_>>();
+        let mut cids = Vec::new();
+        for name in names.iter() {
+            let pair = forest.remove_encrypted(name, store).await.unwrap().unwrap();
+            cids.push(*pair.value.first().unwrap());
+        }
+        forest
+            .put_encrypted(&names[0], [cids[1]], store)
+            .await
+            .unwrap();
+        forest
+            .put_encrypted(&names[1], [cids[0]], store)
+            .await
+            .unwrap();
+
+        let result = content.get_content(forest, store).await;
+
+        assert!(result.is_err());
+    }
+
+    #[async_std::test]
+    async fn faulty_content_blocks_fail_reads() {
+        let store = &FaultyBlockStore::new(MemoryBlockStore::new(), ());
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let content = vec![1u8; MAX_BLOCK_CONTENT_SIZE + 10];
+
+        let file = PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            content,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+        let token = file.view_token(forest, store).await.unwrap();
+        let second_block = *token.get_block_cids().nth(1).unwrap();
+
+        store.inject_for(second_block, Fault::Transient);
+        let err = file.get_content(forest, store).await.unwrap_err();
+        assert!(err
+            .downcast_ref::<BlockStoreError>()
+            .unwrap()
+            .is_transient());
+
+        store.inject_for(second_block, Fault::Corrupt);
+        let err = file.get_content(forest, store).await.unwrap_err();
+        assert!(err.downcast_ref::<CryptError>().is_some());
+
+        store.clear_faults();
+        assert!(file.get_content(forest, store).await.is_ok());
+    }
+
+    #[async_std::test]
+    async fn cancelled_streaming_write_leaves_forest_untouched() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+
+        let content = vec![1u8; MAX_BLOCK_CONTENT_SIZE * 3];
+        let token = CancellationToken::new();
+        let progress = |done, _total| {
+            if done > MAX_BLOCK_CONTENT_SIZE as u64 {
+                token.cancel();
+            }
+        };
+
+        let result = file
+            .set_content_with_progress(
+                token.reader(&content[..]),
+                None,
+                &progress,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(forest.stats(store).await.unwrap().hamt.key_count, 0);
+    }
+
     #[async_std::test]
     async fn can_construct_file_from_stream() {
         let disk_file = File::open("./test/fixtures/Clara Schumann, Scherzo no. 2, Op. 14.mp3")
@@ -0,0 +1,911 @@
+use super::{
+    forest::traits::PrivateForest, ContentKeyHasher, ContentWriter, FileContent, PrivateFile,
+    MAX_BLOCK_CONTENT_SIZE,
+};
+use crate::{error::FsError, options::WriteOptions};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use futures::{future, ready, AsyncRead, AsyncSeek, AsyncWrite, Future, FutureExt, TryFutureExt};
+use rand_core::CryptoRngCore;
+use std::{
+    collections::BTreeMap,
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use wnfs_common::{
+    utils::{boxed_fut, BoxFuture, CondSend},
+    BlockStore,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Files are edited in chunks that line up with their content blocks.
+const CHUNK_SIZE: u64 = MAX_BLOCK_CONTENT_SIZE as u64;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A handle for reading and editing the content of a private file with
+/// `std`-like file IO, via [`AsyncRead`], [`AsyncWrite`] and [`AsyncSeek`].
+///
+/// Content is loaded chunk by chunk as needed. Writes are buffered per chunk
+/// and only encrypted & put into the forest on [flush][futures::AsyncWriteExt::flush]
+/// or [close][futures::AsyncWriteExt::close], which replaces the file's content.
+/// Unflushed changes are lost when the handle is dropped.
+///
+/// Flushed content is written like [`PrivateFile::set_content_with_options`], with the
+/// compression and padding of the file's previous content unless other
+/// [write options][PrivateFileHandle::set_write_options] are set.
+///
+/// The handle edits the file in place, so it's up to the caller to prepare the
+/// file for its next revision (e.g. by getting it via [`PrivateDirectory::open_file_mut`])
+/// and to store it afterwards.
+///
+/// Handles can [read ahead][PrivateFileHandle::set_read_ahead] of sequential
+/// access to hide the latency of the block store, e.g. for media playback or
+/// large copies.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use futures::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use std::io::SeekFrom;
+/// use wnfs::{
+///     private::{
+///         PrivateDirectory, PrivateFileHandle,
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///     },
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let path = &["hello.txt".into()];
+///
+///     root_dir
+///         .write(path, true, Utc::now(), b"Hello, World!".to_vec(), forest, store, rng)
+///         .await?;
+///
+///     let file = root_dir
+///         .open_file_mut(path, true, Utc::now(), forest, store, rng)
+///         .await?;
+///     let mut handle = PrivateFileHandle::open(file, Utc::now(), forest, store, rng).await?;
+///
+///     handle.seek(SeekFrom::Start(7)).await?;
+///     handle.write_all(b"WNFS!").await?;
+///     handle.seek(SeekFrom::Start(0)).await?;
+///
+///     let mut content = String::new();
+///     handle.read_to_string(&mut content).await?;
+///     assert_eq!(content, "Hello, WNFS!!");
+///
+///     handle.close().await?;
+///     drop(handle);
+///
+///     let content = root_dir.read(path, true, forest, store).await?;
+///     assert_eq!(content, b"Hello, WNFS!!");
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [`PrivateDirectory::open_file_mut`]: crate::private::PrivateDirectory::open_file_mut
+pub struct PrivateFileHandle<'a, F, B, R>
+where
+    F: PrivateForest,
+    B: BlockStore,
+    R: CryptoRngCore + CondSend,
+{
+    state: HandleState<'a, F, B, R>,
+    position: u64,
+    read_ahead: usize,
+}
+
+/// A chunk of content with its index.
+type Chunk = (u64, Vec<u8>);
+
+/// An operation that holds on to the handle's state until it's done.
+type PendingOp<'a, F, B, R, T> = BoxFuture<'a, (HandleInner<'a, F, B, R>, T)>;
+
+enum HandleState<'a, F, B, R> {
+    Idle(HandleInner<'a, F, B, R>),
+    Loading(PendingOp<'a, F, B, R, Result<Vec<Chunk>>>),
+    Flushing(PendingOp<'a, F, B, R, Result<()>>),
+    Poisoned,
+}
+
+struct HandleInner<'a, F, B, R> {
+    file: &'a mut PrivateFile,
+    time: DateTime<Utc>,
+    forest: &'a mut F,
+    store: &'a B,
+    rng: &'a mut R,
+    options: WriteOptions,
+    /// The size of the content including all buffered changes.
+    size: u64,
+    /// The number of bytes at the start of the file's current content that are still valid.
+    original_size: u64,
+    /// Chunks that were written to, by chunk index.
+    dirty: BTreeMap<u64, Vec<u8>>,
+    /// The most recently loaded chunk that wasn't written to.
+    cached: Option<(u64, Vec<u8>)>,
+    /// Chunks that were loaded ahead of sequential reads, by chunk index.
+    prefetched: BTreeMap<u64, Vec<u8>>,
+    /// The index of the last chunk that was loaded, to detect sequential access.
+    last_loaded: Option<u64>,
+    modified: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, F, B, R> PrivateFileHandle<'a, F, B, R>
+where
+    F: PrivateForest,
+    B: BlockStore,
+    R: CryptoRngCore + CondSend,
+{
+    /// Opens a handle to given file, positioned at the start of its content.
+    ///
+    /// Given time is used as the modification time when flushing changes.
+    /// Append-only logs can't be opened, since handles rewrite content.
+    pub async fn open(
+        file: &'a mut PrivateFile,
+        time: DateTime<Utc>,
+        forest: &'a mut F,
+        store: &'a B,
+        rng: &'a mut R,
+    ) -> Result<Self> {
+        if let FileContent::Log(_) = file.content.content {
+            bail!(FsError::LogFileContent);
+        }
+
+        let size = file.size(forest, store).await?;
+        let options = WriteOptions {
+            compression: file.get_compression(),
+            padding: file.get_padding(),
+            ..Default::default()
+        };
+
+        Ok(Self {
+            state: HandleState::Idle(HandleInner {
+                file,
+                time,
+                forest,
+                store,
+                rng,
+                options,
+                size,
+                original_size: size,
+                dirty: BTreeMap::new(),
+                cached: None,
+                prefetched: BTreeMap::new(),
+                last_loaded: None,
+                modified: false,
+            }),
+            position: 0,
+            read_ahead: 0,
+        })
+    }
+
+    /// The current position of the handle in bytes from the start of the content.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The number of chunks that are loaded ahead of sequential access.
+    pub fn read_ahead(&self) -> usize {
+        self.read_ahead
+    }
+
+    /// Sets the number of chunks to load ahead, once chunks are accessed sequentially.
+    ///
+    /// Chunks that are loaded ahead are fetched from the block store concurrently with
+    /// the chunk that's needed next. Read-ahead is off (`0`) by default.
+    pub fn set_read_ahead(&mut self, chunks: usize) {
+        self.read_ahead = chunks;
+    }
+
+    /// The options that flushed content is written with.
+    pub fn write_options(&self) -> Result<&WriteOptions> {
+        Ok(&self.idle()?.options)
+    }
+
+    /// Sets the options to write content with on the next flush.
+    ///
+    /// By default, flushes keep the compression and padding of the file's previous content.
+    pub fn set_write_options(&mut self, options: WriteOptions) -> Result<()> {
+        self.idle_mut()?.options = options;
+        Ok(())
+    }
+
+    /// The size of the content in bytes, including changes that haven't been flushed yet.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.idle()?.size)
+    }
+
+    /// Truncates or extends the content to given size. Extended content is filled with zeros.
+    ///
+    /// The position of the handle stays the same.
+    pub fn set_size(&mut self, size: u64) -> Result<()> {
+        let inner = self.idle_mut()?;
+        inner.size = size;
+        inner.original_size = inner.original_size.min(size);
+        inner.dirty.retain(|index, _| index * CHUNK_SIZE < size);
+        if let Some((index, chunk)) = inner.dirty.last_key_value() {
+            let len = inner.chunk_len(*index) as usize;
+            if chunk.len() > len {
+                inner.dirty.insert(*index, chunk[..len].to_vec());
+            }
+        }
+        inner.cached = None;
+        inner.prefetched.clear();
+        inner.modified = true;
+
+        Ok(())
+    }
+
+    fn idle(&self) -> Result<&HandleInner<'a, F, B, R>> {
+        match &self.state {
+            HandleState::Idle(inner) => Ok(inner),
+            _ => bail!("File handle is busy with a pending operation"),
+        }
+    }
+
+    fn idle_mut(&mut self) -> Result<&mut HandleInner<'a, F, B, R>> {
+        match &mut self.state {
+            HandleState::Idle(inner) => Ok(inner),
+            _ => bail!("File handle is busy with a pending operation"),
+        }
+    }
+
+    /// Drives any pending load or flush to completion.
+    fn poll_idle(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<&mut HandleInner<'a, F, B, R>>> {
+        loop {
+            match &mut self.state {
+                HandleState::Idle(_) => break,
+                HandleState::Loading(future) => {
+                    let (mut inner, result) = ready!(future.poll_unpin(cx));
+                    let result = result.map(|chunks| {
+                        let mut chunks = chunks.into_iter();
+                        inner.cached = chunks.next();
+                        inner.prefetched = chunks.collect();
+                        inner.last_loaded = inner
+                            .prefetched
+                            .keys()
+                            .last()
+                            .copied()
+                            .or(inner.cached.as_ref().map(|(index, _)| *index));
+                    });
+                    self.state = HandleState::Idle(inner);
+                    result.map_err(io::Error::other)?;
+                }
+                HandleState::Flushing(future) => {
+                    let (inner, result) = ready!(future.poll_unpin(cx));
+                    self.state = HandleState::Idle(inner);
+                    result.map_err(io::Error::other)?;
+                }
+                HandleState::Poisoned => {
+                    return Poll::Ready(Err(io::Error::other(
+                        "File handle was poisoned by a panic",
+                    )))
+                }
+            }
+        }
+
+        match &mut self.state {
+            HandleState::Idle(inner) => Poll::Ready(Ok(inner)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn start_loading(&mut self, index: u64) {
+        let HandleState::Idle(inner) = std::mem::replace(&mut self.state, HandleState::Poisoned)
+        else {
+            unreachable!()
+        };
+
+        // Only read ahead once the previous load was for the chunk right before this one
+        let sequential = inner.last_loaded.is_some_and(|last| last + 1 == index);
+        let read_ahead = if sequential {
+            self.read_ahead as u64
+        } else {
+            0
+        };
+        let indices = (index..=index.saturating_add(read_ahead))
+            .take_while(|&i| i == index || i * CHUNK_SIZE < inner.original_size)
+            .filter(|&i| i == index || !inner.dirty.contains_key(&i))
+            .collect();
+
+        self.state = HandleState::Loading(boxed_fut(async move {
+            let result = inner.load_chunks(indices).await;
+            (inner, result)
+        }));
+    }
+
+    fn start_flushing(&mut self) {
+        let HandleState::Idle(mut inner) =
+            std::mem::replace(&mut self.state, HandleState::Poisoned)
+        else {
+            unreachable!()
+        };
+
+        self.state = HandleState::Flushing(boxed_fut(async move {
+            let result = inner.flush().await;
+            (inner, result)
+        }));
+    }
+}
+
+impl<'a, F, B, R> HandleInner<'a, F, B, R>
+where
+    F: PrivateForest,
+    B: BlockStore,
+    R: CryptoRngCore + CondSend,
+{
+    /// The length of the chunk at given index, including buffered changes.
+    fn chunk_len(&self, index: u64) -> u64 {
+        self.size.saturating_sub(index * CHUNK_SIZE).min(CHUNK_SIZE)
+    }
+
+    /// Returns the chunk at given index, if it's available without loading it.
+    fn chunk(&mut self, index: u64) -> Option<&[u8]> {
+        let len = self.chunk_len(index) as usize;
+        if !self.dirty.contains_key(&index) {
+            self.take_prefetched(index);
+        }
+
+        let chunk = match (self.dirty.get_mut(&index), &mut self.cached) {
+            (Some(chunk), _) => chunk,
+            (None, Some((cached_index, chunk))) if *cached_index == index => chunk,
+            _ => return None,
+        };
+
+        // Chunks grow with zeros when the content was extended past them
+        chunk.resize(len, 0);
+        Some(chunk)
+    }
+
+    /// Returns the chunk at given index for writing, if it's available without loading it.
+    fn chunk_mut(&mut self, index: u64, overwrites_chunk: bool) -> Option<&mut Vec<u8>> {
+        if !self.dirty.contains_key(&index) {
+            self.take_prefetched(index);
+            let chunk = match self.cached.take() {
+                Some((cached_index, chunk)) if cached_index == index => chunk,
+                cached => {
+                    self.cached = cached;
+                    if !overwrites_chunk && index * CHUNK_SIZE < self.original_size {
+                        return None;
+                    }
+
+                    Vec::new()
+                }
+            };
+
+            self.dirty.insert(index, chunk);
+        }
+
+        let len = self.chunk_len(index) as usize;
+        let chunk = self.dirty.get_mut(&index)?;
+        chunk.resize(len.max(chunk.len()), 0);
+        Some(chunk)
+    }
+
+    /// Moves the chunk at given index from the prefetched chunks into the cache, if it's there.
+    fn take_prefetched(&mut self, index: u64) {
+        if let Some(chunk) = self.prefetched.remove(&index) {
+            self.cached = Some((index, chunk));
+        }
+    }
+
+    /// Loads the chunks at given indices concurrently, returning them in the same order.
+    fn load_chunks(&self, indices: Vec<u64>) -> impl Future<Output = Result<Vec<Chunk>>> + '_ {
+        future::try_join_all(
+            indices
+                .into_iter()
+                .map(|index| self.load_chunk(index).map_ok(move |chunk| (index, chunk))),
+        )
+    }
+
+    /// Loads the chunk at given index from the file's current content.
+    ///
+    /// The returned future only borrows what it needs, so it stays `Send`
+    /// without requiring the rng to be `Sync`.
+    fn load_chunk(&self, index: u64) -> impl Future<Output = Result<Vec<u8>>> + '_ {
+        let (file, forest, store) = (&*self.file, &*self.forest, self.store);
+        let start = index * CHUNK_SIZE;
+        let end = self.original_size.min(start + CHUNK_SIZE);
+        let len = self.chunk_len(index) as usize;
+
+        async move {
+            let mut chunk = if start >= end {
+                Vec::new()
+            } else {
+                match &file.content.content {
+                    FileContent::Inline { data } => data[start as usize..end as usize].to_vec(),
+                    // Compressed content, or content written with a different block size,
+                    // doesn't line up with chunks
+                    FileContent::External(content)
+                        if content.compression.is_some()
+                            || content.block_content_size != CHUNK_SIZE =>
+                    {
+                        let len = (end - start) as usize;
+                        content.read_at(start, Some(len), forest, store).await?
+                    }
+                    FileContent::External(content) => {
+                        let mut chunk = content.read_block(index, forest, store).await?;
+                        chunk.truncate((end - start) as usize);
+                        chunk
+                    }
+                    FileContent::Log(_) => bail!(FsError::LogFileContent),
+                }
+            };
+
+            chunk.resize(len, 0);
+            Ok(chunk)
+        }
+    }
+
+    /// Writes the content with all buffered changes to the file, replacing its
+    /// content the same way [`PrivateFile::set_content_with_options`] does.
+    ///
+    /// Content is encrypted chunk by chunk as it's loaded, so flushes only hold a
+    /// single chunk in memory besides the buffered ones, no matter the file size.
+    /// With a convergence secret, the content is read twice, to derive its key first.
+    async fn flush(&mut self) -> Result<()> {
+        if !self.modified {
+            return Ok(());
+        }
+
+        let chunk_count = self.size.div_ceil(CHUNK_SIZE);
+        let content_key = match &self.options.convergence {
+            Some(secret) => {
+                let mut hasher = ContentKeyHasher::new(secret, self.size, &self.options);
+                for index in 0..chunk_count {
+                    hasher.update(&self.flushed_chunk(index).await?);
+                }
+
+                Some(hasher.finalize()?)
+            }
+            None => None,
+        };
+
+        let name = self.file.header.get_name();
+        let mut writer = ContentWriter::new(
+            name,
+            self.size,
+            &self.options,
+            content_key,
+            self.forest,
+            self.rng,
+        );
+        let mut content_type = None;
+        for index in 0..chunk_count {
+            let chunk = self.flushed_chunk(index).await?;
+            content_type.get_or_insert_with(|| self.options.content_type_update(&chunk));
+            writer
+                .write(&chunk, self.forest, self.store, self.rng)
+                .await?;
+        }
+
+        let content = writer.finish(self.forest, self.store, self.rng).await?;
+        let content_type = content_type.unwrap_or_else(|| self.options.content_type_update(&[]));
+        self.file.content.content = FileContent::External(content);
+        self.file.content.metadata.upsert_mtime(self.time);
+        content_type.apply(&mut self.file.content.metadata);
+
+        self.original_size = self.size;
+        self.dirty.clear();
+        self.cached = None;
+        self.prefetched.clear();
+        self.last_loaded = None;
+        self.modified = false;
+
+        Ok(())
+    }
+
+    /// Gets the chunk at given index with all buffered changes, loading it if it wasn't
+    /// written to.
+    ///
+    /// Like [`Self::load_chunk`], the returned future only borrows what it needs.
+    fn flushed_chunk(&self, index: u64) -> impl Future<Output = Result<Vec<u8>>> + '_ {
+        let dirty = self.dirty.get(&index).cloned();
+        let len = self.chunk_len(index) as usize;
+        let load = self.load_chunk(index);
+
+        async move {
+            let mut chunk = match dirty {
+                Some(chunk) => chunk,
+                None => load.await?,
+            };
+            chunk.resize(len, 0);
+            Ok(chunk)
+        }
+    }
+}
+
+impl<'a, F, B, R> AsyncRead for PrivateFileHandle<'a, F, B, R>
+where
+    F: PrivateForest,
+    B: BlockStore,
+    R: CryptoRngCore + CondSend,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let position = this.position;
+            let inner = ready!(this.poll_idle(cx))?;
+            if buf.is_empty() || position >= inner.size {
+                return Poll::Ready(Ok(0));
+            }
+
+            let index = position / CHUNK_SIZE;
+            let offset = (position % CHUNK_SIZE) as usize;
+            match inner.chunk(index) {
+                Some(chunk) => {
+                    let read = buf.len().min(chunk.len() - offset);
+                    buf[..read].copy_from_slice(&chunk[offset..offset + read]);
+                    this.position += read as u64;
+                    return Poll::Ready(Ok(read));
+                }
+                None => this.start_loading(index),
+            }
+        }
+    }
+}
+
+impl<'a, F, B, R> AsyncWrite for PrivateFileHandle<'a, F, B, R>
+where
+    F: PrivateForest,
+    B: BlockStore,
+    R: CryptoRngCore + CondSend,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let position = this.position;
+            let inner = ready!(this.poll_idle(cx))?;
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            let index = position / CHUNK_SIZE;
+            let offset = (position % CHUNK_SIZE) as usize;
+            let written = buf.len().min(CHUNK_SIZE as usize - offset);
+            let overwrites_chunk = offset == 0 && written == CHUNK_SIZE as usize;
+            if inner.chunk_mut(index, overwrites_chunk).is_none() {
+                this.start_loading(index);
+                continue;
+            }
+
+            inner.size = inner.size.max(position + written as u64);
+            inner.modified = true;
+            if let Some(chunk) = inner.chunk_mut(index, overwrites_chunk) {
+                chunk[offset..offset + written].copy_from_slice(&buf[..written]);
+            }
+
+            this.position += written as u64;
+            return Poll::Ready(Ok(written));
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !ready!(this.poll_idle(cx))?.modified {
+            return Poll::Ready(Ok(()));
+        }
+
+        this.start_flushing();
+        ready!(this.poll_idle(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<'a, F, B, R> AsyncSeek for PrivateFileHandle<'a, F, B, R>
+where
+    F: PrivateForest,
+    B: BlockStore,
+    R: CryptoRngCore + CondSend,
+{
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        let size = ready!(this.poll_idle(cx))?.size;
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => this.position.checked_add_signed(offset),
+        };
+
+        match position {
+            Some(position) => {
+                this.position = position;
+                Poll::Ready(Ok(position))
+            }
+            None => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            ))),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        options::WriteOptions,
+        private::{forest::hamt::HamtForest, Compression, ConvergenceSecret, Padding},
+    };
+    use futures::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::{RngCore, SeedableRng};
+    use testresult::TestResult;
+    use wnfs_common::{utils::get_random_bytes, MemoryBlockStore};
+
+    #[async_std::test]
+    async fn writes_across_chunk_boundaries_are_flushed() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let mut expected = get_random_bytes::<{ 2 * MAX_BLOCK_CONTENT_SIZE + 100 }>(rng).to_vec();
+        let file = &mut PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            expected.clone(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        let patch = vec![7u8; 1000];
+        let offset = MAX_BLOCK_CONTENT_SIZE - 500;
+        expected[offset..offset + patch.len()].copy_from_slice(&patch);
+        expected.extend_from_slice(b"appended");
+
+        let mut handle = PrivateFileHandle::open(file, Utc::now(), forest, store, rng).await?;
+        handle.seek(SeekFrom::Start(offset as u64)).await?;
+        handle.write_all(&patch).await?;
+        handle.seek(SeekFrom::End(0)).await?;
+        handle.write_all(b"appended").await?;
+        assert_eq!(handle.size()?, expected.len() as u64);
+
+        handle.seek(SeekFrom::Start(0)).await?;
+        let mut buffered = Vec::new();
+        handle.read_to_end(&mut buffered).await?;
+        assert_eq!(buffered, expected);
+
+        handle.flush().await?;
+        drop(handle);
+
+        let content = file.get_content(forest, store).await?;
+        assert_eq!(content, expected);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn set_size_truncates_and_zero_extends() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            b"Hello, World!".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        let mut handle = PrivateFileHandle::open(file, Utc::now(), forest, store, rng).await?;
+        handle.set_size(5)?;
+        handle.set_size(8)?;
+        handle.seek(SeekFrom::End(0)).await?;
+        handle.write_all(b"!").await?;
+        handle.close().await?;
+        drop(handle);
+
+        let content = file.get_content(forest, store).await?;
+        assert_eq!(content, b"Hello\0\0\0!");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn empty_files_stay_readable() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+
+        let mut handle = PrivateFileHandle::open(file, Utc::now(), forest, store, rng).await?;
+        let mut content = Vec::new();
+        handle.read_to_end(&mut content).await?;
+        assert!(content.is_empty());
+
+        handle.write_all(b"data").await?;
+        handle.set_size(0)?;
+        handle.close().await?;
+        drop(handle);
+
+        assert_eq!(file.size(forest, store).await?, 0);
+        assert!(file.get_content(forest, store).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn flushes_keep_the_padding_scheme() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        let padding = Padding::Bucket(CHUNK_SIZE + 1);
+        let options = &WriteOptions {
+            padding: Some(padding),
+            ..Default::default()
+        };
+        file.set_content_with_options(b"Hello".to_vec(), options, Utc::now(), forest, store, rng)
+            .await?;
+
+        let mut handle = PrivateFileHandle::open(file, Utc::now(), forest, store, rng).await?;
+        handle.seek(SeekFrom::End(0)).await?;
+        handle.write_all(b", World!").await?;
+        handle.close().await?;
+        drop(handle);
+
+        let FileContent::External(content) = &file.content.content else {
+            panic!("Expected external content");
+        };
+        assert_eq!(content.block_count, 2);
+        assert_eq!(file.get_padding(), Some(padding));
+        assert_eq!(file.size(forest, store).await?, 13);
+        assert_eq!(file.get_content(forest, store).await?, b"Hello, World!");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn flushes_write_content_with_the_write_options() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = WriteOptions {
+            sniff_content_type: true,
+            compression: Some(Compression::Deflate),
+            padding: Some(Padding::Padme),
+            convergence: Some(ConvergenceSecret::new(rng)),
+            ..Default::default()
+        };
+        let mut expected = b"Hello, World!\n".repeat(50_000);
+
+        let file = &mut PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            expected.clone(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        let png = b"\x89PNG\r\n\x1a\n";
+        let offset = 2 * CHUNK_SIZE + 7;
+        let mut handle = PrivateFileHandle::open(file, Utc::now(), forest, store, rng).await?;
+        handle.set_write_options(options.clone())?;
+        handle.write_all(png).await?;
+        handle.seek(SeekFrom::Start(offset)).await?;
+        handle.write_all(b"WNFS!").await?;
+        handle.close().await?;
+        drop(handle);
+        expected[..png.len()].copy_from_slice(png);
+        expected[offset as usize..offset as usize + 5].copy_from_slice(b"WNFS!");
+
+        assert_eq!(file.get_compression(), Some(Compression::Deflate));
+        assert_eq!(file.get_content(forest, store).await?, expected);
+        assert_eq!(file.get_metadata().get_content_type(), Some("image/png"));
+
+        // Convergent content is the same as if it was set directly
+        let other = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        other
+            .set_content_with_options(expected, &options, Utc::now(), forest, store, rng)
+            .await?;
+        let flushed = file.view_token(forest, store).await?;
+        let set = other.view_token(forest, store).await?;
+        assert!(flushed.get_block_cids().eq(set.get_block_cids()));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn sequential_reads_load_chunks_ahead() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let mut expected = vec![0; 6 * MAX_BLOCK_CONTENT_SIZE + 100];
+        rng.fill_bytes(&mut expected);
+        let file = &mut PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            expected.clone(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        let mut handle = PrivateFileHandle::open(file, Utc::now(), forest, store, rng).await?;
+        handle.set_read_ahead(3);
+
+        let mut chunk = vec![0; MAX_BLOCK_CONTENT_SIZE];
+        handle.read_exact(&mut chunk).await?;
+        assert!(handle.idle()?.prefetched.is_empty());
+
+        // The second chunk in a row is loaded together with the next three
+        handle.read_exact(&mut chunk).await?;
+        let prefetched = handle
+            .idle()?
+            .prefetched
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(prefetched, [2, 3, 4]);
+
+        // Writes to chunks that were loaded ahead take effect
+        handle.seek(SeekFrom::Start(3 * CHUNK_SIZE)).await?;
+        handle.write_all(b"patch").await?;
+        let offset = 3 * MAX_BLOCK_CONTENT_SIZE;
+        expected[offset..offset + 5].copy_from_slice(b"patch");
+
+        handle.seek(SeekFrom::Start(0)).await?;
+        let mut content = Vec::new();
+        handle.read_to_end(&mut content).await?;
+        assert_eq!(content, expected);
+
+        handle.close().await?;
+        drop(handle);
+        assert_eq!(file.get_content(forest, store).await?, expected);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+use super::{hamt::NAME_CACHE_CAPACITY, traits::PrivateForest};
+use anyhow::Result;
+use futures::Future;
+use libipld_core::cid::Cid;
+use parking_lot::Mutex;
+use quick_cache::sync::Cache;
+use rand_core::CryptoRngCore;
+use std::collections::{BTreeMap, BTreeSet};
+use wnfs_common::{
+    utils::{Arc, CondSend, CondSync},
+    BlockStore, HashOutput,
+};
+use wnfs_hamt::{Hasher, Pair};
+use wnfs_nameaccumulator::{AccumulatorSetup, ElementsProof, Name, NameAccumulator};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A key-value database that maps forest labels to the CIDs of the ciphertexts stored
+/// under them, like a table in sled or SQLite.
+///
+/// Each label is the hash of a name accumulator, so its set of CIDs can be looked up
+/// with a single query, instead of walking a HAMT block by block.
+pub trait ForestDatabase: CondSync {
+    /// Gets the set of CIDs stored under given label.
+    fn get(
+        &self,
+        label: &HashOutput,
+    ) -> impl Future<Output = Result<Option<BTreeSet<Cid>>>> + CondSend;
+
+    /// Replaces the set of CIDs stored under given label.
+    fn put(
+        &self,
+        label: &HashOutput,
+        cids: &BTreeSet<Cid>,
+    ) -> impl Future<Output = Result<()>> + CondSend;
+
+    /// Adds given CIDs to the set stored under given label, creating it if it's missing.
+    ///
+    /// This has to happen atomically, e.g. in a single transaction, so CIDs that
+    /// concurrent writers add under the same label aren't lost.
+    fn extend(
+        &self,
+        label: &HashOutput,
+        cids: &BTreeSet<Cid>,
+    ) -> impl Future<Output = Result<()>> + CondSend;
+
+    /// Removes given label and returns the set of CIDs that was stored under it.
+    fn remove(
+        &self,
+        label: &HashOutput,
+    ) -> impl Future<Output = Result<Option<BTreeSet<Cid>>>> + CondSend;
+}
+
+/// A [`ForestDatabase`] that keeps all labels in memory.
+#[derive(Debug, Default)]
+pub struct MemoryForestDatabase {
+    entries: Mutex<BTreeMap<HashOutput, BTreeSet<Cid>>>,
+}
+
+/// A private forest that stores labels and their CIDs in a [`ForestDatabase`] directly.
+///
+/// Unlike a [`HamtForest`](super::hamt::HamtForest), this forest isn't content-addressed,
+/// so it can't be stored in a block store, diffed or merged. It's meant for servers that
+/// keep the authoritative forest in their own database, where looking up a label shouldn't
+/// take more than a single query.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{
+///         forest::{
+///             database::{DatabaseForest, MemoryForestDatabase},
+///             traits::PrivateForest,
+///         },
+///         PrivateDirectory, PrivateNode,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut DatabaseForest::new_rsa_2048(MemoryForestDatabase::default(), rng);
+///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///
+///     let path = &["hello.txt".into()];
+///     root.write(path, true, Utc::now(), b"Hello".to_vec(), forest, store, rng)
+///         .await?;
+///     let access_key = root.as_node().store(forest, store, rng).await?;
+///
+///     let loaded = PrivateNode::load(&access_key, forest, store, None).await?;
+///     let content = loaded.as_dir()?.read(path, true, forest, store).await?;
+///
+///     assert_eq!(content, b"Hello");
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DatabaseForest<D: ForestDatabase> {
+    database: D,
+    accumulator: AccumulatorSetup,
+    name_cache: Arc<Cache<Name, (NameAccumulator, ElementsProof)>>,
+    accept_unbound: bool,
+    deterministic_nonces: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<D: ForestDatabase> DatabaseForest<D> {
+    /// Creates a forest on top of given database, with given accumulator setup.
+    ///
+    /// The same setup has to be used every time the forest is created on top of the
+    /// same database.
+    pub fn new(database: D, setup: AccumulatorSetup) -> Self {
+        Self {
+            database,
+            accumulator: setup,
+            name_cache: Arc::new(Cache::new(NAME_CACHE_CAPACITY)),
+            accept_unbound: false,
+            deterministic_nonces: false,
+        }
+    }
+
+    /// Creates a forest on top of given database, with an accumulator setup based on the
+    /// factors of the RSA-2048 factoring challenge modulus.
+    pub fn new_rsa_2048(database: D, rng: &mut impl CryptoRngCore) -> Self {
+        Self::new(database, AccumulatorSetup::from_rsa_2048(rng))
+    }
+
+    /// Sets whether nodes and file content stored in this forest may be read from ciphertexts
+    /// that aren't bound to their labels, see [`PrivateForest::accepts_unbound_ciphertexts`].
+    ///
+    /// The database only holds labels, so like the accumulator setup, this has to be set
+    /// every time the forest is created on top of a database with ciphertexts written
+    /// before they were bound to their labels.
+    pub fn set_accept_unbound_ciphertexts(&mut self, accept: bool) {
+        self.accept_unbound = accept;
+    }
+
+    /// Sets whether everything written to this forest is encrypted with deterministic nonces,
+    /// see [`PrivateForest::uses_deterministic_nonces`].
+    pub fn set_deterministic_nonces(&mut self, deterministic: bool) {
+        self.deterministic_nonces = deterministic;
+    }
+
+    /// Returns the database this forest is stored in.
+    pub fn database(&self) -> &D {
+        &self.database
+    }
+}
+
+impl<D: ForestDatabase> PrivateForest for DatabaseForest<D> {
+    fn empty_name(&self) -> Name {
+        Name::empty(&self.accumulator)
+    }
+
+    fn get_accumulator_setup(&self) -> &AccumulatorSetup {
+        &self.accumulator
+    }
+
+    fn get_proven_name(&self, name: &Name) -> (NameAccumulator, ElementsProof) {
+        match self
+            .name_cache
+            .get_or_insert_with(name, || Ok(name.into_proven_accumulator(&self.accumulator)))
+        {
+            Ok(r) => r,
+            Err(r) => r,
+        }
+    }
+
+    fn accepts_unbound_ciphertexts(&self) -> bool {
+        self.accept_unbound
+    }
+
+    fn uses_deterministic_nonces(&self) -> bool {
+        self.deterministic_nonces
+    }
+
+    async fn has_by_hash(&self, name_hash: &HashOutput, _: &impl BlockStore) -> Result<bool> {
+        Ok(self.database.get(name_hash).await?.is_some())
+    }
+
+    async fn has(&self, name: &Name, store: &impl BlockStore) -> Result<bool> {
+        self.has_by_hash(
+            &blake3::Hasher::hash(&self.get_accumulated_name(name)),
+            store,
+        )
+        .await
+    }
+
+    async fn put_encrypted<I>(
+        &mut self,
+        name: &Name,
+        values: I,
+        _: &impl BlockStore,
+    ) -> Result<NameAccumulator>
+    where
+        I: IntoIterator<Item = Cid> + CondSend,
+        I::IntoIter: CondSend,
+    {
+        let accumulator = self.get_accumulated_name(name);
+        let label = &blake3::Hasher::hash(&accumulator);
+
+        let cids = values.into_iter().collect();
+        self.database.extend(label, &cids).await?;
+
+        Ok(accumulator)
+    }
+
+    async fn get_encrypted_by_hash(
+        &self,
+        name_hash: &HashOutput,
+        _: &impl BlockStore,
+    ) -> Result<Option<BTreeSet<Cid>>> {
+        self.database.get(name_hash).await
+    }
+
+    async fn get_encrypted(
+        &self,
+        name: &Name,
+        store: &impl BlockStore,
+    ) -> Result<Option<BTreeSet<Cid>>> {
+        let name_hash = &blake3::Hasher::hash(&self.get_accumulated_name(name));
+        self.get_encrypted_by_hash(name_hash, store).await
+    }
+
+    async fn remove_encrypted(
+        &mut self,
+        name: &Name,
+        _: &impl BlockStore,
+    ) -> Result<Option<Pair<NameAccumulator, BTreeSet<Cid>>>> {
+        let accumulator = self.get_accumulated_name(name);
+        let label = &blake3::Hasher::hash(&accumulator);
+
+        Ok(self
+            .database
+            .remove(label)
+            .await?
+            .map(|cids| Pair::new(accumulator, cids)))
+    }
+}
+
+impl ForestDatabase for MemoryForestDatabase {
+    async fn get(&self, label: &HashOutput) -> Result<Option<BTreeSet<Cid>>> {
+        Ok(self.entries.lock().get(label).cloned())
+    }
+
+    async fn put(&self, label: &HashOutput, cids: &BTreeSet<Cid>) -> Result<()> {
+        self.entries.lock().insert(*label, cids.clone());
+        Ok(())
+    }
+
+    async fn extend(&self, label: &HashOutput, cids: &BTreeSet<Cid>) -> Result<()> {
+        self.entries
+            .lock()
+            .entry(*label)
+            .or_default()
+            .extend(cids.iter().cloned());
+        Ok(())
+    }
+
+    async fn remove(&self, label: &HashOutput) -> Result<Option<BTreeSet<Cid>>> {
+        Ok(self.entries.lock().remove(label))
+    }
+}
+
+impl<D: ForestDatabase> ForestDatabase for &D {
+    async fn get(&self, label: &HashOutput) -> Result<Option<BTreeSet<Cid>>> {
+        (**self).get(label).await
+    }
+
+    async fn put(&self, label: &HashOutput, cids: &BTreeSet<Cid>) -> Result<()> {
+        (**self).put(label, cids).await
+    }
+
+    async fn extend(&self, label: &HashOutput, cids: &BTreeSet<Cid>) -> Result<()> {
+        (**self).extend(label, cids).await
+    }
+
+    async fn remove(&self, label: &HashOutput) -> Result<Option<BTreeSet<Cid>>> {
+        (**self).remove(label).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{PrivateDirectory, PrivateNode};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, CODEC_RAW};
+    use wnfs_nameaccumulator::NameSegment;
+
+    /// Yields before every query, like a database behind a connection would.
+    #[derive(Debug, Default)]
+    struct YieldingDatabase(MemoryForestDatabase);
+
+    impl ForestDatabase for YieldingDatabase {
+        async fn get(&self, label: &HashOutput) -> Result<Option<BTreeSet<Cid>>> {
+            async_std::task::yield_now().await;
+            self.0.get(label).await
+        }
+
+        async fn put(&self, label: &HashOutput, cids: &BTreeSet<Cid>) -> Result<()> {
+            async_std::task::yield_now().await;
+            self.0.put(label, cids).await
+        }
+
+        async fn extend(&self, label: &HashOutput, cids: &BTreeSet<Cid>) -> Result<()> {
+            async_std::task::yield_now().await;
+            self.0.extend(label, cids).await
+        }
+
+        async fn remove(&self, label: &HashOutput) -> Result<Option<BTreeSet<Cid>>> {
+            async_std::task::yield_now().await;
+            self.0.remove(label).await
+        }
+    }
+
+    #[async_std::test]
+    async fn forests_on_the_same_database_see_each_others_writes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let database = &MemoryForestDatabase::default();
+        let setup = AccumulatorSetup::from_rsa_2048(rng);
+        let writer = &mut DatabaseForest::new(database, setup.clone());
+        let reader = &DatabaseForest::new(database, setup);
+
+        let root = &mut PrivateDirectory::new_rc(&writer.empty_name(), Utc::now(), rng);
+        root.write(
+            &["notes.md".into()],
+            true,
+            Utc::now(),
+            b"# Notes".to_vec(),
+            writer,
+            store,
+            rng,
+        )
+        .await?;
+        let access_key = root.as_node().store(writer, store, rng).await?;
+
+        let loaded = PrivateNode::load(&access_key, reader, store, None).await?;
+        let content = loaded
+            .as_dir()?
+            .read(&["notes.md".into()], true, reader, store)
+            .await?;
+        assert_eq!(content, b"# Notes");
+
+        let name = &root.header.get_revision_name();
+        let extra = store.create_cid(b"extra", CODEC_RAW)?;
+        writer.put_encrypted(name, [extra], store).await?;
+        let cids = reader.get_encrypted(name, store).await?.unwrap();
+        assert_eq!(cids.len(), 3);
+        assert!(cids.contains(&extra));
+
+        let removed = writer.remove_encrypted(name, store).await?.unwrap();
+        assert_eq!(removed.value, cids);
+        assert!(!reader.has(name, store).await?);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn concurrent_writers_keep_each_others_cids() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let database = &YieldingDatabase::default();
+        let setup = AccumulatorSetup::from_rsa_2048(rng);
+        let name = &Name::empty(&setup).with_segments_added([NameSegment::new(rng)]);
+
+        let mut writers = (0..4)
+            .map(|_| DatabaseForest::new(database, setup.clone()))
+            .collect::<Vec<_>>();
+        let cids = (0..4u8)
+            .map(|i| store.create_cid(&[i], CODEC_RAW))
+            .collect::<Result<BTreeSet<_>, _>>()?;
+        futures::future::try_join_all(
+            writers
+                .iter_mut()
+                .zip(&cids)
+                .map(|(writer, cid)| writer.put_encrypted(name, [*cid], store)),
+        )
+        .await?;
+
+        let reader = DatabaseForest::new(database, setup);
+        assert_eq!(reader.get_encrypted(name, store).await?, Some(cids));
+
+        Ok(())
+    }
+}
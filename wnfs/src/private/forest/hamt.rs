@@ -6,15 +6,17 @@ use quick_cache::sync::Cache;
 use rand_core::CryptoRngCore;
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+};
 use wnfs_common::{
-    impl_storable_from_serde,
-    utils::{Arc, CondSend},
-    BlockStore, HashOutput, Link, Storable,
+    utils::{Arc, BoxStream, CondSend},
+    BlockStore, HashOutput, Storable,
 };
 use wnfs_hamt::{
-    constants::HAMT_VERSION, merge, serializable::NodeSerializable, Hamt, Hasher, KeyValueChange,
-    Node, Pair,
+    constants::HAMT_VERSION, serializable::NodeSerializable, ChangeType, HamtStats, Hasher,
+    KeyValueChange, MergeCheckpoint, MergeProgress, MultiValueHamt, Node, Pair,
 };
 use wnfs_nameaccumulator::{AccumulatorSetup, ElementsProof, Name, NameAccumulator};
 
@@ -22,7 +24,7 @@ const APPROX_CACHE_ENTRY_SIZE: usize =
     std::mem::size_of::<(Name, NameAccumulator, ElementsProof)>();
 /// This gives us a *very rough* 2 MB limit on the cache.
 /// It's sligthly more, since the `NameSegment`s inside the `Name`s aren't accounted for.
-const NAME_CACHE_CAPACITY: usize = 2_000_000 / APPROX_CACHE_ENTRY_SIZE;
+pub(super) const NAME_CACHE_CAPACITY: usize = 2_000_000 / APPROX_CACHE_ENTRY_SIZE;
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -47,9 +49,12 @@ const NAME_CACHE_CAPACITY: usize = 2_000_000 / APPROX_CACHE_ENTRY_SIZE;
 /// ```
 #[derive(Debug, Clone)]
 pub struct HamtForest {
-    hamt: Hamt<NameAccumulator, Ciphertexts, blake3::Hasher>,
+    hamt: MultiValueHamt<NameAccumulator, blake3::Hasher>,
     accumulator: AccumulatorSetup,
     name_cache: Arc<Cache<Name, (NameAccumulator, ElementsProof)>>,
+    accept_unbound: bool,
+    may_hold_unbound: bool,
+    deterministic_nonces: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -58,13 +63,49 @@ pub struct HamtForestSerializable {
     pub(crate) version: Version,
     pub(crate) structure: String,
     pub(crate) accumulator: AccumulatorSetup,
+    /// Whether the forest was stored while accepting unbound ciphertexts. Forests stored
+    /// before ciphertexts were bound to their labels don't have this field.
+    ///
+    /// This is only a hint, see [`HamtForest::may_hold_unbound_ciphertexts`].
+    #[serde(rename = "mayHoldUnbound", default = "may_hold_unbound_if_missing")]
+    pub(crate) may_hold_unbound: bool,
 }
 
-/// Links to ciphertexts
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
-pub struct Ciphertexts(pub BTreeSet<Cid>);
+/// Statistics about the structure and size of a [`HamtForest`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ForestStats {
+    /// Statistics about the forest's HAMT. Its keys are the forest's labels.
+    pub hamt: HamtStats,
+    /// The number of ciphertext CIDs stored across all labels.
+    pub ciphertext_count: usize,
+}
 
-impl_storable_from_serde! { Ciphertexts }
+/// What merging two [`HamtForest`]s changed, as returned by
+/// [`HamtForest::merge_with_outcome`].
+///
+/// All counts are relative to the forest `merge_with_outcome` was called on, so they
+/// describe what was pulled in from the other forest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForestMergeOutcome {
+    /// The number of labels that only existed in the other forest and were added as-is.
+    pub labels_added: usize,
+    /// The number of labels both forests had, with different ciphertexts, that were
+    /// merged automatically by taking the union of their ciphertexts.
+    pub labels_merged: usize,
+    /// The number of merged labels that ended up with more ciphertexts than either
+    /// forest had, i.e. the revisions that were written concurrently and now have to be
+    /// reconciled, see [`PrivateNode::conflict_heads`](crate::private::PrivateNode::conflict_heads).
+    pub conflicts: usize,
+    /// The number of ciphertext CIDs that were added to the forest.
+    pub ciphertexts_added: usize,
+    /// The total size of the ciphertext blocks that were added to the forest, in bytes.
+    /// Blocks that aren't in the block store yet aren't counted.
+    pub bytes_added: u64,
+}
+
+/// Links to ciphertexts
+pub use wnfs_hamt::CidSet as Ciphertexts;
 
 //--------------------------------------------------------------------------------------------------
 // Implementations
@@ -74,9 +115,12 @@ impl HamtForest {
     /// Create a new, empty hamt forest with given pre-run accumulator setup
     pub fn new(setup: AccumulatorSetup) -> Self {
         Self {
-            hamt: Hamt::new(),
+            hamt: MultiValueHamt::new(),
             accumulator: setup,
             name_cache: Arc::new(Cache::new(NAME_CACHE_CAPACITY)),
+            accept_unbound: false,
+            may_hold_unbound: false,
+            deterministic_nonces: false,
         }
     }
 
@@ -124,6 +168,44 @@ impl HamtForest {
         Arc::new(Self::new_trusted(rng))
     }
 
+    /// Sets whether nodes and file content stored in this forest may be read from ciphertexts
+    /// that aren't bound to their labels, see [`PrivateForest::accepts_unbound_ciphertexts`].
+    ///
+    /// Forests never accept unbound ciphertexts unless this is called, including forests
+    /// loaded from a block store. Forest blocks aren't authenticated, so what they say is
+    /// only reported by [`may_hold_unbound_ciphertexts`](Self::may_hold_unbound_ciphertexts).
+    /// This setting isn't carried over from other forests when merging either.
+    ///
+    /// The hint is stored with the forest, so turn acceptance off again once all of a
+    /// forest's history was migrated with [`PrivateNode::reencrypt_history`]. Everything
+    /// written is bound either way.
+    ///
+    /// [`PrivateNode::reencrypt_history`]: crate::private::PrivateNode::reencrypt_history
+    pub fn set_accept_unbound_ciphertexts(&mut self, accept: bool) {
+        self.accept_unbound = accept;
+        self.may_hold_unbound = accept;
+    }
+
+    /// Sets whether everything written to this forest is encrypted with deterministic nonces,
+    /// see [`PrivateForest::uses_deterministic_nonces`].
+    ///
+    /// Like accepting unbound ciphertexts, this isn't stored with the forest, so it has to
+    /// be set again after loading it.
+    pub fn set_deterministic_nonces(&mut self, deterministic: bool) {
+        self.deterministic_nonces = deterministic;
+    }
+
+    /// Whether this forest may still hold ciphertexts that aren't bound to their labels,
+    /// because it was stored before ciphertexts were bound to their labels, or while
+    /// accepting unbound ciphertexts, or was merged with such a forest.
+    ///
+    /// This is read from the forest's block and may have been tampered with, so it never
+    /// turns on accepting unbound ciphertexts by itself. Callers can use it to decide
+    /// whether to opt in with [`set_accept_unbound_ciphertexts`](Self::set_accept_unbound_ciphertexts).
+    pub fn may_hold_unbound_ciphertexts(&self) -> bool {
+        self.may_hold_unbound
+    }
+
     /// Gets the difference in changes between two forests.
     #[inline]
     pub async fn diff(
@@ -138,6 +220,84 @@ impl HamtForest {
         self.hamt.diff(&other.hamt, store).await
     }
 
+    /// Streams the difference in changes between two forests.
+    ///
+    /// Unlike [`diff`](Self::diff), this doesn't collect all changes, so memory use stays
+    /// bounded even for forests with millions of labels.
+    pub fn diff_stream<'a>(
+        &self,
+        other: &Self,
+        store: &'a impl BlockStore,
+    ) -> Result<BoxStream<'a, Result<KeyValueChange<NameAccumulator, Ciphertexts>>>> {
+        if self.accumulator != other.accumulator {
+            return Err(FsError::IncompatibleAccumulatorSetups.into());
+        }
+
+        Ok(self.hamt.diff_stream(&other.hamt, store))
+    }
+
+    /// Collects statistics about this forest, like the number of HAMT nodes and
+    /// labels, how deep the HAMT is and how large it is when serialized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         PrivateDirectory,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let stats = forest.stats(store).await?;
+    ///
+    ///     assert_eq!(stats.hamt.key_count, 1);
+    ///     println!("{stats}");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn stats(&self, store: &impl BlockStore) -> Result<ForestStats> {
+        let hamt = self.hamt.root.stats(store).await?;
+        let ciphertext_count = self
+            .hamt
+            .root
+            .flat_map(&|pair| Ok(pair.value.0.len()), store)
+            .await?
+            .into_iter()
+            .sum();
+
+        Ok(ForestStats {
+            hamt,
+            ciphertext_count,
+        })
+    }
+
+    /// Returns the CIDs of all ciphertexts stored in the forest, across all labels.
+    pub(crate) async fn ciphertext_cids(&self, store: &impl BlockStore) -> Result<BTreeSet<Cid>> {
+        Ok(self
+            .hamt
+            .root
+            .flat_map(&|pair| Ok(pair.value.0.clone()), store)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
     /// Merges a private forest with another. If there is a conflict with the values,they are union
     /// combined into a single value in the final merge node
     ///
@@ -184,7 +344,7 @@ impl HamtForest {
     ///     dir_two.mkdir(&["DirTwo".into()], true, Utc::now(), forest_two, store, rng).await?;
     ///     let access_key = dir_two.as_node().store(forest_two, store, rng).await?;
     ///     let label = access_key.get_label();
-    ///     let key = access_key.get_temporal_key()?;
+    ///     let key = access_key.as_revision().unwrap().get_temporal_key();
     ///
     ///     // Merge the forests together
     ///     let forest_merged = forest_one.merge(forest_two, store).await?;
@@ -204,29 +364,227 @@ impl HamtForest {
     /// }
     /// ```
     pub async fn merge(&self, other: &Self, store: &impl BlockStore) -> Result<Self> {
+        let (merged, _) = self.merge_with_outcome(other, store).await?;
+        Ok(merged)
+    }
+
+    /// Merges a private forest with another like [`merge`](Self::merge), and returns
+    /// a summary of what the merge changed along with the merged forest.
+    ///
+    /// This is meant for sync engines to log or display what a sync pulled in, without
+    /// having to diff the forests afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::{utils::Arc, MemoryBlockStore},
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateDirectory,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir =
+    ///         PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng)
+    ///             .await?;
+    ///
+    ///     // Write the same revision of the root directory concurrently
+    ///     let mut forests = Vec::new();
+    ///     for name in ["DirOne", "DirTwo"] {
+    ///         let forest = &mut Arc::clone(forest);
+    ///         let dir = &mut Arc::clone(&root_dir);
+    ///         dir.mkdir(&[name.into()], true, Utc::now(), forest, store, rng).await?;
+    ///         dir.as_node().store(forest, store, rng).await?;
+    ///         forests.push(Arc::clone(forest));
+    ///     }
+    ///
+    ///     let (_, outcome) = forests[0].merge_with_outcome(&forests[1], store).await?;
+    ///     assert_eq!(outcome.conflicts, 1);
+    ///     println!("{outcome}");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn merge_with_outcome(
+        &self,
+        other: &Self,
+        store: &impl BlockStore,
+    ) -> Result<(Self, ForestMergeOutcome)> {
         if self.accumulator != other.accumulator {
             return Err(FsError::IncompatibleAccumulatorSetups.into());
         }
 
-        let merged_root = merge(
-            Link::from(Arc::clone(&self.hamt.root)),
-            Link::from(Arc::clone(&other.hamt.root)),
-            |a, b| Ok(Ciphertexts(a.0.union(&b.0).cloned().collect())),
-            store,
-        )
-        .await?;
+        let mut outcome = ForestMergeOutcome::default();
+        let mut added_cids = Vec::new();
+        let hamt = self
+            .hamt
+            .merge_observed(
+                &other.hamt,
+                |change, merged| {
+                    let existing = match (change.r#type, &change.value1, &change.value2) {
+                        (ChangeType::Modify, Some(main), Some(other)) => {
+                            if main == merged {
+                                // The other forest's ciphertexts were all known already
+                                return;
+                            }
+
+                            outcome.labels_merged += 1;
+                            if merged.0.len() > main.0.len().max(other.0.len()) {
+                                outcome.conflicts += 1;
+                            }
+                            Some(main)
+                        }
+                        _ => {
+                            outcome.labels_added += 1;
+                            None
+                        }
+                    };
+
+                    added_cids.extend(
+                        merged
+                            .0
+                            .iter()
+                            .filter(|cid| existing.map_or(true, |cids| !cids.0.contains(cid))),
+                    );
+                },
+                store,
+            )
+            .await?;
+
+        outcome.ciphertexts_added = added_cids.len();
+        for cid in added_cids.iter() {
+            if store.has_block(cid).await? {
+                outcome.bytes_added += store.get_block(cid).await?.len() as u64;
+            }
+        }
 
         // TODO(matheus23) Should we find some way to sensibly merge caches?
         let name_cache = self.name_cache.clone();
 
-        Ok(Self {
-            hamt: Hamt {
-                version: self.hamt.version.clone(),
-                root: merged_root,
-            },
+        let merged = Self {
+            hamt,
             accumulator: self.accumulator.clone(),
             name_cache,
-        })
+            accept_unbound: self.accept_unbound,
+            may_hold_unbound: self.may_hold_unbound || other.may_hold_unbound,
+            deterministic_nonces: self.deterministic_nonces,
+        };
+
+        Ok((merged, outcome))
+    }
+
+    /// Merges a private forest with another like [`merge`](Self::merge), but pauses once
+    /// at least `max_changes` labels were merged.
+    ///
+    /// A paused merge returns a [`MergeCheckpoint`] that can be serialized, e.g. before a
+    /// mobile app gets suspended. Calling this again with the same two forests and that
+    /// checkpoint resumes the merge where it paused, instead of starting over.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     hamt::MergeProgress,
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateDirectory,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let base = HamtForest::new_rsa_2048(rng);
+    ///
+    ///     let forest = &mut base.clone();
+    ///     let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     for i in 0..10 {
+    ///         root.mkdir(&[format!("dir-{i}")], true, Utc::now(), forest, store, rng)
+    ///             .await?;
+    ///     }
+    ///     root.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let mut checkpoint = None;
+    ///     let merged = loop {
+    ///         match base.merge_resumable(forest, checkpoint.take(), 5, store).await? {
+    ///             MergeProgress::Done(merged) => break merged,
+    ///             MergeProgress::Paused(paused) => checkpoint = Some(paused),
+    ///         }
+    ///     };
+    ///
+    ///     assert_eq!(merged.diff(forest, store).await?, vec![]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn merge_resumable(
+        &self,
+        other: &Self,
+        checkpoint: Option<MergeCheckpoint>,
+        max_changes: usize,
+        store: &impl BlockStore,
+    ) -> Result<MergeProgress<Self>> {
+        if self.accumulator != other.accumulator {
+            return Err(FsError::IncompatibleAccumulatorSetups.into());
+        }
+
+        let progress = self
+            .hamt
+            .merge_resumable(&other.hamt, checkpoint, max_changes, store)
+            .await?;
+
+        Ok(progress.map(|hamt| Self {
+            hamt,
+            accumulator: self.accumulator.clone(),
+            name_cache: self.name_cache.clone(),
+            accept_unbound: self.accept_unbound,
+            may_hold_unbound: self.may_hold_unbound || other.may_hold_unbound,
+            deterministic_nonces: self.deterministic_nonces,
+        }))
+    }
+}
+
+impl Display for ForestStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "labels:           {}", self.hamt.key_count)?;
+        writeln!(f, "ciphertexts:      {}", self.ciphertext_count)?;
+        write!(f, "{}", self.hamt)
+    }
+}
+
+impl ForestMergeOutcome {
+    /// The number of labels the merge added or changed.
+    pub fn labels_touched(&self) -> usize {
+        self.labels_added + self.labels_merged
+    }
+}
+
+impl Display for ForestMergeOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} labels added, {} merged ({} conflicts), {} ciphertexts ({} bytes) added",
+            self.labels_added,
+            self.labels_merged,
+            self.conflicts,
+            self.ciphertexts_added,
+            self.bytes_added
+        )
     }
 }
 
@@ -250,6 +608,14 @@ impl PrivateForest for HamtForest {
         }
     }
 
+    fn accepts_unbound_ciphertexts(&self) -> bool {
+        self.accept_unbound
+    }
+
+    fn uses_deterministic_nonces(&self) -> bool {
+        self.deterministic_nonces
+    }
+
     async fn has_by_hash(&self, name_hash: &HashOutput, store: &impl BlockStore) -> Result<bool> {
         Ok(self
             .hamt
@@ -278,39 +644,27 @@ impl PrivateForest for HamtForest {
         I::IntoIter: CondSend,
     {
         let accumulator = self.get_accumulated_name(name);
-        let values = values.into_iter();
-
-        match self.hamt.root.get_mut(&accumulator, store).await? {
-            Some(ciphers) => ciphers.0.extend(values),
-            None => {
-                let label = accumulator.clone();
-                let ciphers = Ciphertexts(values.collect());
-                self.hamt.root.set(label, ciphers, store).await?;
-            }
-        }
+        self.hamt
+            .add_values(accumulator.clone(), values, store)
+            .await?;
 
         Ok(accumulator)
     }
 
     #[inline]
-    async fn get_encrypted_by_hash<'b>(
-        &'b self,
+    async fn get_encrypted_by_hash(
+        &self,
         name_hash: &HashOutput,
         store: &impl BlockStore,
-    ) -> Result<Option<&'b BTreeSet<Cid>>> {
-        Ok(self
-            .hamt
-            .root
-            .get_by_hash(name_hash, store)
-            .await?
-            .map(|ciphers| &ciphers.0))
+    ) -> Result<Option<BTreeSet<Cid>>> {
+        Ok(self.hamt.get_by_hash(name_hash, store).await?.cloned())
     }
 
     async fn get_encrypted(
         &self,
         name: &Name,
         store: &impl BlockStore,
-    ) -> Result<Option<&BTreeSet<Cid>>> {
+    ) -> Result<Option<BTreeSet<Cid>>> {
         let name_hash = &blake3::Hasher::hash(&self.get_accumulated_name(name));
         self.get_encrypted_by_hash(name_hash, store).await
     }
@@ -321,15 +675,7 @@ impl PrivateForest for HamtForest {
         store: &impl BlockStore,
     ) -> Result<Option<Pair<NameAccumulator, BTreeSet<Cid>>>> {
         let name_hash = &blake3::Hasher::hash(&self.get_accumulated_name(name));
-        Ok(self
-            .hamt
-            .root
-            .remove_by_hash(name_hash, store)
-            .await?
-            .map(|Pair { key, value }| Pair {
-                key,
-                value: value.0,
-            }))
+        self.hamt.remove_by_hash(name_hash, store).await
     }
 }
 
@@ -346,6 +692,14 @@ impl PrivateForest for Arc<HamtForest> {
         (**self).get_proven_name(name)
     }
 
+    fn accepts_unbound_ciphertexts(&self) -> bool {
+        (**self).accepts_unbound_ciphertexts()
+    }
+
+    fn uses_deterministic_nonces(&self) -> bool {
+        (**self).uses_deterministic_nonces()
+    }
+
     async fn has_by_hash(&self, name_hash: &HashOutput, store: &impl BlockStore) -> Result<bool> {
         (**self).has_by_hash(name_hash, store).await
     }
@@ -367,11 +721,11 @@ impl PrivateForest for Arc<HamtForest> {
         Arc::make_mut(self).put_encrypted(name, values, store).await
     }
 
-    async fn get_encrypted_by_hash<'b>(
-        &'b self,
+    async fn get_encrypted_by_hash(
+        &self,
         name_hash: &HashOutput,
         store: &impl BlockStore,
-    ) -> Result<Option<&'b BTreeSet<Cid>>> {
+    ) -> Result<Option<BTreeSet<Cid>>> {
         (**self).get_encrypted_by_hash(name_hash, store).await
     }
 
@@ -379,7 +733,7 @@ impl PrivateForest for Arc<HamtForest> {
         &self,
         name: &Name,
         store: &impl BlockStore,
-    ) -> Result<Option<&BTreeSet<Cid>>> {
+    ) -> Result<Option<BTreeSet<Cid>>> {
         (**self).get_encrypted(name, store).await
     }
 
@@ -401,6 +755,7 @@ impl Storable for HamtForest {
             version: HAMT_VERSION,
             accumulator: self.accumulator.to_serializable(store).await?,
             structure: "hamt".to_string(),
+            may_hold_unbound: self.may_hold_unbound,
         })
     }
 
@@ -409,16 +764,27 @@ impl Storable for HamtForest {
         serializable: Self::Serializable,
     ) -> Result<Self> {
         Ok(Self {
-            hamt: Hamt::with_root(Arc::new(
+            hamt: MultiValueHamt::with_root(Arc::new(
                 Node::from_serializable(None, serializable.root).await?,
             )),
             accumulator: AccumulatorSetup::from_serializable(None, serializable.accumulator)
                 .await?,
             name_cache: Arc::new(Cache::new(NAME_CACHE_CAPACITY)),
+            accept_unbound: false,
+            may_hold_unbound: serializable.may_hold_unbound,
+            deterministic_nonces: false,
         })
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn may_hold_unbound_if_missing() -> bool {
+    true
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -427,10 +793,13 @@ impl Storable for HamtForest {
 mod tests {
     use super::*;
     use crate::private::{PrivateDirectory, PrivateNode};
-    use chrono::Utc;
+    use chrono::{DateTime, Utc};
+    use futures::TryStreamExt;
+    use libipld_core::{ipld::Ipld, serde::to_ipld};
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
-    use wnfs_common::MemoryBlockStore;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, CODEC_DAG_CBOR, CODEC_RAW};
     use wnfs_nameaccumulator::NameSegment;
 
     #[async_std::test]
@@ -451,7 +820,32 @@ mod tests {
             .unwrap();
         let result = forest.get_encrypted(&name, store).await.unwrap();
 
-        assert_eq!(result, Some(&BTreeSet::from([cid])));
+        assert_eq!(result, Some(BTreeSet::from([cid])));
+    }
+
+    #[async_std::test]
+    async fn stats_count_labels_and_ciphertexts() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let cids = [
+            store.put_block(b"one".to_vec(), CODEC_RAW).await.unwrap(),
+            store.put_block(b"two".to_vec(), CODEC_RAW).await.unwrap(),
+        ];
+        for i in 0..100_u32 {
+            let name = forest
+                .empty_name()
+                .with_segments_added([NameSegment::new_hashed("Testing", i.to_le_bytes())]);
+            forest.put_encrypted(&name, cids, store).await.unwrap();
+        }
+
+        let stats = forest.stats(store).await.unwrap();
+
+        assert_eq!(stats.hamt.key_count, 100);
+        assert_eq!(stats.ciphertext_count, 200);
+        assert!(stats.hamt.node_count > 1);
+        assert!(stats.to_string().contains("labels:           100"));
     }
 
     #[async_std::test]
@@ -470,6 +864,75 @@ mod tests {
         assert_eq!(retrieved, private_node);
     }
 
+    #[async_std::test]
+    async fn diff_stream_yields_added_labels() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let base = HamtForest::new_rsa_2048(rng);
+        let forest = &mut base.clone();
+
+        let name = &forest
+            .empty_name()
+            .with_segments_added([NameSegment::new(rng)]);
+        let cid = store.create_cid(b"ciphertext", CODEC_RAW).unwrap();
+        forest.put_encrypted(name, [cid], store).await.unwrap();
+
+        let changes: Vec<_> = forest
+            .diff_stream(&base, store)
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].r#type, ChangeType::Add);
+        assert_eq!(changes[0].key, forest.get_accumulated_name(name));
+
+        let other_setup = HamtForest::new_rsa_2048(rng);
+        assert!(forest.diff_stream(&other_setup, store).is_err());
+    }
+
+    #[async_std::test]
+    async fn merge_outcome_counts_what_was_pulled_in() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let main = &mut HamtForest::new_rsa_2048(rng);
+        let other = &mut main.clone();
+        let [shared, only_main, only_other, missing] = [(); 4].map(|_| {
+            main.empty_name()
+                .with_segments_added([NameSegment::new(rng)])
+        });
+
+        let cid = store.put_block(b"main".to_vec(), CODEC_RAW).await?;
+        main.put_encrypted(&shared, [cid], store).await?;
+        main.put_encrypted(&only_main, [cid], store).await?;
+
+        let cid = store.put_block(b"other".to_vec(), CODEC_RAW).await?;
+        other.put_encrypted(&shared, [cid], store).await?;
+        other.put_encrypted(&only_other, [cid], store).await?;
+        // A ciphertext that hasn't been fetched yet
+        let cid = store.create_cid(b"missing", CODEC_RAW)?;
+        other.put_encrypted(&missing, [cid], store).await?;
+
+        let (merged, outcome) = main.merge_with_outcome(other, store).await?;
+        assert_eq!(
+            outcome,
+            ForestMergeOutcome {
+                labels_added: 2,
+                labels_merged: 1,
+                conflicts: 1,
+                ciphertexts_added: 3,
+                bytes_added: 2 * b"other".len() as u64,
+            }
+        );
+        assert_eq!(outcome.labels_touched(), 3);
+
+        let (_, outcome) = merged.merge_with_outcome(other, store).await?;
+        assert_eq!(outcome, ForestMergeOutcome::default());
+
+        Ok(())
+    }
+
     #[async_std::test]
     async fn multivalue_conflict_can_be_fetched_individually() {
         let store = &mut MemoryBlockStore::new();
@@ -527,6 +990,84 @@ mod tests {
         assert_eq!(retrieved, private_node);
         assert_eq!(retrieved_conflict, private_node_conflict);
     }
+
+    #[async_std::test]
+    async fn accepting_unbound_ciphertexts_is_never_loaded_from_the_forest() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048(rng);
+        assert!(!forest.accepts_unbound_ciphertexts());
+        assert!(!forest.may_hold_unbound_ciphertexts());
+
+        let loaded = HamtForest::load(&forest.store(store).await?, store).await?;
+        assert!(!loaded.accepts_unbound_ciphertexts());
+        assert!(!loaded.may_hold_unbound_ciphertexts());
+
+        forest.set_accept_unbound_ciphertexts(true);
+        let loaded = HamtForest::load(&forest.store(store).await?, store).await?;
+        assert!(!loaded.accepts_unbound_ciphertexts());
+        assert!(loaded.may_hold_unbound_ciphertexts());
+
+        // Forests stored before ciphertexts were bound to labels don't have the field
+        let Ipld::Map(mut legacy) = to_ipld(forest.to_serializable(store).await?)? else {
+            panic!("Expected forest to serialize to a map");
+        };
+        legacy.remove("mayHoldUnbound");
+        let legacy_bytes = serde_ipld_dagcbor::to_vec(&Ipld::Map(legacy))?;
+        let legacy_cid = store.put_block(legacy_bytes, CODEC_DAG_CBOR).await?;
+        let loaded = HamtForest::load(&legacy_cid, store).await?;
+        assert!(!loaded.accepts_unbound_ciphertexts());
+        assert!(loaded.may_hold_unbound_ciphertexts());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn deterministic_nonces_make_forests_reproducible() -> TestResult {
+        async fn build(deterministic: bool, time: DateTime<Utc>) -> Result<Cid> {
+            let store = &MemoryBlockStore::new();
+            let rng = &mut ChaCha12Rng::seed_from_u64(0);
+            let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+            Arc::make_mut(forest).set_deterministic_nonces(deterministic);
+
+            let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+            let notes = &["docs".into(), "notes.md".into()];
+            let large = &["large.bin".into()];
+            root_dir
+                .write(notes, true, time, b"# Notes".to_vec(), forest, store, rng)
+                .await?;
+            root_dir
+                .write(large, true, time, vec![7; 300_000], forest, store, rng)
+                .await?;
+
+            // Nodes are stored with unrelated randomness, which only deterministic nonces ignore
+            let nonce_rng = &mut ChaCha12Rng::from_entropy();
+            root_dir.as_node().store(forest, store, nonce_rng).await?;
+            forest.store(store).await
+        }
+
+        let time = Utc::now();
+        let deterministic = build(true, time).await?;
+        assert_eq!(build(true, time).await?, deterministic);
+        assert_ne!(build(false, time).await?, build(false, time).await?);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn merging_keeps_accepting_unbound_ciphertexts_local() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048(rng);
+        let other = &mut forest.clone();
+        other.set_accept_unbound_ciphertexts(true);
+
+        let merged = forest.merge(other, store).await?;
+        assert!(!merged.accepts_unbound_ciphertexts());
+        assert!(merged.may_hold_unbound_ciphertexts());
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
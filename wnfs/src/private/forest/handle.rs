@@ -0,0 +1,330 @@
+use super::{hamt::HamtForest, traits::PrivateForest};
+use crate::private::{AccessKey, PrivateDirectory, PrivateFile, PrivateNode};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::AsyncRead;
+use rand_core::CryptoRngCore;
+use tokio::sync::{RwLock, RwLockWriteGuard};
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore, Metadata,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A private forest that can be shared between concurrent tasks.
+///
+/// Passing `&mut` forests around means only one task can work with a forest at a time.
+/// A handle can be cloned into every task instead, and the `*_shared` variants of
+/// directory and file methods take a handle rather than a forest:
+///
+/// - Reads work on a [snapshot](Self::snapshot) of the forest, so they never wait for
+///   each other and never see half-done writes.
+/// - Writes hold the handle's write lock until they're done, so they're applied one
+///   after the other. They work on a copy of the forest that only replaces the shared
+///   forest once the write succeeded, so failed writes leave the forest untouched.
+///
+/// Snapshots are cheap, as long as cloning the forest is, like for `Arc<HamtForest>`.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{
+///         forest::{handle::ForestHandle, hamt::HamtForest, traits::PrivateForest},
+///         PrivateDirectory,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = ForestHandle::new(HamtForest::new_rsa_2048_rc(rng));
+///     let name = forest.snapshot().await.empty_name();
+///
+///     let mut photos = PrivateDirectory::new_rc(&name, Utc::now(), rng);
+///     let mut notes = PrivateDirectory::new_rc(&name, Utc::now(), rng);
+///     let rng_two = &mut ChaCha12Rng::from_entropy();
+///     let (cat, todo) = (&["cat.jpg".into()], &["todo.md".into()]);
+///
+///     // Both directories are written to at the same time
+///     let (photos_result, notes_result) = futures::join!(
+///         photos.write_shared(cat, true, Utc::now(), vec![1], &forest, store, rng),
+///         notes.write_shared(todo, true, Utc::now(), vec![2], &forest, store, rng_two),
+///     );
+///     photos_result?;
+///     notes_result?;
+///
+///     assert_eq!(notes.read_shared(todo, true, &forest, store).await?, [2]);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct ForestHandle<F = Arc<HamtForest>> {
+    forest: Arc<RwLock<F>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<F: PrivateForest + Clone> ForestHandle<F> {
+    /// Wraps a forest so it can be shared.
+    pub fn new(forest: F) -> Self {
+        Self {
+            forest: Arc::new(RwLock::new(forest)),
+        }
+    }
+
+    /// Returns the current state of the forest.
+    ///
+    /// Later writes through the handle don't affect the returned forest.
+    pub async fn snapshot(&self) -> F {
+        self.forest.read().await.clone()
+    }
+
+    /// Locks the forest for writing, until the returned guard is dropped.
+    ///
+    /// Other writes wait for the lock, while reads keep working on snapshots
+    /// taken before. Prefer the `*_shared` methods, which only hold the lock
+    /// for as long as needed.
+    pub async fn lock(&self) -> RwLockWriteGuard<'_, F> {
+        self.forest.write().await
+    }
+
+    /// Replaces the forest, e.g. with one that was merged with a remote forest.
+    pub async fn replace(&self, forest: F) {
+        *self.forest.write().await = forest;
+    }
+}
+
+impl<F> Clone for ForestHandle<F> {
+    fn clone(&self) -> Self {
+        Self {
+            forest: Arc::clone(&self.forest),
+        }
+    }
+}
+
+impl PrivateDirectory {
+    /// Like [`PrivateDirectory::get_node`], but with a shared forest. See [`ForestHandle`].
+    pub async fn get_node_shared(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+    ) -> Result<Option<PrivateNode>> {
+        let forest = &forest.snapshot().await;
+        self.get_node(path_segments, search_latest, forest, store)
+            .await
+    }
+
+    /// Like [`PrivateDirectory::read`], but with a shared forest. See [`ForestHandle`].
+    pub async fn read_shared(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        let forest = &forest.snapshot().await;
+        self.read(path_segments, search_latest, forest, store).await
+    }
+
+    /// Like [`PrivateDirectory::ls`], but with a shared forest. See [`ForestHandle`].
+    pub async fn ls_shared(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata)>> {
+        let forest = &forest.snapshot().await;
+        self.ls(path_segments, search_latest, forest, store).await
+    }
+
+    /// Like [`PrivateDirectory::mkdir`], but with a shared forest. See [`ForestHandle`].
+    pub async fn mkdir_shared(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        time: DateTime<Utc>,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let forest = &forest.snapshot().await;
+        self.mkdir(path_segments, search_latest, time, forest, store, rng)
+            .await
+    }
+
+    /// Like [`PrivateDirectory::rm`], but with a shared forest. See [`ForestHandle`].
+    pub async fn rm_shared(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+    ) -> Result<PrivateNode> {
+        let forest = &forest.snapshot().await;
+        self.rm(path_segments, search_latest, forest, store).await
+    }
+
+    /// Like [`PrivateDirectory::write`], but with a shared forest. See [`ForestHandle`].
+    #[allow(clippy::too_many_arguments)]
+    pub async fn write_shared(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        time: DateTime<Utc>,
+        content: Vec<u8>,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let mut guard = forest.lock().await;
+        let mut working = guard.clone();
+        self.write(
+            path_segments,
+            search_latest,
+            time,
+            content,
+            &mut working,
+            store,
+            rng,
+        )
+        .await?;
+        *guard = working;
+        Ok(())
+    }
+}
+
+impl PrivateFile {
+    /// Like [`PrivateFile::get_content`], but with a shared forest. See [`ForestHandle`].
+    pub async fn get_content_shared(
+        &self,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        self.get_content(&forest.snapshot().await, store).await
+    }
+
+    /// Like [`PrivateFile::set_content`], but with a shared forest. See [`ForestHandle`].
+    pub async fn set_content_shared(
+        &mut self,
+        content: impl AsyncRead + Unpin,
+        time: DateTime<Utc>,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let mut guard = forest.lock().await;
+        let mut working = guard.clone();
+        self.set_content(content, time, &mut working, store, rng)
+            .await?;
+        *guard = working;
+        Ok(())
+    }
+}
+
+impl PrivateNode {
+    /// Like [`PrivateNode::store`], but with a shared forest. See [`ForestHandle`].
+    pub async fn store_shared(
+        &self,
+        forest: &ForestHandle<impl PrivateForest + Clone>,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<AccessKey> {
+        let mut guard = forest.lock().await;
+        let mut working = guard.clone();
+        let access_key = self.store(&mut working, store, rng).await?;
+        *guard = working;
+        Ok(access_key)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn concurrent_writes_all_end_up_in_the_forest() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = ForestHandle::new(HamtForest::new_rsa_2048_rc(rng));
+        let name = forest.snapshot().await.empty_name();
+
+        let tasks = (0..4u8).map(|i| {
+            let forest = forest.clone();
+            let mut rng = ChaCha12Rng::seed_from_u64(i as u64);
+            let mut dir = PrivateDirectory::new_rc(&name, Utc::now(), &mut rng);
+            async move {
+                let path = &[format!("{i}.txt")];
+                dir.write_shared(path, true, Utc::now(), vec![i], &forest, store, &mut rng)
+                    .await?;
+                let access_key = dir.as_node().store_shared(&forest, store, &mut rng).await?;
+                Ok::<_, anyhow::Error>((i, access_key))
+            }
+        });
+        let access_keys = futures::future::try_join_all(tasks).await?;
+
+        let snapshot = &forest.snapshot().await;
+        for (i, access_key) in access_keys {
+            let dir = PrivateNode::load(&access_key, snapshot, store, None)
+                .await?
+                .as_dir()?;
+            let content = dir
+                .read_shared(&[format!("{i}.txt")], true, &forest, store)
+                .await?;
+            assert_eq!(content, [i]);
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn failed_writes_leave_the_forest_untouched() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = ForestHandle::new(HamtForest::new_rsa_2048_rc(rng));
+        let dir =
+            &mut PrivateDirectory::new_rc(&forest.snapshot().await.empty_name(), Utc::now(), rng);
+        dir.mkdir_shared(&["docs".into()], true, Utc::now(), &forest, store, rng)
+            .await?;
+
+        let before = forest.snapshot().await;
+        let result = dir
+            .write_shared(
+                &["docs".into()],
+                true,
+                Utc::now(),
+                vec![1],
+                &forest,
+                store,
+                rng,
+            )
+            .await;
+        assert!(result.is_err());
+        assert!(Arc::ptr_eq(&before, &forest.snapshot().await));
+
+        Ok(())
+    }
+}
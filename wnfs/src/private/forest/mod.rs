@@ -1,3 +1,7 @@
+pub mod database;
 pub mod hamt;
+pub mod handle;
+#[cfg(feature = "proofs")]
 pub mod proofs;
+pub mod tenant;
 pub mod traits;
@@ -162,6 +162,14 @@ impl PrivateForest for ProvingHamtForest {
         self.forest.get_proven_name(name)
     }
 
+    fn accepts_unbound_ciphertexts(&self) -> bool {
+        self.forest.accepts_unbound_ciphertexts()
+    }
+
+    fn uses_deterministic_nonces(&self) -> bool {
+        self.forest.uses_deterministic_nonces()
+    }
+
     async fn has_by_hash(&self, name_hash: &HashOutput, store: &impl BlockStore) -> Result<bool> {
         self.forest.has_by_hash(name_hash, store).await
     }
@@ -189,11 +197,11 @@ impl PrivateForest for ProvingHamtForest {
             .await
     }
 
-    async fn get_encrypted_by_hash<'b>(
-        &'b self,
+    async fn get_encrypted_by_hash(
+        &self,
         name_hash: &HashOutput,
         store: &impl BlockStore,
-    ) -> Result<Option<&'b BTreeSet<Cid>>> {
+    ) -> Result<Option<BTreeSet<Cid>>> {
         self.forest.get_encrypted_by_hash(name_hash, store).await
     }
 
@@ -201,7 +209,7 @@ impl PrivateForest for ProvingHamtForest {
         &self,
         name: &Name,
         store: &impl BlockStore,
-    ) -> Result<Option<&BTreeSet<Cid>>> {
+    ) -> Result<Option<BTreeSet<Cid>>> {
         self.forest.get_encrypted(name, store).await
     }
 
@@ -0,0 +1,430 @@
+use super::{hamt::HamtForest, traits::PrivateForest};
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use libipld_core::cid::Cid;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, BTreeSet};
+use wnfs_common::{
+    utils::{Arc, CondSend, CondSync},
+    BlockStore, HashOutput,
+};
+use wnfs_hamt::{Hasher, Pair};
+use wnfs_nameaccumulator::{AccumulatorSetup, ElementsProof, Name, NameAccumulator, NameSegment};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The domain separation info for deriving a tenant's base name from its id.
+const TENANT_SEGMENT_DSI: &str = "wnfs/1.0/tenant segment derivation from tenant id";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Receives every access a [`TenantForest`] session makes to the underlying forest,
+/// including the ones that were denied.
+///
+/// The unit type `()` can be passed to skip auditing, and any closure of type
+/// `Fn(&ForestAccess)` can be used as an auditor.
+pub trait ForestAuditor: CondSync {
+    /// Called after access to a label was checked, before the access itself happens.
+    fn on_access(&self, access: &ForestAccess<'_>);
+}
+
+/// An access of a [`TenantForest`] session to a label, see [`ForestAuditor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForestAccess<'a> {
+    /// The id of the tenant the session belongs to.
+    pub tenant: &'a str,
+    /// What the session tried to do with the label.
+    pub operation: ForestOperation,
+    /// The label that was accessed.
+    pub label: &'a HashOutput,
+    /// Whether the access was allowed.
+    pub allowed: bool,
+}
+
+/// The kinds of access to labels in a private forest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForestOperation {
+    Read,
+    Write,
+    Remove,
+}
+
+/// Records which tenant owns which labels of a shared private forest.
+///
+/// Labels are hashes, so the tenant a label belongs to can't be told from the label
+/// itself. [`TenantForest`] sessions assign the labels they write to their tenant,
+/// labels that already exist in the forest need to be [assigned][TenantLabels::assign]
+/// by the operator.
+///
+/// Clones share the same index.
+#[derive(Debug, Clone, Default)]
+pub struct TenantLabels {
+    owners: Arc<Mutex<BTreeMap<HashOutput, Arc<str>>>>,
+}
+
+/// A session of a single tenant on a private forest that's shared by many tenants.
+///
+/// Sessions enforce isolation between tenants at the API layer:
+/// - New labels may only be written under the tenant's allowed base names, i.e. for
+///   names relative to one of the allowed base name accumulators.
+/// - Labels that are owned by a different tenant can't be read, written or removed.
+/// - Labels that aren't owned by any tenant appear to be missing.
+///
+/// Every access is reported to the session's [`ForestAuditor`].
+///
+/// The names of a tenant's files and directories need to be derived from one of its
+/// bases, which is what [`PrivateForest::empty_name`] returns for a session.
+///
+/// # Examples
+///
+/// ```
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{
+///         forest::{
+///             hamt::HamtForest,
+///             tenant::{TenantForest, TenantLabels},
+///             traits::PrivateForest,
+///         },
+///         PrivateDirectory, PrivateNode,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = HamtForest::new_rsa_2048_rc(rng);
+///     let labels = TenantLabels::new();
+///
+///     let alice = &mut TenantForest::new(forest.clone(), labels.clone(), "alice");
+///     let dir = PrivateDirectory::new_rc(&alice.empty_name(), Utc::now(), rng);
+///     let access_key = dir.as_node().store(alice, store, rng).await.unwrap();
+///
+///     // Bob's session works on the same forest state, but can't read Alice's labels
+///     let bob = &TenantForest::new(alice.forest().clone(), labels, "bob");
+///     assert!(PrivateNode::load(&access_key, bob, store, None).await.is_err());
+///     assert!(PrivateNode::load(&access_key, alice, store, None).await.is_ok());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TenantForest<F = Arc<HamtForest>, A = ()> {
+    forest: F,
+    labels: TenantLabels,
+    tenant: Arc<str>,
+    bases: Vec<NameAccumulator>,
+    auditor: A,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ForestAuditor for () {
+    fn on_access(&self, _access: &ForestAccess<'_>) {}
+}
+
+impl<F: Fn(&ForestAccess<'_>) + CondSync> ForestAuditor for F {
+    fn on_access(&self, access: &ForestAccess<'_>) {
+        self(access)
+    }
+}
+
+impl TenantLabels {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assigns given label to a tenant, replacing its previous owner.
+    pub fn assign(&self, label: HashOutput, tenant: &str) {
+        self.owners.lock().insert(label, Arc::from(tenant));
+    }
+
+    /// Returns the id of the tenant that owns given label, if any.
+    pub fn owner(&self, label: &HashOutput) -> Option<Arc<str>> {
+        self.owners.lock().get(label).cloned()
+    }
+}
+
+impl<F: PrivateForest> TenantForest<F> {
+    /// Opens a session of given tenant on a shared forest.
+    ///
+    /// The tenant is allowed to write under the base name derived from its id,
+    /// see [`TenantForest::tenant_base`].
+    pub fn new(forest: F, labels: TenantLabels, tenant: &str) -> Self {
+        let base = Self::tenant_base(tenant, forest.get_accumulator_setup());
+        Self {
+            forest,
+            labels,
+            tenant: Arc::from(tenant),
+            bases: vec![base],
+            auditor: (),
+        }
+    }
+}
+
+impl<F: PrivateForest, A: ForestAuditor> TenantForest<F, A> {
+    /// Derives the base name accumulator of given tenant, which partitions the forest
+    /// by tenant.
+    pub fn tenant_base(tenant: &str, setup: &AccumulatorSetup) -> NameAccumulator {
+        let segment = NameSegment::new_hashed(TENANT_SEGMENT_DSI, tenant);
+        NameAccumulator::with_segments(&Some(segment), setup)
+    }
+
+    /// Allows the tenant to also write under given base name accumulator.
+    pub fn with_allowed_base(mut self, base: NameAccumulator) -> Self {
+        if !self.bases.contains(&base) {
+            self.bases.push(base);
+        }
+
+        self
+    }
+
+    /// Reports every access of this session to given auditor.
+    pub fn with_auditor<A2: ForestAuditor>(self, auditor: A2) -> TenantForest<F, A2> {
+        TenantForest {
+            forest: self.forest,
+            labels: self.labels,
+            tenant: self.tenant,
+            bases: self.bases,
+            auditor,
+        }
+    }
+
+    /// Returns the id of the tenant this session belongs to.
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// Returns the base name accumulators the tenant may write under.
+    pub fn allowed_bases(&self) -> &[NameAccumulator] {
+        &self.bases
+    }
+
+    /// Returns the underlying forest.
+    pub fn forest(&self) -> &F {
+        &self.forest
+    }
+
+    /// Returns the underlying forest, ending the session.
+    pub fn into_forest(self) -> F {
+        self.forest
+    }
+
+    fn label(&self, name: &Name) -> HashOutput {
+        blake3::Hasher::hash(&self.forest.get_accumulated_name(name))
+    }
+
+    /// Checks access to a label, reporting it to the auditor.
+    ///
+    /// Returns whether the label is visible to the tenant.
+    fn check(
+        &self,
+        operation: ForestOperation,
+        label: &HashOutput,
+        name: Option<&Name>,
+    ) -> Result<bool> {
+        let owner = self.labels.owner(label);
+        let (allowed, visible) = match owner {
+            Some(owner) => (owner == self.tenant, true),
+            // Only names under allowed bases can be claimed by writing to them
+            None if operation == ForestOperation::Write => {
+                let base = name.map(|name| {
+                    NameAccumulator::from_state(self.forest.get_proven_name(name).1.base)
+                });
+                (base.is_some_and(|base| self.bases.contains(&base)), true)
+            }
+            None => (true, false),
+        };
+
+        self.auditor.on_access(&ForestAccess {
+            tenant: &self.tenant,
+            operation,
+            label,
+            allowed,
+        });
+
+        if !allowed {
+            bail!(FsError::TenantAccessDenied(self.tenant.to_string()));
+        }
+
+        Ok(visible)
+    }
+}
+
+impl<F: PrivateForest, A: ForestAuditor> PrivateForest for TenantForest<F, A> {
+    fn empty_name(&self) -> Name {
+        Name::new(self.bases[0].clone(), [])
+    }
+
+    fn get_accumulator_setup(&self) -> &AccumulatorSetup {
+        self.forest.get_accumulator_setup()
+    }
+
+    fn get_proven_name(&self, name: &Name) -> (NameAccumulator, ElementsProof) {
+        self.forest.get_proven_name(name)
+    }
+
+    fn accepts_unbound_ciphertexts(&self) -> bool {
+        self.forest.accepts_unbound_ciphertexts()
+    }
+
+    fn uses_deterministic_nonces(&self) -> bool {
+        self.forest.uses_deterministic_nonces()
+    }
+
+    async fn has_by_hash(&self, name_hash: &HashOutput, store: &impl BlockStore) -> Result<bool> {
+        if !self.check(ForestOperation::Read, name_hash, None)? {
+            return Ok(false);
+        }
+
+        self.forest.has_by_hash(name_hash, store).await
+    }
+
+    async fn has(&self, name: &Name, store: &impl BlockStore) -> Result<bool> {
+        self.has_by_hash(&self.label(name), store).await
+    }
+
+    async fn put_encrypted<I>(
+        &mut self,
+        name: &Name,
+        values: I,
+        store: &impl BlockStore,
+    ) -> Result<NameAccumulator>
+    where
+        I: IntoIterator<Item = Cid> + CondSend,
+        I::IntoIter: CondSend,
+    {
+        let label = self.label(name);
+        self.check(ForestOperation::Write, &label, Some(name))?;
+
+        let accumulator = self.forest.put_encrypted(name, values, store).await?;
+        self.labels.assign(label, &self.tenant);
+        Ok(accumulator)
+    }
+
+    async fn get_encrypted_by_hash(
+        &self,
+        name_hash: &HashOutput,
+        store: &impl BlockStore,
+    ) -> Result<Option<BTreeSet<Cid>>> {
+        if !self.check(ForestOperation::Read, name_hash, None)? {
+            return Ok(None);
+        }
+
+        self.forest.get_encrypted_by_hash(name_hash, store).await
+    }
+
+    async fn get_encrypted(
+        &self,
+        name: &Name,
+        store: &impl BlockStore,
+    ) -> Result<Option<BTreeSet<Cid>>> {
+        self.get_encrypted_by_hash(&self.label(name), store).await
+    }
+
+    async fn remove_encrypted(
+        &mut self,
+        name: &Name,
+        store: &impl BlockStore,
+    ) -> Result<Option<Pair<NameAccumulator, BTreeSet<Cid>>>> {
+        if !self.check(ForestOperation::Remove, &self.label(name), None)? {
+            return Ok(None);
+        }
+
+        self.forest.remove_encrypted(name, store).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{PrivateDirectory, PrivateNode};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn sessions_only_access_their_tenants_labels() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let labels = TenantLabels::new();
+        let denied = Mutex::new(Vec::new());
+        let auditor = |access: &ForestAccess<'_>| {
+            if !access.allowed {
+                denied
+                    .lock()
+                    .push((access.tenant.to_string(), access.operation));
+            }
+        };
+
+        let forest = HamtForest::new_rsa_2048_rc(rng);
+        let alice = &mut TenantForest::new(forest, labels.clone(), "alice").with_auditor(&auditor);
+        let root_dir = &mut PrivateDirectory::new_rc(&alice.empty_name(), Utc::now(), rng);
+        root_dir
+            .write(
+                &["notes.txt".into()],
+                true,
+                Utc::now(),
+                b"hi".to_vec(),
+                alice,
+                store,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(alice, store, rng).await?;
+
+        let bob = &mut TenantForest::new(alice.forest().clone(), labels.clone(), "bob")
+            .with_auditor(&auditor);
+        assert!(PrivateNode::load(&access_key, bob, store, None)
+            .await
+            .is_err());
+
+        // Bob can't write under Alice's base either, even to new labels
+        let name = alice
+            .empty_name()
+            .with_segments_added(Some(NameSegment::new(rng)));
+        assert!(bob
+            .put_encrypted(&name, [Cid::default()], store)
+            .await
+            .is_err());
+        assert!(!alice.has(&name, store).await?);
+
+        // Labels nobody owns are invisible
+        let unowned = alice.label(&name);
+        assert!(!bob.has_by_hash(&unowned, store).await?);
+        assert!(labels.owner(&unowned).is_none());
+
+        let root_dir = PrivateNode::load(&access_key, alice, store, None)
+            .await?
+            .as_dir()?;
+        let content = root_dir
+            .read(&["notes.txt".into()], true, alice, store)
+            .await?;
+        assert_eq!(content, b"hi");
+
+        assert_eq!(
+            *denied.lock(),
+            [
+                ("bob".into(), ForestOperation::Read),
+                ("bob".into(), ForestOperation::Write),
+            ]
+        );
+
+        Ok(())
+    }
+}
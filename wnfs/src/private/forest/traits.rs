@@ -1,6 +1,6 @@
 use crate::{
-    error::CryptError,
-    private::{PrivateNode, TemporalKey},
+    error::{CandidateError, CryptError},
+    private::{PrivateNode, PrivateNodeHeader, TemporalKey},
 };
 use anyhow::Result;
 use async_stream::stream;
@@ -14,6 +14,20 @@ use wnfs_common::{
 use wnfs_hamt::Pair;
 use wnfs_nameaccumulator::{AccumulatorSetup, ElementsProof, Name, NameAccumulator};
 
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The outcome of loading every value at a revision, see
+/// [`PrivateForest::get_multivalue_report_by_hash`].
+#[derive(Debug, Default)]
+pub struct MultivalueReport {
+    /// The private nodes that were loaded, ordered by CID.
+    pub nodes: Vec<(Cid, PrivateNode)>,
+    /// The values that couldn't be loaded, ordered by CID.
+    pub failures: Vec<(Cid, CandidateError)>,
+}
+
 /// A trait representing a (usually serializable) mapping from
 /// WNFS names to a set of encrypted ciphertext blocks.
 ///
@@ -56,6 +70,36 @@ pub trait PrivateForest: CondSync {
         self.get_proven_name(name).0
     }
 
+    /// Whether nodes and file content may be read from ciphertexts that aren't bound to
+    /// the label they're stored under, because they were written before WNFS bound
+    /// ciphertexts to their labels.
+    ///
+    /// Unbound ciphertexts could be swapped between labels unnoticed, so this defaults
+    /// to `false`, even for forests loaded from before. Those have to opt in explicitly,
+    /// e.g. with [`HamtForest::set_accept_unbound_ciphertexts`]. Everything written is
+    /// bound either way.
+    ///
+    /// [`HamtForest::set_accept_unbound_ciphertexts`]: super::hamt::HamtForest::set_accept_unbound_ciphertexts
+    fn accepts_unbound_ciphertexts(&self) -> bool {
+        false
+    }
+
+    /// Whether nodes, file content and everything else written to this forest is encrypted
+    /// with nonces derived from its key, its label and the plaintext, instead of random ones.
+    ///
+    /// Node headers are always encrypted deterministically. With this set, building the same
+    /// forest twice, with keys drawn from the same seeded random number generator, results
+    /// in the same forest, no matter which random number generator nodes are stored with.
+    /// See [`SnapshotKey::encrypt_with_aad_deterministic`] for the construction.
+    ///
+    /// Defaults to `false`, e.g. see [`HamtForest::set_deterministic_nonces`].
+    ///
+    /// [`SnapshotKey::encrypt_with_aad_deterministic`]: crate::private::SnapshotKey::encrypt_with_aad_deterministic
+    /// [`HamtForest::set_deterministic_nonces`]: super::hamt::HamtForest::set_deterministic_nonces
+    fn uses_deterministic_nonces(&self) -> bool {
+        false
+    }
+
     /// Checks that a value with the given saturated name hash key exists.
     ///
     /// # Examples
@@ -109,18 +153,18 @@ pub trait PrivateForest: CondSync {
         I::IntoIter: CondSend;
 
     /// Gets the CIDs to blocks of ciphertext by hash of name.
-    fn get_encrypted_by_hash<'b>(
-        &'b self,
+    fn get_encrypted_by_hash(
+        &self,
         name_hash: &HashOutput,
         store: &impl BlockStore,
-    ) -> impl Future<Output = Result<Option<&'b BTreeSet<Cid>>>> + CondSend;
+    ) -> impl Future<Output = Result<Option<BTreeSet<Cid>>>> + CondSend;
 
     /// Gets the CIDs to blocks of ciphertext by name.
     fn get_encrypted(
         &self,
         name: &Name,
         store: &impl BlockStore,
-    ) -> impl Future<Output = Result<Option<&BTreeSet<Cid>>>> + CondSend;
+    ) -> impl Future<Output = Result<Option<BTreeSet<Cid>>>> + CondSend;
 
     /// Removes the CIDs to blocks of ciphertext by name.
     fn remove_encrypted(
@@ -131,7 +175,8 @@ pub trait PrivateForest: CondSync {
 
     /// Returns a stream of all private nodes that could be decrypted at given revision.
     ///
-    /// The stream of results is ordered by CID.
+    /// The stream of results is ordered by CID. Values that can't be decrypted are skipped,
+    /// use [`PrivateForest::get_multivalue_report_by_hash`] to find out about them.
     ///
     /// Each item in the resulting stream represents an instance of a concurrent write.
     fn get_multivalue_by_hash<'a>(
@@ -150,8 +195,8 @@ pub trait PrivateForest: CondSync {
                 .await
             {
                 Ok(Some(cids)) => {
-                    for cid in cids {
-                        match PrivateNode::from_cid(*cid, temporal_key, self, store, parent_name.clone()).await {
+                    for cid in cids.iter() {
+                        match PrivateNode::from_cid(*cid, temporal_key, label, self, store, parent_name.clone()).await {
                             Ok(node) => yield Ok((*cid, node)),
                             Err(e) if e.downcast_ref::<CryptError>().is_some() => {
                                 // we likely matched a PrivateNodeHeader instead of a PrivateNode.
@@ -167,4 +212,105 @@ pub trait PrivateForest: CondSync {
             }
         })
     }
+
+    /// Loads all private nodes at given revision like [`PrivateForest::get_multivalue_by_hash`],
+    /// but reports every value that fails to decrypt or load instead of skipping or
+    /// failing on it, so corrupted or malicious values are observable.
+    ///
+    /// The header blocks stored next to private nodes are expected at every revision,
+    /// so they're neither returned nor reported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::{BlockStore, MemoryBlockStore, CODEC_RAW},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let access_key = dir.as_node().store(forest, store, rng).await.unwrap();
+    ///
+    ///     // Someone writes garbage next to the directory
+    ///     let garbage = store.put_block(b"garbage".to_vec(), CODEC_RAW).await.unwrap();
+    ///     let revision_name = dir.header.get_revision_name();
+    ///     forest.put_encrypted(&revision_name, [garbage], store).await.unwrap();
+    ///
+    ///     let report = forest
+    ///         .get_multivalue_report_by_hash(
+    ///             access_key.get_label(),
+    ///             access_key.as_revision().unwrap().get_temporal_key(),
+    ///             store,
+    ///             None,
+    ///         )
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     assert_eq!(report.nodes.len(), 1);
+    ///     assert_eq!(report.failures[0].0, garbage);
+    /// }
+    /// ```
+    fn get_multivalue_report_by_hash<'a>(
+        &'a self,
+        label: &'a HashOutput,
+        temporal_key: &'a TemporalKey,
+        store: &'a impl BlockStore,
+        parent_name: Option<Name>,
+    ) -> impl Future<Output = Result<MultivalueReport>> + CondSend + 'a
+    where
+        Self: Sized,
+    {
+        async move {
+            let mut report = MultivalueReport::default();
+            let Some(cids) = self.get_encrypted_by_hash(label, store).await? else {
+                return Ok(report);
+            };
+
+            for cid in cids.iter() {
+                let result = PrivateNode::from_cid(
+                    *cid,
+                    temporal_key,
+                    label,
+                    self,
+                    store,
+                    parent_name.clone(),
+                )
+                .await;
+
+                let err = match result {
+                    Ok(node) => {
+                        report.nodes.push((*cid, node));
+                        continue;
+                    }
+                    Err(err) => err,
+                };
+
+                if err.downcast_ref::<CryptError>().is_none() {
+                    report
+                        .failures
+                        .push((*cid, CandidateError::Unloadable(err)));
+                } else if PrivateNodeHeader::load(cid, temporal_key, self, store, None)
+                    .await
+                    .is_err()
+                {
+                    report
+                        .failures
+                        .push((*cid, CandidateError::Undecryptable(err)));
+                }
+            }
+
+            Ok(report)
+        }
+    }
 }
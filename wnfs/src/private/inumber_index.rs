@@ -0,0 +1,224 @@
+//! Resolving private nodes by inumber, for references that survive moves and renames.
+
+use super::{forest::traits::PrivateForest, PrivateDirectory, PrivateNode};
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt};
+use wnfs_common::{utils::Arc, BlockStore};
+use wnfs_nameaccumulator::NameSegment;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An index from inumbers to the paths of nodes within a private directory.
+///
+/// The inumber of a node stays the same across revisions and when the node is moved or
+/// renamed, so apps can keep it as a durable reference to the node instead of its path.
+/// The index is maintained lazily: resolving an inumber that isn't indexed yet, or whose
+/// indexed path leads to a different node by now, walks the directory until the node is
+/// found and indexes all nodes it passes on the way.
+///
+/// Clones share the same index.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///         InumberIndex, PrivateDirectory,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let path = &["inbox".into(), "invoice.pdf".into()];
+///     root_dir.write(path, true, Utc::now(), vec![1], forest, store, rng).await?;
+///
+///     // Apps store the inumber instead of the path
+///     let node = root_dir.get_node(path, true, forest, store).await?.unwrap();
+///     let inumber = node.get_header().get_inumber().clone();
+///
+///     let archived = &["archive".into(), "2024-invoice.pdf".into()];
+///     root_dir.mkdir(&["archive".into()], true, Utc::now(), forest, store, rng).await?;
+///     root_dir.basic_mv(path, archived, true, Utc::now(), forest, store, rng).await?;
+///
+///     let index = InumberIndex::new();
+///     let (found_path, _) = index.resolve(root_dir, &inumber, true, forest, store).await?.unwrap();
+///     assert_eq!(found_path, archived);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct InumberIndex {
+    paths: Arc<Mutex<HashMap<NameSegment, Vec<String>>>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl InumberIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of indexed nodes.
+    pub fn len(&self) -> usize {
+        self.paths.lock().len()
+    }
+
+    /// Whether no nodes are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.paths.lock().is_empty()
+    }
+
+    /// Drops all indexed paths.
+    pub fn clear(&self) {
+        self.paths.lock().clear();
+    }
+
+    /// Finds the node with given inumber within `root_dir`, returning its path relative to
+    /// `root_dir` and the node itself.
+    ///
+    /// Returns `None` if there's no such node, e.g. because it was removed, or because it's
+    /// outside of `root_dir`. Use the same `root_dir` for all lookups in one index, as the
+    /// paths are relative to it.
+    pub async fn resolve(
+        &self,
+        root_dir: &Arc<PrivateDirectory>,
+        inumber: &NameSegment,
+        search_latest: bool,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Option<(Vec<String>, PrivateNode)>> {
+        if root_dir.header.get_inumber() == inumber {
+            return Ok(Some((Vec::new(), root_dir.as_node())));
+        }
+
+        let indexed = self.paths.lock().get(inumber).cloned();
+        if let Some(path) = indexed {
+            let node = root_dir
+                .get_node(&path, search_latest, forest, store)
+                .await?;
+            match node {
+                Some(node) if node.get_header().get_inumber() == inumber => {
+                    return Ok(Some((path, node)));
+                }
+                _ => {
+                    self.paths.lock().remove(inumber);
+                }
+            }
+        }
+
+        let mut dirs = vec![(Vec::new(), Arc::clone(root_dir))];
+        while let Some((path, dir)) = dirs.pop() {
+            for name in dir.get_entries() {
+                let Some(node) = dir.lookup_node(name, search_latest, forest, store).await? else {
+                    continue;
+                };
+
+                let mut node_path = path.clone();
+                node_path.push(name.clone());
+                let node_inumber = node.get_header().get_inumber();
+                self.paths
+                    .lock()
+                    .insert(node_inumber.clone(), node_path.clone());
+
+                if node_inumber == inumber {
+                    return Ok(Some((node_path, node)));
+                }
+
+                if let PrivateNode::Dir(child_dir) = node {
+                    dirs.push((node_path, child_dir));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl fmt::Debug for InumberIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InumberIndex")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn stale_paths_are_reindexed_after_moves() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let path = &["docs".into(), name.into()];
+            root_dir
+                .write(path, true, Utc::now(), vec![], forest, store, rng)
+                .await?;
+        }
+
+        let path = &["docs".into(), "b.txt".into()];
+        let node = root_dir.get_node(path, true, forest, store).await?.unwrap();
+        let inumber = node.get_header().get_inumber().clone();
+
+        let index = InumberIndex::new();
+        let (found, _) = index
+            .resolve(root_dir, &inumber, true, forest, store)
+            .await?
+            .unwrap();
+        assert_eq!(found, path);
+        assert!(!index.is_empty());
+
+        // A new node at the indexed path doesn't fool the index
+        let moved = &["b.txt".into()];
+        root_dir
+            .basic_mv(path, moved, true, Utc::now(), forest, store, rng)
+            .await?;
+        root_dir
+            .write(path, true, Utc::now(), vec![], forest, store, rng)
+            .await?;
+        let (found, node) = index
+            .resolve(root_dir, &inumber, true, forest, store)
+            .await?
+            .unwrap();
+        assert_eq!(found, moved);
+        assert_eq!(node.get_header().get_inumber(), &inumber);
+
+        root_dir.rm(moved, true, forest, store).await?;
+        let found = index
+            .resolve(root_dir, &inumber, true, forest, store)
+            .await?;
+        assert!(found.is_none());
+
+        Ok(())
+    }
+}
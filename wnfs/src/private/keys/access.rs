@@ -11,6 +11,18 @@ use wnfs_common::HashOutput;
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Access to a private node, which is what apps should persist to get back to the node.
+///
+/// There are two kinds of access, which differ in what they give access to:
+///
+/// - [`RevisionAccess`] gives read access to the revision of the node it points at and all
+///   its later revisions, and can be used to [load the node](crate::private::PrivateNode::load).
+///   Storing a node returns this kind.
+/// - [`SnapshotAccess`] gives read access to exactly the revision it points at.
+///
+/// Revision access can be turned into snapshot access with [`to_snapshot`](Self::to_snapshot),
+/// but not the other way around. The kind is part of the encoding from [`to_bytes`](Self::to_bytes),
+/// so persisted access keys always come back as the same kind.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AccessKey {
     #[serde(rename = "wnfs/share/temporal")]
@@ -19,6 +31,9 @@ pub enum AccessKey {
     Snapshot(SnapshotAccessKey),
 }
 
+/// Read access to a revision of a private node and all of its later revisions.
+///
+/// Derived from the node's skip ratchet, so it can't be used to read earlier revisions.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct TemporalAccessKey {
@@ -28,6 +43,7 @@ pub struct TemporalAccessKey {
     pub(crate) temporal_key: TemporalKey,
 }
 
+/// Read access to exactly one revision of a private node.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct SnapshotAccessKey {
@@ -37,19 +53,91 @@ pub struct SnapshotAccessKey {
     pub snapshot_key: SnapshotKey,
 }
 
+/// Access to a private node, of either kind. Another name for [`AccessKey`].
+pub type NodeAccess = AccessKey;
+
+/// Access to a revision of a private node and its later revisions.
+/// Another name for [`TemporalAccessKey`].
+pub type RevisionAccess = TemporalAccessKey;
+
+/// Access to exactly one revision of a private node. Another name for [`SnapshotAccessKey`].
+pub type SnapshotAccess = SnapshotAccessKey;
+
 //--------------------------------------------------------------------------------------------------
 // Implementations
 //--------------------------------------------------------------------------------------------------
 
 impl AccessKey {
+    /// Decodes access of either kind from bytes created with [`to_bytes`](Self::to_bytes).
     pub fn parse(bytes: impl AsRef<[u8]>) -> Result<Self> {
         Ok(serde_ipld_dagcbor::from_slice(bytes.as_ref())?)
     }
 
+    /// Encodes the access key, including its kind, e.g. to persist it.
+    /// The bytes contain secret key material.
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
         Ok(serde_ipld_dagcbor::to_vec(self)?)
     }
 
+    /// Returns the revision access, if this is revision access.
+    pub fn as_revision(&self) -> Option<&RevisionAccess> {
+        match self {
+            Self::Temporal(key) => Some(key),
+            Self::Snapshot(_) => None,
+        }
+    }
+
+    /// Returns the snapshot access, if this is snapshot access.
+    pub fn as_snapshot(&self) -> Option<&SnapshotAccess> {
+        match self {
+            Self::Temporal(_) => None,
+            Self::Snapshot(key) => Some(key),
+        }
+    }
+
+    /// Returns snapshot access to the revision this points at, which for revision
+    /// access gives up access to later revisions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         AccessKey, PrivateDirectory,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     let access = dir.as_node().store(forest, store, rng).await?;
+    ///     assert!(access.as_revision().is_some());
+    ///
+    ///     let snapshot = AccessKey::from(access.to_snapshot());
+    ///     assert!(snapshot.as_revision().is_none());
+    ///     assert_eq!(snapshot.get_label(), access.get_label());
+    ///     assert_eq!(AccessKey::parse(snapshot.to_bytes()?)?, snapshot);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn to_snapshot(&self) -> SnapshotAccess {
+        match self {
+            Self::Temporal(key) => key.to_snapshot(),
+            Self::Snapshot(key) => key.clone(),
+        }
+    }
+
+    /// Returns the label of the revision in the forest.
     pub fn get_label(&self) -> &HashOutput {
         match self {
             Self::Temporal(key) => &key.label,
@@ -57,6 +145,9 @@ impl AccessKey {
         }
     }
 
+    #[deprecated(
+        note = "fails with a private ref error for snapshot access, use `as_revision` to tell the kinds apart instead"
+    )]
     pub fn get_temporal_key(&self) -> Result<&TemporalKey> {
         let Self::Temporal(key) = self else {
             bail!(AccessKeyError::UnsupportedSnapshotPrivateRefDerive)
@@ -65,6 +156,7 @@ impl AccessKey {
         Ok(&key.temporal_key)
     }
 
+    /// Returns the key to decrypt the revision this points at with.
     pub fn get_snapshot_key(&self) -> SnapshotKey {
         match self {
             Self::Temporal(t) => t.temporal_key.derive_snapshot_key(),
@@ -72,6 +164,8 @@ impl AccessKey {
         }
     }
 
+    /// Returns the CID of the revision's content, which tells it apart from concurrent
+    /// revisions stored under the same label.
     pub fn get_content_cid(&self) -> &Cid {
         match self {
             Self::Temporal(key) => &key.content_cid,
@@ -93,6 +187,75 @@ impl AccessKey {
     }
 }
 
+impl TemporalAccessKey {
+    /// Decodes revision access from bytes created with [`to_bytes`](Self::to_bytes) or
+    /// [`AccessKey::to_bytes`]. Fails for snapshot access.
+    pub fn parse(bytes: impl AsRef<[u8]>) -> Result<Self> {
+        match AccessKey::parse(bytes)? {
+            AccessKey::Temporal(key) => Ok(key),
+            AccessKey::Snapshot(_) => bail!(AccessKeyError::ExpectedRevisionAccess),
+        }
+    }
+
+    /// Encodes the revision access the same way as [`AccessKey::to_bytes`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        AccessKey::Temporal(self.clone()).to_bytes()
+    }
+
+    /// Returns snapshot access to the revision this points at only.
+    pub fn to_snapshot(&self) -> SnapshotAccess {
+        SnapshotAccessKey {
+            label: self.label,
+            content_cid: self.content_cid,
+            snapshot_key: self.temporal_key.derive_snapshot_key(),
+        }
+    }
+
+    /// Returns the label of the revision in the forest.
+    pub fn get_label(&self) -> &HashOutput {
+        &self.label
+    }
+
+    /// Returns the key to decrypt the revision and its later revisions with.
+    pub fn get_temporal_key(&self) -> &TemporalKey {
+        &self.temporal_key
+    }
+
+    /// Returns the CID of the revision's content.
+    pub fn get_content_cid(&self) -> &Cid {
+        &self.content_cid
+    }
+}
+
+impl SnapshotAccessKey {
+    /// Decodes snapshot access from bytes created with [`to_bytes`](Self::to_bytes) or
+    /// [`AccessKey::to_bytes`]. Fails for revision access, which needs to be turned into
+    /// snapshot access explicitly with [`TemporalAccessKey::to_snapshot`].
+    pub fn parse(bytes: impl AsRef<[u8]>) -> Result<Self> {
+        match AccessKey::parse(bytes)? {
+            AccessKey::Temporal(_) => bail!(AccessKeyError::ExpectedSnapshotAccess),
+            AccessKey::Snapshot(key) => Ok(key),
+        }
+    }
+
+    /// Encodes the snapshot access the same way as [`AccessKey::to_bytes`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        AccessKey::Snapshot(self.clone()).to_bytes()
+    }
+}
+
+impl From<TemporalAccessKey> for AccessKey {
+    fn from(key: TemporalAccessKey) -> Self {
+        Self::Temporal(key)
+    }
+}
+
+impl From<SnapshotAccessKey> for AccessKey {
+    fn from(key: SnapshotAccessKey) -> Self {
+        Self::Snapshot(key)
+    }
+}
+
 impl From<&PrivateRef> for TemporalAccessKey {
     fn from(private_ref: &PrivateRef) -> Self {
         Self {
@@ -117,6 +280,42 @@ impl From<&PrivateRef> for SnapshotAccessKey {
 // Tests
 //--------------------------------------------------------------------------------------------------
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+
+    #[test]
+    fn access_kinds_round_trip_and_only_downgrade_explicitly() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let private_ref =
+            PrivateRef::with_temporal_key(rng.gen(), TemporalKey(rng.gen()), Cid::default());
+        let revision = RevisionAccess::from(&private_ref);
+        let snapshot = revision.to_snapshot();
+
+        assert_eq!(snapshot, SnapshotAccess::from(&private_ref));
+        assert_eq!(RevisionAccess::parse(revision.to_bytes()?)?, revision);
+        assert_eq!(SnapshotAccess::parse(snapshot.to_bytes()?)?, snapshot);
+        assert!(RevisionAccess::parse(snapshot.to_bytes()?).is_err());
+        assert!(SnapshotAccess::parse(revision.to_bytes()?).is_err());
+
+        let node_access = NodeAccess::from(revision.clone());
+        assert_eq!(node_access.to_bytes()?, revision.to_bytes()?);
+        assert_eq!(node_access.as_revision(), Some(&revision));
+        assert_eq!(node_access.to_snapshot(), snapshot);
+        assert_eq!(
+            NodeAccess::from(snapshot.clone()).as_snapshot(),
+            Some(&snapshot)
+        );
+        assert!(NodeAccess::from(snapshot).as_revision().is_none());
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod snapshot_tests {
     use super::*;
@@ -0,0 +1,305 @@
+use crate::{
+    error::{AccessKeyError, CryptError, FsError},
+    is_readable_wnfs_version,
+    private::{
+        forest::traits::PrivateForest, FileContent, FileViewToken, PrivateFile, PrivateNode,
+        PrivateNodeContentSerializable, PrivateNodeHeader, SnapshotKey,
+    },
+    WNFS_VERSION,
+};
+use anyhow::{bail, Result};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use wnfs_common::{BlockStore, HashOutput};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Read access to the content of a bounded range of revisions of a private file,
+/// e.g. for sharing the next couple of revisions of a file for a limited time.
+///
+/// Skip ratchets can't be capped: Whoever can read a revision's header learns its
+/// ratchet, and with it the keys to all later revisions. Instead, a bundle holds one
+/// snapshot key per revision, derived ahead of time with
+/// [`PrivateFile::snapshot_key_bundle`]. Snapshot keys can't be turned into each other,
+/// so the bundle discloses exactly the revisions in its range and nothing else.
+///
+/// Snapshot keys don't decrypt node headers, so revisions are read via their
+/// [view tokens](FileViewToken).
+///
+/// Bundles contain secret key material, so keep them as safe as the file content itself.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     private::{PrivateDirectory, SnapshotKeyBundle, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let path = &["draft.md".into()];
+///
+///     let file = root_dir.open_file_mut(path, true, Utc::now(), forest, store, rng).await?;
+///     file.set_content(&b"first draft"[..], Utc::now(), forest, store, rng).await?;
+///     root_dir.as_node().store(forest, store, rng).await?;
+///
+///     // Share the next two revisions of the file, but not any after that
+///     let file = root_dir.get_node(path, true, forest, store).await?.unwrap().as_file()?;
+///     let bundle = file.snapshot_key_bundle(1..3, forest).to_bytes()?;
+///
+///     let content = b"second draft".to_vec();
+///     root_dir.write(path, true, Utc::now(), content.clone(), forest, store, rng).await?;
+///     root_dir.as_node().store(forest, store, rng).await?;
+///
+///     let bundle = SnapshotKeyBundle::parse(bundle)?;
+///     let tokens = bundle.get_view_tokens(1, forest, store).await?;
+///     assert_eq!(tokens[0].get_content(store).await?, content);
+///     assert!(bundle.get_view_tokens(3, forest, store).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotKeyBundle {
+    version: Version,
+    first_revision: u64,
+    revisions: Vec<BundleRevision>,
+}
+
+/// The label and snapshot key of a single revision.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct BundleRevision(
+    #[serde(with = "serde_byte_array")] pub(super) HashOutput,
+    pub(super) SnapshotKey,
+);
+
+#[derive(Serialize, Deserialize)]
+enum SnapshotKeyBundleSerializable {
+    #[serde(rename = "wnfs/share/bundle")]
+    Bundle(SnapshotKeyBundle),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl SnapshotKeyBundle {
+    /// Creates a bundle from the labels and snapshot keys of consecutive revisions.
+    pub(crate) fn new(
+        first_revision: u64,
+        revisions: impl IntoIterator<Item = (HashOutput, SnapshotKey)>,
+    ) -> Self {
+        Self {
+            version: WNFS_VERSION,
+            first_revision,
+            revisions: revisions
+                .into_iter()
+                .map(|(label, key)| BundleRevision(label, key))
+                .collect(),
+        }
+    }
+
+    /// Deserializes a bundle, failing if it was created by an incompatible WNFS version.
+    pub fn parse(bytes: impl AsRef<[u8]>) -> Result<Self> {
+        let SnapshotKeyBundleSerializable::Bundle(bundle) =
+            serde_ipld_dagcbor::from_slice(bytes.as_ref())?;
+        if !is_readable_wnfs_version(&bundle.version) {
+            bail!(FsError::UnexpectedVersion(bundle.version));
+        }
+
+        Ok(bundle)
+    }
+
+    /// Serializes this bundle. The result contains secret key material.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let serializable = SnapshotKeyBundleSerializable::Bundle(self.clone());
+        Ok(serde_ipld_dagcbor::to_vec(&serializable)?)
+    }
+
+    /// Gets the range of revision numbers this bundle gives access to.
+    ///
+    /// Revisions are numbered like [`PrivateNodeHeader::revision`](crate::private::PrivateNodeHeader::revision).
+    pub fn revisions(&self) -> Range<u64> {
+        self.first_revision..self.first_revision + self.revisions.len() as u64
+    }
+
+    /// Gets the label that given revision is stored under in the private forest.
+    pub fn get_label(&self, revision: u64) -> Result<&HashOutput> {
+        Ok(&self.get_revision(revision)?.0)
+    }
+
+    /// Creates view tokens for the content of given revision.
+    ///
+    /// There's one token for each concurrent write of the revision, ordered by CID.
+    /// The result is empty if the revision wasn't written yet.
+    pub async fn get_view_tokens(
+        &self,
+        revision: u64,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<FileViewToken>> {
+        let BundleRevision(label, snapshot_key) = self.get_revision(revision)?;
+        let Some(cids) = forest.get_encrypted_by_hash(label, store).await? else {
+            return Ok(Vec::new());
+        };
+
+        let mut tokens = Vec::new();
+        for cid in cids.iter() {
+            let file = match PrivateNode::decrypt_serializable(
+                cid,
+                snapshot_key,
+                label,
+                forest.accepts_unbound_ciphertexts(),
+                store,
+            )
+            .await
+            {
+                Ok(PrivateNodeContentSerializable::File(file)) => file,
+                Ok(PrivateNodeContentSerializable::Dir(_)) => bail!(FsError::NotAFile),
+                // Header blocks are stored next to the content, but can't be decrypted
+                Err(err) if err.downcast_ref::<CryptError>().is_some() => continue,
+                Err(err) => return Err(err),
+            };
+
+            if !is_readable_wnfs_version(&file.version) {
+                bail!(FsError::UnexpectedVersion(file.version));
+            }
+
+            let content_type = file.metadata.get_content_type().map(String::from);
+            let token = match &file.content {
+                FileContent::External(content) => {
+                    content.view_token(content_type, forest, store).await?
+                }
+                FileContent::Inline { .. } => bail!(FsError::InlineFileContent),
+                FileContent::Log(_) => bail!(FsError::LogFileContent),
+            };
+            tokens.push(token);
+        }
+
+        Ok(tokens)
+    }
+
+    fn get_revision(&self, revision: u64) -> Result<&BundleRevision> {
+        if !self.revisions().contains(&revision) {
+            bail!(AccessKeyError::RevisionNotInBundle(revision));
+        }
+
+        Ok(&self.revisions[(revision - self.first_revision) as usize])
+    }
+}
+
+impl PrivateFile {
+    /// Derives the snapshot keys for given range of revisions of this file, which can
+    /// start at its current revision at the earliest. See [`SnapshotKeyBundle`].
+    ///
+    /// Revisions are numbered like [`PrivateNodeHeader::revision`](crate::private::PrivateNodeHeader::revision).
+    /// The range is cut short at the current revision.
+    pub fn snapshot_key_bundle(
+        &self,
+        revisions: Range<u64>,
+        forest: &impl PrivateForest,
+    ) -> SnapshotKeyBundle {
+        let (first_revision, keys) = derive_revision_keys(&self.header, revisions, forest);
+        SnapshotKeyBundle::new(first_revision, keys)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Derives the labels and snapshot keys for given range of revisions of the node with given
+/// header, cut short at its current revision. Returns the first derived revision with them.
+pub(super) fn derive_revision_keys(
+    header: &PrivateNodeHeader,
+    revisions: Range<u64>,
+    forest: &impl PrivateForest,
+) -> (u64, Vec<(HashOutput, SnapshotKey)>) {
+    let first_revision = revisions.start.max(header.revision());
+    let mut header = header.clone();
+    for _ in header.revision()..first_revision {
+        header.advance_ratchet();
+    }
+
+    let keys = (first_revision..revisions.end)
+        .map(|_| {
+            let revision_ref = header.derive_revision_ref(forest);
+            header.advance_ratchet();
+            (
+                revision_ref.label,
+                revision_ref.temporal_key.derive_snapshot_key(),
+            )
+        })
+        .collect();
+
+    (first_revision, keys)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, PrivateDirectory};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn bundles_only_give_access_to_their_revisions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["notes.txt".into()];
+
+        let mut bundle = None;
+        for revision in 0..5u8 {
+            root_dir
+                .write(path, true, Utc::now(), vec![revision], forest, store, rng)
+                .await?;
+            root_dir.as_node().store(forest, store, rng).await?;
+
+            let file = root_dir.get_node(path, true, forest, store).await?;
+            let file = file.unwrap().as_file()?;
+            if revision == 1 {
+                // Earlier revisions can't be included anymore
+                bundle = Some(file.snapshot_key_bundle(0..4, forest));
+            }
+        }
+
+        let bundle = SnapshotKeyBundle::parse(bundle.unwrap().to_bytes()?)?;
+        assert_eq!(bundle.revisions(), 1..4);
+        for revision in bundle.revisions() {
+            let tokens = bundle.get_view_tokens(revision, forest, store).await?;
+            assert_eq!(tokens.len(), 1);
+            assert_eq!(tokens[0].get_content(store).await?, [revision as u8]);
+        }
+
+        for revision in [0, 4] {
+            assert!(bundle
+                .get_view_tokens(revision, forest, store)
+                .await
+                .is_err());
+        }
+
+        Ok(())
+    }
+}
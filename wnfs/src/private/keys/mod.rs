@@ -1,7 +1,13 @@
 mod access;
+mod bundle;
 mod exchange;
 mod privateref;
+mod scoped;
+mod view;
 
 pub use self::exchange::*;
 pub use access::*;
+pub use bundle::*;
 pub(crate) use privateref::*;
+pub use scoped::*;
+pub use view::*;
@@ -0,0 +1,431 @@
+use super::bundle::{derive_revision_keys, BundleRevision};
+use crate::{
+    error::{AccessKeyError, CryptError, FsError},
+    is_readable_wnfs_version,
+    private::{
+        forest::traits::PrivateForest, FileContent, PrivateDirectory,
+        PrivateDirectoryContentSerializable, PrivateNode, PrivateNodeContentSerializable,
+        PrivateRefSerializable,
+    },
+    utils, WNFS_VERSION,
+};
+use anyhow::{bail, Result};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use wnfs_common::{utils::Arc, BlockStore, Metadata};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Read access to a subtree of a private directory for a bounded range of its revisions,
+/// e.g. for granting compliance auditors visibility into a single folder for a while.
+///
+/// Like a [`SnapshotKeyBundle`](super::SnapshotKeyBundle), it holds one snapshot key per
+/// revision of the subtree's root, derived ahead of time with
+/// [`PrivateDirectory::scoped_key_bundle`]. A directory's snapshot key decrypts the snapshot
+/// keys of the exact revisions of its entries, so each revision gives access to the whole
+/// subtree as it was, but to nothing outside of it and to no other revision. Revisions can
+/// start at the current one at the earliest, since ratchets can't be turned back. Nodes
+/// are only readable through the revisions of the subtree root that link to them.
+///
+/// Bundles and the [`ScopedView`]s opened from them only offer reading. They don't hold
+/// any temporal keys, so they can't be used to look up newer revisions or to write.
+///
+/// Bundles contain secret key material, so keep them as safe as the subtree itself.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     private::{PrivateDirectory, ScopedKeyBundle, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let path = &["ledger".into(), "2024.csv".into()];
+///
+///     root_dir.write(path, true, Utc::now(), b"a,b".to_vec(), forest, store, rng).await?;
+///     root_dir.as_node().store(forest, store, rng).await?;
+///
+///     // Grant access to the current and the next revision of the ledger directory
+///     let scope = &["ledger".into()];
+///     let revision = root_dir.get_node(scope, false, forest, store).await?.unwrap().revision();
+///     let bundle = root_dir
+///         .scoped_key_bundle(scope, revision..revision + 2, forest, store)
+///         .await?
+///         .to_bytes()?;
+///
+///     let bundle = ScopedKeyBundle::parse(bundle)?;
+///     let views = bundle.open(revision, forest, store).await?;
+///     assert_eq!(views[0].read(&["2024.csv".into()], forest, store).await?, b"a,b");
+///     assert!(bundle.open(revision + 2, forest, store).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopedKeyBundle {
+    version: Version,
+    scope: Vec<String>,
+    first_revision: u64,
+    revisions: Vec<BundleRevision>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum ScopedKeyBundleSerializable {
+    #[serde(rename = "wnfs/share/scoped")]
+    Bundle(ScopedKeyBundle),
+}
+
+/// One revision of the subtree a [`ScopedKeyBundle`] gives access to, opened for reading.
+///
+/// Paths are relative to the root of the subtree.
+#[derive(Debug, Clone)]
+pub struct ScopedView {
+    root: PrivateNodeContentSerializable,
+    accept_unbound: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ScopedKeyBundle {
+    /// Deserializes a bundle, failing if it was created by an incompatible WNFS version.
+    pub fn parse(bytes: impl AsRef<[u8]>) -> Result<Self> {
+        let ScopedKeyBundleSerializable::Bundle(bundle) =
+            serde_ipld_dagcbor::from_slice(bytes.as_ref())?;
+        if !is_readable_wnfs_version(&bundle.version) {
+            bail!(FsError::UnexpectedVersion(bundle.version));
+        }
+
+        Ok(bundle)
+    }
+
+    /// Serializes this bundle. The result contains secret key material.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let serializable = ScopedKeyBundleSerializable::Bundle(self.clone());
+        Ok(serde_ipld_dagcbor::to_vec(&serializable)?)
+    }
+
+    /// Gets the path of the subtree's root in the directory the bundle was derived from.
+    pub fn scope(&self) -> &[String] {
+        &self.scope
+    }
+
+    /// Gets the range of revisions of the subtree's root this bundle gives access to.
+    ///
+    /// Revisions are numbered like [`PrivateNodeHeader::revision`](crate::private::PrivateNodeHeader::revision).
+    pub fn revisions(&self) -> Range<u64> {
+        self.first_revision..self.first_revision + self.revisions.len() as u64
+    }
+
+    /// Opens given revision of the subtree for reading.
+    ///
+    /// There's one view for each concurrent write of the revision, ordered by CID.
+    /// The result is empty if the revision wasn't written yet.
+    pub async fn open(
+        &self,
+        revision: u64,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<ScopedView>> {
+        if !self.revisions().contains(&revision) {
+            bail!(AccessKeyError::RevisionNotInBundle(revision));
+        }
+
+        let BundleRevision(label, snapshot_key) =
+            &self.revisions[(revision - self.first_revision) as usize];
+        let Some(cids) = forest.get_encrypted_by_hash(label, store).await? else {
+            return Ok(Vec::new());
+        };
+
+        let mut views = Vec::new();
+        for cid in cids.iter() {
+            let root = match PrivateNode::decrypt_serializable(
+                cid,
+                snapshot_key,
+                label,
+                forest.accepts_unbound_ciphertexts(),
+                store,
+            )
+            .await
+            {
+                Ok(root) => root,
+                // Header blocks are stored next to the content, but can't be decrypted
+                Err(err) if err.downcast_ref::<CryptError>().is_some() => continue,
+                Err(err) => return Err(err),
+            };
+
+            check_version(&root)?;
+            views.push(ScopedView {
+                root,
+                accept_unbound: forest.accepts_unbound_ciphertexts(),
+            });
+        }
+
+        Ok(views)
+    }
+}
+
+impl ScopedView {
+    /// Gets the metadata of the node at given path.
+    pub async fn get_metadata(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Metadata> {
+        Ok(match self.get_node(path_segments, store).await? {
+            PrivateNodeContentSerializable::File(file) => file.metadata,
+            PrivateNodeContentSerializable::Dir(dir) => dir.metadata,
+        })
+    }
+
+    /// Lists the names and metadata of the entries of the directory at given path,
+    /// like [`PrivateDirectory::ls`].
+    pub async fn ls(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata)>> {
+        let PrivateNodeContentSerializable::Dir(dir) = self.get_node(path_segments, store).await?
+        else {
+            bail!(FsError::NotADirectory);
+        };
+
+        let mut result = Vec::new();
+        for (name, private_ref) in utils::iter_in_order(&dir.entries, &dir.order) {
+            let metadata = match decrypt_entry(private_ref, self.accept_unbound, store).await? {
+                PrivateNodeContentSerializable::File(file) => file.metadata,
+                PrivateNodeContentSerializable::Dir(dir) => dir.metadata,
+            };
+            result.push((name.clone(), metadata));
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the content of the file at given path, like [`PrivateDirectory::read`].
+    pub async fn read(
+        &self,
+        path_segments: &[String],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        let PrivateNodeContentSerializable::File(file) =
+            self.get_node(path_segments, store).await?
+        else {
+            bail!(FsError::NotAFile);
+        };
+
+        match file.content {
+            FileContent::Inline { data } => Ok(data),
+            FileContent::External(content) => {
+                let content_type = file.metadata.get_content_type().map(String::from);
+                let token = content.view_token(content_type, forest, store).await?;
+                token.get_content(store).await
+            }
+            FileContent::Log(_) => bail!(FsError::LogFileContent),
+        }
+    }
+
+    /// Follows the snapshot keys of the entries along given path.
+    async fn get_node(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<PrivateNodeContentSerializable> {
+        let mut node = self.root.clone();
+        for segment in path_segments {
+            let PrivateNodeContentSerializable::Dir(dir) = &node else {
+                bail!(FsError::NotADirectory);
+            };
+
+            let private_ref = get_entry(dir, segment)?;
+            node = decrypt_entry(private_ref, self.accept_unbound, store).await?;
+        }
+
+        Ok(node)
+    }
+}
+
+impl PrivateDirectory {
+    /// Derives a bundle giving read access to the subtree at given path for given range of
+    /// revisions of its root, which can start at its current revision at the earliest.
+    /// See [`ScopedKeyBundle`].
+    ///
+    /// The range is cut short at the current revision, so store this directory first.
+    /// The empty path scopes the bundle to this directory itself.
+    pub async fn scoped_key_bundle(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        revisions: Range<u64>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<ScopedKeyBundle> {
+        let header = if path_segments.is_empty() {
+            self.header.clone()
+        } else {
+            let Some(node) = self.get_node(path_segments, false, forest, store).await? else {
+                bail!(FsError::NotFound);
+            };
+            node.get_header().clone()
+        };
+
+        let (first_revision, keys) = derive_revision_keys(&header, revisions, forest);
+        Ok(ScopedKeyBundle {
+            version: WNFS_VERSION,
+            scope: path_segments.to_vec(),
+            first_revision,
+            revisions: keys
+                .into_iter()
+                .map(|(label, key)| BundleRevision(label, key))
+                .collect(),
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn get_entry<'a>(
+    dir: &'a PrivateDirectoryContentSerializable,
+    name: &str,
+) -> Result<&'a PrivateRefSerializable> {
+    match dir.entries.get(name) {
+        Some(private_ref) => Ok(private_ref),
+        None => bail!(FsError::NotFound),
+    }
+}
+
+/// Decrypts the exact revision of an entry with the snapshot key stored in its parent.
+async fn decrypt_entry(
+    private_ref: &PrivateRefSerializable,
+    accept_unbound: bool,
+    store: &impl BlockStore,
+) -> Result<PrivateNodeContentSerializable> {
+    let node = PrivateNode::decrypt_serializable(
+        &private_ref.content_cid,
+        &private_ref.snapshot_key,
+        &private_ref.label,
+        accept_unbound,
+        store,
+    )
+    .await?;
+    check_version(&node)?;
+
+    Ok(node)
+}
+
+fn check_version(node: &PrivateNodeContentSerializable) -> Result<()> {
+    let version = match node {
+        PrivateNodeContentSerializable::File(file) => &file.version,
+        PrivateNodeContentSerializable::Dir(dir) => &dir.version,
+    };
+
+    if !is_readable_wnfs_version(version) {
+        bail!(FsError::UnexpectedVersion(version.clone()));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn scoped_bundles_only_give_access_to_their_subtree_and_revisions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let scope = &["audit".into()];
+        let report = &["audit".into(), "reports".into(), "q1.txt".into()];
+        let secret = &["secret.txt".into()];
+
+        root_dir
+            .write(
+                report,
+                true,
+                Utc::now(),
+                b"q1 v1".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .write(
+                secret,
+                true,
+                Utc::now(),
+                b"secret".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let revision = root_dir
+            .get_node(scope, false, forest, store)
+            .await?
+            .unwrap()
+            .revision();
+        let bundle = root_dir
+            .scoped_key_bundle(scope, 0..revision + 2, forest, store)
+            .await?;
+        let bundle = ScopedKeyBundle::parse(bundle.to_bytes()?)?;
+        assert_eq!(bundle.scope(), scope);
+        assert_eq!(bundle.revisions(), revision..revision + 2);
+
+        for content in ["q1 v2", "q1 v3"] {
+            root_dir
+                .write(report, true, Utc::now(), content.into(), forest, store, rng)
+                .await?;
+            root_dir.as_node().store(forest, store, rng).await?;
+        }
+
+        let relative = &report[1..];
+        for (offset, content) in [(0, "q1 v1"), (1, "q1 v2")] {
+            let views = bundle.open(revision + offset, forest, store).await?;
+            assert_eq!(views.len(), 1);
+            assert_eq!(
+                views[0].read(relative, forest, store).await?,
+                content.as_bytes()
+            );
+
+            let entries = views[0].ls(&["reports".into()], store).await?;
+            assert_eq!(entries.len(), 1);
+            assert!(views[0].read(secret, forest, store).await.is_err());
+        }
+
+        assert!(bundle.open(revision + 2, forest, store).await.is_err());
+
+        Ok(())
+    }
+}
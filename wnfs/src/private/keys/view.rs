@@ -0,0 +1,221 @@
+use crate::{
+    error::FsError,
+    is_readable_wnfs_version,
+    private::{Compression, SnapshotKey},
+    WNFS_VERSION,
+};
+use anyhow::{bail, Result};
+use async_stream::try_stream;
+use futures::{future, Stream, TryStreamExt};
+use libipld_core::cid::Cid;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use wnfs_common::{BlockStore, HashOutput};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Everything needed to read one revision of a private file, without a private forest.
+///
+/// View tokens are created with [`PrivateFile::view_token`](crate::private::PrivateFile::view_token)
+/// and hold the snapshot key and CIDs of the file's content blocks, so anyone holding one
+/// can fetch the blocks from any host of these CIDs and decrypt them. They don't give access
+/// to any other revision, nor to the file's metadata except for its size and content type.
+///
+/// View tokens contain secret key material, so keep them as safe as the file content itself.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     private::{FileViewToken, PrivateFile, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let content = b"Hello, World!".to_vec();
+///     let file =
+///         PrivateFile::with_content(&forest.empty_name(), Utc::now(), content, forest, store, rng)
+///             .await?;
+///
+///     let bytes = file.view_token(forest, store).await?.to_bytes()?;
+///
+///     // Reading the file only needs the token and a block store
+///     let token = FileViewToken::parse(bytes)?;
+///     assert_eq!(token.get_size(), 13);
+///     assert_eq!(token.get_content(store).await?, b"Hello, World!");
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileViewToken {
+    version: Version,
+    snapshot_key: SnapshotKey,
+    blocks: Vec<ViewTokenBlock>,
+    size: u64,
+    content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<Compression>,
+    /// The size of the content compressed into each block, for compressed content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    block_content_size: Option<u64>,
+    /// The size of the stored content before it was padded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unpadded_size: Option<u64>,
+    /// Whether the content blocks may be unbound to their labels, see
+    /// [`PrivateForest::accepts_unbound_ciphertexts`](crate::private::forest::traits::PrivateForest::accepts_unbound_ciphertexts).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    accept_unbound: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+enum FileViewTokenSerializable {
+    #[serde(rename = "wnfs/share/view")]
+    File(FileViewToken),
+}
+
+/// The CID of a content block and the label it's encrypted with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ViewTokenBlock(Cid, #[serde(with = "serde_byte_array")] HashOutput);
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FileViewToken {
+    /// Creates a view token from the key and `(CID, label)` pairs of content blocks.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        snapshot_key: SnapshotKey,
+        blocks: impl IntoIterator<Item = (Cid, HashOutput)>,
+        size: u64,
+        content_type: Option<String>,
+        compression: Option<Compression>,
+        block_content_size: Option<u64>,
+        unpadded_size: Option<u64>,
+        accept_unbound: bool,
+    ) -> Self {
+        Self {
+            version: WNFS_VERSION,
+            snapshot_key,
+            blocks: blocks
+                .into_iter()
+                .map(|(cid, label)| ViewTokenBlock(cid, label))
+                .collect(),
+            size,
+            content_type,
+            compression,
+            block_content_size,
+            unpadded_size,
+            accept_unbound,
+        }
+    }
+
+    /// Deserializes a view token, failing if it was created by an incompatible WNFS version.
+    pub fn parse(bytes: impl AsRef<[u8]>) -> Result<Self> {
+        let FileViewTokenSerializable::File(token) =
+            serde_ipld_dagcbor::from_slice(bytes.as_ref())?;
+        if !is_readable_wnfs_version(&token.version) {
+            bail!(FsError::UnexpectedVersion(token.version));
+        }
+
+        Ok(token)
+    }
+
+    /// Serializes this view token. The result contains secret key material.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let serializable = FileViewTokenSerializable::File(self.clone());
+        Ok(serde_ipld_dagcbor::to_vec(&serializable)?)
+    }
+
+    /// Gets the key the file content is encrypted with.
+    #[cfg(feature = "sharing")]
+    pub(crate) fn get_snapshot_key(&self) -> &SnapshotKey {
+        &self.snapshot_key
+    }
+
+    /// Gets the exact size of the file content in bytes.
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Gets the MIME type of the file content, if it was known.
+    pub fn get_content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Gets the CIDs of the encrypted content blocks, in order.
+    ///
+    /// These are the blocks that need to be available in the block store
+    /// passed to [`FileViewToken::stream`].
+    pub fn get_block_cids(&self) -> impl Iterator<Item = &Cid> {
+        self.blocks.iter().map(|ViewTokenBlock(cid, _)| cid)
+    }
+
+    /// Fetches & decrypts the content blocks one by one, streaming out their content.
+    ///
+    /// Compressed content is decompressed block by block.
+    pub fn stream<'a>(
+        &'a self,
+        store: &'a impl BlockStore,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + 'a {
+        try_stream! {
+            let (mut remaining, block_content_size) = match self.compression {
+                Some(_) => {
+                    let block_content_size = self.block_content_size.ok_or_else(|| {
+                        FsError::InvalidCompressedContent("Missing block content size".to_string())
+                    })?;
+                    (Some(self.size), block_content_size)
+                }
+                None => (self.unpadded_size, u64::MAX),
+            };
+
+            for ViewTokenBlock(cid, label) in self.blocks.iter() {
+                if remaining == Some(0) {
+                    break;
+                }
+
+                let enc_bytes = store.get_block(cid).await?;
+                let mut bytes =
+                    self.snapshot_key
+                        .decrypt_with_aad_if(&enc_bytes, label, self.accept_unbound)?;
+                if let Some(remaining) = remaining.as_mut() {
+                    let len = (*remaining).min(block_content_size) as usize;
+                    bytes = match self.compression {
+                        Some(compression) => compression.decompress_block(&bytes, len)?,
+                        None => {
+                            bytes.truncate(len);
+                            bytes
+                        }
+                    };
+                    *remaining -= bytes.len() as u64;
+                }
+
+                yield bytes;
+            }
+        }
+    }
+
+    /// Collects all content into a `Vec<u8>`.
+    pub async fn get_content(&self, store: &impl BlockStore) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.get_size() as usize);
+        self.stream(store)
+            .try_for_each(|chunk| {
+                bytes.extend_from_slice(&chunk);
+                future::ready(Ok(()))
+            })
+            .await?;
+        Ok(bytes)
+    }
+}
@@ -1,5 +1,6 @@
 use super::{
-    forest::traits::PrivateForest, PrivateDirectory, PrivateFile, PrivateNode, PrivateRef,
+    forest::traits::PrivateForest, NodeCache, PrivateDirectory, PrivateFile, PrivateNode,
+    PrivateRef,
 };
 use crate::utils::OnceCellDebug;
 use anyhow::{anyhow, Result};
@@ -83,6 +84,28 @@ impl PrivateLink {
         }
     }
 
+    /// Gets the node like [`PrivateLink::resolve_node`], but keeps nodes that weren't
+    /// resolved before in given bounded cache instead of in the link.
+    pub(crate) async fn resolve_node_bounded(
+        &self,
+        node_cache: &NodeCache,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+        parent_name: Option<Name>,
+    ) -> Result<PrivateNode> {
+        match self {
+            Self::Encrypted { private_ref, cache } => match cache.get() {
+                Some(node) => Ok(node.clone()),
+                None => {
+                    node_cache
+                        .get_or_load(private_ref, forest, store, parent_name)
+                        .await
+                }
+            },
+            Self::Decrypted { node } => Ok(node.clone()),
+        }
+    }
+
     /// Gets mut value stored in link. It attempts to get it from the store if it is not present in link.
     pub(crate) async fn resolve_node_mut(
         &mut self,
@@ -147,6 +170,19 @@ impl PrivateLink {
         }
     }
 
+    /// Clones this link, copying the node it points at if it wasn't stored yet,
+    /// see [`PrivateNode::detached`].
+    pub(crate) fn detached(&self) -> Self {
+        match self {
+            Self::Decrypted { node } if node.get_persisted_as().get().is_none() => {
+                Self::Decrypted {
+                    node: node.detached(),
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+
     /// Creates a link to a directory node.
     #[inline]
     pub(crate) fn with_dir(dir: PrivateDirectory) -> Self {
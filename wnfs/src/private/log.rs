@@ -0,0 +1,429 @@
+//! Append-only logs of records, stored as the content of private files.
+
+use super::{forest::traits::PrivateForest, PrivateForestContent, SnapshotKey};
+use crate::{error::FsError, private::MAX_BLOCK_CONTENT_SIZE};
+use anyhow::{bail, Result};
+use async_stream::try_stream;
+use futures::Stream;
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::{mem, ops::Range};
+use wnfs_common::{BlockStore, CODEC_RAW};
+use wnfs_nameaccumulator::{Name, NameAccumulator};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The most bytes the DAG-CBOR header of an array or a byte string takes up.
+const CBOR_HEADER_SIZE: usize = 9;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The content of a private file that is an append-only log of records.
+///
+/// Records are packed into encrypted content blocks in the private forest, like the
+/// content of regular files. Appending only ever adds new blocks, so existing blocks
+/// are never rewritten and each append only updates this small manifest.
+///
+/// The manifest keeps running totals of records & bytes per block, so ranges of
+/// records or bytes can be read without decrypting the blocks before them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PrivateLogContent {
+    pub(crate) key: SnapshotKey,
+    pub(crate) base_name: NameAccumulator,
+    /// The number of records in each block and all blocks before it.
+    pub(crate) record_ends: Vec<u64>,
+    /// The number of record bytes in each block and all blocks before it.
+    pub(crate) byte_ends: Vec<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateLogContent {
+    /// Creates an empty log, whose blocks inherit write access from given file name.
+    pub(crate) fn new(
+        file_name: &Name,
+        forest: &impl PrivateForest,
+        rng: &mut impl CryptoRngCore,
+    ) -> Self {
+        let (key, base_name) = PrivateForestContent::prepare_key_and_base_name(file_name, rng);
+
+        Self {
+            key,
+            base_name: forest.get_accumulated_name(&base_name),
+            record_ends: Vec::new(),
+            byte_ends: Vec::new(),
+        }
+    }
+
+    /// Gets the number of records in this log.
+    pub(crate) fn record_count(&self) -> u64 {
+        self.record_ends.last().copied().unwrap_or_default()
+    }
+
+    /// Gets the number of bytes of all records in this log.
+    pub(crate) fn size(&self) -> u64 {
+        self.byte_ends.last().copied().unwrap_or_default()
+    }
+
+    /// Gets the number of content blocks of this log.
+    pub(crate) fn block_count(&self) -> u64 {
+        self.record_ends.len() as u64
+    }
+
+    /// Appends records to this log, packing them into new content blocks.
+    ///
+    /// The blocks are only added to the forest once all of them are encrypted, and
+    /// the manifest is only updated after that, so a failed append leaves the log as-is.
+    pub(crate) async fn append(
+        &mut self,
+        records: impl IntoIterator<Item = Vec<u8>>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let base_name = &Name::new(self.base_name.clone(), []);
+        let mut record_ends = self.record_ends.clone();
+        let mut byte_ends = self.byte_ends.clone();
+        let mut blocks = Vec::new();
+
+        for chunk in Self::pack(records)? {
+            let index = record_ends.len() as u64;
+            record_ends.push(record_ends.last().unwrap_or(&0) + chunk.len() as u64);
+            byte_ends.push(
+                byte_ends.last().unwrap_or(&0) + chunk.iter().map(|r| r.len() as u64).sum::<u64>(),
+            );
+
+            let bytes = serde_ipld_dagcbor::to_vec(&chunk)?;
+            let name = PrivateForestContent::create_block_name(&self.key, index, base_name);
+            let label = PrivateForestContent::block_label(&name, forest);
+            let deterministic = forest.uses_deterministic_nonces();
+            let enc_bytes =
+                self.key
+                    .encrypt_with_aad_deterministic_if(&bytes, &label, deterministic, rng)?;
+            let content_cid = store.put_block(enc_bytes, CODEC_RAW).await?;
+
+            blocks.push((name, content_cid));
+        }
+
+        for (name, content_cid) in blocks {
+            forest
+                .put_encrypted(&name, Some(content_cid), store)
+                .await?;
+        }
+
+        self.record_ends = record_ends;
+        self.byte_ends = byte_ends;
+        Ok(())
+    }
+
+    /// Reads the records with indices in given range.
+    ///
+    /// The range is clamped to the records in this log.
+    pub(crate) async fn read_records(
+        &self,
+        range: Range<u64>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<Vec<u8>>> {
+        let end = range.end.min(self.record_count());
+        if range.start >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut records = Vec::with_capacity((end - range.start) as usize);
+        let first_block = self.record_ends.partition_point(|&e| e <= range.start);
+        for block_index in first_block..self.record_ends.len() {
+            let block_start = block_index
+                .checked_sub(1)
+                .map_or(0, |i| self.record_ends[i]);
+            let chunk = self.read_block(block_index as u64, forest, store).await?;
+
+            records.extend(
+                chunk
+                    .into_iter()
+                    .zip(block_start..)
+                    .filter(|(_, index)| (range.start..end).contains(index))
+                    .map(|(record, _)| record.into_vec()),
+            );
+
+            if self.record_ends[block_index] >= end {
+                break;
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Decrypt & stream out the bytes of all records in each block, starting at given block.
+    pub(crate) fn stream<'a>(
+        &'a self,
+        block_index: u64,
+        forest: &'a impl PrivateForest,
+        store: &'a impl BlockStore,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + 'a {
+        try_stream! {
+            for index in block_index..self.block_count() {
+                let chunk = self.read_block(index, forest, store).await?;
+                yield chunk.into_iter().flat_map(ByteBuf::into_vec).collect();
+            }
+        }
+    }
+
+    /// Reads a number of bytes of the concatenated records, starting from a given offset.
+    pub(crate) async fn read_at(
+        &self,
+        byte_offset: u64,
+        len_limit: Option<usize>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        let end = len_limit.map_or(self.size(), |len| {
+            self.size().min(byte_offset.saturating_add(len as u64))
+        });
+        if byte_offset >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut bytes = Vec::with_capacity((end - byte_offset) as usize);
+        let first_block = self.byte_ends.partition_point(|&e| e <= byte_offset);
+        for block_index in first_block..self.byte_ends.len() {
+            let block_start = block_index.checked_sub(1).map_or(0, |i| self.byte_ends[i]);
+            let chunk = self.read_block(block_index as u64, forest, store).await?;
+            let chunk: Vec<u8> = chunk.into_iter().flat_map(ByteBuf::into_vec).collect();
+
+            let from = byte_offset.saturating_sub(block_start) as usize;
+            let to = (end - block_start).min(chunk.len() as u64) as usize;
+            bytes.extend_from_slice(&chunk[from..to]);
+
+            if self.byte_ends[block_index] >= end {
+                break;
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    /// Generates the names of all content blocks of this log.
+    pub(crate) fn block_names(&self) -> impl Iterator<Item = Name> + '_ {
+        let base_name = Name::new(self.base_name.clone(), []);
+        (0..self.block_count())
+            .map(move |index| PrivateForestContent::create_block_name(&self.key, index, &base_name))
+    }
+
+    /// Re-encrypts all blocks of this log under a fresh key and base name derived from
+    /// `file_name`, keeping the records packed as they are.
+    ///
+    /// The old blocks stay in the forest. Returns the re-encrypted log along with the
+    /// CIDs of the old blocks and the CIDs of the blocks replacing them.
+    pub(crate) async fn reencrypt(
+        &self,
+        file_name: &Name,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<(Self, Vec<(Cid, Cid)>)> {
+        let log = Self {
+            record_ends: self.record_ends.clone(),
+            byte_ends: self.byte_ends.clone(),
+            ..Self::new(file_name, forest, rng)
+        };
+
+        let mut blocks = Vec::with_capacity(self.record_ends.len());
+        let mut cids = Vec::with_capacity(self.record_ends.len());
+        for (old_name, name) in self.block_names().zip(log.block_names()) {
+            let old_cid = forest
+                .get_encrypted(&old_name, store)
+                .await?
+                .and_then(|cids| cids.first().copied())
+                .ok_or(FsError::FileShardNotFound)?;
+
+            let bytes =
+                PrivateForestContent::decrypt_block(&self.key, &old_name, forest, store).await?;
+            let label = PrivateForestContent::block_label(&name, forest);
+            let deterministic = forest.uses_deterministic_nonces();
+            let enc_bytes =
+                log.key
+                    .encrypt_with_aad_deterministic_if(&bytes, &label, deterministic, rng)?;
+            let cid = store.put_block(enc_bytes, CODEC_RAW).await?;
+
+            blocks.push((name, cid));
+            cids.push((old_cid, cid));
+        }
+
+        for (name, content_cid) in blocks {
+            forest
+                .put_encrypted(&name, Some(content_cid), store)
+                .await?;
+        }
+
+        Ok((log, cids))
+    }
+
+    async fn read_block(
+        &self,
+        block_index: u64,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<ByteBuf>> {
+        let base_name = &Name::new(self.base_name.clone(), []);
+        let name = PrivateForestContent::create_block_name(&self.key, block_index, base_name);
+        let bytes = PrivateForestContent::decrypt_block(&self.key, &name, forest, store).await?;
+        Ok(serde_ipld_dagcbor::from_slice(&bytes)?)
+    }
+
+    /// Packs records into chunks that fit into a content block each, keeping their order.
+    fn pack(records: impl IntoIterator<Item = Vec<u8>>) -> Result<Vec<Vec<ByteBuf>>> {
+        let mut chunks = Vec::new();
+        let mut chunk = Vec::new();
+        let mut chunk_size = CBOR_HEADER_SIZE;
+
+        for record in records {
+            let record_size = record.len() + CBOR_HEADER_SIZE;
+            if CBOR_HEADER_SIZE + record_size > MAX_BLOCK_CONTENT_SIZE {
+                bail!(FsError::LogRecordTooLarge(record.len()));
+            }
+
+            if chunk_size + record_size > MAX_BLOCK_CONTENT_SIZE {
+                chunks.push(mem::take(&mut chunk));
+                chunk_size = CBOR_HEADER_SIZE;
+            }
+
+            chunk_size += record_size;
+            chunk.push(ByteBuf::from(record));
+        }
+
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+
+        Ok(chunks)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, PrivateDirectory, PrivateFile, PrivateNode};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn appends_never_rewrite_existing_blocks() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let log = &mut PrivateLogContent::new(&forest.empty_name(), forest, rng);
+
+        // 100 KB records, two of which fit into a block
+        let records: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 100_000]).collect();
+        log.append(records[..3].to_vec(), forest, store, rng)
+            .await?;
+        let names: Vec<_> = log.block_names().collect();
+        let mut cids = Vec::new();
+        for name in names.iter() {
+            cids.push(forest.get_encrypted(name, store).await?);
+        }
+
+        log.append(records[3..].to_vec(), forest, store, rng)
+            .await?;
+        assert_eq!(log.record_ends, [2, 3, 5]);
+        assert_eq!(log.size(), 500_000);
+        for (name, cids) in names.iter().zip(cids) {
+            assert_eq!(forest.get_encrypted(name, store).await?, cids);
+        }
+
+        assert_eq!(log.read_records(1..4, forest, store).await?, &records[1..4]);
+        assert_eq!(log.read_records(4..10, forest, store).await?, &records[4..]);
+        assert!(log.read_records(5..10, forest, store).await?.is_empty());
+        assert_eq!(log.read_at(199_999, Some(2), forest, store).await?, [1, 2]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn logs_survive_store_and_load() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["events".into()];
+
+        let log = root_dir
+            .open_file_mut(path, true, Utc::now(), forest, store, rng)
+            .await?;
+        log.append_records([b"start".to_vec()], Utc::now(), forest, store, rng)
+            .await?;
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        let root_dir = &mut PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let log = root_dir
+            .open_file_mut(path, true, Utc::now(), forest, store, rng)
+            .await?;
+        log.append_records([b"stop".to_vec()], Utc::now(), forest, store, rng)
+            .await?;
+
+        assert_eq!(log.get_record_count(), Some(2));
+        assert_eq!(
+            log.read_records(0..2, forest, store).await?,
+            [b"start".to_vec(), b"stop".to_vec()]
+        );
+        assert!(log.view_token(forest, store).await.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn only_empty_files_become_logs() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            b"Hello".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        let result = file
+            .append_records([b"record".to_vec()], Utc::now(), forest, store, rng)
+            .await;
+        assert!(result.is_err());
+        assert!(file.read_records(0..1, forest, store).await.is_err());
+
+        let file = &mut PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        let result = file
+            .append_records(
+                [vec![0; MAX_BLOCK_CONTENT_SIZE]],
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(file.get_record_count(), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,652 @@
+//! Maintenance operations that rewrite the stored history of private nodes.
+
+use super::{
+    encrypted::Encrypted, forest::traits::PrivateForest, AccessKey, FileContent, OverflowCache,
+    PrivateForestContent, PrivateLink, PrivateLogContent, PrivateNode, TemporalKey,
+};
+use crate::{error::FsError, options::WriteOptions};
+use anyhow::{ensure, Result};
+use async_once_cell::OnceCell;
+use async_recursion::async_recursion;
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use std::collections::{BTreeMap, BTreeSet};
+use wnfs_common::{
+    utils::{Arc, CondSend, ProgressReporter},
+    BlockStore,
+};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateNode {
+    /// Re-encrypts all revisions of this node, starting at the revision of `past_node`
+    /// up to and including this node's revision, with the current data format version
+    /// and cipher.
+    ///
+    /// Every revision is kept under its label and decryptable with its original keys,
+    /// including all concurrent writes at that revision. The previous links of
+    /// re-encrypted revisions are updated to point at the re-encrypted ciphertexts,
+    /// after which the old ciphertexts are removed from the forest.
+    ///
+    /// This node must have been stored before and is updated to its re-encrypted
+    /// revision. Content blocks of files stored externally in the forest, including the
+    /// records of files that are logs, are re-encrypted under fresh keys and labels as well,
+    /// once per distinct content. Their old blocks stay in the forest, since revisions
+    /// before `past_node` may share them.
+    ///
+    /// References to old ciphertexts from outside this node's history, e.g. from older
+    /// revisions of parent directories or from previously created access keys, aren't
+    /// updated. The returned map from old to new CIDs, which includes content blocks,
+    /// can be used to fix those.
+    pub async fn reencrypt_history(
+        &mut self,
+        past_node: &PrivateNode,
+        discrepancy_budget: usize,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<BTreeMap<Cid, Cid>> {
+        let current_cid = self.get_persisted_as().get().cloned();
+        ensure!(
+            current_cid.is_some(),
+            "Can't re-encrypt the history of a node that hasn't been stored yet"
+        );
+
+        let header = self.get_header().clone();
+        let mut ratchets = header
+            .ratchet
+            .previous(&past_node.get_header().ratchet, discrepancy_budget)
+            .map_err(FsError::NoIntermediateRatchet)?
+            .collect::<Vec<_>>();
+        ratchets.reverse();
+        ratchets.push(header.ratchet.clone());
+
+        let mut reencrypted = BTreeMap::new();
+        // Revisions that only changed metadata share their content with the ones before
+        let mut reencrypted_contents: Vec<(PrivateForestContent, PrivateForestContent)> =
+            Vec::new();
+        let mut reencrypted_logs: Vec<(PrivateLogContent, PrivateLogContent)> = Vec::new();
+        for (index, ratchet) in ratchets.iter().enumerate() {
+            let mut revision_header = header.clone();
+            revision_header.update_ratchet(ratchet.clone());
+
+            for (old_cid, mut node) in revision_header.get_multivalue(forest, store).await? {
+                let mut previous = BTreeSet::new();
+                for (revisions_back, link) in node.get_previous().iter() {
+                    // Links reaching further back than `past_node` stay untouched
+                    let Some(previous_index) = index.checked_sub(*revisions_back) else {
+                        previous.insert((*revisions_back, link.clone()));
+                        continue;
+                    };
+
+                    let temporal_key = &TemporalKey::new(&ratchets[previous_index]);
+                    let link = match reencrypted.get(link.resolve_value(temporal_key)?) {
+                        Some(new_cid) => Encrypted::from_value(*new_cid, temporal_key)?,
+                        None => link.clone(),
+                    };
+
+                    previous.insert((*revisions_back, link));
+                }
+
+                if let Self::File(file_rc) = &mut node {
+                    match &file_rc.content.content {
+                        FileContent::Inline { .. } => {}
+                        FileContent::External(content) => {
+                            let reused = reencrypted_contents
+                                .iter()
+                                .find(|(old_content, _)| old_content == content);
+                            let new_content = match reused {
+                                Some((_, new_content)) => new_content.clone(),
+                                None => {
+                                    let (new_content, cids) = content
+                                        .reencrypt(&file_rc.header.name, forest, store, rng)
+                                        .await?;
+                                    reencrypted.extend(cids);
+                                    reencrypted_contents
+                                        .push((content.clone(), new_content.clone()));
+                                    new_content
+                                }
+                            };
+
+                            Arc::make_mut(file_rc).content.content =
+                                FileContent::External(new_content);
+                        }
+                        FileContent::Log(log) => {
+                            let reused =
+                                reencrypted_logs.iter().find(|(old_log, _)| old_log == log);
+                            let new_log = match reused {
+                                Some((_, new_log)) => new_log.clone(),
+                                None => {
+                                    let (new_log, cids) = log
+                                        .reencrypt(&file_rc.header.name, forest, store, rng)
+                                        .await?;
+                                    reencrypted.extend(cids);
+                                    reencrypted_logs.push((log.clone(), new_log.clone()));
+                                    new_log
+                                }
+                            };
+
+                            Arc::make_mut(file_rc).content.content = FileContent::Log(new_log);
+                        }
+                    }
+                }
+
+                node.reset_for_reencryption(previous);
+                let private_ref = node.store_and_get_private_ref(forest, store, rng).await?;
+                reencrypted.insert(old_cid, private_ref.content_cid);
+
+                if Some(old_cid) == current_cid {
+                    *self = node;
+                }
+            }
+
+            let revision_name = revision_header.get_revision_name();
+            if let Some(pair) = forest.remove_encrypted(&revision_name, store).await? {
+                let remaining = pair
+                    .value
+                    .into_iter()
+                    .filter(|cid| !reencrypted.contains_key(cid))
+                    .collect::<Vec<_>>();
+
+                forest
+                    .put_encrypted(&revision_name, remaining, store)
+                    .await?;
+            }
+        }
+
+        Ok(reencrypted)
+    }
+
+    /// Moves this node and all of its descendants into a forest with a different
+    /// accumulator setup, e.g. when moving to a larger RSA modulus.
+    ///
+    /// Names are accumulated with the setup of the forest they're stored in, so nodes in
+    /// `old_forest` can't be found through `new_forest`. This walks all nodes reachable
+    /// from this one, recomputes their names under `parent_name`, which needs to be a name
+    /// from `new_forest`, e.g. its empty name for a root directory, and stores them in
+    /// `new_forest`. This node is updated to its migrated version, and its new access key
+    /// is returned. `progress` is told about every migrated node.
+    ///
+    /// Nodes keep their inumbers, ratchets and revision counters, so their identity and
+    /// previous links are preserved. Previous links still point at the revisions stored
+    /// before the migration, which stay readable through `old_forest` with access keys
+    /// from before the migration. File content stored in the forest is re-encrypted under
+    /// the new names, keeping its compression and padding.
+    pub async fn migrate_setup(
+        &mut self,
+        parent_name: &Name,
+        progress: &impl ProgressReporter,
+        old_forest: &impl PrivateForest,
+        new_forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<AccessKey> {
+        self.migrate_subtree(
+            parent_name,
+            progress,
+            &mut 0,
+            old_forest,
+            new_forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        self.store(new_forest, store, rng).await
+    }
+
+    /// Renames this subtree after `parent_name` and re-encrypts forest content into
+    /// `new_forest`, without storing any nodes yet.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    async fn migrate_subtree(
+        &mut self,
+        parent_name: &Name,
+        progress: &impl ProgressReporter,
+        done: &mut u64,
+        old_forest: &impl PrivateForest,
+        new_forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        match self {
+            Self::File(file_rc) => {
+                let file = Arc::make_mut(file_rc);
+                file.header.update_name(parent_name);
+                file.content.persisted_as = OnceCell::new();
+                file.content.overflow_cache = OverflowCache::default();
+
+                let name = &file.header.name;
+                match &file.content.content {
+                    FileContent::Inline { .. } => {}
+                    FileContent::External(content) => {
+                        let options = &WriteOptions {
+                            compression: content.compression,
+                            padding: content.padding,
+                            ..Default::default()
+                        };
+                        let bytes = content.get_content(old_forest, store).await?;
+                        let content = PrivateForestContent::new_with_options(
+                            name, bytes, options, new_forest, store, rng,
+                        )
+                        .await?;
+                        file.content.content = FileContent::External(content);
+                    }
+                    FileContent::Log(log) => {
+                        // Appending block by block keeps the records packed as before
+                        let mut migrated = PrivateLogContent::new(name, new_forest, rng);
+                        let mut start = 0;
+                        for &end in log.record_ends.iter() {
+                            let records = log.read_records(start..end, old_forest, store).await?;
+                            migrated.append(records, new_forest, store, rng).await?;
+                            start = end;
+                        }
+                        file.content.content = FileContent::Log(migrated);
+                    }
+                }
+            }
+            Self::Dir(dir_rc) => {
+                let dir = Arc::make_mut(dir_rc);
+
+                // Children are still stored under the directory's old name
+                let old_name = dir.header.name.clone();
+                dir.header.update_name(parent_name);
+                dir.content.persisted_as = OnceCell::new();
+                dir.content.overflow_cache = OverflowCache::default();
+
+                for (name, private_link) in dir.content.entries.iter_mut() {
+                    // Pending children are still named after an older ancestry
+                    let child_parent_name = dir
+                        .content
+                        .pending_ancestry
+                        .get(name)
+                        .map_or(&old_name, |pending| &pending.parent_name)
+                        .clone();
+                    let mut node = private_link
+                        .resolve_node(old_forest, store, Some(child_parent_name))
+                        .await?
+                        .clone();
+                    node.migrate_subtree(
+                        &dir.header.name,
+                        progress,
+                        done,
+                        old_forest,
+                        new_forest,
+                        store,
+                        rng,
+                    )
+                    .await?;
+                    *private_link = PrivateLink::from(node);
+                }
+
+                dir.content.pending_ancestry.clear();
+            }
+        }
+
+        *done += 1;
+        progress.on_progress(*done, None);
+
+        Ok(())
+    }
+
+    /// Replaces the previous links and clears the cached CID and overflow blocks, so the
+    /// node gets serialized and encrypted anew the next time it's stored.
+    #[allow(clippy::mutable_key_type)]
+    fn reset_for_reencryption(&mut self, previous: BTreeSet<(usize, Encrypted<Cid>)>) {
+        match self {
+            Self::File(file) => {
+                let file = Arc::make_mut(file);
+                file.content.previous = previous;
+                file.content.persisted_as = OnceCell::new();
+                file.content.overflow_cache = OverflowCache::default();
+            }
+            Self::Dir(dir) => {
+                let dir = Arc::make_mut(dir);
+                dir.content.previous = previous;
+                dir.content.persisted_as = OnceCell::new();
+                dir.content.overflow_cache = OverflowCache::default();
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{
+        forest::hamt::HamtForest, Compression, Padding, PrivateDirectory, PrivateNodeHistory,
+    };
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn reencrypted_history_stays_readable() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_and_store(
+            &forest.empty_name(),
+            Utc::now(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let past_node = root_dir.as_node();
+
+        let mut old_cids = vec![*past_node.get_persisted_as().get().unwrap()];
+        for name in ["one.txt", "two.txt", "three.txt"] {
+            root_dir
+                .write(
+                    &[name.into()],
+                    true,
+                    Utc::now(),
+                    name.as_bytes().to_vec(),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+            old_cids.push(root_dir.store(forest, store, rng).await?.content_cid);
+        }
+
+        let node = &mut root_dir.as_node();
+        let reencrypted = node
+            .reencrypt_history(&past_node, 1_000_000, forest, store, rng)
+            .await?;
+
+        assert_eq!(reencrypted.keys().cloned().collect::<Vec<_>>(), {
+            let mut old_cids = old_cids.clone();
+            old_cids.sort();
+            old_cids
+        });
+        assert_eq!(
+            node.get_persisted_as().get(),
+            reencrypted.get(old_cids.last().unwrap())
+        );
+
+        let past_revision = &past_node.get_header().get_revision_name();
+        let past_cids = forest.get_encrypted(past_revision, store).await?.unwrap();
+        assert!(!past_cids.contains(&old_cids[0]));
+        assert!(past_cids.contains(&reencrypted[&old_cids[0]]));
+
+        let mut history = PrivateNodeHistory::of(node, &past_node, 1_000_000, forest.clone())?;
+        let mut entry_counts = vec![node.as_dir()?.get_entries().count()];
+        while let Some(dir) = history.get_previous_dir(store).await? {
+            let cid = dir.content.persisted_as.get().unwrap();
+            assert!(reencrypted.values().any(|new_cid| new_cid == cid));
+            entry_counts.push(dir.get_entries().count());
+        }
+
+        assert_eq!(entry_counts, vec![3, 2, 1, 0]);
+
+        let content = node
+            .as_dir()?
+            .read(&["two.txt".into()], false, forest, store)
+            .await?;
+        assert_eq!(content, b"two.txt");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reencrypted_history_reencrypts_file_content() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["large.txt".into()];
+
+        let mut old_contents = Vec::new();
+        let mut past_node = None;
+        for content in [b"one\n".repeat(100_000), b"two\n".repeat(100_000)] {
+            root_dir
+                .write(path, true, Utc::now(), content, forest, store, rng)
+                .await?;
+            root_dir.store(forest, store, rng).await?;
+
+            let node = root_dir
+                .get_node(path, false, forest, store)
+                .await?
+                .unwrap();
+            let FileContent::External(content) = &node.as_file()?.content.content else {
+                panic!("Expected content to be stored in the forest");
+            };
+            old_contents.push(content.clone());
+            past_node.get_or_insert_with(|| node.clone());
+        }
+
+        let past_node = past_node.unwrap();
+        let node = &mut root_dir
+            .get_node(path, false, forest, store)
+            .await?
+            .unwrap();
+        let reencrypted = node
+            .reencrypt_history(&past_node, 1_000_000, forest, store, rng)
+            .await?;
+
+        for content in old_contents.iter() {
+            let base_name = &Name::new(content.base_name.clone(), []);
+            let names = PrivateForestContent::generate_shard_labels(
+                &content.key,
+                0,
+                content.block_count,
+                base_name,
+            );
+            for name in names {
+                let cids = forest.get_encrypted(&name, store).await?.unwrap();
+                assert!(reencrypted.contains_key(cids.first().unwrap()));
+            }
+        }
+
+        let file = node.as_file()?;
+        let FileContent::External(content) = &file.content.content else {
+            panic!("Expected content to be stored in the forest");
+        };
+        assert_ne!(content.key, old_contents[1].key);
+        assert_eq!(
+            file.get_content(forest, store).await?,
+            b"two\n".repeat(100_000)
+        );
+
+        let mut history = PrivateNodeHistory::of(node, &past_node, 1_000_000, forest.clone())?;
+        let previous = history.get_previous_file(store).await?.unwrap();
+        let FileContent::External(content) = &previous.content.content else {
+            panic!("Expected content to be stored in the forest");
+        };
+        assert_ne!(content.key, old_contents[0].key);
+        assert_eq!(
+            previous.get_content(forest, store).await?,
+            b"one\n".repeat(100_000)
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reencrypted_history_reencrypts_log_records() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["events.log".into()];
+
+        let mut past_node = None;
+        for record in [b"one".to_vec(), b"two".to_vec()] {
+            root_dir
+                .open_file_mut(path, true, Utc::now(), forest, store, rng)
+                .await?
+                .append_records([record], Utc::now(), forest, store, rng)
+                .await?;
+            root_dir.store(forest, store, rng).await?;
+
+            let node = root_dir.get_node(path, false, forest, store).await?;
+            past_node.get_or_insert_with(|| node.unwrap());
+        }
+
+        let past_node = past_node.unwrap();
+        let node = &mut root_dir
+            .get_node(path, false, forest, store)
+            .await?
+            .unwrap();
+        let FileContent::Log(old_log) = node.as_file()?.content.content.clone() else {
+            panic!("Expected file to be a log");
+        };
+        let reencrypted = node
+            .reencrypt_history(&past_node, 1_000_000, forest, store, rng)
+            .await?;
+
+        let file = node.as_file()?;
+        let FileContent::Log(log) = &file.content.content else {
+            panic!("Expected file to be a log");
+        };
+        assert_ne!(log.key, old_log.key);
+        assert_eq!(
+            file.read_records(0..2, forest, store).await?,
+            [b"one".to_vec(), b"two".to_vec()]
+        );
+
+        for name in log.block_names() {
+            let cids = forest.get_encrypted(&name, store).await?.unwrap();
+            let cid = cids.first().unwrap();
+            assert!(reencrypted.values().any(|new_cid| new_cid == cid));
+
+            let label = &PrivateForestContent::block_label(&name, forest);
+            let enc_bytes = store.get_block(cid).await?;
+            assert!(old_log.key.decrypt_with_aad(&enc_bytes, label).is_err());
+            assert!(log.key.decrypt_with_aad(&enc_bytes, label).is_ok());
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn migrated_trees_are_readable_in_the_new_forest() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let old_forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&old_forest.empty_name(), Utc::now(), rng);
+
+        let large = b"Hello, World!\n".repeat(30_000);
+        let options = &WriteOptions {
+            compression: Some(Compression::Deflate),
+            padding: Some(Padding::Padme),
+            ..Default::default()
+        };
+        root_dir
+            .write(
+                &["docs".into(), "small.txt".into()],
+                true,
+                Utc::now(),
+                b"small".to_vec(),
+                old_forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .write_with_options(
+                &["docs".into(), "large.txt".into()],
+                true,
+                Utc::now(),
+                large.clone(),
+                options,
+                old_forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .open_file_mut(
+                &["events.log".into()],
+                true,
+                Utc::now(),
+                old_forest,
+                store,
+                rng,
+            )
+            .await?
+            .append_records(
+                [b"one".to_vec(), b"two".to_vec()],
+                Utc::now(),
+                old_forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.store(old_forest, store, rng).await?;
+
+        let new_forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let node = &mut root_dir.as_node();
+        let access_key = node
+            .migrate_setup(
+                &new_forest.empty_name(),
+                &(),
+                old_forest,
+                new_forest,
+                store,
+                rng,
+            )
+            .await?;
+
+        assert_eq!(node.get_header().inumber, root_dir.header.inumber);
+        assert_eq!(node.get_header().ratchet, root_dir.header.ratchet);
+
+        let migrated = PrivateNode::load(&access_key, new_forest, store, None)
+            .await?
+            .as_dir()?;
+        let small = migrated
+            .read(
+                &["docs".into(), "small.txt".into()],
+                false,
+                new_forest,
+                store,
+            )
+            .await?;
+        let large_read = migrated
+            .read(
+                &["docs".into(), "large.txt".into()],
+                false,
+                new_forest,
+                store,
+            )
+            .await?;
+        let records = migrated
+            .get_node(&["events.log".into()], false, new_forest, store)
+            .await?
+            .unwrap()
+            .as_file()?
+            .read_records(0..2, new_forest, store)
+            .await?;
+
+        assert_eq!(small, b"small");
+        assert_eq!(large_read, large);
+        assert_eq!(records, [b"one".to_vec(), b"two".to_vec()]);
+
+        let large_file = migrated
+            .get_node(
+                &["docs".into(), "large.txt".into()],
+                false,
+                new_forest,
+                store,
+            )
+            .await?
+            .unwrap()
+            .as_file()?;
+        assert_eq!(large_file.get_compression(), Some(Compression::Deflate));
+        assert_eq!(large_file.get_padding(), Some(Padding::Padme));
+
+        Ok(())
+    }
+}
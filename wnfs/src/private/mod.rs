@@ -1,17 +1,82 @@
 //! Primitives for working with the private file system.
+//!
+//! # Cancellation
+//!
+//! Long-running operations are cancelled cooperatively with a
+//! [`CancellationToken`](wnfs_common::utils::CancellationToken). Run them on a store wrapped
+//! with [`CancellationToken::store`](wnfs_common::utils::CancellationToken::store) to stop
+//! searches for the latest revision, forest diffs and merges, path lookups and chunked writes
+//! at their next block access. Wrap the content of streaming writes with
+//! [`CancellationToken::reader`](wnfs_common::utils::CancellationToken::reader) to stop them
+//! while they read it. A cancelled operation fails with an error for which
+//! [`BlockStoreError::is_cancelled`](wnfs_common::BlockStoreError::is_cancelled) or
+//! `is::<Cancelled>()` holds.
+//!
+//! What a cancelled or dropped operation leaves behind depends on the operation:
+//!
+//! - Reads never modify any in-memory state.
+//! - Streaming writes read and encrypt all content before adding anything to the forest.
+//! - Forest updates are applied copy-on-write, one label at a time, so each label is either
+//!   fully updated or untouched.
+//! - Writes to a directory's descendants copy the directories along the path as they walk
+//!   it. If they're stopped part way, the directory they were called on may be left with
+//!   some of these copies in place, so discard it and load it again instead of storing it.
+//!
+//! Steps that only touch nodes already loaded into memory don't access the store and so
+//! aren't stopped by a cancelled token.
 
+mod ancestry;
+pub mod audit;
+mod backup;
+mod checksums;
+mod compression;
+mod conflicts;
+mod content_diff;
+#[cfg(feature = "sharing")]
+pub mod devices;
 mod directory;
 mod encrypted;
+mod fast_forward;
+mod fetch;
 mod file;
+mod file_handle;
 pub mod forest;
+mod inumber_index;
 mod keys;
 mod link;
+mod log;
+mod maintenance;
 mod node;
+mod node_cache;
+mod padding;
+mod prefetch;
 mod previous;
+mod record;
+mod replay;
+mod restore;
+#[cfg(feature = "sharing")]
 pub mod share;
+mod snapshot_handle;
 
+pub use backup::*;
+pub use checksums::*;
+pub use compression::*;
+pub use content_diff::*;
 pub use directory::*;
+pub use fetch::*;
 pub use file::*;
+pub use file_handle::*;
+pub use inumber_index::*;
 pub use keys::*;
+pub(crate) use link::PrivateLink;
+pub(crate) use log::PrivateLogContent;
 pub use node::*;
+pub use node_cache::*;
+pub use padding::*;
+pub use prefetch::*;
 pub use previous::*;
+pub use record::*;
+pub use replay::*;
+pub use restore::*;
+pub use skip_ratchet::JumpSize;
+pub use snapshot_handle::*;
@@ -13,6 +13,19 @@ use wnfs_common::{BlockStore, CODEC_RAW};
 use wnfs_hamt::Hasher;
 use wnfs_nameaccumulator::{Name, NameSegment};
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The maximum number of large ratchet epochs to step through when comparing two ratchets.
+const RATCHET_COMPARISON_BUDGET: usize = 10_000_000;
+
+/// The inode derivation domain separation info
+/// used for salting the hashing function when turning
+/// inumbers into inode numbers for file system integrations.
+/// This domain separation string is not part of the standard.
+const INODE_DSI: &str = "wnfs/1.0/inode derivation from inumber";
+
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
@@ -47,6 +60,12 @@ pub struct PrivateNodeHeader {
     pub(crate) ratchet: Ratchet,
     /// Stores the name of this node for easier lookup.
     pub(crate) name: Name,
+    /// The number of times the ratchet was advanced since it was created.
+    pub(crate) revision: u64,
+    /// When the ratchet was last advanced without a write, in seconds since the Unix epoch.
+    ///
+    /// See [`PrivateNode::advance_if_due`].
+    pub(crate) ratchet_advanced: Option<i64>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -62,12 +81,15 @@ impl PrivateNodeHeader {
             name: parent_name.with_segments_added(Some(inumber.clone())),
             ratchet: Ratchet::from_rng(rng),
             inumber,
+            revision: 0,
+            ratchet_advanced: None,
         }
     }
 
     /// Advances the ratchet.
     pub(crate) fn advance_ratchet(&mut self) {
         self.ratchet.inc();
+        self.revision += 1;
     }
 
     /// Updates the name to the child of given parent name.
@@ -76,13 +98,55 @@ impl PrivateNodeHeader {
     }
 
     /// Sets the ratchet and makes sure any caches are cleared.
+    ///
+    /// This doesn't adjust the revision counter, so it's meant for headers
+    /// that are only used to look up other revisions.
     pub(crate) fn update_ratchet(&mut self, ratchet: Ratchet) {
         self.ratchet = ratchet;
     }
 
-    /// Resets the ratchet.
+    /// Resets the ratchet, starting a new history at revision zero.
     pub(crate) fn reset_ratchet(&mut self, rng: &mut impl CryptoRngCore) {
         self.update_ratchet(Ratchet::from_rng(rng));
+        self.revision = 0;
+        self.ratchet_advanced = None;
+    }
+
+    /// Gets the revision counter of this header.
+    ///
+    /// It starts at zero when the node is created and increases by one with every
+    /// new revision, so it can be used to number and order revisions. Nodes written
+    /// before the counter existed count from zero at the revision they were at.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::private::PrivateFile;
+    /// use wnfs_nameaccumulator::{AccumulatorSetup, Name};
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// let rng = &mut ChaCha12Rng::from_entropy();
+    /// let setup = &AccumulatorSetup::from_rsa_2048(rng);
+    /// let file = PrivateFile::new(&Name::empty(setup), Utc::now(), rng);
+    ///
+    /// assert_eq!(file.header.revision(), 0);
+    /// ```
+    #[inline]
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Computes how many revisions this header is ahead of `other`.
+    ///
+    /// The result is negative if `other` is the more recent one. Fails if both
+    /// headers aren't on the same ratchet track, e.g. because they belong to
+    /// different nodes, or if they're too far apart to find out.
+    pub fn revisions_ahead_of(&self, other: &Self) -> Result<isize> {
+        self.ratchet
+            .compare(&other.ratchet, RATCHET_COMPARISON_BUDGET)
+            .map_err(|_| FsError::UnrelatedRevisions.into())
     }
 
     /// Derives the revision ref of the current header.
@@ -158,15 +222,73 @@ impl PrivateNodeHeader {
         &self.name
     }
 
+    /// Gets the inumber of this node.
+    ///
+    /// It stays the same across revisions and when the node is moved or renamed, so it can
+    /// be used to refer to the node durably. See [`InumberIndex`](crate::private::InumberIndex).
+    pub fn get_inumber(&self) -> &NameSegment {
+        &self.inumber
+    }
+
+    /// Gets a 64-bit inode number for this node, e.g. for FUSE or NFS integrations.
+    ///
+    /// It's derived from the inumber, so it stays the same across revisions and
+    /// when the node is moved or renamed, but copies of the node get a new one.
+    ///
+    /// It's a hash of the inumber cut down to 64 bits, so distinct nodes can collide,
+    /// although that's unlikely before a file system holds billions of nodes.
+    /// Integrations that need unique numbers should keep track of the numbers they
+    /// hand out and remap collisions, as well as any numbers they reserve, e.g. `1`
+    /// for the FUSE root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::private::PrivateFile;
+    /// use wnfs_nameaccumulator::{AccumulatorSetup, Name};
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// let rng = &mut ChaCha12Rng::from_entropy();
+    /// let setup = &AccumulatorSetup::from_rsa_2048(rng);
+    /// let file = PrivateFile::new(&Name::empty(setup), Utc::now(), rng);
+    /// let other = PrivateFile::new(&Name::empty(setup), Utc::now(), rng);
+    ///
+    /// assert_eq!(file.header.inode(), file.clone().header.inode());
+    /// assert_ne!(file.header.inode(), other.header.inode());
+    /// ```
+    pub fn inode(&self) -> u64 {
+        let hash = blake3::derive_key(INODE_DSI, &self.inumber.to_bytes());
+        u64::from_le_bytes(hash[..8].try_into().expect("hash has 32 bytes"))
+    }
+
     /// Encrypts this private node header in an block, then stores that in the given
     /// BlockStore and returns its CID.
     ///
     /// This *does not* store the block itself in the forest, only in the given block store.
     pub async fn store(&self, store: &impl BlockStore, forest: &impl PrivateForest) -> Result<Cid> {
+        let ciphertext = self.encrypt(forest)?;
+        Ok(store.put_block(ciphertext, CODEC_RAW).await?)
+    }
+
+    /// Computes the CID this header has when stored, without storing it.
+    ///
+    /// Header encryption is deterministic, so this is the CID of the header
+    /// block of the revision this header is at.
+    pub(crate) fn compute_cid(
+        &self,
+        store: &impl BlockStore,
+        forest: &impl PrivateForest,
+    ) -> Result<Cid> {
+        let ciphertext = self.encrypt(forest)?;
+        Ok(store.create_cid(&ciphertext, CODEC_RAW)?)
+    }
+
+    fn encrypt(&self, forest: &impl PrivateForest) -> Result<Vec<u8>> {
         let temporal_key = self.derive_temporal_key();
         let cbor_bytes = serde_ipld_dagcbor::to_vec(&self.to_serializable(forest))?;
-        let ciphertext = temporal_key.key_wrap_encrypt(&cbor_bytes)?;
-        Ok(store.put_block(ciphertext, CODEC_RAW).await?)
+        temporal_key.key_wrap_encrypt(&cbor_bytes)
     }
 
     pub(crate) fn to_serializable(
@@ -177,6 +299,8 @@ impl PrivateNodeHeader {
             inumber: self.inumber.clone(),
             ratchet: self.ratchet.clone(),
             name: forest.get_accumulated_name(&self.name),
+            revision: self.revision,
+            ratchet_advanced: self.ratchet_advanced,
         }
     }
 
@@ -185,6 +309,8 @@ impl PrivateNodeHeader {
             inumber: serializable.inumber,
             ratchet: serializable.ratchet,
             name: Name::new(serializable.name, []),
+            revision: serializable.revision,
+            ratchet_advanced: serializable.ratchet_advanced,
         }
     }
 
@@ -280,7 +406,7 @@ impl PrivateNodeHeader {
 
     pub(crate) fn ratchet_diff_for_merge(&self, other: &Self) -> Result<usize> {
         self.ratchet
-            .compare(&other.ratchet, 10_000_000)
+            .compare(&other.ratchet, RATCHET_COMPARISON_BUDGET)
             .map_err(|e| {
                 anyhow!("merge node set on different ratchet track (or history too far away): {e}")
             })?
@@ -1,9 +1,9 @@
 use crate::error::CryptError;
 use aes_kw::KekAes256;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use blake3::traits::digest::Digest;
 use chacha20poly1305::{
-    aead::{Aead, AeadCore, KeyInit},
+    aead::{Aead, AeadCore, KeyInit, Payload},
     AeadInPlace, Tag, XChaCha20Poly1305, XNonce,
 };
 use rand_core::CryptoRngCore;
@@ -23,6 +23,12 @@ pub(crate) const NONCE_SIZE: usize = 24;
 /// The size of the authentication tag used when encrypting using snapshot keys.
 /// The algorithm used is XChaCha20-Poly1305, so it's 128 bit.
 pub(crate) const AUTHENTICATION_TAG_SIZE: usize = 16;
+/// The size of the version flag in front of ciphertexts that are bound to associated data.
+pub(crate) const CIPHERTEXT_VERSION_SIZE: usize = 1;
+/// The version flag in front of ciphertexts that are bound to associated data, usually the
+/// label they're stored under in the private forest.
+/// Ciphertexts without it were written before associated data was supported.
+pub(crate) const AAD_CIPHERTEXT_VERSION: u8 = 1;
 /// The general key size used in WNFS: 256-bit
 pub const KEY_BYTE_SIZE: usize = 32;
 
@@ -41,10 +47,25 @@ pub(crate) const HIDING_SEGMENT_DSI: &str = "wnfs/1.0/hiding segment derivation
 /// used for salting the hashing function when generating
 /// the segments for each file's external content blocks.
 pub(crate) const BLOCK_SEGMENT_DSI: &str = "wnfs/1.0/segment derivation for file block";
+/// The overflow key derivation domain separation info
+/// used for salting the hashing function when deriving
+/// the keys of overflow blocks from the snapshot key in forests with deterministic nonces.
+/// This domain separation string is not part of the standard.
+pub(crate) const OVERFLOW_KEY_DSI: &str = "wnfs/1.0/overflow key derivation from snapshot";
 /// The temporal key derivation domain seperation info
 /// used for salting the hashing function when deriving
 /// symmetric keys from ratchets.
 pub(crate) const TEMPORAL_KEY_DSI: &str = "wnfs/1.0/temporal derivation from ratchet";
+/// The nonce key derivation domain separation info
+/// used for salting the hashing function when deriving
+/// the key that synthetic nonces are derived with from the snapshot key.
+/// This domain separation string is not part of the standard.
+pub(crate) const NONCE_KEY_DSI: &str = "wnfs/1.0/synthetic nonce key derivation from snapshot";
+/// The convergent key derivation domain separation info
+/// used for salting the hashing function when deriving
+/// the key that external file content keys are derived with from a convergence secret.
+/// This domain separation string is not part of the standard.
+pub(crate) const CONVERGENT_KEY_DSI: &str = "wnfs/1.0/convergent content key derivation";
 /// The snapshot key derivation domain separation info
 /// used for salting the hashing function when deriving
 /// the snapshot key from the temporal key.
@@ -58,6 +79,14 @@ pub(crate) const SNAPSHOT_KEY_DSI: &str = "wnfs/1.0/snapshot key derivation from
 #[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct SnapshotKey(#[serde(with = "serde_byte_array")] pub(crate) [u8; KEY_BYTE_SIZE]);
 
+/// A secret that file content keys are derived from with convergent encryption, see
+/// [`WriteOptions::convergence`](crate::options::WriteOptions::convergence).
+///
+/// Anyone holding it can check whether a forest contains a file with some guessed
+/// content, so it should be kept as secret as the keys to the forest's root directory.
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ConvergenceSecret(#[serde(with = "serde_byte_array")] pub(crate) [u8; KEY_BYTE_SIZE]);
+
 /// The key used to encrypt the header section of a node.
 #[derive(PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct TemporalKey(#[serde(with = "serde_byte_array")] pub(crate) [u8; KEY_BYTE_SIZE]);
@@ -144,20 +173,139 @@ impl SnapshotKey {
         Ok([nonce.to_vec(), cipher_text].concat())
     }
 
+    /// Encrypts the given plaintext using the key, binding it to given associated data.
+    ///
+    /// The ciphertext can only be decrypted by providing the same associated data again.
+    /// WNFS uses the label a ciphertext is stored under in the private forest, so
+    /// ciphertexts can't be swapped between labels unnoticed.
+    ///
+    /// The ciphertext is prefixed with a version flag, so it can be told apart from
+    /// ciphertexts produced by [`encrypt`][Self::encrypt].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::private::SnapshotKey;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// let rng = &mut ChaCha12Rng::from_entropy();
+    /// let key = SnapshotKey::new(rng);
+    ///
+    /// let plaintext = b"Hello World!";
+    /// let ciphertext = key.encrypt_with_aad(plaintext, b"label", rng).unwrap();
+    ///
+    /// assert_eq!(key.decrypt_with_aad(&ciphertext, b"label").unwrap(), plaintext);
+    /// assert!(key.decrypt_with_aad(&ciphertext, b"other label").is_err());
+    /// ```
+    pub fn encrypt_with_aad(
+        &self,
+        data: &[u8],
+        aad: &[u8],
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Vec<u8>> {
+        self.encrypt_with_nonce(&Self::generate_nonce(rng), data, aad)
+    }
+
+    /// Encrypts like [`encrypt_with_aad`][Self::encrypt_with_aad], but derives the nonce
+    /// from the key, the associated data and the plaintext instead of drawing it randomly.
+    ///
+    /// This is a synthetic IV construction, like the one of AES-GCM-SIV: encrypting the
+    /// same plaintext bound to the same associated data with the same key always results
+    /// in the same ciphertext, while nonces of different plaintexts don't repeat. The only
+    /// thing this reveals is whether two ciphertexts are equal, which is what makes it
+    /// useful for reproducible forests.
+    ///
+    /// The ciphertexts decrypt with [`decrypt_with_aad`][Self::decrypt_with_aad], just like
+    /// ones with random nonces.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::private::SnapshotKey;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// let rng = &mut ChaCha12Rng::from_entropy();
+    /// let key = SnapshotKey::new(rng);
+    ///
+    /// let plaintext = b"Hello World!";
+    /// let ciphertext = key.encrypt_with_aad_deterministic(plaintext, b"label").unwrap();
+    ///
+    /// assert_eq!(
+    ///     key.encrypt_with_aad_deterministic(plaintext, b"label").unwrap(),
+    ///     ciphertext
+    /// );
+    /// assert_eq!(key.decrypt_with_aad(&ciphertext, b"label").unwrap(), plaintext);
+    /// ```
+    pub fn encrypt_with_aad_deterministic(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce_key = blake3::derive_key(NONCE_KEY_DSI, &self.0);
+        let mut hasher = blake3::Hasher::new_keyed(&nonce_key);
+        hasher.update(&(aad.len() as u64).to_le_bytes());
+        hasher.update(aad);
+        hasher.update(data);
+        let hash = blake3::Hasher::finalize(&hasher);
+
+        let nonce = XNonce::clone_from_slice(&hash.as_bytes()[..NONCE_SIZE]);
+        self.encrypt_with_nonce(&nonce, data, aad)
+    }
+
+    /// Encrypts with [`encrypt_with_aad_deterministic`][Self::encrypt_with_aad_deterministic]
+    /// if `deterministic` is set, e.g. from
+    /// [`PrivateForest::uses_deterministic_nonces`](crate::private::forest::traits::PrivateForest::uses_deterministic_nonces),
+    /// and with [`encrypt_with_aad`][Self::encrypt_with_aad] otherwise.
+    pub(crate) fn encrypt_with_aad_deterministic_if(
+        &self,
+        data: &[u8],
+        aad: &[u8],
+        deterministic: bool,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Vec<u8>> {
+        if deterministic {
+            self.encrypt_with_aad_deterministic(data, aad)
+        } else {
+            self.encrypt_with_aad(data, aad, rng)
+        }
+    }
+
+    fn encrypt_with_nonce(&self, nonce: &XNonce, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let key = self.0.into();
+        let payload = Payload {
+            msg: data,
+            aad: &Self::versioned_aad(aad),
+        };
+        let cipher_text = XChaCha20Poly1305::new(&key)
+            .encrypt(nonce, payload)
+            .map_err(|e| CryptError::UnableToEncrypt(anyhow!(e)))?;
+
+        Ok([&[AAD_CIPHERTEXT_VERSION], nonce.as_slice(), &cipher_text].concat())
+    }
+
     /// Generates a random 24-byte extended nonce for encryption.
     pub(crate) fn generate_nonce(rng: &mut impl CryptoRngCore) -> XNonce {
         XChaCha20Poly1305::generate_nonce(rng)
     }
 
+    /// Returns the associated data that's actually authenticated for ciphertexts
+    /// bound to given associated data, which includes the version flag.
+    pub(crate) fn versioned_aad(aad: &[u8]) -> Vec<u8> {
+        [&[AAD_CIPHERTEXT_VERSION], aad].concat()
+    }
+
     /// Encrypts the cleartext in the given buffer in-place, with given key.
     ///
     /// The nonce is usually pre-pended to the ciphertext.
     ///
     /// The authentication tag is required for decryption and usually appended to the ciphertext.
-    pub(crate) fn encrypt_in_place(&self, nonce: &XNonce, buffer: &mut [u8]) -> Result<Tag> {
+    pub(crate) fn encrypt_in_place(
+        &self,
+        nonce: &XNonce,
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<Tag> {
         let key = self.0.into();
         let tag = XChaCha20Poly1305::new(&key)
-            .encrypt_in_place_detached(nonce, &[], buffer)
+            .encrypt_in_place_detached(nonce, aad, buffer)
             .map_err(|e| CryptError::UnableToEncrypt(anyhow!(e)))?;
         Ok(tag)
     }
@@ -191,6 +339,75 @@ impl SnapshotKey {
             .map_err(|e| CryptError::UnableToDecrypt(anyhow!(e)))?)
     }
 
+    /// Decrypts a ciphertext that was encrypted with [`encrypt_with_aad`][Self::encrypt_with_aad],
+    /// checking that it was bound to given associated data.
+    ///
+    /// Ciphertexts without version flag that were encrypted using [`encrypt`][Self::encrypt]
+    /// fail to decrypt, see [`decrypt_with_aad_or_unbound`][Self::decrypt_with_aad_or_unbound].
+    pub fn decrypt_with_aad(&self, cipher_text: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let [AAD_CIPHERTEXT_VERSION, rest @ ..] = cipher_text else {
+            bail!(CryptError::UnableToDecrypt(anyhow!(
+                "Ciphertext isn't bound to associated data"
+            )));
+        };
+
+        if rest.len() < NONCE_SIZE {
+            bail!(CryptError::UnableToDecrypt(anyhow!(
+                "Ciphertext is shorter than a nonce"
+            )));
+        }
+
+        let (nonce_bytes, data) = rest.split_at(NONCE_SIZE);
+        let key = self.0.into();
+        let payload = Payload {
+            msg: data,
+            aad: &Self::versioned_aad(aad),
+        };
+
+        Ok(XChaCha20Poly1305::new(&key)
+            .decrypt(XNonce::from_slice(nonce_bytes), payload)
+            .map_err(|e| CryptError::UnableToDecrypt(anyhow!(e)))?)
+    }
+
+    /// Decrypts like [`decrypt_with_aad`][Self::decrypt_with_aad], but also accepts
+    /// ciphertexts without version flag that were encrypted using [`encrypt`][Self::encrypt],
+    /// before associated data was supported. Their associated data can't be checked.
+    ///
+    /// This lets unbound ciphertexts be swapped between labels, so only use it for data
+    /// that's known to be written in the old format.
+    pub fn decrypt_with_aad_or_unbound(&self, cipher_text: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if let Ok(bytes) = self.decrypt_with_aad(cipher_text, aad) {
+            return Ok(bytes);
+        }
+
+        // Either an unversioned ciphertext that happens to start with the version flag,
+        // or a ciphertext that doesn't belong here, in which case this fails too.
+        if cipher_text.len() < NONCE_SIZE {
+            bail!(CryptError::UnableToDecrypt(anyhow!(
+                "Ciphertext is shorter than a nonce"
+            )));
+        }
+
+        self.decrypt(cipher_text)
+    }
+
+    /// Decrypts with [`decrypt_with_aad_or_unbound`][Self::decrypt_with_aad_or_unbound] if
+    /// `accept_unbound` is set, e.g. from
+    /// [`PrivateForest::accepts_unbound_ciphertexts`](crate::private::forest::traits::PrivateForest::accepts_unbound_ciphertexts),
+    /// and with [`decrypt_with_aad`][Self::decrypt_with_aad] otherwise.
+    pub(crate) fn decrypt_with_aad_if(
+        &self,
+        cipher_text: &[u8],
+        aad: &[u8],
+        accept_unbound: bool,
+    ) -> Result<Vec<u8>> {
+        if accept_unbound {
+            self.decrypt_with_aad_or_unbound(cipher_text, aad)
+        } else {
+            self.decrypt_with_aad(cipher_text, aad)
+        }
+    }
+
     /// Decrypts the ciphertext in the given buffer in-place, with given key.
     ///
     /// Usually the nonce is stored as the cipher's prefix and the tag as
@@ -199,12 +416,13 @@ impl SnapshotKey {
     pub(crate) fn decrypt_in_place(
         &self,
         nonce: &XNonce,
+        aad: &[u8],
         tag: &Tag,
         buffer: &mut [u8],
     ) -> Result<()> {
         let key = self.0.into();
         XChaCha20Poly1305::new(&key)
-            .decrypt_in_place_detached(nonce, &[], buffer, tag)
+            .decrypt_in_place_detached(nonce, aad, buffer, tag)
             .map_err(|e| CryptError::UnableToDecrypt(anyhow!(e)))?;
         Ok(())
     }
@@ -215,6 +433,61 @@ impl SnapshotKey {
     }
 }
 
+impl ConvergenceSecret {
+    /// Generate a random convergence secret from given randomness.
+    pub fn new(rng: &mut impl CryptoRngCore) -> Self {
+        Self(utils::get_random_bytes(rng))
+    }
+
+    /// Creates a convergence secret from its key material, e.g. after loading it from
+    /// a keystore.
+    pub fn from_bytes(bytes: [u8; KEY_BYTE_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    /// Derives the key to encrypt given content with.
+    ///
+    /// The key is a keyed hash of the content, so encrypting the same content with
+    /// keys derived from the same secret results in the same key, while the key reveals
+    /// nothing about the content to anyone who doesn't know the secret.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::private::ConvergenceSecret;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// let rng = &mut ChaCha12Rng::from_entropy();
+    /// let secret = ConvergenceSecret::new(rng);
+    ///
+    /// assert_eq!(secret.derive_content_key(b"Hello"), secret.derive_content_key(b"Hello"));
+    /// assert_ne!(secret.derive_content_key(b"Hello"), secret.derive_content_key(b"World"));
+    /// ```
+    pub fn derive_content_key(&self, content: &[u8]) -> SnapshotKey {
+        let mut hasher = self.content_key_hasher();
+        hasher.update(content);
+        SnapshotKey(hasher.finalize().into())
+    }
+
+    /// Returns a hasher that derives the same key as [`derive_content_key`](Self::derive_content_key),
+    /// with the content fed to it in parts.
+    pub(crate) fn content_key_hasher(&self) -> blake3::Hasher {
+        blake3::Hasher::new_keyed(&blake3::derive_key(CONVERGENT_KEY_DSI, &self.0))
+    }
+
+    /// Return the convergence secret's key material.
+    pub fn as_bytes(&self) -> &[u8; KEY_BYTE_SIZE] {
+        &self.0
+    }
+}
+
+impl Debug for ConvergenceSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ConvergenceSecret").field(&"..").finish()
+    }
+}
+
 impl Debug for SnapshotKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_tuple("SnapshotKey")
@@ -238,7 +511,7 @@ impl Debug for TemporalKey {
 #[cfg(test)]
 mod proptests {
     use super::*;
-    use proptest::{prelude::any, prop_assert_eq, prop_assert_ne};
+    use proptest::{prelude::any, prop_assert, prop_assert_eq, prop_assert_ne};
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
     use test_strategy::proptest;
@@ -273,14 +546,60 @@ mod proptests {
         let nonce = XNonce::from_slice(&nonce);
         let key = SnapshotKey(key_bytes);
 
-        let tag = key.encrypt_in_place(nonce, &mut buffer).unwrap();
+        let tag = key.encrypt_in_place(nonce, &[], &mut buffer).unwrap();
 
         if buffer.len() >= 16 {
             prop_assert_ne!(&buffer, &data);
         }
 
-        key.decrypt_in_place(nonce, &tag, &mut buffer).unwrap();
+        key.decrypt_in_place(nonce, &[], &tag, &mut buffer).unwrap();
 
         prop_assert_eq!(&buffer, &data);
     }
+
+    #[proptest(cases = 100)]
+    fn snapshot_key_binds_ciphertexts_to_associated_data(
+        data: Vec<u8>,
+        aad: [u8; 32],
+        other_aad: [u8; 32],
+        #[strategy(any::<[u8; KEY_BYTE_SIZE]>())] rng_seed: [u8; KEY_BYTE_SIZE],
+        key_bytes: [u8; KEY_BYTE_SIZE],
+    ) {
+        let key = SnapshotKey(key_bytes);
+        let rng = &mut ChaCha12Rng::from_seed(rng_seed);
+
+        let bound = key.encrypt_with_aad(&data, &aad, rng).unwrap();
+        prop_assert_eq!(key.decrypt_with_aad(&bound, &aad).unwrap(), data.clone());
+        prop_assert!(key.decrypt(&bound).is_err());
+        if aad != other_aad {
+            prop_assert!(key.decrypt_with_aad(&bound, &other_aad).is_err());
+        }
+
+        let deterministic = key.encrypt_with_aad_deterministic(&data, &aad).unwrap();
+        prop_assert_eq!(
+            key.encrypt_with_aad_deterministic(&data, &aad).unwrap(),
+            deterministic.clone()
+        );
+        prop_assert_eq!(
+            key.decrypt_with_aad(&deterministic, &aad).unwrap(),
+            data.clone()
+        );
+        if aad != other_aad {
+            prop_assert!(key.decrypt_with_aad(&deterministic, &other_aad).is_err());
+            prop_assert_ne!(
+                key.encrypt_with_aad_deterministic(&data, &other_aad)
+                    .unwrap(),
+                deterministic
+            );
+        }
+
+        // Ciphertexts from before associated data was supported are only read on request
+        let unbound = key.encrypt(&data, rng).unwrap();
+        prop_assert!(key.decrypt_with_aad(&unbound, &aad).is_err());
+        prop_assert_eq!(
+            key.decrypt_with_aad_or_unbound(&unbound, &aad).unwrap(),
+            data.clone()
+        );
+        prop_assert_eq!(key.decrypt_with_aad_or_unbound(&bound, &aad).unwrap(), data);
+    }
 }
@@ -2,9 +2,11 @@ mod header;
 mod keys;
 #[allow(clippy::module_inception)]
 mod node;
+mod overflow;
 mod serializable;
 
 pub use header::*;
 pub use keys::*;
 pub use node::*;
+pub use overflow::*;
 pub(crate) use serializable::*;
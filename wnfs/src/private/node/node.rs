@@ -1,30 +1,46 @@
-use super::{PrivateNodeHeader, TemporalKey};
+use super::{
+    resolve_entries_overflow, resolve_metadata_overflow, PrivateNodeHeader, SnapshotKey,
+    TemporalKey,
+};
 use crate::{
-    error::FsError,
+    error::{FsError, ValidationError},
     private::{
-        encrypted::Encrypted, forest::traits::PrivateForest, link::PrivateLink, AccessKey,
-        PrivateDirectory, PrivateFile, PrivateNodeContentSerializable, PrivateRef,
+        ancestry::PendingAncestry, encrypted::Encrypted, forest::traits::PrivateForest,
+        link::PrivateLink, AccessKey, PreviousLink, PrivateDirectory, PrivateFile,
+        PrivateNodeContentSerializable, PrivateRef,
     },
     traits::Id,
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use async_once_cell::OnceCell;
 use async_recursion::async_recursion;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use libipld_core::cid::Cid;
 use rand_core::CryptoRngCore;
-use skip_ratchet::{JumpSize, RatchetSeeker};
+use serde::{Deserialize, Serialize};
+use skip_ratchet::{JumpSize, Ratchet, RatchetSeeker};
 use std::{
     cmp::Ordering,
     collections::{BTreeMap, BTreeSet},
     fmt::Debug,
+    mem,
 };
 use wnfs_common::{
-    utils::{Arc, CondSend},
-    BlockStore,
+    utils::{boxed_fut, Arc, CondSend, ProgressReporter},
+    BlockStore, DryRunBlockStore, HashOutput, Metadata,
 };
 use wnfs_nameaccumulator::Name;
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of revisions in a medium ratchet epoch.
+const MEDIUM_EPOCH_LENGTH: u64 = 256;
+
+/// The number of revisions in a large ratchet epoch.
+const LARGE_EPOCH_LENGTH: u64 = 256 * 256;
+
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
@@ -55,6 +71,81 @@ pub enum PrivateNode {
     Dir(Arc<PrivateDirectory>),
 }
 
+/// Limits on how merge nodes keep track of the concurrent writes they merged.
+///
+/// Merge nodes link to every conflicting write they merged. When merge nodes get merged
+/// again, they keep all of these links, so repeated concurrent writes make them grow
+/// without bound. Limiting the links bounds the work of seeking through such revisions,
+/// at the cost of forgetting about some of the merged writes. Forgotten writes may get
+/// merged again later, which doesn't change the result, but they're no longer part of
+/// the node's history.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::private::ConflictPolicy;
+///
+/// let policy = ConflictPolicy {
+///     max_siblings: Some(16),
+/// };
+///
+/// assert_eq!(ConflictPolicy::default().max_siblings, None);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConflictPolicy {
+    /// The maximum number of previous links a merge node keeps, or `None` to keep all of them.
+    ///
+    /// A merge node always keeps at least two links, so lower limits are raised to two.
+    pub max_siblings: Option<usize>,
+}
+
+/// When to advance the ratchets of private nodes, independently of writes.
+///
+/// Every write starts a new revision with new keys, but rarely edited nodes can stay at
+/// the same revision for a long time. Advancing their ratchets regularly with
+/// [`PrivateNode::advance_if_due`] bounds how long the keys of any one revision stay
+/// current, e.g. how long a snapshot key that was handed out keeps giving access to the
+/// latest revision.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::private::RatchetPolicy;
+/// use chrono::Duration;
+///
+/// let policy = RatchetPolicy::daily();
+///
+/// assert_eq!(policy.max_revision_age, Some(Duration::days(1)));
+/// assert_eq!(RatchetPolicy::default().max_revision_age, None);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RatchetPolicy {
+    /// How old a revision may get before its ratchet is advanced, or `None` to only
+    /// advance ratchets on writes.
+    pub max_revision_age: Option<Duration>,
+}
+
+/// How far behind private nodes are expected to be when seeking to their latest revision
+/// with [`PrivateNode::search_latest_with_hint`].
+///
+/// Searches start with a jump size that fits the expected distance, instead of always
+/// checking the next revision first. Every search records the distance it observed, so
+/// keeping a hint around, e.g. per node or per forest, adapts it to the workload.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::private::{JumpSize, SearchHint};
+///
+/// assert_eq!(SearchHint::default().jump_size(), JumpSize::Small);
+/// assert_eq!(SearchHint::starting_with(JumpSize::Large).jump_size(), JumpSize::Large);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchHint {
+    /// How many revisions ahead the latest revision was in the last search.
+    pub last_distance: u64,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Implementations
 //--------------------------------------------------------------------------------------------------
@@ -107,12 +198,52 @@ impl PrivateNode {
         }
     }
 
+    /// Updates the bare name ancestry of this node only, like [`PrivateNode::update_ancestry`]
+    /// does for the whole subtree.
+    ///
+    /// A directory's children get their ancestry updated once they're modified instead,
+    /// using randomness that is drawn now and stored with the directory.
+    /// Unlike [`PrivateNode::update_ancestry`], it keeps inumbers, since it's used for
+    /// moving nodes rather than copying them.
+    pub(crate) fn update_ancestry_lazily(
+        &mut self,
+        parent_name: &Name,
+        rng: &mut impl CryptoRngCore,
+    ) {
+        match self {
+            Self::File(file_rc) => {
+                let file = Arc::make_mut(file_rc);
+                file.prepare_move(parent_name, rng);
+            }
+            Self::Dir(dir_rc) => {
+                let dir = Arc::make_mut(dir_rc);
+                let old_name = dir.header.name.clone();
+                // Entries that are still pending from an earlier move keep their older name
+                let mut pending_ancestry = std::mem::take(&mut dir.content.pending_ancestry);
+                for name in dir.content.entries.keys() {
+                    pending_ancestry
+                        .entry(name.clone())
+                        .or_insert_with(|| PendingAncestry::new(&old_name, rng));
+                }
+                dir.content.pending_ancestry = pending_ancestry;
+                dir.prepare_move(parent_name, rng);
+            }
+        }
+    }
+
     /// Updates bare name ancestry of private sub tree.
+    ///
+    /// `done` counts the nodes updated so far, which are reported to `progress`
+    /// along with the `total` number of nodes in the subtree.
+    #[allow(clippy::too_many_arguments)]
     #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
     #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
     pub(crate) async fn update_ancestry(
         &mut self,
         parent_name: &Name,
+        progress: &impl ProgressReporter,
+        done: &mut u64,
+        total: u64,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut (impl CryptoRngCore + CondSend),
@@ -125,19 +256,126 @@ impl PrivateNode {
             Self::Dir(dir_rc) => {
                 let dir = Arc::make_mut(dir_rc);
 
-                for private_link in &mut dir.content.entries.values_mut() {
+                // Children need to be named after the directory's new name,
+                // but are still stored under its old one
+                let old_name = dir.header.name.clone();
+                dir.prepare_key_rotation(parent_name, rng);
+
+                for (name, private_link) in dir.content.entries.iter_mut() {
+                    // Pending children are still named after an older ancestry
+                    let child_parent_name = dir
+                        .content
+                        .pending_ancestry
+                        .get(name)
+                        .map_or(&old_name, |pending| &pending.parent_name)
+                        .clone();
                     let mut node = private_link
-                        .resolve_node(forest, store, Some(dir.header.name.clone()))
+                        .resolve_node(forest, store, Some(child_parent_name))
                         .await?
                         .clone();
-                    node.update_ancestry(&dir.header.name, forest, store, rng)
-                        .await?;
+                    node.update_ancestry(
+                        &dir.header.name,
+                        progress,
+                        done,
+                        total,
+                        forest,
+                        store,
+                        rng,
+                    )
+                    .await?;
                     *private_link = PrivateLink::from(node);
                 }
 
+                dir.content.pending_ancestry.clear();
+            }
+        }
+
+        *done += 1;
+        progress.on_progress(*done, Some(total));
+
+        Ok(())
+    }
+
+    /// Counts the nodes of this subtree, including this node itself.
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    pub(crate) async fn count_nodes(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<u64> {
+        let Self::Dir(dir) = self else {
+            return Ok(1);
+        };
+
+        let mut count = 1;
+        for (name, private_link) in dir.content.entries.iter() {
+            // Pending children are still named after an older ancestry
+            let parent_name = dir
+                .content
+                .pending_ancestry
+                .get(name)
+                .map_or(&dir.header.name, |pending| &pending.parent_name)
+                .clone();
+            let node = private_link
+                .resolve_node(forest, store, Some(parent_name))
+                .await?;
+            count += node.count_nodes(forest, store).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Turns this subtree into a fresh instance of a template under given parent.
+    ///
+    /// Works like [`PrivateNode::update_ancestry`], except that every node also starts
+    /// a new history and gets its created and modified times set to `time`. File content
+    /// keeps its keys and blocks, so it's shared with the template instead of copied.
+    #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+    #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+    pub(crate) async fn instantiate_template(
+        &mut self,
+        parent_name: &Name,
+        time: DateTime<Utc>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        match self {
+            Self::File(file_rc) => {
+                let file = Arc::make_mut(file_rc);
+                file.prepare_key_rotation(parent_name, rng).await?;
+                file.content.previous.clear();
+                file.content.metadata.update(&Metadata::new(time));
+            }
+            Self::Dir(dir_rc) => {
+                let dir = Arc::make_mut(dir_rc);
+
+                let old_name = dir.header.name.clone();
                 dir.prepare_key_rotation(parent_name, rng);
+                dir.content.previous.clear();
+                dir.content.metadata.update(&Metadata::new(time));
+
+                for (name, private_link) in dir.content.entries.iter_mut() {
+                    let child_parent_name = dir
+                        .content
+                        .pending_ancestry
+                        .get(name)
+                        .map_or(&old_name, |pending| &pending.parent_name)
+                        .clone();
+                    let mut node = private_link
+                        .resolve_node(forest, store, Some(child_parent_name))
+                        .await?
+                        .clone();
+                    node.instantiate_template(&dir.header.name, time, forest, store, rng)
+                        .await?;
+                    *private_link = PrivateLink::from(node);
+                }
+
+                dir.content.pending_ancestry.clear();
             }
         }
+
         Ok(())
     }
 
@@ -172,6 +410,36 @@ impl PrivateNode {
         }
     }
 
+    /// Gets a 64-bit inode number for the node, e.g. for FUSE or NFS integrations.
+    ///
+    /// See [`PrivateNodeHeader::inode`] for how stable it is and how to handle collisions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, PrivateNode,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    /// };
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// let rng = &mut ChaCha12Rng::from_entropy();
+    /// let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    /// let dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    /// let node = PrivateNode::Dir(Arc::clone(&dir));
+    ///
+    /// assert_eq!(dir.header.inode(), node.inode());
+    /// ```
+    #[inline]
+    pub fn inode(&self) -> u64 {
+        self.get_header().inode()
+    }
+
     /// Gets the previous links of the node.
     ///
     /// The previous links are encrypted with the previous revision's
@@ -185,6 +453,9 @@ impl PrivateNode {
     /// If this node is a merge-node, it has two or more previous Cids.
     /// A single previous Cid must be from the previous revision, but all
     /// other Cids may appear in even older revisions.
+    ///
+    /// Use [`PrivateNode::decrypt_previous_links`] or [`PrivateNode::previous_links_since`]
+    /// to decrypt them.
     #[allow(clippy::mutable_key_type)]
     pub fn get_previous(&self) -> &BTreeSet<(usize, Encrypted<Cid>)> {
         match self {
@@ -193,6 +464,241 @@ impl PrivateNode {
         }
     }
 
+    /// Decrypts the previous links that given temporal key decrypts.
+    ///
+    /// Each previous link is encrypted with the temporal key of the revision it links to,
+    /// so given the temporal key of an older revision of this node, e.g. from its access key,
+    /// this returns the links to that revision. Links to other revisions are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateDirectory,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let old_access_key = dir.as_node().store(forest, store, rng).await?;
+    ///
+    ///     dir.mkdir(&["pictures".into()], true, Utc::now(), forest, store, rng).await?;
+    ///     dir.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let links = dir
+    ///         .as_node()
+    ///         .decrypt_previous_links(old_access_key.as_revision().unwrap().get_temporal_key());
+    ///
+    ///     assert_eq!(links.len(), 1);
+    ///     assert_eq!(links[0].revisions_back, 1);
+    ///     assert_eq!(&links[0].content_cid, old_access_key.get_content_cid());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn decrypt_previous_links(&self, temporal_key: &TemporalKey) -> Vec<PreviousLink> {
+        self.get_previous()
+            .iter()
+            .filter_map(|(revisions_back, encrypted)| {
+                let content_cid = encrypted.decrypt_value(temporal_key).ok()?;
+                Some(PreviousLink {
+                    revisions_back: *revisions_back,
+                    content_cid,
+                })
+            })
+            .collect()
+    }
+
+    /// Decrypts all previous links to revisions between given past revision of this
+    /// node and this revision, ordered by how many revisions back they link.
+    ///
+    /// Links to revisions before the past revision are skipped, since their keys
+    /// can't be derived. The discrepancy budget bounds the search for the revisions
+    /// in between, like in [`PrivateNodeHistory::of`](crate::private::PrivateNodeHistory::of).
+    pub fn previous_links_since(
+        &self,
+        past_node: &PrivateNode,
+        discrepancy_budget: usize,
+    ) -> Result<Vec<PreviousLink>> {
+        let ratchets = self
+            .get_header()
+            .ratchet
+            .previous(&past_node.get_header().ratchet, discrepancy_budget)
+            .map_err(FsError::NoIntermediateRatchet)?;
+
+        let mut links = Vec::new();
+        for (revisions_back, ratchet) in (1..).zip(ratchets) {
+            let temporal_key = TemporalKey::new(&ratchet);
+            for (_, encrypted) in self
+                .get_previous()
+                .iter()
+                .filter(|(back, _)| *back == revisions_back)
+            {
+                links.push(PreviousLink {
+                    revisions_back,
+                    content_cid: encrypted.decrypt_value(&temporal_key)?,
+                });
+            }
+        }
+
+        Ok(links)
+    }
+
+    /// Gets the revision counter of this node.
+    ///
+    /// See [`PrivateNodeHeader::revision`].
+    #[inline]
+    pub fn revision(&self) -> u64 {
+        self.get_header().revision()
+    }
+
+    /// Gets the time the current revision of this node started at.
+    ///
+    /// That's the later one of the time it was last modified and the time its ratchet was
+    /// last advanced with [`PrivateNode::advance_if_due`].
+    pub fn get_revision_time(&self) -> Option<DateTime<Utc>> {
+        let advanced = self
+            .get_header()
+            .ratchet_advanced
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single());
+
+        self.metadata().get_modified().max(advanced)
+    }
+
+    /// Starts a new revision of this node with the same content and stores it, if its
+    /// current revision is older than given policy allows.
+    ///
+    /// The node needs to have been stored before. Returns whether a new revision was
+    /// started. Parent directories keep linking to the previous revision until they're
+    /// stored again, but [`PrivateNode::search_latest`] finds the new one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         PrivateDirectory, RatchetPolicy,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    /// };
+    /// use chrono::{Duration, Utc};
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let now = Utc::now();
+    ///     let dir = PrivateDirectory::new_and_store(&forest.empty_name(), now, forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let policy = &RatchetPolicy::daily();
+    ///     let node = &mut dir.as_node();
+    ///
+    ///     assert!(!node.advance_if_due(policy, now + Duration::hours(1), forest, store, rng).await?);
+    ///     assert!(node.advance_if_due(policy, now + Duration::days(2), forest, store, rng).await?);
+    ///     assert_eq!(node.revision(), 1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn advance_if_due(
+        &mut self,
+        policy: &RatchetPolicy,
+        now: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<bool> {
+        if !policy.is_due(self, now) {
+            return Ok(false);
+        }
+
+        ensure!(
+            self.get_persisted_as().get().is_some(),
+            "Can't advance the ratchet of a node that hasn't been stored yet"
+        );
+
+        let header = match self {
+            Self::File(file) => &mut file.prepare_next_revision()?.header,
+            Self::Dir(dir) => &mut dir.prepare_next_revision()?.header,
+        };
+        header.ratchet_advanced = Some(now.timestamp());
+
+        self.store(forest, store, rng).await?;
+        Ok(true)
+    }
+
+    fn metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => &file.content.metadata,
+            Self::Dir(dir) => &dir.content.metadata,
+        }
+    }
+
+    /// Computes how many revisions this node is ahead of another revision of the same node.
+    ///
+    /// The result is negative if `other` is the more recent revision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         PrivateDirectory,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    /// };
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = &mut PrivateDirectory::new_and_store(
+    ///         &forest.empty_name(),
+    ///         Utc::now(),
+    ///         forest,
+    ///         store,
+    ///         rng,
+    ///     )
+    ///     .await?;
+    ///     let old_node = dir.as_node();
+    ///
+    ///     dir.mkdir(&["docs".into()], true, Utc::now(), forest, store, rng).await?;
+    ///     let new_node = dir.as_node();
+    ///     new_node.store(forest, store, rng).await?;
+    ///
+    ///     assert_eq!(new_node.revisions_ahead_of(&old_node)?, 1);
+    ///     assert_eq!(old_node.revisions_ahead_of(&new_node)?, -1);
+    ///     assert_eq!(new_node.revision(), old_node.revision() + 1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn revisions_ahead_of(&self, other: &PrivateNode) -> Result<isize> {
+        self.get_header().revisions_ahead_of(other.get_header())
+    }
+
     /// Casts a node to a directory.
     ///
     /// # Examples
@@ -400,6 +906,21 @@ impl PrivateNode {
         &self,
         forest: &impl PrivateForest,
         store: &impl BlockStore,
+    ) -> Result<PrivateNode> {
+        self.search_latest_reconciled_with_policy(&ConflictPolicy::default(), forest, store)
+            .await
+    }
+
+    /// Works like [`PrivateNode::search_latest_reconciled`], but limits the previous links
+    /// of the resulting merge node according to given [`ConflictPolicy`].
+    ///
+    /// Concurrent writes are always merged in the order of their content CIDs, so all
+    /// replicas arrive at the same merge node given the same policy.
+    pub async fn search_latest_reconciled_with_policy(
+        &self,
+        policy: &ConflictPolicy,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
     ) -> Result<PrivateNode> {
         let mut header = self.get_header().clone();
         let mut unmerged_heads = header.seek_unmerged_heads(forest, store).await?;
@@ -410,7 +931,7 @@ impl PrivateNode {
                 Ok(head)
             } else {
                 // We need to create a merge node
-                Self::merge(header, (cid, head), unmerged_heads, forest, store).await
+                Self::merge(header, (cid, head), unmerged_heads, policy, forest, store).await
             }
         } else {
             // If None, then there's nothing to merge in (and this node was never stored)
@@ -418,18 +939,99 @@ impl PrivateNode {
         }
     }
 
-    /// Merges a non-empty set of conflicting private nodes together
-    /// by merging them pair-wise.
-    pub(crate) async fn merge(
-        header: PrivateNodeHeader,
-        (cid, node): (Cid, PrivateNode),
-        nodes: BTreeMap<Cid, PrivateNode>,
-        forest: &impl PrivateForest,
-        store: &impl BlockStore,
-    ) -> Result<PrivateNode> {
-        match node {
-            PrivateNode::File(mut file) => {
-                // They *should* all be files, but we won't error out if they're not.
+    /// Reconciles this node with all concurrent writes and collapses the result
+    /// into a new revision with a single previous link, returning its access key.
+    ///
+    /// The merge node is stored first, so the collapsed revision links to it and
+    /// through it to all merged writes. Merging conflicts that come up later
+    /// starts from this single link instead of carrying over all of the merge
+    /// node's links. If there's nothing to merge, this stores the latest revision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::{utils::Arc, MemoryBlockStore},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir =
+    ///         PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng)
+    ///             .await?;
+    ///
+    ///     // Two concurrent writes
+    ///     for name in ["cats", "dogs"] {
+    ///         let mut fork = Arc::clone(&dir);
+    ///         fork.mkdir(&[name.into()], false, Utc::now(), forest, store, rng).await?;
+    ///         fork.as_node().store(forest, store, rng).await?;
+    ///     }
+    ///
+    ///     let mut node = dir.as_node();
+    ///     node.resolve_conflicts(forest, store, rng).await?;
+    ///
+    ///     assert_eq!(node.get_previous().len(), 1);
+    ///     assert_eq!(node.as_dir()?.get_entries().count(), 2);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn resolve_conflicts(
+        &mut self,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<AccessKey> {
+        let mut node = self.search_latest_reconciled(forest, store).await?;
+        let access_key = node.store_collapsed(forest, store, rng).await?;
+        *self = node;
+        Ok(access_key)
+    }
+
+    /// Stores this node, and if it's a merge node, follows it up with a revision
+    /// that links to it alone, so later readers don't need to merge again.
+    pub(crate) async fn store_collapsed(
+        &mut self,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<AccessKey> {
+        if self.get_previous().len() > 1 {
+            self.store(forest, store, rng).await?;
+            match self {
+                Self::File(file) => {
+                    file.prepare_next_revision()?;
+                }
+                Self::Dir(dir) => {
+                    dir.prepare_next_revision()?;
+                }
+            }
+        }
+
+        self.store(forest, store, rng).await
+    }
+
+    /// Merges a non-empty set of conflicting private nodes together
+    /// by merging them pair-wise.
+    pub(crate) async fn merge(
+        header: PrivateNodeHeader,
+        (cid, node): (Cid, PrivateNode),
+        nodes: BTreeMap<Cid, PrivateNode>,
+        policy: &ConflictPolicy,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<PrivateNode> {
+        match node {
+            PrivateNode::File(mut file) => {
+                // They *should* all be files, but we won't error out if they're not.
                 let files = nodes
                     .into_iter()
                     .filter_map(|(cid, node)| node.as_file().ok().map(|file| (cid, file)))
@@ -439,6 +1041,7 @@ impl PrivateNode {
                     file.merge(header.clone(), cid, &other_file, other_cid)?;
                 }
 
+                policy.limit_siblings(&mut Arc::make_mut(&mut file).content.previous);
                 Ok(PrivateNode::File(file))
             }
             PrivateNode::Dir(mut dir) => {
@@ -456,6 +1059,7 @@ impl PrivateNode {
                         .await?;
                 }
 
+                policy.limit_siblings(&mut Arc::make_mut(&mut dir).content.previous);
                 Ok(PrivateNode::Dir(dir))
             }
         }
@@ -464,11 +1068,76 @@ impl PrivateNode {
     /// Seek ahead to the latest revision in this node's history.
     ///
     /// The result are all nodes from the latest revision, each one
-    /// representing an instance of a concurrent write.
+    /// representing an instance of a concurrent write, ordered by content CID.
     pub async fn search_latest_nodes(
         &self,
         forest: &impl PrivateForest,
         store: &impl BlockStore,
+    ) -> Result<Vec<PrivateNode>> {
+        self.search_latest_nodes_with_hint(&mut SearchHint::default(), forest, store)
+            .await
+    }
+
+    /// Works like [`PrivateNode::search_latest`], but starts seeking with a jump size
+    /// that fits given hint, and updates the hint with the distance it observed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateDirectory, SearchHint,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let old_node = root_dir.as_node();
+    ///     old_node.store(forest, store, rng).await?;
+    ///
+    ///     for name in ["a", "b", "c"] {
+    ///         root_dir.mkdir(&[name.into()], true, Utc::now(), forest, store, rng).await?;
+    ///         root_dir.as_node().store(forest, store, rng).await?;
+    ///     }
+    ///
+    ///     let mut hint = SearchHint::default();
+    ///     let latest = old_node.search_latest_with_hint(&mut hint, forest, store).await?;
+    ///
+    ///     assert_eq!(latest.as_dir()?.ls(&[], true, forest, store).await?.len(), 3);
+    ///     assert_eq!(hint.last_distance, 3);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn search_latest_with_hint(
+        &self,
+        hint: &mut SearchHint,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<PrivateNode> {
+        self.search_latest_nodes_with_hint(hint, forest, store)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(FsError::NotFound.into())
+    }
+
+    /// Works like [`PrivateNode::search_latest_nodes`], but starts seeking with a jump size
+    /// that fits given hint, and updates the hint with the distance it observed.
+    pub async fn search_latest_nodes_with_hint(
+        &self,
+        hint: &mut SearchHint,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
     ) -> Result<Vec<PrivateNode>> {
         let header = self.get_header();
 
@@ -477,11 +1146,11 @@ impl PrivateNode {
             return Ok(vec![self.clone()]);
         }
 
-        // Start an exponential search, starting with a small jump.
+        // Start an exponential search, by default starting with a small jump.
         // In many cases, we'll be at the latest revision already, so we only
         // do a single lookup to the next version, most likely realize it's not
         // there and thus stop seeking.
-        let mut search = RatchetSeeker::new(header.ratchet.clone(), JumpSize::Small);
+        let mut search = RatchetSeeker::new(header.ratchet.clone(), hint.jump_size());
         let mut current_header = header.clone();
 
         loop {
@@ -504,6 +1173,8 @@ impl PrivateNode {
         }
 
         current_header.update_ratchet(search.current().clone());
+        hint.observe(&current_header.ratchet, &header.ratchet);
+
         Ok(current_header
             .get_multivalue(forest, store)
             .await?
@@ -520,29 +1191,124 @@ impl PrivateNode {
         store: &impl BlockStore,
         parent_name: Option<Name>,
     ) -> Result<PrivateNode> {
-        let cid = match forest
+        let cid = Self::find_content_cid(private_ref, forest, store).await?;
+
+        Self::from_cid(
+            cid,
+            &private_ref.temporal_key,
+            &private_ref.label,
+            forest,
+            store,
+            parent_name,
+        )
+        .await
+    }
+
+    async fn find_content_cid(
+        private_ref: &PrivateRef,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Cid> {
+        match forest
             .get_encrypted_by_hash(&private_ref.label, store)
             .await?
         {
-            Some(cids) if cids.contains(&private_ref.content_cid) => private_ref.content_cid,
+            Some(cids) if cids.contains(&private_ref.content_cid) => Ok(private_ref.content_cid),
             _ => bail!(FsError::NotFound),
-        };
-
-        Self::from_cid(cid, &private_ref.temporal_key, forest, store, parent_name).await
+        }
     }
 
+    /// Decrypts and deserializes the node at given CID, which must have been
+    /// stored under given label.
     pub(crate) async fn from_cid(
         cid: Cid,
         temporal_key: &TemporalKey,
+        label: &HashOutput,
         forest: &impl PrivateForest,
         store: &impl BlockStore,
         parent_name: Option<Name>,
     ) -> Result<PrivateNode> {
-        let encrypted_bytes = store.get_block(&cid).await?;
-        let snapshot_key = temporal_key.derive_snapshot_key();
-        let bytes = snapshot_key.decrypt(&encrypted_bytes)?;
-        let node: PrivateNodeContentSerializable = serde_ipld_dagcbor::from_slice(&bytes)?;
-        Ok(match node {
+        let snapshot_key = &temporal_key.derive_snapshot_key();
+        let serializable = Self::decrypt_serializable(
+            &cid,
+            snapshot_key,
+            label,
+            forest.accepts_unbound_ciphertexts(),
+            store,
+        )
+        .await?;
+        // Boxing the future, since it's part of every recursive node lookup
+        boxed_fut(Self::from_serializable(
+            serializable,
+            temporal_key,
+            cid,
+            forest,
+            store,
+            parent_name,
+        ))
+        .await
+    }
+
+    /// Decrypts the content of the node at given CID, without its header.
+    ///
+    /// Only the content block itself may be unbound to its label, if `accept_unbound` is set.
+    /// Overflow blocks were always bound.
+    pub(crate) async fn decrypt_serializable(
+        cid: &Cid,
+        snapshot_key: &SnapshotKey,
+        label: &HashOutput,
+        accept_unbound: bool,
+        store: &impl BlockStore,
+    ) -> Result<PrivateNodeContentSerializable> {
+        let encrypted_bytes = store.get_block(cid).await?;
+        let bytes = snapshot_key.decrypt_with_aad_if(&encrypted_bytes, label, accept_unbound)?;
+        let mut serializable: PrivateNodeContentSerializable =
+            serde_ipld_dagcbor::from_slice(&bytes)?;
+
+        // Boxing the futures, since they're part of every node lookup
+        let (metadata, overflow, cache) = match &mut serializable {
+            PrivateNodeContentSerializable::File(file) => (
+                &mut file.metadata,
+                &mut file.metadata_overflow,
+                &file.overflow_cache,
+            ),
+            PrivateNodeContentSerializable::Dir(dir) => {
+                let overflow = mem::take(&mut dir.entries_overflow);
+                let cache = &dir.overflow_cache;
+                boxed_fut(resolve_entries_overflow(
+                    &mut dir.entries,
+                    overflow,
+                    cache,
+                    store,
+                ))
+                .await?;
+                (
+                    &mut dir.metadata,
+                    &mut dir.metadata_overflow,
+                    &dir.overflow_cache,
+                )
+            }
+        };
+        boxed_fut(resolve_metadata_overflow(
+            metadata,
+            mem::take(overflow),
+            cache,
+            store,
+        ))
+        .await?;
+
+        Ok(serializable)
+    }
+
+    async fn from_serializable(
+        serializable: PrivateNodeContentSerializable,
+        temporal_key: &TemporalKey,
+        cid: Cid,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+        parent_name: Option<Name>,
+    ) -> Result<PrivateNode> {
+        Ok(match serializable {
             PrivateNodeContentSerializable::File(file) => {
                 let file = PrivateFile::from_serializable(
                     file,
@@ -643,6 +1409,72 @@ impl PrivateNode {
         PrivateNode::from_private_ref(&private_ref, forest, store, parent_name).await
     }
 
+    /// Loads a node like [`PrivateNode::load`], but first validates it, failing with a
+    /// [`ValidationError`] if it's malformed.
+    ///
+    /// Checks that previous links don't repeat and point to earlier revisions, that the
+    /// `created` and `modified` metadata fields are timestamps, that directory entry names
+    /// are valid path segments and, given a `parent_name`, that the node's name matches
+    /// its mount point. Only the node itself is checked, not its children.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     private::{PrivateDirectory, PrivateNode, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let access_key = dir.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let node = PrivateNode::load_strict(&access_key, forest, store, None).await?;
+    ///
+    ///     assert!(node.is_dir());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn load_strict(
+        access_key: &AccessKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+        parent_name: Option<Name>,
+    ) -> Result<PrivateNode> {
+        let private_ref = access_key.derive_private_ref()?;
+        let cid = Self::find_content_cid(&private_ref, forest, store).await?;
+        let temporal_key = &private_ref.temporal_key;
+        let snapshot_key = &temporal_key.derive_snapshot_key();
+        let serializable = Self::decrypt_serializable(
+            &cid,
+            snapshot_key,
+            &private_ref.label,
+            forest.accepts_unbound_ciphertexts(),
+            store,
+        )
+        .await?;
+        serializable.validate()?;
+
+        Self::from_serializable(serializable, temporal_key, cid, forest, store, parent_name)
+            .await
+            .map_err(|err| match err.downcast::<FsError>() {
+                Ok(FsError::MountPointAndDeserializedNameMismatch(mounted, deserialized)) => {
+                    ValidationError::NameMismatch(mounted, deserialized).into()
+                }
+                Ok(err) => err.into(),
+                Err(err) => err,
+            })
+    }
+
     /// Stores a node in the forest and returns an access key.
     pub async fn store(
         &self,
@@ -653,6 +1485,146 @@ impl PrivateNode {
         let private_ref = &self.store_and_get_private_ref(forest, store, rng).await?;
         Ok(AccessKey::Temporal(private_ref.into()))
     }
+
+    /// Computes the content CID that storing this node would result in, without writing
+    /// any blocks or changing the forest.
+    ///
+    /// The node and all of its descendants that weren't stored yet are encrypted and
+    /// encoded like [`PrivateNode::store`] does, against a copy of given forest. `store`
+    /// is only read from. Unlike storing, this doesn't remember the CIDs in the node, so it
+    /// can be stored for real afterwards.
+    ///
+    /// Encryption uses random nonces, so the CID only matches what a later
+    /// [`PrivateNode::store`] returns if that's given an rng in the same state, e.g. a
+    /// clone of the rng given here.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::{BlockStore, MemoryBlockStore},
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateDirectory,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let node = dir.as_node();
+    ///
+    ///     let cid = node.compute_cid(forest, store, &mut rng.clone()).await?;
+    ///     assert!(!store.has_block(&cid).await?);
+    ///
+    ///     let access_key = node.store(forest, store, rng).await?;
+    ///     assert_eq!(access_key.get_content_cid(), &cid);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn compute_cid(
+        &self,
+        forest: &(impl PrivateForest + Clone),
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Cid> {
+        let forest = &mut forest.clone();
+        let dry_run = &DryRunBlockStore::new(store);
+        let private_ref = self
+            .detached()
+            .store_and_get_private_ref(forest, dry_run, rng)
+            .await?;
+        Ok(private_ref.content_cid)
+    }
+
+    /// Clones this node, copying all of its descendants that weren't stored yet.
+    ///
+    /// Storing a node remembers its CID in the node, and clones share their descendants,
+    /// so storing the clone returned by this doesn't affect this node's descendants.
+    pub(crate) fn detached(&self) -> Self {
+        match self {
+            Self::File(file) => Self::File(Arc::new((**file).clone())),
+            Self::Dir(dir) => Self::Dir(Arc::new(dir.detached())),
+        }
+    }
+}
+
+impl ConflictPolicy {
+    /// Drops the previous links exceeding the sibling limit.
+    ///
+    /// Links to the most recent revisions are kept. Links to the same revision are
+    /// encrypted with the same key deterministically, so ordering them by ciphertext
+    /// makes all replicas keep the same ones.
+    #[allow(clippy::mutable_key_type)]
+    pub(crate) fn limit_siblings(&self, previous: &mut BTreeSet<(usize, Encrypted<Cid>)>) {
+        let Some(max_siblings) = self.max_siblings else {
+            return;
+        };
+
+        while previous.len() > max_siblings.max(2) {
+            previous.pop_last();
+        }
+    }
+}
+
+impl RatchetPolicy {
+    /// A policy that advances ratchets at most once a day.
+    pub fn daily() -> Self {
+        Self {
+            max_revision_age: Some(Duration::days(1)),
+        }
+    }
+
+    /// Whether the current revision of given node is older than this policy allows.
+    ///
+    /// Nodes without a modification time are never due.
+    pub fn is_due(&self, node: &PrivateNode, now: DateTime<Utc>) -> bool {
+        match (self.max_revision_age, node.get_revision_time()) {
+            (Some(max_age), Some(revision_time)) => now - revision_time >= max_age,
+            _ => false,
+        }
+    }
+}
+
+impl SearchHint {
+    /// Creates a hint that makes searches start with given jump size.
+    pub fn starting_with(jump_size: JumpSize) -> Self {
+        let last_distance = match jump_size {
+            JumpSize::Zero | JumpSize::Small => 0,
+            JumpSize::Medium => MEDIUM_EPOCH_LENGTH,
+            JumpSize::Large => LARGE_EPOCH_LENGTH,
+        };
+
+        Self { last_distance }
+    }
+
+    /// The jump size searches start with.
+    pub fn jump_size(&self) -> JumpSize {
+        match self.last_distance {
+            distance if distance >= LARGE_EPOCH_LENGTH => JumpSize::Large,
+            distance if distance >= MEDIUM_EPOCH_LENGTH => JumpSize::Medium,
+            _ => JumpSize::Small,
+        }
+    }
+
+    /// Records the distance between the revision a search started at and the latest one.
+    ///
+    /// Distances spanning more than one large epoch are only counted as one large epoch,
+    /// since finding out the exact distance would take a lot of hashing.
+    fn observe(&mut self, latest: &Ratchet, start: &Ratchet) {
+        self.last_distance = match latest.compare(start, 1) {
+            Ok(distance) => distance.max(0) as u64,
+            Err(_) => LARGE_EPOCH_LENGTH,
+        };
+    }
 }
 
 impl Id for PrivateNode {
@@ -686,7 +1658,53 @@ mod tests {
     use crate::private::forest::hamt::HamtForest;
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
-    use wnfs_common::MemoryBlockStore;
+    use testresult::TestResult;
+    use wnfs_common::{utils::CancellationToken, BlockStoreError, MemoryBlockStore, CODEC_RAW};
+
+    #[async_std::test]
+    async fn ratchets_advance_once_per_interval_without_writes() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let store = &MemoryBlockStore::new();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let file = PrivateFile::with_content(
+            &forest.empty_name(),
+            start,
+            b"rarely edited".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let old_node = PrivateNode::File(Arc::new(file));
+        old_node.store(forest, store, rng).await?;
+
+        let policy = &RatchetPolicy::daily();
+        let node = &mut old_node.clone();
+        let mut advanced = Vec::new();
+        for hours in [12, 24, 36, 47, 48] {
+            let now = start + Duration::hours(hours);
+            advanced.push(node.advance_if_due(policy, now, forest, store, rng).await?);
+        }
+
+        assert_eq!(advanced, [false, true, false, false, true]);
+        assert_eq!(node.revision(), 2);
+        assert_eq!(node.get_revision_time(), Some(start + Duration::hours(48)));
+
+        let latest = old_node.search_latest(forest, store).await?;
+        assert_eq!(latest.revision(), 2);
+        assert_eq!(
+            latest.get_revision_time(),
+            Some(start + Duration::hours(48))
+        );
+        assert_eq!(latest.metadata(), old_node.metadata());
+        assert_eq!(
+            latest.as_file()?.get_content(forest, store).await?,
+            b"rarely edited"
+        );
+
+        Ok(())
+    }
 
     #[async_std::test]
     async fn serialized_private_node_can_be_deserialized() {
@@ -730,4 +1748,265 @@ mod tests {
         assert_eq!(file_node, deserialized_file_node);
         assert_eq!(dir_node, deserialized_dir_node);
     }
+
+    #[async_std::test]
+    async fn revision_counter_survives_storing_and_searching() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PrivateDirectory::new_and_store(
+            &forest.empty_name(),
+            Utc::now(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let first = dir.as_node();
+
+        for name in ["a", "b", "c"] {
+            dir.mkdir(&[name.into()], true, Utc::now(), forest, store, rng)
+                .await?;
+            dir.store(forest, store, rng).await?;
+        }
+
+        let latest = first.search_latest(forest, store).await?;
+
+        assert_eq!(first.revision(), 0);
+        assert_eq!(latest.revision(), 3);
+        assert_eq!(latest.revisions_ahead_of(&first)?, 3);
+        assert_eq!(first.revisions_ahead_of(&latest)?, -3);
+
+        let unrelated = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng).as_node();
+        assert!(latest.revisions_ahead_of(&unrelated).is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn search_latest_stops_once_cancelled() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PrivateDirectory::new_and_store(
+            &forest.empty_name(),
+            Utc::now(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let first = dir.as_node();
+        dir.mkdir(&["a".into()], true, Utc::now(), forest, store, rng)
+            .await?;
+        dir.store(forest, store, rng).await?;
+
+        let token = CancellationToken::new();
+        let cancellable = &token.store(store);
+        assert_eq!(
+            first.search_latest(forest, cancellable).await?.revision(),
+            1
+        );
+
+        token.cancel();
+
+        let error = first.search_latest(forest, cancellable).await.unwrap_err();
+        assert!(error
+            .downcast_ref::<BlockStoreError>()
+            .unwrap()
+            .is_cancelled());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn search_hints_adapt_to_the_observed_distance() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PrivateDirectory::new_and_store(
+            &forest.empty_name(),
+            Utc::now(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let first = dir.as_node();
+
+        for _ in 0..5 {
+            dir.prepare_next_revision()?;
+            dir.store(forest, store, rng).await?;
+        }
+
+        let mut hint = SearchHint::starting_with(JumpSize::Large);
+        let latest = first
+            .search_latest_with_hint(&mut hint, forest, store)
+            .await?;
+        assert_eq!(latest.revision(), 5);
+        assert_eq!(hint.last_distance, 5);
+        assert_eq!(hint.jump_size(), JumpSize::Small);
+
+        let mut hint = SearchHint::starting_with(JumpSize::Medium);
+        latest
+            .search_latest_with_hint(&mut hint, forest, store)
+            .await?;
+        assert_eq!(hint, SearchHint::default());
+
+        let far_behind = SearchHint {
+            last_distance: 1_000,
+        };
+        assert_eq!(far_behind.jump_size(), JumpSize::Medium);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn computed_cids_match_what_gets_stored() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = &["docs".into(), "notes.md".into()];
+        root.write(path, true, Utc::now(), b"hi".to_vec(), forest, store, rng)
+            .await?;
+        let node = root.as_node();
+
+        let cid = node.compute_cid(forest, store, &mut rng.clone()).await?;
+        assert!(!store.has_block(&cid).await?);
+
+        let access_key = node.store(forest, store, rng).await?;
+        assert_eq!(access_key.get_content_cid(), &cid);
+
+        let loaded = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        assert_eq!(loaded.read(path, true, forest, store).await?, b"hi");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn strict_loading_checks_the_mount_point() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let store = &MemoryBlockStore::new();
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        root.mkdir(&["child".into()], true, Utc::now(), forest, store, rng)
+            .await?;
+        let child = root
+            .get_node(&["child".into()], false, forest, store)
+            .await?
+            .unwrap();
+        let access_key = child.store(forest, store, rng).await?;
+
+        let parent_name = Some(root.header.name.clone());
+        let loaded = PrivateNode::load_strict(&access_key, forest, store, parent_name).await?;
+        assert!(loaded.is_dir());
+
+        let wrong_parent = Some(forest.empty_name());
+        let result = PrivateNode::load_strict(&access_key, forest, store, wrong_parent).await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(ValidationError::NameMismatch(..))
+        ));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn unbound_ciphertexts_are_only_read_from_legacy_forests() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let private_ref = dir
+            .as_node()
+            .store_and_get_private_ref(forest, store, rng)
+            .await?;
+        let PrivateRef {
+            label,
+            temporal_key,
+            content_cid,
+        } = &private_ref;
+
+        // Written like before ciphertexts were bound to their labels
+        let snapshot_key = temporal_key.derive_snapshot_key();
+        let bytes = snapshot_key.decrypt_with_aad(&store.get_block(content_cid).await?, label)?;
+        let PrivateNodeContentSerializable::Dir(mut legacy) =
+            serde_ipld_dagcbor::from_slice(&bytes)?
+        else {
+            panic!("Expected a directory");
+        };
+        legacy.version = semver::Version::new(1, 0, 0);
+        let bytes = serde_ipld_dagcbor::to_vec(&PrivateNodeContentSerializable::Dir(legacy))?;
+        let unbound = snapshot_key.encrypt(&bytes, rng)?;
+        let unbound_cid = store.put_block(unbound, CODEC_RAW).await?;
+
+        let result = PrivateNode::from_cid(unbound_cid, temporal_key, label, forest, store, None);
+        assert!(result.await.is_err());
+
+        // Legacy versions aren't read from bound ciphertexts either
+        let bound = snapshot_key.encrypt_with_aad(&bytes, label, rng)?;
+        let bound_cid = store.put_block(bound, CODEC_RAW).await?;
+        let result = PrivateNode::from_cid(bound_cid, temporal_key, label, forest, store, None);
+        assert!(matches!(
+            result.await.unwrap_err().downcast_ref(),
+            Some(FsError::UnexpectedVersion(_))
+        ));
+
+        Arc::make_mut(forest).set_accept_unbound_ciphertexts(true);
+        let node =
+            PrivateNode::from_cid(unbound_cid, temporal_key, label, forest, store, None).await?;
+        assert!(node.is_dir());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn merge_nodes_respect_the_sibling_limit_and_can_be_collapsed() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir =
+            PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng)
+                .await?;
+
+        for i in 0..4 {
+            let mut fork = Arc::clone(&dir);
+            fork.mkdir(&[format!("dir{i}")], false, Utc::now(), forest, store, rng)
+                .await?;
+            fork.store(forest, store, rng).await?;
+        }
+
+        let node = dir.as_node();
+        let merged = node.search_latest_reconciled(forest, store).await?;
+        assert_eq!(merged.get_previous().len(), 4);
+
+        let policy = ConflictPolicy {
+            max_siblings: Some(2),
+        };
+        let limited = node
+            .search_latest_reconciled_with_policy(&policy, forest, store)
+            .await?;
+        assert_eq!(limited.get_previous().len(), 2);
+        assert_eq!(limited.as_dir()?.get_entries().count(), 4);
+        assert_eq!(
+            limited.get_previous(),
+            node.search_latest_reconciled_with_policy(&policy, forest, store)
+                .await?
+                .get_previous()
+        );
+
+        let mut collapsed = node.clone();
+        collapsed.resolve_conflicts(forest, store, rng).await?;
+        assert_eq!(collapsed.get_previous().len(), 1);
+        assert_eq!(collapsed.revision(), merged.revision() + 1);
+
+        let latest = node.search_latest_reconciled(forest, store).await?;
+        assert_eq!(latest.get_previous().len(), 1);
+        assert_eq!(latest.as_dir()?.get_entries().count(), 4);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,401 @@
+use super::{
+    SnapshotKey, AUTHENTICATION_TAG_SIZE, CIPHERTEXT_VERSION_SIZE, NONCE_SIZE, OVERFLOW_KEY_DSI,
+};
+use crate::{
+    private::{forest::traits::PrivateForest, PrivateForestContent},
+    utils,
+};
+use anyhow::Result;
+use libipld_core::{cid::Cid, ipld::Ipld};
+use parking_lot::Mutex;
+use rand_core::CryptoRngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wnfs_common::{
+    utils::{boxed_fut, CondSend},
+    BlockStore, HashOutput, Metadata, CODEC_RAW,
+};
+use wnfs_hamt::Hasher;
+use wnfs_nameaccumulator::{Name, NameAccumulator};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+pub use crate::METADATA_OVERFLOW_THRESHOLD;
+
+/// The number of bytes encryption adds to a block.
+pub(crate) const ENCRYPTION_OVERHEAD: usize =
+    CIPHERTEXT_VERSION_SIZE + NONCE_SIZE + AUTHENTICATION_TAG_SIZE;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A block that a node moved some of its content into.
+///
+/// Like external file content, it's encrypted with a key of its own and stored in the
+/// forest under a name derived from the node's name, so it's reachable from the forest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OverflowBlock {
+    pub(crate) key: SnapshotKey,
+    pub(crate) name: NameAccumulator,
+    pub(crate) cid: Cid,
+}
+
+/// The overflow blocks a node was last loaded from or stored with, by the hash of their
+/// plaintext, so storing the node again keeps the blocks of unchanged values.
+#[derive(Debug, Default)]
+pub(crate) struct OverflowCache(Mutex<BTreeMap<HashOutput, OverflowBlock>>);
+
+/// Writes the overflow blocks of a node, reusing the blocks it was stored with before.
+pub(crate) struct OverflowWriter<'a> {
+    name: &'a Name,
+    snapshot_key: &'a SnapshotKey,
+    cached: BTreeMap<HashOutput, OverflowBlock>,
+    written: BTreeMap<HashOutput, OverflowBlock>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl OverflowCache {
+    fn insert(&self, hash: HashOutput, block: OverflowBlock) {
+        self.0.lock().insert(hash, block);
+    }
+}
+
+impl Clone for OverflowCache {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.0.lock().clone()))
+    }
+}
+
+impl<'a> OverflowWriter<'a> {
+    /// Creates a writer for the node with given name and snapshot key.
+    pub(crate) fn new(
+        name: &'a Name,
+        snapshot_key: &'a SnapshotKey,
+        cache: &OverflowCache,
+    ) -> Self {
+        Self {
+            name,
+            snapshot_key,
+            cached: cache.0.lock().clone(),
+            written: BTreeMap::new(),
+        }
+    }
+
+    /// Stores given bytes in an overflow block, unless they were stored before.
+    async fn put(
+        &mut self,
+        bytes: &[u8],
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<OverflowBlock> {
+        let hash = blake3::hash(bytes).into();
+        if let Some(block) = self.cached.get(&hash) {
+            self.written.insert(hash, block.clone());
+            return Ok(block.clone());
+        }
+
+        // Forests with deterministic nonces mustn't depend on the rng nodes are stored with
+        let deterministic = forest.uses_deterministic_nonces();
+        let key = if deterministic {
+            let material = [&self.snapshot_key.0[..], &hash].concat();
+            SnapshotKey(blake3::derive_key(OVERFLOW_KEY_DSI, &material))
+        } else {
+            SnapshotKey::new(rng)
+        };
+
+        let name = PrivateForestContent::hide_base_name(self.name, &key);
+        let label = PrivateForestContent::block_label(&name, forest);
+        let block = key.encrypt_with_aad_deterministic_if(bytes, &label, deterministic, rng)?;
+        let cid = store.put_block(block, CODEC_RAW).await?;
+        let name = forest.put_encrypted(&name, Some(cid), store).await?;
+
+        let block = OverflowBlock { key, name, cid };
+        self.written.insert(hash, block.clone());
+        Ok(block)
+    }
+
+    /// Remembers the blocks written for the next time the node is stored.
+    pub(crate) fn finish(self, cache: &OverflowCache) {
+        *cache.0.lock() = self.written;
+    }
+}
+
+impl OverflowBlock {
+    /// Loads and decrypts the block, remembering it in given cache.
+    async fn get(&self, cache: &OverflowCache, store: &impl BlockStore) -> Result<Vec<u8>> {
+        let block = store.get_block(&self.cid).await?;
+        let label = blake3::Hasher::hash(&self.name);
+        let bytes = self.key.decrypt_with_aad(&block, &label)?;
+        cache.insert(blake3::hash(&bytes).into(), self.clone());
+        Ok(bytes)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Moves all values larger than [`METADATA_OVERFLOW_THRESHOLD`] out of given metadata.
+///
+/// Each moved value is stored in an overflow block of its own. Returns the remaining
+/// metadata and the moved values' blocks by key.
+pub(crate) async fn overflow_metadata(
+    metadata: &Metadata,
+    writer: &mut OverflowWriter<'_>,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut (impl CryptoRngCore + CondSend),
+) -> Result<(Metadata, BTreeMap<String, OverflowBlock>)> {
+    let mut inline = Metadata(BTreeMap::new());
+    let mut overflow = BTreeMap::new();
+    for (key, value) in metadata.0.iter() {
+        let bytes = serde_ipld_dagcbor::to_vec(value)?;
+        if bytes.len() <= METADATA_OVERFLOW_THRESHOLD {
+            inline.0.insert(key.clone(), value.clone());
+            continue;
+        }
+
+        // Boxing the future, since it's part of every recursive node store
+        let block = boxed_fut(writer.put(&bytes, forest, store, rng)).await?;
+        overflow.insert(key.clone(), block);
+    }
+
+    Ok((inline, overflow))
+}
+
+/// Loads the values that [`overflow_metadata`] moved out back into given metadata.
+pub(crate) async fn resolve_metadata_overflow(
+    metadata: &mut Metadata,
+    overflow: BTreeMap<String, OverflowBlock>,
+    cache: &OverflowCache,
+    store: &impl BlockStore,
+) -> Result<()> {
+    for (key, block) in overflow {
+        let bytes = block.get(cache, store).await?;
+        let value: Ipld = serde_ipld_dagcbor::from_slice(&bytes)?;
+        metadata.0.insert(key, value);
+    }
+
+    Ok(())
+}
+
+/// Moves the entries of a directory that doesn't fit into a single block of the store into
+/// overflow blocks, returning them.
+pub(crate) async fn overflow_entries<V: Serialize + Clone>(
+    entries: &BTreeMap<String, V>,
+    writer: &mut OverflowWriter<'_>,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut (impl CryptoRngCore + CondSend),
+) -> Result<Vec<OverflowBlock>> {
+    let max_size = store.max_block_size().saturating_sub(ENCRYPTION_OVERHEAD);
+    let mut blocks = Vec::new();
+    for group in utils::split_entries(entries, max_size)? {
+        let bytes = serde_ipld_dagcbor::to_vec(&group)?;
+        // Boxing the future, since it's part of every recursive node store
+        blocks.push(boxed_fut(writer.put(&bytes, forest, store, rng)).await?);
+    }
+
+    Ok(blocks)
+}
+
+/// Loads the entries that [`overflow_entries`] moved out back into given entries.
+pub(crate) async fn resolve_entries_overflow<V: DeserializeOwned>(
+    entries: &mut BTreeMap<String, V>,
+    overflow: Vec<OverflowBlock>,
+    cache: &OverflowCache,
+    store: &impl BlockStore,
+) -> Result<()> {
+    for block in overflow {
+        let bytes = block.get(cache, store).await?;
+        let group: BTreeMap<String, V> = serde_ipld_dagcbor::from_slice(&bytes)?;
+        entries.extend(group);
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{
+        forest::{hamt::HamtForest, traits::PrivateForest},
+        PrivateDirectory, PrivateNode,
+    };
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::{utils::reachable_blocks, MemoryBlockStore, SizeLimitedBlockStore, Storable};
+
+    fn cached_cids(cache: &OverflowCache) -> Vec<Cid> {
+        cache.0.lock().values().map(|block| block.cid).collect()
+    }
+
+    #[async_std::test]
+    async fn large_metadata_values_are_stored_outside_the_content_block() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let thumbnail = Ipld::Bytes(vec![0xFF; 64 * 1024]);
+        dir.update_metadata(
+            &[],
+            false,
+            |metadata| {
+                metadata.put("thumbnail", thumbnail.clone());
+                metadata.put("title", Ipld::String("Holiday".into()));
+            },
+            forest,
+            store,
+        )
+        .await?;
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+
+        let content_block = store.get_block(access_key.get_content_cid()).await?;
+        assert!(content_block.len() < METADATA_OVERFLOW_THRESHOLD * 2);
+
+        let loaded = PrivateNode::load(&access_key, forest, store, None).await?;
+        let metadata = loaded.as_dir()?.get_metadata().clone();
+        assert_eq!(metadata.get("thumbnail"), Some(&thumbnail));
+        assert_eq!(metadata, *dir.get_metadata());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn overflow_blocks_are_reachable_from_the_forest() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let thumbnail = Ipld::Bytes(vec![0xFF; 64 * 1024]);
+        dir.update_metadata(
+            &[],
+            false,
+            |metadata| {
+                metadata.put("thumbnail", thumbnail);
+            },
+            forest,
+            store,
+        )
+        .await?;
+        dir.as_node().store(forest, store, rng).await?;
+        let forest_cid = forest.store(store).await?;
+
+        let overflow_cids = cached_cids(&dir.content.overflow_cache);
+        assert_eq!(overflow_cids.len(), 1);
+        let reachable = reachable_blocks(&forest_cid, store).await?;
+        assert!(reachable.contains(&overflow_cids[0]));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn unchanged_overflow_values_keep_their_blocks() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        dir.update_metadata(
+            &[],
+            false,
+            |metadata| {
+                metadata.put("thumbnail", Ipld::Bytes(vec![0xFF; 64 * 1024]));
+            },
+            forest,
+            store,
+        )
+        .await?;
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let stored_cids = cached_cids(&dir.content.overflow_cache);
+
+        let mut loaded = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        loaded
+            .update_metadata(
+                &[],
+                false,
+                |metadata| {
+                    metadata.put("title", Ipld::String("Holiday".into()));
+                },
+                forest,
+                store,
+            )
+            .await?;
+        loaded.as_node().store(forest, store, rng).await?;
+        assert_eq!(cached_cids(&loaded.content.overflow_cache), stored_cids);
+
+        loaded
+            .update_metadata(
+                &[],
+                false,
+                |metadata| {
+                    metadata.put("thumbnail", Ipld::Bytes(vec![0xEE; 64 * 1024]));
+                },
+                forest,
+                store,
+            )
+            .await?;
+        loaded.as_node().store(forest, store, rng).await?;
+        let changed_cids = cached_cids(&loaded.content.overflow_cache);
+        assert_eq!(changed_cids.len(), 1);
+        assert_ne!(changed_cids, stored_cids);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn entries_of_large_directories_are_split_to_fit_the_block_size() -> TestResult {
+        let store = &SizeLimitedBlockStore::new(MemoryBlockStore::new(), 16 * 1024);
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        for i in 0..300 {
+            let path = &["docs".into(), format!("{i:03}.txt")];
+            dir.write(path, true, Utc::now(), vec![i as u8], forest, store, rng)
+                .await?;
+        }
+
+        // Without splitting, the docs directory wouldn't fit into a single block
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let forest_cid = forest.store(store).await?;
+
+        let loaded = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        let docs = loaded.ls(&["docs".into()], true, forest, store).await?;
+        assert_eq!(docs.len(), 300);
+
+        let docs_dir = loaded
+            .get_node(&["docs".into()], true, forest, store)
+            .await?
+            .unwrap()
+            .as_dir()?;
+        let overflow_cids = cached_cids(&docs_dir.content.overflow_cache);
+        assert!(overflow_cids.len() > 1);
+        let reachable = reachable_blocks(&forest_cid, store).await?;
+        assert!(overflow_cids.iter().all(|cid| reachable.contains(cid)));
+
+        let path = &["docs".into(), "123.txt".into()];
+        assert_eq!(loaded.read(path, true, forest, store).await?, [123]);
+
+        Ok(())
+    }
+}
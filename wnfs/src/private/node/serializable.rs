@@ -1,5 +1,9 @@
-use super::SnapshotKey;
-use crate::private::{encrypted::Encrypted, FileContent};
+use super::{OverflowBlock, OverflowCache, SnapshotKey};
+use crate::{
+    error::ValidationError,
+    private::{ancestry::AncestrySeed, encrypted::Encrypted, FileContent},
+    utils::{validate_entry_name, validate_metadata, validate_order, validate_previous},
+};
 use libipld_core::cid::Cid;
 use semver::Version;
 use serde::{Deserialize, Serialize};
@@ -27,7 +31,13 @@ pub(crate) struct PrivateFileContentSerializable {
     pub header_cid: Cid,
     pub previous: Vec<(usize, Encrypted<Cid>)>,
     pub metadata: Metadata,
+    /// Metadata values that are stored in blocks of their own, by key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata_overflow: BTreeMap<String, OverflowBlock>,
     pub content: FileContent,
+    /// The overflow blocks the node was loaded from.
+    #[serde(skip)]
+    pub overflow_cache: OverflowCache,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,7 +47,32 @@ pub(crate) struct PrivateDirectoryContentSerializable {
     pub previous: Vec<(usize, Encrypted<Cid>)>,
     pub header_cid: Cid,
     pub metadata: Metadata,
+    /// Metadata values that are stored in blocks of their own, by key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata_overflow: BTreeMap<String, OverflowBlock>,
     pub entries: BTreeMap<String, PrivateRefSerializable>,
+    /// Groups of entries that are stored in blocks of their own, for directories that
+    /// don't fit into a single block.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries_overflow: Vec<OverflowBlock>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>,
+    /// How often the entries were reordered, to merge orders with.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub order_revision: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pending_ancestry: BTreeMap<String, PendingAncestrySerializable>,
+    /// The overflow blocks the node was loaded from.
+    #[serde(skip)]
+    pub overflow_cache: OverflowCache,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PendingAncestrySerializable {
+    pub seed: AncestrySeed,
+    /// The accumulated name the entry is still named after.
+    pub parent_name: NameAccumulator,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +83,18 @@ pub(crate) struct PrivateNodeHeaderSerializable {
     pub ratchet: Ratchet,
     /// Stores the name of this node for easier lookup.
     pub name: NameAccumulator,
+    /// Counts the revisions since the ratchet was created.
+    ///
+    /// Left out while zero, so headers of first revisions keep their earlier encoding.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub revision: u64,
+    /// When the ratchet was last advanced without a write, in seconds since the Unix epoch.
+    #[serde(
+        default,
+        rename = "ratchetAdvanced",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ratchet_advanced: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,3 +107,80 @@ pub(crate) struct PrivateRefSerializable {
     pub temporal_key: Vec<u8>,
     pub content_cid: Cid,
 }
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateNodeContentSerializable {
+    /// Checks the invariants that strict loading enforces.
+    pub(crate) fn validate(&self) -> Result<(), ValidationError> {
+        let (metadata, previous) = match self {
+            Self::File(file) => (&file.metadata, &file.previous),
+            Self::Dir(dir) => {
+                for name in dir.entries.keys() {
+                    validate_entry_name(name)?;
+                }
+                validate_order(&dir.order)?;
+
+                (&dir.metadata, &dir.previous)
+            }
+        };
+
+        if previous
+            .iter()
+            .any(|(revisions_back, _)| *revisions_back == 0)
+        {
+            return Err(ValidationError::InvalidPrevious(
+                "links must point to earlier revisions".into(),
+            ));
+        }
+
+        validate_metadata(metadata)?;
+        validate_previous(previous)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn is_zero(revision: &u64) -> bool {
+    *revision == 0
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{
+        forest::{hamt::HamtForest, traits::PrivateForest},
+        PrivateNodeHeader,
+    };
+    use libipld_core::ipld::Ipld;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn headers_only_encode_non_zero_revisions() -> anyhow::Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &HamtForest::new_rsa_2048(rng);
+        let mut header = PrivateNodeHeader::new(&forest.empty_name(), rng).to_serializable(forest);
+
+        let encoded = serde_ipld_dagcbor::to_vec(&header)?;
+        let Ipld::Map(fields) = serde_ipld_dagcbor::from_slice(&encoded)? else {
+            unreachable!()
+        };
+        assert!(!fields.contains_key("revision"));
+
+        header.revision = 3;
+        let encoded = serde_ipld_dagcbor::to_vec(&header)?;
+        let decoded: PrivateNodeHeaderSerializable = serde_ipld_dagcbor::from_slice(&encoded)?;
+        assert_eq!(decoded.revision, 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+//! Bounded-memory resolution of private directory children.
+
+use super::{forest::traits::PrivateForest, PrivateDirectory, PrivateNode, PrivateRef};
+use crate::{error::FsError, SearchResult};
+use anyhow::{bail, Result};
+use libipld_core::cid::Cid;
+use quick_cache::sync::Cache;
+use std::fmt;
+use wnfs_common::{utils::Arc, BlockStore, Metadata};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A cache of resolved private nodes with a bounded capacity.
+///
+/// Directories keep every child they resolve for as long as they're around, so listing a
+/// directory with thousands of entries keeps thousands of nodes in memory. The `*_bounded`
+/// reads, like [`PrivateDirectory::ls_bounded`], leave children that weren't resolved
+/// before as references and only keep the most recently used nodes in this cache instead.
+/// Memory then stays proportional to the working set rather than to directory sizes.
+///
+/// Clones share the same cache.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///         NodeCache, PrivateDirectory, PrivateNode,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     for i in 0..10 {
+///         let path = &["photos".into(), format!("{i}.jpg")];
+///         root_dir.write(path, true, Utc::now(), vec![], forest, store, rng).await?;
+///     }
+///     let access_key = root_dir.as_node().store(forest, store, rng).await?;
+///
+///     let root_dir = PrivateNode::load(&access_key, forest, store, None).await?.as_dir()?;
+///     let cache = &NodeCache::new(4);
+///     let photos = root_dir
+///         .ls_bounded(&["photos".into()], true, cache, forest, store)
+///         .await?;
+///
+///     assert_eq!(photos.len(), 10);
+///     assert!(cache.len() <= 4);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct NodeCache {
+    nodes: Arc<Cache<Cid, PrivateNode>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl NodeCache {
+    /// Creates a cache that holds on to about `capacity` nodes at most.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            nodes: Arc::new(Cache::new(capacity.max(1))),
+        }
+    }
+
+    /// Returns the number of cached nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether no nodes are cached.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Drops all cached nodes.
+    pub fn clear(&self) {
+        self.nodes.clear();
+    }
+
+    /// Returns the node given private ref points to, loading it if it isn't cached.
+    pub(crate) async fn get_or_load(
+        &self,
+        private_ref: &PrivateRef,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+        parent_name: Option<Name>,
+    ) -> Result<PrivateNode> {
+        if let Some(node) = self.nodes.get(&private_ref.content_cid) {
+            return Ok(node);
+        }
+
+        let node = PrivateNode::from_private_ref(private_ref, forest, store, parent_name).await?;
+        self.nodes.insert(private_ref.content_cid, node.clone());
+        Ok(node)
+    }
+}
+
+impl fmt::Debug for NodeCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeCache")
+            .field("len", &self.len())
+            .field("capacity", &self.nodes.capacity())
+            .finish()
+    }
+}
+
+impl PrivateDirectory {
+    /// Looks up a child by name, like [`PrivateDirectory::lookup_node`], but without
+    /// keeping it in this directory. See [`NodeCache`].
+    pub async fn lookup_node_bounded(
+        &self,
+        path_segment: &str,
+        search_latest: bool,
+        cache: &NodeCache,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Option<PrivateNode>> {
+        let Some(private_link) = self.content.entries.get(path_segment) else {
+            return Ok(None);
+        };
+
+        let node = private_link
+            .resolve_node_bounded(
+                cache,
+                forest,
+                store,
+                Some(self.child_parent_name(path_segment)),
+            )
+            .await?;
+        if search_latest {
+            Ok(Some(node.search_latest_reconciled(forest, store).await?))
+        } else {
+            Ok(Some(node))
+        }
+    }
+
+    /// Follows a path to a node, like [`PrivateDirectory::get_node`], but without keeping
+    /// the nodes along the path in their directories. See [`NodeCache`].
+    pub async fn get_node_bounded(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        cache: &NodeCache,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Option<PrivateNode>> {
+        let Some((tail, path)) = path_segments.split_last() else {
+            return Ok(None);
+        };
+
+        let SearchResult::Found(dir) = self
+            .get_leaf_dir_bounded(path, search_latest, cache, forest, store)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        dir.lookup_node_bounded(tail, search_latest, cache, forest, store)
+            .await
+    }
+
+    /// Lists the names and metadata of a directory's children, like [`PrivateDirectory::ls`],
+    /// but without keeping the children in the directory. See [`NodeCache`].
+    pub async fn ls_bounded(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        cache: &NodeCache,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata)>> {
+        let dir = match self
+            .get_leaf_dir_bounded(path_segments, search_latest, cache, forest, store)
+            .await?
+        {
+            SearchResult::Found(dir) => dir,
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            SearchResult::Missing(_, _) => bail!(FsError::NotFound),
+        };
+
+        let mut result = vec![];
+        for (name, link) in crate::utils::iter_in_order(&dir.content.entries, &dir.content.order) {
+            let metadata = match link
+                .resolve_node_bounded(cache, forest, store, Some(dir.child_parent_name(name)))
+                .await?
+            {
+                PrivateNode::File(file) => file.content.metadata.clone(),
+                PrivateNode::Dir(dir) => dir.content.metadata.clone(),
+            };
+            result.push((name.clone(), metadata));
+        }
+
+        Ok(result)
+    }
+
+    async fn get_leaf_dir_bounded(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        cache: &NodeCache,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<SearchResult<Arc<Self>>> {
+        let mut working_dir = Arc::clone(self);
+        if search_latest {
+            working_dir = working_dir.search_latest_reconciled(forest, store).await?;
+        }
+
+        for (depth, segment) in path_segments.iter().enumerate() {
+            match working_dir
+                .lookup_node_bounded(segment, search_latest, cache, forest, store)
+                .await?
+            {
+                Some(PrivateNode::Dir(directory)) => working_dir = directory,
+                Some(_) => return Ok(SearchResult::NotADir(working_dir, depth)),
+                None => return Ok(SearchResult::Missing(working_dir, depth)),
+            }
+        }
+
+        Ok(SearchResult::Found(working_dir))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, PrivateLink};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn bounded_reads_leave_children_unresolved() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        for i in 0..20 {
+            let path = &["docs".into(), format!("{i:02}.txt")];
+            root_dir
+                .write(path, true, Utc::now(), vec![i], forest, store, rng)
+                .await?;
+        }
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+        let root_dir = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+
+        let cache = &NodeCache::new(4);
+        let docs = root_dir
+            .ls_bounded(&["docs".into()], true, cache, forest, store)
+            .await?;
+        assert_eq!(docs.len(), 20);
+        assert_eq!(docs[7].0, "07.txt");
+        assert!(cache.len() <= 4);
+
+        let path = &["docs".into(), "13.txt".into()];
+        let file = root_dir
+            .get_node_bounded(path, true, cache, forest, store)
+            .await?
+            .unwrap()
+            .as_file()?;
+        assert_eq!(file.get_content(forest, store).await?, [13]);
+
+        let docs_link = root_dir.content.entries.get("docs").unwrap();
+        assert!(matches!(docs_link, PrivateLink::Encrypted { cache, .. } if cache.get().is_none()));
+
+        let unbounded = root_dir.get_node(path, true, forest, store).await?;
+        assert_eq!(unbounded.unwrap().as_file()?, file);
+        assert!(root_dir
+            .get_node_bounded(
+                &["docs".into(), "missing".into()],
+                true,
+                cache,
+                forest,
+                store
+            )
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+}
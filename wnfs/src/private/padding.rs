@@ -0,0 +1,82 @@
+//! Padding of private file content, to hide its exact size.
+
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A scheme to pad private file content with before it's encrypted.
+///
+/// Ciphertexts are as long as the content they encrypt, so without padding anyone
+/// holding the content blocks learns the exact size of a file. Padding rounds sizes
+/// up, so files of similar sizes become indistinguishable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Padding {
+    /// Padmé, which leaks at most O(log log M) bits of sizes up to M, with an overhead
+    /// of at most 12% that shrinks for larger sizes.
+    ///
+    /// See [Reducing Metadata Leakage from Encrypted Files and Communication with PURBs](https://arxiv.org/abs/1806.03160).
+    Padme,
+
+    /// Rounds sizes up to the next multiple of given bucket size.
+    Bucket(u64),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Padding {
+    /// Returns the size content of given size is padded to.
+    pub fn padded_len(&self, len: u64) -> u64 {
+        match self {
+            Self::Padme => padme(len),
+            Self::Bucket(size) => len.next_multiple_of((*size).max(1)),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Keeps the top `floor(log2(E)) + 1` bits of the size's `E + 1` bits and rounds up the rest.
+fn padme(len: u64) -> u64 {
+    if len < 2 {
+        return len;
+    }
+
+    let exponent = len.ilog2();
+    let significant_bits = exponent.ilog2() + 1;
+    let mask = (1 << (exponent - significant_bits)) - 1;
+    (len + mask) & !mask
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padme_rounds_up_to_few_significant_bits() {
+        let padded: Vec<_> = [0, 1, 2, 9, 100, 1000, 1024, 1_000_000]
+            .into_iter()
+            .map(|len| Padding::Padme.padded_len(len))
+            .collect();
+
+        assert_eq!(padded, [0, 1, 2, 10, 104, 1024, 1024, 1_015_808]);
+    }
+
+    #[test]
+    fn buckets_round_up_to_multiples() {
+        assert_eq!(Padding::Bucket(4096).padded_len(0), 0);
+        assert_eq!(Padding::Bucket(4096).padded_len(1), 4096);
+        assert_eq!(Padding::Bucket(4096).padded_len(4097), 8192);
+        assert_eq!(Padding::Bucket(0).padded_len(7), 7);
+    }
+}
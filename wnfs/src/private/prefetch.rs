@@ -0,0 +1,278 @@
+//! Planning which blocks to fetch ahead of opening a private path.
+
+use super::{
+    forest::{hamt::HamtForest, traits::PrivateForest},
+    AccessKey, FileContent, PrivateForestContent, PrivateNode,
+};
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use libipld_core::cid::Cid;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use wnfs_common::{utils::CondSend, BlockStore, BlockStoreError, Storable};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A block store that records the CIDs of the blocks read from it, in the order they're
+/// first read.
+#[derive(Debug)]
+struct RecordingBlockStore<'a, B: BlockStore> {
+    inner: &'a B,
+    read: Mutex<(Vec<Cid>, HashSet<Cid>)>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, B: BlockStore> RecordingBlockStore<'a, B> {
+    fn new(inner: &'a B) -> Self {
+        Self {
+            inner,
+            read: Mutex::new((Vec::new(), HashSet::new())),
+        }
+    }
+
+    fn record(&self, cid: Cid) {
+        let (read, seen) = &mut *self.read.lock();
+        if seen.insert(cid) {
+            read.push(cid);
+        }
+    }
+
+    fn into_recorded(self) -> Vec<Cid> {
+        self.read.into_inner().0
+    }
+}
+
+impl<B: BlockStore> BlockStore for RecordingBlockStore<'_, B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        let bytes = self.inner.get_block(cid).await?;
+        self.record(*cid);
+        Ok(bytes)
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        self.inner.put_block_keyed(cid, bytes).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        self.inner.has_block(cid).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.inner.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+/// Computes the CIDs of all blocks needed to open the node at `path` below the node
+/// `access_key` gives access to, in the order they'll be needed.
+///
+/// That's the forest root, the forest nodes along the hashed labels that are looked up,
+/// the header and content blocks of every node on the path and, if the path leads to a
+/// file, its content blocks. Fetching these from a remote store in one batch, e.g. into
+/// a [`FetchingBlockStore`](super::FetchingBlockStore)'s local store, avoids a round
+/// trip per block when opening the path with the regular APIs afterwards.
+///
+/// The plan is made by loading the path from `store`, so it has to be made where the
+/// blocks are available, like on the remote that serves them. Only the content blocks of
+/// the target file aren't read, their CIDs are taken from the forest. With `search_latest`,
+/// the plan includes the blocks to find the latest revisions along the path.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::{BlockStore, MemoryBlockStore, Storable},
+///     private::{
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///         plan_open, PrivateDirectory, PrivateNode,
+///     },
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let (local, remote) = (&MemoryBlockStore::new(), &MemoryBlockStore::new());
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let path = &["docs".into(), "notes.md".into()];
+///     root_dir.write(path, true, Utc::now(), b"Hi".to_vec(), forest, remote, rng).await?;
+///     let access_key = root_dir.as_node().store(forest, remote, rng).await?;
+///     let forest_cid = forest.store(remote).await?;
+///
+///     // The remote plans, the client fetches everything in one go
+///     let plan = plan_open(&forest_cid, &access_key, path, true, remote).await?;
+///     for cid in plan {
+///         local.put_block_keyed(cid, remote.get_block(&cid).await?).await?;
+///     }
+///
+///     let forest = &HamtForest::load(&forest_cid, local).await?;
+///     let root_dir = PrivateNode::load(&access_key, forest, local, None).await?.as_dir()?;
+///     assert_eq!(root_dir.read(path, true, forest, local).await?, b"Hi");
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn plan_open(
+    forest_cid: &Cid,
+    access_key: &AccessKey,
+    path: &[String],
+    search_latest: bool,
+    store: &impl BlockStore,
+) -> Result<Vec<Cid>> {
+    let store = RecordingBlockStore::new(store);
+    let forest = &HamtForest::load(forest_cid, &store).await?;
+
+    let mut node = PrivateNode::load(access_key, forest, &store, None).await?;
+    if search_latest {
+        node = node.search_latest_reconciled(forest, &store).await?;
+    }
+
+    if !path.is_empty() {
+        let Some(target) = node
+            .as_dir()?
+            .get_node(path, search_latest, forest, &store)
+            .await?
+        else {
+            bail!(FsError::NotFound);
+        };
+        node = target;
+    }
+
+    let mut content_cids = Vec::new();
+    if let PrivateNode::File(file) = &node {
+        let names: Vec<Name> = match &file.content.content {
+            FileContent::External(content) => {
+                let base_name = &Name::new(content.base_name.clone(), []);
+                PrivateForestContent::generate_shard_labels(
+                    &content.key,
+                    0,
+                    content.block_count,
+                    base_name,
+                )
+                .collect()
+            }
+            FileContent::Log(log) => log.block_names().collect(),
+            FileContent::Inline { .. } => Vec::new(),
+        };
+
+        for name in names.iter() {
+            if let Some(cids) = forest.get_encrypted(name, &store).await? {
+                content_cids.extend(cids);
+            }
+        }
+    }
+
+    for cid in content_cids {
+        store.record(cid);
+    }
+
+    Ok(store.into_recorded())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::PrivateDirectory;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn planned_blocks_suffice_to_open_the_path() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let (local, remote) = (&MemoryBlockStore::new(), &MemoryBlockStore::new());
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let notes_path = &["docs".into(), "notes.md".into()];
+        let video_path = &["videos".into(), "talk.mp4".into()];
+        let notes = vec![3; 300_000];
+        root_dir
+            .write(
+                notes_path,
+                true,
+                Utc::now(),
+                notes.clone(),
+                forest,
+                remote,
+                rng,
+            )
+            .await?;
+        root_dir
+            .write(
+                video_path,
+                true,
+                Utc::now(),
+                vec![7; 300_000],
+                forest,
+                remote,
+                rng,
+            )
+            .await?;
+        let access_key = root_dir.as_node().store(forest, remote, rng).await?;
+        let video = root_dir
+            .get_node(video_path, true, forest, remote)
+            .await?
+            .unwrap()
+            .as_file()?;
+        let forest_cid = forest.store(remote).await?;
+
+        let plan = plan_open(&forest_cid, &access_key, notes_path, true, remote).await?;
+        assert_eq!(plan[0], forest_cid);
+        assert_eq!(plan.len(), plan.iter().collect::<HashSet<_>>().len());
+
+        // None of the other file's content is planned
+        let video_content = match &video.content.content {
+            FileContent::External(content) => content.clone(),
+            _ => panic!("expected external content"),
+        };
+        let base_name = &Name::new(video_content.base_name.clone(), []);
+        for name in PrivateForestContent::generate_shard_labels(
+            &video_content.key,
+            0,
+            video_content.block_count,
+            base_name,
+        ) {
+            for cid in forest.get_encrypted(&name, remote).await?.unwrap() {
+                assert!(!plan.contains(&cid));
+            }
+        }
+
+        for cid in plan {
+            local
+                .put_block_keyed(cid, remote.get_block(&cid).await?)
+                .await?;
+        }
+
+        let forest = &HamtForest::load(&forest_cid, local).await?;
+        let root_dir = PrivateNode::load(&access_key, forest, local, None)
+            .await?
+            .as_dir()?;
+        assert_eq!(root_dir.read(notes_path, true, forest, local).await?, notes);
+
+        Ok(())
+    }
+}
@@ -13,6 +13,17 @@ use wnfs_common::{utils::Arc, BlockStore, PathNodes, PathNodesResult};
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// A decrypted link from a private node to one of its previous revisions.
+///
+/// See [`PrivateNode::decrypt_previous_links`] and [`PrivateNode::previous_links_since`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PreviousLink {
+    /// How many revisions before the node's revision the linked revision is.
+    pub revisions_back: usize,
+    /// The CID of the linked revision's encrypted content block.
+    pub content_cid: Cid,
+}
+
 /// Represents the state of an iterator through the history
 /// of a private node on a path relative to a root directory.
 pub struct PrivateNodeOnPathHistory<F: PrivateForest + Clone> {
@@ -584,6 +595,54 @@ mod tests {
         assert!(iterator.get_previous(store).await.unwrap().is_none());
     }
 
+    #[async_std::test]
+    async fn previous_links_decrypt_with_older_revisions() {
+        let TestSetup {
+            mut rng,
+            mut store,
+            ref mut forest,
+            mut root_dir,
+            discrepancy_budget,
+        } = TestSetup::new();
+
+        let rng = &mut rng;
+        let store = &mut store;
+
+        let first_key = root_dir.as_node().store(forest, store, rng).await.unwrap();
+        let past_node = root_dir.as_node();
+
+        root_dir
+            .mkdir(&["docs".into()], true, Utc::now(), forest, store, rng)
+            .await
+            .unwrap();
+        let second_key = root_dir.as_node().store(forest, store, rng).await.unwrap();
+
+        root_dir
+            .mkdir(&["pictures".into()], true, Utc::now(), forest, store, rng)
+            .await
+            .unwrap();
+        root_dir.as_node().store(forest, store, rng).await.unwrap();
+
+        let node = root_dir.as_node();
+        let expected = vec![PreviousLink {
+            revisions_back: 1,
+            content_cid: *second_key.get_content_cid(),
+        }];
+
+        assert_eq!(
+            node.previous_links_since(&past_node, discrepancy_budget)
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            node.decrypt_previous_links(second_key.as_revision().unwrap().get_temporal_key()),
+            expected
+        );
+        assert!(node
+            .decrypt_previous_links(first_key.as_revision().unwrap().get_temporal_key())
+            .is_empty());
+    }
+
     /// This test will generate the following file system structure:
     ///
     /// (horizontal = time series, vertical = hierarchy)
@@ -0,0 +1,342 @@
+//! Encrypted, versioned values stored directly in a private forest.
+//!
+//! Records are for applications that want the private forest's encryption and
+//! versioning for small values, without any file system structure around them.
+
+use super::{forest::traits::PrivateForest, AccessKey, PrivateNodeHeader, PrivateRef, TemporalKey};
+use crate::{error::FsError, is_readable_wnfs_version, WNFS_VERSION};
+use anyhow::{bail, Result};
+use async_once_cell::OnceCell;
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use wnfs_common::{BlockStore, HashOutput, CODEC_RAW};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An encrypted value stored under a name in a private forest.
+///
+/// Records are versioned and encrypted with ratcheted keys just like private nodes:
+/// an access key to one revision gives read access to all later revisions, but not
+/// to earlier ones. Unlike private nodes, they don't link to their previous revisions
+/// and they aren't mounted anywhere, so they don't have a path or metadata.
+///
+/// Values are stored inline in a single block, so records are meant for small values.
+/// Use [`PrivateFile`](crate::private::PrivateFile)s for anything bigger.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     private::{PrivateRecord, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///
+///     let mut record = PrivateRecord::new(&forest.empty_name(), b"dark mode".to_vec(), rng);
+///     let access_key = record.store(forest, store, rng).await?;
+///
+///     record.set_value(b"light mode".to_vec());
+///     record.store(forest, store, rng).await?;
+///
+///     let record = PrivateRecord::load(&access_key, forest, store).await?;
+///     assert_eq!(record.get_value(), b"dark mode");
+///
+///     let latest = record.search_latest(forest, store).await?;
+///     assert_eq!(latest.get_value(), b"light mode");
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PrivateRecord {
+    pub header: PrivateNodeHeader,
+    value: Vec<u8>,
+    persisted_as: OnceCell<Cid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum PrivateRecordSerializable {
+    #[serde(rename = "wnfs/priv/record")]
+    Record(PrivateRecordContentSerializable),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PrivateRecordContentSerializable {
+    version: Version,
+    header_cid: Cid,
+    #[serde(with = "serde_bytes")]
+    value: Vec<u8>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateRecord {
+    /// Creates a new record with given value under given name.
+    ///
+    /// The record's name is derived from the name, so write access to the
+    /// name can be delegated like write access to private directories.
+    pub fn new(parent_name: &Name, value: Vec<u8>, rng: &mut impl CryptoRngCore) -> Self {
+        Self {
+            header: PrivateNodeHeader::new(parent_name, rng),
+            value,
+            persisted_as: OnceCell::new(),
+        }
+    }
+
+    /// Gets the value of this record.
+    pub fn get_value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// Sets the value of this record.
+    ///
+    /// If the current revision was stored already, this starts a new revision,
+    /// so the next [`PrivateRecord::store`] doesn't overwrite it.
+    pub fn set_value(&mut self, value: Vec<u8>) {
+        if self.persisted_as.get().is_some() {
+            self.header.advance_ratchet();
+            self.persisted_as = OnceCell::new();
+        }
+
+        self.value = value;
+    }
+
+    /// Encrypts and stores the current revision of this record in the forest,
+    /// returning the access key to it.
+    pub async fn store(
+        &self,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<AccessKey> {
+        let header_cid = self.header.store(store, forest).await?;
+        let revision_ref = self.header.derive_revision_ref(forest);
+        let snapshot_key = revision_ref.temporal_key.derive_snapshot_key();
+
+        let content_cid = *self
+            .persisted_as
+            .get_or_try_init::<anyhow::Error>(async {
+                let serializable =
+                    PrivateRecordSerializable::Record(PrivateRecordContentSerializable {
+                        version: WNFS_VERSION,
+                        header_cid,
+                        value: self.value.clone(),
+                    });
+                let bytes = serde_ipld_dagcbor::to_vec(&serializable)?;
+                let label = &revision_ref.label;
+                let deterministic = forest.uses_deterministic_nonces();
+                let block = snapshot_key.encrypt_with_aad_deterministic_if(
+                    &bytes,
+                    label,
+                    deterministic,
+                    rng,
+                )?;
+                Ok(store.put_block(block, CODEC_RAW).await?)
+            })
+            .await?;
+
+        forest
+            .put_encrypted(
+                &self.header.get_revision_name(),
+                [header_cid, content_cid],
+                store,
+            )
+            .await?;
+
+        let private_ref = &revision_ref.into_private_ref(content_cid);
+        Ok(AccessKey::Temporal(private_ref.into()))
+    }
+
+    /// Loads the revision of a record that the access key points to.
+    pub async fn load(
+        access_key: &AccessKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let PrivateRef {
+            label,
+            temporal_key,
+            content_cid,
+        } = access_key.derive_private_ref()?;
+
+        match forest.get_encrypted_by_hash(&label, store).await? {
+            Some(cids) if cids.contains(&content_cid) => {}
+            _ => bail!(FsError::NotFound),
+        }
+
+        Self::from_cid(content_cid, &temporal_key, &label, forest, store).await
+    }
+
+    /// Looks up the latest revision of this record in the forest.
+    ///
+    /// Records written concurrently at the same revision aren't merged,
+    /// the one with the lowest CID wins instead.
+    pub async fn search_latest(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let mut header = self.header.clone();
+        let mut latest = None;
+
+        loop {
+            let revision_ref = header.derive_revision_ref(forest);
+            let Some(cids) = forest
+                .get_encrypted_by_hash(&revision_ref.label, store)
+                .await?
+            else {
+                break;
+            };
+
+            // The header blocks stored next to records don't decrypt as records
+            for cid in cids.iter() {
+                let temporal_key = &revision_ref.temporal_key;
+                let label = &revision_ref.label;
+                if let Ok(record) = Self::from_cid(*cid, temporal_key, label, forest, store).await {
+                    latest = Some(record);
+                    break;
+                }
+            }
+
+            header.advance_ratchet();
+        }
+
+        Ok(latest.unwrap_or_else(|| self.clone()))
+    }
+
+    async fn from_cid(
+        cid: Cid,
+        temporal_key: &TemporalKey,
+        label: &HashOutput,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let encrypted_bytes = store.get_block(&cid).await?;
+        let snapshot_key = temporal_key.derive_snapshot_key();
+        let bytes = snapshot_key.decrypt_with_aad(&encrypted_bytes, label)?;
+        let PrivateRecordSerializable::Record(serializable) =
+            serde_ipld_dagcbor::from_slice(&bytes)?;
+
+        if !is_readable_wnfs_version(&serializable.version) {
+            bail!(FsError::UnexpectedVersion(serializable.version));
+        }
+
+        let header =
+            PrivateNodeHeader::load(&serializable.header_cid, temporal_key, forest, store, None)
+                .await?;
+
+        Ok(Self {
+            header,
+            value: serializable.value,
+            persisted_as: OnceCell::new_with(cid),
+        })
+    }
+}
+
+impl Clone for PrivateRecord {
+    fn clone(&self) -> Self {
+        Self {
+            header: self.header.clone(),
+            value: self.value.clone(),
+            persisted_as: self
+                .persisted_as
+                .get()
+                .cloned()
+                .map(OnceCell::new_with)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl PartialEq for PrivateRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.header == other.header && self.value == other.value
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, PrivateNode};
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn access_keys_only_give_access_to_later_revisions() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let mut record = PrivateRecord::new(&forest.empty_name(), b"one".to_vec(), rng);
+        record.store(forest, store, rng).await?;
+        record.set_value(b"two".to_vec());
+        let access_key = record.store(forest, store, rng).await?;
+        record.set_value(b"three".to_vec());
+        record.store(forest, store, rng).await?;
+
+        let loaded = PrivateRecord::load(&access_key, forest, store).await?;
+        assert_eq!(loaded.get_value(), b"two");
+        assert_eq!(loaded.header.revision(), 1);
+
+        let latest = loaded.search_latest(forest, store).await?;
+        assert_eq!(latest, record);
+        assert_eq!(latest.header.revision(), 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn setting_values_before_storing_keeps_the_revision() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let mut record = PrivateRecord::new(&forest.empty_name(), b"draft".to_vec(), rng);
+        record.set_value(b"final".to_vec());
+        let access_key = record.store(forest, store, rng).await?;
+
+        assert_eq!(record.header.revision(), 0);
+        let loaded = PrivateRecord::load(&access_key, forest, store).await?;
+        assert_eq!(loaded.get_value(), b"final");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn records_cant_be_loaded_as_nodes() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let record = PrivateRecord::new(&forest.empty_name(), b"value".to_vec(), rng);
+        let access_key = record.store(forest, store, rng).await?;
+
+        assert!(PrivateNode::load(&access_key, forest, store, None)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+//! Reproducibly building private forests from logs of file system operations.
+
+use super::{
+    forest::{hamt::HamtForest, traits::PrivateForest},
+    AccessKey, PrivateDirectory, PrivateNode, PrivateNodeHistory,
+};
+use anyhow::{anyhow, Result};
+use async_recursion::async_recursion;
+use chrono::{DateTime, TimeZone, Utc};
+use rand_chacha::ChaCha12Rng;
+use rand_core::SeedableRng;
+use serde::{Deserialize, Serialize};
+use wnfs_common::{impl_storable_from_serde, utils::Arc, BlockStore, Metadata};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A log of operations on a private root directory that can be replayed into the exact
+/// same private forest, down to the last block, by any implementation.
+///
+/// All randomness is drawn from a [`ChaCha12Rng`] seeded with `seed`, and all times are
+/// fixed in the log, in seconds since the unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationLog {
+    /// The seed for all randomness used when replaying the log.
+    #[serde(with = "serde_byte_array")]
+    pub seed: [u8; 32],
+    /// The time the root directory is created at.
+    pub time: i64,
+    /// The operations, applied in order.
+    pub operations: Vec<Operation>,
+}
+
+/// An operation in an [`OperationLog`], with paths relative to the root directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+    /// Creates a directory and any missing parents, see [`PrivateDirectory::mkdir`].
+    Mkdir { path: Vec<String>, time: i64 },
+    /// Writes a file and creates any missing parents, see [`PrivateDirectory::write`].
+    Write {
+        path: Vec<String>,
+        time: i64,
+        #[serde(with = "serde_bytes")]
+        content: Vec<u8>,
+    },
+    /// Removes a file or directory, see [`PrivateDirectory::rm`].
+    Rm { path: Vec<String> },
+}
+
+/// The result of replaying an [`OperationLog`].
+#[derive(Debug, Clone)]
+pub struct ReplayedForest {
+    /// The forest all revisions were stored in.
+    pub forest: Arc<HamtForest>,
+    /// The root directory after the last operation.
+    pub root_dir: Arc<PrivateDirectory>,
+    /// The access keys of all stored revisions of the root directory: one for the empty
+    /// root directory, then one per operation.
+    pub access_keys: Vec<AccessKey>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl OperationLog {
+    /// Creates an empty log.
+    pub fn new(seed: [u8; 32], time: i64) -> Self {
+        Self {
+            seed,
+            time,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Applies all operations to a new root directory in a new, empty forest.
+    ///
+    /// The root directory is stored once empty and once after every operation, so every
+    /// operation ends up as its own revision. Replaying the same log always results in the
+    /// same forest and access keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{Operation, OperationLog},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut log = OperationLog::new([0; 32], 1_700_000_000);
+    ///     log.operations.push(Operation::Write {
+    ///         path: vec!["hello.txt".into()],
+    ///         time: 1_700_000_060,
+    ///         content: b"Hello, World!".to_vec(),
+    ///     });
+    ///
+    ///     let first = log.replay(&MemoryBlockStore::new()).await?;
+    ///     let second = log.replay(&MemoryBlockStore::new()).await?;
+    ///     assert_eq!(first.access_keys, second.access_keys);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn replay(&self, store: &impl BlockStore) -> Result<ReplayedForest> {
+        let rng = &mut ChaCha12Rng::from_seed(self.seed);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir =
+            &mut PrivateDirectory::new_rc(&forest.empty_name(), timestamp_to_time(self.time)?, rng);
+
+        let mut access_keys = vec![root_dir.as_node().store(forest, store, rng).await?];
+        for operation in self.operations.iter() {
+            match operation {
+                Operation::Mkdir { path, time } => {
+                    let time = timestamp_to_time(*time)?;
+                    root_dir
+                        .mkdir(path, false, time, forest, store, rng)
+                        .await?;
+                }
+                Operation::Write {
+                    path,
+                    time,
+                    content,
+                } => {
+                    let time = timestamp_to_time(*time)?;
+                    root_dir
+                        .write(path, false, time, content.clone(), forest, store, rng)
+                        .await?;
+                }
+                Operation::Rm { path } => {
+                    root_dir.rm(path, false, forest, store).await?;
+                }
+            }
+
+            access_keys.push(root_dir.as_node().store(forest, store, rng).await?);
+        }
+
+        Ok(ReplayedForest {
+            forest: Arc::clone(forest),
+            root_dir: Arc::clone(root_dir),
+            access_keys,
+        })
+    }
+
+    /// Re-derives a log from the stored revisions of a root directory, from `past_root`
+    /// up to `root_dir`.
+    ///
+    /// The log starts out with what's needed to build `past_root` from an empty directory,
+    /// followed by the operations that turn each revision into the next one. This is only
+    /// an approximation of what happened: Seeds can't be recovered, so the log uses given
+    /// `seed`, changes within a single revision are listed removals first, moves show up as
+    /// removals and writes, and metadata-only changes are left out entirely. Replaying it
+    /// gives a tree with the same paths and contents, but only the same forest if the
+    /// history was written by replaying a log with the same seed, one operation per
+    /// revision, creating directories explicitly.
+    ///
+    /// The discrepancy budget bounds the search for the revisions in between, like in
+    /// [`PrivateNodeHistory::of`].
+    pub async fn from_history(
+        seed: [u8; 32],
+        past_root: &Arc<PrivateDirectory>,
+        root_dir: &Arc<PrivateDirectory>,
+        discrepancy_budget: usize,
+        forest: &(impl PrivateForest + Clone),
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let mut revisions = vec![Arc::clone(root_dir)];
+        if past_root.header.ratchet != root_dir.header.ratchet {
+            let mut history = PrivateNodeHistory::of(
+                &root_dir.as_node(),
+                &past_root.as_node(),
+                discrepancy_budget,
+                forest.clone(),
+            )?;
+            while let Some(dir) = history.get_previous_dir(store).await? {
+                revisions.push(dir);
+            }
+        }
+        revisions.reverse();
+
+        let time = past_root
+            .get_metadata()
+            .get_created()
+            .map_or(0, |time| time.timestamp());
+        let mut log = Self::new(seed, time);
+        let mut previous = None;
+        for revision in revisions.iter() {
+            diff_dirs(&[], previous, revision, forest, store, &mut log.operations).await?;
+            previous = Some(revision);
+        }
+
+        Ok(log)
+    }
+}
+
+impl_storable_from_serde! { OperationLog }
+
+fn timestamp_to_time(timestamp: i64) -> Result<DateTime<Utc>> {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .ok_or_else(|| anyhow!("Invalid timestamp in operation log: {timestamp}"))
+}
+
+fn metadata_time(metadata: &Metadata, created: bool) -> i64 {
+    let time = if created {
+        metadata.get_created()
+    } else {
+        metadata.get_modified()
+    };
+    time.map_or(0, |time| time.timestamp())
+}
+
+/// Appends the operations that turn `old` into `new` to `operations`.
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn diff_dirs(
+    path: &[String],
+    old: Option<&'async_recursion Arc<PrivateDirectory>>,
+    new: &Arc<PrivateDirectory>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+    operations: &mut Vec<Operation>,
+) -> Result<()> {
+    let child_path = |name: &String| {
+        let mut child_path = path.to_vec();
+        child_path.push(name.clone());
+        child_path
+    };
+
+    if let Some(old) = old {
+        for name in old.get_entries() {
+            if !new.content.entries.contains_key(name) {
+                operations.push(Operation::Rm {
+                    path: child_path(name),
+                });
+            }
+        }
+    }
+
+    for name in new.get_entries() {
+        let Some(new_node) = new.lookup_node(name, false, forest, store).await? else {
+            continue;
+        };
+        let old_node = match old {
+            Some(old) => old.lookup_node(name, false, forest, store).await?,
+            None => None,
+        };
+
+        if let Some(old_node) = &old_node {
+            let old_cid = old_node.get_persisted_as().get();
+            if old_cid.is_some() && old_cid == new_node.get_persisted_as().get() {
+                continue;
+            }
+        }
+
+        let path = child_path(name);
+        match (old_node, new_node) {
+            (Some(PrivateNode::Dir(old_dir)), PrivateNode::Dir(new_dir)) => {
+                diff_dirs(&path, Some(&old_dir), &new_dir, forest, store, operations).await?;
+            }
+            (Some(PrivateNode::File(old_file)), PrivateNode::File(new_file))
+                if old_file.content.content == new_file.content.content => {}
+            (old_node, new_node) => {
+                if old_node.is_some_and(|old_node| old_node.is_dir() != new_node.is_dir()) {
+                    operations.push(Operation::Rm { path: path.clone() });
+                }
+
+                match &new_node {
+                    PrivateNode::Dir(new_dir) => {
+                        operations.push(Operation::Mkdir {
+                            path: path.clone(),
+                            time: metadata_time(new_dir.get_metadata(), true),
+                        });
+                        diff_dirs(&path, None, new_dir, forest, store, operations).await?;
+                    }
+                    PrivateNode::File(new_file) => {
+                        operations.push(Operation::Write {
+                            time: metadata_time(new_file.get_metadata(), false),
+                            content: new_file.get_content(forest, store).await?,
+                            path,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, Storable};
+
+    fn example_log() -> OperationLog {
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect();
+        let mut log = OperationLog::new([7; 32], 1_000);
+        log.operations = vec![
+            Operation::Mkdir {
+                path: path(&["docs"]),
+                time: 1_010,
+            },
+            Operation::Write {
+                path: path(&["docs", "notes.md"]),
+                time: 1_020,
+                content: b"first".to_vec(),
+            },
+            Operation::Mkdir {
+                path: path(&["music"]),
+                time: 1_030,
+            },
+            Operation::Write {
+                path: path(&["docs", "notes.md"]),
+                time: 1_040,
+                content: b"second".to_vec(),
+            },
+            Operation::Rm {
+                path: path(&["music"]),
+            },
+        ];
+        log
+    }
+
+    #[async_std::test]
+    async fn replays_are_reproducible_and_can_be_rederived() -> TestResult {
+        let log = example_log();
+        let store = &MemoryBlockStore::new();
+        let replayed = log.replay(store).await?;
+        assert_eq!(replayed.access_keys.len(), log.operations.len() + 1);
+
+        let other_store = &MemoryBlockStore::new();
+        let other = log.replay(other_store).await?;
+        assert_eq!(replayed.access_keys, other.access_keys);
+        assert_eq!(
+            replayed.forest.store(store).await?,
+            other.forest.store(other_store).await?
+        );
+
+        // The log survives a roundtrip through the store
+        let cid = log.store(store).await?;
+        assert_eq!(OperationLog::load(&cid, store).await?, log);
+
+        let forest = &replayed.forest;
+        let past_root = PrivateNode::load(&replayed.access_keys[0], forest, store, None)
+            .await?
+            .as_dir()?;
+        let derived = OperationLog::from_history(
+            log.seed,
+            &past_root,
+            &replayed.root_dir,
+            100,
+            forest,
+            store,
+        )
+        .await?;
+        assert_eq!(derived, log);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+//! Restoring files to revisions from their history.
+
+use super::{
+    forest::traits::PrivateForest, PrivateDirectory, PrivateFile, PrivateNode, PrivateNodeHistory,
+};
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Selects a past revision of a file for [`PrivateDirectory::restore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevisionSelector {
+    /// The revision that many distinct revisions before the current one.
+    ///
+    /// If the file was removed, `RevisionsBack(1)` is the last revision before its removal.
+    RevisionsBack(usize),
+    /// The latest revision that was last modified at or before given time.
+    AsOf(DateTime<Utc>),
+    /// The revision stored at given CID, e.g. from [`PreviousLink::content_cid`](super::PreviousLink::content_cid).
+    ContentCid(Cid),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateDirectory {
+    /// Writes the content of a past revision of the file at given path as its new latest
+    /// revision, e.g. to roll back edits or to undelete it.
+    ///
+    /// Past revisions are found by following the path in the stored revisions of this
+    /// directory, from this one back to `past_root`. The discrepancy budget bounds the search
+    /// for the revisions in between, like in [`PrivateNodeHistory::of`].
+    ///
+    /// The file keeps its history, with the restored content as an additional revision.
+    /// An undeleted file starts a new history, since its old one ended with the removal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand_chacha::ChaCha12Rng;
+    /// use rand_core::SeedableRng;
+    /// use wnfs::{
+    ///     common::MemoryBlockStore,
+    ///     private::{
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///         PrivateDirectory, RevisionSelector,
+    ///     },
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut ChaCha12Rng::from_entropy();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///     let path = &["notes.md".into()];
+    ///
+    ///     dir.write(path, true, Utc::now(), b"draft".to_vec(), forest, store, rng).await?;
+    ///     dir.as_node().store(forest, store, rng).await?;
+    ///     let past_root = dir.clone();
+    ///
+    ///     dir.rm(path, true, forest, store).await?;
+    ///     dir.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let selector = &RevisionSelector::RevisionsBack(1);
+    ///     dir.restore(path, selector, &past_root, 100, Utc::now(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     assert_eq!(dir.read(path, true, forest, store).await?, b"draft");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn restore(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        selector: &RevisionSelector,
+        past_root: &Arc<PrivateDirectory>,
+        discrepancy_budget: usize,
+        time: DateTime<Utc>,
+        forest: &mut (impl PrivateForest + Clone),
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let file = self
+            .find_file_revision(
+                path_segments,
+                selector,
+                past_root,
+                discrepancy_budget,
+                forest,
+                store,
+            )
+            .await?;
+        let content = file.get_content(forest, store).await?;
+
+        self.write(path_segments, true, time, content, forest, store, rng)
+            .await
+    }
+
+    /// Finds the revision of the file at given path that given selector selects,
+    /// see [`PrivateDirectory::restore`].
+    async fn find_file_revision(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        selector: &RevisionSelector,
+        past_root: &Arc<PrivateDirectory>,
+        discrepancy_budget: usize,
+        forest: &(impl PrivateForest + Clone),
+        store: &impl BlockStore,
+    ) -> Result<Arc<PrivateFile>> {
+        let mut history = if self.header.ratchet != past_root.header.ratchet {
+            Some(PrivateNodeHistory::of(
+                &self.as_node(),
+                &past_root.as_node(),
+                discrepancy_budget,
+                forest.clone(),
+            )?)
+        } else {
+            None
+        };
+
+        // The CID of the last revision seen, or `None` while the file is missing
+        let mut last_cid = None;
+        let mut revisions_back = 0;
+        let mut dir = Arc::clone(self);
+        loop {
+            if let Some(PrivateNode::File(file)) =
+                dir.get_node(path_segments, false, forest, store).await?
+            {
+                let cid = PrivateNode::File(Arc::clone(&file))
+                    .get_persisted_as()
+                    .get()
+                    .copied();
+                // Revisions that stayed the same across directory revisions only count once
+                if !Arc::ptr_eq(&dir, self) && (cid.is_none() || cid != last_cid) {
+                    revisions_back += 1;
+                }
+                last_cid = cid;
+
+                let selected = match selector {
+                    RevisionSelector::RevisionsBack(n) => revisions_back == *n,
+                    RevisionSelector::AsOf(time) => file
+                        .content
+                        .metadata
+                        .get_modified()
+                        .is_some_and(|modified| modified <= *time),
+                    RevisionSelector::ContentCid(content_cid) => cid == Some(*content_cid),
+                };
+
+                if selected {
+                    return Ok(file);
+                }
+            } else {
+                last_cid = None;
+            }
+
+            match history.as_mut() {
+                Some(history) => match history.get_previous_dir(store).await? {
+                    Some(previous) => dir = previous,
+                    None => break,
+                },
+                None => break,
+            }
+        }
+
+        bail!(FsError::NotFound)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::{Duration, TimeZone};
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn files_can_be_restored_from_their_history() -> TestResult {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), start, rng);
+        dir.as_node().store(forest, store, rng).await?;
+        let past_root = Arc::clone(dir);
+        let path = &["notes.md".into()];
+
+        for (minutes, content) in [(1, "v1"), (2, "v2"), (3, "v3")] {
+            let time = start + Duration::minutes(minutes);
+            dir.write(path, true, time, content.into(), forest, store, rng)
+                .await?;
+            dir.as_node().store(forest, store, rng).await?;
+        }
+        let v3 = dir.get_node(path, false, forest, store).await?.unwrap();
+        let v3_cid = *v3.get_persisted_as().get().unwrap();
+
+        let time = start + Duration::minutes(4);
+        let selector = &RevisionSelector::RevisionsBack(2);
+        dir.restore(path, selector, &past_root, 100, time, forest, store, rng)
+            .await?;
+        assert_eq!(dir.read(path, false, forest, store).await?, b"v1");
+        let restored = dir.get_node(path, false, forest, store).await?.unwrap();
+        assert_eq!(restored.revisions_ahead_of(&v3)?, 1);
+        dir.as_node().store(forest, store, rng).await?;
+
+        let selector = &RevisionSelector::AsOf(start + Duration::minutes(2));
+        dir.restore(path, selector, &past_root, 100, time, forest, store, rng)
+            .await?;
+        assert_eq!(dir.read(path, false, forest, store).await?, b"v2");
+        dir.as_node().store(forest, store, rng).await?;
+
+        let selector = &RevisionSelector::ContentCid(v3_cid);
+        dir.restore(path, selector, &past_root, 100, time, forest, store, rng)
+            .await?;
+        assert_eq!(dir.read(path, false, forest, store).await?, b"v3");
+        dir.as_node().store(forest, store, rng).await?;
+
+        // Undeleting
+        dir.rm(path, false, forest, store).await?;
+        dir.as_node().store(forest, store, rng).await?;
+        let selector = &RevisionSelector::RevisionsBack(1);
+        dir.restore(path, selector, &past_root, 100, time, forest, store, rng)
+            .await?;
+        assert_eq!(dir.read(path, false, forest, store).await?, b"v3");
+
+        let selector = &RevisionSelector::RevisionsBack(10);
+        let result = dir
+            .restore(path, selector, &past_root, 100, time, forest, store, rng)
+            .await;
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
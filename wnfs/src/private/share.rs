@@ -4,27 +4,243 @@
 //! Public keys are widely distributed in a "exchange keys partition" and are grouped by device for easy selection by the sender.
 //! Asymmetrically encrypted access keys containing pointers to the private data are stored in the "Private Forest" and are labeled with a name filter that includes the sender's and recipient's information,
 //! as well as a counter.
+//! Shares can carry a [`SharePolicy`], which is stored next to the share, encrypted with the shared node's snapshot key.
+//! Sharers can also sign shares, so recipients can verify that a share was made by the holder of the sharer's root DID.
+//! When recipients rotate an exchange key, they record the keys it replaced next to it, so sharers can move
+//! their existing shares over to the new key.
+//! Single revisions of files can be shared as snapshots, which hold a [`FileViewToken`] instead of an access key,
+//! so recipients can read that revision without getting access to any other.
+
+use crate::{
+    private::{AccessKey, FileViewToken, PrivateNode, PublicKeyModulus},
+    public::PublicDirectory,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::Future;
+use libipld_core::cid::Cid;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use wnfs_common::{utils::CondSend, BlockStore};
+use wnfs_nameaccumulator::{Name, NameAccumulator};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
 //--------------------------------------------------------------------------------------------------
 
-const EXCHANGE_KEY_NAME: &str = "v1.exchange_key";
+pub(crate) const EXCHANGE_KEY_NAME: &str = "v1.exchange_key";
+
+/// The file next to a device's exchange key that lists the exchange keys it replaced.
+pub(crate) const EXCHANGE_KEY_ROTATIONS_NAME: &str = "v1.exchange_key_rotations";
+
+/// The domain separation info for the name segment
+/// that turns a share's name into the name of its policy.
+const SHARE_POLICY_SEGMENT_DSI: &str = "wnfs/share/policy segment";
+
+/// The domain separation info for the name segment
+/// that turns a share's name into the name of its signatures.
+const SHARE_SIGNATURE_SEGMENT_DSI: &str = "wnfs/share/signature segment";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Metadata a sharer can attach to a share, which is surfaced to recipients
+/// when they receive it.
+///
+/// The policy is advisory: recipients keep read access to whatever the access key
+/// grants, so it's up to applications to honor e.g. the expiry time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SharePolicy {
+    /// The time after which the sharer considers the share expired.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// A human-readable label for the share, e.g. to show in an inbox of shares.
+    pub label: Option<String>,
+}
+
+/// A received share: the shared node, the access key it was shared with and
+/// the policy the sharer attached to it.
+#[derive(Debug, Clone)]
+pub struct SharePayload {
+    pub node: PrivateNode,
+    pub access_key: AccessKey,
+    pub policy: SharePolicy,
+}
+
+/// A received snapshot share: a view token for exactly one revision of a file, shared
+/// with [`sharer::share_snapshot`], and the policy the sharer attached to it.
+///
+/// Unlike [`SharePayload`], this can't hold an access key, so it never grants access to
+/// other revisions of the file, nor to anything else in the sharer's forest.
+#[derive(Debug, Clone)]
+pub struct SnapshotSharePayload {
+    pub view_token: FileViewToken,
+    pub policy: SharePolicy,
+}
+
+/// A share that was accepted from a known sharer, along with where it came from.
+#[derive(Debug, Clone)]
+pub struct AcceptedShare {
+    pub payload: SharePayload,
+    pub provenance: ShareProvenance,
+}
+
+/// Where an accepted share came from.
+#[derive(Debug, Clone)]
+pub struct ShareProvenance {
+    /// The root DID of the sharer, which the share label is derived from.
+    pub sharer_root_did: String,
+    /// The counter the share was found at.
+    pub share_count: u64,
+    /// The label the share was found at.
+    pub share_label: Name,
+    /// Whether the sharer's signature over the share was verified.
+    pub signed: bool,
+}
+
+/// A device's exchange key, along with the exchange keys it replaced, as published in
+/// the recipient's exchange partition with [`recipient::rotate_exchange_key`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExchangeKeyRotation {
+    /// The name of the device, which is its directory in the exchange partition.
+    pub device: String,
+    /// The current exchange key of the device.
+    pub exchange_key: PublicKeyModulus,
+    /// The exchange keys the device used before, oldest first.
+    pub previous_exchange_keys: Vec<PublicKeyModulus>,
+}
+
+/// The `ShareSigner` trait represents a key that signs shares on behalf of the sharer's root DID.
+pub trait ShareSigner {
+    /// Signs a message with the key.
+    fn sign(&self, message: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + CondSend;
+}
+
+/// The `ShareVerifier` trait verifies signatures made by [`ShareSigner`]s,
+/// e.g. by resolving the sharer's root DID to a public key.
+pub trait ShareVerifier {
+    /// Checks whether the signature over the message was made for given root DID.
+    fn verify(
+        &self,
+        sharer_root_did: &str,
+        message: &[u8],
+        signature: &[u8],
+    ) -> impl Future<Output = Result<bool>> + CondSend;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SharePolicySerializable {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl SharePolicy {
+    /// Checks whether the share is expired at given time.
+    /// Shares without an expiry time never expire.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::private::share::SharePolicy;
+    /// use chrono::{Duration, Utc};
+    ///
+    /// let now = Utc::now();
+    /// let policy = SharePolicy {
+    ///     expires_at: Some(now + Duration::days(7)),
+    ///     label: Some("Holiday pictures".into()),
+    /// };
+    ///
+    /// assert!(!policy.is_expired(now));
+    /// assert!(policy.is_expired(now + Duration::days(8)));
+    /// assert!(!SharePolicy::default().is_expired(now));
+    /// ```
+    pub fn is_expired(&self, time: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| time >= expires_at)
+    }
+
+    fn to_serializable(&self) -> SharePolicySerializable {
+        SharePolicySerializable {
+            expires_at: self.expires_at.map(|time| time.timestamp()),
+            label: self.label.clone(),
+        }
+    }
+
+    fn from_serializable(serializable: SharePolicySerializable) -> Self {
+        Self {
+            expires_at: serializable
+                .expires_at
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            label: serializable.label,
+        }
+    }
+}
+
+impl SharePayload {
+    /// Whether the share grants access to future revisions of the node as well,
+    /// as opposed to a read-only snapshot of the shared revision.
+    pub fn is_temporal(&self) -> bool {
+        matches!(self.access_key, AccessKey::Temporal(_))
+    }
+}
 
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
+/// Generates the message that's signed for the encrypted access key at given share label.
+///
+/// The share label is derived from both the sharer and the recipient, so the
+/// signature can't be moved to another share.
+fn create_signature_message(share_label: &NameAccumulator, access_key_cid: &Cid) -> Vec<u8> {
+    [
+        share_label.as_bytes().as_slice(),
+        &access_key_cid.to_bytes(),
+    ]
+    .concat()
+}
+
+/// Reads the exchange keys a device's current exchange key replaced, oldest first.
+async fn read_previous_exchange_keys(
+    exchange_root: &PublicDirectory,
+    device: &str,
+    store: &impl BlockStore,
+) -> Result<Vec<PublicKeyModulus>> {
+    let path = [device.into(), EXCHANGE_KEY_ROTATIONS_NAME.into()];
+    if exchange_root.get_node(&path, store).await?.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let encoded = exchange_root.read(&path, store).await?;
+    let previous: Vec<ByteBuf> = serde_ipld_dagcbor::from_slice(&encoded)?;
+    Ok(previous.into_iter().map(ByteBuf::into_vec).collect())
+}
+
 pub mod sharer {
-    use super::EXCHANGE_KEY_NAME;
+    use super::{
+        create_signature_message, read_previous_exchange_keys, ExchangeKeyRotation, SharePolicy,
+        ShareSigner, EXCHANGE_KEY_NAME, SHARE_POLICY_SEGMENT_DSI, SHARE_SIGNATURE_SEGMENT_DSI,
+    };
     use crate::{
-        private::{forest::traits::PrivateForest, AccessKey, ExchangeKey, PublicKeyModulus},
-        public::PublicLink,
+        error::ShareError,
+        private::{
+            forest::traits::PrivateForest, AccessKey, ExchangeKey, FileViewToken, PublicKeyModulus,
+            SnapshotKey,
+        },
+        public::{PublicDirectory, PublicLink},
     };
-    use anyhow::Result;
+    use anyhow::{ensure, Result};
     use async_stream::try_stream;
     use futures::{Stream, TryStreamExt};
-    use wnfs_common::{BlockStore, CODEC_RAW};
+    use rand_core::CryptoRngCore;
+    use wnfs_common::{BlockStore, HashOutput, CODEC_RAW};
+    use wnfs_hamt::Hasher;
     use wnfs_nameaccumulator::{Name, NameSegment};
 
     /// Encrypts and shares a access key with multiple recipients using their
@@ -38,25 +254,247 @@ pub mod sharer {
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<()> {
-        let mut exchange_keys = fetch_exchange_keys(recipient_exchange_root, store).await;
-        let encoded_key = &serde_ipld_dagcbor::to_vec(access_key)?;
+        share_access_key::<K>(
+            access_key,
+            share_count,
+            sharer_root_did,
+            recipient_exchange_root,
+            forest,
+            store,
+        )
+        .await?;
 
-        while let Some(public_key_modulus) = exchange_keys.try_next().await? {
-            let exchange_key = K::from_modulus(public_key_modulus.as_ref()).await?;
-            let encrypted_key = exchange_key.encrypt(encoded_key).await?;
-            let share_label =
-                create_share_name(share_count, sharer_root_did, &public_key_modulus, forest);
+        Ok(())
+    }
+
+    /// Like [`share`], but also attaches the given policy to the shares, which
+    /// recipients get to see when they receive them.
+    ///
+    /// The policy is encrypted with the snapshot key of the shared node, so anyone
+    /// with read access to the shared revision can read the policy if they know
+    /// where to look for it.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn share_with_policy<K: ExchangeKey>(
+        access_key: &AccessKey,
+        policy: &SharePolicy,
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_root: PublicLink,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let share_labels = share_access_key::<K>(
+            access_key,
+            share_count,
+            sharer_root_did,
+            recipient_exchange_root,
+            forest,
+            store,
+        )
+        .await?;
+
+        let snapshot_key = &access_key.get_snapshot_key();
+        put_policy(policy, snapshot_key, &share_labels, forest, store, rng).await
+    }
+
+    /// Shares exactly one revision of a file with multiple recipients, like
+    /// [`share_with_policy`], but with a view token instead of an access key.
+    ///
+    /// The view token only holds the snapshot key and content block CIDs of the revision,
+    /// so recipients can read it with just a block store, but can't find or read any
+    /// other revision. Recipients receive it with [`receive_snapshot_share`].
+    ///
+    /// [`receive_snapshot_share`]: super::recipient::receive_snapshot_share
+    #[allow(clippy::too_many_arguments)]
+    pub async fn share_snapshot<K: ExchangeKey>(
+        view_token: &FileViewToken,
+        policy: &SharePolicy,
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_root: PublicLink,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let share_labels = share_encoded::<K>(
+            &view_token.to_bytes()?,
+            share_count,
+            sharer_root_did,
+            recipient_exchange_root,
+            forest,
+            store,
+        )
+        .await?;
 
-            let access_key_cid = store.put_block(encrypted_key, CODEC_RAW).await?;
+        let snapshot_key = view_token.get_snapshot_key();
+        put_policy(policy, snapshot_key, &share_labels, forest, store, rng).await
+    }
 
+    /// Encrypts given policy with given key and stores it next to each of the shares,
+    /// bound to the label it's stored under.
+    async fn put_policy(
+        policy: &SharePolicy,
+        snapshot_key: &SnapshotKey,
+        share_labels: &[Name],
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let encoded_policy = &serde_ipld_dagcbor::to_vec(&policy.to_serializable())?;
+        for share_label in share_labels {
+            let label = share_policy_label(share_label, forest);
+            let deterministic = forest.uses_deterministic_nonces();
+            let encrypted_policy = snapshot_key.encrypt_with_aad_deterministic_if(
+                encoded_policy,
+                &label,
+                deterministic,
+                rng,
+            )?;
+            let policy_cid = store.put_block(encrypted_policy, CODEC_RAW).await?;
             forest
-                .put_encrypted(&share_label, Some(access_key_cid), store)
+                .put_encrypted(
+                    &create_share_policy_name(share_label),
+                    Some(policy_cid),
+                    store,
+                )
                 .await?;
         }
 
         Ok(())
     }
 
+    /// Signs the share at given label, so recipients can check that it was made
+    /// by the holder of the sharer's root DID using [`accept_signed_share`].
+    ///
+    /// [`accept_signed_share`]: super::recipient::accept_signed_share
+    pub async fn sign_share(
+        share_label: &Name,
+        signer: &impl ShareSigner,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let access_key_cids = forest
+            .get_encrypted(share_label, store)
+            .await?
+            .ok_or(ShareError::AccessKeyNotFound)?
+            .clone();
+
+        let accumulated_label = forest.get_accumulated_name(share_label);
+        let mut signature_cids = Vec::with_capacity(access_key_cids.len());
+        for access_key_cid in access_key_cids {
+            let message = create_signature_message(&accumulated_label, &access_key_cid);
+            let signature = signer.sign(&message).await?;
+            signature_cids.push(store.put_block(signature, CODEC_RAW).await?);
+        }
+
+        forest
+            .put_encrypted(
+                &create_share_signature_name(share_label),
+                signature_cids,
+                store,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Stores the encrypted access key for each of the recipient's exchange keys
+    /// and returns the share labels they were stored at.
+    async fn share_access_key<K: ExchangeKey>(
+        access_key: &AccessKey,
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_root: PublicLink,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<Name>> {
+        share_encoded::<K>(
+            &serde_ipld_dagcbor::to_vec(access_key)?,
+            share_count,
+            sharer_root_did,
+            recipient_exchange_root,
+            forest,
+            store,
+        )
+        .await
+    }
+
+    /// Stores the encrypted share for each of the recipient's exchange keys
+    /// and returns the share labels they were stored at.
+    async fn share_encoded<K: ExchangeKey>(
+        encoded: &[u8],
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_root: PublicLink,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<Name>> {
+        let mut exchange_keys = fetch_exchange_keys(recipient_exchange_root, store).await;
+        let mut share_labels = Vec::new();
+
+        while let Some(public_key_modulus) = exchange_keys.try_next().await? {
+            let share_label = share_encoded_with_exchange_key::<K>(
+                encoded,
+                share_count,
+                sharer_root_did,
+                &public_key_modulus,
+                forest,
+                store,
+            )
+            .await?;
+
+            share_labels.push(share_label);
+        }
+
+        Ok(share_labels)
+    }
+
+    /// Encrypts and shares an access key with a single exchange key,
+    /// returning the share label it was stored at.
+    pub async fn share_with_exchange_key<K: ExchangeKey>(
+        access_key: &AccessKey,
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_key: &[u8],
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Name> {
+        share_encoded_with_exchange_key::<K>(
+            &serde_ipld_dagcbor::to_vec(access_key)?,
+            share_count,
+            sharer_root_did,
+            recipient_exchange_key,
+            forest,
+            store,
+        )
+        .await
+    }
+
+    /// Encrypts and shares an encoded share with a single exchange key,
+    /// returning the share label it was stored at.
+    async fn share_encoded_with_exchange_key<K: ExchangeKey>(
+        encoded: &[u8],
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_key: &[u8],
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Name> {
+        let exchange_key = K::from_modulus(recipient_exchange_key).await?;
+        let encrypted_key = exchange_key.encrypt(encoded).await?;
+        let share_label =
+            create_share_name(share_count, sharer_root_did, recipient_exchange_key, forest);
+
+        let access_key_cid = store.put_block(encrypted_key, CODEC_RAW).await?;
+
+        forest
+            .put_encrypted(&share_label, Some(access_key_cid), store)
+            .await?;
+
+        Ok(share_label)
+    }
+
     /// Fetches the exchange keys of recipients using their exchange root, resolve the root_dir,
     /// search for the exchange key, and read the exchange key's cid in the recipient's store and
     /// yield the exchange key's value.
@@ -83,6 +521,120 @@ pub mod sharer {
         })
     }
 
+    /// Lists the devices in a recipient's exchange partition that rotated their exchange
+    /// key, along with the exchange keys they used before.
+    pub async fn fetch_exchange_key_rotations(
+        recipient_exchange_root: &PublicDirectory,
+        store: &impl BlockStore,
+    ) -> Result<Vec<ExchangeKeyRotation>> {
+        let mut rotations = Vec::new();
+        for (device, _) in recipient_exchange_root.ls(&[], store).await? {
+            let path = [device.clone(), EXCHANGE_KEY_NAME.into()];
+            let Ok(exchange_key) = recipient_exchange_root.read(&path, store).await else {
+                continue;
+            };
+
+            let previous_exchange_keys =
+                read_previous_exchange_keys(recipient_exchange_root, &device, store).await?;
+            if !previous_exchange_keys.is_empty() {
+                rotations.push(ExchangeKeyRotation {
+                    device,
+                    exchange_key,
+                    previous_exchange_keys,
+                });
+            }
+        }
+
+        Ok(rotations)
+    }
+
+    /// Finds the share counters with shares for given exchange key from given sharer,
+    /// looking at counters from `share_count_start` up to, but not including,
+    /// `share_count_start + limit`.
+    pub async fn find_shares(
+        share_count_start: u64,
+        limit: u64,
+        sharer_root_did: &str,
+        recipient_exchange_key: &[u8],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u64>> {
+        let mut share_counts = Vec::new();
+        for share_count in share_count_start..share_count_start + limit {
+            let share_label =
+                create_share_name(share_count, sharer_root_did, recipient_exchange_key, forest);
+            if forest.has(&share_label, store).await? {
+                share_counts.push(share_count);
+            }
+        }
+
+        Ok(share_counts)
+    }
+
+    /// Re-shares the access keys shared with an old exchange key of a recipient with
+    /// their new exchange key, at the same share counters. Returns the new share labels.
+    ///
+    /// Shares are encrypted to the recipient, so the sharer needs to pass the access key
+    /// it shared at each counter, e.g. for the counters from [`find_shares`]. Policies
+    /// attached to the old shares carry over, signatures need to be made again with
+    /// [`sign_share`]. The old shares stay in the forest.
+    pub async fn migrate_shares<K: ExchangeKey>(
+        shares: &[(u64, AccessKey)],
+        sharer_root_did: &str,
+        old_exchange_key: &[u8],
+        new_exchange_key: &[u8],
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<Name>> {
+        let mut share_labels = Vec::with_capacity(shares.len());
+        for (share_count, access_key) in shares {
+            let old_label =
+                create_share_name(*share_count, sharer_root_did, old_exchange_key, forest);
+            ensure!(
+                forest.has(&old_label, store).await?,
+                ShareError::AccessKeyNotFound
+            );
+
+            let share_label = share_with_exchange_key::<K>(
+                access_key,
+                *share_count,
+                sharer_root_did,
+                new_exchange_key,
+                forest,
+                store,
+            )
+            .await?;
+
+            // Policies are bound to the label they're stored under, so they're encrypted again
+            let old_policy_label = share_policy_label(&old_label, forest);
+            let policy_cid = forest
+                .get_encrypted_by_hash(&old_policy_label, store)
+                .await?
+                .and_then(|cids| cids.first().copied());
+            if let Some(policy_cid) = policy_cid {
+                let snapshot_key = &access_key.get_snapshot_key();
+                let encrypted_policy = store.get_block(&policy_cid).await?;
+                let encoded_policy =
+                    snapshot_key.decrypt_with_aad(&encrypted_policy, &old_policy_label)?;
+                let label = share_policy_label(&share_label, forest);
+                let encrypted_policy =
+                    snapshot_key.encrypt_with_aad_deterministic(&encoded_policy, &label)?;
+                let policy_cid = store.put_block(encrypted_policy, CODEC_RAW).await?;
+                forest
+                    .put_encrypted(
+                        &create_share_policy_name(&share_label),
+                        Some(policy_cid),
+                        store,
+                    )
+                    .await?;
+            }
+
+            share_labels.push(share_label);
+        }
+
+        Ok(share_labels)
+    }
+
     /// Generates the name for a share for given recipient,
     /// at given count and from given sharer.
     pub fn create_share_name(
@@ -97,19 +649,82 @@ pub mod sharer {
             NameSegment::new_hashed("Testing", share_count.to_le_bytes()),
         ])
     }
+
+    /// Generates the name of the policy attached to the share with given name.
+    pub fn create_share_policy_name(share_name: &Name) -> Name {
+        share_name.with_segments_added([NameSegment::new_hashed(SHARE_POLICY_SEGMENT_DSI, b"")])
+    }
+
+    /// The label the policy attached to the share with given name is stored under,
+    /// which its ciphertext is bound to.
+    pub(super) fn share_policy_label(share_name: &Name, forest: &impl PrivateForest) -> HashOutput {
+        blake3::Hasher::hash(&forest.get_accumulated_name(&create_share_policy_name(share_name)))
+    }
+
+    /// Generates the name of the signatures of the share with given name.
+    pub fn create_share_signature_name(share_name: &Name) -> Name {
+        share_name.with_segments_added([NameSegment::new_hashed(SHARE_SIGNATURE_SEGMENT_DSI, b"")])
+    }
 }
 
 pub mod recipient {
-    use super::sharer;
+    use super::{
+        create_signature_message, read_previous_exchange_keys, sharer, AcceptedShare, SharePayload,
+        SharePolicy, ShareProvenance, ShareVerifier, SnapshotSharePayload, EXCHANGE_KEY_NAME,
+        EXCHANGE_KEY_ROTATIONS_NAME,
+    };
     use crate::{
         error::ShareError,
-        private::{forest::traits::PrivateForest, AccessKey, PrivateKey, PrivateNode},
+        private::{
+            forest::traits::PrivateForest, AccessKey, FileViewToken, PrivateKey, PrivateNode,
+            PublicKeyModulus, SnapshotKey,
+        },
+        public::PublicDirectory,
     };
-    use anyhow::Result;
-    use wnfs_common::BlockStore;
+    use anyhow::{bail, Result};
+    use chrono::{DateTime, Utc};
+    use libipld_core::cid::Cid;
+    use serde_bytes::ByteBuf;
+    use std::collections::BTreeSet;
+    use wnfs_common::{utils::Arc, BlockStore};
     use wnfs_hamt::Hasher;
     use wnfs_nameaccumulator::Name;
 
+    /// Replaces a device's exchange key in the exchange partition and records the
+    /// replaced key next to it, so sharers can find shares made to it with
+    /// [`sharer::fetch_exchange_key_rotations`] and move them to the new key.
+    pub async fn rotate_exchange_key(
+        device: &str,
+        new_exchange_key: PublicKeyModulus,
+        exchange_root: &mut Arc<PublicDirectory>,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let key_path = [device.into(), EXCHANGE_KEY_NAME.into()];
+        let old_exchange_key = exchange_root.read(&key_path, store).await?;
+
+        let mut previous: Vec<_> = read_previous_exchange_keys(exchange_root, device, store)
+            .await?
+            .into_iter()
+            .map(ByteBuf::from)
+            .collect();
+        previous.push(ByteBuf::from(old_exchange_key));
+
+        exchange_root
+            .write(
+                &[device.into(), EXCHANGE_KEY_ROTATIONS_NAME.into()],
+                serde_ipld_dagcbor::to_vec(&previous)?,
+                time,
+                store,
+            )
+            .await?;
+        exchange_root
+            .write(&key_path, new_exchange_key, time, store)
+            .await?;
+
+        Ok(())
+    }
+
     /// Seeks to the latest share counter that is populated.
     pub async fn find_latest_share_counter(
         share_count_start: u64,
@@ -139,150 +754,711 @@ pub mod recipient {
             }
         }
 
-        // We've exhausted the limit, this seems to be the last valid share count under the limit
-        Ok(Some(share_count_start + limit - 1))
-    }
+        // We've exhausted the limit, this seems to be the last valid share count under the limit
+        Ok(Some(share_count_start + limit - 1))
+    }
+
+    /// Lets a recipient receive a share from a sharer using the sharer's forest and store.
+    /// The recipient's private forest and store are used to store the share.
+    ///
+    /// Shares without a policy attached come with the default policy.
+    pub async fn receive_share(
+        share_label: &Name,
+        recipient_key: &impl PrivateKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<SharePayload> {
+        receive_share_payload(share_label, recipient_key, forest, store).await
+    }
+
+    /// Lets a recipient receive a snapshot share, made with [`sharer::share_snapshot`],
+    /// from a sharer using the sharer's forest and store.
+    ///
+    /// Errors with [`ShareError::UnexpectedShareKind`] if the share holds an access key
+    /// instead, so a recipient expecting a single revision never ends up with access to
+    /// more than that.
+    pub async fn receive_snapshot_share(
+        share_label: &Name,
+        recipient_key: &impl PrivateKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<SnapshotSharePayload> {
+        let decoded = receive_decoded(share_label, recipient_key, forest, store).await?;
+        let view_token = match FileViewToken::parse(&decoded) {
+            Ok(view_token) => view_token,
+            Err(_) if AccessKey::parse(&decoded).is_ok() => {
+                bail!(ShareError::UnexpectedShareKind {
+                    expected: "a file snapshot",
+                    actual: "an access key",
+                })
+            }
+            Err(error) => return Err(error),
+        };
+
+        let snapshot_key = view_token.get_snapshot_key();
+        let policy = receive_share_policy(share_label, snapshot_key, forest, store).await?;
+
+        Ok(SnapshotSharePayload { view_token, policy })
+    }
+
+    /// Accepts the share with given counter from given sharer.
+    ///
+    /// Unlike [`receive_share`], this derives the share label from the sharer's root DID
+    /// itself, so the share is known to be stored under that DID. That doesn't prove that
+    /// the holder of the DID made the share, see [`accept_signed_share`] for that.
+    pub async fn accept_share(
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_key: &[u8],
+        recipient_key: &impl PrivateKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<AcceptedShare> {
+        let share_label =
+            sharer::create_share_name(share_count, sharer_root_did, recipient_exchange_key, forest);
+        let payload = receive_share_payload(&share_label, recipient_key, forest, store).await?;
+
+        Ok(AcceptedShare {
+            payload,
+            provenance: ShareProvenance {
+                sharer_root_did: sharer_root_did.to_string(),
+                share_count,
+                share_label,
+                signed: false,
+            },
+        })
+    }
+
+    /// Accepts the share with given counter from given sharer, like [`accept_share`],
+    /// but also requires the share to be signed for the sharer's root DID.
+    ///
+    /// Other access keys put at the share label are skipped, so only a share that
+    /// is signed can be accepted.
+    ///
+    /// Errors with [`ShareError::SignatureNotFound`] if the share isn't signed, and
+    /// with [`ShareError::InvalidSignature`] if none of its signatures are valid.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn accept_signed_share(
+        share_count: u64,
+        sharer_root_did: &str,
+        recipient_exchange_key: &[u8],
+        recipient_key: &impl PrivateKey,
+        verifier: &impl ShareVerifier,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<AcceptedShare> {
+        let share_label =
+            sharer::create_share_name(share_count, sharer_root_did, recipient_exchange_key, forest);
+        let signature_cids = forest
+            .get_encrypted(&sharer::create_share_signature_name(&share_label), store)
+            .await?
+            .ok_or(ShareError::SignatureNotFound)?;
+
+        // Anyone can put access keys at the share label, so look for the one
+        // that is signed instead of trusting whichever comes first.
+        let accumulated_name = forest.get_accumulated_name(&share_label);
+        for access_key_cid in share_access_key_cids(&share_label, forest, store).await? {
+            let message = create_signature_message(&accumulated_name, &access_key_cid);
+            if !is_signed(&message, &signature_cids, sharer_root_did, verifier, store).await? {
+                continue;
+            }
+
+            let decoded = decrypt_share(&access_key_cid, recipient_key, store).await?;
+            let payload = share_payload(&share_label, &decoded, forest, store).await?;
+
+            return Ok(AcceptedShare {
+                payload,
+                provenance: ShareProvenance {
+                    sharer_root_did: sharer_root_did.to_string(),
+                    share_count,
+                    share_label,
+                    signed: true,
+                },
+            });
+        }
+
+        bail!(ShareError::InvalidSignature)
+    }
+
+    /// Whether any of the signatures with given CIDs is a valid signature of given
+    /// message for the sharer's root DID.
+    async fn is_signed(
+        message: &[u8],
+        signature_cids: &BTreeSet<Cid>,
+        sharer_root_did: &str,
+        verifier: &impl ShareVerifier,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        for signature_cid in signature_cids.iter() {
+            let signature = store.get_block(signature_cid).await?;
+            if verifier
+                .verify(sharer_root_did, message, signature.as_ref())
+                .await?
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Receives the share at given label.
+    async fn receive_share_payload(
+        share_label: &Name,
+        recipient_key: &impl PrivateKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<SharePayload> {
+        let decoded = receive_decoded(share_label, recipient_key, forest, store).await?;
+        share_payload(share_label, &decoded, forest, store).await
+    }
+
+    /// Loads the shared node and policy for the decrypted share at given label.
+    async fn share_payload(
+        share_label: &Name,
+        decoded: &[u8],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<SharePayload> {
+        // Decode the access key, which snapshot shares don't have.
+        let access_key = match AccessKey::parse(decoded) {
+            Ok(access_key) => access_key,
+            Err(_) if FileViewToken::parse(decoded).is_ok() => {
+                bail!(ShareError::UnexpectedShareKind {
+                    expected: "an access key",
+                    actual: "a file snapshot",
+                })
+            }
+            Err(error) => return Err(error),
+        };
+
+        // Use decrypted key to get cid to encrypted node in sharer's forest.
+        let node =
+            PrivateNode::from_private_ref(&access_key.derive_private_ref()?, forest, store, None)
+                .await?;
+
+        let snapshot_key = &access_key.get_snapshot_key();
+        let policy = receive_share_policy(share_label, snapshot_key, forest, store).await?;
+
+        Ok(SharePayload {
+            node,
+            access_key,
+            policy,
+        })
+    }
+
+    /// Receives the first share at given label and returns its decrypted bytes.
+    async fn receive_decoded(
+        share_label: &Name,
+        recipient_key: &impl PrivateKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        let access_key_cid = share_access_key_cids(share_label, forest, store)
+            .await?
+            .first()
+            .copied()
+            .ok_or(ShareError::AccessKeyNotFound)?;
+
+        decrypt_share(&access_key_cid, recipient_key, store).await
+    }
+
+    /// Gets the CIDs to encrypted access keys from sharer's forest using share_label.
+    async fn share_access_key_cids(
+        share_label: &Name,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<BTreeSet<Cid>> {
+        let cids = forest
+            .get_encrypted_by_hash(
+                &blake3::Hasher::hash(&forest.get_accumulated_name(share_label)),
+                store,
+            )
+            .await?
+            .ok_or(ShareError::AccessKeyNotFound)?;
+
+        Ok(cids)
+    }
+
+    /// Decrypts the encrypted share with given CID using recipient's private key.
+    async fn decrypt_share(
+        access_key_cid: &Cid,
+        recipient_key: &impl PrivateKey,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        let encrypted_access_key = store.get_block(access_key_cid).await?.to_vec();
+        recipient_key.decrypt(&encrypted_access_key).await
+    }
+
+    /// Loads and decrypts the policy attached to the share with given name.
+    async fn receive_share_policy(
+        share_label: &Name,
+        snapshot_key: &SnapshotKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<SharePolicy> {
+        let label = sharer::share_policy_label(share_label, forest);
+        let Some(policy_cid) = forest
+            .get_encrypted_by_hash(&label, store)
+            .await?
+            .and_then(|cids| cids.first().copied())
+        else {
+            return Ok(SharePolicy::default());
+        };
+
+        let encrypted_policy = store.get_block(&policy_cid).await?;
+        let encoded_policy = snapshot_key.decrypt_with_aad(&encrypted_policy, &label)?;
+
+        Ok(SharePolicy::from_serializable(
+            serde_ipld_dagcbor::from_slice(&encoded_policy)?,
+        ))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        recipient::{self, find_latest_share_counter},
+        sharer, ExchangeKeyRotation, SharePolicy, EXCHANGE_KEY_NAME,
+    };
+    use crate::{
+        error::ShareError,
+        private::{
+            forest::{hamt::HamtForest, traits::PrivateForest},
+            AccessKey, PrivateDirectory, PrivateFile, RsaPrivateKey, RsaPublicKey,
+        },
+        public::PublicLink,
+    };
+    use chrono::{TimeZone, Utc};
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::{utils::Arc, BlockStore, MemoryBlockStore, CODEC_RAW};
+
+    mod helper {
+        use crate::{
+            private::{
+                forest::traits::PrivateForest,
+                share::{ShareSigner, ShareVerifier, EXCHANGE_KEY_NAME},
+                PrivateDirectory, RsaPrivateKey,
+            },
+            public::PublicDirectory,
+        };
+        use anyhow::Result;
+        use chrono::Utc;
+        use rand_core::CryptoRngCore;
+        use wnfs_common::{
+            utils::{Arc, CondSend},
+            BlockStore,
+        };
+
+        pub(super) async fn create_sharer_dir(
+            forest: &mut impl PrivateForest,
+            store: &impl BlockStore,
+            rng: &mut (impl CryptoRngCore + CondSend),
+        ) -> Result<Arc<PrivateDirectory>> {
+            let mut dir = PrivateDirectory::new_and_store(
+                &forest.empty_name(),
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+
+            dir.write(
+                &["text.txt".into()],
+                true,
+                Utc::now(),
+                b"Hello World!".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+
+            Ok(dir)
+        }
+
+        pub(super) async fn create_recipient_exchange_root(
+            store: &impl BlockStore,
+        ) -> Result<(RsaPrivateKey, Arc<PublicDirectory>)> {
+            let key = RsaPrivateKey::new()?;
+            let exchange_key = key.get_public_key().get_public_key_modulus()?;
+
+            let mut root_dir = PublicDirectory::new_rc(Utc::now());
+            root_dir
+                .write(
+                    &["device1".into(), EXCHANGE_KEY_NAME.into()],
+                    exchange_key,
+                    Utc::now(),
+                    store,
+                )
+                .await?;
+
+            Ok((key, root_dir))
+        }
+
+        /// Stands in for a DID key pair by using the DID as key for a keyed hash.
+        pub(super) struct DidKey(pub(super) &'static str);
+
+        impl DidKey {
+            fn mac(did: &str, message: &[u8]) -> Vec<u8> {
+                let key = blake3::derive_key("wnfs/test/did key", did.as_bytes());
+                blake3::keyed_hash(&key, message).as_bytes().to_vec()
+            }
+        }
+
+        impl ShareSigner for DidKey {
+            async fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+                Ok(Self::mac(self.0, message))
+            }
+        }
+
+        impl ShareVerifier for DidKey {
+            async fn verify(&self, did: &str, message: &[u8], signature: &[u8]) -> Result<bool> {
+                Ok(Self::mac(did, message) == signature)
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn can_share_and_recieve_share() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let sharer_root_did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+
+        // Create directory to share.
+        let sharer_dir = helper::create_sharer_dir(forest, store, rng).await.unwrap();
+
+        // Establish recipient exchange root.
+        let (recipient_key, recipient_exchange_root) =
+            helper::create_recipient_exchange_root(store).await.unwrap();
+
+        // Construct access key from sharer's directory.
+        let access_key = sharer_dir
+            .as_node()
+            .store(forest, store, rng)
+            .await
+            .unwrap();
+
+        // Share access key with recipient.
+        sharer::share::<RsaPublicKey>(
+            &access_key,
+            0,
+            sharer_root_did,
+            PublicLink::with_rc_dir(recipient_exchange_root),
+            forest,
+            store,
+        )
+        .await
+        .unwrap();
+
+        // Create share label.
+        let share_label = sharer::create_share_name(
+            0,
+            sharer_root_did,
+            &recipient_key
+                .get_public_key()
+                .get_public_key_modulus()
+                .unwrap(),
+            forest,
+        );
+
+        // Grab node using share label.
+        let payload = recipient::receive_share(&share_label, &recipient_key, forest, store)
+            .await
+            .unwrap();
+
+        // Assert node is the same as the original.
+        assert_eq!(payload.node.as_dir().unwrap(), sharer_dir);
+        assert!(payload.is_temporal());
+        assert_eq!(payload.policy, SharePolicy::default());
+    }
+
+    #[async_std::test]
+    async fn can_share_with_policy_and_receive_it() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let sharer_root_did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+
+        let sharer_dir = helper::create_sharer_dir(forest, store, rng).await.unwrap();
+        let (recipient_key, recipient_exchange_root) =
+            helper::create_recipient_exchange_root(store).await.unwrap();
+        let access_key = sharer_dir
+            .as_node()
+            .store(forest, store, rng)
+            .await
+            .unwrap();
+
+        let expires_at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let policy = SharePolicy {
+            expires_at: Some(expires_at),
+            label: Some("Vacation pictures".into()),
+        };
+
+        sharer::share_with_policy::<RsaPublicKey>(
+            &access_key,
+            &policy,
+            0,
+            sharer_root_did,
+            PublicLink::with_rc_dir(recipient_exchange_root),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let share_label = sharer::create_share_name(
+            0,
+            sharer_root_did,
+            &recipient_key
+                .get_public_key()
+                .get_public_key_modulus()
+                .unwrap(),
+            forest,
+        );
+
+        let payload = recipient::receive_share(&share_label, &recipient_key, forest, store)
+            .await
+            .unwrap();
+
+        assert_eq!(payload.node.as_dir().unwrap(), sharer_dir);
+        assert_eq!(payload.access_key, access_key);
+        assert_eq!(payload.policy, policy);
+        assert!(payload.policy.is_expired(expires_at));
+    }
+
+    #[async_std::test]
+    async fn snapshot_shares_only_give_access_to_the_shared_revision() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let sharer_root_did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+
+        let (recipient_key, recipient_exchange_root) =
+            helper::create_recipient_exchange_root(store).await.unwrap();
+        let exchange_key = recipient_key
+            .get_public_key()
+            .get_public_key_modulus()
+            .unwrap();
+        let exchange_root = PublicLink::with_rc_dir(recipient_exchange_root);
+
+        let file = &mut PrivateFile::new_rc(&forest.empty_name(), Utc::now(), rng);
+        Arc::make_mut(file)
+            .set_content(&b"Draft 1"[..], Utc::now(), forest, store, rng)
+            .await
+            .unwrap();
+        let access_key = file.as_node().store(forest, store, rng).await.unwrap();
+        let view_token = file.view_token(forest, store).await.unwrap();
+
+        let policy = SharePolicy {
+            expires_at: None,
+            label: Some("First draft".into()),
+        };
+        sharer::share_snapshot::<RsaPublicKey>(
+            &view_token,
+            &policy,
+            0,
+            sharer_root_did,
+            exchange_root.clone(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+        sharer::share::<RsaPublicKey>(
+            &access_key,
+            1,
+            sharer_root_did,
+            exchange_root,
+            forest,
+            store,
+        )
+        .await
+        .unwrap();
 
-    /// Lets a recipient receive a share from a sharer using the sharer's forest and store.
-    /// The recipient's private forest and store are used to store the share.
-    pub async fn receive_share(
-        share_label: &Name,
-        recipient_key: &impl PrivateKey,
-        forest: &impl PrivateForest,
-        store: &impl BlockStore,
-    ) -> Result<PrivateNode> {
-        // Get cid to encrypted payload from sharer's forest using share_label
-        let access_key_cid = forest
-            .get_encrypted_by_hash(
-                &blake3::Hasher::hash(&forest.get_accumulated_name(share_label)),
-                store,
-            )
-            .await?
-            .ok_or(ShareError::AccessKeyNotFound)?
-            .first()
-            .ok_or(ShareError::AccessKeyNotFound)?;
+        // Later revisions don't change what the snapshot share gives access to
+        file.prepare_next_revision()
+            .unwrap()
+            .set_content(&b"Draft 2"[..], Utc::now(), forest, store, rng)
+            .await
+            .unwrap();
+        file.as_node().store(forest, store, rng).await.unwrap();
 
-        // Get encrypted access key from store using cid
-        let encrypted_access_key = store.get_block(access_key_cid).await?.to_vec();
+        let snapshot_label = sharer::create_share_name(0, sharer_root_did, &exchange_key, forest);
+        let payload =
+            recipient::receive_snapshot_share(&snapshot_label, &recipient_key, forest, store)
+                .await
+                .unwrap();
+        assert_eq!(payload.view_token, view_token);
+        assert_eq!(
+            payload.view_token.get_content(store).await.unwrap(),
+            b"Draft 1"
+        );
+        assert_eq!(payload.policy, policy);
 
-        // Decrypt access key using recipient's private key and decode it.
-        let access_key: AccessKey =
-            serde_ipld_dagcbor::from_slice(&recipient_key.decrypt(&encrypted_access_key).await?)?;
+        // Each kind of share is only received as that kind
+        let error = recipient::receive_share(&snapshot_label, &recipient_key, forest, store)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(ShareError::UnexpectedShareKind { .. })
+        ));
 
-        // Use decrypted key to get cid to encrypted node in sharer's forest.
-        PrivateNode::from_private_ref(&access_key.derive_private_ref()?, forest, store, None).await
+        let access_key_label = sharer::create_share_name(1, sharer_root_did, &exchange_key, forest);
+        let error =
+            recipient::receive_snapshot_share(&access_key_label, &recipient_key, forest, store)
+                .await
+                .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(ShareError::UnexpectedShareKind { .. })
+        ));
     }
-}
-
-//--------------------------------------------------------------------------------------------------
-// Tests
-//--------------------------------------------------------------------------------------------------
 
-#[cfg(test)]
-mod tests {
-    use super::{
-        recipient::{self, find_latest_share_counter},
-        sharer, EXCHANGE_KEY_NAME,
-    };
-    use crate::{
-        private::{
-            forest::{hamt::HamtForest, traits::PrivateForest},
-            AccessKey, PrivateDirectory, RsaPublicKey,
-        },
-        public::PublicLink,
-    };
-    use chrono::Utc;
-    use rand_chacha::ChaCha12Rng;
-    use rand_core::SeedableRng;
-    use wnfs_common::{utils::Arc, MemoryBlockStore};
+    #[async_std::test]
+    async fn accepting_signed_shares_verifies_the_sharer() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let sharer_root_did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+        let impostor_did = "did:key:z6MkiTBz1ymuepAQ4HEHYSF1H8quG5GLVVQR3djdX3mDooWp";
 
-    mod helper {
-        use crate::{
-            private::{
-                forest::traits::PrivateForest, share::EXCHANGE_KEY_NAME, PrivateDirectory,
-                RsaPrivateKey,
-            },
-            public::PublicDirectory,
-        };
-        use anyhow::Result;
-        use chrono::Utc;
-        use rand_core::CryptoRngCore;
-        use wnfs_common::{
-            utils::{Arc, CondSend},
-            BlockStore,
-        };
+        let sharer_dir = helper::create_sharer_dir(forest, store, rng).await.unwrap();
+        let (recipient_key, recipient_exchange_root) =
+            helper::create_recipient_exchange_root(store).await.unwrap();
+        let recipient_exchange_key = &recipient_key
+            .get_public_key()
+            .get_public_key_modulus()
+            .unwrap();
+        let access_key = sharer_dir
+            .as_node()
+            .store(forest, store, rng)
+            .await
+            .unwrap();
 
-        pub(super) async fn create_sharer_dir(
-            forest: &mut impl PrivateForest,
-            store: &impl BlockStore,
-            rng: &mut (impl CryptoRngCore + CondSend),
-        ) -> Result<Arc<PrivateDirectory>> {
-            let mut dir = PrivateDirectory::new_and_store(
-                &forest.empty_name(),
-                Utc::now(),
+        for share_count in 0..2 {
+            sharer::share::<RsaPublicKey>(
+                &access_key,
+                share_count,
+                sharer_root_did,
+                PublicLink::with_rc_dir(Arc::clone(&recipient_exchange_root)),
                 forest,
                 store,
-                rng,
             )
-            .await?;
+            .await
+            .unwrap();
+        }
 
-            dir.write(
-                &["text.txt".into()],
-                true,
-                Utc::now(),
-                b"Hello World!".to_vec(),
+        // Only the first share is signed, and with the wrong key at that.
+        let share_label =
+            sharer::create_share_name(0, sharer_root_did, recipient_exchange_key, forest);
+        let impostor = &helper::DidKey(impostor_did);
+        sharer::sign_share(&share_label, impostor, forest, store)
+            .await
+            .unwrap();
+
+        let verifier = &helper::DidKey("");
+        let accept_signed = |share_count| {
+            recipient::accept_signed_share(
+                share_count,
+                sharer_root_did,
+                recipient_exchange_key,
+                &recipient_key,
+                verifier,
                 forest,
                 store,
-                rng,
             )
-            .await?;
+        };
 
-            Ok(dir)
-        }
+        let err = accept_signed(0).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ShareError::InvalidSignature)
+        ));
+        let err = accept_signed(1).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ShareError::SignatureNotFound)
+        ));
 
-        pub(super) async fn create_recipient_exchange_root(
-            store: &impl BlockStore,
-        ) -> Result<(RsaPrivateKey, Arc<PublicDirectory>)> {
-            let key = RsaPrivateKey::new()?;
-            let exchange_key = key.get_public_key().get_public_key_modulus()?;
+        let accepted = recipient::accept_share(
+            1,
+            sharer_root_did,
+            recipient_exchange_key,
+            &recipient_key,
+            forest,
+            store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(accepted.payload.node.as_dir().unwrap(), sharer_dir);
+        assert_eq!(accepted.provenance.sharer_root_did, sharer_root_did);
+        assert_eq!(accepted.provenance.share_count, 1);
+        assert!(!accepted.provenance.signed);
 
-            let mut root_dir = PublicDirectory::new_rc(Utc::now());
-            root_dir
-                .write(
-                    &["device1".into(), EXCHANGE_KEY_NAME.into()],
-                    exchange_key,
-                    Utc::now(),
-                    store,
-                )
-                .await?;
+        // The share is stored under the sharer's DID, not the impostor's.
+        let result = recipient::accept_share(
+            0,
+            impostor_did,
+            recipient_exchange_key,
+            &recipient_key,
+            forest,
+            store,
+        )
+        .await;
+        assert!(result.is_err());
 
-            Ok((key, root_dir))
-        }
+        let sharer = &helper::DidKey(sharer_root_did);
+        sharer::sign_share(&share_label, sharer, forest, store)
+            .await
+            .unwrap();
+
+        let accepted = recipient::accept_signed_share(
+            0,
+            sharer_root_did,
+            recipient_exchange_key,
+            &recipient_key,
+            verifier,
+            forest,
+            store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(accepted.payload.node.as_dir().unwrap(), sharer_dir);
+        assert!(accepted.provenance.signed);
     }
 
     #[async_std::test]
-    async fn can_share_and_recieve_share() {
+    async fn accepting_signed_shares_skips_other_access_keys_at_the_label() {
         let rng = &mut ChaCha12Rng::seed_from_u64(0);
         let store = &MemoryBlockStore::new();
         let forest = &mut HamtForest::new_rsa_2048_rc(rng);
-
         let sharer_root_did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
 
-        // Create directory to share.
         let sharer_dir = helper::create_sharer_dir(forest, store, rng).await.unwrap();
-
-        // Establish recipient exchange root.
         let (recipient_key, recipient_exchange_root) =
             helper::create_recipient_exchange_root(store).await.unwrap();
-
-        // Construct access key from sharer's directory.
+        let recipient_exchange_key = &recipient_key
+            .get_public_key()
+            .get_public_key_modulus()
+            .unwrap();
         let access_key = sharer_dir
             .as_node()
             .store(forest, store, rng)
             .await
             .unwrap();
 
-        // Share access key with recipient.
         sharer::share::<RsaPublicKey>(
             &access_key,
             0,
@@ -294,24 +1470,54 @@ mod tests {
         .await
         .unwrap();
 
-        // Create share label.
-        let share_label = sharer::create_share_name(
-            0,
-            sharer_root_did,
-            &recipient_key
-                .get_public_key()
-                .get_public_key_modulus()
-                .unwrap(),
+        let share_label =
+            sharer::create_share_name(0, sharer_root_did, recipient_exchange_key, forest);
+        sharer::sign_share(
+            &share_label,
+            &helper::DidKey(sharer_root_did),
             forest,
-        );
+            store,
+        )
+        .await
+        .unwrap();
 
-        // Grab node using share label.
-        let node = recipient::receive_share(&share_label, &recipient_key, forest, store)
+        // Put an access key at the label that comes before the signed one.
+        let signed_cid = *forest
+            .get_encrypted(&share_label, store)
+            .await
+            .unwrap()
+            .unwrap()
+            .first()
+            .unwrap();
+        let mut forged = 0;
+        let forged_cid = loop {
+            let cid = store
+                .put_block(format!("forged {forged}").into_bytes(), CODEC_RAW)
+                .await
+                .unwrap();
+            if cid < signed_cid {
+                break cid;
+            }
+            forged += 1;
+        };
+        forest
+            .put_encrypted(&share_label, [forged_cid], store)
             .await
             .unwrap();
 
-        // Assert node is the same as the original.
-        assert_eq!(node.as_dir().unwrap(), sharer_dir);
+        let accepted = recipient::accept_signed_share(
+            0,
+            sharer_root_did,
+            recipient_exchange_key,
+            &recipient_key,
+            &helper::DidKey(""),
+            forest,
+            store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(accepted.payload.node.as_dir().unwrap(), sharer_dir);
+        assert!(accepted.provenance.signed);
     }
 
     #[async_std::test]
@@ -407,4 +1613,113 @@ mod tests {
         // We expect the count to be the latest share
         assert_eq!(max_share_count, Some(expected_max_share_count));
     }
+
+    #[async_std::test]
+    async fn shares_migrate_to_rotated_exchange_keys() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let sharer_root_did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+
+        let sharer_dir = helper::create_sharer_dir(forest, store, rng).await.unwrap();
+        let (old_key, mut recipient_exchange_root) =
+            helper::create_recipient_exchange_root(store).await.unwrap();
+        let access_key = sharer_dir
+            .as_node()
+            .store(forest, store, rng)
+            .await
+            .unwrap();
+        let policy = SharePolicy {
+            expires_at: None,
+            label: Some("Notes".into()),
+        };
+
+        for share_count in [0, 2] {
+            sharer::share_with_policy::<RsaPublicKey>(
+                &access_key,
+                &policy,
+                share_count,
+                sharer_root_did,
+                PublicLink::with_rc_dir(Arc::clone(&recipient_exchange_root)),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+        }
+
+        // The recipient rotates the device's exchange key.
+        let new_key = RsaPrivateKey::new().unwrap();
+        let new_exchange_key = new_key.get_public_key().get_public_key_modulus().unwrap();
+        recipient::rotate_exchange_key(
+            "device1",
+            new_exchange_key.clone(),
+            &mut recipient_exchange_root,
+            Utc::now(),
+            store,
+        )
+        .await
+        .unwrap();
+
+        // The sharer finds the rotation and migrates its shares.
+        let rotations = sharer::fetch_exchange_key_rotations(&recipient_exchange_root, store)
+            .await
+            .unwrap();
+        let old_exchange_key = old_key.get_public_key().get_public_key_modulus().unwrap();
+        assert_eq!(
+            rotations,
+            [ExchangeKeyRotation {
+                device: "device1".into(),
+                exchange_key: new_exchange_key.clone(),
+                previous_exchange_keys: vec![old_exchange_key.clone()],
+            }]
+        );
+
+        let share_counts =
+            sharer::find_shares(0, 10, sharer_root_did, &old_exchange_key, forest, store)
+                .await
+                .unwrap();
+        assert_eq!(share_counts, [0, 2]);
+
+        let shares: Vec<_> = share_counts
+            .into_iter()
+            .map(|share_count| (share_count, access_key.clone()))
+            .collect();
+        sharer::migrate_shares::<RsaPublicKey>(
+            &shares,
+            sharer_root_did,
+            &old_exchange_key,
+            &new_exchange_key,
+            forest,
+            store,
+        )
+        .await
+        .unwrap();
+
+        let accepted = recipient::accept_share(
+            2,
+            sharer_root_did,
+            &new_exchange_key,
+            &new_key,
+            forest,
+            store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(accepted.payload.access_key, access_key);
+        assert_eq!(accepted.payload.policy, policy);
+
+        // Only shares that exist can be migrated.
+        let result = sharer::migrate_shares::<RsaPublicKey>(
+            &[(1, access_key)],
+            sharer_root_did,
+            &old_exchange_key,
+            &new_exchange_key,
+            forest,
+            store,
+        )
+        .await;
+        assert!(result.is_err());
+    }
 }
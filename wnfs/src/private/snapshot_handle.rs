@@ -0,0 +1,302 @@
+//! Handles to past states of private directories.
+
+use super::{forest::hamt::HamtForest, AccessKey, PrivateDirectory, PrivateNode};
+use anyhow::Result;
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore, Metadata, Storable,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A handle to a private directory as it was when the handle was taken.
+///
+/// It consists of the CID of the private forest at that point and an access key
+/// for the exact revision of the directory. Since forests are copy-on-write,
+/// writes after taking the handle don't affect what it refers to, as long as the
+/// blocks reachable from the forest CID are kept around.
+///
+/// Handles contain access keys, so they need to be kept as secret as those.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     private::{PrivateDirectory, SnapshotHandle, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///     let path = &["notes.md".into()];
+///
+///     dir.write(path, true, Utc::now(), b"v1".to_vec(), forest, store, rng).await?;
+///     let handle = SnapshotHandle::take(dir, forest, store, rng).await?;
+///
+///     dir.write(path, true, Utc::now(), b"v2".to_vec(), forest, store, rng).await?;
+///
+///     let snapshot = handle.open(store).await?;
+///     assert_eq!(snapshot.read(path, store).await?, b"v1");
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotHandle {
+    pub forest: Cid,
+    pub access_key: AccessKey,
+}
+
+/// A read-only view of a private directory, opened from a [`SnapshotHandle`].
+///
+/// Lookups never search for newer revisions of nodes, so they resolve
+/// to what was current when the handle was taken.
+#[derive(Debug, Clone)]
+pub struct SnapshotView {
+    root: Arc<PrivateDirectory>,
+    forest: Arc<HamtForest>,
+}
+
+/// Snapshot handles, each under a name.
+///
+/// Blocks reachable from [`NamedSnapshots::pinned_forests`] need to be kept around
+/// for the snapshots to stay readable, so garbage collection has to treat them as roots,
+/// e.g. by scanning [`NamedSnapshots::gc_roots`] together with the latest roots.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedSnapshots {
+    snapshots: BTreeMap<String, SnapshotHandle>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl SnapshotHandle {
+    /// Takes a handle to the current state of `dir`.
+    ///
+    /// Stores `dir` and the forest, unless they were stored already.
+    pub async fn take(
+        dir: &Arc<PrivateDirectory>,
+        forest: &mut Arc<HamtForest>,
+        store: &impl BlockStore,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<Self> {
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let forest = forest.store(store).await?;
+
+        Ok(Self { forest, access_key })
+    }
+
+    /// Opens the directory this handle refers to for reading.
+    pub async fn open(&self, store: &impl BlockStore) -> Result<SnapshotView> {
+        let forest = Arc::new(HamtForest::load(&self.forest, store).await?);
+        let root = PrivateNode::load(&self.access_key, &forest, store, None)
+            .await?
+            .as_dir()?;
+
+        Ok(SnapshotView { root, forest })
+    }
+}
+
+impl SnapshotView {
+    /// The root directory of this snapshot.
+    pub fn root(&self) -> &Arc<PrivateDirectory> {
+        &self.root
+    }
+
+    /// The private forest as it was when the snapshot was taken.
+    pub fn forest(&self) -> &Arc<HamtForest> {
+        &self.forest
+    }
+
+    /// Gets the node at given path, like [`PrivateDirectory::get_node`].
+    pub async fn get_node(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Option<PrivateNode>> {
+        self.root
+            .get_node(path_segments, false, &self.forest, store)
+            .await
+    }
+
+    /// Reads the file at given path, like [`PrivateDirectory::read`].
+    pub async fn read(&self, path_segments: &[String], store: &impl BlockStore) -> Result<Vec<u8>> {
+        self.root
+            .read(path_segments, false, &self.forest, store)
+            .await
+    }
+
+    /// Lists the directory at given path, like [`PrivateDirectory::ls`].
+    pub async fn ls(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata)>> {
+        self.root
+            .ls(path_segments, false, &self.forest, store)
+            .await
+    }
+}
+
+impl NamedSnapshots {
+    /// Creates an empty set of snapshots.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a snapshot under given name, returning the snapshot it replaced, if any.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        handle: SnapshotHandle,
+    ) -> Option<SnapshotHandle> {
+        self.snapshots.insert(name.into(), handle)
+    }
+
+    /// Gets the snapshot with given name.
+    pub fn get(&self, name: &str) -> Option<&SnapshotHandle> {
+        self.snapshots.get(name)
+    }
+
+    /// Removes the snapshot with given name, so it no longer needs to be preserved.
+    pub fn remove(&mut self, name: &str) -> Option<SnapshotHandle> {
+        self.snapshots.remove(name)
+    }
+
+    /// Iterates over all named snapshots, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &SnapshotHandle)> {
+        self.snapshots.iter()
+    }
+
+    /// The forest CIDs garbage collection must keep reachable to preserve all named snapshots.
+    pub fn pinned_forests(&self) -> BTreeSet<Cid> {
+        self.snapshots
+            .values()
+            .map(|handle| handle.forest)
+            .collect()
+    }
+
+    /// The forest of each snapshot under the snapshot's name, to be passed to
+    /// [`ReachableBlocks::scan`](wnfs_common::utils::ReachableBlocks::scan) along with
+    /// other roots, which also reports how much each snapshot keeps alive on its own.
+    pub fn gc_roots(&self) -> impl Iterator<Item = (String, Cid)> + '_ {
+        self.snapshots
+            .iter()
+            .map(|(name, handle)| (name.clone(), handle.forest))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::traits::PrivateForest;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::{utils::ReachableBlocks, MemoryBlockStore};
+
+    #[async_std::test]
+    async fn snapshots_are_unaffected_by_later_writes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let snapshots = &mut NamedSnapshots::new();
+
+        dir.write(
+            &["a.txt".into()],
+            true,
+            Utc::now(),
+            b"a".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        snapshots.insert(
+            "before",
+            SnapshotHandle::take(dir, forest, store, rng).await?,
+        );
+
+        dir.write(
+            &["a.txt".into()],
+            true,
+            Utc::now(),
+            b"changed".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        dir.write(
+            &["b.txt".into()],
+            true,
+            Utc::now(),
+            b"b".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        snapshots.insert(
+            "after",
+            SnapshotHandle::take(dir, forest, store, rng).await?,
+        );
+
+        let before = snapshots.get("before").unwrap().open(store).await?;
+        assert_eq!(before.read(&["a.txt".into()], store).await?, b"a");
+        assert_eq!(before.ls(&[], store).await?.len(), 1);
+
+        let after = snapshots.get("after").unwrap().open(store).await?;
+        assert_eq!(after.read(&["a.txt".into()], store).await?, b"changed");
+        assert!(after.get_node(&["b.txt".into()], store).await?.is_some());
+
+        assert_eq!(snapshots.pinned_forests().len(), 2);
+        dir.write(
+            &["c.txt".into()],
+            true,
+            Utc::now(),
+            b"c".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        dir.as_node().store(forest, store, rng).await?;
+        let forest_cid = forest.store(store).await?;
+        let roots = snapshots.gc_roots().chain([("latest".into(), forest_cid)]);
+        let reachable = ReachableBlocks::scan(roots, store).await?;
+        let usage = reachable.root_usage();
+        assert_eq!(usage[0].name, "after");
+        assert!(usage.iter().all(|root| root.exclusive_blocks > 0));
+        assert!(reachable.shared_bytes() > 0);
+
+        snapshots.remove("before");
+        assert_eq!(
+            snapshots.pinned_forests(),
+            BTreeSet::from([snapshots.get("after").unwrap().forest])
+        );
+
+        Ok(())
+    }
+}
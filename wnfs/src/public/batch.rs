@@ -0,0 +1,205 @@
+use super::PublicDirectory;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use wnfs_common::{utils::Arc, BlockStore};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Queues up changes to a [`PublicDirectory`] and applies them all at once.
+///
+/// Applying a batch produces a single new revision of the root directory,
+/// no matter how many changes it contains, and changes either apply
+/// completely or not at all.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use wnfs::{
+///     public::{PublicBatch, PublicDirectory},
+///     common::{MemoryBlockStore, Storable},
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+///     let old_cid = dir.store(store).await?;
+///
+///     PublicBatch::new()
+///         .mkdir(&["pictures".into()])
+///         .write(&["notes".into(), "todo.md".into()], b"- [ ] groceries".to_vec())
+///         .mv(&["notes".into()], &["archive".into()])
+///         .apply(dir, Utc::now(), store)
+///         .await?;
+///
+///     dir.store(store).await?;
+///
+///     assert_eq!(dir.get_previous().iter().collect::<Vec<_>>(), vec![&old_cid]);
+///     assert_eq!(dir.ls(&[], store).await?.len(), 2);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PublicBatch {
+    ops: Vec<PublicBatchOp>,
+}
+
+/// A single change queued up in a [`PublicBatch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicBatchOp {
+    /// Creates a directory and any missing parents.
+    Mkdir(Vec<String>),
+    /// Writes a file, creating any missing parent directories.
+    Write(Vec<String>, Vec<u8>),
+    /// Removes a file or directory.
+    Rm(Vec<String>),
+    /// Moves a file or directory from the first path to the second one.
+    Mv(Vec<String>, Vec<String>),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PublicBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues creating a directory, like [`PublicDirectory::mkdir`].
+    pub fn mkdir(mut self, path_segments: &[String]) -> Self {
+        self.ops.push(PublicBatchOp::Mkdir(path_segments.to_vec()));
+        self
+    }
+
+    /// Queues writing a file, like [`PublicDirectory::write`].
+    pub fn write(mut self, path_segments: &[String], content: Vec<u8>) -> Self {
+        self.ops
+            .push(PublicBatchOp::Write(path_segments.to_vec(), content));
+        self
+    }
+
+    /// Queues removing a file or directory, like [`PublicDirectory::rm`].
+    pub fn rm(mut self, path_segments: &[String]) -> Self {
+        self.ops.push(PublicBatchOp::Rm(path_segments.to_vec()));
+        self
+    }
+
+    /// Queues moving a file or directory, like [`PublicDirectory::basic_mv`].
+    pub fn mv(mut self, path_segments_from: &[String], path_segments_to: &[String]) -> Self {
+        self.ops.push(PublicBatchOp::Mv(
+            path_segments_from.to_vec(),
+            path_segments_to.to_vec(),
+        ));
+        self
+    }
+
+    /// The changes queued up so far, in the order they'll be applied in.
+    pub fn ops(&self) -> &[PublicBatchOp] {
+        &self.ops
+    }
+
+    /// Whether there are no changes queued up.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Applies all queued changes to `dir` in order, using `time` as the
+    /// modification time.
+    ///
+    /// If any change fails, its error is returned and `dir` is left untouched.
+    pub async fn apply(
+        self,
+        dir: &mut Arc<PublicDirectory>,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let mut working_dir = Arc::clone(dir);
+        for op in self.ops {
+            match op {
+                PublicBatchOp::Mkdir(path) => working_dir.mkdir(&path, time, store).await?,
+                PublicBatchOp::Write(path, content) => {
+                    working_dir.write(&path, content, time, store).await?
+                }
+                PublicBatchOp::Rm(path) => {
+                    working_dir.rm(&path, store).await?;
+                }
+                PublicBatchOp::Mv(from, to) => {
+                    working_dir.basic_mv(&from, &to, time, store).await?
+                }
+            }
+        }
+
+        *dir = working_dir;
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FsError;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, Storable};
+
+    #[async_std::test]
+    async fn batch_creates_a_single_revision() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        dir.write(&["a.txt".into()], b"a".to_vec(), Utc::now(), store)
+            .await?;
+        let old_cid = dir.store(store).await?;
+
+        PublicBatch::new()
+            .write(&["a.txt".into()], b"changed".to_vec())
+            .write(&["docs".into(), "b.txt".into()], b"b".to_vec())
+            .mkdir(&["empty".into()])
+            .rm(&["empty".into()])
+            .apply(dir, Utc::now(), store)
+            .await?;
+
+        let new_cid = dir.store(store).await?;
+        let loaded = PublicDirectory::load(&new_cid, store).await?;
+
+        assert_eq!(loaded.previous.iter().collect::<Vec<_>>(), vec![&old_cid]);
+        assert_eq!(loaded.read(&["a.txt".into()], store).await?, b"changed");
+        assert_eq!(
+            loaded.read(&["docs".into(), "b.txt".into()], store).await?,
+            b"b"
+        );
+        assert!(loaded.get_node(&["empty".into()], store).await?.is_none());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn failed_batch_leaves_directory_untouched() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        let old_cid = dir.store(store).await?;
+
+        let result = PublicBatch::new()
+            .write(&["a.txt".into()], b"a".to_vec())
+            .rm(&["missing.txt".into()])
+            .apply(dir, Utc::now(), store)
+            .await;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::NotFound)
+        ));
+        assert_eq!(dir.store(store).await?, old_cid);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,486 @@
+//! App-defined public fs nodes.
+
+use super::{PublicCustomNodeSerializable, PublicNodeSerializable};
+use crate::{
+    error::FsError, is_legacy_wnfs_version, is_readable_wnfs_version, traits::Id,
+    utils::OnceCellDebug, WNFS_VERSION,
+};
+use anyhow::{bail, ensure, Result};
+use async_once_cell::OnceCell;
+use chrono::{DateTime, Utc};
+use libipld_core::{
+    cid::Cid,
+    ipld::Ipld,
+    serde::{from_ipld, to_ipld},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{cmp::Ordering, collections::BTreeSet};
+use wnfs_common::{
+    encode,
+    libipld::cbor::DagCborCodec,
+    utils::{Arc, CondSend},
+    BlockStore, Metadata, NodeType, Storable,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A kind of node defined by an application rather than by WNFS itself,
+/// e.g. a mount point or a database shard.
+///
+/// Each kind is registered under a type string, which is stored alongside the
+/// node's content. Type strings must not start with `wnfs/`, as that namespace
+/// is reserved for WNFS' own node types.
+///
+/// # Examples
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use wnfs::public::CustomNodeKind;
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct MountPoint {
+///     target: String,
+/// }
+///
+/// impl CustomNodeKind for MountPoint {
+///     const NODE_TYPE: &'static str = "my-app/mount";
+/// }
+/// ```
+pub trait CustomNodeKind: Serialize + DeserializeOwned + CondSend {
+    /// The type string nodes of this kind are stored under.
+    const NODE_TYPE: &'static str;
+}
+
+/// A node of an app-defined kind in the WNFS public file system.
+///
+/// Its content is kept as opaque IPLD, so nodes of kinds that the reader
+/// doesn't know about still load, list and round-trip unchanged. Use
+/// [`PublicCustomNode::decode`] to turn the content into a [`CustomNodeKind`].
+///
+/// # Examples
+///
+/// ```
+/// use chrono::Utc;
+/// use serde::{Deserialize, Serialize};
+/// use wnfs::public::{CustomNodeKind, PublicCustomNode};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct MountPoint {
+///     target: String,
+/// }
+///
+/// impl CustomNodeKind for MountPoint {
+///     const NODE_TYPE: &'static str = "my-app/mount";
+/// }
+///
+/// let mount = MountPoint { target: "/data".into() };
+/// let node = PublicCustomNode::new(&mount, Utc::now()).unwrap();
+///
+/// assert_eq!(node.get_type_name(), "my-app/mount");
+/// assert_eq!(node.decode::<MountPoint>().unwrap(), mount);
+/// ```
+pub struct PublicCustomNode {
+    persisted_as: OnceCell<Cid>,
+    pub(crate) node_type: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) content: Ipld,
+    pub(crate) previous: BTreeSet<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PublicCustomNode {
+    /// Creates a new node holding given content.
+    pub fn new<T: CustomNodeKind>(content: &T, time: DateTime<Utc>) -> Result<Self> {
+        Self::with_raw_content(T::NODE_TYPE, to_ipld(content)?, time)
+    }
+
+    /// Creates an `Arc` wrapped node, a shorthand wrapper around `PublicCustomNode::new`.
+    pub fn new_rc<T: CustomNodeKind>(content: &T, time: DateTime<Utc>) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self::new(content, time)?))
+    }
+
+    /// Creates a new node from a type string and its raw IPLD content.
+    ///
+    /// Fails if the type string is in the reserved `wnfs/` namespace.
+    pub fn with_raw_content(
+        node_type: impl Into<String>,
+        content: Ipld,
+        time: DateTime<Utc>,
+    ) -> Result<Self> {
+        let node_type = node_type.into();
+        ensure!(
+            !NodeType::is_reserved(&node_type),
+            FsError::ReservedNodeType(node_type)
+        );
+
+        Ok(Self {
+            persisted_as: OnceCell::new(),
+            node_type,
+            metadata: Metadata::new(time),
+            content,
+            previous: BTreeSet::new(),
+        })
+    }
+
+    /// Gets the type of this node, which is always a [`NodeType::Custom`].
+    pub fn get_type(&self) -> NodeType {
+        NodeType::Custom(self.node_type.clone())
+    }
+
+    /// Gets the type string this node is registered under.
+    pub fn get_type_name(&self) -> &str {
+        &self.node_type
+    }
+
+    /// Whether this node is of given kind.
+    pub fn is<T: CustomNodeKind>(&self) -> bool {
+        self.node_type == T::NODE_TYPE
+    }
+
+    /// Decodes the content of this node.
+    ///
+    /// Fails with `UnexpectedNodeType` if this node isn't of kind `T`.
+    pub fn decode<T: CustomNodeKind>(&self) -> Result<T> {
+        if !self.is::<T>() {
+            bail!(FsError::UnexpectedNodeType(NodeType::Custom(
+                T::NODE_TYPE.into()
+            )));
+        }
+
+        Ok(from_ipld(self.content.clone())?)
+    }
+
+    /// Gets the raw IPLD content of this node.
+    pub fn get_raw_content(&self) -> &Ipld {
+        &self.content
+    }
+
+    /// Replaces the content of this node.
+    ///
+    /// Fails with `UnexpectedNodeType` if this node isn't of kind `T`.
+    pub fn set_content<T: CustomNodeKind>(
+        &mut self,
+        content: &T,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        if !self.is::<T>() {
+            bail!(FsError::UnexpectedNodeType(self.get_type()));
+        }
+
+        self.content = to_ipld(content)?;
+        self.metadata.upsert_mtime(time);
+
+        Ok(())
+    }
+
+    /// Gets the previous value of the node.
+    pub fn get_previous(&self) -> &BTreeSet<Cid> {
+        &self.previous
+    }
+
+    /// Gets the metadata of the node.
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns a mutable reference to metadata for this node.
+    pub fn get_metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// Takes care of creating previous links, in case the current
+    /// node was previously `.store()`ed.
+    /// In any case it'll try to give you ownership of the node if possible,
+    /// otherwise it clones.
+    pub fn prepare_next_revision<'a>(self: &'a mut Arc<Self>) -> &'a mut Self {
+        let Some(previous_cid) = self.persisted_as.get().cloned() else {
+            return Arc::make_mut(self);
+        };
+
+        let cloned = Arc::make_mut(self);
+        cloned.persisted_as = OnceCell::new();
+        cloned.previous = [previous_cid].into_iter().collect();
+
+        cloned
+    }
+
+    /// Prepares this node for conflict reconciliation merge changes, like
+    /// `PublicFile::prepare_next_merge`.
+    pub(crate) async fn prepare_next_merge<'a>(
+        self: &'a mut Arc<Self>,
+        store: &impl BlockStore,
+    ) -> Result<&'a mut Self> {
+        if self.previous.len() > 1 {
+            // This is a merge node
+            let cloned = Arc::make_mut(self);
+            cloned.persisted_as = OnceCell::new();
+            return Ok(cloned);
+        }
+
+        let previous_cid = self.store(store).await?;
+        let cloned = Arc::make_mut(self);
+        cloned.persisted_as = OnceCell::new();
+        cloned.previous = BTreeSet::from([previous_cid]);
+        Ok(cloned)
+    }
+
+    /// Runs the merge part of the conflict reconciliation algorithm on this
+    /// node together with the other node.
+    ///
+    /// Conflicting contents are tie-broken by comparing their encoded bytes,
+    /// so just like `PublicFile::merge` this function is commutative and associative.
+    ///
+    /// The return value indicates whether tie-breaking was necessary or not.
+    pub(crate) async fn merge(
+        self: &mut Arc<Self>,
+        other: &Arc<Self>,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        let our_cid = self.store(store).await?;
+        let other_cid = other.store(store).await?;
+        if our_cid == other_cid {
+            return Ok(false); // No need to merge, the nodes are equal
+        }
+
+        let ours = (self.node_type.clone(), encode(&self.content, DagCborCodec)?);
+        let theirs = (
+            other.node_type.clone(),
+            encode(&other.content, DagCborCodec)?,
+        );
+
+        let node = self.prepare_next_merge(store).await?;
+        if other.previous.len() > 1 {
+            // The other node is a merge node, we should merge the merge nodes directly:
+            node.previous.extend(other.previous.iter().cloned());
+        } else {
+            // The other node is a 'normal' node - we need to merge it normally
+            node.previous.insert(other_cid);
+        }
+
+        match ours.cmp(&theirs) {
+            Ordering::Greater => {
+                node.node_type.clone_from(&other.node_type);
+                node.content.clone_from(&other.content);
+                node.metadata.clone_from(&other.metadata);
+            }
+            Ordering::Equal => {
+                node.metadata.tie_break_with(&other.metadata)?;
+            }
+            Ordering::Less => {
+                // We take ours
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl std::fmt::Debug for PublicCustomNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublicCustomNode")
+            .field(
+                "persisted_as",
+                &OnceCellDebug(self.persisted_as.get().map(|cid| format!("{cid}"))),
+            )
+            .field("node_type", &self.node_type)
+            .field("metadata", &self.metadata)
+            .field("content", &self.content)
+            .field(
+                "previous",
+                &self
+                    .previous
+                    .iter()
+                    .map(|cid| format!("{cid}"))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Storable for PublicCustomNode {
+    type Serializable = PublicNodeSerializable;
+
+    async fn to_serializable(&self, _store: &impl BlockStore) -> Result<Self::Serializable> {
+        Ok(PublicNodeSerializable::Custom(
+            self.node_type.clone(),
+            PublicCustomNodeSerializable {
+                version: WNFS_VERSION,
+                metadata: self.metadata.clone(),
+                previous: self.previous.iter().cloned().collect(),
+                content: self.content.clone(),
+            },
+        ))
+    }
+
+    async fn from_serializable(
+        cid: Option<&Cid>,
+        serializable: Self::Serializable,
+    ) -> Result<Self> {
+        let PublicNodeSerializable::Custom(node_type, serializable) = serializable else {
+            bail!(FsError::UnexpectedNodeType(NodeType::Custom(String::new())));
+        };
+
+        if !is_readable_wnfs_version(&serializable.version)
+            && !is_legacy_wnfs_version(&serializable.version)
+        {
+            bail!(FsError::UnexpectedVersion(serializable.version))
+        }
+
+        Ok(Self {
+            persisted_as: cid.cloned().map(OnceCell::new_with).unwrap_or_default(),
+            node_type,
+            metadata: serializable.metadata,
+            content: serializable.content,
+            previous: serializable.previous.into_iter().collect(),
+        })
+    }
+
+    fn persisted_as(&self) -> Option<&OnceCell<Cid>> {
+        Some(&self.persisted_as)
+    }
+}
+
+impl Id for PublicCustomNode {
+    fn get_id(&self) -> String {
+        format!("{:p}", &self.metadata)
+    }
+}
+
+impl PartialEq for PublicCustomNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.node_type == other.node_type
+            && self.metadata == other.metadata
+            && self.content == other.content
+            && self.previous == other.previous
+    }
+}
+
+impl Clone for PublicCustomNode {
+    fn clone(&self) -> Self {
+        Self {
+            persisted_as: self
+                .persisted_as
+                .get()
+                .cloned()
+                .map(OnceCell::new_with)
+                .unwrap_or_default(),
+            node_type: self.node_type.clone(),
+            metadata: self.metadata.clone(),
+            content: self.content.clone(),
+            previous: self.previous.clone(),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::public::{PublicDirectory, PublicNode};
+    use serde::Deserialize;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct MountPoint {
+        target: String,
+    }
+
+    impl CustomNodeKind for MountPoint {
+        const NODE_TYPE: &'static str = "test/mount";
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Shard(u32);
+
+    impl CustomNodeKind for Shard {
+        const NODE_TYPE: &'static str = "test/shard";
+    }
+
+    #[async_std::test]
+    async fn custom_nodes_round_trip() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let mount = MountPoint {
+            target: "/data".into(),
+        };
+        let node = PublicCustomNode::new(&mount, Utc::now())?;
+
+        let cid = node.store(store).await?;
+        let loaded = PublicNode::load(&cid, store).await?;
+        let loaded = loaded.as_custom()?;
+
+        assert_eq!(loaded.decode::<MountPoint>()?, mount);
+        assert_eq!(loaded.store(store).await?, cid);
+        assert!(loaded.decode::<Shard>().is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reserved_node_types_are_rejected() {
+        let result = PublicCustomNode::with_raw_content("wnfs/pub/mount", Ipld::Null, Utc::now());
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(FsError::ReservedNodeType(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn unknown_custom_nodes_are_listed_and_kept() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let root = &mut PublicDirectory::new_rc(Utc::now());
+        let raw = PublicCustomNode::with_raw_content(
+            "someone-else/thing",
+            Ipld::List(vec![1.into(), 2.into()]),
+            Utc::now(),
+        )?;
+
+        root.write(&["file.txt".into()], b"hi".to_vec(), Utc::now(), store)
+            .await?;
+        root.write_custom(
+            &["mnt".into()],
+            &MountPoint {
+                target: "/data".into(),
+            },
+            Utc::now(),
+            store,
+        )
+        .await?;
+        root.put_custom_node(&["thing".into()], raw.clone(), Utc::now(), store)
+            .await?;
+
+        let cid = root.store(store).await?;
+        let loaded = PublicDirectory::load(&cid, store).await?;
+
+        let names = loaded
+            .ls(&[], store)
+            .await?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["file.txt", "mnt", "thing"]);
+
+        let thing = loaded.get_node(&["thing".into()], store).await?.unwrap();
+        assert_eq!(thing.as_custom()?.get_raw_content(), raw.get_raw_content());
+        assert!(loaded.read(&["thing".into()], store).await.is_err());
+        assert_eq!(
+            loaded
+                .read_custom::<MountPoint>(&["mnt".into()], store)
+                .await?
+                .target,
+            "/data"
+        );
+        assert_eq!(Arc::new(loaded).store(store).await?, cid);
+
+        Ok(())
+    }
+}
@@ -1,14 +1,17 @@
 //! Public fs directory node.
 
 use super::{
-    PublicDirectorySerializable, PublicFile, PublicLink, PublicNode, PublicNodeSerializable,
+    load_stored_dir, overflow_metadata, overflow_userland, page_userland_shards, CustomNodeKind,
+    PublicCustomNode, PublicDirectorySerializable, PublicFile, PublicLink, PublicNode,
+    PublicNodeSerializable, ShardedEntries,
 };
 use crate::{
     error::FsError,
-    is_readable_wnfs_version,
+    is_legacy_wnfs_version, is_readable_wnfs_version,
+    options::WriteOptions,
     traits::Id,
     utils::{self, OnceCellDebug},
-    SearchResult, WNFS_VERSION,
+    SearchResult, DIRECTORY_SHARD_THRESHOLD, WNFS_VERSION,
 };
 use anyhow::{bail, ensure, Result};
 use async_once_cell::OnceCell;
@@ -16,12 +19,13 @@ use async_recursion::async_recursion;
 use chrono::{DateTime, Utc};
 use libipld_core::cid::Cid;
 use std::{
+    borrow::Cow,
     cmp::Ordering,
-    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet},
 };
 use wnfs_common::{
     utils::{boxed_fut, error, Arc},
-    BlockStore, Metadata, NodeType, Storable,
+    BlockStore, Metadata, NodeType, Storable, StoreIpld,
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -43,10 +47,23 @@ use wnfs_common::{
 pub struct PublicDirectory {
     persisted_as: OnceCell<Cid>,
     pub(crate) metadata: Metadata,
+    /// The entries, unless there are so many that they're kept in `shards` instead.
     pub(crate) userland: BTreeMap<String, PublicLink>,
+    pub(crate) shards: Option<ShardedEntries>,
+    pub(crate) order: Vec<String>,
+    pub(crate) order_revision: u64,
     pub(crate) previous: BTreeSet<Cid>,
 }
 
+/// A page of the entries of a stored directory, see [`PublicDirectory::ls_page`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPage {
+    /// The names of the entries together with the CIDs of their nodes.
+    pub entries: Vec<(String, Cid)>,
+    /// The name to pass to get the next page, or `None` if this is the last page.
+    pub next: Option<String>,
+}
+
 /// Different types of reconciliation results we can detect
 #[derive(Debug, Clone)]
 pub enum Reconciliation {
@@ -84,6 +101,9 @@ impl PublicDirectory {
             persisted_as: OnceCell::new(),
             metadata: Metadata::new(time),
             userland: BTreeMap::new(),
+            shards: None,
+            order: Vec::new(),
+            order_revision: 0,
             previous: BTreeSet::new(),
         }
     }
@@ -165,6 +185,17 @@ impl PublicDirectory {
         cloned
     }
 
+    /// Clones this directory, copying all entries that weren't stored yet,
+    /// see [`PublicNode::detached`].
+    pub(crate) fn detached(&self) -> Self {
+        let mut cloned = self.clone();
+        for link in cloned.userland.values_mut() {
+            *link = link.detached();
+        }
+        cloned.shards = self.shards.as_ref().map(ShardedEntries::detached);
+        cloned
+    }
+
     /// Call this function to prepare this directory for conflict reconciliation merge changes.
     /// Advances this node to the next revision, unless it's already a merge node.
     /// Merge nodes preferably just grow in size. This allows them to combine more nicely
@@ -213,7 +244,7 @@ impl PublicDirectory {
     async fn get_leaf_dir_mut<'a>(
         self: &'a mut Arc<Self>,
         path_segments: &[String],
-        store: &impl BlockStore,
+        store: &'a impl BlockStore,
     ) -> Result<SearchResult<&'a mut Self>> {
         // TODO(matheus23) actually set the modification time of all these nodes
         let mut working_dir = self.prepare_next_revision();
@@ -240,20 +271,22 @@ impl PublicDirectory {
         Ok(SearchResult::Found(working_dir))
     }
 
-    async fn get_or_create_leaf_dir_mut<'a>(
+    pub(crate) async fn get_or_create_leaf_dir_mut<'a>(
         self: &'a mut Arc<Self>,
         path_segments: &[String],
         time: DateTime<Utc>,
-        store: &impl BlockStore,
+        store: &'a impl BlockStore,
     ) -> Result<&'a mut Self> {
         match self.get_leaf_dir_mut(path_segments, store).await? {
             SearchResult::Found(dir) => Ok(dir),
             SearchResult::Missing(mut dir, depth) => {
                 for segment in &path_segments[depth..] {
+                    let link = PublicLink::with_dir(Self::new(time));
+                    dir.insert_entry(segment.to_string(), link, store).await?;
                     dir = Arc::make_mut(
-                        dir.userland
-                            .entry(segment.to_string())
-                            .or_insert_with(|| PublicLink::with_dir(Self::new(time)))
+                        dir.lookup_link_mut(segment, store)
+                            .await?
+                            .unwrap()
                             .resolve_value_mut(store)
                             .await
                             .unwrap()
@@ -358,13 +391,17 @@ impl PublicDirectory {
         let (path, filename) = utils::split_last(path_segments)?;
 
         // Resolve the path to an entry
-        let file_ref = self
-            .get_or_create_leaf_dir_mut(path, time, store)
-            .await?
-            .userland
-            .entry(filename.clone())
+        let dir = self.get_or_create_leaf_dir_mut(path, time, store).await?;
+        if !dir.has_entry(filename, store).await? {
             // Create a file, if it doesn't exist yet
-            .or_insert_with(|| PublicLink::with_file(PublicFile::new(time)))
+            let link = PublicLink::with_file(PublicFile::new(time));
+            dir.insert_entry(filename.clone(), link, store).await?;
+        }
+
+        let file_ref = dir
+            .lookup_link_mut(filename, store)
+            .await?
+            .ok_or(FsError::NotFound)?
             // Get a mutable ref out of the directory entry
             .resolve_value_mut(store)
             .await?
@@ -408,7 +445,7 @@ impl PublicDirectory {
         path_segment: &str,
         store: &impl BlockStore,
     ) -> Result<Option<&'a PublicNode>> {
-        Ok(match self.userland.get(path_segment) {
+        Ok(match self.lookup_link(path_segment, store).await? {
             Some(link) => Some(link.resolve_value(store).await?),
             None => None,
         })
@@ -418,14 +455,103 @@ impl PublicDirectory {
     async fn lookup_node_mut<'a>(
         &'a mut self,
         path_segment: &str,
-        store: &impl BlockStore,
+        store: &'a impl BlockStore,
     ) -> Result<Option<&'a mut PublicNode>> {
-        Ok(match self.userland.get_mut(path_segment) {
+        Ok(match self.lookup_link_mut(path_segment, store).await? {
             Some(link) => Some(link.resolve_value_mut(store).await?),
             None => None,
         })
     }
 
+    /// Looks up the link to an entry of the current directory.
+    ///
+    /// For sharded directories, this only loads the blocks of the HAMT on the way.
+    pub(crate) async fn lookup_link<'a>(
+        &'a self,
+        name: &str,
+        store: &impl BlockStore,
+    ) -> Result<Option<&'a PublicLink>> {
+        match &self.shards {
+            Some(shards) => shards.get(name, store).await,
+            None => Ok(self.userland.get(name)),
+        }
+    }
+
+    /// Looks up the link to an entry of the current directory for modification.
+    async fn lookup_link_mut<'a>(
+        &'a mut self,
+        name: &str,
+        store: &'a impl BlockStore,
+    ) -> Result<Option<&'a mut PublicLink>> {
+        match &mut self.shards {
+            Some(shards) => shards.get_mut(name, store).await,
+            None => Ok(self.userland.get_mut(name)),
+        }
+    }
+
+    /// Checks whether the current directory has an entry with given name.
+    pub(crate) async fn has_entry(&self, name: &str, store: &impl BlockStore) -> Result<bool> {
+        Ok(self.lookup_link(name, store).await?.is_some())
+    }
+
+    /// Adds or replaces an entry of the current directory.
+    ///
+    /// Once there are more than [`DIRECTORY_SHARD_THRESHOLD`] entries, they're moved into
+    /// a HAMT.
+    pub(crate) async fn insert_entry(
+        &mut self,
+        name: String,
+        link: PublicLink,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        match &mut self.shards {
+            Some(shards) => shards.insert(name, link, store).await?,
+            None => {
+                self.userland.insert(name, link);
+                if self.userland.len() > DIRECTORY_SHARD_THRESHOLD {
+                    let entries = std::mem::take(&mut self.userland);
+                    self.shards = Some(ShardedEntries::from_entries(entries, store).await?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes an entry of the current directory, returning it if there was one.
+    ///
+    /// Once there are no more than [`DIRECTORY_SHARD_THRESHOLD`] entries, they're moved
+    /// out of the HAMT again.
+    pub(crate) async fn remove_entry(
+        &mut self,
+        name: &str,
+        store: &impl BlockStore,
+    ) -> Result<Option<PublicLink>> {
+        let Some(shards) = &mut self.shards else {
+            return Ok(self.userland.remove(name));
+        };
+
+        let removed = shards.remove(name, store).await?;
+        if shards.len() <= DIRECTORY_SHARD_THRESHOLD as u64 {
+            self.userland = shards.entries(store).await?;
+            self.shards = None;
+        }
+
+        Ok(removed)
+    }
+
+    /// Gets all entries of the current directory, loading the whole HAMT for sharded
+    /// directories.
+    pub(crate) async fn entries(
+        &self,
+        store: &impl BlockStore,
+    ) -> Result<Cow<'_, BTreeMap<String, PublicLink>>> {
+        match &self.shards {
+            Some(shards) => Ok(Cow::Owned(shards.entries(store).await?)),
+            None => Ok(Cow::Borrowed(&self.userland)),
+        }
+    }
+
     /// Reads specified file content from the directory.
     ///
     /// # Examples
@@ -508,28 +634,242 @@ impl PublicDirectory {
         content: Vec<u8>,
         time: DateTime<Utc>,
         store: &impl BlockStore,
+    ) -> Result<()> {
+        self.write_with_options(
+            path_segments,
+            content,
+            time,
+            &WriteOptions::default(),
+            store,
+        )
+        .await
+    }
+
+    /// Writes a file to the directory, like [`PublicDirectory::write`], with given options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     options::WriteOptions,
+    ///     public::PublicDirectory,
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///     let path = &["scan.pdf".into()];
+    ///
+    ///     dir.write_with_options(path, b"%PDF-1.7".to_vec(), Utc::now(), &WriteOptions::sniffing(), store)
+    ///         .await?;
+    ///
+    ///     let file = dir.get_node(path, store).await?.unwrap().as_file()?;
+    ///     assert_eq!(file.get_metadata().get_content_type(), Some("application/pdf"));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn write_with_options(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        content: Vec<u8>,
+        time: DateTime<Utc>,
+        options: &WriteOptions,
+        store: &impl BlockStore,
     ) -> Result<()> {
         let (path, filename) = utils::split_last(path_segments)?;
         let dir = self.get_or_create_leaf_dir_mut(path, time, store).await?;
+        let content_type = options.content_type_update(&content);
 
         match dir.lookup_node_mut(filename, store).await? {
             Some(PublicNode::File(file)) => {
-                file.prepare_next_revision()
-                    .set_content(content, time, store)
-                    .await?
+                let file = file.prepare_next_revision();
+                file.set_content(content, time, store).await?;
+                content_type.apply(file.get_metadata_mut());
+            }
+            Some(PublicNode::Dir(_)) => bail!(FsError::DirectoryAlreadyExists),
+            Some(PublicNode::Custom(_)) => bail!(FsError::NotAFile),
+            None => {
+                let mut file = PublicFile::with_content(time, content, store).await?;
+                content_type.apply(file.get_metadata_mut());
+                let link = PublicLink::with_file(file);
+                dir.insert_entry(filename.to_string(), link, store).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the metadata of the node at given path without touching its content,
+    /// like [`PrivateDirectory::update_metadata`](crate::private::PrivateDirectory::update_metadata).
+    ///
+    /// An empty path updates this directory's metadata. Large values, like thumbnails,
+    /// are stored in blocks of their own, just like in private nodes, so they don't bloat
+    /// the node's block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use libipld_core::ipld::Ipld;
+    /// use wnfs::{public::PublicDirectory, common::MemoryBlockStore};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///     let path = &["code".into(), "hello.py".into()];
+    ///
+    ///     dir.write(path, b"print('hello world')".to_vec(), Utc::now(), store).await?;
+    ///     dir.update_metadata(
+    ///         path,
+    ///         |metadata| {
+    ///             metadata.put("language", Ipld::String("python".into()));
+    ///         },
+    ///         store,
+    ///     )
+    ///     .await?;
+    ///
+    ///     let file = dir.get_node(path, store).await?.unwrap().as_file()?;
+    ///     assert_eq!(
+    ///         file.get_metadata().get("language"),
+    ///         Some(&Ipld::String("python".into()))
+    ///     );
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn update_metadata(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        update: impl FnOnce(&mut Metadata),
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let Some((node_name, path)) = path_segments.split_last() else {
+            update(&mut self.prepare_next_revision().metadata);
+            return Ok(());
+        };
+
+        let SearchResult::Found(dir) = self.get_leaf_dir_mut(path, store).await? else {
+            bail!(FsError::NotFound)
+        };
+
+        match dir.lookup_node_mut(node_name, store).await? {
+            Some(PublicNode::File(file)) => update(&mut file.prepare_next_revision().metadata),
+            Some(PublicNode::Dir(dir)) => update(&mut dir.prepare_next_revision().metadata),
+            Some(PublicNode::Custom(node)) => {
+                update(node.prepare_next_revision().get_metadata_mut());
+            }
+            None => bail!(FsError::NotFound),
+        }
+
+        Ok(())
+    }
+
+    /// Reads the content of a node of an app-defined kind.
+    ///
+    /// Fails with `UnexpectedNodeType` if the node at given path isn't of kind `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use serde::{Deserialize, Serialize};
+    /// use wnfs::{
+    ///     public::{CustomNodeKind, PublicDirectory},
+    ///     common::MemoryBlockStore
+    /// };
+    ///
+    /// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    /// struct MountPoint {
+    ///     target: String,
+    /// }
+    ///
+    /// impl CustomNodeKind for MountPoint {
+    ///     const NODE_TYPE: &'static str = "my-app/mount";
+    /// }
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///     let mount = MountPoint { target: "/data".into() };
+    ///
+    ///     dir.write_custom(&["mnt".into()], &mount, Utc::now(), store).await?;
+    ///
+    ///     let result = dir.read_custom::<MountPoint>(&["mnt".into()], store).await?;
+    ///
+    ///     assert_eq!(result, mount);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn read_custom<T: CustomNodeKind>(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<T> {
+        match self.get_node(path_segments, store).await? {
+            Some(node) => node.as_custom()?.decode(),
+            None => error(FsError::NotFound),
+        }
+    }
+
+    /// Writes a node of an app-defined kind to the directory, creating any missing
+    /// parent directories.
+    ///
+    /// If a node of the same kind already exists at given path, its content is replaced.
+    pub async fn write_custom<T: CustomNodeKind>(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        content: &T,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let (path, filename) = utils::split_last(path_segments)?;
+        let dir = self.get_or_create_leaf_dir_mut(path, time, store).await?;
+
+        match dir.lookup_node_mut(filename, store).await? {
+            Some(PublicNode::Custom(custom)) => {
+                custom.prepare_next_revision().set_content(content, time)?
             }
+            Some(PublicNode::File(_)) => bail!(FsError::FileAlreadyExists),
             Some(PublicNode::Dir(_)) => bail!(FsError::DirectoryAlreadyExists),
             None => {
-                dir.userland.insert(
-                    filename.to_string(),
-                    PublicLink::with_file(PublicFile::with_content(time, content, store).await?),
-                );
+                let link = PublicLink::with_custom(PublicCustomNode::new(content, time)?);
+                dir.insert_entry(filename.to_string(), link, store).await?;
             }
         }
 
         Ok(())
     }
 
+    /// Places given node of an app-defined kind at given path, replacing whatever was
+    /// there before and creating any missing parent directories.
+    ///
+    /// Unlike [`PublicDirectory::write_custom`], this works with nodes of kinds that
+    /// are only known by their type string.
+    pub async fn put_custom_node(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        node: PublicCustomNode,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let (path, filename) = utils::split_last(path_segments)?;
+        let dir = self.get_or_create_leaf_dir_mut(path, time, store).await?;
+        dir.insert_entry(filename.to_string(), PublicLink::with_custom(node), store)
+            .await?;
+
+        Ok(())
+    }
+
     /// Creates a new directory at the specified path.
     ///
     /// # Examples
@@ -620,7 +960,8 @@ impl PublicDirectory {
         match self.get_leaf_dir(path_segments, store).await? {
             SearchResult::Found(dir) => {
                 let mut result = vec![];
-                for (name, link) in dir.userland.iter() {
+                let entries = dir.entries(store).await?;
+                for (name, link) in utils::iter_in_order(&entries, &dir.order) {
                     match link.resolve_value(store).await? {
                         PublicNode::File(file) => {
                             result.push((name.clone(), file.metadata.clone()));
@@ -628,6 +969,9 @@ impl PublicDirectory {
                         PublicNode::Dir(dir) => {
                             result.push((name.clone(), dir.metadata.clone()));
                         }
+                        PublicNode::Custom(custom) => {
+                            result.push((name.clone(), custom.metadata.clone()));
+                        }
                     }
                 }
                 Ok(result)
@@ -637,6 +981,204 @@ impl PublicDirectory {
         }
     }
 
+    /// Returns names and metadata of directory's immediate children, like
+    /// [PublicDirectory::ls], together with the CIDs of the blocks they are stored in.
+    ///
+    /// Children that haven't been stored yet get stored, so they have a CID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     public::PublicDirectory,
+    ///     common::{BlockStore, MemoryBlockStore},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///
+    ///     dir.write(&["notes.md".into()], b"hi".to_vec(), Utc::now(), store).await?;
+    ///
+    ///     let entries = dir.ls_with_cids(&[], store).await?;
+    ///     let (name, _, cid) = &entries[0];
+    ///
+    ///     assert_eq!(name, "notes.md");
+    ///     assert!(store.has_block(cid).await?);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn ls_with_cids(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata, Cid)>> {
+        match self.get_leaf_dir(path_segments, store).await? {
+            SearchResult::Found(dir) => {
+                let mut result = vec![];
+                let entries = dir.entries(store).await?;
+                for (name, link) in utils::iter_in_order(&entries, &dir.order) {
+                    let cid = link.resolve_cid(store).await?;
+                    let metadata = link.resolve_value(store).await?.get_metadata().clone();
+                    result.push((name.clone(), metadata, cid));
+                }
+                Ok(result)
+            }
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            _ => bail!(FsError::NotFound),
+        }
+    }
+
+    /// Lists a page of the entries of the directory stored at given CID, starting after the
+    /// entry with given name, or at the start.
+    ///
+    /// Directories with more than [`DIRECTORY_SHARD_THRESHOLD`] entries are listed in the
+    /// order of the hashes of the entry names, loading only the blocks the page is stored in.
+    /// That way, pages stay consistent even if entries are added or removed in between.
+    /// Other directories are loaded whole and listed in the order of [`PublicDirectory::ls`],
+    /// which fails if the entry to start after was removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     public::PublicDirectory,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     for name in ["a.txt", "b.txt", "c.txt"] {
+    ///         dir.write(&[name.into()], b"hi".to_vec(), Utc::now(), store).await?;
+    ///     }
+    ///     let cid = dir.store(store).await?;
+    ///
+    ///     let first = PublicDirectory::ls_page(&cid, None, 2, store).await?;
+    ///     let next = first.next.as_deref();
+    ///     let second = PublicDirectory::ls_page(&cid, next, 2, store).await?;
+    ///
+    ///     assert_eq!(first.entries.len(), 2);
+    ///     assert_eq!(second.entries[0].0, "c.txt");
+    ///     assert_eq!(second.next, None);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn ls_page(
+        cid: &Cid,
+        after: Option<&str>,
+        limit: usize,
+        store: &impl BlockStore,
+    ) -> Result<EntryPage> {
+        let Some(dir) = load_stored_dir(cid, store).await? else {
+            bail!(FsError::NotADirectory)
+        };
+
+        let mut entries = match dir.userland_shards {
+            Some(shards) => {
+                page_userland_shards(&shards, after, limit.saturating_add(1), store).await?
+            }
+            None => {
+                let mut entries = utils::iter_in_order(&dir.userland, &dir.order);
+                if let Some(after) = after {
+                    ensure!(dir.userland.contains_key(after), FsError::NotFound);
+                    entries.find(|(name, _)| name.as_str() == after);
+                }
+
+                entries
+                    .take(limit.saturating_add(1))
+                    .map(|(name, cid)| (name.clone(), *cid))
+                    .collect()
+            }
+        };
+
+        let next = if entries.len() > limit {
+            entries.truncate(limit);
+            entries.last().map(|(name, _)| name.clone())
+        } else {
+            None
+        };
+
+        Ok(EntryPage { entries, next })
+    }
+
+    /// Returns the order this directory's entries are listed in, as set with
+    /// [PublicDirectory::reorder].
+    ///
+    /// Empty if the entries are listed in lexicographic order.
+    pub fn get_entry_order(&self) -> &[String] {
+        &self.order
+    }
+
+    /// Sets the order in which [PublicDirectory::ls] lists the entries of the directory at given path.
+    ///
+    /// The order may mention only some of the entries. All others, including any added later,
+    /// are listed after them in lexicographic order. Passing an empty order restores
+    /// lexicographic listing, and removing an entry also removes it from the order.
+    ///
+    /// When concurrent reorders get reconciled, the latest one wins. Reorders made equally
+    /// often on both sides are tie-broken by the lexicographically greater order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     public::PublicDirectory,
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///
+    ///     for chapter in ["intro.md", "outro.md", "middle.md"] {
+    ///         dir.write(&["book".into(), chapter.into()], vec![], Utc::now(), store).await?;
+    ///     }
+    ///
+    ///     dir.reorder(&["book".into()], vec!["intro.md".into(), "middle.md".into()], Utc::now(), store)
+    ///         .await?;
+    ///
+    ///     let chapters = dir.ls(&["book".into()], store).await?;
+    ///     assert_eq!(
+    ///         chapters.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(),
+    ///         ["intro.md", "middle.md", "outro.md"]
+    ///     );
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn reorder(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        order: Vec<String>,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let dir = match self.get_leaf_dir_mut(path_segments, store).await? {
+            SearchResult::Found(dir) => dir,
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            SearchResult::Missing(_, _) => bail!(FsError::NotFound),
+        };
+
+        utils::check_order(&*dir.entries(store).await?, &order)?;
+        dir.order = order;
+        dir.order_revision += 1;
+        dir.metadata.upsert_mtime(time);
+
+        Ok(())
+    }
+
     /// Removes a file or directory from the directory.
     ///
     /// # Examples
@@ -694,11 +1236,13 @@ impl PublicDirectory {
             bail!(FsError::NotFound)
         };
 
-        let removed_node = match dir.userland.remove(node_name) {
+        let removed_node = match dir.remove_entry(node_name, store).await? {
             Some(link) => link.resolve_owned_value(store).await?,
             None => bail!(FsError::NotFound),
         };
 
+        dir.order.retain(|name| name != node_name);
+
         Ok(removed_node)
     }
 
@@ -764,14 +1308,14 @@ impl PublicDirectory {
         };
 
         ensure!(
-            !dir.userland.contains_key(filename),
+            !dir.has_entry(filename, store).await?,
             FsError::FileAlreadyExists
         );
 
         removed_node.upsert_mtime(time);
 
-        dir.userland
-            .insert(filename.clone(), PublicLink::new(removed_node));
+        dir.insert_entry(filename.clone(), PublicLink::new(removed_node), store)
+            .await?;
 
         Ok(())
     }
@@ -838,13 +1382,14 @@ impl PublicDirectory {
         };
 
         ensure!(
-            !dir.userland.contains_key(filename),
+            !dir.has_entry(filename, store).await?,
             FsError::FileAlreadyExists
         );
 
         node.upsert_mtime(time);
 
-        dir.userland.insert(filename.clone(), PublicLink::new(node));
+        dir.insert_entry(filename.clone(), PublicLink::new(node), store)
+            .await?;
 
         Ok(())
     }
@@ -917,15 +1462,23 @@ impl PublicDirectory {
             dir.previous.insert(other.store(store).await?);
         }
         dir.metadata.tie_break_with(&other.metadata)?;
+        utils::merge_order(
+            &mut dir.order,
+            &mut dir.order_revision,
+            &other.order,
+            other.order_revision,
+        );
 
-        for (name, other_link) in other.userland.iter() {
+        let other_entries = other.entries(store).await?;
+        for (name, other_link) in other_entries.iter() {
             let other_node = other_link.resolve_value(store).await?;
-            match dir.userland.entry(name.clone()) {
-                Entry::Vacant(vacant) => {
-                    vacant.insert(other_link.clone());
+            match dir.lookup_link_mut(name, store).await? {
+                None => {
+                    dir.insert_entry(name.clone(), other_link.clone(), store)
+                        .await?;
                 }
-                Entry::Occupied(mut occupied) => {
-                    let our_node = occupied.get_mut().resolve_value_mut(store).await?;
+                Some(our_link) => {
+                    let our_node = our_link.resolve_value_mut(store).await?;
 
                     match our_node.causal_compare(other_node, store).await? {
                         Some(Ordering::Equal) => {
@@ -963,6 +1516,20 @@ impl PublicDirectory {
                             dir.reconcile_helper(other_dir, store, &path, file_tie_breaks)
                                 .await?;
                         }
+                        (PublicNode::Custom(our_custom), PublicNode::Custom(other_custom)) => {
+                            if our_custom.merge(other_custom, store).await? {
+                                let mut path = current_path.to_vec();
+                                path.push(name.clone());
+                                file_tie_breaks.insert(path);
+                            }
+                        }
+                        (node @ PublicNode::Custom(_), other_node) => {
+                            // nodes of kinds WNFS knows about have priority
+                            *node = other_node.clone();
+                        }
+                        (_, PublicNode::Custom(_)) => {
+                            // nodes of kinds WNFS knows about have priority, no changes necessary
+                        }
                     }
                 }
             }
@@ -981,6 +1548,9 @@ impl std::fmt::Debug for PublicDirectory {
             )
             .field("metadata", &self.metadata)
             .field("userland", &self.userland)
+            .field("shards", &self.shards)
+            .field("order", &self.order)
+            .field("order_revision", &self.order_revision)
             .field(
                 "previous",
                 &self
@@ -1003,6 +1573,9 @@ impl PartialEq for PublicDirectory {
     fn eq(&self, other: &Self) -> bool {
         self.metadata == other.metadata
             && self.userland == other.userland
+            && self.shards == other.shards
+            && self.order == other.order
+            && self.order_revision == other.order_revision
             && self.previous == other.previous
     }
 }
@@ -1018,6 +1591,9 @@ impl Clone for PublicDirectory {
                 .unwrap_or_default(),
             metadata: self.metadata.clone(),
             userland: self.userland.clone(),
+            shards: self.shards.clone(),
+            order: self.order.clone(),
+            order_revision: self.order_revision,
             previous: self.previous.clone(),
         }
     }
@@ -1036,12 +1612,35 @@ impl Storable for PublicDirectory {
             map
         };
 
-        Ok(PublicNodeSerializable::Dir(PublicDirectorySerializable {
+        let (metadata, metadata_overflow) = overflow_metadata(&self.metadata, store).await?;
+        let mut serializable = PublicDirectorySerializable {
             version: WNFS_VERSION,
-            metadata: self.metadata.clone(),
+            metadata,
             userland,
+            order: self.order.clone(),
+            order_revision: self.order_revision,
             previous: self.previous.iter().cloned().collect(),
-        }))
+            userland_overflow: Vec::new(),
+            userland_shards: None,
+            userland_count: 0,
+            metadata_overflow,
+        };
+
+        // Directories with many entries store them in a HAMT, so they can be read in parts
+        if let Some(shards) = &self.shards {
+            serializable.userland_shards = Some(boxed_fut(shards.store(store)).await?);
+            serializable.userland_count = shards.len();
+        }
+
+        // Directories that don't fit into a single block store their entries separately
+        let (bytes, _) = PublicNodeSerializable::Dir(serializable.clone()).encode_ipld()?;
+        if bytes.len() > store.max_block_size() {
+            serializable.userland_overflow =
+                overflow_userland(&serializable.userland, store).await?;
+            serializable.userland.clear();
+        }
+
+        Ok(PublicNodeSerializable::Dir(serializable))
     }
 
     async fn from_serializable(
@@ -1052,7 +1651,9 @@ impl Storable for PublicDirectory {
             bail!(FsError::UnexpectedNodeType(NodeType::PublicFile));
         };
 
-        if !is_readable_wnfs_version(&serializable.version) {
+        if !is_readable_wnfs_version(&serializable.version)
+            && !is_legacy_wnfs_version(&serializable.version)
+        {
             bail!(FsError::UnexpectedVersion(serializable.version))
         }
 
@@ -1061,15 +1662,26 @@ impl Storable for PublicDirectory {
             .into_iter()
             .map(|(name, cid)| (name, PublicLink::from_cid(cid)))
             .collect();
+        let shards = serializable
+            .userland_shards
+            .map(|shards| ShardedEntries::from_cid(shards, serializable.userland_count));
 
         Ok(Self {
             persisted_as: cid.cloned().map(OnceCell::new_with).unwrap_or_default(),
             metadata: serializable.metadata,
             userland,
+            shards,
+            order: serializable.order,
+            order_revision: serializable.order_revision,
             previous: serializable.previous.iter().cloned().collect(),
         })
     }
 
+    async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let serializable = PublicNodeSerializable::load(cid, store).await?;
+        Self::from_serializable(Some(cid), serializable).await
+    }
+
     fn persisted_as(&self) -> Option<&OnceCell<Cid>> {
         Some(&self.persisted_as)
     }
@@ -1084,7 +1696,7 @@ mod tests {
     use super::*;
     use libipld_core::ipld::Ipld;
     use testresult::TestResult;
-    use wnfs_common::{decode, libipld::cbor::DagCborCodec, MemoryBlockStore};
+    use wnfs_common::{decode, libipld::cbor::DagCborCodec, MemoryBlockStore, StagingBlockStore};
 
     #[async_std::test]
     async fn look_up_can_fetch_file_added_to_directory() -> TestResult {
@@ -1398,6 +2010,38 @@ mod tests {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn inode_numbers_are_kept_across_renames_and_revisions() -> TestResult {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::new();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+
+        root_dir
+            .write(&["file.txt".into()], b"Hello".to_vec(), time, store)
+            .await?;
+        root_dir.store(store).await?;
+        let file = root_dir.get_node(&["file.txt".into()], store).await?;
+        let inode = file.unwrap().inode(store).await?;
+
+        root_dir
+            .basic_mv(
+                &["file.txt".into()],
+                &["renamed.txt".into()],
+                Utc::now(),
+                store,
+            )
+            .await?;
+        root_dir
+            .write(&["renamed.txt".into()], b"World".to_vec(), time, store)
+            .await?;
+        root_dir.store(store).await?;
+
+        let file = root_dir.get_node(&["renamed.txt".into()], store).await?;
+        assert_eq!(file.unwrap().inode(store).await?, inode);
+
+        Ok(())
+    }
+
     #[async_std::test]
     async fn mv_fails_moving_directories_to_files() -> TestResult {
         let time = Utc::now();
@@ -1504,6 +2148,201 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn reorder_is_persisted_and_pruned_on_rm() -> TestResult {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::new();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        for name in ["a", "b", "c"] {
+            root_dir.write(&[name.into()], vec![], time, store).await?;
+        }
+
+        let result = root_dir
+            .reorder(&[], vec!["c".into(), "missing".into()], time, store)
+            .await;
+        assert!(result.is_err());
+
+        root_dir
+            .reorder(&[], vec!["c".into(), "a".into()], time, store)
+            .await?;
+        let cid = root_dir.store(store).await?;
+        let loaded = &mut Arc::new(PublicDirectory::load(&cid, store).await?);
+        let names = loaded.ls(&[], store).await?;
+        assert_eq!(
+            names
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["c", "a", "b"]
+        );
+
+        loaded.rm(&["c".into()], store).await?;
+        assert_eq!(loaded.get_entry_order(), ["a"]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reconciling_concurrent_reorders_converges() -> TestResult {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::new();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        root_dir.write(&["a".into()], vec![], time, store).await?;
+        root_dir.write(&["b".into()], vec![], time, store).await?;
+        root_dir.store(store).await?;
+
+        let fork = &mut Arc::clone(root_dir);
+        fork.reorder(&[], vec!["b".into(), "a".into()], time, store)
+            .await?;
+        fork.store(store).await?;
+
+        root_dir
+            .reorder(&[], vec!["a".into(), "b".into()], time, store)
+            .await?;
+        root_dir.store(store).await?;
+
+        let other = &mut Arc::clone(fork);
+        other.reconcile(root_dir, store).await?;
+        root_dir.reconcile(fork, store).await?;
+
+        assert_eq!(root_dir.get_entry_order(), ["b", "a"]);
+        assert_eq!(root_dir.get_entry_order(), other.get_entry_order());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reconciling_keeps_reorders_made_on_one_side_only() -> TestResult {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::new();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        for name in ["a", "b", "c"] {
+            root_dir.write(&[name.into()], vec![], time, store).await?;
+        }
+        root_dir
+            .reorder(&[], vec!["a".into(), "c".into()], time, store)
+            .await?;
+        root_dir.store(store).await?;
+
+        let fork = &mut Arc::clone(root_dir);
+        fork.write(&["d".into()], vec![], time, store).await?;
+        fork.store(store).await?;
+
+        root_dir
+            .reorder(&[], vec!["a".into(), "b".into()], time, store)
+            .await?;
+        root_dir.store(store).await?;
+
+        let other = &mut Arc::clone(fork);
+        other.reconcile(root_dir, store).await?;
+        root_dir.reconcile(fork, store).await?;
+
+        assert_eq!(root_dir.get_entry_order(), ["a", "b"]);
+        assert_eq!(other.get_entry_order(), ["a", "b"]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn write_with_sniffing_tracks_the_content_type() -> TestResult {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::new();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        let path = &["upload".into()];
+        let sniffing = &WriteOptions::sniffing();
+
+        root_dir
+            .write(path, b"\x89PNG\r\n\x1a\n".to_vec(), time, store)
+            .await?;
+        let file = root_dir.get_node(path, store).await?.unwrap().as_file()?;
+        assert_eq!(file.get_metadata().get_content_type(), None);
+
+        root_dir
+            .write_with_options(path, b"\x89PNG\r\n\x1a\n".to_vec(), time, sniffing, store)
+            .await?;
+        let file = root_dir.get_node(path, store).await?.unwrap().as_file()?;
+        assert_eq!(file.get_metadata().get_content_type(), Some("image/png"));
+
+        root_dir
+            .write_with_options(path, b"plain text".to_vec(), time, sniffing, store)
+            .await?;
+        let file = root_dir.get_node(path, store).await?.unwrap().as_file()?;
+        assert_eq!(file.get_metadata().get_content_type(), None);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn directories_with_many_entries_are_sharded() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = Utc::now();
+        let dir = &mut PublicDirectory::new_rc(time);
+        let count = DIRECTORY_SHARD_THRESHOLD + 100;
+        for i in 0..count {
+            let content = i.to_string().into_bytes();
+            dir.write(&[format!("{i}.txt")], content, time, store)
+                .await?;
+        }
+        let cid = dir.store(store).await?;
+
+        assert!(store.get_block(&cid).await?.len() < 1024);
+        let loaded = PublicDirectory::load(&cid, store).await?;
+        assert_eq!(loaded.ls(&[], store).await?.len(), count);
+        assert_eq!(loaded.read(&["7.txt".into()], store).await?, b"7");
+        assert_eq!(loaded.store(store).await?, cid);
+
+        let mut names = BTreeSet::new();
+        let mut after = None;
+        loop {
+            let page = PublicDirectory::ls_page(&cid, after.as_deref(), 100, store).await?;
+            assert!(page.entries.len() <= 100);
+            names.extend(page.entries.into_iter().map(|(name, _)| name));
+            after = page.next;
+            if after.is_none() {
+                break;
+            }
+        }
+        assert_eq!(names.len(), count);
+
+        // Directories that shrink below the threshold store their entries inline again
+        for i in 0..200 {
+            dir.rm(&[format!("{i}.txt")], store).await?;
+        }
+        let cid = dir.store(store).await?;
+        let stored = load_stored_dir(&cid, store).await?.unwrap();
+        assert!(stored.userland_shards.is_none());
+        assert_eq!(stored.userland.len(), count - 200);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn sharded_directories_only_store_changed_shards() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = Utc::now();
+        let dir = &mut PublicDirectory::new_rc(time);
+        for i in 0..DIRECTORY_SHARD_THRESHOLD + 100 {
+            let content = i.to_string().into_bytes();
+            dir.write(&[format!("{i}.txt")], content, time, store)
+                .await?;
+        }
+        let cid = dir.store(store).await?;
+
+        let staging = &StagingBlockStore::new(store);
+        let loaded = &mut Arc::new(PublicDirectory::load(&cid, staging).await?);
+        loaded
+            .write(&["new.txt".into()], b"new".to_vec(), time, staging)
+            .await?;
+        loaded.store(staging).await?;
+
+        let manifest = staging.manifest().await?;
+        assert!(manifest.existing_blocks.is_empty());
+        assert!(manifest.new_blocks.len() < 10);
+        assert_eq!(loaded.read(&["7.txt".into()], staging).await?, b"7");
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1,8 +1,9 @@
 //! Public fs file node.
 
-use super::{PublicFileSerializable, PublicNodeSerializable};
+use super::{overflow_metadata, PublicFileSerializable, PublicNodeSerializable};
 use crate::{
-    error::FsError, is_readable_wnfs_version, traits::Id, utils::OnceCellDebug, WNFS_VERSION,
+    error::FsError, is_legacy_wnfs_version, is_readable_wnfs_version, traits::Id,
+    utils::OnceCellDebug, WNFS_VERSION,
 };
 use anyhow::{anyhow, bail, Result};
 use async_once_cell::OnceCell;
@@ -571,11 +572,13 @@ impl Storable for PublicFile {
     type Serializable = PublicNodeSerializable;
 
     async fn to_serializable(&self, store: &impl BlockStore) -> Result<Self::Serializable> {
+        let (metadata, metadata_overflow) = overflow_metadata(&self.metadata, store).await?;
         Ok(PublicNodeSerializable::File(PublicFileSerializable {
             version: WNFS_VERSION,
-            metadata: self.metadata.clone(),
+            metadata,
             userland: self.userland.resolve_cid(store).await?,
             previous: self.previous.iter().cloned().collect(),
+            metadata_overflow,
         }))
     }
 
@@ -587,7 +590,9 @@ impl Storable for PublicFile {
             bail!(FsError::UnexpectedNodeType(NodeType::PublicDirectory));
         };
 
-        if !is_readable_wnfs_version(&serializable.version) {
+        if !is_readable_wnfs_version(&serializable.version)
+            && !is_legacy_wnfs_version(&serializable.version)
+        {
             bail!(FsError::UnexpectedVersion(serializable.version))
         }
 
@@ -599,6 +604,11 @@ impl Storable for PublicFile {
         })
     }
 
+    async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let serializable = PublicNodeSerializable::load(cid, store).await?;
+        Self::from_serializable(Some(cid), serializable).await
+    }
+
     fn persisted_as(&self) -> Option<&OnceCell<Cid>> {
         Some(&self.persisted_as)
     }
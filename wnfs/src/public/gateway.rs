@@ -0,0 +1,395 @@
+//! Helpers for serving the public file system through IPFS HTTP gateways.
+//!
+//! Gateways can only resolve paths in UnixFS directories, and public WNFS directories are
+//! DAG-CBOR. So paths are resolved locally instead, and the gateway is asked for the CID
+//! of the node directly. For files that's the CID of their UnixFS content, which gateways
+//! serve like any other file.
+//!
+//! Gateways are usually not trusted, so blocks fetched from them should be checked with
+//! [`verify_block`] before use. A block store that fetches blocks from a gateway can be
+//! wrapped in a [`VerifyingBlockStore`] to do that for every block, e.g. while streaming
+//! a file.
+
+use super::{lookup_stored_entry, PublicNodeSerializable};
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use libipld_core::cid::Cid;
+use wnfs_common::{
+    utils::{self, CondSend},
+    BlockStore, BlockStoreError, LoadIpld,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An IPFS HTTP gateway, identified by its origin, e.g. `https://dweb.link`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gateway {
+    scheme: String,
+    host: String,
+}
+
+/// The gateway URLs for a node in the public file system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayUrls {
+    /// The CID the gateway is asked for.
+    pub cid: Cid,
+    /// The path style URL, e.g. `https://dweb.link/ipfs/<cid>`.
+    pub path: String,
+    /// The subdomain style URL, e.g. `https://<cid>.ipfs.dweb.link/`.
+    pub subdomain: String,
+}
+
+/// A block store that checks every block it gets from an untrusted inner block store
+/// against the CID it was asked for, failing with [`BlockStoreError::CidMismatch`] otherwise.
+///
+/// File content is a DAG of blocks linked by CID, so starting from a trusted root CID,
+/// reading through this store verifies all content incrementally: each block is checked
+/// as it arrives, before any of its bytes or links are used, and reads fail at the first
+/// block that doesn't match.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use futures::AsyncReadExt;
+/// use wnfs::{
+///     common::{MemoryBlockStore, Storable},
+///     public::{PublicDirectory, PublicFile, VerifyingBlockStore},
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let gateway = MemoryBlockStore::new();
+///     let content = b"Hello, World!\n".repeat(1000);
+///     let file = PublicFile::with_content(Utc::now(), content.clone(), &gateway).await?;
+///     let file_cid = file.store(&gateway).await?;
+///
+///     // Only the CID of the file needs to come from a trusted source.
+///     let store = &VerifyingBlockStore::new(&gateway);
+///     let file = PublicFile::load(&file_cid, store).await?;
+///     let mut streamed = Vec::new();
+///     file.stream_content(0, store).await?.read_to_end(&mut streamed).await?;
+///
+///     assert_eq!(streamed, content);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct VerifyingBlockStore<B: BlockStore> {
+    inner: B,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Gateway {
+    /// Creates a gateway from its origin. Origins without a scheme default to `https`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::public::Gateway;
+    ///
+    /// let gateway = Gateway::new("https://dweb.link/").unwrap();
+    ///
+    /// assert_eq!(gateway, Gateway::new("dweb.link").unwrap());
+    /// ```
+    pub fn new(origin: &str) -> Result<Self> {
+        let (scheme, host) = origin.split_once("://").unwrap_or(("https", origin));
+        let host = host.trim_end_matches('/');
+        if host.is_empty() || host.contains('/') {
+            bail!("Invalid gateway origin: {origin:?}")
+        }
+
+        Ok(Self {
+            scheme: scheme.to_lowercase(),
+            host: host.to_lowercase(),
+        })
+    }
+
+    /// Returns the path style URL for given CID.
+    pub fn path_url(&self, cid: &Cid) -> String {
+        format!("{}://{}/ipfs/{cid}", self.scheme, self.host)
+    }
+
+    /// Returns the subdomain style URL for given CID.
+    ///
+    /// Subdomains are case-insensitive, so the CID is converted to a CIDv1,
+    /// which is displayed in lowercase base32.
+    pub fn subdomain_url(&self, cid: &Cid) -> Result<String> {
+        let cid = cid.into_v1()?;
+        Ok(format!("{}://{cid}.ipfs.{}/", self.scheme, self.host))
+    }
+
+    /// Returns the URL to fetch the block with given CID as is, so it can
+    /// be checked with [`verify_block`].
+    pub fn block_url(&self, cid: &Cid) -> String {
+        format!("{}?format=raw", self.path_url(cid))
+    }
+
+    /// Resolves a path in the public file system with given root and returns
+    /// the gateway URLs for the node at that path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     public::{Gateway, PublicDirectory},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let root = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let path = &["site".into(), "index.html".into()];
+    ///     root.write(path, b"<h1>Hello</h1>".to_vec(), Utc::now(), store).await?;
+    ///     let root_cid = root.store(store).await?;
+    ///
+    ///     let gateway = Gateway::new("https://dweb.link")?;
+    ///     let urls = gateway.urls(&root_cid, path, store).await?;
+    ///
+    ///     assert_eq!(urls.path, format!("https://dweb.link/ipfs/{}", urls.cid));
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn urls(
+        &self,
+        root_cid: &Cid,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<GatewayUrls> {
+        let cid = resolve_gateway_cid(root_cid, path_segments, store).await?;
+        Ok(GatewayUrls {
+            path: self.path_url(&cid),
+            subdomain: self.subdomain_url(&cid)?,
+            cid,
+        })
+    }
+}
+
+impl<B: BlockStore> VerifyingBlockStore<B> {
+    /// Wraps an untrusted block store.
+    pub fn new(inner: B) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the inner block store.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+}
+
+impl<B: BlockStore> BlockStore for VerifyingBlockStore<B> {
+    async fn get_block(&self, cid: &Cid) -> Result<Bytes, BlockStoreError> {
+        let bytes = self.inner.get_block(cid).await?;
+        utils::verify_block(cid, &bytes)?;
+        Ok(bytes)
+    }
+
+    async fn put_block_keyed(
+        &self,
+        cid: Cid,
+        bytes: impl Into<Bytes> + CondSend,
+    ) -> Result<(), BlockStoreError> {
+        self.inner.put_block_keyed(cid, bytes).await
+    }
+
+    async fn has_block(&self, cid: &Cid) -> Result<bool, BlockStoreError> {
+        self.inner.has_block(cid).await
+    }
+
+    fn max_block_size(&self) -> usize {
+        self.inner.max_block_size()
+    }
+
+    fn create_cid(&self, bytes: &[u8], codec: u64) -> Result<Cid, BlockStoreError> {
+        self.inner.create_cid(bytes, codec)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Resolves a path in the public file system with given root to the CID a gateway should
+/// be asked for.
+///
+/// For files that's the CID of their UnixFS content, for everything else the CID of the
+/// node itself. The empty path resolves to the root.
+pub async fn resolve_gateway_cid(
+    root_cid: &Cid,
+    path_segments: &[String],
+    store: &impl BlockStore,
+) -> Result<Cid> {
+    if path_segments.is_empty() {
+        return Ok(*root_cid);
+    }
+
+    // Only the blocks on the way are loaded, so sharded directories aren't loaded whole
+    let mut cid = *root_cid;
+    for segment in path_segments {
+        cid = lookup_stored_entry(&cid, segment, store)
+            .await?
+            .ok_or(FsError::NotFound)?;
+    }
+
+    let bytes = store.get_block(&cid).await?;
+    match PublicNodeSerializable::decode_ipld(&cid, bytes)? {
+        PublicNodeSerializable::File(file) => Ok(file.userland),
+        _ => Ok(cid),
+    }
+}
+
+/// Checks that bytes fetched for given CID hash to that CID.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::{
+///     common::{BlockStore, MemoryBlockStore, CODEC_RAW},
+///     public::verify_block,
+/// };
+///
+/// let cid = MemoryBlockStore::new().create_cid(b"hello", CODEC_RAW).unwrap();
+///
+/// assert!(verify_block(&cid, b"hello").is_ok());
+/// assert!(verify_block(&cid, b"tampered").is_err());
+/// ```
+pub fn verify_block(cid: &Cid, bytes: &[u8]) -> Result<()> {
+    Ok(utils::verify_block(cid, bytes)?)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::public::{PublicDirectory, PublicFile};
+    use chrono::Utc;
+    use futures::AsyncReadExt;
+    use testresult::TestResult;
+    use wnfs_common::{DryRunBlockStore, MemoryBlockStore, Storable, CODEC_DAG_PB, CODEC_RAW};
+
+    #[test]
+    fn urls_use_canonical_forms() -> TestResult {
+        let gateway = Gateway::new("HTTP://Localhost:8080/")?;
+        let cid: Cid = "QmbWqxBEKC3P8tqsKc98xmWNzrzDtRLMiMPL8wBuTGsMnR".parse()?;
+
+        assert_eq!(
+            gateway.path_url(&cid),
+            "http://localhost:8080/ipfs/QmbWqxBEKC3P8tqsKc98xmWNzrzDtRLMiMPL8wBuTGsMnR"
+        );
+        assert_eq!(
+            gateway.subdomain_url(&cid)?,
+            "http://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi.ipfs.localhost:8080/"
+        );
+        assert_eq!(cid.codec(), CODEC_DAG_PB);
+        assert!(Gateway::new("https://dweb.link/ipfs").is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn files_resolve_to_verifiable_content() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let root = &mut PublicDirectory::new_rc(Utc::now());
+        let path = &["site".into(), "index.html".into()];
+        root.write(path, b"<h1>Hello</h1>".to_vec(), Utc::now(), store)
+            .await?;
+        let root_cid = root.store(store).await?;
+
+        let gateway = Gateway::new("dweb.link")?;
+        let file_urls = gateway.urls(&root_cid, path, store).await?;
+        let file = root.get_node(path, store).await?.unwrap().as_file()?;
+        assert_eq!(file_urls.cid, file.get_raw_content_cid(store).await);
+
+        let dir_urls = gateway.urls(&root_cid, &["site".into()], store).await?;
+        let dir = root.get_node(&["site".into()], store).await?.unwrap();
+        assert_eq!(dir_urls.cid, dir.store(store).await?);
+        assert_eq!(gateway.urls(&root_cid, &[], store).await?.cid, root_cid);
+
+        let block = store.get_block(&file_urls.cid).await?;
+        verify_block(&file_urls.cid, &block)?;
+        assert!(verify_block(&file_urls.cid, b"<h1>Goodbye</h1>").is_err());
+
+        let missing = gateway
+            .urls(&root_cid, &["site".into(), "missing.html".into()], store)
+            .await;
+        assert!(missing.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn paths_resolve_through_sharded_directories() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let root = &mut PublicDirectory::new_rc(Utc::now());
+        for i in 0..=crate::DIRECTORY_SHARD_THRESHOLD {
+            let path = &[format!("{i}.html")];
+            root.write(path, i.to_string().into_bytes(), Utc::now(), store)
+                .await?;
+        }
+        let root_cid = root.store(store).await?;
+
+        let path = &["42.html".into()];
+        let cid = resolve_gateway_cid(&root_cid, path, store).await?;
+        let file = root.get_node(path, store).await?.unwrap().as_file()?;
+        assert_eq!(cid, file.get_raw_content_cid(store).await);
+
+        let missing = resolve_gateway_cid(&root_cid, &["missing.html".into()], store).await;
+        assert!(missing.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn streaming_through_verifying_stores_fails_on_tampered_blocks() -> TestResult {
+        let gateway = MemoryBlockStore::new();
+        let dry_run = DryRunBlockStore::new(&gateway);
+        let content: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+        let file = PublicFile::with_content(Utc::now(), content.clone(), &dry_run).await?;
+        let file_cid = file.store(&dry_run).await?;
+        let leaf_cids: Vec<_> = dry_run
+            .recorded()
+            .into_iter()
+            .map(|(cid, _)| cid)
+            .filter(|cid| cid.codec() == CODEC_RAW)
+            .collect();
+        dry_run.commit().await?;
+        assert!(leaf_cids.len() > 1);
+
+        let store = &VerifyingBlockStore::new(&gateway);
+        let file = PublicFile::load(&file_cid, store).await?;
+        let mut streamed = Vec::new();
+        file.stream_content(0, store)
+            .await?
+            .read_to_end(&mut streamed)
+            .await?;
+        assert_eq!(streamed, content);
+
+        gateway
+            .put_block_keyed(leaf_cids[0], b"tampered".to_vec())
+            .await?;
+        let mut streamed = Vec::new();
+        let result = file
+            .stream_content(0, store)
+            .await?
+            .read_to_end(&mut streamed)
+            .await;
+        assert!(result.is_err());
+        assert!(streamed.len() < content.len());
+        assert!(store.get_block(&leaf_cids[0]).await.is_err());
+
+        Ok(())
+    }
+}
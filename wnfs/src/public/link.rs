@@ -1,9 +1,12 @@
 //! Public node link.
 
-use super::{PublicDirectory, PublicFile, PublicNode};
+use super::{PublicCustomNode, PublicDirectory, PublicFile, PublicNode};
 use anyhow::Result;
 use libipld_core::cid::Cid;
-use wnfs_common::{utils::Arc, BlockStore, Link};
+use wnfs_common::{
+    utils::{boxed_fut, Arc},
+    BlockStore, Link, Storable,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -50,6 +53,28 @@ impl PublicLink {
         Self(Link::from(PublicNode::File(Arc::new(file))))
     }
 
+    /// Creates a link to a node of an app-defined kind.
+    #[inline]
+    pub fn with_custom(custom: PublicCustomNode) -> Self {
+        Self(Link::from(PublicNode::Custom(Arc::new(custom))))
+    }
+
+    /// Clones this link, copying the node it points at if it wasn't stored yet,
+    /// see [`PublicNode::detached`].
+    pub(crate) fn detached(&self) -> Self {
+        match self.0.get_value() {
+            Some(node) if !self.0.has_cid() => Self::new(node.detached()),
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns the CID of the node if it's already known, without storing the node.
+    #[cfg(feature = "private")]
+    #[inline]
+    pub(crate) fn get_cid(&self) -> Option<&Cid> {
+        self.0.get_cid()
+    }
+
     /// Gets the Cid stored in type. It attempts to get it from the store if it is not present in type.
     #[inline]
     pub async fn resolve_cid(&self, store: &impl BlockStore) -> Result<Cid> {
@@ -81,6 +106,24 @@ impl PublicLink {
     }
 }
 
+/// Links are stored as the CID of the node they link to, e.g. in the HAMT of a sharded
+/// directory.
+impl Storable for PublicLink {
+    type Serializable = Cid;
+
+    async fn to_serializable(&self, store: &impl BlockStore) -> Result<Self::Serializable> {
+        // Boxing the future due to recursion
+        boxed_fut(self.resolve_cid(store)).await
+    }
+
+    async fn from_serializable(
+        _cid: Option<&Cid>,
+        serializable: Self::Serializable,
+    ) -> Result<Self> {
+        Ok(Self::from_cid(serializable))
+    }
+}
+
 impl From<PublicNode> for PublicLink {
     #[inline]
     fn from(value: PublicNode) -> Self {
@@ -1,11 +1,23 @@
 //! Primitives for working with the public file system.
 
+mod batch;
+mod custom;
 mod directory;
 mod file;
+mod gateway;
 mod link;
+#[cfg(feature = "private")]
+mod mount;
 mod node;
+mod publish;
 
+pub use batch::*;
+pub use custom::*;
 pub use directory::*;
 pub use file::*;
+pub use gateway::*;
 pub use link::*;
+#[cfg(feature = "private")]
+pub use mount::*;
 pub use node::*;
+pub use publish::*;
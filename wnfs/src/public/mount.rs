@@ -0,0 +1,337 @@
+//! Mount points for grafting other file systems into a public directory tree.
+
+use super::{CustomNodeKind, PublicDirectory, PublicNode};
+use crate::{
+    error::FsError,
+    private::{forest::hamt::HamtForest, AccessKey, PrivateDirectory, PrivateNode},
+};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use futures::Future;
+use libipld_core::cid::Cid;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wnfs_common::{
+    utils::{Arc, CondSend, CondSync},
+    BlockStore, Metadata, Storable,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A node that stands in for the root of another file system.
+///
+/// Mount points are stored as [`PublicCustomNode`](super::PublicCustomNode)s, so readers
+/// that don't know about them just see an opaque node. Path lookups only go through
+/// a mount point if it's attached via a [`MountResolver`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MountPoint {
+    pub target: MountTarget,
+}
+
+/// What a [`MountPoint`] refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MountTarget {
+    /// The root directory of a public file system.
+    Public(Cid),
+    /// A private directory in given private forest.
+    ///
+    /// The access key is never stored in the tree, since anyone who can read the
+    /// mount point could read it. Instead, `key_label` tells the resolver which of
+    /// the access keys it was given belongs to this mount.
+    Private { forest: Cid, key_label: String },
+}
+
+/// A mounted directory, as returned by a [`MountResolver`].
+#[derive(Debug, Clone)]
+pub enum MountedDir {
+    Public(Arc<PublicDirectory>),
+    Private {
+        dir: Arc<PrivateDirectory>,
+        forest: Arc<HamtForest>,
+    },
+}
+
+/// Attaches mount points, so path lookups can go through them.
+pub trait MountResolver: CondSync {
+    /// Resolves given mount point to the directory it refers to.
+    ///
+    /// Returning `Ok(None)` leaves the mount point detached, in which case
+    /// it's treated like any other node that isn't a directory.
+    fn resolve_mount(
+        &self,
+        mount: &MountPoint,
+        store: &impl BlockStore,
+    ) -> impl Future<Output = Result<Option<MountedDir>>> + CondSend;
+}
+
+/// A [`MountResolver`] that attaches all public mount points, and private mount
+/// points for which an access key was registered.
+///
+/// Mounted file systems are loaded from the same block store as the directory
+/// they're mounted into.
+#[derive(Debug, Clone, Default)]
+pub struct MountTable {
+    access_keys: BTreeMap<String, AccessKey>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl CustomNodeKind for MountPoint {
+    const NODE_TYPE: &'static str = "wnfs-mount";
+}
+
+impl MountTable {
+    /// Creates a mount table without any access keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the access key for private mount points with given key label.
+    pub fn attach_private(&mut self, key_label: impl Into<String>, access_key: AccessKey) {
+        self.access_keys.insert(key_label.into(), access_key);
+    }
+
+    /// Removes the access key registered under given key label, detaching its mount points.
+    pub fn detach_private(&mut self, key_label: &str) -> Option<AccessKey> {
+        self.access_keys.remove(key_label)
+    }
+}
+
+impl MountResolver for MountTable {
+    async fn resolve_mount(
+        &self,
+        mount: &MountPoint,
+        store: &impl BlockStore,
+    ) -> Result<Option<MountedDir>> {
+        Ok(Some(match &mount.target {
+            MountTarget::Public(root) => {
+                MountedDir::Public(Arc::new(PublicDirectory::load(root, store).await?))
+            }
+            MountTarget::Private { forest, key_label } => {
+                let Some(access_key) = self.access_keys.get(key_label) else {
+                    return Ok(None);
+                };
+
+                let forest = Arc::new(HamtForest::load(forest, store).await?);
+                let dir = PrivateNode::load(access_key, &forest, store, None)
+                    .await?
+                    .as_dir()?;
+
+                MountedDir::Private { dir, forest }
+            }
+        }))
+    }
+}
+
+impl PublicDirectory {
+    /// Places a mount point at given path, creating any missing parent directories.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     public::{MountTable, MountTarget, PublicDirectory},
+    ///     common::{MemoryBlockStore, Storable},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///
+    ///     let shared = &mut PublicDirectory::new_rc(Utc::now());
+    ///     shared.write(&["hello.txt".into()], b"Hello!".to_vec(), Utc::now(), store).await?;
+    ///     let shared_cid = shared.store(store).await?;
+    ///
+    ///     let root = &mut PublicDirectory::new_rc(Utc::now());
+    ///     root.mount(&["shared".into()], MountTarget::Public(shared_cid), Utc::now(), store)
+    ///         .await?;
+    ///
+    ///     let path = &["shared".into(), "hello.txt".into()];
+    ///     let content = root.read_mounted(path, &MountTable::new(), store).await?;
+    ///
+    ///     assert_eq!(content, b"Hello!");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn mount(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        target: MountTarget,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        self.write_custom(path_segments, &MountPoint { target }, time, store)
+            .await
+    }
+
+    /// Follows given path as far as possible, going through any mount points
+    /// `resolver` attaches.
+    ///
+    /// Returns the last directory reached and the part of the path that's
+    /// relative to it.
+    pub async fn resolve_mounts<'p>(
+        self: &Arc<Self>,
+        path_segments: &'p [String],
+        resolver: &impl MountResolver,
+        store: &impl BlockStore,
+    ) -> Result<(MountedDir, &'p [String])> {
+        let mut working_dir = Arc::clone(self);
+        for (depth, segment) in path_segments.iter().enumerate() {
+            match working_dir.lookup_node(segment, store).await? {
+                Some(PublicNode::Dir(dir)) => {
+                    working_dir = Arc::clone(dir);
+                }
+                Some(PublicNode::Custom(custom)) if custom.is::<MountPoint>() => {
+                    let mount = custom.decode::<MountPoint>()?;
+                    match resolver.resolve_mount(&mount, store).await? {
+                        Some(MountedDir::Public(dir)) => working_dir = dir,
+                        Some(private @ MountedDir::Private { .. }) => {
+                            return Ok((private, &path_segments[depth + 1..]));
+                        }
+                        None => {
+                            return Ok((MountedDir::Public(working_dir), &path_segments[depth..]))
+                        }
+                    }
+                }
+                _ => return Ok((MountedDir::Public(working_dir), &path_segments[depth..])),
+            }
+        }
+
+        Ok((MountedDir::Public(working_dir), &[]))
+    }
+
+    /// Reads specified file content, going through any mount points `resolver` attaches.
+    pub async fn read_mounted(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        resolver: &impl MountResolver,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        match self.resolve_mounts(path_segments, resolver, store).await? {
+            (MountedDir::Public(dir), path) => dir.read(path, store).await,
+            (MountedDir::Private { dir, forest }, path) => {
+                if path.is_empty() {
+                    bail!(FsError::NotAFile);
+                }
+
+                dir.read(path, true, &forest, store).await
+            }
+        }
+    }
+
+    /// Gets the directory listing at given path, going through any mount points
+    /// `resolver` attaches.
+    pub async fn ls_mounted(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        resolver: &impl MountResolver,
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata)>> {
+        match self.resolve_mounts(path_segments, resolver, store).await? {
+            (MountedDir::Public(dir), path) => dir.ls(path, store).await,
+            (MountedDir::Private { dir, forest }, path) => dir.ls(path, true, &forest, store).await,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::traits::PrivateForest;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn public_mounts_resolve_through() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let time = Utc::now();
+
+        let shared = &mut PublicDirectory::new_rc(time);
+        shared
+            .write(&["docs".into(), "a.txt".into()], b"a".to_vec(), time, store)
+            .await?;
+        let shared_cid = shared.store(store).await?;
+
+        let root = &mut PublicDirectory::new_rc(time);
+        root.mount(
+            &["mnt".into(), "shared".into()],
+            MountTarget::Public(shared_cid),
+            time,
+            store,
+        )
+        .await?;
+        let root = Arc::new(PublicDirectory::load(&root.store(store).await?, store).await?);
+
+        let resolver = &MountTable::new();
+        let path = &["mnt".into(), "shared".into(), "docs".into(), "a.txt".into()];
+        assert_eq!(root.read_mounted(path, resolver, store).await?, b"a");
+
+        let listing = root
+            .ls_mounted(&["mnt".into(), "shared".into()], resolver, store)
+            .await?;
+        assert_eq!(listing.len(), 1);
+        assert_eq!(listing[0].0, "docs");
+
+        // Without resolving mounts, the mount point is just an opaque node
+        assert!(root.read(path, store).await.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn private_mounts_need_an_access_key() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc::now();
+
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+        dir.write(
+            &["secret.txt".into()],
+            true,
+            time,
+            b"psst".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+        let access_key = dir.as_node().store(forest, store, rng).await?;
+        let forest_cid = forest.store(store).await?;
+
+        let root = &mut PublicDirectory::new_rc(time);
+        let target = MountTarget::Private {
+            forest: forest_cid,
+            key_label: "alice".into(),
+        };
+        root.mount(&["alice".into()], target, time, store).await?;
+
+        let path = &["alice".into(), "secret.txt".into()];
+        let resolver = &mut MountTable::new();
+        assert!(root.read_mounted(path, resolver, store).await.is_err());
+
+        resolver.attach_private("alice", access_key);
+        assert_eq!(root.read_mounted(path, resolver, store).await?, b"psst");
+        assert_eq!(
+            root.ls_mounted(&["alice".into()], resolver, store)
+                .await?
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+}
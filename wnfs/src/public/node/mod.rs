@@ -1,6 +1,10 @@
 #[allow(clippy::module_inception)]
 mod node;
+mod overflow;
 mod serializable;
+mod shard;
 
 pub use node::*;
+pub(crate) use overflow::*;
 pub(crate) use serializable::*;
+pub(crate) use shard::*;
@@ -1,9 +1,9 @@
 //! Public node system in-memory representation.
 
-use super::PublicNodeSerializable;
+use super::{validate_userland_shards, PublicNodeSerializable};
 use crate::{
-    error::FsError,
-    public::{PublicDirectory, PublicFile},
+    error::{FsError, ValidationError},
+    public::{PublicCustomNode, PublicDirectory, PublicFile},
     traits::Id,
 };
 use anyhow::{bail, Result};
@@ -11,13 +11,22 @@ use async_once_cell::OnceCell;
 use chrono::{DateTime, Utc};
 use libipld_core::cid::Cid;
 use std::{cmp::Ordering, collections::BTreeSet};
-use wnfs_common::{utils::Arc, BlockStore, Storable};
+use wnfs_common::{utils::Arc, BlockStore, DryRunBlockStore, Metadata, NodeType, Storable};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The domain separation info used for salting the hashing function
+/// when turning CIDs into inode numbers for file system integrations.
+const INODE_DSI: &str = "wnfs/1.0/inode derivation from public node history";
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
 
-/// A node in the WNFS public file system. This can either be a file or a directory.
+/// A node in the WNFS public file system. This can either be a file, a directory
+/// or a node of an app-defined kind.
 ///
 /// # Examples
 ///
@@ -34,6 +43,7 @@ use wnfs_common::{utils::Arc, BlockStore, Storable};
 pub enum PublicNode {
     File(Arc<PublicFile>),
     Dir(Arc<PublicDirectory>),
+    Custom(Arc<PublicCustomNode>),
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -41,6 +51,100 @@ pub enum PublicNode {
 //--------------------------------------------------------------------------------------------------
 
 impl PublicNode {
+    /// Loads a node like [`Storable::load`], but first validates it, failing with a
+    /// [`ValidationError`](crate::error::ValidationError) if it's malformed.
+    ///
+    /// Checks that previous links don't repeat, that the `created` and `modified`
+    /// metadata fields are timestamps and, for directories, that all entry names
+    /// are valid path segments. Only the node itself is checked, not its children.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     public::{PublicDirectory, PublicNode},
+    ///     common::{MemoryBlockStore, Storable},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     dir.write(&["notes.md".into()], b"hi".to_vec(), Utc::now(), store).await?;
+    ///     let cid = dir.store(store).await?;
+    ///
+    ///     let node = PublicNode::load_strict(&cid, store).await?;
+    ///
+    ///     assert!(node.is_dir());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn load_strict(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let serializable = PublicNodeSerializable::load(cid, store).await?;
+        serializable.validate()?;
+        if let PublicNodeSerializable::Dir(dir) = &serializable {
+            if let Some(shards) = &dir.userland_shards {
+                validate_userland_shards(shards, store).await?;
+            }
+        }
+
+        Self::from_serializable(Some(cid), serializable).await
+    }
+
+    /// Computes the CID that storing this node would return, without writing any blocks.
+    ///
+    /// The node and all of its descendants that weren't stored yet are encoded like
+    /// [`Storable::store`] does, so the CID can be used e.g. in manifests or for
+    /// compare-and-swap operations before anything is uploaded. `store` is only read
+    /// from, to load existing blocks the encoding depends on. Unlike storing, this doesn't
+    /// remember the CIDs in the node, so it can be stored for real afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use wnfs::{
+    ///     public::{PublicDirectory, PublicNode},
+    ///     common::{BlockStore, MemoryBlockStore, Storable},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     dir.write(&["notes.md".into()], b"hi".to_vec(), Utc::now(), store).await?;
+    ///     let node = PublicNode::Dir(dir.clone());
+    ///
+    ///     let cid = node.compute_cid(store).await?;
+    ///     assert!(!store.has_block(&cid).await?);
+    ///
+    ///     assert_eq!(node.store(store).await?, cid);
+    ///     assert!(store.has_block(&cid).await?);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn compute_cid(&self, store: &impl BlockStore) -> Result<Cid> {
+        let dry_run = &DryRunBlockStore::new(store);
+        self.detached().store(dry_run).await
+    }
+
+    /// Clones this node, copying all of its descendants that weren't stored yet.
+    ///
+    /// Storing a node remembers its CID in the node, and clones share their descendants,
+    /// so storing the clone returned by this doesn't affect this node's descendants.
+    pub(crate) fn detached(&self) -> Self {
+        match self {
+            Self::File(file) => Self::File(Arc::new((**file).clone())),
+            Self::Dir(dir) => Self::Dir(Arc::new(dir.detached())),
+            Self::Custom(custom) => Self::Custom(Arc::new((**custom).clone())),
+        }
+    }
+
     /// Creates node with upserted modified time.
     ///
     /// # Examples
@@ -72,6 +176,9 @@ impl PublicNode {
             Self::Dir(dir) => {
                 Arc::make_mut(dir).metadata.upsert_mtime(time);
             }
+            Self::Custom(custom) => {
+                Arc::make_mut(custom).metadata.upsert_mtime(time);
+            }
         }
     }
 
@@ -110,6 +217,11 @@ impl PublicNode {
                 dir.previous = cids.into_iter().collect();
                 Self::Dir(Arc::new(dir))
             }
+            Self::Custom(custom) => {
+                let mut custom = (**custom).clone();
+                custom.previous = cids.into_iter().collect();
+                Self::Custom(Arc::new(custom))
+            }
         }
     }
 
@@ -135,6 +247,7 @@ impl PublicNode {
         match self {
             Self::File(file) => file.get_previous(),
             Self::Dir(dir) => dir.get_previous(),
+            Self::Custom(custom) => custom.get_previous(),
         }
     }
 
@@ -196,6 +309,15 @@ impl PublicNode {
         }
     }
 
+    /// Casts a node to a node of an app-defined kind.
+    pub fn as_custom(&self) -> Result<Arc<PublicCustomNode>> {
+        Ok(match self {
+            Self::Custom(custom) => Arc::clone(custom),
+            Self::File(_) => bail!(FsError::UnexpectedNodeType(NodeType::PublicFile)),
+            Self::Dir(_) => bail!(FsError::UnexpectedNodeType(NodeType::PublicDirectory)),
+        })
+    }
+
     /// Returns true if underlying node is a directory.
     ///
     /// # Examples
@@ -230,6 +352,99 @@ impl PublicNode {
         matches!(self, Self::File(_))
     }
 
+    /// Returns true if the underlying node is of an app-defined kind.
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Self::Custom(_))
+    }
+
+    /// Gets the metadata of the node, whatever kind it is.
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => file.get_metadata(),
+            Self::Dir(dir) => dir.get_metadata(),
+            Self::Custom(custom) => custom.get_metadata(),
+        }
+    }
+
+    /// Gets the type of the node.
+    pub fn get_type(&self) -> NodeType {
+        match self {
+            Self::File(_) => NodeType::PublicFile,
+            Self::Dir(_) => NodeType::PublicDirectory,
+            Self::Custom(custom) => custom.get_type(),
+        }
+    }
+
+    /// Gets a 64-bit inode number for the node, e.g. for FUSE or NFS integrations.
+    ///
+    /// Public nodes don't have an inumber, so it's derived from the CID of the first
+    /// revision in the node's history instead. That makes it stay the same across
+    /// revisions and when the node is moved or renamed. If merges joined several
+    /// histories, the first revision with the lowest CID is used.
+    ///
+    /// This walks the whole history, so integrations should cache the result by the
+    /// node's CID. Copies share their history with the original and thus its inode
+    /// number, and so do nodes created with the same content at the same time.
+    /// Distinct nodes can also collide, since the number is cut down to 64 bits.
+    /// Integrations that need unique numbers should keep track of the numbers they
+    /// hand out and remap collisions, as well as any numbers they reserve, e.g. `1`
+    /// for the FUSE root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::{public::{PublicDirectory, PublicNode}, common::{MemoryBlockStore, Storable}};
+    /// use chrono::Utc;
+    /// use std::sync::Arc;
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let inode = PublicNode::Dir(Arc::clone(dir)).inode(store).await.unwrap();
+    ///
+    ///     dir.store(store).await.unwrap();
+    ///     dir.mkdir(&["pictures".into()], Utc::now(), store)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let node = PublicNode::Dir(Arc::clone(dir));
+    ///     assert_eq!(node.inode(store).await.unwrap(), inode);
+    /// }
+    /// ```
+    pub async fn inode(&self, store: &impl BlockStore) -> Result<u64> {
+        let mut first_revisions = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut frontier = self.get_previous().clone();
+
+        if frontier.is_empty() {
+            first_revisions.insert(self.compute_cid(store).await?);
+        }
+
+        while let Some(cid) = frontier.pop_first() {
+            if !visited.insert(cid) {
+                continue;
+            }
+
+            let node = PublicNode::load(&cid, store).await?;
+            if node.get_previous().is_empty() {
+                first_revisions.insert(cid);
+            }
+
+            frontier.extend(node.get_previous().difference(&visited));
+        }
+
+        let Some(first_revision) = first_revisions.first() else {
+            bail!(ValidationError::InvalidPrevious(
+                "history has no first revision".into()
+            ));
+        };
+        let hash = blake3::derive_key(INODE_DSI, &first_revision.to_bytes());
+        Ok(u64::from_le_bytes(
+            hash[..8].try_into().expect("hash has 32 bytes"),
+        ))
+    }
+
     /// Comparing the merkle clocks of this node to the other node.
     ///
     /// This gives you information about which node is "ahead" of which other node
@@ -314,6 +529,7 @@ impl Id for PublicNode {
         match self {
             PublicNode::File(file) => file.get_id(),
             PublicNode::Dir(dir) => dir.get_id(),
+            PublicNode::Custom(custom) => custom.get_id(),
         }
     }
 }
@@ -327,6 +543,9 @@ impl PartialEq for PublicNode {
             (Self::Dir(self_dir), Self::Dir(other_dir)) => {
                 Arc::ptr_eq(self_dir, other_dir) || self_dir == other_dir
             }
+            (Self::Custom(self_custom), Self::Custom(other_custom)) => {
+                Arc::ptr_eq(self_custom, other_custom) || self_custom == other_custom
+            }
             _ => false,
         }
     }
@@ -344,6 +563,12 @@ impl From<PublicDirectory> for PublicNode {
     }
 }
 
+impl From<PublicCustomNode> for PublicNode {
+    fn from(custom: PublicCustomNode) -> Self {
+        Self::Custom(Arc::new(custom))
+    }
+}
+
 impl Storable for PublicNode {
     type Serializable = PublicNodeSerializable;
 
@@ -351,6 +576,7 @@ impl Storable for PublicNode {
         Ok(match self {
             Self::File(file) => file.to_serializable(store).await?,
             Self::Dir(dir) => dir.to_serializable(store).await?,
+            Self::Custom(custom) => custom.to_serializable(store).await?,
         })
     }
 
@@ -366,13 +592,22 @@ impl Storable for PublicNode {
             PublicNodeSerializable::Dir(dir) => Self::Dir(Arc::new(
                 PublicDirectory::from_serializable(cid, PublicNodeSerializable::Dir(dir)).await?,
             )),
+            custom @ PublicNodeSerializable::Custom(..) => Self::Custom(Arc::new(
+                PublicCustomNode::from_serializable(cid, custom).await?,
+            )),
         })
     }
 
+    async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let serializable = PublicNodeSerializable::load(cid, store).await?;
+        Self::from_serializable(Some(cid), serializable).await
+    }
+
     fn persisted_as(&self) -> Option<&OnceCell<Cid>> {
         match self {
             PublicNode::File(file) => file.as_ref().persisted_as(),
             PublicNode::Dir(dir) => dir.as_ref().persisted_as(),
+            PublicNode::Custom(custom) => custom.as_ref().persisted_as(),
         }
     }
 }
@@ -383,10 +618,14 @@ impl Storable for PublicNode {
 
 #[cfg(test)]
 mod tests {
-    use crate::public::{PublicDirectory, PublicFile, PublicNode};
+    use crate::{
+        error::ValidationError,
+        public::{PublicDirectory, PublicFile, PublicNode},
+    };
     use chrono::Utc;
+    use libipld_core::ipld::Ipld;
     use testresult::TestResult;
-    use wnfs_common::{MemoryBlockStore, Storable};
+    use wnfs_common::{BlockStore, MemoryBlockStore, Storable};
 
     #[async_std::test]
     async fn serialized_public_node_can_be_deserialized() -> TestResult {
@@ -408,6 +647,69 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn strict_loading_rejects_malformed_nodes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        dir.write(&["a/b".into()], b"x".to_vec(), Utc::now(), store)
+            .await?;
+        let cid = dir.store(store).await?;
+
+        // Lenient loading accepts what strict loading doesn't
+        PublicNode::load(&cid, store).await?;
+        let result = PublicNode::load_strict(&cid, store).await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(ValidationError::InvalidEntryName(name)) if name == "a/b"
+        ));
+
+        let file = &mut PublicFile::new_rc(Utc::now());
+        file.get_metadata_mut_rc().put("created", Ipld::Bool(true));
+        let result = PublicNode::load_strict(&file.store(store).await?, store).await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(ValidationError::InvalidMetadata(..))
+        ));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn computing_cids_doesnt_keep_blocks_from_being_stored() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        let path = &["docs".into(), "notes.md".into()];
+        dir.write(path, b"hi".to_vec(), Utc::now(), store).await?;
+        let node = PublicNode::Dir(dir.clone());
+
+        let cid = node.compute_cid(store).await?;
+        assert!(!store.has_block(&cid).await?);
+        assert_eq!(node.compute_cid(store).await?, cid);
+
+        assert_eq!(node.store(store).await?, cid);
+        let loaded = PublicNode::load(&cid, store).await?.as_dir()?;
+        assert_eq!(loaded.read(path, store).await?, b"hi");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn inode_numbers_of_unstored_nodes_dont_store_them() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        dir.write(&["a.txt".into()], b"a".to_vec(), Utc::now(), store)
+            .await?;
+        let node = PublicNode::Dir(dir.clone());
+
+        let inode = node.inode(store).await?;
+        assert!(!store.has_block(&node.compute_cid(store).await?).await?);
+
+        node.store(store).await?;
+        assert_eq!(node.inode(store).await?, inode);
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,150 @@
+use crate::{utils, METADATA_OVERFLOW_THRESHOLD};
+use anyhow::Result;
+use libipld_core::{cid::Cid, ipld::Ipld};
+use std::collections::BTreeMap;
+use wnfs_common::{BlockStore, Metadata, CODEC_DAG_CBOR};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Moves all values larger than [`METADATA_OVERFLOW_THRESHOLD`] out of given metadata,
+/// like private nodes do.
+///
+/// Each moved value is stored as a dag-cbor block of its own. Returns the remaining
+/// metadata and the CIDs of the moved values by key.
+pub(crate) async fn overflow_metadata(
+    metadata: &Metadata,
+    store: &impl BlockStore,
+) -> Result<(Metadata, BTreeMap<String, Cid>)> {
+    let mut inline = Metadata(BTreeMap::new());
+    let mut overflow = BTreeMap::new();
+    for (key, value) in metadata.0.iter() {
+        let bytes = serde_ipld_dagcbor::to_vec(value)?;
+        if bytes.len() <= METADATA_OVERFLOW_THRESHOLD {
+            inline.0.insert(key.clone(), value.clone());
+            continue;
+        }
+
+        let cid = store.put_block(bytes, CODEC_DAG_CBOR).await?;
+        overflow.insert(key.clone(), cid);
+    }
+
+    Ok((inline, overflow))
+}
+
+/// Loads the values that [`overflow_metadata`] moved out back into given metadata.
+pub(crate) async fn resolve_metadata_overflow(
+    metadata: &mut Metadata,
+    overflow: BTreeMap<String, Cid>,
+    store: &impl BlockStore,
+) -> Result<()> {
+    for (key, cid) in overflow {
+        let block = store.get_block(&cid).await?;
+        let value: Ipld = serde_ipld_dagcbor::from_slice(&block)?;
+        metadata.0.insert(key, value);
+    }
+
+    Ok(())
+}
+
+/// Moves the entries of a directory that doesn't fit into a single block of the store into
+/// dag-cbor blocks of their own, returning their CIDs.
+pub(crate) async fn overflow_userland(
+    userland: &BTreeMap<String, Cid>,
+    store: &impl BlockStore,
+) -> Result<Vec<Cid>> {
+    let mut cids = Vec::new();
+    for group in utils::split_entries(userland, store.max_block_size())? {
+        let bytes = serde_ipld_dagcbor::to_vec(&group)?;
+        cids.push(store.put_block(bytes, CODEC_DAG_CBOR).await?);
+    }
+
+    Ok(cids)
+}
+
+/// Loads the entries that [`overflow_userland`] moved out back into given entries.
+pub(crate) async fn resolve_userland_overflow(
+    userland: &mut BTreeMap<String, Cid>,
+    overflow: Vec<Cid>,
+    store: &impl BlockStore,
+) -> Result<()> {
+    for cid in overflow {
+        let block = store.get_block(&cid).await?;
+        let group: BTreeMap<String, Cid> = serde_ipld_dagcbor::from_slice(&block)?;
+        userland.extend(group);
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::public::{PublicDirectory, PublicNode};
+    use chrono::Utc;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, SizeLimitedBlockStore, Storable};
+
+    #[async_std::test]
+    async fn large_metadata_values_are_stored_outside_the_node_block() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        let path = &["photo.jpg".into()];
+        dir.write(path, vec![1, 2, 3], Utc::now(), store).await?;
+
+        let thumbnail = Ipld::Bytes(vec![0xFF; 64 * 1024]);
+        dir.update_metadata(
+            path,
+            |metadata| {
+                metadata.put("thumbnail", thumbnail.clone());
+                metadata.put("title", Ipld::String("Holiday".into()));
+            },
+            store,
+        )
+        .await?;
+        let cid = dir.store(store).await?;
+
+        let file = dir.get_node(path, store).await?.unwrap();
+        let file_cid = file.store(store).await?;
+        let file_block = store.get_block(&file_cid).await?;
+        assert!(file_block.len() < METADATA_OVERFLOW_THRESHOLD * 2);
+
+        let loaded = PublicDirectory::load(&cid, store).await?;
+        let loaded_file = loaded.get_node(path, store).await?.unwrap().as_file()?;
+        let metadata = loaded_file.get_metadata();
+        assert_eq!(metadata.get("thumbnail"), Some(&thumbnail));
+        assert_eq!(metadata, file.as_file()?.get_metadata());
+
+        let strict = PublicNode::load_strict(&file_cid, store).await?;
+        assert_eq!(strict.as_file()?.get_metadata(), metadata);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn entries_of_large_directories_are_split_to_fit_the_block_size() -> TestResult {
+        let store = &SizeLimitedBlockStore::new(MemoryBlockStore::new(), 4 * 1024);
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        for i in 0..300 {
+            let path = &["docs".into(), format!("{i:03}.txt")];
+            dir.write(path, vec![i as u8], Utc::now(), store).await?;
+        }
+
+        // Without splitting, the docs directory wouldn't fit into a single block
+        let cid = dir.store(store).await?;
+
+        let loaded = PublicDirectory::load(&cid, store).await?;
+        let docs = loaded.ls(&["docs".into()], store).await?;
+        assert_eq!(docs.len(), 300);
+
+        let path = &["docs".into(), "123.txt".into()];
+        assert_eq!(loaded.read(path, store).await?, [123]);
+
+        Ok(())
+    }
+}
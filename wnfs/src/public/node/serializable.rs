@@ -1,19 +1,39 @@
-use libipld_core::cid::Cid;
+use super::{resolve_metadata_overflow, resolve_userland_overflow};
+use crate::{
+    error::ValidationError,
+    utils::{validate_entry_name, validate_metadata, validate_order, validate_previous},
+};
+use anyhow::Result;
+use libipld_core::{cid::Cid, ipld::Ipld};
 use semver::Version;
-use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use wnfs_common::Metadata;
+use serde::{
+    de::{Error as DeError, MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{collections::BTreeMap, fmt, mem};
+use wnfs_common::{BlockStore, LoadIpld, Metadata, NodeType};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+const PUBLIC_FILE_TAG: &str = "wnfs/pub/file";
+const PUBLIC_DIR_TAG: &str = "wnfs/pub/dir";
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Serializes as a single-entry map from the node's type string to its fields.
+///
+/// Type strings outside the reserved `wnfs/` namespace are deserialized as
+/// [`PublicNodeSerializable::Custom`], keeping their content as-is.
+#[derive(Debug, Clone)]
 pub enum PublicNodeSerializable {
-    #[serde(rename = "wnfs/pub/file")]
     File(PublicFileSerializable),
-    #[serde(rename = "wnfs/pub/dir")]
     Dir(PublicDirectorySerializable),
+    Custom(String, PublicCustomNodeSerializable),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +42,13 @@ pub struct PublicFileSerializable {
     pub metadata: Metadata,
     pub previous: Vec<Cid>,
     pub userland: Cid,
+    /// Metadata values that are stored in blocks of their own, by key.
+    #[serde(
+        rename = "metadataOverflow",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub metadata_overflow: BTreeMap<String, Cid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,4 +57,158 @@ pub struct PublicDirectorySerializable {
     pub metadata: Metadata,
     pub previous: Vec<Cid>,
     pub userland: BTreeMap<String, Cid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>,
+    /// How often the entries were reordered, to merge orders with.
+    #[serde(rename = "orderRevision", default, skip_serializing_if = "is_zero")]
+    pub order_revision: u64,
+    /// Groups of entries that are stored in blocks of their own, for directories that
+    /// don't fit into a single block.
+    #[serde(
+        rename = "userlandOverflow",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub userland_overflow: Vec<Cid>,
+    /// The root of the HAMT the entries are stored in, for directories with more than
+    /// [`DIRECTORY_SHARD_THRESHOLD`](crate::DIRECTORY_SHARD_THRESHOLD) entries.
+    #[serde(
+        rename = "userlandShards",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub userland_shards: Option<Cid>,
+    /// The number of entries in the HAMT at `userland_shards`.
+    #[serde(rename = "userlandCount", default, skip_serializing_if = "is_zero")]
+    pub userland_count: u64,
+    /// Metadata values that are stored in blocks of their own, by key.
+    #[serde(
+        rename = "metadataOverflow",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub metadata_overflow: BTreeMap<String, Cid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicCustomNodeSerializable {
+    pub version: Version,
+    pub metadata: Metadata,
+    pub previous: Vec<Cid>,
+    pub content: Ipld,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PublicNodeSerializable {
+    /// Loads a public node block, together with the metadata values and entries stored
+    /// outside of it, except for the HAMT of sharded directories.
+    pub(crate) async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let bytes = store.get_block(cid).await?;
+        let mut serializable = Self::decode_ipld(cid, bytes)?;
+        let (metadata, overflow) = match &mut serializable {
+            Self::File(file) => (&mut file.metadata, &mut file.metadata_overflow),
+            Self::Dir(dir) => {
+                let overflow = mem::take(&mut dir.userland_overflow);
+                resolve_userland_overflow(&mut dir.userland, overflow, store).await?;
+                (&mut dir.metadata, &mut dir.metadata_overflow)
+            }
+            Self::Custom(..) => return Ok(serializable),
+        };
+        resolve_metadata_overflow(metadata, mem::take(overflow), store).await?;
+
+        Ok(serializable)
+    }
+
+    /// Checks the invariants that strict loading enforces.
+    pub(crate) fn validate(&self) -> Result<(), ValidationError> {
+        let (metadata, previous) = match self {
+            Self::File(file) => (&file.metadata, &file.previous),
+            Self::Dir(dir) => {
+                for name in dir.userland.keys() {
+                    validate_entry_name(name)?;
+                }
+                validate_order(&dir.order)?;
+
+                (&dir.metadata, &dir.previous)
+            }
+            Self::Custom(_, custom) => (&custom.metadata, &custom.previous),
+        };
+
+        validate_metadata(metadata)?;
+        validate_previous(previous)
+    }
+}
+
+impl Serialize for PublicNodeSerializable {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            Self::File(file) => map.serialize_entry(PUBLIC_FILE_TAG, file)?,
+            Self::Dir(dir) => map.serialize_entry(PUBLIC_DIR_TAG, dir)?,
+            Self::Custom(node_type, custom) => map.serialize_entry(node_type, custom)?,
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicNodeSerializable {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PublicNodeVisitor;
+
+        impl<'de> Visitor<'de> for PublicNodeVisitor {
+            type Value = PublicNodeSerializable;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map with a single public node type key")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let Some(tag) = map.next_key::<String>()? else {
+                    return Err(A::Error::custom("Missing public node type"));
+                };
+
+                let node = match tag.as_str() {
+                    PUBLIC_FILE_TAG => PublicNodeSerializable::File(map.next_value()?),
+                    PUBLIC_DIR_TAG => PublicNodeSerializable::Dir(map.next_value()?),
+                    _ if !NodeType::is_reserved(&tag) => {
+                        PublicNodeSerializable::Custom(tag, map.next_value()?)
+                    }
+                    _ => {
+                        return Err(A::Error::unknown_variant(
+                            &tag,
+                            &[PUBLIC_FILE_TAG, PUBLIC_DIR_TAG],
+                        ))
+                    }
+                };
+
+                if map.next_key::<String>()?.is_some() {
+                    return Err(A::Error::custom("Expected a single public node type"));
+                }
+
+                Ok(node)
+            }
+        }
+
+        deserializer.deserialize_map(PublicNodeVisitor)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+fn is_zero(revision: &u64) -> bool {
+    *revision == 0
 }
@@ -0,0 +1,228 @@
+use super::{resolve_userland_overflow, PublicDirectorySerializable, PublicNodeSerializable};
+use crate::{public::PublicLink, utils::validate_entry_name};
+use anyhow::Result;
+use libipld_core::cid::Cid;
+use std::{collections::BTreeMap, mem};
+use wnfs_common::{utils::Arc, BlockStore, Link, LoadIpld, Storable};
+use wnfs_hamt::{Hasher, Node, Pair};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The HAMT that sharded directories store their entries in, from names to nodes.
+pub(crate) type EntryShards = Node<String, PublicLink>;
+
+/// The entries of a directory with more than
+/// [`DIRECTORY_SHARD_THRESHOLD`](crate::DIRECTORY_SHARD_THRESHOLD) entries.
+///
+/// The blocks of the HAMT are only loaded as far as the entries are accessed, and storing
+/// only stores the parts of the HAMT that changed.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ShardedEntries {
+    root: Link<Arc<EntryShards>>,
+    count: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ShardedEntries {
+    /// Refers to the HAMT stored at given CID, holding given number of entries.
+    pub(crate) fn from_cid(cid: Cid, count: u64) -> Self {
+        Self {
+            root: Link::from_cid(cid),
+            count,
+        }
+    }
+
+    /// Moves given entries into a new HAMT.
+    pub(crate) async fn from_entries(
+        entries: BTreeMap<String, PublicLink>,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let count = entries.len() as u64;
+        let mut root = Arc::new(EntryShards::default());
+        for (name, link) in entries {
+            root.set(name, link, store).await?;
+        }
+
+        Ok(Self {
+            root: Link::from(root),
+            count,
+        })
+    }
+
+    /// The number of entries.
+    pub(crate) fn len(&self) -> u64 {
+        self.count
+    }
+
+    /// Gets the entry with given name, loading only the blocks on the way.
+    pub(crate) async fn get<'a>(
+        &'a self,
+        name: &str,
+        store: &impl BlockStore,
+    ) -> Result<Option<&'a PublicLink>> {
+        let root = self.root.resolve_value(store).await?;
+        root.get(&name.to_string(), store).await
+    }
+
+    /// Gets the entry with given name for modification, loading only the blocks on the way.
+    pub(crate) async fn get_mut<'a>(
+        &'a mut self,
+        name: &str,
+        store: &'a impl BlockStore,
+    ) -> Result<Option<&'a mut PublicLink>> {
+        let root = self.root.resolve_value_mut(store).await?;
+        root.get_mut(&name.to_string(), store).await
+    }
+
+    /// Adds or replaces the entry with given name.
+    pub(crate) async fn insert(
+        &mut self,
+        name: String,
+        link: PublicLink,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        if self.get(&name, store).await?.is_none() {
+            self.count += 1;
+        }
+
+        let root = self.root.resolve_value_mut(store).await?;
+        root.set(name, link, store).await
+    }
+
+    /// Removes the entry with given name, returning it if there was one.
+    pub(crate) async fn remove(
+        &mut self,
+        name: &str,
+        store: &impl BlockStore,
+    ) -> Result<Option<PublicLink>> {
+        let root = self.root.resolve_value_mut(store).await?;
+        let Some(Pair { value, .. }) = root.remove(&name.to_string(), store).await? else {
+            return Ok(None);
+        };
+
+        self.count = self.count.saturating_sub(1);
+        Ok(Some(value))
+    }
+
+    /// Loads all entries.
+    pub(crate) async fn entries(
+        &self,
+        store: &impl BlockStore,
+    ) -> Result<BTreeMap<String, PublicLink>> {
+        let root = self.root.resolve_value(store).await?;
+        let entries = root
+            .flat_map(
+                &|Pair { key, value }| Ok((key.clone(), value.clone())),
+                store,
+            )
+            .await?;
+
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Stores the parts of the HAMT that changed, returning the CID of its root node.
+    pub(crate) async fn store(&self, store: &impl BlockStore) -> Result<Cid> {
+        self.root.resolve_cid(store).await
+    }
+
+    /// Clones these entries, copying all parts of the HAMT and all entries that weren't
+    /// stored yet, see [`PublicNode::detached`](super::PublicNode::detached).
+    pub(crate) fn detached(&self) -> Self {
+        let root = match self.root.get_value() {
+            Some(root) if !self.root.has_cid() => {
+                Link::from(Arc::new(root.detached(&PublicLink::detached)))
+            }
+            _ => self.root.clone(),
+        };
+
+        Self {
+            root,
+            count: self.count,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that the names of the entries in the HAMT stored at given CID are valid path
+/// segments.
+pub(crate) async fn validate_userland_shards(shards: &Cid, store: &impl BlockStore) -> Result<()> {
+    let shards = EntryShards::load(shards, store).await?;
+    for name in shards
+        .flat_map(&|Pair { key, .. }| Ok(key.clone()), store)
+        .await?
+    {
+        validate_entry_name(&name)?;
+    }
+
+    Ok(())
+}
+
+/// Loads the directory stored at given CID, leaving its sharded entries in the store.
+///
+/// Returns `None` if the node there isn't a directory.
+pub(crate) async fn load_stored_dir(
+    cid: &Cid,
+    store: &impl BlockStore,
+) -> Result<Option<PublicDirectorySerializable>> {
+    let bytes = store.get_block(cid).await?;
+    let PublicNodeSerializable::Dir(mut dir) = PublicNodeSerializable::decode_ipld(cid, bytes)?
+    else {
+        return Ok(None);
+    };
+
+    let overflow = mem::take(&mut dir.userland_overflow);
+    resolve_userland_overflow(&mut dir.userland, overflow, store).await?;
+
+    Ok(Some(dir))
+}
+
+/// Looks up the CID of an entry of the directory stored at given CID, loading only the
+/// blocks on the way for sharded directories.
+///
+/// Returns `None` if there's no such entry or the node there isn't a directory.
+pub(crate) async fn lookup_stored_entry(
+    cid: &Cid,
+    name: &str,
+    store: &impl BlockStore,
+) -> Result<Option<Cid>> {
+    let Some(dir) = load_stored_dir(cid, store).await? else {
+        return Ok(None);
+    };
+
+    match dir.userland_shards {
+        Some(shards) => {
+            let shards = EntryShards::load(&shards, store).await?;
+            match shards.get(&name.to_string(), store).await? {
+                Some(link) => Ok(Some(link.resolve_cid(store).await?)),
+                None => Ok(None),
+            }
+        }
+        None => Ok(dir.userland.get(name).copied()),
+    }
+}
+
+/// Lists up to `limit` entries of a sharded directory that come after the entry with
+/// given name, in order of the hashes of their names.
+pub(crate) async fn page_userland_shards(
+    shards: &Cid,
+    after: Option<&str>,
+    limit: usize,
+    store: &impl BlockStore,
+) -> Result<Vec<(String, Cid)>> {
+    let shards = EntryShards::load(shards, store).await?;
+    let hash = after.map(|name| blake3::Hasher::hash(&name));
+    let mut entries = Vec::new();
+    for Pair { key, value } in shards.pairs_after(hash.as_ref(), limit, store).await? {
+        entries.push((key, value.resolve_cid(store).await?));
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,197 @@
+//! Publishing public directories with only part of their history.
+
+use super::{PublicDirectory, PublicLink, PublicNode};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use libipld_core::cid::Cid;
+use std::collections::{BTreeMap, BTreeSet};
+use wnfs_common::{BlockStore, Storable};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How much history a published directory links to. See [`PublicDirectory::publish`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PublishHistory {
+    /// Links all previous revisions, just like storing the directory does.
+    #[default]
+    Full,
+    /// Links no previous revisions, only publishing the current state.
+    Snapshot,
+    /// Links up to given number of previous revisions.
+    Revisions(usize),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PublicDirectory {
+    /// Stores this directory for publishing it, e.g. via a gateway, with only as much
+    /// history as given.
+    ///
+    /// Storing a directory links every node to its previous revisions, which in turn link
+    /// to theirs, so the DAG behind a directory grows with every change. Publishing with
+    /// [`PublishHistory::Snapshot`] or [`PublishHistory::Revisions`] instead stores copies
+    /// of all nodes whose chain of previous revisions is cut off after the given number of
+    /// revisions, which results in a smaller DAG with a different root CID.
+    ///
+    /// The directory itself and its full history stay untouched, so it can be kept locally
+    /// and published again later.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use std::sync::Arc;
+    /// use wnfs::{
+    ///     common::{MemoryBlockStore, Storable},
+    ///     public::{PublicDirectory, PublishHistory},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let path = &["index.html".into()];
+    ///     for version in 0..3u8 {
+    ///         dir.write(path, vec![version], Utc::now(), store).await?;
+    ///         dir.store(store).await?;
+    ///     }
+    ///
+    ///     let cid = dir.publish(PublishHistory::Snapshot, store).await?;
+    ///
+    ///     let published = Arc::new(PublicDirectory::load(&cid, store).await?);
+    ///     assert!(published.get_previous().is_empty());
+    ///     assert_eq!(published.read(path, store).await?, [2]);
+    ///     assert_eq!(dir.get_previous().len(), 1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn publish(&self, history: PublishHistory, store: &impl BlockStore) -> Result<Cid> {
+        let cid = self.store(store).await?;
+        let revisions = match history {
+            PublishHistory::Full => return Ok(cid),
+            PublishHistory::Snapshot => 0,
+            PublishHistory::Revisions(revisions) => revisions,
+        };
+
+        truncate_history(cid, revisions, &mut BTreeMap::new(), store).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Stores a copy of the node with given CID, with its own history and that of all its
+/// descendants cut off after `revisions` previous revisions.
+///
+/// Nodes and revisions are shared a lot between revisions of a directory, so the copies
+/// are remembered by CID and number of revisions.
+#[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
+#[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
+async fn truncate_history(
+    cid: Cid,
+    revisions: usize,
+    copies: &mut BTreeMap<(Cid, usize), Cid>,
+    store: &impl BlockStore,
+) -> Result<Cid> {
+    if let Some(copy) = copies.get(&(cid, revisions)) {
+        return Ok(*copy);
+    }
+
+    let mut previous = BTreeSet::new();
+    let mut node = PublicNode::load(&cid, store).await?;
+    if let Some(revisions) = revisions.checked_sub(1) {
+        for previous_cid in node.get_previous().clone() {
+            previous.insert(truncate_history(previous_cid, revisions, copies, store).await?);
+        }
+    }
+
+    match &mut node {
+        PublicNode::File(file) => file.prepare_next_revision().previous = previous,
+        PublicNode::Custom(custom) => custom.prepare_next_revision().previous = previous,
+        PublicNode::Dir(dir) => {
+            let mut userland = BTreeMap::new();
+            for (name, link) in dir.entries(store).await?.iter() {
+                let child_cid = link.resolve_cid(store).await?;
+                let child_copy = truncate_history(child_cid, revisions, copies, store).await?;
+                userland.insert(name.clone(), PublicLink::from_cid(child_copy));
+            }
+
+            let dir = dir.prepare_next_revision();
+            dir.previous = previous;
+            for (name, link) in userland {
+                dir.insert_entry(name, link, store).await?;
+            }
+        }
+    }
+
+    let copy = node.store(store).await?;
+    copies.insert((cid, revisions), copy);
+    Ok(copy)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use testresult::TestResult;
+    use wnfs_common::{utils::Arc, MemoryBlockStore};
+
+    async fn history_len(node: &PublicNode, store: &impl BlockStore) -> Result<usize> {
+        let mut len = 0;
+        let mut node = node.clone();
+        while let Some(previous) = node.get_previous().first().cloned() {
+            node = PublicNode::load(&previous, store).await?;
+            len += 1;
+        }
+
+        Ok(len)
+    }
+
+    #[async_std::test]
+    async fn publishing_truncates_the_history_of_all_nodes() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let dir = &mut PublicDirectory::new_rc(Utc::now());
+        let path = &["docs".into(), "notes.txt".into()];
+        for version in 0..5u8 {
+            dir.write(path, vec![version], Utc::now(), store).await?;
+            dir.store(store).await?;
+        }
+
+        let full = dir.publish(PublishHistory::Full, store).await?;
+        assert_eq!(full, dir.store(store).await?);
+
+        let cid = dir.publish(PublishHistory::Revisions(2), store).await?;
+        let published = PublicNode::Dir(Arc::new(PublicDirectory::load(&cid, store).await?));
+        assert_eq!(history_len(&published, store).await?, 2);
+
+        let published_dir = published.as_dir()?;
+        let notes = published_dir.get_node(path, store).await?.unwrap();
+        assert_eq!(history_len(notes, store).await?, 2);
+        assert_eq!(notes.as_file()?.get_content(store).await?, [4]);
+
+        // Older revisions keep the history of their descendants cut off, too
+        let oldest = PublicNode::load(published.get_previous().first().unwrap(), store).await?;
+        let oldest = PublicNode::load(oldest.get_previous().first().unwrap(), store).await?;
+        let oldest_dir = oldest.as_dir()?;
+        let oldest_notes = oldest_dir.get_node(path, store).await?.unwrap();
+        assert!(oldest_notes.get_previous().is_empty());
+        assert_eq!(oldest_notes.as_file()?.get_content(store).await?, [2]);
+
+        // The local history stays untouched
+        let dir_node = PublicNode::Dir(Arc::clone(dir));
+        assert_eq!(history_len(&dir_node, store).await?, 4);
+
+        Ok(())
+    }
+}
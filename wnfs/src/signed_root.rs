@@ -0,0 +1,325 @@
+//! Signed envelopes around committed forest and public roots.
+//!
+//! Name proofs show that writes to a private forest are allowed, but checking them means
+//! diffing the forest against the previous root. Signing each committed root with a device
+//! key lets replicators reject roots that weren't produced by an authorized device right
+//! away, before doing any of that work.
+//!
+//! This crate doesn't pick a signature scheme. Devices sign with a [`RootSigner`], and
+//! replicators decide which devices are authorized and check their signatures with a
+//! [`RootVerifier`].
+
+use crate::{error::FsError, is_readable_wnfs_version, WNFS_VERSION};
+use anyhow::{bail, Result};
+use futures::Future;
+use libipld_core::cid::Cid;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use wnfs_common::{decode, encode, libipld::cbor::DagCborCodec, utils::CondSend, BlockStore};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The domain separation tag prepended to the roots in signed messages,
+/// so root signatures can't be confused with other signatures by the same key.
+const ROOT_SIGNATURE_DSI: &str = "wnfs/root/signature";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The `RootSigner` trait represents a device key that signs the roots the device commits.
+pub trait RootSigner {
+    /// Gets the identifier of the device, e.g. the DID of its key.
+    fn device(&self) -> &str;
+
+    /// Signs a message with the device key.
+    fn sign(&self, message: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + CondSend;
+}
+
+/// The `RootVerifier` trait checks signatures made by [`RootSigner`]s,
+/// e.g. by looking up the device in a list of authorized device keys.
+pub trait RootVerifier {
+    /// Checks whether the device is authorized and the signature over the message was made by its key.
+    fn verify(
+        &self,
+        device: &str,
+        message: &[u8],
+        signature: &[u8],
+    ) -> impl Future<Output = Result<bool>> + CondSend;
+}
+
+/// A committed forest root, and optionally a public root, signed by a device key.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::{MemoryBlockStore, Storable},
+///     private::forest::hamt::HamtForest,
+///     signed_root::{RootSigner, RootVerifier, SignedRoot},
+/// };
+///
+/// // Stands in for a real device key pair.
+/// struct Device(&'static str);
+///
+/// impl RootSigner for Device {
+///     fn device(&self) -> &str {
+///         self.0
+///     }
+///
+///     async fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+///         Ok([self.0.as_bytes(), message].concat())
+///     }
+/// }
+///
+/// impl RootVerifier for Device {
+///     async fn verify(&self, device: &str, message: &[u8], signature: &[u8]) -> Result<bool> {
+///         Ok(device == self.0 && signature == [device.as_bytes(), message].concat())
+///     }
+/// }
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = HamtForest::new_rsa_2048(rng);
+///     let forest_cid = forest.store(store).await?;
+///
+///     let signed = SignedRoot::sign(forest_cid, None, &Device("laptop")).await?;
+///     let cid = signed.store(store).await?;
+///
+///     // Replicators only accept roots from devices they know
+///     let verified = SignedRoot::load_verified(&cid, store, &Device("laptop")).await?;
+///     assert_eq!(verified.get_forest(), &forest_cid);
+///     assert!(SignedRoot::load_verified(&cid, store, &Device("phone")).await.is_err());
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedRoot {
+    version: Version,
+    forest: Cid,
+    public: Option<Cid>,
+    device: String,
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SignedRootSerializable {
+    #[serde(rename = "wnfs/root/signed")]
+    Root(SignedRoot),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl SignedRoot {
+    /// Signs a forest root, and optionally a public root, with the device key.
+    pub async fn sign(forest: Cid, public: Option<Cid>, signer: &impl RootSigner) -> Result<Self> {
+        let device = signer.device().to_string();
+        let message = create_signature_message(&forest, public.as_ref(), &device)?;
+        let signature = signer.sign(&message).await?;
+
+        Ok(Self {
+            version: WNFS_VERSION,
+            forest,
+            public,
+            device,
+            signature,
+        })
+    }
+
+    /// Gets the CID of the signed forest root.
+    pub fn get_forest(&self) -> &Cid {
+        &self.forest
+    }
+
+    /// Gets the CID of the signed public root, if one was signed.
+    pub fn get_public(&self) -> Option<&Cid> {
+        self.public.as_ref()
+    }
+
+    /// Gets the identifier of the device that signed the roots.
+    pub fn get_device(&self) -> &str {
+        &self.device
+    }
+
+    /// Checks that the roots were signed by an authorized device.
+    ///
+    /// Errors with [`FsError::UnauthorizedRoot`] if the verifier rejects the signature.
+    pub async fn verify(&self, verifier: &impl RootVerifier) -> Result<()> {
+        let message = create_signature_message(&self.forest, self.public.as_ref(), &self.device)?;
+        if !verifier
+            .verify(&self.device, &message, &self.signature)
+            .await?
+        {
+            bail!(FsError::UnauthorizedRoot(self.device.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Stores the signed roots as a block, returning its CID.
+    pub async fn store(&self, store: &impl BlockStore) -> Result<Cid> {
+        let serializable = SignedRootSerializable::Root(self.clone());
+        let bytes = encode(&serializable, DagCborCodec)?;
+        Ok(store.put_block(bytes, DagCborCodec.into()).await?)
+    }
+
+    /// Loads signed roots, without verifying the signature.
+    pub async fn load(cid: &Cid, store: &impl BlockStore) -> Result<Self> {
+        let SignedRootSerializable::Root(signed) =
+            decode(&store.get_block(cid).await?, DagCborCodec)?;
+        if !is_readable_wnfs_version(&signed.version) {
+            bail!(FsError::UnexpectedVersion(signed.version));
+        }
+
+        Ok(signed)
+    }
+
+    /// Loads signed roots and checks that they were signed by an authorized device.
+    ///
+    /// Nothing but the envelope itself is loaded, so this is cheap enough to run
+    /// before fetching or diffing any of the signed roots.
+    pub async fn load_verified(
+        cid: &Cid,
+        store: &impl BlockStore,
+        verifier: &impl RootVerifier,
+    ) -> Result<Self> {
+        let signed = Self::load(cid, store).await?;
+        signed.verify(verifier).await?;
+        Ok(signed)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Generates the message that's signed for given roots.
+///
+/// The device is part of the message, so a signature can't be passed off as another device's.
+fn create_signature_message(forest: &Cid, public: Option<&Cid>, device: &str) -> Result<Vec<u8>> {
+    encode(&(ROOT_SIGNATURE_DSI, forest, public, device), DagCborCodec)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+    use wnfs_common::{MemoryBlockStore, CODEC_RAW};
+
+    /// Stands in for a device key pair by using the device as key for a keyed hash.
+    struct DeviceKey(&'static str);
+
+    /// Stands in for a replicator that only accepts roots from some devices.
+    struct AuthorizedDevices(&'static [&'static str]);
+
+    impl DeviceKey {
+        fn mac(device: &str, message: &[u8]) -> Vec<u8> {
+            let key = blake3::derive_key("wnfs/test/device key", device.as_bytes());
+            blake3::keyed_hash(&key, message).as_bytes().to_vec()
+        }
+    }
+
+    impl RootSigner for DeviceKey {
+        fn device(&self) -> &str {
+            self.0
+        }
+
+        async fn sign(&self, message: &[u8]) -> Result<Vec<u8>> {
+            Ok(Self::mac(self.0, message))
+        }
+    }
+
+    impl RootVerifier for AuthorizedDevices {
+        async fn verify(&self, device: &str, message: &[u8], signature: &[u8]) -> Result<bool> {
+            Ok(self.0.contains(&device) && DeviceKey::mac(device, message) == signature)
+        }
+    }
+
+    async fn roots(store: &impl BlockStore) -> Result<(Cid, Cid)> {
+        let forest = store.put_block(b"forest".to_vec(), CODEC_RAW).await?;
+        let public = store.put_block(b"public".to_vec(), CODEC_RAW).await?;
+        Ok((forest, public))
+    }
+
+    #[async_std::test]
+    async fn roots_signed_by_authorized_devices_verify() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let (forest, public) = roots(store).await?;
+        let verifier = &AuthorizedDevices(&["laptop", "phone"]);
+
+        let signed = SignedRoot::sign(forest, Some(public), &DeviceKey("phone")).await?;
+        let cid = signed.store(store).await?;
+        let loaded = SignedRoot::load_verified(&cid, store, verifier).await?;
+
+        assert_eq!(loaded, signed);
+        assert_eq!(loaded.get_forest(), &forest);
+        assert_eq!(loaded.get_public(), Some(&public));
+        assert_eq!(loaded.get_device(), "phone");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn roots_from_unauthorized_devices_are_rejected() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let (forest, _) = roots(store).await?;
+
+        let signed = SignedRoot::sign(forest, None, &DeviceKey("stolen tablet")).await?;
+        let err = signed
+            .verify(&AuthorizedDevices(&["laptop"]))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<FsError>(),
+            Some(FsError::UnauthorizedRoot(device)) if device == "stolen tablet"
+        ));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn signatures_dont_carry_over_to_other_roots_or_devices() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let (forest, public) = roots(store).await?;
+        let verifier = &AuthorizedDevices(&["laptop", "phone"]);
+        let signed = SignedRoot::sign(forest, Some(public), &DeviceKey("laptop")).await?;
+
+        let swapped_roots = SignedRoot {
+            forest: public,
+            public: Some(forest),
+            ..signed.clone()
+        };
+        let dropped_public = SignedRoot {
+            public: None,
+            ..signed.clone()
+        };
+        let other_device = SignedRoot {
+            device: "phone".into(),
+            ..signed.clone()
+        };
+
+        assert!(signed.verify(verifier).await.is_ok());
+        assert!(swapped_roots.verify(verifier).await.is_err());
+        assert!(dropped_public.verify(verifier).await.is_err());
+        assert!(other_device.verify(verifier).await.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! Canned private file systems for integration tests.
+//!
+//! A [`PrivateFixture`] is a stored private root directory filled with a random tree,
+//! together with everything needed to load it again: its forest, block store and access key.
+//! Use [`SnapshotBlockStore`](wnfs_common::utils::SnapshotBlockStore) as the block store to
+//! snapshot the resulting blocks.
+
+use crate::private::{
+    forest::{hamt::HamtForest, traits::PrivateForest},
+    AccessKey, PrivateDirectory, PrivateNode,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use rand_chacha::ChaCha12Rng;
+use rand_core::{CryptoRngCore, SeedableRng};
+use std::collections::BTreeMap;
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore, MemoryBlockStore, Storable,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The shape of a tree generated by [`build_random_tree`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeShape {
+    /// How many levels of directories there are below the root.
+    pub depth: usize,
+    /// How many subdirectories each directory above the deepest level has.
+    pub dirs_per_dir: usize,
+    /// How many files each directory has, including the root.
+    pub files_per_dir: usize,
+    /// The largest size of file content. Sizes are picked at random up to this.
+    pub max_file_size: usize,
+}
+
+/// A stored private root directory with a random tree, and everything needed to load it.
+///
+/// All randomness, including key generation, is derived from the seed, so the same seed
+/// and shape always produce the same files.
+#[derive(Debug, Clone)]
+pub struct PrivateFixture<B: BlockStore = MemoryBlockStore> {
+    pub store: B,
+    pub forest: Arc<HamtForest>,
+    /// The CID of the stored forest.
+    pub forest_cid: Cid,
+    pub root: Arc<PrivateDirectory>,
+    /// The access key of the stored root.
+    pub access_key: AccessKey,
+    /// The content of all files in the tree, by path.
+    pub files: BTreeMap<Vec<String>, Vec<u8>>,
+    /// The random number generator the fixture was built with, for further changes.
+    pub rng: ChaCha12Rng,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl TreeShape {
+    /// A tree with 6 files in 3 directories.
+    pub const SMALL: Self = Self {
+        depth: 1,
+        dirs_per_dir: 2,
+        files_per_dir: 2,
+        max_file_size: 256,
+    };
+
+    /// A tree with 52 files in 13 directories.
+    pub const MEDIUM: Self = Self {
+        depth: 2,
+        dirs_per_dir: 3,
+        files_per_dir: 4,
+        max_file_size: 4 * 1024,
+    };
+
+    /// A tree with 340 files in 85 directories, some of which are larger than a block.
+    pub const LARGE: Self = Self {
+        depth: 3,
+        dirs_per_dir: 4,
+        files_per_dir: 4,
+        max_file_size: 512 * 1024,
+    };
+}
+
+impl PrivateFixture {
+    /// Builds a fixture with given shape in a new in-memory block store.
+    pub async fn new(seed: u64, shape: TreeShape) -> Result<Self> {
+        Self::with_store(MemoryBlockStore::new(), seed, shape).await
+    }
+}
+
+impl<B: BlockStore> PrivateFixture<B> {
+    /// Builds a fixture with given shape in given block store.
+    pub async fn with_store(store: B, seed: u64, shape: TreeShape) -> Result<Self> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(seed);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let time = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+        let root = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+
+        let files = build_random_tree(root, &shape, time, forest, &store, rng).await?;
+        let access_key = root.as_node().store(forest, &store, rng).await?;
+        let forest_cid = forest.store(&store).await?;
+
+        Ok(Self {
+            forest: Arc::clone(forest),
+            root: Arc::clone(root),
+            rng: rng.clone(),
+            store,
+            forest_cid,
+            access_key,
+            files,
+        })
+    }
+
+    /// Loads the root from the stored forest with the fixture's access key, like
+    /// another device would.
+    pub async fn load_root(&self) -> Result<PrivateNode> {
+        let forest = HamtForest::load(&self.forest_cid, &self.store).await?;
+        PrivateNode::load(&self.access_key, &forest, &self.store, None).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Fills a private directory with a tree of given shape, with random file content.
+///
+/// Directories are named `dir-<n>` and files `file-<n>`. Returns the content of all
+/// written files by path. Nothing is stored, so `root` needs to be stored afterwards.
+pub async fn build_random_tree(
+    root: &mut Arc<PrivateDirectory>,
+    shape: &TreeShape,
+    time: DateTime<Utc>,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut (impl CryptoRngCore + CondSend),
+) -> Result<BTreeMap<Vec<String>, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![(Vec::<String>::new(), 0)];
+    while let Some((dir_path, depth)) = dirs.pop() {
+        for i in 0..shape.files_per_dir {
+            let mut content = vec![0; rng.next_u32() as usize % (shape.max_file_size + 1)];
+            rng.fill_bytes(&mut content);
+
+            let mut path = dir_path.clone();
+            path.push(format!("file-{i}"));
+            root.write(&path, false, time, content.clone(), forest, store, rng)
+                .await?;
+            files.insert(path, content);
+        }
+
+        if depth < shape.depth {
+            for i in 0..shape.dirs_per_dir {
+                let mut path = dir_path.clone();
+                path.push(format!("dir-{i}"));
+                root.mkdir(&path, false, time, forest, store, rng).await?;
+                dirs.push((path, depth + 1));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+    use wnfs_common::utils::SnapshotBlockStore;
+
+    #[async_std::test]
+    async fn fixtures_are_deterministic_and_loadable() -> TestResult {
+        let shape = TreeShape {
+            depth: 2,
+            dirs_per_dir: 2,
+            files_per_dir: 1,
+            max_file_size: 64,
+        };
+        let one = PrivateFixture::new(7, shape).await?;
+        let two = PrivateFixture::with_store(SnapshotBlockStore::default(), 7, shape).await?;
+
+        assert_eq!(one.files.len(), 7);
+        assert_eq!(one.files, two.files);
+        assert_eq!(one.forest_cid, two.forest_cid);
+
+        let root = two.load_root().await?.as_dir()?;
+        let deepest = vec!["dir-1".into(), "dir-0".into(), "file-0".into()];
+        assert_eq!(
+            root.read(&deepest, false, &two.forest, &two.store).await?,
+            two.files[&deepest]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+//! Utilities for testing applications built on top of WNFS.
+//!
+//! These are only available with the `test_utils` feature enabled.
+
+mod fixtures;
+mod simulation;
+
+pub use fixtures::*;
+pub use simulation::*;
@@ -0,0 +1,520 @@
+//! A deterministic simulation of concurrent writers on a private file system.
+//!
+//! Every writer works on a clone of the same root directory and forest,
+//! just like separate devices would after syncing. Once the writers are done,
+//! their forests get merged and the root is reconciled, after which the
+//! simulation checks that the merge didn't lose any files and that the
+//! merged root's history links point at every writer's head.
+
+use crate::{
+    error::FsError,
+    private::{
+        forest::{hamt::HamtForest, traits::PrivateForest},
+        PrivateDirectory, PrivateNode, TemporalKey,
+    },
+};
+use anyhow::{ensure, Result};
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use rand_chacha::ChaCha12Rng;
+use rand_core::{RngCore, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+use wnfs_common::{utils::Arc, BlockStore, MemoryBlockStore};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How far back in history the merged root's previous links are followed.
+const DISCREPANCY_BUDGET: usize = 1_000_000;
+
+/// The number of distinct file names randomly generated operations write to.
+///
+/// Kept small, so that writers regularly conflict on the same file.
+const FILE_POOL_SIZE: u64 = 8;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An operation a simulated writer can run on its copy of the root directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulatedOp {
+    /// Writes a file, creating any missing intermediate directories.
+    Write { path: Vec<String>, content: Vec<u8> },
+    /// Creates a directory.
+    Mkdir { path: Vec<String> },
+    /// Stores the writer's root, creating a new revision in its forest.
+    Commit,
+}
+
+/// An invariant that didn't hold after merging concurrent writers.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InvariantViolation {
+    #[error("File {path:?} is missing after merge")]
+    LostFile { path: Vec<String> },
+
+    #[error("File {path:?} has content no writer wrote: {content:?}")]
+    UnexpectedContent { path: Vec<String>, content: Vec<u8> },
+
+    #[error("Head {cid} of writer {writer} isn't referenced from the merged root")]
+    UnreferencedHead { writer: usize, cid: Cid },
+
+    #[error("Previous link reaching back {revisions_back} revisions can't be resolved")]
+    DanglingPreviousLink { revisions_back: usize },
+}
+
+/// A writer with its own copy of the root directory and forest.
+#[derive(Clone)]
+pub struct SimulatedWriter {
+    root: Arc<PrivateDirectory>,
+    forest: Arc<HamtForest>,
+    written: BTreeMap<Vec<String>, Vec<u8>>,
+    head: Option<Cid>,
+    dirty: bool,
+}
+
+/// Simulates writers concurrently modifying clones of the same private root
+/// directory, then merges them and checks the library's merge invariants.
+///
+/// All randomness, including key generation, is derived from the seed
+/// passed to [`ConflictSimulation::new`], so failing runs can be replayed.
+///
+/// Note that directories concurrently created under the same name are
+/// tie-broken as a whole, so files written into the losing directory will
+/// be reported as lost. Create shared directories via
+/// [`ConflictSimulation::write_base`] before forking writers.
+pub struct ConflictSimulation {
+    rng: ChaCha12Rng,
+    store: MemoryBlockStore,
+    forest: Arc<HamtForest>,
+    root: Arc<PrivateDirectory>,
+    files: BTreeMap<Vec<String>, Vec<u8>>,
+    writers: Vec<SimulatedWriter>,
+    clock: i64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ConflictSimulation {
+    /// Creates a simulation with an empty, stored root directory.
+    pub async fn new(seed: u64) -> Result<Self> {
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let store = MemoryBlockStore::new();
+        let mut forest = HamtForest::new_rsa_2048_rc(&mut rng);
+        let root = PrivateDirectory::new_and_store(
+            &forest.empty_name(),
+            Self::time(0),
+            &mut forest,
+            &store,
+            &mut rng,
+        )
+        .await?;
+
+        Ok(Self {
+            rng,
+            store,
+            forest,
+            root,
+            files: BTreeMap::new(),
+            writers: Vec::new(),
+            clock: 0,
+        })
+    }
+
+    /// The block store shared by all writers.
+    pub fn store(&self) -> &MemoryBlockStore {
+        &self.store
+    }
+
+    /// The forest as of the last merge.
+    pub fn forest(&self) -> &Arc<HamtForest> {
+        &self.forest
+    }
+
+    /// The root directory as of the last merge.
+    pub fn root(&self) -> &Arc<PrivateDirectory> {
+        &self.root
+    }
+
+    /// The files and their contents as of the last merge.
+    pub fn files(&self) -> &BTreeMap<Vec<String>, Vec<u8>> {
+        &self.files
+    }
+
+    /// The number of writers forked since the last merge.
+    pub fn writer_count(&self) -> usize {
+        self.writers.len()
+    }
+
+    /// Writes a file to the root directory before any writers are forked.
+    pub async fn write_base(&mut self, path: &[String], content: Vec<u8>) -> Result<()> {
+        ensure!(
+            self.writers.is_empty(),
+            "Can't write to the base root while there are forked writers"
+        );
+
+        let time = self.tick();
+        self.root
+            .write(
+                path,
+                true,
+                time,
+                content.clone(),
+                &mut self.forest,
+                &self.store,
+                &mut self.rng,
+            )
+            .await?;
+        self.root
+            .store(&mut self.forest, &self.store, &mut self.rng)
+            .await?;
+        self.files.insert(path.to_vec(), content);
+
+        Ok(())
+    }
+
+    /// Forks a new writer off the current root and returns its index.
+    pub fn fork(&mut self) -> usize {
+        self.writers.push(SimulatedWriter {
+            root: Arc::clone(&self.root),
+            forest: Arc::clone(&self.forest),
+            written: BTreeMap::new(),
+            head: None,
+            dirty: false,
+        });
+
+        self.writers.len() - 1
+    }
+
+    /// Runs an operation on the given writer's copy of the root.
+    pub async fn apply(&mut self, writer: usize, op: SimulatedOp) -> Result<()> {
+        ensure!(writer < self.writers.len(), "No writer with index {writer}");
+
+        let time = self.tick();
+        let Self {
+            writers,
+            store,
+            rng,
+            ..
+        } = self;
+        let writer = &mut writers[writer];
+
+        match op {
+            SimulatedOp::Write { path, content } => {
+                writer
+                    .root
+                    .write(
+                        &path,
+                        true,
+                        time,
+                        content.clone(),
+                        &mut writer.forest,
+                        store,
+                        rng,
+                    )
+                    .await?;
+                writer.written.insert(path, content);
+                writer.dirty = true;
+            }
+            SimulatedOp::Mkdir { path } => {
+                writer
+                    .root
+                    .mkdir(&path, true, time, &writer.forest, store, rng)
+                    .await?;
+                writer.dirty = true;
+            }
+            SimulatedOp::Commit => writer.commit(store, rng).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Generates a random operation for a random writer.
+    ///
+    /// Returns `None` if there are no forked writers.
+    pub fn random_op(&mut self) -> Option<(usize, SimulatedOp)> {
+        if self.writers.is_empty() {
+            return None;
+        }
+
+        let writer = (self.rng.next_u64() % self.writers.len() as u64) as usize;
+        let op = if self.rng.next_u32() % 8 == 0 {
+            SimulatedOp::Commit
+        } else {
+            let file = self.rng.next_u64() % FILE_POOL_SIZE;
+            let nonce = self.rng.next_u64();
+            SimulatedOp::Write {
+                path: vec![format!("file-{file}.txt")],
+                content: format!("writer {writer}: {nonce}").into_bytes(),
+            }
+        };
+
+        Some((writer, op))
+    }
+
+    /// Runs a number of random operations, interleaved across all writers.
+    pub async fn run_random(&mut self, count: usize) -> Result<()> {
+        for _ in 0..count {
+            let Some((writer, op)) = self.random_op() else {
+                break;
+            };
+
+            self.apply(writer, op).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Forks the given number of writers, runs random interleaved operations
+    /// on them and merges them back together.
+    pub async fn simulate(
+        &mut self,
+        writers: usize,
+        ops: usize,
+    ) -> Result<Vec<InvariantViolation>> {
+        for _ in 0..writers {
+            self.fork();
+        }
+
+        self.run_random(ops).await?;
+        self.merge().await
+    }
+
+    /// Commits all writers, merges their forests and reconciles the root.
+    ///
+    /// Returns all invariant violations found in the merged result. The
+    /// merged root becomes the base for writers forked afterwards.
+    pub async fn merge(&mut self) -> Result<Vec<InvariantViolation>> {
+        let mut writers = std::mem::take(&mut self.writers);
+
+        let mut heads = BTreeMap::new();
+        for (i, writer) in writers.iter_mut().enumerate() {
+            writer.commit(&self.store, &mut self.rng).await?;
+            if let Some(cid) = writer.head {
+                heads.insert(i, cid);
+            }
+        }
+
+        let mut forest = Arc::clone(&self.forest);
+        for writer in writers.iter() {
+            forest = Arc::new(forest.merge(&writer.forest, &self.store).await?);
+        }
+
+        let merged = Arc::clone(&self.root)
+            .search_latest_reconciled(&forest, &self.store)
+            .await?;
+
+        let mut violations = Vec::new();
+        self.check_history(&merged, &heads, &mut violations).await?;
+        self.check_files(&merged, &forest, &writers, &mut violations)
+            .await?;
+
+        merged
+            .store(&mut forest, &self.store, &mut self.rng)
+            .await?;
+        self.root = merged;
+        self.forest = forest;
+
+        Ok(violations)
+    }
+
+    /// Checks that every writer's head is either the merged root itself or
+    /// referenced from its previous links, and that these links resolve.
+    async fn check_history(
+        &self,
+        merged: &Arc<PrivateDirectory>,
+        heads: &BTreeMap<usize, Cid>,
+        violations: &mut Vec<InvariantViolation>,
+    ) -> Result<()> {
+        let ratchets = merged
+            .header
+            .ratchet
+            .previous(&self.root.header.ratchet, DISCREPANCY_BUDGET)
+            .map_err(FsError::NoIntermediateRatchet)?
+            .collect::<Vec<_>>();
+
+        let mut referenced = BTreeSet::new();
+        if let Some(cid) = merged.content.persisted_as.get() {
+            referenced.insert(*cid);
+        }
+
+        for (revisions_back, encrypted_cid) in merged.content.previous.iter() {
+            // Links reaching back before the fork point aren't part of this simulation
+            let Some(ratchet) = revisions_back
+                .checked_sub(1)
+                .and_then(|index| ratchets.get(index))
+            else {
+                continue;
+            };
+
+            let resolved = match encrypted_cid.resolve_value(&TemporalKey::new(ratchet)) {
+                Ok(cid) if self.store.has_block(cid).await? => Some(*cid),
+                _ => None,
+            };
+
+            match resolved {
+                Some(cid) => {
+                    referenced.insert(cid);
+                }
+                None => violations.push(InvariantViolation::DanglingPreviousLink {
+                    revisions_back: *revisions_back,
+                }),
+            }
+        }
+
+        for (writer, cid) in heads {
+            if !referenced.contains(cid) {
+                violations.push(InvariantViolation::UnreferencedHead {
+                    writer: *writer,
+                    cid: *cid,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every known file still exists and has the content of
+    /// either the base (if untouched) or one of the writers' last writes.
+    async fn check_files(
+        &mut self,
+        merged: &Arc<PrivateDirectory>,
+        forest: &Arc<HamtForest>,
+        writers: &[SimulatedWriter],
+        violations: &mut Vec<InvariantViolation>,
+    ) -> Result<()> {
+        let mut expected: BTreeMap<Vec<String>, BTreeSet<Vec<u8>>> = BTreeMap::new();
+        for writer in writers {
+            for (path, content) in writer.written.iter() {
+                expected
+                    .entry(path.clone())
+                    .or_default()
+                    .insert(content.clone());
+            }
+        }
+
+        for (path, content) in self.files.iter() {
+            expected
+                .entry(path.clone())
+                .or_insert_with(|| BTreeSet::from([content.clone()]));
+        }
+
+        for (path, allowed) in expected {
+            match merged.get_node(&path, true, forest, &self.store).await? {
+                Some(PrivateNode::File(file)) => {
+                    let content = file.get_content(forest, &self.store).await?;
+                    if !allowed.contains(&content) {
+                        violations.push(InvariantViolation::UnexpectedContent {
+                            path: path.clone(),
+                            content: content.clone(),
+                        });
+                    }
+
+                    self.files.insert(path, content);
+                }
+                _ => {
+                    violations.push(InvariantViolation::LostFile { path: path.clone() });
+                    self.files.remove(&path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tick(&mut self) -> DateTime<Utc> {
+        self.clock += 1;
+        Self::time(self.clock)
+    }
+
+    fn time(n: i64) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(n, 0).unwrap()
+    }
+}
+
+impl SimulatedWriter {
+    /// Stores the writer's root, if it changed since the last commit.
+    async fn commit(&mut self, store: &impl BlockStore, rng: &mut ChaCha12Rng) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let private_ref = self.root.store(&mut self.forest, store, rng).await?;
+        self.head = Some(private_ref.content_cid);
+        self.dirty = false;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testresult::TestResult;
+
+    #[async_std::test]
+    async fn random_concurrent_writes_uphold_invariants() -> TestResult {
+        for seed in 0..3 {
+            let sim = &mut ConflictSimulation::new(seed).await?;
+
+            let violations = sim.simulate(3, 20).await?;
+            assert!(violations.is_empty(), "seed {seed}: {violations:?}");
+
+            // Another round on top of the merged root
+            let violations = sim.simulate(2, 10).await?;
+            assert!(violations.is_empty(), "seed {seed}: {violations:?}");
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn simulations_with_the_same_seed_are_deterministic() -> TestResult {
+        let one = &mut ConflictSimulation::new(42).await?;
+        let two = &mut ConflictSimulation::new(42).await?;
+
+        one.simulate(2, 10).await?;
+        two.simulate(2, 10).await?;
+
+        assert_eq!(one.files(), two.files());
+        assert_eq!(
+            one.root().content.persisted_as.get(),
+            two.root().content.persisted_as.get()
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn conflicting_writes_keep_one_writers_content() -> TestResult {
+        let sim = &mut ConflictSimulation::new(0).await?;
+        let path = vec!["docs".to_string(), "notes.txt".to_string()];
+        sim.write_base(&path, b"base".to_vec()).await?;
+
+        let first = sim.fork();
+        let second = sim.fork();
+        for (writer, content) in [(first, &b"first"[..]), (second, &b"second"[..])] {
+            let op = SimulatedOp::Write {
+                path: path.clone(),
+                content: content.to_vec(),
+            };
+            sim.apply(writer, op).await?;
+        }
+
+        let violations = sim.merge().await?;
+        assert!(violations.is_empty(), "{violations:?}");
+
+        let content = &sim.files()[&path];
+        assert!(content == b"first" || content == b"second");
+
+        Ok(())
+    }
+}
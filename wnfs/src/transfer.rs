@@ -0,0 +1,421 @@
+//! Copying files and directories between the public and the private partition.
+//!
+//! [`copy_public_to_private`] encrypts a public subtree into a private directory, and
+//! [`copy_private_to_public`] decrypts a private subtree and publishes it. Both keep the
+//! metadata of every copied node, including its creation and modification times.
+
+use crate::{
+    error::FsError,
+    private::{
+        forest::traits::PrivateForest, PrivateDirectory, PrivateFile, PrivateLink, PrivateNode,
+    },
+    public::{PublicDirectory, PublicFile, PublicLink, PublicNode},
+    utils,
+};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rand_core::CryptoRngCore;
+use wnfs_common::{
+    utils::{Arc, CondSend, ProgressReporter},
+    BlockStore,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Copies the public file or directory at `path_segments_from` into the private directory
+/// `private_root` at `path_segments_to`, encrypting all content on the way.
+///
+/// Missing parent directories at the destination are created with `time`, while copied
+/// nodes keep their metadata. The destination must not exist yet.
+///
+/// After every copied node the number of nodes copied so far is reported to `progress`.
+/// The total isn't known upfront, so it's always reported as `None`.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{forest::{hamt::HamtForest, traits::PrivateForest}, PrivateDirectory},
+///     public::PublicDirectory,
+///     transfer::copy_public_to_private,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let public_root = &mut PublicDirectory::new_rc(Utc::now());
+///     let private_root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///
+///     let path = &["notes".into(), "todo.txt".into()];
+///     public_root.write(path, b"Buy milk".to_vec(), Utc::now(), store).await?;
+///
+///     copy_public_to_private(
+///         public_root,
+///         &["notes".into()],
+///         private_root,
+///         &["notes".into()],
+///         true,
+///         Utc::now(),
+///         &|_, _| {},
+///         forest,
+///         store,
+///         rng,
+///     )
+///     .await?;
+///
+///     let content = private_root.read(path, true, forest, store).await?;
+///     assert_eq!(content, b"Buy milk");
+///
+///     Ok(())
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub async fn copy_public_to_private(
+    public_root: &Arc<PublicDirectory>,
+    path_segments_from: &[String],
+    private_root: &mut Arc<PrivateDirectory>,
+    path_segments_to: &[String],
+    search_latest: bool,
+    time: DateTime<Utc>,
+    progress: &impl ProgressReporter,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut (impl CryptoRngCore + CondSend),
+) -> Result<()> {
+    let source = match path_segments_from {
+        [] => PublicNode::Dir(Arc::clone(public_root)),
+        _ => public_root
+            .get_node(path_segments_from, store)
+            .await?
+            .ok_or(FsError::NotFound)?
+            .clone(),
+    };
+
+    // Only the first lookup searches for the latest revision. Everything after
+    // that happens in directories that were just created or brought up to date.
+    let (path, name) = utils::split_last(path_segments_to)?;
+    let parent = private_root
+        .get_or_create_leaf_dir_mut(path, time, search_latest, forest, store, rng)
+        .await?;
+    if parent.content.entries.contains_key(name) {
+        bail!(FsError::FileAlreadyExists);
+    }
+
+    let mut done = 0;
+    let mut worklist = vec![(path_segments_to.to_vec(), source)];
+    while let Some((path_segments, node)) = worklist.pop() {
+        match node {
+            PublicNode::File(file) => {
+                let content = file.get_content(store).await?;
+                let (path, name) = utils::split_last(&path_segments)?;
+                let dir = private_root
+                    .get_or_create_leaf_dir_mut(path, time, false, forest, store, rng)
+                    .await?;
+
+                let mut private_file =
+                    PrivateFile::with_content(&dir.header.name, time, content, forest, store, rng)
+                        .await?;
+                private_file.content.metadata = file.get_metadata().clone();
+                dir.content
+                    .entries
+                    .insert(name.clone(), PrivateLink::with_file(private_file));
+            }
+            PublicNode::Dir(dir) => {
+                let private_dir = private_root
+                    .get_or_create_leaf_dir_mut(&path_segments, time, false, forest, store, rng)
+                    .await?;
+                private_dir.content.metadata = dir.get_metadata().clone();
+
+                for (name, link) in dir.entries(store).await?.iter() {
+                    let child = link.resolve_value(store).await?.clone();
+                    let mut child_path = path_segments.clone();
+                    child_path.push(name.clone());
+                    worklist.push((child_path, child));
+                }
+            }
+            PublicNode::Custom(_) => bail!(FsError::NotAFile),
+        }
+
+        done += 1;
+        progress.on_progress(done, None);
+    }
+
+    Ok(())
+}
+
+/// Copies the private file or directory at `path_segments_from` into the public directory
+/// `public_root` at `path_segments_to`, decrypting all content on the way.
+///
+/// Missing parent directories at the destination are created with `time`, while copied
+/// nodes keep their metadata. The destination must not exist yet.
+///
+/// After every copied node the number of nodes copied so far is reported to `progress`.
+/// The total isn't known upfront, so it's always reported as `None`.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand_chacha::ChaCha12Rng;
+/// use rand_core::SeedableRng;
+/// use wnfs::{
+///     common::MemoryBlockStore,
+///     private::{forest::{hamt::HamtForest, traits::PrivateForest}, PrivateDirectory},
+///     public::PublicDirectory,
+///     transfer::copy_private_to_public,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut ChaCha12Rng::from_entropy();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let public_root = &mut PublicDirectory::new_rc(Utc::now());
+///     let private_root = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///
+///     let path = &["site".into(), "index.html".into()];
+///     private_root
+///         .write(path, true, Utc::now(), b"<h1>Hello</h1>".to_vec(), forest, store, rng)
+///         .await?;
+///
+///     copy_private_to_public(
+///         private_root,
+///         &["site".into()],
+///         true,
+///         public_root,
+///         &["site".into()],
+///         Utc::now(),
+///         &|_, _| {},
+///         forest,
+///         store,
+///     )
+///     .await?;
+///
+///     let content = public_root.read(path, store).await?;
+///     assert_eq!(content, b"<h1>Hello</h1>");
+///
+///     Ok(())
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub async fn copy_private_to_public(
+    private_root: &Arc<PrivateDirectory>,
+    path_segments_from: &[String],
+    search_latest: bool,
+    public_root: &mut Arc<PublicDirectory>,
+    path_segments_to: &[String],
+    time: DateTime<Utc>,
+    progress: &impl ProgressReporter,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<()> {
+    let source = match path_segments_from {
+        [] => private_root.as_node(),
+        _ => private_root
+            .get_node(path_segments_from, search_latest, forest, store)
+            .await?
+            .ok_or(FsError::NotFound)?,
+    };
+
+    if public_root
+        .get_node(path_segments_to, store)
+        .await?
+        .is_some()
+    {
+        bail!(FsError::FileAlreadyExists);
+    }
+
+    let mut done = 0;
+    let mut worklist = vec![(path_segments_to.to_vec(), source)];
+    while let Some((path_segments, node)) = worklist.pop() {
+        match node {
+            PrivateNode::File(file) => {
+                let content = file.get_content(forest, store).await?;
+                let (path, name) = utils::split_last(&path_segments)?;
+                let dir = public_root
+                    .get_or_create_leaf_dir_mut(path, time, store)
+                    .await?;
+
+                let mut public_file = PublicFile::with_content(time, content, store).await?;
+                public_file.metadata = file.get_metadata().clone();
+                dir.insert_entry(name.clone(), PublicLink::with_file(public_file), store)
+                    .await?;
+            }
+            PrivateNode::Dir(dir) => {
+                let public_dir = public_root
+                    .get_or_create_leaf_dir_mut(&path_segments, time, store)
+                    .await?;
+                public_dir.metadata = dir.get_metadata().clone();
+
+                for name in dir.content.entries.keys() {
+                    let Some(child) = dir.lookup_node(name, search_latest, forest, store).await?
+                    else {
+                        continue;
+                    };
+
+                    let mut child_path = path_segments.clone();
+                    child_path.push(name.clone());
+                    worklist.push((child_path, child));
+                }
+            }
+        }
+
+        done += 1;
+        progress.on_progress(done, None);
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use testresult::TestResult;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn subtrees_round_trip_with_their_metadata() -> TestResult {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let time = Utc::now();
+        let private_root = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+
+        let file_path = &["docs".into(), "drafts".into(), "plan.md".into()];
+        private_root
+            .write(
+                file_path,
+                true,
+                time,
+                b"# Plan".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        private_root
+            .mkdir(
+                &["docs".into(), "empty".into()],
+                true,
+                time,
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        private_root
+            .open_file_mut(file_path, true, time, forest, store, rng)
+            .await?
+            .get_metadata_mut()
+            .put("author", "alice".into());
+
+        let copied = AtomicU64::new(0);
+        let progress = |done, _total| copied.store(done, Ordering::Relaxed);
+
+        let public_root = &mut PublicDirectory::new_rc(time);
+        let later = time + chrono::Duration::hours(1);
+        let docs = &["docs".into()];
+        copy_private_to_public(
+            private_root,
+            docs,
+            true,
+            public_root,
+            &["published".into(), "docs".into()],
+            later,
+            &progress,
+            forest,
+            store,
+        )
+        .await?;
+
+        assert_eq!(copied.load(Ordering::Relaxed), 4);
+        let public_path = &[
+            "published".into(),
+            "docs".into(),
+            "drafts".into(),
+            "plan.md".into(),
+        ];
+        assert_eq!(public_root.read(public_path, store).await?, b"# Plan");
+        let public_file = public_root.get_node(public_path, store).await?.unwrap();
+        let private_file = private_root
+            .get_node(file_path, true, forest, store)
+            .await?
+            .unwrap();
+        assert_eq!(
+            public_file.as_file()?.get_metadata(),
+            private_file.as_file()?.get_metadata()
+        );
+
+        let restored = &["restored".into()];
+        copy_public_to_private(
+            public_root,
+            &["published".into(), "docs".into()],
+            private_root,
+            restored,
+            true,
+            later,
+            &progress,
+            forest,
+            store,
+            rng,
+        )
+        .await?;
+
+        assert_eq!(
+            private_root.ls(restored, true, forest, store).await?,
+            private_root.ls(docs, true, forest, store).await?
+        );
+        let restored_file = private_root
+            .get_node(
+                &["restored".into(), "drafts".into(), "plan.md".into()],
+                true,
+                forest,
+                store,
+            )
+            .await?
+            .unwrap();
+        assert_eq!(
+            restored_file.as_file()?.get_metadata(),
+            private_file.as_file()?.get_metadata()
+        );
+        assert_eq!(
+            restored_file.as_file()?.get_content(forest, store).await?,
+            b"# Plan"
+        );
+
+        let again = copy_public_to_private(
+            public_root,
+            &["published".into()],
+            private_root,
+            restored,
+            true,
+            later,
+            &progress,
+            forest,
+            store,
+            rng,
+        )
+        .await;
+        assert!(again.is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+//! A recycle bin for root trees, so apps can offer undo for deletions.
+
+use crate::{
+    error::FsError,
+    root_tree::{Partition, RootTree},
+};
+use anyhow::{ensure, Result};
+use chrono::{DateTime, Duration, SubsecRound, TimeZone, Utc};
+use libipld_core::ipld::Ipld;
+use rand_chacha::ChaCha12Rng;
+use rand_core::{CryptoRngCore, SeedableRng};
+use wnfs_common::{
+    utils::{Arc, CondSend},
+    BlockStore, Metadata,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The name of the directory in each partition root that deleted nodes are moved into.
+pub const TRASH_DIR: &str = ".trash";
+
+/// The metadata key for the path a node had before it was moved into the trash,
+/// relative to its partition root.
+const ORIGINAL_PATH_KEY: &str = "trashedFrom";
+
+/// The metadata key for the time a node was moved into the trash, in seconds.
+const DELETED_AT_KEY: &str = "trashedAt";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A node that was moved into the trash of a partition root with [`RootTree::rm_soft`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    /// The path of the partition root the node was deleted from, e.g. `["public"]`.
+    pub root: Vec<String>,
+    /// The name of the node within the trash directory.
+    pub name: String,
+    /// The full path the node had before it was deleted.
+    pub original_path: Vec<String>,
+    /// When the node was deleted, in whole seconds.
+    pub deleted_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl TrashEntry {
+    /// The full path of the node within the trash.
+    pub fn path(&self) -> Vec<String> {
+        let mut path = self.root.clone();
+        path.extend([TRASH_DIR.to_string(), self.name.clone()]);
+        path
+    }
+
+    fn from_metadata(root: &[String], name: String, metadata: &Metadata) -> Option<Self> {
+        let relative_path: Vec<String> = metadata.get_deserializable(ORIGINAL_PATH_KEY)?.ok()?;
+        let deleted_at = match metadata.get(DELETED_AT_KEY)? {
+            Ipld::Integer(i) => Utc.timestamp_opt(i64::try_from(*i).ok()?, 0).single()?,
+            _ => return None,
+        };
+
+        let mut original_path = root.to_vec();
+        original_path.extend(relative_path);
+        Some(Self {
+            root: root.to_vec(),
+            name,
+            original_path,
+            deleted_at,
+        })
+    }
+}
+
+impl<B: BlockStore> RootTree<B> {
+    /// Moves the node at given path into the trash of its partition root instead of
+    /// deleting it, so it can be [restored](Self::restore) later.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use wnfs::{common::MemoryBlockStore, root_tree::RootTree};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let mut root = RootTree::empty(MemoryBlockStore::new());
+    ///     let path = &["public".into(), "notes".into(), "todo.md".into()];
+    ///     root.write(path, b"- Buy milk".to_vec()).await?;
+    ///
+    ///     let entry = root.rm_soft(path).await?;
+    ///     assert!(root.read(path).await.is_err());
+    ///     assert_eq!(root.ls_trash(&["public".into()]).await?, [entry.clone()]);
+    ///
+    ///     root.restore(&entry).await?;
+    ///     assert_eq!(root.read(path).await?, b"- Buy milk");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn rm_soft(&mut self, path: &[String]) -> Result<TrashEntry> {
+        self.rm_soft_with(path, Utc::now(), &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Moves the node at given path into the trash, recording `time` as its deletion time.
+    /// See [`rm_soft`](Self::rm_soft).
+    pub async fn rm_soft_with(
+        &mut self,
+        path: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<TrashEntry> {
+        let (relative_path, _) = self.get_partition(path)?;
+        let Some(node_name) = relative_path.last() else {
+            anyhow::bail!(FsError::InvalidPath)
+        };
+        ensure!(relative_path[0] != TRASH_DIR, FsError::InvalidPath);
+
+        let relative_path = relative_path.to_vec();
+        let root = path[..path.len() - relative_path.len()].to_vec();
+
+        let taken = self.ls_trash_dir(&root).await?;
+        let mut name = format!("{}-{node_name}", time.timestamp());
+        for i in 1.. {
+            if !taken.iter().any(|(taken, _)| *taken == name) {
+                break;
+            }
+            name = format!("{}-{i}-{node_name}", time.timestamp());
+        }
+
+        let entry = TrashEntry {
+            root,
+            name,
+            original_path: path.to_vec(),
+            deleted_at: time.trunc_subsecs(0),
+        };
+
+        let to = &[TRASH_DIR.to_string(), entry.name.clone()];
+        self.move_within_partition(path, to, time, rng, |metadata| {
+            metadata.put_serializable(ORIGINAL_PATH_KEY, &relative_path)?;
+            metadata.put(DELETED_AT_KEY, Ipld::Integer(time.timestamp() as i128));
+            Ok(())
+        })
+        .await?;
+
+        Ok(entry)
+    }
+
+    /// Lists the nodes in the trash of the partition root at given path, e.g. `["public"]`.
+    ///
+    /// Nodes that were put into the trash directory by other means than
+    /// [`rm_soft`](Self::rm_soft) are skipped.
+    pub async fn ls_trash(&self, root: &[String]) -> Result<Vec<TrashEntry>> {
+        let (relative_path, _) = self.get_partition(root)?;
+        ensure!(relative_path.is_empty(), FsError::InvalidPath);
+
+        let entries = self
+            .ls_trash_dir(root)
+            .await?
+            .into_iter()
+            .filter_map(|(name, metadata)| TrashEntry::from_metadata(root, name, &metadata))
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Moves a node from the trash back to its original path, creating missing parent
+    /// directories.
+    ///
+    /// Fails if there's a node at the original path already.
+    pub async fn restore(&mut self, entry: &TrashEntry) -> Result<()> {
+        self.restore_with(entry, Utc::now(), &mut ChaCha12Rng::from_entropy())
+            .await
+    }
+
+    /// Moves a node from the trash back to its original path. See [`restore`](Self::restore).
+    pub async fn restore_with(
+        &mut self,
+        entry: &TrashEntry,
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+    ) -> Result<()> {
+        let to = &entry.original_path[entry.root.len()..];
+        self.move_within_partition(&entry.path(), to, time, rng, |metadata| {
+            metadata.delete(ORIGINAL_PATH_KEY);
+            metadata.delete(DELETED_AT_KEY);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Deletes a node from the trash for good.
+    pub async fn purge(&mut self, entry: &TrashEntry) -> Result<()> {
+        self.rm(&entry.path()).await
+    }
+
+    /// Deletes all nodes from the trash of given partition root that were deleted at least
+    /// `ttl` before `time`, or all of them if no `ttl` is given.
+    ///
+    /// Returns the purged entries.
+    pub async fn purge_trash(
+        &mut self,
+        root: &[String],
+        ttl: Option<Duration>,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<TrashEntry>> {
+        let mut purged = Vec::new();
+        for entry in self.ls_trash(root).await? {
+            if ttl.is_some_and(|ttl| entry.deleted_at + ttl > time) {
+                continue;
+            }
+
+            self.purge(&entry).await?;
+            purged.push(entry);
+        }
+
+        Ok(purged)
+    }
+
+    /// Lists the trash directory of given partition root, which might not exist yet.
+    async fn ls_trash_dir(&self, root: &[String]) -> Result<Vec<(String, Metadata)>> {
+        let has_trash = self
+            .ls(root)
+            .await?
+            .iter()
+            .any(|(name, _)| name == TRASH_DIR);
+        if !has_trash {
+            return Ok(Vec::new());
+        }
+
+        let mut trash_path = root.to_vec();
+        trash_path.push(TRASH_DIR.to_string());
+        self.ls(&trash_path).await
+    }
+
+    /// Moves the node at given full path to a path relative to the same partition root,
+    /// creating missing parent directories and updating the moved node's metadata.
+    async fn move_within_partition(
+        &mut self,
+        path_from: &[String],
+        relative_path_to: &[String],
+        time: DateTime<Utc>,
+        rng: &mut (impl CryptoRngCore + CondSend),
+        update: impl FnOnce(&mut Metadata) -> Result<()>,
+    ) -> Result<()> {
+        let Some((_, parent)) = relative_path_to.split_last() else {
+            anyhow::bail!(FsError::InvalidPath)
+        };
+
+        let mut result = Ok(());
+        let update = |metadata: &mut Metadata| result = update(metadata);
+        let store = &self.store;
+        let forest = &mut Arc::clone(&self.forest);
+        let partition = match self.get_partition(path_from)? {
+            (path_from, Partition::Public(mut root)) => {
+                root.mkdir(parent, time, store).await?;
+                root.basic_mv(path_from, relative_path_to, time, store)
+                    .await?;
+                root.update_metadata(relative_path_to, update, store)
+                    .await?;
+                Partition::Public(root)
+            }
+            (path_from, Partition::Exchange(mut root)) => {
+                root.mkdir(parent, time, store).await?;
+                root.basic_mv(path_from, relative_path_to, time, store)
+                    .await?;
+                root.update_metadata(relative_path_to, update, store)
+                    .await?;
+                Partition::Exchange(root)
+            }
+            (path_from, Partition::Private(prefix, mut root)) => {
+                root.mkdir(parent, true, time, forest, store, rng).await?;
+                root.basic_mv(path_from, relative_path_to, true, time, forest, store, rng)
+                    .await?;
+                root.update_metadata(relative_path_to, true, update, forest, store)
+                    .await?;
+                Partition::Private(prefix, root)
+            }
+        };
+        result?;
+
+        self.forest = Arc::clone(forest);
+        self.save_partition(partition);
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn trashed_private_nodes_can_be_restored_and_purged() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc::now();
+        let mut root = RootTree::empty_with(MemoryBlockStore::new(), rng, time);
+        let private = &["private".to_string()];
+        root.create_private_root(private).await?;
+
+        let docs = &["private".into(), "docs".into()];
+        let report = &["private".into(), "docs".into(), "report.pdf".into()];
+        let draft = &["private".into(), "draft.txt".into()];
+        root.write_with(report, b"report".to_vec(), time, rng)
+            .await?;
+        root.write_with(draft, b"draft".to_vec(), time, rng).await?;
+
+        let trashed_docs = root.rm_soft_with(docs, time, rng).await?;
+        let later = time + Duration::days(10);
+        let trashed_draft = root.rm_soft_with(draft, later, rng).await?;
+        assert!(root.read(report).await.is_err());
+        assert_eq!(trashed_docs.original_path, docs);
+
+        // Entries are read back from the metadata of the trashed nodes
+        root.store_with(rng).await?;
+        let trash = root.ls_trash(private).await?;
+        assert_eq!(trash, [trashed_docs.clone(), trashed_draft.clone()]);
+
+        // Restoring recreates missing parents and clears the trash metadata
+        root.restore_with(&trashed_docs, later, rng).await?;
+        assert_eq!(root.read(report).await?, b"report");
+        let (_, metadata) = root
+            .ls(private)
+            .await?
+            .into_iter()
+            .find(|(name, _)| name == "docs")
+            .unwrap();
+        assert!(metadata.get(ORIGINAL_PATH_KEY).is_none());
+
+        // Restoring onto an existing node fails
+        let trashed_report = root.rm_soft_with(report, later, rng).await?;
+        root.write_with(report, b"new report".to_vec(), later, rng)
+            .await?;
+        assert!(root
+            .restore_with(&trashed_report, later, rng)
+            .await
+            .is_err());
+
+        // Only entries older than the TTL are purged
+        let purged = root
+            .purge_trash(private, Some(Duration::days(7)), later + Duration::days(1))
+            .await?;
+        assert!(purged.is_empty());
+
+        let purged = root
+            .purge_trash(private, Some(Duration::days(7)), later + Duration::days(7))
+            .await?;
+        assert_eq!(purged, [trashed_draft, trashed_report]);
+        assert!(root.ls_trash(private).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn nodes_with_the_same_name_get_distinct_trash_entries() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let time = Utc::now();
+        let mut root = RootTree::empty_with(MemoryBlockStore::new(), rng, time);
+        let path = &["public".into(), "todo.md".into()];
+
+        root.write_with(path, b"first".to_vec(), time, rng).await?;
+        let first = root.rm_soft_with(path, time, rng).await?;
+        root.write_with(path, b"second".to_vec(), time, rng).await?;
+        let second = root.rm_soft_with(path, time, rng).await?;
+        assert_ne!(first.name, second.name);
+
+        root.restore_with(&first, time, rng).await?;
+        assert_eq!(root.read(path).await?, b"first");
+        assert!(root.read(&second.path()).await.is_ok());
+
+        // The trash itself can't be moved to the trash
+        let trash = &["public".into(), TRASH_DIR.into()];
+        assert!(root.rm_soft_with(trash, time, rng).await.is_err());
+
+        Ok(())
+    }
+}
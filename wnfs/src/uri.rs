@@ -0,0 +1,388 @@
+//! `wnfs://` URIs, which point at a node by root CID, partition and path.
+//!
+//! A URI looks like `wnfs://<root cid>/<partition>/<path...>`, e.g.
+//! `wnfs://bafy.../public/docs/notes.md`. URIs into the private partition may carry an
+//! `access` query parameter, which names the access material needed to resolve them,
+//! e.g. `wnfs://bafy.../private/docs?access=shared-docs`. The reference is opaque to
+//! this crate: apps map it to an [`AccessKey`] however they keep those, so a URI never
+//! contains keys itself.
+
+use crate::{
+    error::FsError,
+    private::{
+        forest::{hamt::HamtForest, traits::PrivateForest},
+        AccessKey, PrivateNode,
+    },
+    public::{PublicDirectory, PublicNode},
+    root_tree::RootTreeSerializable,
+    utils,
+};
+use anyhow::{bail, ensure, Result};
+use libipld_core::cid::Cid;
+use std::{fmt, str::FromStr};
+use wnfs_common::{decode, libipld::cbor::DagCborCodec, utils::Arc, BlockStore, Storable};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The scheme prefix of all WNFS URIs.
+pub const WNFS_URI_SCHEME: &str = "wnfs://";
+
+/// The query parameter that references access material.
+const ACCESS_PARAM: &str = "access";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A parsed `wnfs://` URI.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::uri::{PartitionKind, WnfsUri};
+///
+/// let uri: WnfsUri = "wnfs://bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku/private/docs/my%20notes.md?access=shared-docs"
+///     .parse()
+///     .unwrap();
+///
+/// assert_eq!(uri.partition, PartitionKind::Private);
+/// assert_eq!(uri.path, ["docs", "my notes.md"]);
+/// assert_eq!(uri.access.as_deref(), Some("shared-docs"));
+/// assert_eq!(
+///     uri.to_string(),
+///     "wnfs://bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku/private/docs/my%20notes.md?access=shared-docs"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WnfsUri {
+    /// The CID of the root tree the URI resolves against.
+    pub root_cid: Cid,
+    /// The partition of the root tree the path is in.
+    pub partition: PartitionKind,
+    /// The path, relative to the partition root. For the private partition, that's
+    /// the root the access material gives access to.
+    pub path: Vec<String>,
+    /// An opaque reference to the access material needed to resolve the URI.
+    pub access: Option<String>,
+}
+
+/// The partitions of a root tree a [`WnfsUri`] can point into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionKind {
+    Public,
+    Exchange,
+    Private,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl WnfsUri {
+    /// Creates a URI pointing at given path in a partition of a root tree.
+    pub fn new(root_cid: Cid, partition: PartitionKind, path: &[String]) -> Self {
+        Self {
+            root_cid,
+            partition,
+            path: path.to_vec(),
+            access: None,
+        }
+    }
+
+    /// Attaches a reference to access material to the URI.
+    pub fn with_access(mut self, access: impl Into<String>) -> Self {
+        self.access = Some(access.into());
+        self
+    }
+
+    /// Resolves a URI into the public or exchange partition to the node it points at.
+    ///
+    /// Returns `None` if there's no node at the path.
+    pub async fn resolve_public(&self, store: &impl BlockStore) -> Result<Option<PublicNode>> {
+        let root = self.load_root(store).await?;
+        let root_cid = match self.partition {
+            PartitionKind::Public => root.public,
+            PartitionKind::Exchange => root.exchange,
+            PartitionKind::Private => {
+                bail!(FsError::InvalidUri(
+                    "URI points into the private partition".into()
+                ))
+            }
+        };
+
+        let dir = PublicDirectory::load(&root_cid, store).await?;
+        if self.path.is_empty() {
+            return Ok(Some(PublicNode::Dir(Arc::new(dir))));
+        }
+
+        Ok(dir.get_node(&self.path, store).await?.cloned())
+    }
+
+    /// Loads the private forest of the root tree the URI points into.
+    pub async fn load_forest(&self, store: &impl BlockStore) -> Result<Arc<HamtForest>> {
+        let root = self.load_root(store).await?;
+        Ok(Arc::new(HamtForest::load(&root.forest, store).await?))
+    }
+
+    /// Resolves a URI into the private partition to the node it points at, given the
+    /// access key its `access` reference stands for and the forest from [`Self::load_forest`].
+    ///
+    /// The path is resolved from the node the access key points at, following the latest
+    /// revisions in the forest. Returns `None` if there's no node at the path.
+    pub async fn resolve_private(
+        &self,
+        access_key: &AccessKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Option<PrivateNode>> {
+        ensure!(
+            self.partition == PartitionKind::Private,
+            FsError::InvalidUri("URI doesn't point into the private partition".into())
+        );
+
+        let node = PrivateNode::load(access_key, forest, store, None).await?;
+        if self.path.is_empty() {
+            return Ok(Some(node));
+        }
+
+        node.as_dir()?
+            .get_node(&self.path, true, forest, store)
+            .await
+    }
+
+    async fn load_root(&self, store: &impl BlockStore) -> Result<RootTreeSerializable> {
+        decode(&store.get_block(&self.root_cid).await?, DagCborCodec)
+    }
+}
+
+impl fmt::Display for WnfsUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{WNFS_URI_SCHEME}{}/{}", self.root_cid, self.partition)?;
+        for segment in &self.path {
+            write!(f, "/{}", percent_encode(segment))?;
+        }
+
+        if let Some(access) = &self.access {
+            write!(f, "?{ACCESS_PARAM}={}", percent_encode(access))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for WnfsUri {
+    type Err = FsError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| FsError::InvalidUri(format!("{reason}: {uri:?}"));
+
+        let rest = uri
+            .get(..WNFS_URI_SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(WNFS_URI_SCHEME))
+            .map(|_| &uri[WNFS_URI_SCHEME.len()..])
+            .ok_or_else(|| invalid("Missing wnfs:// scheme"))?;
+
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+
+        let mut segments = path.strip_suffix('/').unwrap_or(path).split('/');
+        let root_cid = segments
+            .next()
+            .and_then(|cid| Cid::try_from(cid).ok())
+            .ok_or_else(|| invalid("Invalid root CID"))?;
+        let partition = segments
+            .next()
+            .ok_or_else(|| invalid("Missing partition"))?
+            .parse()
+            .map_err(|_| invalid("Unknown partition"))?;
+        let path = segments
+            .map(|segment| {
+                let segment = percent_decode(segment).ok_or_else(|| invalid("Invalid escape"))?;
+                utils::validate_entry_name(&segment)
+                    .map_err(|_| invalid("Invalid path segment"))?;
+                Ok(segment)
+            })
+            .collect::<Result<Vec<_>, FsError>>()?;
+
+        let mut access = None;
+        for param in query.into_iter().flat_map(|query| query.split('&')) {
+            if let Some(value) = param
+                .strip_prefix(ACCESS_PARAM)
+                .and_then(|p| p.strip_prefix('='))
+            {
+                access = Some(percent_decode(value).ok_or_else(|| invalid("Invalid escape"))?);
+            }
+        }
+
+        Ok(Self {
+            root_cid,
+            partition,
+            path,
+            access,
+        })
+    }
+}
+
+impl fmt::Display for PartitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Public => "public",
+            Self::Exchange => "exchange",
+            Self::Private => "private",
+        })
+    }
+}
+
+impl FromStr for PartitionKind {
+    type Err = FsError;
+
+    fn from_str(partition: &str) -> Result<Self, Self::Err> {
+        match partition {
+            "public" => Ok(Self::Public),
+            "exchange" => Ok(Self::Exchange),
+            "private" => Ok(Self::Private),
+            _ => Err(FsError::PartitionNotFound),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Escapes everything but the unreserved characters of RFC 3986.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    encoded
+}
+
+/// Reverses [`percent_encode`]. Returns `None` for malformed escapes or non-UTF-8 results.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut iter = value.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::root_tree::RootTree;
+    use wnfs_common::MemoryBlockStore;
+
+    #[test]
+    fn uris_round_trip_through_strings() {
+        let root_cid = Cid::default();
+        let uri = WnfsUri::new(
+            root_cid,
+            PartitionKind::Exchange,
+            &["a b".into(), "100%?#&".into(), "ünïcödé".into()],
+        )
+        .with_access("key/1");
+
+        let string = uri.to_string();
+        assert!(string.starts_with(&format!(
+            "wnfs://{root_cid}/exchange/a%20b/100%25%3F%23%26/"
+        )));
+        assert!(string.ends_with("?access=key%2F1"));
+        assert_eq!(string.parse::<WnfsUri>().unwrap(), uri);
+    }
+
+    #[test]
+    fn malformed_uris_are_rejected() {
+        let root_cid = Cid::default();
+        for uri in [
+            format!("https://{root_cid}/public"),
+            "wnfs://not-a-cid/public".to_string(),
+            format!("wnfs://{root_cid}"),
+            format!("wnfs://{root_cid}/secret/file"),
+            format!("wnfs://{root_cid}/public/a//b"),
+            format!("wnfs://{root_cid}/public/%2E%2E"),
+            format!("wnfs://{root_cid}/public/%zz"),
+        ] {
+            assert!(uri.parse::<WnfsUri>().is_err(), "{uri} should be rejected");
+        }
+
+        let uri: WnfsUri = format!("WNFS://{root_cid}/public/docs/").parse().unwrap();
+        assert_eq!(uri.path, ["docs"]);
+    }
+
+    #[async_std::test]
+    async fn uris_resolve_against_stored_root_trees() {
+        let store = MemoryBlockStore::default();
+        let mut root_tree = RootTree::empty(&store);
+        let access_key = root_tree
+            .create_private_root(&["private".into()])
+            .await
+            .unwrap();
+        let public_path = ["public".into(), "docs".into(), "readme.md".into()];
+        let private_path = ["private".into(), "notes".into(), "todo.md".into()];
+        root_tree
+            .write(&public_path, b"public".to_vec())
+            .await
+            .unwrap();
+        root_tree
+            .write(&private_path, b"private".to_vec())
+            .await
+            .unwrap();
+        let root_cid = root_tree.store().await.unwrap();
+
+        let uri = WnfsUri::new(root_cid, PartitionKind::Public, &public_path[1..]);
+        let file = uri.resolve_public(&store).await.unwrap().unwrap();
+        let content = file.as_file().unwrap().get_content(&store).await.unwrap();
+        assert_eq!(content, b"public");
+
+        let uri = WnfsUri::new(root_cid, PartitionKind::Private, &private_path[1..])
+            .with_access("private-root");
+        let uri: WnfsUri = uri.to_string().parse().unwrap();
+        let forest = uri.load_forest(&store).await.unwrap();
+        let file = uri
+            .resolve_private(&access_key, &forest, &store)
+            .await
+            .unwrap()
+            .unwrap();
+        let content = file
+            .as_file()
+            .unwrap()
+            .get_content(&forest, &store)
+            .await
+            .unwrap();
+        assert_eq!(content, b"private");
+
+        let missing = WnfsUri::new(root_cid, PartitionKind::Exchange, &["nope".into()]);
+        assert!(missing.resolve_public(&store).await.unwrap().is_none());
+        assert!(uri.resolve_public(&store).await.is_err());
+
+        let root = WnfsUri::new(root_cid, PartitionKind::Private, &[]);
+        let node = root
+            .resolve_private(&access_key, &forest, &store)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(node.as_dir().is_ok());
+    }
+}
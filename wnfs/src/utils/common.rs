@@ -1,8 +1,16 @@
 use crate::error::FsError;
 use anyhow::Result;
-use std::fmt::Debug;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Debug};
 use wnfs_common::utils::error;
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The largest size of a dag-cbor map header.
+const MAP_HEADER_SIZE: usize = 9;
+
 pub struct OnceCellDebug<T>(pub Option<T>);
 
 impl<T: Debug> Debug for OnceCellDebug<T> {
@@ -22,6 +30,36 @@ pub(crate) fn split_last(path_segments: &[String]) -> Result<(&[String], &String
     }
 }
 
+/// Splits directory entries into groups whose dag-cbor encoding stays within `max_size`
+/// bytes, for directories that don't fit into a single block.
+///
+/// Entries that don't fit into `max_size` bytes by themselves end up in a group of their own.
+pub(crate) fn split_entries<V: Serialize + Clone>(
+    entries: &BTreeMap<String, V>,
+    max_size: usize,
+) -> Result<Vec<BTreeMap<String, V>>> {
+    let mut groups = Vec::new();
+    let mut group = BTreeMap::new();
+    let mut group_size = MAP_HEADER_SIZE;
+    for (name, value) in entries {
+        let size =
+            serde_ipld_dagcbor::to_vec(name)?.len() + serde_ipld_dagcbor::to_vec(value)?.len();
+        if !group.is_empty() && group_size + size > max_size {
+            groups.push(std::mem::take(&mut group));
+            group_size = MAP_HEADER_SIZE;
+        }
+
+        group.insert(name.clone(), value.clone());
+        group_size += size;
+    }
+
+    if !group.is_empty() {
+        groups.push(group);
+    }
+
+    Ok(groups)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -37,4 +75,20 @@ mod tests {
         assert_eq!(rest, &["a", "b"]);
         assert_eq!(last, &"c");
     }
+
+    #[test]
+    fn split_entries_keeps_groups_within_the_size_limit() {
+        let entries = (0..100)
+            .map(|i| (format!("{i:03}.txt"), vec![i as u8; 20]))
+            .collect::<BTreeMap<_, _>>();
+
+        let groups = split_entries(&entries, 200).unwrap();
+        assert!(groups.len() > 1);
+        for group in groups.iter() {
+            assert!(serde_ipld_dagcbor::to_vec(group).unwrap().len() <= 200);
+        }
+
+        let joined = groups.into_iter().flatten().collect::<BTreeMap<_, _>>();
+        assert_eq!(joined, entries);
+    }
 }
@@ -0,0 +1,64 @@
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks whether an entry name matches a glob pattern.
+///
+/// `*` matches any sequence of characters, including none, and `?` matches
+/// any single character. All other characters match themselves.
+pub(crate) fn matches_glob(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // Where to resume after the last `*`, if the match after it fails
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the `*` swallow one more character
+                Some((star, star_n)) => {
+                    backtrack = Some((star, star_n + 1));
+                    p = star + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_like_shell_globs() {
+        assert!(matches_glob("notes.md", "notes.md"));
+        assert!(!matches_glob("notes.md", "notes.mdx"));
+        assert!(matches_glob("draft-*", "draft-"));
+        assert!(matches_glob("draft-*", "draft-2023.txt"));
+        assert!(!matches_glob("draft-*", "final-draft-1"));
+        assert!(matches_glob("*.jpg", "cat.jpg"));
+        assert!(!matches_glob("*.jpg", "cat.jpg.bak"));
+        assert!(matches_glob("a*b*c", "aXbYbZc"));
+        assert!(matches_glob("img-??.png", "img-01.png"));
+        assert!(!matches_glob("img-??.png", "img-1.png"));
+        assert!(matches_glob("*", ""));
+        assert!(!matches_glob("?", ""));
+    }
+}
@@ -1,7 +1,15 @@
 mod common;
-#[cfg(test)]
+#[cfg(feature = "private")]
+mod glob;
+mod order;
+#[cfg(all(test, feature = "private"))]
 mod test;
+mod validation;
 
 pub(crate) use common::*;
-#[cfg(test)]
+#[cfg(feature = "private")]
+pub(crate) use glob::*;
+pub(crate) use order::*;
+#[cfg(all(test, feature = "private"))]
 pub(crate) use test::*;
+pub(crate) use validation::*;
@@ -0,0 +1,121 @@
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, BTreeSet};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Iterates over directory entries in the given order first, followed by all
+/// entries the order doesn't mention in lexicographic order.
+///
+/// Names in the order that don't refer to an entry are skipped.
+pub(crate) fn iter_in_order<'a, V>(
+    entries: &'a BTreeMap<String, V>,
+    order: &'a [String],
+) -> impl Iterator<Item = (&'a String, &'a V)> {
+    let mentioned = order.iter().collect::<BTreeSet<_>>();
+    let ordered = order.iter().filter_map(|name| entries.get_key_value(name));
+    let rest = entries
+        .iter()
+        .filter(move |(name, _)| !mentioned.contains(name));
+
+    ordered.chain(rest)
+}
+
+/// Checks that a new order only refers to existing entries, and each of them at most once.
+pub(crate) fn check_order<V>(entries: &BTreeMap<String, V>, order: &[String]) -> Result<()> {
+    let mut seen = BTreeSet::new();
+    for name in order {
+        if !entries.contains_key(name) {
+            bail!(FsError::NotFound);
+        }
+
+        if !seen.insert(name) {
+            bail!(FsError::DuplicateOrderEntry(name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Merges concurrently changed entry orders, given how often each of them was reordered.
+///
+/// Orders can't be combined element-wise without a common ancestor, so one of them wins
+/// outright. The order that was reordered more often wins, so the latest reorder is kept,
+/// and an order that was only reordered on one side is kept too. Orders reordered equally
+/// often are tie-broken by the greater order, comparing the entry names lexicographically.
+/// This keeps merges commutative and associative.
+pub(crate) fn merge_order(
+    ours: &mut Vec<String>,
+    our_revision: &mut u64,
+    other: &[String],
+    other_revision: u64,
+) {
+    if (other_revision, other) > (*our_revision, ours.as_slice()) {
+        *ours = other.to_vec();
+        *our_revision = other_revision;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unordered_entries_come_after_ordered_ones() {
+        let entries = BTreeMap::from([("a".into(), 1), ("b".into(), 2), ("c".into(), 3)]);
+        let order = ["c".into(), "gone".into(), "a".into()];
+
+        let names = iter_in_order(&entries, &order)
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn orders_must_refer_to_entries_once() {
+        let entries = BTreeMap::from([("a".into(), 1), ("b".into(), 2)]);
+
+        assert!(check_order(&entries, &["b".into(), "a".into()]).is_ok());
+        assert!(check_order(&entries, &["c".into()]).is_err());
+        assert!(check_order(&entries, &["a".into(), "a".into()]).is_err());
+    }
+
+    #[test]
+    fn merging_orders_is_commutative() {
+        let first = vec!["b".to_string(), "a".to_string()];
+        let second = vec!["a".to_string(), "b".to_string()];
+
+        let (mut left, mut left_revision) = (first.clone(), 1);
+        merge_order(&mut left, &mut left_revision, &second, 1);
+        let (mut right, mut right_revision) = (second.clone(), 1);
+        merge_order(&mut right, &mut right_revision, &first, 1);
+        assert_eq!(left, right);
+        assert_eq!(left_revision, right_revision);
+
+        let (mut cleared, mut cleared_revision) = (vec![], 2);
+        merge_order(&mut cleared, &mut cleared_revision, &first, 1);
+        assert!(cleared.is_empty());
+    }
+
+    #[test]
+    fn orders_reordered_on_one_side_only_are_kept() {
+        let base = vec!["a".to_string(), "c".to_string()];
+        let reordered = vec!["a".to_string(), "b".to_string()];
+
+        let (mut ours, mut our_revision) = (reordered.clone(), 2);
+        merge_order(&mut ours, &mut our_revision, &base, 1);
+        assert_eq!(ours, reordered);
+
+        let (mut theirs, mut their_revision) = (base.clone(), 1);
+        merge_order(&mut theirs, &mut their_revision, &reordered, 2);
+        assert_eq!(theirs, reordered);
+        assert_eq!(their_revision, 2);
+    }
+}
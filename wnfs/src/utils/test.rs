@@ -1,26 +1,34 @@
 use crate::private::{
     forest::{hamt::HamtForest, traits::PrivateForest},
-    FileContent, PrivateDirectory, PrivateForestContent, PrivateNode, PrivateRef, SnapshotKey,
-    TemporalKey,
+    AccessKey, FileContent, PrivateDirectory, PrivateForestContent, PrivateNode, PrivateRef,
+    SnapshotKey, TemporalKey,
 };
 use anyhow::Result;
 use bytes::Bytes;
 use libipld_core::ipld::Ipld;
 use rand_core::CryptoRngCore;
+use std::collections::BTreeMap;
 use wnfs_common::{
     decode,
     libipld::cbor::DagCborCodec,
     utils::{Arc, BytesToIpld, CondSend, SnapshotBlockStore},
+    HashOutput,
 };
 use wnfs_nameaccumulator::Name;
 
 struct EncryptedBlockHandler {
     snapshot_key: SnapshotKey,
+    label: HashOutput,
 }
 
 impl BytesToIpld for EncryptedBlockHandler {
     fn convert(&self, bytes: &Bytes) -> Result<Ipld> {
-        decode(&self.snapshot_key.decrypt(bytes.as_ref())?, DagCborCodec)
+        decode(
+            &self
+                .snapshot_key
+                .decrypt_with_aad(bytes.as_ref(), &self.label)?,
+            DagCborCodec,
+        )
     }
 }
 
@@ -39,11 +47,60 @@ impl BytesToIpld for KeyWrappedBlockHandler {
 
 struct FileShardHandler {
     key: SnapshotKey,
+    label: HashOutput,
 }
 
 impl BytesToIpld for FileShardHandler {
     fn convert(&self, bytes: &Bytes) -> Result<Ipld> {
-        Ok(Ipld::Bytes(self.key.decrypt(bytes.as_ref())?))
+        Ok(Ipld::Bytes(
+            self.key.decrypt_with_aad(bytes.as_ref(), &self.label)?,
+        ))
+    }
+}
+
+/// Decrypts any block that was encrypted with a known key and bound to a known label,
+/// showing the label next to the decrypted value.
+struct LabelledDecryptor {
+    key: KnownKey,
+    label: HashOutput,
+}
+
+enum KnownKey {
+    /// Decrypts key-wrapped node headers.
+    Header(TemporalKey),
+    /// Decrypts node content, which is DAG-CBOR.
+    Content(SnapshotKey),
+    /// Decrypts file content blocks, which are kept as bytes.
+    Block(SnapshotKey),
+}
+
+impl BytesToIpld for LabelledDecryptor {
+    fn convert(&self, bytes: &Bytes) -> Result<Ipld> {
+        let (kind, value) = match &self.key {
+            KnownKey::Header(temporal_key) => (
+                "header",
+                decode(
+                    &temporal_key.key_wrap_decrypt(bytes.as_ref())?,
+                    DagCborCodec,
+                )?,
+            ),
+            KnownKey::Content(snapshot_key) => (
+                "content",
+                decode(
+                    &snapshot_key.decrypt_with_aad(bytes.as_ref(), &self.label)?,
+                    DagCborCodec,
+                )?,
+            ),
+            KnownKey::Block(snapshot_key) => (
+                "block",
+                Ipld::Bytes(snapshot_key.decrypt_with_aad(bytes.as_ref(), &self.label)?),
+            ),
+        };
+
+        Ok(Ipld::Map(BTreeMap::from([
+            ("label".into(), Ipld::String(hex::encode(self.label))),
+            (kind.into(), value),
+        ])))
     }
 }
 
@@ -51,6 +108,70 @@ impl BytesToIpld for FileShardHandler {
 // Functions
 //--------------------------------------------------------------------------------------------------
 
+/// Adds decryptors to given store for all blocks of the private nodes reachable from
+/// given access key, so snapshots show them decrypted and labelled with the forest
+/// labels they're stored under, instead of as opaque ciphertexts.
+///
+/// Unlike [`walk_dir`], this only reads, so it works on any forest the keys are known for.
+pub(crate) async fn add_known_keys(
+    store: &mut SnapshotBlockStore,
+    forest: &impl PrivateForest,
+    access_key: &AccessKey,
+) -> Result<()> {
+    let mut stack = vec![PrivateNode::load(access_key, forest, store, None).await?];
+    while let Some(node) = stack.pop() {
+        let revision_ref = node.get_header().derive_revision_ref(forest);
+        let mut decryptors = vec![
+            LabelledDecryptor {
+                key: KnownKey::Header(revision_ref.temporal_key.clone()),
+                label: revision_ref.label,
+            },
+            LabelledDecryptor {
+                key: KnownKey::Content(revision_ref.temporal_key.derive_snapshot_key()),
+                label: revision_ref.label,
+            },
+        ];
+
+        match &node {
+            PrivateNode::Dir(dir) => {
+                for (name, link) in dir.content.entries.iter() {
+                    let child = link
+                        .resolve_node(forest, store, Some(dir.child_parent_name(name)))
+                        .await?;
+                    stack.push(child.clone());
+                }
+            }
+            PrivateNode::File(file) => {
+                let blocks: Vec<_> = match &file.content.content {
+                    FileContent::Inline { .. } => Vec::new(),
+                    FileContent::External(content) => PrivateForestContent::generate_shard_labels(
+                        &content.key,
+                        0,
+                        content.block_count,
+                        &Name::new(content.base_name.clone(), []),
+                    )
+                    .map(|name| (&content.key, name))
+                    .collect(),
+                    FileContent::Log(log) => {
+                        log.block_names().map(|name| (&log.key, name)).collect()
+                    }
+                };
+
+                decryptors.extend(blocks.into_iter().map(|(key, name)| LabelledDecryptor {
+                    key: KnownKey::Block(key.clone()),
+                    label: PrivateForestContent::block_label(&name, forest),
+                }));
+            }
+        }
+
+        for decryptor in decryptors {
+            store.add_decryptor(Arc::new(decryptor));
+        }
+    }
+
+    Ok(())
+}
+
 pub(crate) async fn walk_dir(
     store: &mut SnapshotBlockStore,
     forest: &mut Arc<HamtForest>,
@@ -62,9 +183,13 @@ pub(crate) async fn walk_dir(
         let private_ref: PrivateRef = dir.store(forest, store, rng).await?;
         let temporal_key = private_ref.temporal_key;
         let snapshot_key = temporal_key.derive_snapshot_key();
+        let label = private_ref.label;
         store.add_block_handler(
             private_ref.content_cid,
-            Arc::new(EncryptedBlockHandler { snapshot_key }),
+            Arc::new(EncryptedBlockHandler {
+                snapshot_key,
+                label,
+            }),
         );
         store.add_block_handler(
             dir.header.store(store, forest).await?,
@@ -82,9 +207,13 @@ pub(crate) async fn walk_dir(
                     let private_ref: PrivateRef = file.store(forest, store, rng).await?;
                     let temporal_key = private_ref.temporal_key;
                     let snapshot_key = temporal_key.derive_snapshot_key();
+                    let label = private_ref.label;
                     store.add_block_handler(
                         private_ref.content_cid,
-                        Arc::new(EncryptedBlockHandler { snapshot_key }),
+                        Arc::new(EncryptedBlockHandler {
+                            snapshot_key,
+                            label,
+                        }),
                     );
                     store.add_block_handler(
                         file.header.store(store, forest).await?,
@@ -106,9 +235,10 @@ pub(crate) async fn walk_dir(
                             match forest.get_encrypted(&name, store).await? {
                                 Some(cids) => {
                                     let key = key.clone();
+                                    let label = PrivateForestContent::block_label(&name, forest);
                                     store.add_block_handler(
                                         *cids.first().unwrap(),
-                                        Arc::new(FileShardHandler { key }),
+                                        Arc::new(FileShardHandler { key, label }),
                                     )
                                 }
                                 None => unreachable!(),
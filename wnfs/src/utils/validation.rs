@@ -0,0 +1,98 @@
+use crate::error::ValidationError;
+use libipld_core::ipld::Ipld;
+use std::collections::BTreeSet;
+use wnfs_common::Metadata;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks that the metadata fields WNFS itself interprets have the types it expects.
+pub(crate) fn validate_metadata(metadata: &Metadata) -> Result<(), ValidationError> {
+    for key in ["created", "modified"] {
+        match metadata.get(key) {
+            None => {}
+            Some(Ipld::Integer(i)) if i64::try_from(*i).is_ok() => {}
+            Some(_) => {
+                return Err(ValidationError::InvalidMetadata(
+                    key.into(),
+                    "a unix timestamp",
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a directory entry name can be used as a path segment.
+pub(crate) fn validate_entry_name(name: &str) -> Result<(), ValidationError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+        return Err(ValidationError::InvalidEntryName(name.into()));
+    }
+
+    Ok(())
+}
+
+/// Checks that a directory's entry order names each entry at most once.
+pub(crate) fn validate_order(order: &[String]) -> Result<(), ValidationError> {
+    let mut seen = BTreeSet::new();
+    for name in order {
+        validate_entry_name(name)?;
+        if !seen.insert(name) {
+            return Err(ValidationError::InvalidOrder(format!(
+                "{name:?} appears more than once"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a list of previous links is a set, i.e. that none of them repeat.
+pub(crate) fn validate_previous<T: Ord>(previous: &[T]) -> Result<(), ValidationError> {
+    let unique = previous.iter().collect::<BTreeSet<_>>();
+    if unique.len() != previous.len() {
+        return Err(ValidationError::InvalidPrevious(
+            "links must not repeat".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn path_like_entry_names_are_rejected() {
+        assert!(validate_entry_name("notes.md").is_ok());
+        for name in ["", ".", "..", "a/b", "a\0"] {
+            assert!(validate_entry_name(name).is_err());
+        }
+    }
+
+    #[test]
+    fn repeated_order_entries_are_rejected() {
+        assert!(validate_order(&["b".into(), "a".into()]).is_ok());
+        assert!(validate_order(&["a".into(), "a".into()]).is_err());
+    }
+
+    #[test]
+    fn mistyped_timestamps_are_rejected() {
+        let mut metadata = Metadata::new(Utc::now());
+        assert!(validate_metadata(&metadata).is_ok());
+
+        metadata.put("modified", Ipld::String("yesterday".into()));
+        assert!(matches!(
+            validate_metadata(&metadata),
+            Err(ValidationError::InvalidMetadata(key, _)) if key == "modified"
+        ));
+    }
+}